@@ -4,5 +4,6 @@ mod benchmarks;
 
 criterion_main! {
     benchmarks::parsing::parse_rpus,
-    benchmarks::rewriting::rewrite_rpus
+    benchmarks::rewriting::rewrite_rpus,
+    benchmarks::generating::generate_rpus
 }
@@ -0,0 +1,72 @@
+use criterion::{criterion_group, Criterion};
+use dolby_vision::rpu::extension_metadata::blocks::{
+    ExtMetadataBlock, ExtMetadataBlockLevel1, ExtMetadataBlockLevel2, ExtMetadataBlockLevel6,
+};
+use dolby_vision::rpu::generate::{GenerateConfig, ShotFrameEdit, VideoShot};
+
+const SHOT_DURATION: usize = 100;
+const SHOT_COUNTS: &[usize] = &[20, 2_000];
+
+fn config_with_shots(count: usize) -> GenerateConfig {
+    let shots = (0..count)
+        .map(|i| {
+            let max_pq = 2000 + (i % 1000) as u16;
+
+            VideoShot {
+                start: i * SHOT_DURATION,
+                duration: SHOT_DURATION,
+                metadata_blocks: vec![
+                    ExtMetadataBlock::Level1(ExtMetadataBlockLevel1::new(0, max_pq, max_pq / 3)),
+                    ExtMetadataBlock::Level2(ExtMetadataBlockLevel2::from_nits(100)),
+                ],
+                frame_edits: vec![ShotFrameEdit {
+                    edit_offset: SHOT_DURATION / 2,
+                    metadata_blocks: vec![ExtMetadataBlock::Level1(ExtMetadataBlockLevel1::new(
+                        0,
+                        max_pq + 10,
+                        max_pq / 3,
+                    ))],
+                }],
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    GenerateConfig {
+        length: count * SHOT_DURATION,
+        level6: Some(ExtMetadataBlockLevel6 {
+            max_display_mastering_luminance: 1000,
+            min_display_mastering_luminance: 1,
+            max_content_light_level: 1000,
+            max_frame_average_light_level: 400,
+        }),
+        shots,
+        ..Default::default()
+    }
+}
+
+fn generate_rpus_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_rpus");
+    group.sample_size(10);
+
+    for count in SHOT_COUNTS {
+        let config = config_with_shots(*count);
+        let frames = config.length;
+
+        group.bench_function(format!("rpu_list_{frames}_frames"), |b| {
+            b.iter(|| {
+                let rpus = config.generate_rpu_list().unwrap();
+
+                for rpu in &rpus {
+                    rpu.write_hevc_unspec62_nalu().unwrap();
+                }
+            })
+        });
+
+        group.bench_function(format!("write_rpus_to_{frames}_frames"), |b| {
+            b.iter(|| config.write_rpus_to(&mut std::io::sink()).unwrap())
+        });
+    }
+}
+
+criterion_group!(generate_rpus, generate_rpus_benchmark);
@@ -1,2 +1,3 @@
+pub mod generating;
 pub mod parsing;
 pub mod rewriting;
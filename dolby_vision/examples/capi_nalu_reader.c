@@ -0,0 +1,53 @@
+#include <stdio.h>
+#include <stdint.h>
+#include <inttypes.h>
+
+#include "helpers.h"
+
+int main(void) {
+    char *path = "../../assets/hevc_tests/regular.hevc";
+    int frames = 0;
+
+    DoviNaluReaderOpaque *reader = dovi_nalu_reader_open_file(path);
+
+    const DoviNalu *nalu;
+    while ((nalu = dovi_nalu_reader_next(reader))) {
+        if (nalu->nal_type != 62)
+            continue;
+
+        // Unescaped RPU, without the 0x7C01 NAL header
+        const DoviData *payload = dovi_nalu_reader_get_rpu_payload(reader);
+        if (!payload) {
+            printf("NALU at %" PRIu64 ": %s\n", nalu->offset, dovi_nalu_reader_get_error(reader));
+            continue;
+        }
+
+        DoviRpuOpaque *rpu = dovi_parse_rpu(payload->data, payload->len);
+        dovi_data_free(payload);
+
+        const DoviRpuDataHeader *header = dovi_rpu_get_header(rpu);
+        if (header) {
+            if (frames == 0)
+                printf("Guessed profile: %i\n", header->guessed_profile);
+
+            dovi_rpu_free_header(header);
+        } else {
+            printf("%s\n", dovi_rpu_get_error(rpu));
+        }
+
+        dovi_rpu_free(rpu);
+        frames++;
+    }
+
+    const char *error = dovi_nalu_reader_get_error(reader);
+    if (error) {
+        printf("%s\n", error);
+
+        dovi_nalu_reader_free(reader);
+        return 1;
+    }
+
+    printf("Read %d RPUs\n", frames);
+
+    dovi_nalu_reader_free(reader);
+}
@@ -2,6 +2,7 @@ use crate::rpu::NUM_COMPONENTS;
 
 mod buffers;
 mod extension_metadata;
+mod nalu;
 mod rpu;
 mod rpu_data_header;
 mod rpu_data_mapping;
@@ -10,6 +11,7 @@ mod vdr_dm_data;
 
 pub use buffers::*;
 pub use extension_metadata::DmData;
+pub use nalu::{Nalu, NaluReaderOpaque};
 pub use rpu::{RpuOpaque, RpuOpaqueList};
 pub use rpu_data_header::RpuDataHeader;
 pub use rpu_data_mapping::RpuDataMapping;
@@ -0,0 +1,94 @@
+use std::ffi::CString;
+use std::io::Read;
+
+use libc::size_t;
+
+use crate::rpu::nalu_reader::{HevcNalu, NaluReader};
+
+/// HEVC NAL unit, from `dovi_nalu_reader_next`.
+///
+/// Owned by the reader, valid until the next call to `dovi_nalu_reader_next` or `dovi_nalu_reader_free`.
+#[repr(C)]
+pub struct Nalu {
+    /// NAL unit type, 62 for the Dolby Vision RPU
+    pub nal_type: u8,
+    /// nuh_layer_id
+    pub layer_id: u8,
+    /// nuh_temporal_id_plus1 - 1
+    pub temporal_id: u8,
+    /// Start code length, 3 or 4 bytes
+    pub start_code_len: u8,
+    /// Offset of the start code in the stream
+    pub start_code_offset: u64,
+    /// Offset of the NAL unit header in the stream
+    pub offset: u64,
+    /// Escaped NAL unit data, starting with the NAL unit header
+    pub data: *const u8,
+    /// Data buffer size
+    pub len: size_t,
+}
+
+/// Opaque HEVC Annex B stream reader.
+///
+/// Use dovi_nalu_reader_free to free.
+/// It should be freed regardless of whether or not an error occurred.
+pub struct NaluReaderOpaque {
+    pub(crate) reader: NaluReader<Box<dyn Read>>,
+    /// Last NAL unit read, pointed to by its C struct
+    pub(crate) current: Option<(HevcNalu, Nalu)>,
+    /// Error String of the last operation, in cases of failure.
+    pub error: Option<CString>,
+    /// The stream can't be read further
+    pub(crate) failed: bool,
+}
+
+impl NaluReaderOpaque {
+    pub(crate) fn new(reader: Box<dyn Read>, error: Option<CString>) -> Self {
+        Self {
+            reader: NaluReader::new(reader),
+            current: None,
+            failed: error.is_some(),
+            error,
+        }
+    }
+
+    /// Reads the next NAL unit, none at the end of the stream or on error
+    pub(crate) fn advance(&mut self) -> Option<&Nalu> {
+        if self.failed {
+            return None;
+        }
+
+        self.current = None;
+        self.error = None;
+
+        match self.reader.next_nalu() {
+            Ok(Some(nalu)) => {
+                let c_nalu = Nalu::from(&nalu);
+                self.current = Some((nalu, c_nalu));
+
+                self.current.as_ref().map(|(_, c_nalu)| c_nalu)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                self.failed = true;
+                self.error = Some(CString::new(format!("Failed reading NAL unit: {e}")).unwrap());
+                None
+            }
+        }
+    }
+}
+
+impl From<&HevcNalu> for Nalu {
+    fn from(nalu: &HevcNalu) -> Self {
+        Self {
+            nal_type: nalu.nal_type,
+            layer_id: nalu.layer_id,
+            temporal_id: nalu.temporal_id,
+            start_code_len: nalu.start_code_len,
+            start_code_offset: nalu.start_code_offset,
+            offset: nalu.offset,
+            data: nalu.data.as_ptr(),
+            len: nalu.data.len(),
+        }
+    }
+}
@@ -41,6 +41,10 @@ pub struct RpuDataHeader {
     vdr_dm_metadata_present_flag: bool,
     use_prev_vdr_rpu_flag: bool,
     prev_vdr_rpu_id: u64,
+
+    /// Extension mapping, bits 8 to 15 of the signaled `el_bit_depth_minus8`
+    ext_mapping_idc_lsb: u8,
+    ext_mapping_idc_msb: u8,
 }
 
 impl RpuDataHeader {
@@ -81,6 +85,8 @@ impl From<&RuRpuDataHeader> for RpuDataHeader {
             vdr_dm_metadata_present_flag: header.vdr_dm_metadata_present_flag,
             use_prev_vdr_rpu_flag: header.use_prev_vdr_rpu_flag,
             prev_vdr_rpu_id: header.prev_vdr_rpu_id,
+            ext_mapping_idc_lsb: header.ext_mapping_idc_lsb,
+            ext_mapping_idc_msb: header.ext_mapping_idc_msb,
         }
     }
 }
@@ -3,6 +3,8 @@
 use libc::{c_char, size_t};
 use std::{
     ffi::{CStr, CString},
+    fs::File,
+    io::{self, Cursor},
     path::PathBuf,
     ptr::{null, null_mut},
     slice,
@@ -141,6 +143,37 @@ pub unsafe extern "C" fn dovi_write_unspec62_nalu(ptr: *mut RpuOpaque) -> *const
     }
 }
 
+/// # Safety
+/// The struct pointer must be valid.
+/// The start code length must be 3 or 4.
+///
+/// Same as `dovi_write_unspec62_nalu`, with the buffer prefixed by a start code.
+/// If an error occurs in the writing, it is logged to RpuOpaque.error
+#[no_mangle]
+pub unsafe extern "C" fn dovi_write_unspec62_nalu_with_start_code(
+    ptr: *mut RpuOpaque,
+    start_code_length: u8,
+) -> *const Data {
+    if ptr.is_null() {
+        return null_mut();
+    }
+
+    let opaque = &mut *ptr;
+
+    if let Some(rpu) = &opaque.rpu {
+        match rpu.write_hevc_unspec62_nalu_with_start_code(start_code_length as usize) {
+            Ok(buf) => Box::into_raw(Box::new(Data::from(buf))),
+            Err(e) => {
+                opaque.error =
+                    Some(CString::new(format!("Failed writing byte buffer: {e}")).unwrap());
+                null_mut()
+            }
+        }
+    } else {
+        null_mut()
+    }
+}
+
 /// # Safety
 /// The struct pointer must be valid.
 /// The mode must be between 0 and 4.
@@ -408,3 +441,143 @@ pub unsafe extern "C" fn dovi_rpu_remove_mapping(ptr: *mut RpuOpaque) -> i32 {
         -1
     }
 }
+
+/// # Safety
+/// The pointer to the file path must be valid.
+///
+/// Opens an HEVC Annex B file, to iterate its NAL units with `dovi_nalu_reader_next`.
+/// The file is read in chunks as the NAL units are iterated.
+///
+/// If the file can't be opened, the error is logged to the reader and no NAL unit is returned.
+#[no_mangle]
+pub unsafe extern "C" fn dovi_nalu_reader_open_file(path: *const c_char) -> *mut NaluReaderOpaque {
+    if path.is_null() {
+        return null_mut();
+    }
+
+    let opaque = match CStr::from_ptr(path).to_str() {
+        Ok(str) => match File::open(str) {
+            Ok(file) => NaluReaderOpaque::new(Box::new(file), None),
+            Err(e) => NaluReaderOpaque::new(
+                Box::new(io::empty()),
+                Some(
+                    CString::new(format!(
+                        "nalu_reader_open_file: Failed opening the file: {e}"
+                    ))
+                    .unwrap(),
+                ),
+            ),
+        },
+        Err(_) => NaluReaderOpaque::new(
+            Box::new(io::empty()),
+            Some(
+                CString::new("nalu_reader_open_file: Failed parsing the input path as a string")
+                    .unwrap(),
+            ),
+        ),
+    };
+
+    Box::into_raw(Box::new(opaque))
+}
+
+/// # Safety
+/// The pointer to the data must be valid.
+///
+/// Reader of the NAL units of an HEVC Annex B byte buffer.
+/// The buffer is copied, it can be freed once the reader is created.
+#[no_mangle]
+pub unsafe extern "C" fn dovi_nalu_reader_from_buffer(
+    buf: *const u8,
+    len: size_t,
+) -> *mut NaluReaderOpaque {
+    assert!(!buf.is_null());
+
+    let data = slice::from_raw_parts(buf, len).to_vec();
+    let opaque = NaluReaderOpaque::new(Box::new(Cursor::new(data)), None);
+
+    Box::into_raw(Box::new(opaque))
+}
+
+/// # Safety
+/// The pointer to the opaque struct must be valid.
+///
+/// Reads the next NAL unit of the stream.
+/// The returned NAL unit is owned by the reader, and valid until the next call or until the reader is freed.
+///
+/// Returns null at the end of the stream, or if an error occurred.
+/// The user should check `dovi_nalu_reader_get_error` to tell them apart.
+/// The error of the previous NAL unit is cleared, reading errors end the iteration.
+#[no_mangle]
+pub unsafe extern "C" fn dovi_nalu_reader_next(ptr: *mut NaluReaderOpaque) -> *const Nalu {
+    if ptr.is_null() {
+        return null();
+    }
+
+    let opaque = &mut *ptr;
+
+    match opaque.advance() {
+        Some(nalu) => nalu as *const Nalu,
+        None => null(),
+    }
+}
+
+/// # Safety
+/// The pointer to the opaque struct must be valid.
+///
+/// Unescaped RPU payload of the last NAL unit read, which must be an UNSPEC62 NAL unit.
+/// The payload starts with the 0x19 RPU prefix, and can be parsed with `dovi_parse_rpu`.
+///
+/// Returns null if the NAL unit isn't a valid RPU, the error is then logged to the reader.
+/// Free the payload with `dovi_data_free`.
+#[no_mangle]
+pub unsafe extern "C" fn dovi_nalu_reader_get_rpu_payload(
+    ptr: *mut NaluReaderOpaque,
+) -> *const Data {
+    if ptr.is_null() {
+        return null();
+    }
+
+    let opaque = &mut *ptr;
+
+    let nalu = match &opaque.current {
+        Some((nalu, _)) => nalu,
+        None => return null(),
+    };
+
+    match nalu.rpu_payload() {
+        Ok(payload) => Box::into_raw(Box::new(Data::from(payload))),
+        Err(e) => {
+            opaque.error = Some(CString::new(format!("Failed getting RPU payload: {e}")).unwrap());
+            null()
+        }
+    }
+}
+
+/// # Safety
+/// The pointer to the opaque struct must be valid.
+///
+/// Get the last logged error for the NaluReaderOpaque operations.
+#[no_mangle]
+pub unsafe extern "C" fn dovi_nalu_reader_get_error(ptr: *const NaluReaderOpaque) -> *const c_char {
+    if ptr.is_null() {
+        return null();
+    }
+
+    let opaque = &*ptr;
+
+    match &opaque.error {
+        Some(s) => s.as_ptr(),
+        None => null(),
+    }
+}
+
+/// # Safety
+/// The pointer to the opaque struct must be valid.
+///
+/// Free the NaluReaderOpaque, and the last NAL unit read.
+#[no_mangle]
+pub unsafe extern "C" fn dovi_nalu_reader_free(ptr: *mut NaluReaderOpaque) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr));
+    }
+}
@@ -2,9 +2,11 @@
 pub mod rpu;
 
 /// Dolby Vision RPU (as found in AV1 ITU T.35 metadata OBUs)
+#[cfg(feature = "bitstream")]
 pub mod av1;
 
 /// SMPTE ST2094-10 metadata module
+#[cfg(feature = "bitstream")]
 pub mod st2094_10;
 
 /// Various utils
@@ -0,0 +1,565 @@
+use anyhow::{bail, ensure, Result};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::dovi_rpu::DoviRpu;
+use super::{MMR_MAX_COEFFS, NLQ_NUM_PIVOTS, NUM_COMPONENTS};
+use crate::utils::clear_start_code_emulation_prevention_3_byte;
+
+/// A syntax element of the RPU bitstream
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct RpuBitField {
+    /// Offset in bits from the start of the payload
+    pub offset: u64,
+    pub bits: u64,
+    pub name: String,
+
+    /// `None` for padding or unparsed data
+    pub value: Option<i64>,
+}
+
+/// The RPU payload, annotated field by field.
+///
+/// The bitstream is walked independently from `DoviRpu` parsing,
+/// so that the raw bits can be compared against the specification or other parsers.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct AnnotatedRpu {
+    /// Payload starting at the `rpu_nal_prefix`, without emulation prevention bytes
+    pub payload: Vec<u8>,
+    pub fields: Vec<RpuBitField>,
+}
+
+struct AnnotatingReader<'a> {
+    data: &'a [u8],
+    pos: u64,
+    fields: Vec<RpuBitField>,
+}
+
+struct HeaderInfo {
+    rpu_format: u64,
+    coefficient_data_type: u64,
+    coefficient_log2_denom_length: u32,
+    bl_bit_depth: u32,
+    el_bit_depth: u32,
+    disable_residual_flag: bool,
+    compressed_dm_data: bool,
+}
+
+impl AnnotatedRpu {
+    /// HEVC UNSPEC62 NALU, clears start code emulation prevention 3 bytes
+    pub fn from_unspec62_nalu(data: &[u8]) -> Result<Self> {
+        let trimmed_data = DoviRpu::validated_trimmed_data(data)?;
+        let payload = clear_start_code_emulation_prevention_3_byte(trimmed_data);
+
+        let fields = annotate_payload(&payload)?;
+
+        Ok(Self { payload, fields })
+    }
+}
+
+fn annotate_payload(data: &[u8]) -> Result<Vec<RpuBitField>> {
+    let trailing_zeroes = data.iter().rev().take_while(|b| **b == 0).count();
+    ensure!(data.len() > trailing_zeroes + 5, "Invalid RPU length");
+
+    // CRC32 + 0x80 + trailing
+    let final_length = (32 + 8 + (trailing_zeroes * 8)) as u64;
+
+    let mut reader = AnnotatingReader {
+        data,
+        pos: 0,
+        fields: Vec::new(),
+    };
+
+    reader.u("rpu_nal_prefix", 8)?;
+
+    let header = annotate_header(&mut reader)?;
+
+    let vdr_dm_metadata_present_flag = reader.flag("vdr_dm_metadata_present_flag")?;
+    let use_prev_vdr_rpu_flag = reader.flag("use_prev_vdr_rpu_flag")?;
+
+    if use_prev_vdr_rpu_flag {
+        reader.ue("prev_vdr_rpu_id")?;
+    } else {
+        annotate_mapping(&mut reader, &header)?;
+    }
+
+    if vdr_dm_metadata_present_flag {
+        annotate_vdr_dm_data(&mut reader, &header)?;
+
+        annotate_dm_data(&mut reader, "cmv29")?;
+
+        // 16 bits min for required level 254
+        if reader.available() >= final_length + 16 {
+            annotate_dm_data(&mut reader, "cmv40")?;
+        }
+    }
+
+    reader.alignment("rpu_alignment_zero_bit")?;
+
+    if reader.available() > final_length {
+        let remaining = reader.available() - final_length;
+        reader.skip("remaining", remaining)?;
+    }
+
+    reader.u("rpu_data_crc32", 32)?;
+    reader.u("rpu_nal_end", 8)?;
+
+    if trailing_zeroes > 0 {
+        reader.skip("trailing_zero_bytes", trailing_zeroes as u64 * 8)?;
+    }
+
+    Ok(reader.fields)
+}
+
+fn annotate_header(reader: &mut AnnotatingReader) -> Result<HeaderInfo> {
+    reader.u("rpu_type", 6)?;
+    let rpu_format = reader.u("rpu_format", 11)?;
+    reader.u("vdr_rpu_profile", 4)?;
+    reader.u("vdr_rpu_level", 4)?;
+
+    let mut header = HeaderInfo {
+        rpu_format,
+        coefficient_data_type: 0,
+        coefficient_log2_denom_length: 0,
+        bl_bit_depth: 8,
+        el_bit_depth: 8,
+        disable_residual_flag: false,
+        compressed_dm_data: false,
+    };
+
+    if reader.flag("vdr_seq_info_present_flag")? {
+        reader.flag("chroma_resampling_explicit_filter_flag")?;
+        header.coefficient_data_type = reader.u("coefficient_data_type", 2)?;
+
+        header.coefficient_log2_denom_length = match header.coefficient_data_type {
+            0 => reader.ue("coefficient_log2_denom")? as u32,
+            1 => 32,
+            v => bail!("Invalid coefficient_data_type value: {}", v),
+        };
+
+        reader.u("vdr_rpu_normalized_idc", 2)?;
+        reader.flag("bl_video_full_range_flag")?;
+
+        if rpu_format & 0x700 == 0 {
+            header.bl_bit_depth = reader.ue("bl_bit_depth_minus8")? as u32 + 8;
+            // Bits 8 to 15 are the extension mapping idc
+            header.el_bit_depth = (reader.ue("el_bit_depth_minus8")? & 0xFF) as u32 + 8;
+            reader.ue("vdr_bit_depth_minus8")?;
+            reader.flag("spatial_resampling_filter_flag")?;
+            header.compressed_dm_data = reader.u("reserved_zero_3bits", 3)? == 1;
+            reader.flag("el_spatial_resampling_filter_flag")?;
+            header.disable_residual_flag = reader.flag("disable_residual_flag")?;
+        }
+    }
+
+    Ok(header)
+}
+
+fn annotate_mapping(reader: &mut AnnotatingReader, header: &HeaderInfo) -> Result<()> {
+    let denom_length = header.coefficient_log2_denom_length;
+    let coef_int = header.coefficient_data_type == 0;
+
+    reader.ue("vdr_rpu_id")?;
+    reader.ue("mapping_color_space")?;
+    reader.ue("mapping_chroma_format_idc")?;
+
+    let mut num_pieces = [0; NUM_COMPONENTS];
+
+    for (cmp, pieces) in num_pieces.iter_mut().enumerate() {
+        let num_pivots_minus2 = reader.ue(format!("num_pivots_minus2[{cmp}]"))?;
+        *pieces = num_pivots_minus2 + 1;
+
+        for i in 0..num_pivots_minus2 + 2 {
+            reader.u(format!("pred_pivot_value[{cmp}][{i}]"), header.bl_bit_depth)?;
+        }
+    }
+
+    let has_nlq = header.rpu_format & 0x700 == 0 && !header.disable_residual_flag;
+
+    if has_nlq {
+        reader.u("nlq_method_idc", 3)?;
+
+        for i in 0..NLQ_NUM_PIVOTS {
+            reader.u(format!("nlq_pred_pivot_value[{i}]"), header.bl_bit_depth)?;
+        }
+    }
+
+    reader.ue("num_x_partitions_minus1")?;
+    reader.ue("num_y_partitions_minus1")?;
+
+    for (cmp, pieces) in num_pieces.iter().enumerate() {
+        for piece in 0..*pieces {
+            let idx = format!("[{cmp}][{piece}]");
+
+            match reader.ue(format!("mapping_idc{idx}"))? {
+                0 => {
+                    let poly_order_minus1 = reader.ue(format!("poly_order_minus1{idx}"))?;
+
+                    if poly_order_minus1 == 0 && reader.flag(format!("linear_interp_flag{idx}"))? {
+                        bail!("Polynomial linear interpolation is not supported");
+                    }
+
+                    for i in 0..poly_order_minus1 + 2 {
+                        if coef_int {
+                            reader.se(format!("poly_coef_int{idx}[{i}]"))?;
+                        }
+
+                        reader.u(format!("poly_coef{idx}[{i}]"), denom_length)?;
+                    }
+                }
+                1 => {
+                    let mmr_order_minus1 = reader.u(format!("mmr_order_minus1{idx}"), 2)?;
+
+                    if coef_int {
+                        reader.se(format!("mmr_constant_int{idx}"))?;
+                    }
+                    reader.u(format!("mmr_constant{idx}"), denom_length)?;
+
+                    for i in 0..mmr_order_minus1 + 1 {
+                        for j in 0..MMR_MAX_COEFFS {
+                            if coef_int {
+                                reader.se(format!("mmr_coef_int{idx}[{i}][{j}]"))?;
+                            }
+
+                            reader.u(format!("mmr_coef{idx}[{i}][{j}]"), denom_length)?;
+                        }
+                    }
+                }
+                v => bail!("Invalid mapping_idc value: {}", v),
+            }
+        }
+    }
+
+    if has_nlq {
+        for cmp in 0..NUM_COMPONENTS {
+            reader.u(format!("nlq_offset[{cmp}]"), header.el_bit_depth)?;
+
+            for name in [
+                "vdr_in_max",
+                "linear_deadzone_slope",
+                "linear_deadzone_threshold",
+            ] {
+                if coef_int {
+                    reader.ue(format!("{name}_int[{cmp}]"))?;
+                }
+
+                reader.u(format!("{name}[{cmp}]"), denom_length)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn annotate_vdr_dm_data(reader: &mut AnnotatingReader, header: &HeaderInfo) -> Result<()> {
+    reader.ue("affected_dm_metadata_id")?;
+    reader.ue("current_dm_metadata_id")?;
+    reader.ue("scene_refresh_flag")?;
+
+    if header.compressed_dm_data {
+        return Ok(());
+    }
+
+    for i in 0..9 {
+        reader.u(format!("ycc_to_rgb_coef{i}"), 16)?;
+    }
+    for i in 0..3 {
+        reader.u(format!("ycc_to_rgb_offset{i}"), 32)?;
+    }
+    for i in 0..9 {
+        reader.u(format!("rgb_to_lms_coef{i}"), 16)?;
+    }
+
+    reader.u("signal_eotf", 16)?;
+    reader.u("signal_eotf_param0", 16)?;
+    reader.u("signal_eotf_param1", 16)?;
+    reader.u("signal_eotf_param2", 32)?;
+    reader.u("signal_bit_depth", 5)?;
+    reader.u("signal_color_space", 2)?;
+    reader.u("signal_chroma_format", 2)?;
+    reader.u("signal_full_range_flag", 2)?;
+    reader.u("source_min_pq", 12)?;
+    reader.u("source_max_pq", 12)?;
+    reader.u("source_diagonal", 10)?;
+
+    Ok(())
+}
+
+fn annotate_dm_data(reader: &mut AnnotatingReader, version: &str) -> Result<()> {
+    let num_ext_blocks = reader.ue(format!("{version}.num_ext_blocks"))?;
+    reader.alignment(&format!("{version}.dm_alignment_zero_bit"))?;
+
+    for i in 0..num_ext_blocks {
+        let prefix = format!("{version}.ext_block[{i}]");
+
+        let length = reader.ue(format!("{prefix}.ext_block_length"))?;
+        let level = reader.u(format!("{prefix}.ext_block_level"), 8)? as u8;
+
+        let block_end = reader.pos + length * 8;
+
+        for (name, bits) in ext_block_fields(level, length) {
+            if reader.pos + bits as u64 > block_end {
+                break;
+            }
+
+            reader.u(format!("{prefix}.{name}"), bits)?;
+        }
+
+        ensure!(
+            reader.pos <= block_end,
+            "{}: block level {} overruns its length",
+            prefix,
+            level
+        );
+
+        if reader.pos < block_end {
+            let name = if ext_block_fields(level, length).is_empty() {
+                "ext_block_payload"
+            } else {
+                "ext_dm_alignment_zero_bit"
+            };
+
+            reader.skip(&format!("{prefix}.{name}"), block_end - reader.pos)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fields of the known block levels, in bitstream order
+fn ext_block_fields(level: u8, length: u64) -> Vec<(&'static str, u32)> {
+    const PRIMARIES: [&str; 8] = [
+        "primary_red_x",
+        "primary_red_y",
+        "primary_green_x",
+        "primary_green_y",
+        "primary_blue_x",
+        "primary_blue_y",
+        "primary_white_x",
+        "primary_white_y",
+    ];
+
+    let trims = |max_bits| {
+        vec![
+            ("trim_slope", 12),
+            ("trim_offset", 12),
+            ("trim_power", 12),
+            ("trim_chroma_weight", 12),
+            ("trim_saturation_gain", 12),
+            ("ms_weight", max_bits),
+        ]
+    };
+
+    match level {
+        1 => vec![("min_pq", 12), ("max_pq", 12), ("avg_pq", 12)],
+        2 => {
+            let mut fields = vec![("target_max_pq", 12)];
+            fields.extend(trims(13));
+            fields
+        }
+        3 => vec![
+            ("min_pq_offset", 12),
+            ("max_pq_offset", 12),
+            ("avg_pq_offset", 12),
+        ],
+        4 => vec![("anchor_pq", 12), ("anchor_power", 12)],
+        5 => vec![
+            ("active_area_left_offset", 13),
+            ("active_area_right_offset", 13),
+            ("active_area_top_offset", 13),
+            ("active_area_bottom_offset", 13),
+        ],
+        6 => vec![
+            ("max_display_mastering_luminance", 16),
+            ("min_display_mastering_luminance", 16),
+            ("max_content_light_level", 16),
+            ("max_frame_average_light_level", 16),
+        ],
+        8 => {
+            let mut fields = vec![("target_display_index", 8)];
+            fields.extend(trims(12));
+
+            if length > 10 {
+                fields.push(("target_mid_contrast", 12));
+            }
+            if length > 12 {
+                fields.push(("clip_trim", 12));
+            }
+            if length > 13 {
+                fields.extend([
+                    ("saturation_vector_field0", 8),
+                    ("saturation_vector_field1", 8),
+                    ("saturation_vector_field2", 8),
+                    ("saturation_vector_field3", 8),
+                    ("saturation_vector_field4", 8),
+                    ("saturation_vector_field5", 8),
+                ]);
+            }
+            if length > 19 {
+                fields.extend([
+                    ("hue_vector_field0", 8),
+                    ("hue_vector_field1", 8),
+                    ("hue_vector_field2", 8),
+                    ("hue_vector_field3", 8),
+                    ("hue_vector_field4", 8),
+                    ("hue_vector_field5", 8),
+                ]);
+            }
+
+            fields
+        }
+        9 => {
+            let mut fields = vec![("source_primary_index", 8)];
+
+            if length > 1 {
+                fields.extend(PRIMARIES.iter().map(|name| (*name, 16)));
+            }
+
+            fields
+        }
+        10 => {
+            let mut fields = vec![
+                ("target_display_index", 8),
+                ("target_max_pq", 12),
+                ("target_min_pq", 12),
+                ("target_primary_index", 8),
+            ];
+
+            if length > 5 {
+                fields.extend(PRIMARIES.iter().map(|name| (*name, 16)));
+            }
+
+            fields
+        }
+        11 => vec![
+            ("content_type", 8),
+            ("whitepoint", 8),
+            ("reserved_byte2", 8),
+            ("reserved_byte3", 8),
+        ],
+        254 => vec![("dm_mode", 8), ("dm_version_index", 8)],
+        255 => vec![
+            ("dm_run_mode", 8),
+            ("dm_run_version", 8),
+            ("dm_debug0", 8),
+            ("dm_debug1", 8),
+            ("dm_debug2", 8),
+            ("dm_debug3", 8),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+impl<'a> AnnotatingReader<'a> {
+    fn available(&self) -> u64 {
+        (self.data.len() as u64 * 8).saturating_sub(self.pos)
+    }
+
+    fn read_bits(&mut self, n: u32) -> Result<u64> {
+        ensure!(n <= 64, "Cannot read {} bits at once", n);
+        ensure!(
+            self.available() >= n as u64,
+            "Unexpected end of RPU at bit {}",
+            self.pos
+        );
+
+        let mut value = 0;
+
+        for _ in 0..n {
+            let byte = self.data[(self.pos / 8) as usize];
+            let bit = (byte >> (7 - (self.pos % 8))) & 1;
+
+            value = (value << 1) | bit as u64;
+            self.pos += 1;
+        }
+
+        Ok(value)
+    }
+
+    fn push(&mut self, offset: u64, name: impl Into<String>, value: Option<i64>) {
+        self.fields.push(RpuBitField {
+            offset,
+            bits: self.pos - offset,
+            name: name.into(),
+            value,
+        });
+    }
+
+    fn u(&mut self, name: impl Into<String>, n: u32) -> Result<u64> {
+        let offset = self.pos;
+        let value = self.read_bits(n)?;
+
+        self.push(offset, name, Some(value as i64));
+
+        Ok(value)
+    }
+
+    fn flag(&mut self, name: impl Into<String>) -> Result<bool> {
+        Ok(self.u(name, 1)? == 1)
+    }
+
+    fn read_ue(&mut self) -> Result<u64> {
+        let mut leading_zeroes = 0;
+
+        while self.read_bits(1)? == 0 {
+            leading_zeroes += 1;
+            ensure!(leading_zeroes <= 32, "Invalid Exp-Golomb code");
+        }
+
+        Ok((1 << leading_zeroes) - 1 + self.read_bits(leading_zeroes)?)
+    }
+
+    fn ue(&mut self, name: impl Into<String>) -> Result<u64> {
+        let offset = self.pos;
+        let value = self.read_ue()?;
+
+        self.push(offset, name, Some(value as i64));
+
+        Ok(value)
+    }
+
+    fn se(&mut self, name: impl Into<String>) -> Result<i64> {
+        let offset = self.pos;
+        let code = self.read_ue()? as i64;
+
+        let value = if code % 2 == 1 {
+            (code + 1) / 2
+        } else {
+            -(code / 2)
+        };
+
+        self.push(offset, name, Some(value));
+
+        Ok(value)
+    }
+
+    fn skip(&mut self, name: &str, bits: u64) -> Result<()> {
+        ensure!(
+            self.available() >= bits,
+            "Unexpected end of RPU at bit {}",
+            self.pos
+        );
+
+        let offset = self.pos;
+        self.pos += bits;
+
+        self.push(offset, name, None);
+
+        Ok(())
+    }
+
+    fn alignment(&mut self, name: &str) -> Result<()> {
+        if self.pos % 8 != 0 {
+            let offset = self.pos;
+            let value = self.read_bits((8 - self.pos % 8) as u32)?;
+
+            ensure!(value == 0, "{} != 0 at bit {}", name, offset);
+            self.push(offset, name, Some(0));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+//! Reading RPU files written by very old dovi_tool versions.
+//!
+//! Their writer had known bugs, leaving artifacts in the extension metadata:
+//!  - the blocks were written in insertion order, instead of sorted by level.
+//!  - `num_ext_blocks` was not updated after blocks were added or removed.
+//!
+//! With `RpuParseOpts::compat_old` enabled, the parsing corrects the artifacts of the DM sections
+//! and reports each correction as a `ParseWarning`. A corrected RPU is marked as modified,
+//! it is rewritten with the fixed metadata.
+
+use anyhow::Result;
+use bitvec_helpers::bitstream_io_reader::BsIoSliceReader;
+
+use super::extension_metadata::blocks::CustomBlockHandlers;
+use super::extension_metadata::WithExtMetadataBlocks;
+use super::parse_warning::ParseWarning;
+
+/// Shortest extension block: 3 bits `ext_block_length`, 8 bits level and one byte of payload
+pub(crate) const MIN_EXT_BLOCK_BITS: u64 = 3 + 8 + 8;
+
+/// State of a RPU parsed with the corrections
+pub(crate) struct CompatParser<'a> {
+    /// Whole RPU payload, to look ahead without consuming the reader
+    data: &'a [u8],
+    /// Bits after the DM data: CRC32, final byte and trailing zeroes
+    final_length: u64,
+
+    pub(crate) corrections: Vec<ParseWarning>,
+}
+
+impl<'a> CompatParser<'a> {
+    pub(crate) fn new(data: &'a [u8], final_length: u64) -> Self {
+        Self {
+            data,
+            final_length,
+            corrections: Vec::new(),
+        }
+    }
+
+    /// Parses the blocks of a DM section, regardless of the signaled `num_ext_blocks`.
+    ///
+    /// The section ends when no block fits before the CRC32 anymore.
+    /// Past the signaled count, it only continues when the rest of the payload parses as blocks,
+    /// so missing blocks are only recovered in the last DM section.
+    pub(crate) fn parse_blocks<T: WithExtMetadataBlocks>(
+        &mut self,
+        reader: &mut BsIoSliceReader,
+        meta: &mut T,
+        handlers: &CustomBlockHandlers,
+    ) -> Result<()> {
+        let signaled = meta.num_ext_blocks();
+        let mut parsed = 0;
+
+        while reader.available()? >= self.final_length + MIN_EXT_BLOCK_BITS {
+            if parsed >= signaled && !self.only_blocks_follow::<T>(reader, handlers)? {
+                break;
+            }
+
+            meta.parse_block(reader, handlers)?;
+            parsed += 1;
+        }
+
+        if parsed != signaled {
+            meta.set_num_ext_blocks(parsed);
+
+            self.corrections.push(ParseWarning::WrongNumExtBlocks {
+                cm_version: T::VERSION,
+                signaled,
+                parsed,
+            });
+        }
+
+        let misordered = meta
+            .blocks_ref()
+            .windows(2)
+            .any(|pair| pair[0].level() > pair[1].level());
+
+        if misordered {
+            // Stable, keeps the order of the trims of a same level
+            meta.blocks_mut().sort_by_key(|block| block.level());
+
+            self.corrections.push(ParseWarning::MisorderedBlocks {
+                cm_version: T::VERSION,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Whether the bits from the reader position to the CRC32 are blocks of `T`,
+    /// followed by the alignment zero bits. The reader is not consumed.
+    fn only_blocks_follow<T: WithExtMetadataBlocks>(
+        &self,
+        reader: &mut BsIoSliceReader,
+        handlers: &CustomBlockHandlers,
+    ) -> Result<bool> {
+        let position = self.data.len() as u64 * 8 - reader.available()?;
+
+        let mut lookahead = BsIoSliceReader::from_slice(&self.data[(position / 8) as usize..]);
+        for _ in 0..position % 8 {
+            lookahead.get()?;
+        }
+
+        let mut section = T::with_blocks_allocation(1);
+
+        while lookahead.available()? >= self.final_length + MIN_EXT_BLOCK_BITS {
+            if section.parse_block(&mut lookahead, handlers).is_err() {
+                return Ok(false);
+            }
+        }
+
+        let available = lookahead.available()?;
+        if available < self.final_length || available - self.final_length >= 8 {
+            return Ok(false);
+        }
+
+        // rpu_alignment_zero_bit
+        for _ in self.final_length..available {
+            if lookahead.get()? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
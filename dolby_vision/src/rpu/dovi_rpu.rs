@@ -1,7 +1,13 @@
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{bail, ensure, Result};
 use bitvec::prelude::{BitVec, Msb0};
-use bitvec_helpers::{
-    bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
+
+#[cfg(feature = "bitstream")]
+use {
+    anyhow::anyhow,
+    bitvec_helpers::{
+        bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
+    },
+    std::io::Read,
 };
 
 #[cfg(feature = "serde")]
@@ -9,21 +15,40 @@ use serde::Serialize;
 
 use super::extension_metadata::blocks::{ExtMetadataBlock, ExtMetadataBlockLevel5};
 use super::generate::GenerateConfig;
+use super::parse_warning::ParseWarning;
 use super::profiles::profile81::Profile81;
 use super::profiles::profile84::Profile84;
+use super::profiles::RpuProfile;
 use super::rpu_data_header::RpuDataHeader;
 use super::rpu_data_mapping::{DoviNlqMethod, RpuDataMapping};
 use super::rpu_data_nlq::{DoviELType, RpuDataNlq};
-use super::vdr_dm_data::{vdr_dm_data_payload, VdrDmData};
-use super::{compute_crc32, ConversionMode};
-
-use crate::av1::convert_regular_rpu_to_av1_payload;
-use crate::utils::{
-    add_start_code_emulation_prevention_3_byte, clear_start_code_emulation_prevention_3_byte,
+use super::vdr_dm_data::VdrDmData;
+use super::ConversionMode;
+
+#[cfg(feature = "bitstream")]
+use {
+    super::compat::CompatParser,
+    super::compute_crc32,
+    super::extension_metadata::{blocks::CustomBlockHandlers, DmData, WithExtMetadataBlocks},
+    super::nalu_reader::NaluReader,
+    super::vdr_dm_data::vdr_dm_data_payload,
+    crate::av1::convert_regular_rpu_to_av1_payload,
+    crate::utils::{
+        add_start_code_emulation_prevention_3_byte, clear_start_code_emulation_prevention_3_byte,
+    },
 };
 
+#[cfg(feature = "bitstream")]
 const FINAL_BYTE: u8 = 0x80;
 
+/// Options for parsing RPUs, see `DoviRpu::parse_unspec62_nalu_with_opts`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RpuParseOpts {
+    /// Corrects the artifacts of the writer bugs of very old dovi_tool versions, see `compat`
+    pub compat_old: bool,
+}
+
 #[derive(Debug, Default, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct DoviRpu {
@@ -58,6 +83,9 @@ pub struct DoviRpu {
 
     #[cfg_attr(feature = "serde", serde(skip_serializing))]
     original_payload_size: usize,
+
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    parse_warnings: Vec<ParseWarning>,
 }
 
 impl DoviRpu {
@@ -80,15 +108,80 @@ impl DoviRpu {
     }
 
     /// HEVC UNSPEC62 NALU, clears start code emulation prevention 3 bytes
+    #[cfg(feature = "bitstream")]
     pub fn parse_unspec62_nalu(data: &[u8]) -> Result<DoviRpu> {
+        DoviRpu::parse_unspec62_nalu_with_opts(data, RpuParseOpts::default())
+    }
+
+    /// HEVC UNSPEC62 NALU, parsed with `opts`
+    #[cfg(feature = "bitstream")]
+    pub fn parse_unspec62_nalu_with_opts(data: &[u8], opts: RpuParseOpts) -> Result<DoviRpu> {
         let trimmed_data = DoviRpu::validated_trimmed_data(data)?;
 
         // Clear start code emulation prevention 3 byte
         let bytes: Vec<u8> = clear_start_code_emulation_prevention_3_byte(trimmed_data);
 
-        DoviRpu::parse(&bytes)
+        DoviRpu::parse_with(&bytes, opts.compat_old, &CustomBlockHandlers::default())
+    }
+
+    /// HEVC UNSPEC62 NALU with custom extension blocks.
+    ///
+    /// The blocks of the levels with a handler are kept as `ReservedExtMetadataBlock`,
+    /// instead of failing as unknown blocks.
+    #[cfg(feature = "bitstream")]
+    pub fn parse_unspec62_nalu_with_handlers(
+        data: &[u8],
+        handlers: &CustomBlockHandlers,
+    ) -> Result<DoviRpu> {
+        let trimmed_data = DoviRpu::validated_trimmed_data(data)?;
+        let bytes: Vec<u8> = clear_start_code_emulation_prevention_3_byte(trimmed_data);
+
+        DoviRpu::parse_with(&bytes, false, handlers)
     }
 
+    /// HEVC UNSPEC62 NALU written by a very old dovi_tool version.
+    ///
+    /// Corrects the artifacts of its writer bugs, same as `RpuParseOpts::compat_old`.
+    #[cfg(feature = "bitstream")]
+    pub fn parse_unspec62_nalu_compat_old(data: &[u8]) -> Result<DoviRpu> {
+        let opts = RpuParseOpts { compat_old: true };
+
+        DoviRpu::parse_unspec62_nalu_with_opts(data, opts)
+    }
+
+    /// Checks the structure and CRC32 of a HEVC UNSPEC62 NALU, without parsing the RPU
+    #[cfg(feature = "bitstream")]
+    pub fn verify_unspec62_nalu(data: &[u8]) -> Result<()> {
+        let trimmed_data = DoviRpu::validated_trimmed_data(data)?;
+        let bytes: Vec<u8> = clear_start_code_emulation_prevention_3_byte(trimmed_data);
+
+        let trailing_zeroes = bytes.iter().rev().take_while(|b| **b == 0).count();
+        let rpu_end = bytes.len() - trailing_zeroes;
+
+        ensure!(rpu_end > 6, "Invalid RPU length: {}", rpu_end);
+
+        let last_byte = bytes[rpu_end - 1];
+        ensure!(
+            last_byte == FINAL_BYTE,
+            "Invalid RPU last byte: {}",
+            last_byte
+        );
+
+        let crc32_start = rpu_end - 5;
+        let expected_crc32 = u32::from_be_bytes(bytes[crc32_start..rpu_end - 1].try_into()?);
+        let received_crc32 = compute_crc32(&bytes[1..crc32_start]);
+
+        ensure!(
+            received_crc32 == expected_crc32,
+            "RPU CRC32 does not match the data. Received: {}, expected {}",
+            received_crc32,
+            expected_crc32
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "bitstream")]
     pub fn parse_rpu(data: &[u8]) -> Result<DoviRpu> {
         let trimmed_data = DoviRpu::validated_trimmed_data(data)?;
 
@@ -96,11 +189,23 @@ impl DoviRpu {
     }
 
     #[inline(always)]
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(data: &[u8]) -> Result<DoviRpu> {
+        DoviRpu::parse_with(data, false, &CustomBlockHandlers::default())
+    }
+
+    #[cfg(feature = "bitstream")]
+    fn parse_with(
+        data: &[u8],
+        compat_old: bool,
+        handlers: &CustomBlockHandlers,
+    ) -> Result<DoviRpu> {
         let trailing_zeroes = data.iter().rev().take_while(|b| **b == 0).count();
 
         // Ignore trailing bytes
         let rpu_end = data.len() - trailing_zeroes;
+        ensure!(rpu_end > 6, "Invalid RPU length: {}", rpu_end);
+
         let last_byte = data[rpu_end - 1];
 
         // Minus 4 bytes for the CRC32, 1 for the 0x80 ending byte
@@ -113,7 +218,7 @@ impl DoviRpu {
             bail!("Invalid RPU last byte: {}", last_byte);
         }
 
-        let dovi_rpu = DoviRpu::read_rpu_data(data, trailing_zeroes)?;
+        let dovi_rpu = DoviRpu::read_rpu_data(data, trailing_zeroes, compat_old, handlers)?;
 
         if received_crc32 != dovi_rpu.rpu_data_crc32 {
             bail!(
@@ -127,12 +232,20 @@ impl DoviRpu {
     }
 
     #[inline(always)]
-    fn read_rpu_data(bytes: &[u8], trailing_zeroes: usize) -> Result<DoviRpu> {
+    #[cfg(feature = "bitstream")]
+    fn read_rpu_data(
+        bytes: &[u8],
+        trailing_zeroes: usize,
+        compat_old: bool,
+        handlers: &CustomBlockHandlers,
+    ) -> Result<DoviRpu> {
         let mut reader = BsIoSliceReader::from_slice(bytes);
 
         // CRC32 + 0x80 + trailing
         let final_length = (32 + 8 + (trailing_zeroes * 8)) as u64;
 
+        let mut compat = compat_old.then(|| CompatParser::new(bytes, final_length));
+
         let rpu_prefix = reader.get_n(8)?;
         ensure!(rpu_prefix == 25, "rpu_nal_prefix should be 25");
 
@@ -160,7 +273,13 @@ impl DoviRpu {
             .unwrap_or(None);
 
         let vdr_dm_data = if header.vdr_dm_metadata_present_flag {
-            Some(vdr_dm_data_payload(&mut reader, &header, final_length)?)
+            Some(vdr_dm_data_payload(
+                &mut reader,
+                &header,
+                final_length,
+                compat.as_mut(),
+                handlers,
+            )?)
         } else {
             None
         };
@@ -188,7 +307,7 @@ impl DoviRpu {
         let last_byte: u8 = reader.get_n(8)?;
         ensure!(last_byte == FINAL_BYTE, "last byte should be 0x80");
 
-        let dovi_rpu = DoviRpu {
+        let mut dovi_rpu = DoviRpu {
             dovi_profile: header.get_dovi_profile(),
             el_type,
             header,
@@ -199,14 +318,24 @@ impl DoviRpu {
             modified: false,
             trailing_zeroes,
             original_payload_size: bytes.len(),
+            parse_warnings: Vec::new(),
         };
 
         // Validate
         dovi_rpu.validate()?;
 
+        dovi_rpu.parse_warnings = ParseWarning::collect(&dovi_rpu);
+
+        // The corrected metadata differs from the bitstream
+        if let Some(compat) = compat.filter(|compat| !compat.corrections.is_empty()) {
+            dovi_rpu.modified = true;
+            dovi_rpu.parse_warnings.extend(compat.corrections);
+        }
+
         Ok(dovi_rpu)
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write_hevc_unspec62_nalu(&self) -> Result<Vec<u8>> {
         let mut out = self.write_rpu_data()?;
         add_start_code_emulation_prevention_3_byte(&mut out);
@@ -218,10 +347,65 @@ impl DoviRpu {
         Ok(out)
     }
 
+    /// Same as `write_hevc_unspec62_nalu`, with the custom extension blocks payload
+    /// written by their handler. Fails for custom blocks without a handler.
+    #[cfg(feature = "bitstream")]
+    pub fn write_hevc_unspec62_nalu_with_handlers(
+        &self,
+        handlers: &CustomBlockHandlers,
+    ) -> Result<Vec<u8>> {
+        let mut rpu = self.clone();
+
+        if let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() {
+            let dm_data = vdr_dm_data
+                .cmv29_metadata
+                .iter_mut()
+                .chain(vdr_dm_data.cmv40_metadata.iter_mut());
+
+            for dm_data in dm_data {
+                let blocks = match dm_data {
+                    DmData::V29(cmv29) => cmv29.blocks_mut(),
+                    DmData::V40(cmv40) => cmv40.blocks_mut(),
+                };
+
+                for block in blocks.iter_mut() {
+                    if let ExtMetadataBlock::Reserved(block) = block {
+                        handlers.apply_write(block)?;
+                    }
+                }
+            }
+        }
+
+        rpu.write_hevc_unspec62_nalu()
+    }
+
+    /// Same as `write_hevc_unspec62_nalu`, prefixed with a 3 or 4 bytes start code
+    #[cfg(feature = "bitstream")]
+    pub fn write_hevc_unspec62_nalu_with_start_code(
+        &self,
+        start_code_length: usize,
+    ) -> Result<Vec<u8>> {
+        let start_code: &[u8] = match start_code_length {
+            3 => &[0, 0, 1],
+            4 => &[0, 0, 0, 1],
+            _ => bail!("Invalid start code length {start_code_length}, must be 3 or 4"),
+        };
+
+        let nalu = self.write_hevc_unspec62_nalu()?;
+
+        let mut out = Vec::with_capacity(start_code.len() + nalu.len());
+        out.extend_from_slice(start_code);
+        out.extend_from_slice(&nalu);
+
+        Ok(out)
+    }
+
+    #[cfg(feature = "bitstream")]
     pub fn write_rpu(&self) -> Result<Vec<u8>> {
         self.write_rpu_data()
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write_av1_rpu_metadata_obu_t35_payload(&self) -> Result<Vec<u8>> {
         let mut encoded_rpu = self.write_rpu_data()?;
         convert_regular_rpu_to_av1_payload(encoded_rpu.as_mut())?;
@@ -230,6 +414,7 @@ impl DoviRpu {
     }
 
     #[inline(always)]
+    #[cfg(feature = "bitstream")]
     fn write_rpu_data(&self) -> Result<Vec<u8>> {
         let mut num_bytes: usize = 72;
         let header = &self.header;
@@ -243,7 +428,7 @@ impl DoviRpu {
         }
         // Capacity is in bits
         let mut writer = BitstreamIoWriter::with_capacity(num_bytes * 8);
-        
+
         self.validate()?;
 
         // RPU prefix
@@ -263,11 +448,12 @@ impl DoviRpu {
             .to_owned())
     }
 
+    #[cfg(feature = "bitstream")]
     fn validate(&self) -> Result<()> {
         self.header.validate(self.dovi_profile)?;
 
         if let Some(mapping) = self.rpu_data_mapping.as_ref() {
-            mapping.validate(self.dovi_profile)?;
+            mapping.validate(self.dovi_profile, self.header.bl_bit_depth_minus8 as u8 + 8)?;
         }
 
         if let Some(vdr_dm_data) = &self.vdr_dm_data {
@@ -277,6 +463,18 @@ impl DoviRpu {
         Ok(())
     }
 
+    /// Recoverable oddities found when the RPU was parsed.
+    ///
+    /// Empty for RPUs that were not parsed from a bitstream.
+    pub fn parse_warnings(&self) -> &[ParseWarning] {
+        &self.parse_warnings
+    }
+
+    /// Profile with its EL type, for the capability queries
+    pub fn profile(&self) -> RpuProfile {
+        RpuProfile::new(self.dovi_profile, self.el_type)
+    }
+
     pub fn get_enhancement_layer_type(&self) -> Option<DoviELType> {
         self.rpu_data_mapping
             .as_ref()
@@ -309,12 +507,12 @@ impl DoviRpu {
                 self.convert_to_mel()?;
                 true
             }
-            ConversionMode::To81 => match self.dovi_profile {
-                7 | 8 => {
+            ConversionMode::To81 => match self.profile() {
+                RpuProfile::Profile7 { .. } | RpuProfile::Profile8 => {
                     self.convert_to_p81_remove_mapping();
                     true
                 }
-                5 => {
+                RpuProfile::Profile5 => {
                     self.p5_to_p81()?;
                     true
                 }
@@ -325,7 +523,10 @@ impl DoviRpu {
                 true
             }
             ConversionMode::To81MappingPreserved => {
-                if matches!(self.dovi_profile, 7 | 8) {
+                if matches!(
+                    self.profile(),
+                    RpuProfile::Profile7 { .. } | RpuProfile::Profile8
+                ) {
                     self.convert_to_p81();
                     true
                 } else {
@@ -357,6 +558,7 @@ impl DoviRpu {
     }
 
     fn convert_to_mel(&mut self) -> Result<()> {
+        let profile = self.profile();
         let header = &mut self.header;
 
         header.el_spatial_resampling_filter_flag = true;
@@ -371,7 +573,7 @@ impl DoviRpu {
 
             if let Some(nlq) = mapping.nlq.as_mut() {
                 nlq.convert_to_mel();
-            } else if self.dovi_profile == 8 {
+            } else if profile == RpuProfile::Profile8 {
                 mapping.nlq = Some(RpuDataNlq::mel_default());
             } else {
                 bail!("Not profile 7 or 8, cannot convert to MEL!");
@@ -410,17 +612,16 @@ impl DoviRpu {
         self.modified = true;
         self.convert_to_p81();
 
-        if let Some(el_type) = self.el_type.as_ref() {
-            if el_type == &DoviELType::FEL {
-                self.remove_mapping();
-            }
+        // The residual is lost without the EL
+        if self.profile().requires_el() {
+            self.remove_mapping();
         }
     }
 
     fn p5_to_p81(&mut self) -> Result<()> {
         self.modified = true;
 
-        if self.dovi_profile == 5 {
+        if self.profile() == RpuProfile::Profile5 {
             self.convert_to_p81();
 
             self.dovi_profile = 8;
@@ -462,6 +663,17 @@ impl DoviRpu {
         })
     }
 
+    /// Profile 8.1 RPU with default metadata, standing in for a corrupt RPU
+    pub fn placeholder() -> Result<Self> {
+        let mut rpu = Self::profile81_config(&GenerateConfig::default())?;
+
+        if let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() {
+            vdr_dm_data.set_scene_cut(true);
+        }
+
+        Ok(rpu)
+    }
+
     /// Set existing L5 metadata to zero offsets
     /// If there is no L5 metadata, creates it with zero offsets
     pub fn crop(&mut self) -> Result<()> {
@@ -494,6 +706,30 @@ impl DoviRpu {
         Ok(())
     }
 
+    /// Rewrites the BL bit depth signaling, 10 or 12 bits.
+    ///
+    /// The mapping pivots are rescaled to the new bit depth.
+    pub fn set_bl_bit_depth(&mut self, bit_depth: u8) -> Result<()> {
+        ensure!(
+            matches!(bit_depth, 10 | 12),
+            "Unsupported BL bit depth {}, should be 10 or 12",
+            bit_depth
+        );
+
+        let current_bit_depth = self.header.bl_bit_depth_minus8 as u8 + 8;
+
+        if current_bit_depth != bit_depth {
+            self.modified = true;
+            self.header.bl_bit_depth_minus8 = (bit_depth - 8) as u64;
+
+            if let Some(rpu_data_mapping) = self.rpu_data_mapping.as_mut() {
+                rpu_data_mapping.rescale_pivots(current_bit_depth, bit_depth);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn remove_mapping(&mut self) {
         self.modified = true;
 
@@ -502,6 +738,7 @@ impl DoviRpu {
         }
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn parse_list_of_unspec62_nalus(data: &[Vec<u8>]) -> Vec<DoviRpu> {
         data.iter()
             .map(|rpu| DoviRpu::parse_unspec62_nalu(rpu))
@@ -539,6 +776,22 @@ impl DoviRpu {
         Ok(())
     }
 
+    /// Removes the CM v2.9 trims (L2) and L4, superseded by the CM v4.0 metadata.
+    ///
+    /// Only applies to RPUs with CM v4.0 metadata. L1, L5, L6 and L255 are used by both versions and kept.
+    pub fn remove_cmv29_extension_metadata(&mut self) -> Result<()> {
+        if let Some(vdr_dm_data) = self.vdr_dm_data.as_mut() {
+            if vdr_dm_data.cmv40_metadata.is_some() {
+                self.modified = true;
+
+                vdr_dm_data.remove_metadata_level(2);
+                vdr_dm_data.remove_metadata_level(4);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn replace_levels_from_rpu(&mut self, src_rpu: &Self, levels: &Vec<u8>) -> Result<()> {
         ensure!(!levels.is_empty(), "Must have levels to replace");
 
@@ -556,3 +809,61 @@ impl DoviRpu {
         Ok(())
     }
 }
+
+/// Streaming parser of the RPUs of an HEVC Annex B stream or RPU file, from any reader.
+///
+/// The stream is read in chunks, only the NAL unit being parsed is buffered.
+/// The NAL units other than UNSPEC62 are skipped.
+#[cfg(feature = "bitstream")]
+pub struct DoviRpuReader<R: Read> {
+    nalus: NaluReader<R>,
+    /// RPUs read so far, valid or not
+    rpu_count: usize,
+}
+
+#[cfg(feature = "bitstream")]
+impl<R: Read> DoviRpuReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            nalus: NaluReader::new(reader),
+            rpu_count: 0,
+        }
+    }
+
+    /// Next RPU, none at the end of the stream.
+    ///
+    /// An invalid RPU is an error, the reading can continue with the following ones.
+    pub fn next_rpu(&mut self) -> Result<Option<DoviRpu>> {
+        while let Some(nalu) = self.nalus.next_nalu()? {
+            if !nalu.is_rpu() {
+                continue;
+            }
+
+            let index = self.rpu_count;
+            self.rpu_count += 1;
+
+            let rpu = nalu
+                .rpu_payload()
+                .and_then(|payload| DoviRpu::parse_rpu(&payload))
+                .map_err(|e| anyhow!("Invalid RPU {index}: {e}"))?;
+
+            return Ok(Some(rpu));
+        }
+
+        Ok(None)
+    }
+
+    /// Number of RPUs read so far, including the invalid ones
+    pub fn rpu_count(&self) -> usize {
+        self.rpu_count
+    }
+}
+
+#[cfg(feature = "bitstream")]
+impl<R: Read> Iterator for DoviRpuReader<R> {
+    type Item = Result<DoviRpu>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_rpu().transpose()
+    }
+}
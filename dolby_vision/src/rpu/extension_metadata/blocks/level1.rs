@@ -1,4 +1,5 @@
 use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -7,8 +8,11 @@ use bitvec_helpers::{
 use serde::{Deserialize, Serialize};
 
 use crate::rpu::vdr_dm_data::CmVersion;
+use crate::utils::{nits_to_pq12, pq12_to_nits};
 
-use super::{ExtMetadataBlock, ExtMetadataBlockInfo};
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::ExtMetadataBlockInfo;
 
 /// cbindgen:ignore
 pub const L1_MIN_PQ_MAX_VALUE: u16 = 12;
@@ -23,7 +27,7 @@ pub const L1_AVG_PQ_MIN_VALUE_CMV40: u16 = 1229;
 
 /// Statistical analysis of the frame: min, max, avg brightness.
 #[repr(C)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct ExtMetadataBlockLevel1 {
     pub min_pq: u16,
@@ -31,7 +35,17 @@ pub struct ExtMetadataBlockLevel1 {
     pub avg_pq: u16,
 }
 
+/// L1 statistics in nits
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Level1Nits {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
 impl ExtMetadataBlockLevel1 {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader) -> Result<ExtMetadataBlock> {
         Ok(ExtMetadataBlock::Level1(Self {
             min_pq: reader.get_n(12)?,
@@ -40,6 +54,7 @@ impl ExtMetadataBlockLevel1 {
         }))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         self.validate()?;
 
@@ -58,6 +73,28 @@ impl ExtMetadataBlockLevel1 {
         Ok(())
     }
 
+    /// Whether the statistics are inverted: `min_pq` above `max_pq`, or `avg_pq` outside of them
+    pub fn has_inconsistent_stats(&self) -> bool {
+        self.min_pq > self.max_pq || self.avg_pq > self.max_pq || self.avg_pq < self.min_pq
+    }
+
+    /// Swaps inverted min/max values and clamps `avg_pq` between them.
+    ///
+    /// Returns whether the block was modified.
+    pub fn fix_inconsistent_stats(&mut self) -> bool {
+        if !self.has_inconsistent_stats() {
+            return false;
+        }
+
+        if self.min_pq > self.max_pq {
+            std::mem::swap(&mut self.min_pq, &mut self.max_pq);
+        }
+
+        self.avg_pq = self.avg_pq.clamp(self.min_pq, self.max_pq);
+
+        true
+    }
+
     pub fn new(min_pq: u16, max_pq: u16, avg_pq: u16) -> ExtMetadataBlockLevel1 {
         ExtMetadataBlockLevel1 {
             min_pq,
@@ -90,11 +127,48 @@ impl ExtMetadataBlockLevel1 {
         block
     }
 
+    /// Returns a L1 metadata block from normalized PQ statistics, clamped to valid values
+    pub fn from_normalized_pq_cm_version(
+        min_pq: f64,
+        max_pq: f64,
+        avg_pq: f64,
+        cm_version: CmVersion,
+    ) -> ExtMetadataBlockLevel1 {
+        let to_pq12 = |pq: f64| (pq * 4095.0).round().clamp(0.0, 4095.0) as u16;
+
+        Self::from_stats_cm_version(
+            to_pq12(min_pq),
+            to_pq12(max_pq),
+            to_pq12(avg_pq),
+            cm_version,
+        )
+    }
+
     pub fn clamp_values_cm_version(&mut self, cm_version: CmVersion) {
         self.clamp_values_int(cm_version);
     }
 }
 
+impl From<&ExtMetadataBlockLevel1> for Level1Nits {
+    fn from(block: &ExtMetadataBlockLevel1) -> Self {
+        Self {
+            min: pq12_to_nits(block.min_pq),
+            max: pq12_to_nits(block.max_pq),
+            avg: pq12_to_nits(block.avg_pq),
+        }
+    }
+}
+
+impl From<Level1Nits> for ExtMetadataBlockLevel1 {
+    fn from(nits: Level1Nits) -> Self {
+        Self::new(
+            nits_to_pq12(nits.min),
+            nits_to_pq12(nits.max),
+            nits_to_pq12(nits.avg),
+        )
+    }
+}
+
 impl ExtMetadataBlockInfo for ExtMetadataBlockLevel1 {
     fn level(&self) -> u8 {
         1
@@ -1,4 +1,5 @@
 use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,7 +7,9 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 
-use super::{level6::MAX_PQ_LUMINANCE, ColorPrimaries, ExtMetadataBlock, ExtMetadataBlockInfo};
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::{level6::MAX_PQ_LUMINANCE, ColorPrimaries, ExtMetadataBlockInfo};
 
 pub const PRESET_TARGET_DISPLAYS: &[u8] = &[1, 16, 18, 21, 27, 28, 37, 38, 42, 48, 49];
 
@@ -18,7 +21,7 @@ pub const PRESET_TARGET_DISPLAYS: &[u8] = &[1, 16, 18, 21, 27, 28, 37, 38, 42, 4
 ///     - 5: target_primary_index
 ///     - 21: target_primary_{red,green,blue,white}_{x,y}
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize))]
 #[cfg_attr(feature = "serde", serde(default))]
 pub struct ExtMetadataBlockLevel10 {
@@ -39,7 +42,14 @@ pub struct ExtMetadataBlockLevel10 {
 }
 
 impl ExtMetadataBlockLevel10 {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader, length: u64) -> Result<ExtMetadataBlock> {
+        ensure!(
+            matches!(length, 5 | 21),
+            "Invalid L10 block length: {}",
+            length
+        );
+
         let mut block = Self {
             length,
             target_display_index: reader.get_n(8)?,
@@ -63,6 +73,7 @@ impl ExtMetadataBlockLevel10 {
         Ok(ExtMetadataBlock::Level10(block))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         self.validate()?;
 
@@ -1,4 +1,5 @@
 use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,13 +7,16 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use super::{ExtMetadataBlock, ExtMetadataBlockInfo};
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::ExtMetadataBlockInfo;
 
+#[cfg(feature = "bitstream")]
 const MAX_WHITEPOINT_VALUE: u8 = 15;
 
 /// Content type metadata level
 #[repr(C)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct ExtMetadataBlockLevel11 {
     pub content_type: u8,
@@ -26,6 +30,7 @@ pub struct ExtMetadataBlockLevel11 {
 }
 
 impl ExtMetadataBlockLevel11 {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader) -> Result<ExtMetadataBlock> {
         let mut l11 = Self {
             content_type: reader.get_n(8)?,
@@ -43,6 +48,7 @@ impl ExtMetadataBlockLevel11 {
         Ok(ExtMetadataBlock::Level11(l11))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         self.validate()?;
 
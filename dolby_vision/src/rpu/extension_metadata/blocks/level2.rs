@@ -1,4 +1,5 @@
 use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,13 +7,17 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use crate::utils::nits_to_pq;
+use crate::utils::{
+    nits_to_pq, nits_to_pq12, normalized_to_trim12, pq12_to_nits, trim12_to_normalized,
+};
 
-use super::{ExtMetadataBlock, ExtMetadataBlockInfo, MAX_12_BIT_VALUE};
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::{ExtMetadataBlockInfo, MAX_12_BIT_VALUE};
 
 /// Creative intent trim passes per target display peak brightness
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 #[cfg_attr(feature = "serde", serde(default))]
 pub struct ExtMetadataBlockLevel2 {
@@ -26,7 +31,22 @@ pub struct ExtMetadataBlockLevel2 {
     pub ms_weight: i16,
 }
 
+/// L2 trims with the target in nits, and the trims normalized to -1.0..1.0
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Level2Trims {
+    pub target_max_nits: f64,
+
+    pub trim_slope: f64,
+    pub trim_offset: f64,
+    pub trim_power: f64,
+    pub trim_chroma_weight: f64,
+    pub trim_saturation_gain: f64,
+    pub ms_weight: f64,
+}
+
 impl ExtMetadataBlockLevel2 {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader) -> Result<ExtMetadataBlock> {
         let mut level2 = Self {
             target_max_pq: reader.get_n(12)?,
@@ -45,6 +65,7 @@ impl ExtMetadataBlockLevel2 {
         Ok(ExtMetadataBlock::Level2(level2))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         self.validate()?;
 
@@ -79,6 +100,52 @@ impl ExtMetadataBlockLevel2 {
     }
 }
 
+impl Level2Trims {
+    /// Applies the trims to a signal normalized to the target display range.
+    ///
+    /// The trims are `((x * slope) + offset) ^ power`, with the slope and power in 0.5..1.5
+    /// and the offset in -0.5..0.5. Negative values are clamped to zero before the power.
+    ///
+    /// This is the inverse of the CM XML lift, gain and gamma conversion: the signal is remapped
+    /// from `lift / 2..1`, scaled by `1 + gain / 2`, and raised to `1 / (1 + gamma / 2)`.
+    pub fn apply(&self, x: f64) -> f64 {
+        let slope = 1.0 + self.trim_slope / 2.0;
+        let offset = self.trim_offset / 2.0;
+        let power = 1.0 + self.trim_power / 2.0;
+
+        (x * slope + offset).max(0.0).powf(power)
+    }
+}
+
+impl From<&ExtMetadataBlockLevel2> for Level2Trims {
+    fn from(block: &ExtMetadataBlockLevel2) -> Self {
+        Self {
+            target_max_nits: pq12_to_nits(block.target_max_pq),
+            trim_slope: trim12_to_normalized(block.trim_slope),
+            trim_offset: trim12_to_normalized(block.trim_offset),
+            trim_power: trim12_to_normalized(block.trim_power),
+            trim_chroma_weight: trim12_to_normalized(block.trim_chroma_weight),
+            trim_saturation_gain: trim12_to_normalized(block.trim_saturation_gain),
+            // -1 is only used for disabled weights
+            ms_weight: (block.ms_weight as f64 - 2048.0) / 2048.0,
+        }
+    }
+}
+
+impl From<Level2Trims> for ExtMetadataBlockLevel2 {
+    fn from(trims: Level2Trims) -> Self {
+        Self {
+            target_max_pq: nits_to_pq12(trims.target_max_nits),
+            trim_slope: normalized_to_trim12(trims.trim_slope),
+            trim_offset: normalized_to_trim12(trims.trim_offset),
+            trim_power: normalized_to_trim12(trims.trim_power),
+            trim_chroma_weight: normalized_to_trim12(trims.trim_chroma_weight),
+            trim_saturation_gain: normalized_to_trim12(trims.trim_saturation_gain),
+            ms_weight: normalized_to_trim12(trims.ms_weight) as i16,
+        }
+    }
+}
+
 impl ExtMetadataBlockInfo for ExtMetadataBlockLevel2 {
     fn level(&self) -> u8 {
         2
@@ -1,4 +1,6 @@
+#[cfg(feature = "bitstream")]
 use anyhow::Result;
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,11 +8,13 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use super::{ExtMetadataBlock, ExtMetadataBlockInfo};
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::ExtMetadataBlockInfo;
 
 /// Metadata level present in CM v4.0
 #[repr(C)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 #[cfg_attr(feature = "serde", serde(default))]
 pub struct ExtMetadataBlockLevel254 {
@@ -19,6 +23,7 @@ pub struct ExtMetadataBlockLevel254 {
 }
 
 impl ExtMetadataBlockLevel254 {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader) -> Result<ExtMetadataBlock> {
         Ok(ExtMetadataBlock::Level254(Self {
             dm_mode: reader.get_n(8)?,
@@ -26,6 +31,7 @@ impl ExtMetadataBlockLevel254 {
         }))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         writer.write_n(&self.dm_mode, 8)?;
         writer.write_n(&self.dm_version_index, 8)?;
@@ -1,4 +1,6 @@
+#[cfg(feature = "bitstream")]
 use anyhow::Result;
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,12 +8,14 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use super::{ExtMetadataBlock, ExtMetadataBlockInfo};
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::ExtMetadataBlockInfo;
 
 /// Metadata level optionally present in CM v2.9.
 /// Different display modes (calibration/verify/bypass), debugging
 #[repr(C)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 #[cfg_attr(feature = "serde", serde(default))]
 pub struct ExtMetadataBlockLevel255 {
@@ -24,6 +28,7 @@ pub struct ExtMetadataBlockLevel255 {
 }
 
 impl ExtMetadataBlockLevel255 {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader) -> Result<ExtMetadataBlock> {
         Ok(ExtMetadataBlock::Level255(Self {
             dm_run_mode: reader.get_n(8)?,
@@ -35,6 +40,7 @@ impl ExtMetadataBlockLevel255 {
         }))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         writer.write_n(&self.dm_run_mode, 8)?;
         writer.write_n(&self.dm_run_version, 8)?;
@@ -1,4 +1,5 @@
 use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,11 +7,15 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use super::{ExtMetadataBlock, ExtMetadataBlockInfo, MAX_12_BIT_VALUE};
+use crate::utils::{normalized_to_trim12, trim12_to_normalized};
+
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::{ExtMetadataBlockInfo, MAX_12_BIT_VALUE};
 
 /// Level 1 offsets.
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 #[cfg_attr(feature = "serde", serde(default))]
 pub struct ExtMetadataBlockLevel3 {
@@ -19,7 +24,17 @@ pub struct ExtMetadataBlockLevel3 {
     pub avg_pq_offset: u16,
 }
 
+/// L3 offsets normalized to -1.0..1.0
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Level3Offsets {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
 impl ExtMetadataBlockLevel3 {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader) -> Result<ExtMetadataBlock> {
         Ok(ExtMetadataBlock::Level3(Self {
             min_pq_offset: reader.get_n(12)?,
@@ -28,6 +43,7 @@ impl ExtMetadataBlockLevel3 {
         }))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         self.validate()?;
 
@@ -47,6 +63,26 @@ impl ExtMetadataBlockLevel3 {
     }
 }
 
+impl From<&ExtMetadataBlockLevel3> for Level3Offsets {
+    fn from(block: &ExtMetadataBlockLevel3) -> Self {
+        Self {
+            min: trim12_to_normalized(block.min_pq_offset),
+            max: trim12_to_normalized(block.max_pq_offset),
+            avg: trim12_to_normalized(block.avg_pq_offset),
+        }
+    }
+}
+
+impl From<Level3Offsets> for ExtMetadataBlockLevel3 {
+    fn from(offsets: Level3Offsets) -> Self {
+        Self {
+            min_pq_offset: normalized_to_trim12(offsets.min),
+            max_pq_offset: normalized_to_trim12(offsets.max),
+            avg_pq_offset: normalized_to_trim12(offsets.avg),
+        }
+    }
+}
+
 impl ExtMetadataBlockInfo for ExtMetadataBlockLevel3 {
     fn level(&self) -> u8 {
         3
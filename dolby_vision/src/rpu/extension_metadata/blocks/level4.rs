@@ -1,4 +1,5 @@
 use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,11 +7,13 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use super::{ExtMetadataBlock, ExtMetadataBlockInfo, MAX_12_BIT_VALUE};
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::{ExtMetadataBlockInfo, MAX_12_BIT_VALUE};
 
 /// Something about temporal stability
 #[repr(C)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct ExtMetadataBlockLevel4 {
     pub anchor_pq: u16,
@@ -18,6 +21,7 @@ pub struct ExtMetadataBlockLevel4 {
 }
 
 impl ExtMetadataBlockLevel4 {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader) -> Result<ExtMetadataBlock> {
         Ok(ExtMetadataBlock::Level4(Self {
             anchor_pq: reader.get_n(12)?,
@@ -25,6 +29,7 @@ impl ExtMetadataBlockLevel4 {
         }))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         self.validate()?;
 
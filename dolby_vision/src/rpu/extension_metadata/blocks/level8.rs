@@ -1,4 +1,5 @@
 use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,7 +7,11 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 
-use super::{ExtMetadataBlock, ExtMetadataBlockInfo, MAX_12_BIT_VALUE};
+use crate::utils::{normalized_to_trim12, trim12_to_normalized};
+
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::{ExtMetadataBlockInfo, MAX_12_BIT_VALUE};
 
 /// Creative intent trim passes per target display peak brightness
 /// For CM v4.0, L8 metadata only is present and used to compute L2
@@ -20,7 +25,7 @@ use super::{ExtMetadataBlock, ExtMetadataBlockInfo, MAX_12_BIT_VALUE};
 ///     - 19: saturation_vector_field[0-5]
 ///     - 25: hue_vector_field[0-5]
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize))]
 #[cfg_attr(feature = "serde", serde(default))]
 pub struct ExtMetadataBlockLevel8 {
@@ -52,8 +57,32 @@ pub struct ExtMetadataBlockLevel8 {
     pub hue_vector_field5: u8,
 }
 
+/// L8 trims normalized to -1.0..1.0, without the saturation and hue vectors
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Level8Trims {
+    pub target_display_index: u8,
+
+    pub trim_slope: f64,
+    pub trim_offset: f64,
+    pub trim_power: f64,
+    pub trim_chroma_weight: f64,
+    pub trim_saturation_gain: f64,
+    pub ms_weight: f64,
+
+    pub target_mid_contrast: f64,
+    pub clip_trim: f64,
+}
+
 impl ExtMetadataBlockLevel8 {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader, length: u64) -> Result<ExtMetadataBlock> {
+        ensure!(
+            matches!(length, 10 | 12 | 13 | 19 | 25),
+            "Invalid L8 block length: {}",
+            length
+        );
+
         let mut block = Self {
             length,
             target_display_index: reader.get_n(8)?,
@@ -95,6 +124,7 @@ impl ExtMetadataBlockLevel8 {
         Ok(ExtMetadataBlock::Level8(block))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         self.validate()?;
 
@@ -148,6 +178,117 @@ impl ExtMetadataBlockLevel8 {
 
         Ok(())
     }
+
+    /// Saturation vector fields, in order: red, yellow, green, cyan, blue, magenta
+    pub fn saturation_vectors(&self) -> [u8; 6] {
+        [
+            self.saturation_vector_field0,
+            self.saturation_vector_field1,
+            self.saturation_vector_field2,
+            self.saturation_vector_field3,
+            self.saturation_vector_field4,
+            self.saturation_vector_field5,
+        ]
+    }
+
+    /// Hue vector fields, in order: red, yellow, green, cyan, blue, magenta
+    pub fn hue_vectors(&self) -> [u8; 6] {
+        [
+            self.hue_vector_field0,
+            self.hue_vector_field1,
+            self.hue_vector_field2,
+            self.hue_vector_field3,
+            self.hue_vector_field4,
+            self.hue_vector_field5,
+        ]
+    }
+
+    /// Sets the saturation vector fields, extending the block length to include them
+    pub fn set_saturation_vectors(&mut self, vectors: [u8; 6]) {
+        [
+            self.saturation_vector_field0,
+            self.saturation_vector_field1,
+            self.saturation_vector_field2,
+            self.saturation_vector_field3,
+            self.saturation_vector_field4,
+            self.saturation_vector_field5,
+        ] = vectors;
+
+        self.length = self.length.max(19);
+    }
+
+    /// Sets the hue vector fields, extending the block length to include them
+    pub fn set_hue_vectors(&mut self, vectors: [u8; 6]) {
+        [
+            self.hue_vector_field0,
+            self.hue_vector_field1,
+            self.hue_vector_field2,
+            self.hue_vector_field3,
+            self.hue_vector_field4,
+            self.hue_vector_field5,
+        ] = vectors;
+
+        self.length = 25;
+    }
+
+    /// Copy of the trim, with `clip_trim` raised by the PQ range above a custom target peak.
+    ///
+    /// For content mapped to a `target_pq` lower than its actual `max_pq` (both normalized PQ),
+    /// so that the highlights discarded by the target are also clipped with CM v4.0 trims.
+    pub fn with_clip_to_target(&self, max_pq: f64, target_pq: f64) -> Self {
+        let clipped_range = (max_pq - target_pq).clamp(0.0, 1.0);
+        let clip_trim = trim12_to_normalized(self.clip_trim) + clipped_range;
+
+        Self {
+            length: self.length.max(13),
+            clip_trim: normalized_to_trim12(clip_trim),
+            ..self.clone()
+        }
+    }
+}
+
+impl From<&ExtMetadataBlockLevel8> for Level8Trims {
+    fn from(block: &ExtMetadataBlockLevel8) -> Self {
+        Self {
+            target_display_index: block.target_display_index,
+            trim_slope: trim12_to_normalized(block.trim_slope),
+            trim_offset: trim12_to_normalized(block.trim_offset),
+            trim_power: trim12_to_normalized(block.trim_power),
+            trim_chroma_weight: trim12_to_normalized(block.trim_chroma_weight),
+            trim_saturation_gain: trim12_to_normalized(block.trim_saturation_gain),
+            ms_weight: trim12_to_normalized(block.ms_weight),
+            target_mid_contrast: trim12_to_normalized(block.target_mid_contrast),
+            clip_trim: trim12_to_normalized(block.clip_trim),
+        }
+    }
+}
+
+impl From<Level8Trims> for ExtMetadataBlockLevel8 {
+    /// The block length only includes the mid contrast and clip trims if they are set
+    fn from(trims: Level8Trims) -> Self {
+        let target_mid_contrast = normalized_to_trim12(trims.target_mid_contrast);
+        let clip_trim = normalized_to_trim12(trims.clip_trim);
+
+        let length = if target_mid_contrast != 2048 || clip_trim != 2048 {
+            13
+        } else {
+            10
+        };
+
+        Self {
+            length,
+            target_display_index: trims.target_display_index,
+            trim_slope: normalized_to_trim12(trims.trim_slope),
+            trim_offset: normalized_to_trim12(trims.trim_offset),
+            trim_power: normalized_to_trim12(trims.trim_power),
+            trim_chroma_weight: normalized_to_trim12(trims.trim_chroma_weight),
+            trim_saturation_gain: normalized_to_trim12(trims.trim_saturation_gain),
+            ms_weight: normalized_to_trim12(trims.ms_weight),
+            target_mid_contrast,
+            clip_trim,
+            ..Default::default()
+        }
+    }
 }
 
 impl ExtMetadataBlockInfo for ExtMetadataBlockLevel8 {
@@ -1,4 +1,5 @@
 use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,9 +7,12 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 
+use crate::rpu::extension_metadata::primaries::PREDEFINED_COLORSPACE_PRIMARIES;
 use crate::rpu::extension_metadata::MasteringDisplayPrimaries;
 
-use super::{ColorPrimaries, ExtMetadataBlock, ExtMetadataBlockInfo};
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::{ColorPrimaries, ExtMetadataBlockInfo};
 
 pub const PREDEFINED_REALDEVICE_PRIMARIES: &[[f64; 8]] = &[
     [0.693, 0.304, 0.208, 0.761, 0.1467, 0.0527, 0.3127, 0.329],
@@ -39,7 +43,7 @@ pub const PREDEFINED_REALDEVICE_PRIMARIES: &[[f64; 8]] = &[
 ///     - 1: source_primary_index
 ///     - 17: source_primary_{red,green,blue,white}_{x,y}
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize))]
 #[cfg_attr(feature = "serde", serde(default))]
 pub struct ExtMetadataBlockLevel9 {
@@ -57,7 +61,14 @@ pub struct ExtMetadataBlockLevel9 {
 }
 
 impl ExtMetadataBlockLevel9 {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader, length: u64) -> Result<ExtMetadataBlock> {
+        ensure!(
+            matches!(length, 1 | 17),
+            "Invalid L9 block length: {}",
+            length
+        );
+
         let mut block = Self {
             length,
             source_primary_index: reader.get_n(8)?,
@@ -78,6 +89,7 @@ impl ExtMetadataBlockLevel9 {
         Ok(ExtMetadataBlock::Level9(block))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         self.validate()?;
 
@@ -129,6 +141,36 @@ impl ExtMetadataBlockLevel9 {
         self.source_primary_white_y = primaries.white_y;
     }
 
+    /// Source primaries as CIE 1931 xy coordinates, for red, green, blue and white.
+    ///
+    /// `None` for unknown preset indices.
+    pub fn primaries_xy(&self) -> Option<[f64; 8]> {
+        let index = self.source_primary_index as usize;
+        let colorspace_presets = PREDEFINED_COLORSPACE_PRIMARIES.len();
+
+        if self.length > 1 {
+            let primaries = [
+                self.source_primary_red_x,
+                self.source_primary_red_y,
+                self.source_primary_green_x,
+                self.source_primary_green_y,
+                self.source_primary_blue_x,
+                self.source_primary_blue_y,
+                self.source_primary_white_x,
+                self.source_primary_white_y,
+            ];
+
+            // Signed 16 bit values
+            Some(primaries.map(|v| v as i16 as f64 / 32767.0))
+        } else if index < colorspace_presets {
+            Some(PREDEFINED_COLORSPACE_PRIMARIES[index])
+        } else {
+            PREDEFINED_REALDEVICE_PRIMARIES
+                .get(index - colorspace_presets)
+                .copied()
+        }
+    }
+
     pub fn default_dci_p3() -> ExtMetadataBlockLevel9 {
         Self {
             length: 1,
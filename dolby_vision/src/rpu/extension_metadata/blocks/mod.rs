@@ -1,4 +1,5 @@
 use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -20,26 +21,26 @@ pub mod level8;
 pub mod level9;
 pub mod reserved;
 
-pub use level1::ExtMetadataBlockLevel1;
+pub use level1::{ExtMetadataBlockLevel1, Level1Nits};
 pub use level10::ExtMetadataBlockLevel10;
 pub use level11::ExtMetadataBlockLevel11;
-pub use level2::ExtMetadataBlockLevel2;
+pub use level2::{ExtMetadataBlockLevel2, Level2Trims};
 pub use level254::ExtMetadataBlockLevel254;
 pub use level255::ExtMetadataBlockLevel255;
-pub use level3::ExtMetadataBlockLevel3;
+pub use level3::{ExtMetadataBlockLevel3, Level3Offsets};
 pub use level4::ExtMetadataBlockLevel4;
 pub use level5::ExtMetadataBlockLevel5;
 pub use level6::ExtMetadataBlockLevel6;
-pub use level8::ExtMetadataBlockLevel8;
+pub use level8::{ExtMetadataBlockLevel8, Level8Trims};
 pub use level9::ExtMetadataBlockLevel9;
-pub use reserved::ReservedExtMetadataBlock;
+pub use reserved::{CustomBlockHandlers, CustomExtBlockHandler, ReservedExtMetadataBlock};
 
 use super::{ColorPrimaries, WithExtMetadataBlocks};
 
 /// cbindgen:ignore
 pub const MAX_12_BIT_VALUE: u16 = 4095;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub enum ExtMetadataBlock {
     Level1(ExtMetadataBlockLevel1),
@@ -181,6 +182,7 @@ impl ExtMetadataBlock {
         }
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         match self {
             ExtMetadataBlock::Level1(b) => b.write(writer),
@@ -199,11 +201,35 @@ impl ExtMetadataBlock {
         }
     }
 
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            ExtMetadataBlock::Level1(b) => b.validate(),
+            ExtMetadataBlock::Level2(b) => b.validate(),
+            ExtMetadataBlock::Level3(b) => b.validate(),
+            ExtMetadataBlock::Level4(b) => b.validate(),
+            ExtMetadataBlock::Level5(b) => b.validate(),
+            ExtMetadataBlock::Level6(b) => b.validate(),
+            ExtMetadataBlock::Level8(b) => b.validate(),
+            ExtMetadataBlock::Level9(b) => b.validate(),
+            ExtMetadataBlock::Level10(b) => b.validate(),
+            ExtMetadataBlock::Level11(b) => b.validate(),
+            ExtMetadataBlock::Level254(_)
+            | ExtMetadataBlock::Level255(_)
+            | ExtMetadataBlock::Reserved(_) => Ok(()),
+        }
+    }
+
     pub fn validate_correct_dm_data<T: WithExtMetadataBlocks>(&self) -> Result<()> {
         let level = self.level();
 
+        let allowed = match self {
+            // Custom levels are allowed in both CM versions
+            ExtMetadataBlock::Reserved(_) => reserved::is_custom_level(level),
+            _ => T::ALLOWED_BLOCK_LEVELS.contains(&level),
+        };
+
         ensure!(
-            T::ALLOWED_BLOCK_LEVELS.contains(&level),
+            allowed,
             "Metadata block level {} is invalid for {}",
             &level,
             T::VERSION
@@ -212,6 +238,7 @@ impl ExtMetadataBlock {
         Ok(())
     }
 
+    #[cfg(feature = "bitstream")]
     pub(crate) fn validate_and_read_remaining<T: WithExtMetadataBlocks>(
         &self,
         reader: &mut BsIoSliceReader,
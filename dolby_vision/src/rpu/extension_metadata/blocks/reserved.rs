@@ -1,6 +1,11 @@
-use anyhow::{bail, Result};
+use std::sync::Arc;
+
+#[cfg(feature = "bitstream")]
+use anyhow::bail;
+use anyhow::{ensure, Result};
 
 use bitvec::{order::Msb0, prelude::BitVec};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -8,9 +13,11 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use super::{ExtMetadataBlock, ExtMetadataBlockInfo};
+#[cfg(feature = "bitstream")]
+use super::ExtMetadataBlock;
+use super::ExtMetadataBlockInfo;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct ReservedExtMetadataBlock {
     pub ext_block_length: u64,
@@ -23,11 +30,127 @@ pub struct ReservedExtMetadataBlock {
     pub data: BitVec<u8, Msb0>,
 }
 
+/// Parse and write handler for a vendor-private or reserved extension block level.
+///
+/// Blocks of a handled level are kept as `ReservedExtMetadataBlock`,
+/// instead of the RPU being rejected as containing an unknown block.
+pub trait CustomExtBlockHandler: Send + Sync {
+    /// Checks a parsed block, an error fails the RPU parsing
+    fn parse(&self, _block: &ReservedExtMetadataBlock) -> Result<()> {
+        Ok(())
+    }
+
+    /// Payload to write for the block, must be `ext_block_length` bytes.
+    /// Defaults to the parsed payload.
+    fn write(&self, block: &ReservedExtMetadataBlock) -> Result<Vec<u8>> {
+        Ok(block.payload())
+    }
+}
+
+/// Levels defined by the specification, which cannot have a custom handler
+const DEFINED_LEVELS: &[u8] = &[1, 2, 3, 4, 5, 6, 8, 9, 10, 11, 254, 255];
+
+/// Handlers for custom extension block levels, passed to the parsing and writing functions.
+#[derive(Default, Clone)]
+pub struct CustomBlockHandlers {
+    handlers: Vec<(u8, Arc<dyn CustomExtBlockHandler>)>,
+}
+
+impl CustomBlockHandlers {
+    /// Adds the handler for a custom extension block level, replacing the previous one.
+    pub fn register(&mut self, level: u8, handler: Arc<dyn CustomExtBlockHandler>) -> Result<()> {
+        ensure!(
+            is_custom_level(level),
+            "Cannot register a custom handler for defined block level {}",
+            level
+        );
+
+        self.unregister(level);
+        self.handlers.push((level, handler));
+
+        Ok(())
+    }
+
+    /// Returns whether a handler was registered for the level
+    pub fn unregister(&mut self, level: u8) -> bool {
+        let count = self.handlers.len();
+        self.handlers.retain(|(l, _)| *l != level);
+
+        self.handlers.len() != count
+    }
+
+    pub fn get(&self, level: u8) -> Option<&dyn CustomExtBlockHandler> {
+        self.handlers
+            .iter()
+            .find(|(l, _)| *l == level)
+            .map(|(_, handler)| handler.as_ref())
+    }
+
+    pub fn contains(&self, level: u8) -> bool {
+        self.get(level).is_some()
+    }
+
+    /// Replaces the payload of the block with the one written by its handler
+    #[cfg(feature = "bitstream")]
+    pub(crate) fn apply_write(&self, block: &mut ReservedExtMetadataBlock) -> Result<()> {
+        let handler = match self.get(block.ext_block_level) {
+            Some(handler) => handler,
+            None => bail!(
+                "Cannot write reserved block level {}, no custom handler registered",
+                block.ext_block_level
+            ),
+        };
+
+        let payload = handler.write(block)?;
+        ensure!(
+            payload.len() as u64 == block.ext_block_length,
+            "Custom block level {}: payload should have length {}, got {}",
+            block.ext_block_level,
+            block.ext_block_length,
+            payload.len()
+        );
+
+        block.data = BitVec::from_slice(&payload);
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for CustomBlockHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let levels: Vec<u8> = self.handlers.iter().map(|(level, _)| *level).collect();
+
+        f.debug_struct("CustomBlockHandlers")
+            .field("levels", &levels)
+            .finish()
+    }
+}
+
+/// Whether the level is not defined by the specification, and can be a custom block
+pub fn is_custom_level(level: u8) -> bool {
+    !DEFINED_LEVELS.contains(&level)
+}
+
 impl ReservedExtMetadataBlock {
+    /// Custom block with a byte payload
+    pub fn new(ext_block_level: u8, payload: &[u8]) -> Self {
+        Self {
+            ext_block_length: payload.len() as u64,
+            ext_block_level,
+            data: BitVec::from_slice(payload),
+        }
+    }
+
+    pub fn payload(&self) -> Vec<u8> {
+        self.data.as_raw_slice().to_vec()
+    }
+
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(
         ext_block_length: u64,
         ext_block_level: u8,
         reader: &mut BsIoSliceReader,
+        handlers: &CustomBlockHandlers,
     ) -> Result<ExtMetadataBlock> {
         let bits = 8 * ext_block_length;
         let mut data = BitVec::new();
@@ -36,23 +159,42 @@ impl ReservedExtMetadataBlock {
             data.push(reader.get()?);
         }
 
-        Ok(ExtMetadataBlock::Reserved(Self {
+        let block = Self {
             ext_block_length,
             ext_block_level,
             data,
-        }))
+        };
+
+        if let Some(handler) = handlers.get(ext_block_level) {
+            handler.parse(&block)?;
+        }
+
+        Ok(ExtMetadataBlock::Reserved(block))
     }
 
-    pub fn write(&self, _writer: &mut BitstreamIoWriter) -> Result<()> {
-        bail!("Cannot write reserved block");
-        // self.data.iter().for_each(|b| writer.write(*b))?;
+    /// Writes the payload as is, `CustomBlockHandlers` rewrite it beforehand
+    #[cfg(feature = "bitstream")]
+    pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
+        let payload = self.payload();
+        ensure!(
+            payload.len() as u64 == self.ext_block_length,
+            "Custom block level {}: payload should have length {}, got {}",
+            self.ext_block_level,
+            self.ext_block_length,
+            payload.len()
+        );
+
+        for byte in payload {
+            writer.write_n(&byte, 8)?;
+        }
+
+        Ok(())
     }
 }
 
 impl ExtMetadataBlockInfo for ReservedExtMetadataBlock {
-    // TODO: Level 255 is actually definded for DM debugging purposes, we may add it.
     fn level(&self) -> u8 {
-        0
+        self.ext_block_level
     }
 
     fn bytes_size(&self) -> u64 {
@@ -0,0 +1,131 @@
+use serde_json::{Map, Value};
+
+/// Metadata block field names, and the matching Dolby Vision CM XML terminology.
+///
+/// The L8 and L10 fields share the target display fields naming,
+/// the L2 and L8 trims share the same names.
+pub const CM_XML_FIELD_NAMES: &[(&str, &str)] = &[
+    // L1
+    ("min_pq", "ImageCharacterMin"),
+    ("avg_pq", "ImageCharacterMid"),
+    ("max_pq", "ImageCharacterMax"),
+    // L2, L8
+    ("target_max_pq", "TargetPeakBrightness"),
+    ("trim_slope", "TrimSlope"),
+    ("trim_offset", "TrimOffset"),
+    ("trim_power", "TrimPower"),
+    ("trim_chroma_weight", "TrimChromaWeight"),
+    ("trim_saturation_gain", "TrimSaturationGain"),
+    ("ms_weight", "MSWeight"),
+    // L3
+    ("min_pq_offset", "L1OffsetMin"),
+    ("avg_pq_offset", "L1OffsetMid"),
+    ("max_pq_offset", "L1OffsetMax"),
+    // L4
+    ("anchor_pq", "AnchorPQ"),
+    ("anchor_power", "AnchorPower"),
+    // L5
+    ("active_area_left_offset", "ActiveAreaLeftOffset"),
+    ("active_area_right_offset", "ActiveAreaRightOffset"),
+    ("active_area_top_offset", "ActiveAreaTopOffset"),
+    ("active_area_bottom_offset", "ActiveAreaBottomOffset"),
+    // L6
+    (
+        "max_display_mastering_luminance",
+        "MasteringDisplayPeakBrightness",
+    ),
+    (
+        "min_display_mastering_luminance",
+        "MasteringDisplayMinimumBrightness",
+    ),
+    ("max_content_light_level", "MaxCLL"),
+    ("max_frame_average_light_level", "MaxFALL"),
+    // L8, L10
+    ("target_display_index", "TargetDisplayID"),
+    ("target_mid_contrast", "MidContrastBias"),
+    ("clip_trim", "HighlightClipping"),
+    ("saturation_vector_field0", "SaturationVectorFieldRed"),
+    ("saturation_vector_field1", "SaturationVectorFieldYellow"),
+    ("saturation_vector_field2", "SaturationVectorFieldGreen"),
+    ("saturation_vector_field3", "SaturationVectorFieldCyan"),
+    ("saturation_vector_field4", "SaturationVectorFieldBlue"),
+    ("saturation_vector_field5", "SaturationVectorFieldMagenta"),
+    ("hue_vector_field0", "HueVectorFieldRed"),
+    ("hue_vector_field1", "HueVectorFieldYellow"),
+    ("hue_vector_field2", "HueVectorFieldGreen"),
+    ("hue_vector_field3", "HueVectorFieldCyan"),
+    ("hue_vector_field4", "HueVectorFieldBlue"),
+    ("hue_vector_field5", "HueVectorFieldMagenta"),
+    // L9
+    ("source_primary_index", "SourceColorPrimary"),
+    ("source_primary_red_x", "SourceColorPrimaryRedX"),
+    ("source_primary_red_y", "SourceColorPrimaryRedY"),
+    ("source_primary_green_x", "SourceColorPrimaryGreenX"),
+    ("source_primary_green_y", "SourceColorPrimaryGreenY"),
+    ("source_primary_blue_x", "SourceColorPrimaryBlueX"),
+    ("source_primary_blue_y", "SourceColorPrimaryBlueY"),
+    ("source_primary_white_x", "SourceColorPrimaryWhiteX"),
+    ("source_primary_white_y", "SourceColorPrimaryWhiteY"),
+    // L10
+    ("target_min_pq", "TargetMinimumBrightness"),
+    ("target_primary_index", "TargetColorPrimary"),
+    ("target_primary_red_x", "TargetColorPrimaryRedX"),
+    ("target_primary_red_y", "TargetColorPrimaryRedY"),
+    ("target_primary_green_x", "TargetColorPrimaryGreenX"),
+    ("target_primary_green_y", "TargetColorPrimaryGreenY"),
+    ("target_primary_blue_x", "TargetColorPrimaryBlueX"),
+    ("target_primary_blue_y", "TargetColorPrimaryBlueY"),
+    ("target_primary_white_x", "TargetColorPrimaryWhiteX"),
+    ("target_primary_white_y", "TargetColorPrimaryWhiteY"),
+    // L11
+    ("content_type", "ContentType"),
+    ("whitepoint", "IntendedWhitePoint"),
+    ("reference_mode_flag", "ReferenceMode"),
+    // L254
+    ("dm_mode", "DMMode"),
+    ("dm_version_index", "DMVersion"),
+];
+
+/// Renames the metadata block fields of serialized JSON to the CM XML terminology.
+///
+/// Applies to any nesting, e.g. a single block, an exported RPU list or a generate config.
+pub fn to_cm_xml_names(value: &mut Value) {
+    rename_keys(value, &|key| {
+        CM_XML_FIELD_NAMES
+            .iter()
+            .find(|(field, _)| *field == key)
+            .map(|(_, xml_name)| *xml_name)
+    });
+}
+
+/// Renames the CM XML terminology field names back to the metadata block fields,
+/// so that the JSON can be deserialized.
+pub fn from_cm_xml_names(value: &mut Value) {
+    rename_keys(value, &|key| {
+        CM_XML_FIELD_NAMES
+            .iter()
+            .find(|(_, xml_name)| *xml_name == key)
+            .map(|(field, _)| *field)
+    });
+}
+
+fn rename_keys(value: &mut Value, rename: &dyn Fn(&str) -> Option<&'static str>) {
+    match value {
+        Value::Object(map) => {
+            // Rebuilt to keep the key order
+            let renamed: Map<String, Value> = std::mem::take(map)
+                .into_iter()
+                .map(|(key, mut v)| {
+                    rename_keys(&mut v, rename);
+
+                    let key = rename(&key).map_or(key, str::to_string);
+                    (key, v)
+                })
+                .collect();
+
+            *map = renamed;
+        }
+        Value::Array(values) => values.iter_mut().for_each(|v| rename_keys(v, rename)),
+        _ => (),
+    }
+}
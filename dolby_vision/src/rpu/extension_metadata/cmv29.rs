@@ -1,4 +1,9 @@
-use anyhow::{bail, ensure, Result};
+use std::sync::Arc;
+
+#[cfg(feature = "bitstream")]
+use anyhow::bail;
+use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::bitstream_io_reader::BsIoSliceReader;
 
 #[cfg(feature = "serde")]
@@ -7,11 +12,14 @@ use serde::{Deserialize, Serialize};
 use super::WithExtMetadataBlocks;
 use crate::rpu::extension_metadata::blocks::*;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct CmV29DmData {
     num_ext_blocks: u64,
-    ext_metadata_blocks: Vec<ExtMetadataBlock>,
+
+    // Shared between frames with identical metadata, copied when modified
+    #[cfg_attr(feature = "serde", serde(with = "super::shared_blocks"))]
+    ext_metadata_blocks: Arc<Vec<ExtMetadataBlock>>,
 }
 
 impl WithExtMetadataBlocks for CmV29DmData {
@@ -20,7 +28,7 @@ impl WithExtMetadataBlocks for CmV29DmData {
 
     fn with_blocks_allocation(num_ext_blocks: u64) -> Self {
         Self {
-            ext_metadata_blocks: Vec::with_capacity(num_ext_blocks as usize),
+            ext_metadata_blocks: Arc::new(Vec::with_capacity(num_ext_blocks as usize)),
             ..Default::default()
         }
     }
@@ -34,14 +42,19 @@ impl WithExtMetadataBlocks for CmV29DmData {
     }
 
     fn blocks_ref(&self) -> &Vec<ExtMetadataBlock> {
-        self.ext_metadata_blocks.as_ref()
+        &self.ext_metadata_blocks
     }
 
     fn blocks_mut(&mut self) -> &mut Vec<ExtMetadataBlock> {
-        self.ext_metadata_blocks.as_mut()
+        Arc::make_mut(&mut self.ext_metadata_blocks)
     }
 
-    fn parse_block(&mut self, reader: &mut BsIoSliceReader) -> Result<()> {
+    #[cfg(feature = "bitstream")]
+    fn parse_block(
+        &mut self,
+        reader: &mut BsIoSliceReader,
+        handlers: &CustomBlockHandlers,
+    ) -> Result<()> {
         let ext_block_length = reader.get_ue()?;
         let ext_block_level = reader.get_n(8)?;
 
@@ -57,9 +70,10 @@ impl WithExtMetadataBlocks for CmV29DmData {
                 ext_block_level,
                 Self::VERSION,
             ),
+            // Vendor-private or reserved levels, only with a handler
             _ => {
                 ensure!(
-                    false,
+                    handlers.contains(ext_block_level),
                     format!("{} - Unknown metadata block found: Level {}, length {}, please open an issue.", Self::VERSION, ext_block_level, ext_block_length)
                 );
 
@@ -67,19 +81,30 @@ impl WithExtMetadataBlocks for CmV29DmData {
                     ext_block_length,
                     ext_block_level,
                     reader,
+                    handlers,
                 )?
             }
         };
 
         ext_metadata_block.validate_and_read_remaining::<Self>(reader, ext_block_length)?;
 
-        self.ext_metadata_blocks.push(ext_metadata_block);
+        self.blocks_mut().push(ext_metadata_block);
 
         Ok(())
     }
 }
 
 impl CmV29DmData {
+    /// Shares the blocks allocation of `other` if the blocks are identical
+    #[cfg(feature = "bitstream")]
+    pub(crate) fn share_blocks_with(&mut self, other: &Self) {
+        if !Arc::ptr_eq(&self.ext_metadata_blocks, &other.ext_metadata_blocks)
+            && self.ext_metadata_blocks == other.ext_metadata_blocks
+        {
+            self.ext_metadata_blocks = Arc::clone(&other.ext_metadata_blocks);
+        }
+    }
+
     pub fn replace_level2_block(&mut self, block: &ExtMetadataBlockLevel2) {
         let blocks = self.blocks_mut();
 
@@ -106,7 +131,7 @@ impl CmV29DmData {
 
         let invalid_blocks_count = blocks
             .iter()
-            .filter(|b| !Self::ALLOWED_BLOCK_LEVELS.contains(&b.level()))
+            .filter(|b| b.validate_correct_dm_data::<Self>().is_err())
             .count();
 
         let level1_count = blocks.iter().filter(|b| b.level() == 1).count();
@@ -1,4 +1,7 @@
-use anyhow::{ensure, Result};
+use anyhow::Result;
+#[cfg(feature = "bitstream")]
+use anyhow::ensure;
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -10,6 +13,9 @@ pub mod blocks;
 pub mod cmv29;
 pub mod cmv40;
 
+#[cfg(feature = "serde")]
+pub mod cm_xml_names;
+
 pub mod primaries;
 pub use primaries::*;
 
@@ -17,8 +23,13 @@ pub use cmv29::CmV29DmData;
 pub use cmv40::CmV40DmData;
 
 use blocks::ExtMetadataBlock;
+#[cfg(feature = "bitstream")]
+use blocks::CustomBlockHandlers;
+
+#[cfg(feature = "bitstream")]
+use super::compat::{CompatParser, MIN_EXT_BLOCK_BITS};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 #[cfg_attr(feature = "serde", serde(untagged))]
 pub enum DmData {
@@ -27,7 +38,9 @@ pub enum DmData {
 }
 
 pub trait ExtMetadata {
+    #[cfg(feature = "bitstream")]
     fn parse(&mut self, reader: &mut BsIoSliceReader) -> Result<()>;
+    #[cfg(feature = "bitstream")]
     fn write(&self, writer: &mut BitstreamIoWriter);
     fn ext_block_write_length(&self) -> Result<u16>;
     fn num_ext_blocks(&self) -> u64;
@@ -42,7 +55,12 @@ pub trait WithExtMetadataBlocks {
     fn set_num_ext_blocks(&mut self, num_ext_blocks: u64);
     fn num_ext_blocks(&self) -> u64;
 
-    fn parse_block(&mut self, reader: &mut BsIoSliceReader) -> Result<()>;
+    #[cfg(feature = "bitstream")]
+    fn parse_block(
+        &mut self,
+        reader: &mut BsIoSliceReader,
+        handlers: &CustomBlockHandlers,
+    ) -> Result<()>;
     fn blocks_ref(&self) -> &Vec<ExtMetadataBlock>;
     fn blocks_mut(&mut self) -> &mut Vec<ExtMetadataBlock>;
 
@@ -56,15 +74,11 @@ pub trait WithExtMetadataBlocks {
         self.sort_blocks();
     }
 
-    fn add_block(&mut self, meta: ExtMetadataBlock) -> Result<()> {
-        let level = meta.level();
-
-        ensure!(
-            Self::ALLOWED_BLOCK_LEVELS.contains(&level),
-            "Metadata block level {} is invalid for {}",
-            &level,
-            Self::VERSION
-        );
+    fn add_block(&mut self, meta: ExtMetadataBlock) -> Result<()>
+    where
+        Self: Sized,
+    {
+        meta.validate_correct_dm_data::<Self>()?;
 
         let blocks = self.blocks_mut();
         blocks.push(meta);
@@ -81,6 +95,7 @@ pub trait WithExtMetadataBlocks {
         self.update_extension_block_info();
     }
 
+    #[cfg(feature = "bitstream")]
     fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         let ext_metadata_blocks = self.blocks_ref();
 
@@ -112,11 +127,17 @@ pub trait WithExtMetadataBlocks {
 }
 
 impl DmData {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse<T: WithExtMetadataBlocks + Default>(
         reader: &mut BsIoSliceReader,
+        compat: Option<&mut CompatParser>,
+        handlers: &CustomBlockHandlers,
     ) -> Result<Option<T>> {
         let num_ext_blocks = reader.get_ue()?;
-        let mut meta = T::with_blocks_allocation(num_ext_blocks);
+
+        // A corrupt count can't allocate more blocks than the remaining data holds
+        let max_ext_blocks = reader.available()? / MIN_EXT_BLOCK_BITS;
+        let mut meta = T::with_blocks_allocation(num_ext_blocks.min(max_ext_blocks));
 
         meta.set_num_ext_blocks(num_ext_blocks);
 
@@ -127,13 +148,18 @@ impl DmData {
             );
         }
 
-        for _ in 0..num_ext_blocks {
-            meta.parse_block(reader)?;
+        if let Some(compat) = compat {
+            compat.parse_blocks(reader, &mut meta, handlers)?;
+        } else {
+            for _ in 0..num_ext_blocks {
+                meta.parse_block(reader, handlers)?;
+            }
         }
 
         Ok(Some(meta))
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         match self {
             DmData::V29(m) => m.write(writer),
@@ -161,4 +187,37 @@ impl DmData {
             DmData::V40(m) => m.validate(),
         }
     }
+
+    /// Shares the blocks allocation of `other` if the blocks are identical
+    #[cfg(feature = "bitstream")]
+    pub(crate) fn share_blocks_with(&mut self, other: &DmData) {
+        match (self, other) {
+            (DmData::V29(m), DmData::V29(other)) => m.share_blocks_with(other),
+            (DmData::V40(m), DmData::V40(other)) => m.share_blocks_with(other),
+            _ => (),
+        }
+    }
+}
+
+/// Serializes the shared blocks as a plain list
+#[cfg(feature = "serde")]
+mod shared_blocks {
+    use std::sync::Arc;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::ExtMetadataBlock;
+
+    pub fn serialize<S: Serializer>(
+        blocks: &Arc<Vec<ExtMetadataBlock>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        blocks.as_slice().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arc<Vec<ExtMetadataBlock>>, D::Error> {
+        Vec::deserialize(deserializer).map(Arc::new)
+    }
 }
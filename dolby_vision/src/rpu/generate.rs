@@ -1,23 +1,42 @@
+use std::collections::HashMap;
+#[cfg(feature = "bitstream")]
 use std::{
     fs::File,
     io::{BufWriter, Write},
     path::Path,
+    sync::Arc,
+    thread,
 };
 
-use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
+use anyhow::anyhow;
+use anyhow::{bail, ensure, Result};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 use crate::rpu::dovi_rpu::DoviRpu;
+use crate::utils::{nits_to_pq12, pq12_to_nits};
 
-use super::{extension_metadata::blocks, vdr_dm_data::CmVersion};
+use super::extension_metadata::{CmV29DmData, CmV40DmData, WithExtMetadataBlocks};
+use super::{
+    extension_metadata::blocks,
+    vdr_dm_data::{CmVersion, LongPlayModeCheck},
+};
 use blocks::*;
 
+#[cfg(feature = "bitstream")]
 const OUT_NAL_HEADER: &[u8] = &[0, 0, 0, 1];
 
+/// Frames encoded at once by a thread of `GenerateConfig::write_rpus_to`, shots are never split
+#[cfg(feature = "bitstream")]
+const WRITE_CHUNK_FRAMES: usize = 1000;
+
+/// L1 average used for static metadata configs when MaxFALL is unknown
+const STATIC_METADATA_DEFAULT_AVG_NITS: f64 = 100.0;
+
 /// Generic generation config struct.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct GenerateConfig {
     /// Content mapping version
@@ -41,6 +60,14 @@ pub struct GenerateConfig {
     #[cfg_attr(feature = "serde", serde(default))]
     pub length: usize,
 
+    /// Frame rate of the content.
+    /// When set, frame counts can also be `HH:MM:SS:FF` timecodes in the JSON config.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub fps: Option<f64>,
+
     /// Mastering display min luminance, as 12 bit PQ code.
     #[cfg_attr(feature = "serde", serde(default))]
     pub source_min_pq: Option<u16>,
@@ -53,6 +80,16 @@ pub struct GenerateConfig {
     #[cfg_attr(feature = "serde", serde(default))]
     pub l1_avg_pq_cm_version: Option<CmVersion>,
 
+    /// Signal range and chroma overrides, for profiles 8.1 and 8.4.
+    /// Optional, the profile defaults are used otherwise.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub signal: GenerateSignalConfig,
+
+    /// Presence of the automatically added CM v4.0 blocks.
+    /// Optional, L9 and L11 are added by default.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub auto_blocks: GenerateAutoBlocksConfig,
+
     /// Active area offsets.
     /// Defaults to zero offsets, should be present in RPU
     #[cfg_attr(feature = "serde", serde(default))]
@@ -78,10 +115,17 @@ pub struct GenerateConfig {
     /// List of shots to generate.
     #[cfg_attr(feature = "serde", serde(default))]
     pub shots: Vec<VideoShot>,
+
+    /// Review notes, kept in the JSON config and ignored when generating.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub annotations: Vec<Annotation>,
 }
 
 /// Supported profiles for generating RPU metadata
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub enum GenerateProfile {
     #[cfg_attr(feature = "serde", serde(alias = "5"))]
@@ -92,6 +136,44 @@ pub enum GenerateProfile {
     Profile84,
 }
 
+/// Signal description overrides, for delivery specs differing from the profile defaults.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GenerateSignalConfig {
+    /// RPU header `bl_video_full_range_flag`: base layer range.
+    /// Defaults to limited range (false).
+    pub bl_video_full_range: Option<bool>,
+
+    /// DM `signal_full_range_flag`: 0 narrow range, 1 full range, 2 SDI range.
+    /// Defaults to 1.
+    pub signal_full_range_flag: Option<u8>,
+
+    /// DM `signal_chroma_format`: 0 4:2:0, 1 4:2:2, 2 4:4:4.
+    /// Defaults to 0.
+    pub signal_chroma_format: Option<u8>,
+
+    /// RPU header `chroma_resampling_explicit_filter_flag`, signaling the chroma siting.
+    /// Defaults to false.
+    pub chroma_resampling_explicit_filter: Option<bool>,
+}
+
+/// Presence of the blocks automatically added to every generated RPU.
+///
+/// L254 is always added for CM v4.0, as it is required.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GenerateAutoBlocksConfig {
+    /// Add L9 source primaries, DCI-P3 unless set in the default metadata blocks.
+    /// Defaults to true.
+    pub level9: bool,
+
+    /// Add L11 content type, reference cinema unless set in the default metadata blocks.
+    /// Defaults to true.
+    pub level11: bool,
+}
+
 /// Struct defining a video shot.
 /// A shot is a group of frames that share the same metadata.
 #[derive(Default, Debug, Clone)]
@@ -116,6 +198,80 @@ pub struct VideoShot {
     /// List of per-frame metadata edits.
     #[cfg_attr(feature = "serde", serde(default))]
     pub frame_edits: Vec<ShotFrameEdit>,
+
+    /// HDR10+ generation: peak source of the shot L1 max, overriding the generator choice.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub hdr10plus_peak_source: Option<Hdr10PlusPeakSource>,
+
+    /// Editorial labels of the shot, carried into the shot statistics exports.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub labels: Option<ShotLabels>,
+}
+
+/// Editorial context of a shot, for the pipeline artifacts.
+///
+/// Like the annotations, the labels are only carried through the JSON files, never written to the RPUs.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct ShotLabels {
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub name: Option<String>,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub reel: Option<String>,
+
+    /// Scene number, as a string for numbers like `12A`
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub scene: Option<String>,
+}
+
+/// HDR10+ measurement used as the L1 max, when generating from HDR10+ metadata
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Hdr10PlusPeakSource {
+    /// The max value from the histogram measurements
+    Histogram,
+    /// The last percentile in the histogram, usually 99.98% brightness percentile
+    Histogram99,
+    /// The max value in `maxscl`
+    MaxScl,
+    /// The luminance calculated from the `maxscl` components
+    MaxSclLuminance,
+    /// The highest of the histogram max and `maxscl`
+    Max,
+}
+
+/// Free-form note on a frame or a range of frames, e.g. QC comments during review.
+///
+/// Annotations are only carried through the JSON files, never written to the RPUs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Annotation {
+    /// First frame of the annotated range.
+    pub start: usize,
+
+    /// Number of frames annotated.
+    /// Optional, defaults to a single frame.
+    #[cfg_attr(feature = "serde", serde(default = "Annotation::default_duration"))]
+    pub duration: usize,
+
+    pub text: String,
 }
 
 /// Struct to represent a list of metadata edits for a specific frame.
@@ -129,16 +285,34 @@ pub struct ShotFrameEdit {
     pub metadata_blocks: Vec<ExtMetadataBlock>,
 }
 
+/// Cap of the L1 max brightness, for delivery specs below the measured master peak.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxPqClip {
+    /// Highest L1 max, 12-bit PQ.
+    pub max_pq: u16,
+
+    /// Start of the highlight roll-off, 12-bit PQ.
+    /// Above it, the values are compressed smoothly towards `max_pq` instead of being clipped.
+    pub knee_pq: Option<u16>,
+}
+
 impl GenerateConfig {
     pub fn generate_rpu_list(&self) -> Result<Vec<DoviRpu>> {
-        let rpu = match self.profile {
-            GenerateProfile::Profile5 => DoviRpu::profile5_config(self)?,
-            GenerateProfile::Profile81 => DoviRpu::profile81_config(self)?,
-            GenerateProfile::Profile84 => DoviRpu::profile84_config(self)?,
-        };
+        self.ensure_shots_length()?;
+
+        let rpu = self.base_rpu()?;
 
         let mut list = Vec::with_capacity(self.length);
 
+        for shot in &self.shots {
+            list.extend(self.shot_rpus(&rpu, shot)?);
+        }
+
+        Ok(list)
+    }
+
+    /// Checks that the shots cover exactly the config length
+    pub fn ensure_shots_length(&self) -> Result<()> {
         let shots_length: usize = self.shots.iter().map(|s| s.duration).sum();
 
         ensure!(
@@ -149,39 +323,113 @@ impl GenerateConfig {
             )
         );
 
-        for shot in &self.shots {
-            let end = shot.duration;
+        Ok(())
+    }
+
+    /// RPU of the configured profile and signal, before any shot metadata is set
+    pub fn base_rpu(&self) -> Result<DoviRpu> {
+        let mut rpu = match self.profile {
+            GenerateProfile::Profile5 => DoviRpu::profile5_config(self)?,
+            GenerateProfile::Profile81 => DoviRpu::profile81_config(self)?,
+            GenerateProfile::Profile84 => DoviRpu::profile84_config(self)?,
+        };
 
-            for i in 0..end {
-                let mut frame_rpu = rpu.clone();
+        self.signal.apply(&self.profile, &mut rpu)?;
 
-                if let Some(vdr_dm_data) = frame_rpu.vdr_dm_data.as_mut() {
-                    if i == 0 || self.long_play_mode {
-                        vdr_dm_data.set_scene_cut(true);
-                    }
+        Ok(rpu)
+    }
 
-                    // Set metadata for this shot
-                    for block in &shot.metadata_blocks {
-                        vdr_dm_data.replace_metadata_block(block.clone())?;
-                    }
+    /// RPUs of every frame of a shot, from the `base_rpu`.
+    ///
+    /// The frames without edits share the shot metadata blocks.
+    pub fn shot_rpus(&self, base_rpu: &DoviRpu, shot: &VideoShot) -> Result<Vec<DoviRpu>> {
+        let shot_rpu = Self::shot_rpu(base_rpu, shot)?;
+        let frame_edits = shot.frame_edits_by_offset();
+
+        (0..shot.duration)
+            .map(|i| self.frame_rpu(&shot_rpu, i, frame_edits.get(&i).copied()))
+            .collect()
+    }
 
-                    let frame_edit = shot.frame_edits.iter().find(|e| e.edit_offset == i);
+    /// RPU with the shot metadata set, shared by the frames of the shot
+    fn shot_rpu(base_rpu: &DoviRpu, shot: &VideoShot) -> Result<DoviRpu> {
+        let mut shot_rpu = base_rpu.clone();
 
-                    // Set different metadata for this frame
-                    if let Some(edit) = frame_edit {
-                        for block in &edit.metadata_blocks {
-                            vdr_dm_data.replace_metadata_block(block.clone())?;
-                        }
-                    }
+        if let Some(vdr_dm_data) = shot_rpu.vdr_dm_data.as_mut() {
+            for block in &shot.metadata_blocks {
+                vdr_dm_data.replace_metadata_block(block.clone())?;
+            }
+        }
+
+        Ok(shot_rpu)
+    }
+
+    fn frame_rpu(
+        &self,
+        shot_rpu: &DoviRpu,
+        offset: usize,
+        edit: Option<&ShotFrameEdit>,
+    ) -> Result<DoviRpu> {
+        let mut frame_rpu = shot_rpu.clone();
+
+        if let Some(vdr_dm_data) = frame_rpu.vdr_dm_data.as_mut() {
+            if offset == 0 || self.long_play_mode {
+                vdr_dm_data.set_scene_cut(true);
+            }
+
+            // Set different metadata for this frame
+            if let Some(edit) = edit {
+                for block in &edit.metadata_blocks {
+                    vdr_dm_data.replace_metadata_block(block.clone())?;
                 }
+            }
+        }
 
-                list.push(frame_rpu)
+        Ok(frame_rpu)
+    }
+
+    /// Appends the encoded RPUs of a shot to `out`, with start codes.
+    ///
+    /// The frames without edits only differ by the scene cut flag,
+    /// so they are encoded once per flag value and the bytes are reused.
+    #[cfg(feature = "bitstream")]
+    fn encode_shot_rpus(
+        &self,
+        out: &mut Vec<u8>,
+        base_rpu: &DoviRpu,
+        shot: &VideoShot,
+    ) -> Result<()> {
+        let shot_rpu = Self::shot_rpu(base_rpu, shot)?;
+        let frame_edits = shot.frame_edits_by_offset();
+
+        // Indexed by the scene cut flag
+        let mut encoded_frames: [Option<Vec<u8>>; 2] = [None, None];
+
+        for i in 0..shot.duration {
+            if let Some(&edit) = frame_edits.get(&i) {
+                let frame_rpu = self.frame_rpu(&shot_rpu, i, Some(edit))?;
+                write_encoded_rpu(out, &frame_rpu.write_hevc_unspec62_nalu()?)?;
+
+                continue;
+            }
+
+            let scene_cut = i == 0 || self.long_play_mode;
+            let slot = &mut encoded_frames[usize::from(scene_cut)];
+
+            if slot.is_none() {
+                let frame_rpu = self.frame_rpu(&shot_rpu, i, None)?;
+                *slot = Some(frame_rpu.write_hevc_unspec62_nalu()?);
+            }
+
+            if let Some(encoded) = slot {
+                write_encoded_rpu(out, encoded)?;
             }
         }
 
-        Ok(list)
+        Ok(())
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn encode_option_rpus(rpus: &mut [Option<DoviRpu>]) -> Vec<Vec<u8>> {
         let encoded_rpus = rpus
             .iter_mut()
@@ -193,6 +441,7 @@ impl GenerateConfig {
         encoded_rpus
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn encode_rpus(rpus: &mut [DoviRpu]) -> Vec<Vec<u8>> {
         let encoded_rpus = rpus
             .iter_mut()
@@ -203,26 +452,356 @@ impl GenerateConfig {
         encoded_rpus
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write_rpus<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let mut writer =
             BufWriter::with_capacity(100_000, File::create(path).expect("Can't create file"));
 
-        let rpus = self.generate_rpu_list()?;
+        self.write_rpus_to(&mut writer)?;
+        writer.flush()?;
 
-        for rpu in &rpus {
-            let encoded_rpu = rpu.write_hevc_unspec62_nalu()?;
+        Ok(())
+    }
+
+    /// Encodes and writes every RPU of the config, with start codes.
+    ///
+    /// The shots are encoded in parallel by chunks of about 1000 frames, and written in order.
+    /// Only one chunk per thread is kept in memory, the next chunks are encoded once written.
+    #[cfg(feature = "bitstream")]
+    pub fn write_rpus_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.ensure_shots_length()?;
+
+        let base_rpu = Arc::new(self.base_rpu()?);
+        let config = Arc::new(self.clone());
+
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
 
-            writer.write_all(OUT_NAL_HEADER)?;
+        // Consecutive shots, a longer shot is a chunk by itself
+        let mut chunks = Vec::new();
+        let mut chunk_start = 0;
+        let mut chunk_frames = 0;
 
-            // Remove 0x7C01
-            writer.write_all(&encoded_rpu[2..])?;
+        for (i, shot) in self.shots.iter().enumerate() {
+            chunk_frames += shot.duration;
+
+            if chunk_frames >= WRITE_CHUNK_FRAMES || i == self.shots.len() - 1 {
+                chunks.push(chunk_start..i + 1);
+
+                chunk_start = i + 1;
+                chunk_frames = 0;
+            }
         }
 
-        writer.flush()?;
+        for batch in chunks.chunks(threads) {
+            let handles: Vec<_> = batch
+                .iter()
+                .cloned()
+                .map(|shots| {
+                    let base_rpu = Arc::clone(&base_rpu);
+                    let config = Arc::clone(&config);
+
+                    thread::spawn(move || -> Result<Vec<u8>> {
+                        let mut encoded = Vec::new();
+
+                        for shot in &config.shots[shots] {
+                            config.encode_shot_rpus(&mut encoded, &base_rpu, shot)?;
+                        }
+
+                        Ok(encoded)
+                    })
+                })
+                .collect();
+
+            for handle in handles {
+                let encoded = handle
+                    .join()
+                    .map_err(|_| anyhow!("RPU encoding thread panicked"))??;
+
+                writer.write_all(&encoded)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generates and writes the RPUs of a single shot, for generating incrementally
+    #[cfg(feature = "bitstream")]
+    pub fn write_shot_rpus<W: Write>(
+        &self,
+        writer: &mut W,
+        base_rpu: &DoviRpu,
+        shot: &VideoShot,
+    ) -> Result<()> {
+        let mut encoded = Vec::new();
+        self.encode_shot_rpus(&mut encoded, base_rpu, shot)?;
+
+        writer.write_all(&encoded)?;
+
+        Ok(())
+    }
+
+    /// Long play mode check of the generated RPUs, estimated from the config.
+    ///
+    /// Every shot boundary is counted as a metadata change,
+    /// and every frame edit as a change into the edited frame and back to the shot metadata.
+    pub fn long_play_mode_check(&self) -> LongPlayModeCheck {
+        let mut check = LongPlayModeCheck {
+            frames: self.length,
+            scene_cuts: if self.long_play_mode {
+                self.length
+            } else {
+                self.shots.len().min(self.length)
+            },
+            ..Default::default()
+        };
+
+        for (i, shot) in self.shots.iter().enumerate() {
+            if i > 0 {
+                check.metadata_changes += 1;
+            }
+
+            let edits = shot.frame_edits_by_offset();
+
+            for offset in edits.keys() {
+                let into_edit = usize::from(*offset > 0);
+                let out_of_edit =
+                    usize::from(offset + 1 < shot.duration && !edits.contains_key(&(offset + 1)));
+
+                check.metadata_changes += into_edit + out_of_edit;
+
+                if !self.long_play_mode {
+                    check.unflagged_changes += into_edit + out_of_edit;
+                }
+            }
+        }
+
+        let transitions = self.length.saturating_sub(1);
+        check.metadata_changes = check.metadata_changes.min(transitions);
+        check.unflagged_changes = check.unflagged_changes.min(transitions);
+
+        check
+    }
+
+    /// Checks the config for inconsistencies before generating the RPUs.
+    ///
+    /// Every issue found is reported with its location in the config:
+    /// shot overlaps or gaps, total duration, duplicate trim targets,
+    /// metadata levels invalid for the CM version, out of range values
+    /// and inconsistent L1 statistics.
+    pub fn validate(&self) -> Result<()> {
+        let mut issues = Vec::new();
+
+        for (name, value) in [
+            ("source_min_pq", self.source_min_pq),
+            ("source_max_pq", self.source_max_pq),
+        ] {
+            if let Some(pq) = value.filter(|pq| *pq > MAX_12_BIT_VALUE) {
+                issues.push(format!("{name}: PQ value {pq} is above {MAX_12_BIT_VALUE}"));
+            }
+        }
+
+        if let Some(level6) = &self.level6 {
+            if let Err(e) = level6.validate() {
+                issues.push(format!("level6: {e}"));
+            }
+        }
+
+        if let Err(e) = self.level5.validate() {
+            issues.push(format!("level5: {e}"));
+        }
+
+        self.validate_blocks(
+            &self.default_metadata_blocks,
+            "default_metadata_blocks",
+            &mut issues,
+        );
+
+        // The shots can start at an offset (e.g. XML record timecodes)
+        let mut expected_start = self.shots.first().map_or(0, |s| s.start);
+        for (i, shot) in self.shots.iter().enumerate() {
+            let location = if shot.id.is_empty() {
+                format!("shots[{i}] (start {})", shot.start)
+            } else {
+                format!("shots[{i}] (id {}, start {})", shot.id, shot.start)
+            };
+
+            if shot.duration == 0 {
+                issues.push(format!("{location}: duration is zero"));
+            }
+
+            if shot.start < expected_start {
+                issues.push(format!(
+                    "{location}: overlaps the previous shot, which ends at frame {}",
+                    expected_start - 1
+                ));
+            } else if shot.start > expected_start {
+                issues.push(format!(
+                    "{location}: gap of {} frames after the previous shot",
+                    shot.start - expected_start
+                ));
+            }
+
+            expected_start = shot.start + shot.duration;
+
+            self.validate_blocks(
+                &shot.metadata_blocks,
+                &format!("{location}.metadata_blocks"),
+                &mut issues,
+            );
+
+            for (j, edit) in shot.frame_edits.iter().enumerate() {
+                let edit_location = format!("{location}.frame_edits[{j}]");
+
+                if edit.edit_offset >= shot.duration {
+                    issues.push(format!(
+                        "{edit_location}: edit_offset {} is outside of the shot duration {}",
+                        edit.edit_offset, shot.duration
+                    ));
+                }
+
+                self.validate_blocks(
+                    &edit.metadata_blocks,
+                    &format!("{edit_location}.metadata_blocks"),
+                    &mut issues,
+                );
+            }
+        }
+
+        let shots_length: usize = self.shots.iter().map(|s| s.duration).sum();
+        if self.length != shots_length {
+            issues.push(format!(
+                "length: {} is not the same as the shots total duration {shots_length}",
+                self.length
+            ));
+        }
+
+        ensure!(
+            issues.is_empty(),
+            "Invalid generate config:\n  {}",
+            issues.join("\n  ")
+        );
 
         Ok(())
     }
 
+    fn validate_blocks(
+        &self,
+        blocks: &[ExtMetadataBlock],
+        location: &str,
+        issues: &mut Vec<String>,
+    ) {
+        let mut targets = Vec::new();
+
+        for (i, block) in blocks.iter().enumerate() {
+            let level = block.level();
+            let block_location = format!("{location}[{i}] (L{level})");
+
+            if !allowed_level(self.cm_version, level) {
+                issues.push(format!(
+                    "{block_location}: level is invalid for CM {:?}",
+                    self.cm_version
+                ));
+            }
+
+            if let Err(e) = block.validate() {
+                issues.push(format!("{block_location}: {e}"));
+            }
+
+            if let ExtMetadataBlock::Level1(l1) = block {
+                if l1.has_inconsistent_stats() {
+                    issues.push(format!(
+                        "{block_location}: inconsistent statistics, min_pq {} avg_pq {} max_pq {}",
+                        l1.min_pq, l1.avg_pq, l1.max_pq
+                    ));
+                }
+            }
+
+            let target = match block {
+                ExtMetadataBlock::Level2(b) => Some(b.target_max_pq),
+                ExtMetadataBlock::Level8(b) => Some(b.target_display_index as u16),
+                ExtMetadataBlock::Level10(b) => Some(b.target_display_index as u16),
+                _ => None,
+            };
+
+            if let Some(target) = target {
+                if targets.contains(&(level, target)) {
+                    issues.push(format!("{block_location}: duplicate target {target}"));
+                } else {
+                    targets.push((level, target));
+                }
+            }
+        }
+    }
+
+    /// Retargets the config to another profile, keeping the metadata intent.
+    ///
+    /// - Profile 5: the signal overrides are dropped, as they are only supported for profiles 8.1 and 8.4.
+    /// - Profile 8.4: CM v2.9 configs are upgraded to CM v4.0, keeping the CM v2.9 L1 `avg_pq` minimum.
+    ///
+    /// Metadata blocks invalid for the resulting CM version are dropped.
+    /// Returns a warning for every change to the config.
+    pub fn convert_profile(&mut self, target: GenerateProfile) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if self.profile == target {
+            return warnings;
+        }
+
+        if target == GenerateProfile::Profile5 && self.signal != GenerateSignalConfig::default() {
+            warnings.push(format!(
+                "dropped the signal overrides, not supported for profile 5: {:?}",
+                self.signal
+            ));
+
+            self.signal = GenerateSignalConfig::default();
+        }
+
+        if target == GenerateProfile::Profile84 && self.cm_version == CmVersion::V29 {
+            warnings.push("upgraded CM v2.9 to CM v4.0, required for profile 8.4".to_string());
+
+            self.cm_version = CmVersion::V40;
+            self.l1_avg_pq_cm_version.get_or_insert(CmVersion::V29);
+        }
+
+        let cm_version = self.cm_version;
+        let mut dropped: Vec<(u8, usize)> = Vec::new();
+        let mut retain_allowed = |blocks: &mut Vec<ExtMetadataBlock>| {
+            blocks.retain(|block| {
+                let level = block.level();
+                let allowed = allowed_level(cm_version, level);
+
+                if !allowed {
+                    match dropped.iter_mut().find(|(l, _)| *l == level) {
+                        Some((_, count)) => *count += 1,
+                        None => dropped.push((level, 1)),
+                    }
+                }
+
+                allowed
+            });
+        };
+
+        retain_allowed(&mut self.default_metadata_blocks);
+
+        for shot in &mut self.shots {
+            retain_allowed(&mut shot.metadata_blocks);
+
+            for edit in &mut shot.frame_edits {
+                retain_allowed(&mut edit.metadata_blocks);
+            }
+        }
+
+        for (level, count) in dropped {
+            warnings.push(format!(
+                "dropped {count} L{level} blocks, invalid for CM {cm_version:?}"
+            ));
+        }
+
+        self.profile = target;
+
+        warnings
+    }
+
     pub fn fixup_l1(&mut self) {
         let clamp_l1 = |block: &mut ExtMetadataBlock| {
             if let ExtMetadataBlock::Level1(l1) = block {
@@ -239,6 +818,92 @@ impl GenerateConfig {
                 .for_each(|e| e.metadata_blocks.iter_mut().for_each(clamp_l1));
         });
     }
+
+    /// Caps the L1 max of every shot and frame edit, see [`MaxPqClip::apply_blocks`].
+    ///
+    /// The L6 MaxCLL and MaxFALL are capped as well.
+    /// Returns the number of block lists modified.
+    pub fn clip_max_pq(&mut self, clip: MaxPqClip) -> usize {
+        let mut modified = usize::from(clip.apply_blocks(&mut self.default_metadata_blocks));
+
+        for shot in &mut self.shots {
+            modified += shot.clip_max_pq(clip);
+        }
+
+        if let Some(level6) = self.level6.as_mut() {
+            // Never above the capped L1 max
+            let max_nits = pq12_to_nits(clip.max_pq).floor() as u16;
+
+            level6.max_content_light_level = level6.max_content_light_level.min(max_nits);
+            level6.max_frame_average_light_level = level6
+                .max_frame_average_light_level
+                .min(level6.max_content_light_level);
+        }
+
+        modified
+    }
+
+    /// Minimal config for valid Dolby Vision signaling, from the HDR10 static metadata only.
+    ///
+    /// A single shot of `length` frames is generated, with constant L1 metadata derived from `level6`:
+    /// - min from the mastering display min luminance.
+    /// - max from MaxCLL, or the mastering display max luminance if unknown.
+    /// - avg from MaxFALL, or 100 nits if unknown. At most the max.
+    pub fn from_static_metadata(length: usize, level6: ExtMetadataBlockLevel6) -> Self {
+        let min_nits = level6.min_display_mastering_luminance as f64 / 10_000.0;
+
+        let max_nits = match level6.max_content_light_level {
+            0 => level6.max_display_mastering_luminance,
+            max_cll => max_cll,
+        } as f64;
+
+        let avg_nits = match level6.max_frame_average_light_level {
+            0 => STATIC_METADATA_DEFAULT_AVG_NITS,
+            max_fall => max_fall as f64,
+        }
+        .min(max_nits);
+
+        let cm_version = CmVersion::V40;
+        let level1 = ExtMetadataBlockLevel1::from_stats_cm_version(
+            nits_to_pq12(min_nits),
+            nits_to_pq12(max_nits),
+            nits_to_pq12(avg_nits),
+            cm_version,
+        );
+
+        Self {
+            cm_version,
+            length,
+            level6: Some(level6),
+            shots: vec![VideoShot {
+                start: 0,
+                duration: length,
+                metadata_blocks: vec![ExtMetadataBlock::Level1(level1)],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+}
+
+fn allowed_level(cm_version: CmVersion, level: u8) -> bool {
+    match cm_version {
+        CmVersion::V29 => CmV29DmData::ALLOWED_BLOCK_LEVELS.contains(&level),
+        CmVersion::V40 => {
+            CmV29DmData::ALLOWED_BLOCK_LEVELS.contains(&level)
+                || CmV40DmData::ALLOWED_BLOCK_LEVELS.contains(&level)
+        }
+    }
+}
+
+#[cfg(feature = "bitstream")]
+fn write_encoded_rpu<W: Write>(writer: &mut W, encoded_rpu: &[u8]) -> Result<()> {
+    writer.write_all(OUT_NAL_HEADER)?;
+
+    // Remove 0x7C01
+    writer.write_all(&encoded_rpu[2..])?;
+
+    Ok(())
 }
 
 impl Default for GenerateConfig {
@@ -247,10 +912,13 @@ impl Default for GenerateConfig {
             cm_version: CmVersion::V40,
             profile: Default::default(),
             length: Default::default(),
+            fps: Default::default(),
             long_play_mode: Default::default(),
             source_min_pq: Default::default(),
             source_max_pq: Default::default(),
             l1_avg_pq_cm_version: Default::default(),
+            signal: Default::default(),
+            auto_blocks: Default::default(),
             default_metadata_blocks: Default::default(),
             level5: Default::default(),
             level6: Some(ExtMetadataBlockLevel6 {
@@ -261,11 +929,219 @@ impl Default for GenerateConfig {
             }),
             level254: Default::default(),
             shots: Default::default(),
+            annotations: Default::default(),
         }
     }
 }
 
+#[cfg(feature = "serde")]
+impl GenerateConfig {
+    /// Deserializes a JSON config, converting the timecodes to frame counts.
+    ///
+    /// Timecodes are accepted for `length`, the shots and annotations `start` and `duration`,
+    /// and the frame edits `edit_offset`. They require `fps` to be set.
+    ///
+    /// The metadata block fields can also use the CM XML terminology, see [`to_cm_xml_names`].
+    ///
+    /// [`to_cm_xml_names`]: super::extension_metadata::cm_xml_names::to_cm_xml_names
+    pub fn from_json_value(mut value: serde_json::Value) -> Result<Self> {
+        super::extension_metadata::cm_xml_names::from_cm_xml_names(&mut value);
+
+        let fps = value.get("fps").and_then(serde_json::Value::as_f64);
+
+        let convert = |object: &mut serde_json::Value, key: &str| -> Result<()> {
+            if let Some(serde_json::Value::String(timecode)) = object.get(key) {
+                let fps = match fps {
+                    Some(fps) => fps,
+                    None => bail!("Timecode '{timecode}' for `{key}` requires `fps` to be set"),
+                };
+
+                let frames = crate::utils::timecode_to_frames(timecode, fps)?;
+                object[key] = frames.into();
+            }
+
+            Ok(())
+        };
+
+        convert(&mut value, "length")?;
+
+        if let Some(shots) = value.get_mut("shots").and_then(|s| s.as_array_mut()) {
+            for shot in shots {
+                convert(shot, "start")?;
+                convert(shot, "duration")?;
+
+                if let Some(edits) = shot.get_mut("frame_edits").and_then(|e| e.as_array_mut()) {
+                    for edit in edits {
+                        convert(edit, "edit_offset")?;
+                    }
+                }
+            }
+        }
+
+        if let Some(annotations) = value.get_mut("annotations").and_then(|a| a.as_array_mut()) {
+            for annotation in annotations {
+                convert(annotation, "start")?;
+                convert(annotation, "duration")?;
+            }
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+impl Annotation {
+    #[cfg(feature = "serde")]
+    fn default_duration() -> usize {
+        1
+    }
+
+    /// Whether any frame of the annotation is within `start..start + duration`
+    pub fn overlaps(&self, start: usize, duration: usize) -> bool {
+        self.start < start + duration && start < self.start + self.duration.max(1)
+    }
+
+    /// Text of the annotations covering `frame`, in order
+    pub fn frame_texts(annotations: &[Annotation], frame: usize) -> Vec<&str> {
+        annotations
+            .iter()
+            .filter(|a| a.overlaps(frame, 1))
+            .map(|a| a.text.as_str())
+            .collect()
+    }
+}
+
+impl GenerateSignalConfig {
+    pub fn apply(&self, profile: &GenerateProfile, rpu: &mut DoviRpu) -> Result<()> {
+        if *self == Self::default() {
+            return Ok(());
+        }
+
+        if matches!(profile, GenerateProfile::Profile5) {
+            bail!("Signal overrides are only supported for profiles 8.1 and 8.4");
+        }
+
+        if let Some(full_range) = self.signal_full_range_flag {
+            ensure!(
+                full_range <= 2,
+                "signal_full_range_flag should be 0, 1 or 2, got {full_range}"
+            );
+        }
+
+        if let Some(chroma_format) = self.signal_chroma_format {
+            ensure!(
+                chroma_format <= 2,
+                "signal_chroma_format should be 0, 1 or 2, got {chroma_format}"
+            );
+        }
+
+        if let Some(full_range) = self.bl_video_full_range {
+            rpu.header.bl_video_full_range_flag = full_range;
+        }
+
+        if let Some(explicit_filter) = self.chroma_resampling_explicit_filter {
+            rpu.header.chroma_resampling_explicit_filter_flag = explicit_filter;
+        }
+
+        if let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() {
+            if let Some(full_range) = self.signal_full_range_flag {
+                vdr_dm_data.signal_full_range_flag = full_range;
+            }
+
+            if let Some(chroma_format) = self.signal_chroma_format {
+                vdr_dm_data.signal_chroma_format = chroma_format;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl MaxPqClip {
+    /// Capped value of a 12-bit PQ brightness.
+    ///
+    /// The roll-off has a slope of 1 at the knee, and approaches `max_pq` asymptotically.
+    pub fn apply(&self, pq: u16) -> u16 {
+        match self.knee_pq {
+            Some(knee_pq) if knee_pq < self.max_pq => {
+                if pq <= knee_pq {
+                    return pq;
+                }
+
+                let over = (pq - knee_pq) as f64;
+                let range = (self.max_pq - knee_pq) as f64;
+
+                knee_pq + (range * over / (over + range)).round() as u16
+            }
+            _ => pq.min(self.max_pq),
+        }
+    }
+
+    /// Caps the L1 max of a block list, keeping the average and min below it.
+    ///
+    /// The L3 max offset of the same list is adjusted so that the offset L1 max is capped the same way.
+    /// Returns whether the blocks were modified.
+    pub fn apply_blocks(&self, blocks: &mut [ExtMetadataBlock]) -> bool {
+        let Some((original_max, capped_max)) = blocks.iter_mut().find_map(|block| match block {
+            ExtMetadataBlock::Level1(level1) => {
+                let original_max = level1.max_pq;
+
+                level1.max_pq = self.apply(level1.max_pq);
+                level1.avg_pq = level1.avg_pq.min(level1.max_pq.saturating_sub(1));
+                level1.min_pq = level1.min_pq.min(level1.avg_pq);
+
+                Some((original_max, level1.max_pq))
+            }
+            _ => None,
+        }) else {
+            return false;
+        };
+
+        for block in blocks.iter_mut() {
+            if let ExtMetadataBlock::Level3(level3) = block {
+                let offset_max = (original_max as i32 + level3.max_pq_offset as i32 - 2048)
+                    .clamp(0, 4095) as u16;
+                let capped_offset = self.apply(offset_max) as i32 - capped_max as i32;
+
+                level3.max_pq_offset = (capped_offset + 2048).clamp(0, 4095) as u16;
+            }
+        }
+
+        original_max != capped_max
+    }
+}
+
 impl VideoShot {
+    /// Labels of the shot containing `frame`
+    pub fn labels_at(shots: &[VideoShot], frame: usize) -> Option<&ShotLabels> {
+        shots
+            .iter()
+            .find(|shot| (shot.start..shot.start + shot.duration).contains(&frame))
+            .and_then(|shot| shot.labels.as_ref())
+    }
+
+    /// Caps the L1 max of the shot and its frame edits.
+    /// Returns the number of block lists modified.
+    pub fn clip_max_pq(&mut self, clip: MaxPqClip) -> usize {
+        let mut modified = usize::from(clip.apply_blocks(&mut self.metadata_blocks));
+
+        for edit in &mut self.frame_edits {
+            modified += usize::from(clip.apply_blocks(&mut edit.metadata_blocks));
+        }
+
+        modified
+    }
+
+    /// Frame edits by offset, the first edit wins for duplicate offsets
+    fn frame_edits_by_offset(&self) -> HashMap<usize, &ShotFrameEdit> {
+        let mut edits = HashMap::with_capacity(self.frame_edits.len());
+
+        for edit in &self.frame_edits {
+            edits.entry(edit.edit_offset).or_insert(edit);
+        }
+
+        edits
+    }
+
     pub fn copy_metadata_from_shot(
         &mut self,
         other_shot: &VideoShot,
@@ -351,6 +1227,7 @@ mod tests {
         let opts = XmlParserOpts {
             canvas_width: Some(3840),
             canvas_height: Some(2160),
+            ..Default::default()
         };
         let parser = CmXmlParser::parse_file(assets_path.join("assets/tests/cmv4_0_2.xml"), opts)?;
 
@@ -625,6 +1502,31 @@ impl Default for GenerateProfile {
     }
 }
 
+impl Default for GenerateAutoBlocksConfig {
+    fn default() -> Self {
+        Self {
+            level9: true,
+            level11: true,
+        }
+    }
+}
+
+impl std::fmt::Display for ShotLabels {
+    /// Joins the set labels, e.g. `Cold open, reel R1, scene 12A`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = [
+            self.name.clone(),
+            self.reel.as_ref().map(|reel| format!("reel {reel}")),
+            self.scene.as_ref().map(|scene| format!("scene {scene}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
 impl std::fmt::Display for GenerateProfile {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
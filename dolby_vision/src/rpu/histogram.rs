@@ -0,0 +1,187 @@
+use anyhow::{ensure, Result};
+
+use super::extension_metadata::blocks::ExtMetadataBlockLevel1;
+use super::vdr_dm_data::CmVersion;
+use crate::utils::nits_to_pq;
+
+/// Percentiles of the HDR10+ `DistributionMaxRgb` luminance distribution
+pub const HDR10PLUS_PERCENTILES: [f64; 9] = [1.0, 5.0, 10.0, 25.0, 50.0, 75.0, 90.0, 95.0, 99.0];
+
+/// Unit of the histogram bin edges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistogramScale {
+    Nits,
+    /// Normalized PQ, 0.0 to 1.0
+    Pq,
+}
+
+/// Luminance histogram of a frame or a shot, for computing L1 metadata.
+///
+/// The bin edges are converted to normalized PQ, all the statistics are computed in PQ.
+#[derive(Debug, Clone)]
+pub struct LuminanceHistogram {
+    edges_pq: Vec<f64>,
+    counts: Vec<u64>,
+    total: u64,
+}
+
+/// Options for computing L1 metadata from a histogram
+#[derive(Debug, Clone, Default)]
+pub struct HistogramL1Opts {
+    /// Percentile used as the max brightness, instead of the brightest populated bin.
+    /// Ignores the outlier pixels.
+    pub max_percentile: Option<f64>,
+
+    /// Percentiles for the percentile-weighted average, as used for HDR10+ distributions.
+    /// When empty, the average is the mean of the histogram.
+    pub avg_percentiles: Vec<f64>,
+}
+
+impl LuminanceHistogram {
+    /// `edges` are the ascending bin boundaries, one more than the `counts`
+    pub fn new(edges: &[f64], counts: &[u64], scale: HistogramScale) -> Result<Self> {
+        ensure!(!counts.is_empty(), "Histogram has no bins");
+        ensure!(
+            edges.len() == counts.len() + 1,
+            "Histogram must have one more edge than bins, found {} edges for {} bins",
+            edges.len(),
+            counts.len()
+        );
+        ensure!(
+            edges.windows(2).all(|w| w[0] < w[1]),
+            "Histogram edges must be strictly ascending"
+        );
+
+        let max_value = match scale {
+            HistogramScale::Nits => 10_000.0,
+            HistogramScale::Pq => 1.0,
+        };
+        ensure!(
+            edges[0] >= 0.0 && edges[edges.len() - 1] <= max_value,
+            "Histogram edges must be between 0 and {}",
+            max_value
+        );
+
+        let total: u64 = counts.iter().sum();
+        ensure!(total > 0, "Histogram is empty");
+
+        let edges_pq = match scale {
+            HistogramScale::Nits => edges.iter().map(|nits| nits_to_pq(*nits)).collect(),
+            HistogramScale::Pq => edges.to_vec(),
+        };
+
+        Ok(Self {
+            edges_pq,
+            counts: counts.to_vec(),
+            total,
+        })
+    }
+
+    /// Lower edge of the darkest populated bin
+    pub fn min_pq(&self) -> f64 {
+        let first = self.counts.iter().position(|c| *c > 0).unwrap_or(0);
+
+        self.edges_pq[first]
+    }
+
+    /// Upper edge of the brightest populated bin
+    pub fn max_pq(&self) -> f64 {
+        let last = self.counts.iter().rposition(|c| *c > 0).unwrap_or(0);
+
+        self.edges_pq[last + 1]
+    }
+
+    /// Mean of the bin centers, weighted by their counts
+    pub fn mean_pq(&self) -> f64 {
+        let sum: f64 = self
+            .counts
+            .iter()
+            .zip(self.edges_pq.windows(2))
+            .map(|(count, edges)| *count as f64 * (edges[0] + edges[1]) / 2.0)
+            .sum();
+
+        sum / self.total as f64
+    }
+
+    /// Luminance at the percentile `p` (0 to 100), interpolated within the bin
+    pub fn percentile_pq(&self, p: f64) -> f64 {
+        let target = (p.clamp(0.0, 100.0) / 100.0) * self.total as f64;
+        let mut cumulative = 0.0;
+
+        for (count, edges) in self.counts.iter().zip(self.edges_pq.windows(2)) {
+            let count = *count as f64;
+
+            if count > 0.0 && cumulative + count >= target {
+                let fraction = (target - cumulative) / count;
+
+                return edges[0] + fraction * (edges[1] - edges[0]);
+            }
+
+            cumulative += count;
+        }
+
+        self.max_pq()
+    }
+
+    /// Average of the luminance at the sorted `percentiles`, weighted by the distance between them.
+    ///
+    /// The distribution is extended to the min at 0% and the max at 100%,
+    /// the quantile function is then integrated with the trapezoidal rule.
+    pub fn percentile_weighted_avg_pq(&self, percentiles: &[f64]) -> f64 {
+        let points: Vec<(f64, f64)> = std::iter::once((0.0, self.min_pq()))
+            .chain(
+                percentiles
+                    .iter()
+                    .map(|p| (p.clamp(0.0, 100.0), self.percentile_pq(*p))),
+            )
+            .chain(std::iter::once((100.0, self.max_pq())))
+            .collect();
+
+        points
+            .windows(2)
+            .map(|w| {
+                let ((p0, v0), (p1, v1)) = (w[0], w[1]);
+                ((p1 - p0) / 100.0) * (v0 + v1) / 2.0
+            })
+            .sum()
+    }
+
+    /// L1 metadata of the histogram, clamped to valid values for the CM version
+    pub fn level1(
+        &self,
+        opts: &HistogramL1Opts,
+        cm_version: CmVersion,
+    ) -> Result<ExtMetadataBlockLevel1> {
+        let percentiles = opts.max_percentile.iter().chain(&opts.avg_percentiles);
+
+        for p in percentiles {
+            ensure!(
+                (0.0..=100.0).contains(p),
+                "Invalid percentile {}, must be between 0 and 100",
+                p
+            );
+        }
+        ensure!(
+            opts.avg_percentiles.windows(2).all(|w| w[0] <= w[1]),
+            "Average percentiles must be ascending"
+        );
+
+        let max_pq = match opts.max_percentile {
+            Some(p) => self.percentile_pq(p),
+            None => self.max_pq(),
+        };
+
+        let avg_pq = if opts.avg_percentiles.is_empty() {
+            self.mean_pq()
+        } else {
+            self.percentile_weighted_avg_pq(&opts.avg_percentiles)
+        };
+
+        Ok(ExtMetadataBlockLevel1::from_normalized_pq_cm_version(
+            self.min_pq(),
+            max_pq,
+            avg_pq,
+            cm_version,
+        ))
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+
+use hevc_parser::hevc::{NALUnit, NAL_EOB_NUT, NAL_EOS_NUT, NAL_UNSPEC62};
+use hevc_parser::HevcParser;
+
+use super::dovi_rpu::DoviRpu;
+
+const HEVC_START_CODE: &[u8] = &[0, 0, 0, 1];
+
+/// `io::Write` adapter injecting RPUs into an HEVC Annex B byte stream.
+///
+/// The stream written into it is split into NAL units, and the RPU of every frame
+/// is inserted at the end of its access unit before forwarding to `inner`.
+/// RPU NAL units already present in the stream are dropped.
+///
+/// The RPUs are consumed in presentation order, the frames being ordered by POC like `inject-rpu`.
+/// The order is only known at the next key frame, so the access units of a GOP are held
+/// in memory until then. `finish` must be called to write the frames of the last GOP.
+pub struct RpuInjectWriter<W: Write, I: Iterator<Item = DoviRpu>> {
+    inner: W,
+    rpus: I,
+    parser: HevcParser,
+
+    buffer: Vec<u8>,
+    scan_pos: usize,
+
+    // Access units not written yet, in decoding order
+    pending_frames: VecDeque<PendingFrame>,
+    // Presentation numbers of the pending frames already ordered by the parser
+    presentation_numbers: HashMap<u64, u64>,
+    ordered_count: usize,
+
+    // RPUs taken from the iterator ahead of their frame
+    reordered_rpus: HashMap<u64, DoviRpu>,
+    next_rpu: u64,
+
+    frame_count: usize,
+}
+
+struct PendingFrame {
+    decoded_number: u64,
+    data: Vec<u8>,
+    // End of the last NAL unit that isn't EOS/EOB, where the RPU is inserted
+    rpu_position: usize,
+}
+
+impl<W: Write, I: Iterator<Item = DoviRpu>> RpuInjectWriter<W, I> {
+    pub fn new<R: IntoIterator<IntoIter = I>>(inner: W, rpus: R) -> Self {
+        Self {
+            inner,
+            rpus: rpus.into_iter(),
+            parser: HevcParser::default(),
+            buffer: Vec::with_capacity(100_000),
+            scan_pos: 0,
+            pending_frames: VecDeque::new(),
+            presentation_numbers: HashMap::new(),
+            ordered_count: 0,
+            reordered_rpus: HashMap::new(),
+            next_rpu: 0,
+            frame_count: 0,
+        }
+    }
+
+    /// Number of frames that had a RPU injected
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// Processes the last NAL unit, injects the RPUs of the last GOP and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.process_buffer(true)?;
+
+        self.parser.finish();
+        self.write_ordered_frames(true)?;
+
+        // Bytes without any start code
+        self.inner.write_all(&self.buffer)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+
+    fn process_buffer(&mut self, finished: bool) -> io::Result<()> {
+        let mut start = 0;
+
+        while let Some(start_code) = find_start_code(&self.buffer, start) {
+            let payload_start = start_code + 3;
+            let next = find_start_code(&self.buffer, payload_start.max(self.scan_pos));
+
+            let end = match next {
+                // Zero bytes before the start code belong to the next NAL unit
+                Some(next) => {
+                    let mut end = next;
+                    while end > payload_start && self.buffer[end - 1] == 0 {
+                        end -= 1;
+                    }
+
+                    end
+                }
+                None if finished => self.buffer.len(),
+                None => break,
+            };
+
+            self.process_nal(start, start_code, end)?;
+            start = end;
+            self.scan_pos = 0;
+        }
+
+        self.buffer.drain(..start);
+
+        // Resume searching for the end of the incomplete NAL unit
+        self.scan_pos = self.buffer.len().saturating_sub(2);
+
+        self.write_ordered_frames(false)
+    }
+
+    fn process_nal(&mut self, start: usize, start_code: usize, end: usize) -> io::Result<()> {
+        // Too short for a NAL unit header, kept with the current access unit
+        if end - start_code < 5 {
+            return self.push_to_frame(start, end, None);
+        }
+
+        let nal = self
+            .parser
+            .split_nals(&self.buffer[start_code..end], &[0], 0, true)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .remove(0);
+
+        if nal.nal_type == NAL_UNSPEC62 {
+            return Ok(());
+        }
+
+        self.push_to_frame(start, end, Some(nal))
+    }
+
+    fn push_to_frame(&mut self, start: usize, end: usize, nal: Option<NALUnit>) -> io::Result<()> {
+        let decoded_number = match (&nal, self.pending_frames.back()) {
+            (Some(nal), _) => nal.decoded_frame_index,
+            (None, Some(frame)) => frame.decoded_number,
+            (None, None) => return self.inner.write_all(&self.buffer[start..end]),
+        };
+
+        if self
+            .pending_frames
+            .back()
+            .map_or(true, |frame| frame.decoded_number != decoded_number)
+        {
+            self.pending_frames.push_back(PendingFrame {
+                decoded_number,
+                data: Vec::new(),
+                rpu_position: 0,
+            });
+        }
+
+        if let Some(frame) = self.pending_frames.back_mut() {
+            frame.data.extend_from_slice(&self.buffer[start..end]);
+
+            let trailing = nal.map_or(false, |nal| {
+                matches!(nal.nal_type, NAL_EOS_NUT | NAL_EOB_NUT)
+            });
+            if !trailing {
+                frame.rpu_position = frame.data.len();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the complete frames with a known presentation number, in decoding order.
+    fn write_ordered_frames(&mut self, finished: bool) -> io::Result<()> {
+        let ordered_frames = self.parser.ordered_frames();
+
+        for frame in &ordered_frames[self.ordered_count..] {
+            self.presentation_numbers
+                .insert(frame.decoded_number, frame.presentation_number);
+        }
+        self.ordered_count = ordered_frames.len();
+
+        while let Some(decoded_number) = self.pending_frames.front().map(|f| f.decoded_number) {
+            // The last access unit can still receive NAL units
+            if !finished && self.pending_frames.len() == 1 {
+                break;
+            }
+
+            let presentation_number = self.presentation_numbers.remove(&decoded_number);
+
+            if presentation_number.is_none() && !finished {
+                break;
+            }
+
+            let frame = match self.pending_frames.pop_front() {
+                Some(frame) => frame,
+                None => break,
+            };
+
+            match presentation_number {
+                Some(presentation_number) => {
+                    let rpu = self.take_rpu(presentation_number)?;
+                    let data = rpu
+                        .write_hevc_unspec62_nalu()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                    self.inner.write_all(&frame.data[..frame.rpu_position])?;
+                    self.inner.write_all(HEVC_START_CODE)?;
+                    self.inner.write_all(&data)?;
+                    self.inner.write_all(&frame.data[frame.rpu_position..])?;
+
+                    self.frame_count += 1;
+                }
+                // NAL units after the last picture
+                None => self.inner.write_all(&frame.data)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn take_rpu(&mut self, presentation_number: u64) -> io::Result<DoviRpu> {
+        if let Some(rpu) = self.reordered_rpus.remove(&presentation_number) {
+            return Ok(rpu);
+        }
+
+        while self.next_rpu <= presentation_number {
+            let rpu = match self.rpus.next() {
+                Some(rpu) => rpu,
+                None => break,
+            };
+
+            let index = self.next_rpu;
+            self.next_rpu += 1;
+
+            if index == presentation_number {
+                return Ok(rpu);
+            }
+
+            self.reordered_rpus.insert(index, rpu);
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No RPU available for presentation frame {presentation_number}"),
+        ))
+    }
+}
+
+impl<W: Write, I: Iterator<Item = DoviRpu>> Write for RpuInjectWriter<W, I> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        self.process_buffer(false)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn find_start_code(data: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(3)
+        .position(|w| w == [0, 0, 1])
+        .map(|pos| from + pos)
+}
@@ -1,20 +1,38 @@
+#[cfg(feature = "bitstream")]
 use crc::{Crc, CRC_32_MPEG_2};
 
 pub mod dovi_rpu;
 pub mod extension_metadata;
 pub mod generate;
+pub mod histogram;
+pub mod parse_warning;
 pub mod profiles;
 pub mod rpu_data_header;
 pub mod rpu_data_mapping;
 pub mod rpu_data_nlq;
+pub mod synthetic;
+pub mod timeline;
 pub mod vdr_dm_data;
 
+#[cfg(feature = "hevc")]
+pub mod inject_writer;
+#[cfg(feature = "bitstream")]
+pub mod bit_fields;
+#[cfg(feature = "bitstream")]
+pub mod compat;
+#[cfg(feature = "bitstream")]
+pub mod nalu_reader;
+#[cfg(feature = "bitstream")]
+pub mod roundtrip;
+#[cfg(feature = "bitstream")]
 pub mod utils;
 
+#[cfg(feature = "bitstream")]
 static CRC32_INSTANCE: Crc<u32> = Crc::<u32>::new(&CRC_32_MPEG_2);
 
 pub const NUM_COMPONENTS: usize = 3;
 
+#[cfg(feature = "bitstream")]
 pub(crate) const MMR_MAX_COEFFS: usize = 7;
 pub(crate) const NLQ_NUM_PIVOTS: usize = 2;
 
@@ -28,6 +46,7 @@ pub enum ConversionMode {
 }
 
 #[inline(always)]
+#[cfg(feature = "bitstream")]
 fn compute_crc32(data: &[u8]) -> u32 {
     let mut digest = CRC32_INSTANCE.digest();
     digest.update(data);
@@ -42,6 +61,7 @@ impl From<u8> for ConversionMode {
             1 => ConversionMode::ToMel,
             2 | 3 => ConversionMode::To81,
             4 => ConversionMode::To84,
+            5 => ConversionMode::To81MappingPreserved,
             _ => ConversionMode::Lossless,
         }
     }
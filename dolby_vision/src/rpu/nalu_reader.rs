@@ -0,0 +1,197 @@
+use std::io::{self, Read};
+
+use anyhow::{ensure, Result};
+
+use super::dovi_rpu::DoviRpu;
+use crate::utils::clear_start_code_emulation_prevention_3_byte;
+
+const READ_CHUNK_SIZE: usize = 100_000;
+
+const NAL_UNSPEC62: u8 = 62;
+
+/// NAL unit of an HEVC Annex B byte stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HevcNalu {
+    pub nal_type: u8,
+    pub layer_id: u8,
+    pub temporal_id: u8,
+
+    /// Offset of the start code in the stream
+    pub start_code_offset: u64,
+    /// Start code length, 3 or 4 bytes
+    pub start_code_len: u8,
+    /// Offset of the NAL unit header in the stream
+    pub offset: u64,
+
+    /// Escaped NAL unit data starting with the header, without the trailing zero bytes
+    pub data: Vec<u8>,
+}
+
+/// Iterator over the NAL units of an HEVC Annex B byte stream.
+///
+/// The stream is read in chunks, only the NAL unit being split is buffered.
+/// Bytes preceding the first start code are skipped.
+pub struct NaluReader<R: Read> {
+    reader: R,
+    eof: bool,
+
+    buffer: Vec<u8>,
+    /// Start of the unprocessed data in the buffer
+    pos: usize,
+    /// Stream offset of the first buffer byte
+    buffer_offset: u64,
+}
+
+impl HevcNalu {
+    pub fn is_rpu(&self) -> bool {
+        self.nal_type == NAL_UNSPEC62
+    }
+
+    /// Unescaped RPU payload of an UNSPEC62 NAL unit, starting with the 0x19 prefix.
+    ///
+    /// The payload can be parsed with `DoviRpu::parse_rpu`.
+    pub fn rpu_payload(&self) -> Result<Vec<u8>> {
+        ensure!(
+            self.is_rpu(),
+            "NAL unit type {} is not a RPU",
+            self.nal_type
+        );
+
+        let trimmed_data = DoviRpu::validated_trimmed_data(&self.data)?;
+
+        Ok(clear_start_code_emulation_prevention_3_byte(trimmed_data))
+    }
+}
+
+impl<R: Read> NaluReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            eof: false,
+            buffer: Vec::with_capacity(READ_CHUNK_SIZE),
+            pos: 0,
+            buffer_offset: 0,
+        }
+    }
+
+    /// Next NAL unit, none at the end of the stream
+    pub fn next_nalu(&mut self) -> io::Result<Option<HevcNalu>> {
+        let start_code = loop {
+            if let Some(start_code) = find_start_code(&self.buffer, self.pos) {
+                break start_code;
+            } else if self.eof {
+                self.pos = self.buffer.len();
+                return Ok(None);
+            }
+
+            // Keeps the zero bytes of a start code split between reads
+            self.pos = self.buffer.len().saturating_sub(3).max(self.pos);
+            self.read_chunk()?;
+        };
+
+        // A zero byte before the start code makes it 4 bytes long
+        let start_code_len = if start_code > self.pos && self.buffer[start_code - 1] == 0 {
+            4
+        } else {
+            3
+        };
+
+        // The NAL unit is kept at the start of the buffer while reading
+        self.pos = start_code + 3 - start_code_len;
+
+        // Searched from the payload, relative to the start code
+        let mut scan_from = start_code_len;
+
+        let end = loop {
+            if let Some(next) = find_start_code(&self.buffer, self.pos + scan_from) {
+                break next;
+            } else if self.eof {
+                break self.buffer.len();
+            }
+
+            // The next start code can be split between reads
+            scan_from = (self.buffer.len() - self.pos)
+                .saturating_sub(2)
+                .max(start_code_len);
+            self.read_chunk()?;
+        };
+
+        let payload_start = self.pos + start_code_len;
+
+        // Zero bytes before the next start code are trailing zeroes, or part of the start code
+        let data_end = end
+            - self.buffer[payload_start..end]
+                .iter()
+                .rev()
+                .take_while(|b| **b == 0)
+                .count();
+        let data = self.buffer[payload_start..data_end].to_vec();
+
+        let (nal_type, layer_id, temporal_id) = match data.as_slice() {
+            [first, second, ..] => (
+                (first >> 1) & 0x3F,
+                ((first & 1) << 5) | (second >> 3),
+                (second & 0x07).saturating_sub(1),
+            ),
+            [first] => ((first >> 1) & 0x3F, 0, 0),
+            [] => (0, 0, 0),
+        };
+
+        let nalu = HevcNalu {
+            nal_type,
+            layer_id,
+            temporal_id,
+            start_code_offset: self.buffer_offset + self.pos as u64,
+            start_code_len: start_code_len as u8,
+            offset: self.buffer_offset + payload_start as u64,
+            data,
+        };
+
+        self.pos = data_end;
+
+        Ok(Some(nalu))
+    }
+
+    /// Drops the processed data and reads the next chunk
+    fn read_chunk(&mut self) -> io::Result<()> {
+        if self.pos > 0 {
+            self.buffer.drain(..self.pos);
+            self.buffer_offset += self.pos as u64;
+            self.pos = 0;
+        }
+
+        let len = self.buffer.len();
+        self.buffer.resize(len + READ_CHUNK_SIZE, 0);
+
+        let read = loop {
+            match self.reader.read(&mut self.buffer[len..]) {
+                Ok(read) => break read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.buffer.truncate(len);
+                    return Err(e);
+                }
+            }
+        };
+
+        self.buffer.truncate(len + read);
+        self.eof = read == 0;
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for NaluReader<R> {
+    type Item = io::Result<HevcNalu>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_nalu().transpose()
+    }
+}
+
+fn find_start_code(data: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(3)
+        .position(|w| w == [0, 0, 1])
+        .map(|pos| from + pos)
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+#[cfg(feature = "bitstream")]
+use super::{
+    dovi_rpu::DoviRpu,
+    extension_metadata::blocks::{level10::PRESET_TARGET_DISPLAYS, ExtMetadataBlock},
+};
+
+/// Levels that are expected at most once per RPU
+#[cfg(feature = "bitstream")]
+const SINGLE_BLOCK_LEVELS: [u8; 8] = [1, 3, 4, 5, 6, 9, 11, 254];
+
+/// Recoverable oddity found while parsing a RPU.
+///
+/// The RPU is still valid and rewritten as parsed,
+/// but the encoder that produced it likely deviates from the usual metadata.
+///
+/// The `MisorderedBlocks` and `WrongNumExtBlocks` artifacts are only reported
+/// when parsing with the old dovi_tool compatibility, see `rpu::compat`, which corrects them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum ParseWarning {
+    /// Reserved header bits set to a value without a known meaning
+    ReservedBitsSet { field: &'static str, value: u64 },
+
+    /// Bits between the DM data and the CRC32 that were not parsed
+    UnparsedPayload { bits: usize },
+
+    /// Metadata level present more than once
+    DuplicateBlock { level: u8, count: usize },
+
+    /// L1 `min_pq` above `max_pq`, or `avg_pq` outside of them
+    InconsistentLevel1 {
+        min_pq: u16,
+        max_pq: u16,
+        avg_pq: u16,
+    },
+
+    /// L8 trim for a target display that is neither a preset nor defined by L10
+    UndefinedTargetDisplay { target_display_index: u8 },
+
+    /// L9 `source_primary_index` without predefined primaries
+    UnknownSourcePrimaries { source_primary_index: u8 },
+
+    /// Extension blocks not sorted by level, sorted when parsed
+    MisorderedBlocks { cm_version: &'static str },
+
+    /// `num_ext_blocks` not matching the blocks present, corrected when parsed
+    WrongNumExtBlocks {
+        cm_version: &'static str,
+        signaled: u64,
+        parsed: u64,
+    },
+}
+
+impl ParseWarning {
+    #[cfg(feature = "bitstream")]
+    pub(crate) fn collect(rpu: &DoviRpu) -> Vec<ParseWarning> {
+        let mut warnings = Vec::new();
+
+        // 1 signals compressed DM data
+        if rpu.header.reserved_zero_3bits > 1 {
+            warnings.push(ParseWarning::ReservedBitsSet {
+                field: "reserved_zero_3bits",
+                value: rpu.header.reserved_zero_3bits as u64,
+            });
+        }
+
+        if let Some(remaining) = &rpu.remaining {
+            warnings.push(ParseWarning::UnparsedPayload {
+                bits: remaining.len(),
+            });
+        }
+
+        let vdr_dm_data = match &rpu.vdr_dm_data {
+            Some(vdr_dm_data) => vdr_dm_data,
+            None => return warnings,
+        };
+
+        for level in SINGLE_BLOCK_LEVELS {
+            let count = vdr_dm_data.level_blocks_iter(level).count();
+
+            if count > 1 {
+                warnings.push(ParseWarning::DuplicateBlock { level, count });
+            }
+        }
+
+        let l10_indexes: Vec<u8> = vdr_dm_data
+            .level_blocks_iter(10)
+            .filter_map(|block| match block {
+                ExtMetadataBlock::Level10(b) => Some(b.target_display_index),
+                _ => None,
+            })
+            .collect();
+
+        for block in vdr_dm_data.level_blocks_iter(1) {
+            if let ExtMetadataBlock::Level1(b) = block {
+                if b.has_inconsistent_stats() {
+                    warnings.push(ParseWarning::InconsistentLevel1 {
+                        min_pq: b.min_pq,
+                        max_pq: b.max_pq,
+                        avg_pq: b.avg_pq,
+                    });
+                }
+            }
+        }
+
+        for block in vdr_dm_data.level_blocks_iter(8) {
+            if let ExtMetadataBlock::Level8(b) = block {
+                let index = b.target_display_index;
+
+                if !PRESET_TARGET_DISPLAYS.contains(&index) && !l10_indexes.contains(&index) {
+                    warnings.push(ParseWarning::UndefinedTargetDisplay {
+                        target_display_index: index,
+                    });
+                }
+            }
+        }
+
+        for block in vdr_dm_data.level_blocks_iter(9) {
+            if let ExtMetadataBlock::Level9(b) = block {
+                if b.primaries_xy().is_none() {
+                    warnings.push(ParseWarning::UnknownSourcePrimaries {
+                        source_primary_index: b.source_primary_index,
+                    });
+                }
+            }
+        }
+
+        warnings
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarning::ReservedBitsSet { field, value } => {
+                write!(f, "reserved bits set, {field} = {value}")
+            }
+            ParseWarning::UnparsedPayload { bits } => {
+                write!(f, "{bits} unparsed bits before the CRC32")
+            }
+            ParseWarning::DuplicateBlock { level, count } => {
+                write!(f, "L{level} metadata present {count} times")
+            }
+            ParseWarning::InconsistentLevel1 {
+                min_pq,
+                max_pq,
+                avg_pq,
+            } => write!(
+                f,
+                "inconsistent L1 metadata, min {min_pq}, max {max_pq}, avg {avg_pq}"
+            ),
+            ParseWarning::UndefinedTargetDisplay {
+                target_display_index,
+            } => write!(
+                f,
+                "L8 trim for target display {target_display_index}, not a preset and without L10"
+            ),
+            ParseWarning::UnknownSourcePrimaries {
+                source_primary_index,
+            } => write!(
+                f,
+                "unknown L9 source primaries index {source_primary_index}"
+            ),
+            ParseWarning::MisorderedBlocks { cm_version } => {
+                write!(
+                    f,
+                    "{cm_version} metadata blocks not sorted by level, sorted"
+                )
+            }
+            ParseWarning::WrongNumExtBlocks {
+                cm_version,
+                signaled,
+                parsed,
+            } => write!(
+                f,
+                "{cm_version} num_ext_blocks is {signaled} for {parsed} blocks, corrected"
+            ),
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use std::fmt::Display;
+
+use super::rpu_data_nlq::DoviELType;
 use super::vdr_dm_data::VdrDmData;
 
 pub mod profile4;
@@ -15,3 +18,101 @@ pub trait DoviProfile {
         true
     }
 }
+
+/// Dolby Vision profile of a RPU, as signaled by its header and mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RpuProfile {
+    /// Dual layer, SDR compatible base layer
+    Profile4,
+    /// Single layer, IPT base layer without backwards compatibility
+    Profile5,
+    /// Dual layer, HDR10 compatible base layer
+    Profile7 { el_type: Option<DoviELType> },
+    /// Single layer, the base layer compatibility (8.1, 8.2 or 8.4) isn't signaled in the RPU
+    Profile8,
+    /// Header not matching any profile
+    Unknown,
+}
+
+/// Base layer the non Dolby Vision devices can decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlCompatibility {
+    /// IPT, not viewable without Dolby Vision
+    None,
+    Hdr10,
+    Sdr,
+}
+
+impl RpuProfile {
+    /// Profile from the number returned by `RpuDataHeader::get_dovi_profile`
+    pub fn new(profile: u8, el_type: Option<DoviELType>) -> Self {
+        match profile {
+            4 => Self::Profile4,
+            5 => Self::Profile5,
+            7 => Self::Profile7 { el_type },
+            8 => Self::Profile8,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Profile number, 0 if unknown
+    pub fn number(&self) -> u8 {
+        match self {
+            Self::Profile4 => 4,
+            Self::Profile5 => 5,
+            Self::Profile7 { .. } => 7,
+            Self::Profile8 => 8,
+            Self::Unknown => 0,
+        }
+    }
+
+    pub fn is_single_layer(&self) -> bool {
+        matches!(self, Self::Profile5 | Self::Profile8)
+    }
+
+    /// Whether the EL carries a residual, so that discarding it loses picture data.
+    ///
+    /// A MEL can be discarded without loss.
+    pub fn requires_el(&self) -> bool {
+        matches!(
+            self,
+            Self::Profile4
+                | Self::Profile7 {
+                    el_type: Some(DoviELType::FEL)
+                }
+        )
+    }
+
+    /// Base layer compatibility, `None` when it isn't known from the RPU
+    pub fn bl_compatibility(&self) -> Option<BlCompatibility> {
+        match self {
+            Self::Profile4 => Some(BlCompatibility::Sdr),
+            Self::Profile5 => Some(BlCompatibility::None),
+            Self::Profile7 { .. } => Some(BlCompatibility::Hdr10),
+            Self::Profile8 | Self::Unknown => None,
+        }
+    }
+
+    /// `bl_signal_compatibility_id` of the Dolby Vision configuration record,
+    /// `None` when it isn't known from the RPU
+    pub fn bl_signal_compatibility_id(&self) -> Option<u8> {
+        self.bl_compatibility()
+            .map(|compatibility| match compatibility {
+                BlCompatibility::None => 0,
+                BlCompatibility::Hdr10 => 6,
+                BlCompatibility::Sdr => 2,
+            })
+    }
+}
+
+impl Display for RpuProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Profile7 {
+                el_type: Some(el_type),
+            } => write!(f, "7 ({el_type})"),
+            Self::Unknown => f.write_str("unknown"),
+            profile => write!(f, "{}", profile.number()),
+        }
+    }
+}
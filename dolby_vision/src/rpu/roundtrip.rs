@@ -0,0 +1,270 @@
+//! Reverse derivation of a generate config from RPUs, to check the generator and the writer.
+//!
+//! A config is generated, written, parsed back and derived again from the parsed RPUs.
+//! The derived config must generate the same RPUs, which catches asymmetries
+//! between the generator, the writer and the parser.
+
+use anyhow::{bail, ensure, Result};
+
+use super::dovi_rpu::DoviRpu;
+use super::extension_metadata::blocks::ExtMetadataBlock;
+use super::extension_metadata::{DmData, WithExtMetadataBlocks};
+use super::generate::{
+    GenerateAutoBlocksConfig, GenerateConfig, GenerateProfile, GenerateSignalConfig, ShotFrameEdit,
+    VideoShot,
+};
+use super::profiles::RpuProfile;
+use super::vdr_dm_data::{CmVersion, VdrDmData};
+
+/// Derives a config generating the same RPUs, frame for frame.
+///
+/// Shots start on the scene cuts, or on the metadata changes in long play mode.
+/// The blocks shared by every shot are used as the default metadata blocks,
+/// and the frames differing from the first frame of their shot become frame edits.
+///
+/// The derived config only uses explicit blocks, the automatic L9 and L11 are disabled.
+pub fn derive_config(rpus: &[DoviRpu]) -> Result<GenerateConfig> {
+    ensure!(!rpus.is_empty(), "No RPU to derive a config from");
+
+    let dm_data = rpus
+        .iter()
+        .enumerate()
+        .map(|(frame, rpu)| match rpu.vdr_dm_data.as_ref() {
+            Some(vdr_dm_data) => Ok(vdr_dm_data),
+            None => bail!("Frame {frame}: no DM metadata to derive a config from"),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let first_rpu = &rpus[0];
+    let first_dm = dm_data[0];
+
+    let profile = match first_rpu.profile() {
+        RpuProfile::Profile5 => GenerateProfile::Profile5,
+        RpuProfile::Profile8 => {
+            // Only the 8.4 mapping uses MMR
+            let mmr_mapping = first_rpu
+                .rpu_data_mapping
+                .as_ref()
+                .map_or(false, |mapping| {
+                    mapping.curves.iter().any(|curve| curve.mmr.is_some())
+                });
+
+            if mmr_mapping {
+                GenerateProfile::Profile84
+            } else {
+                GenerateProfile::Profile81
+            }
+        }
+        profile => bail!("Profile {profile} RPUs can't be generated"),
+    };
+
+    let cm_version = if first_dm.cmv40_metadata.is_some() {
+        CmVersion::V40
+    } else {
+        CmVersion::V29
+    };
+
+    let signal = match profile {
+        GenerateProfile::Profile5 => GenerateSignalConfig::default(),
+        _ => GenerateSignalConfig {
+            bl_video_full_range: Some(first_rpu.header.bl_video_full_range_flag),
+            signal_full_range_flag: Some(first_dm.signal_full_range_flag),
+            signal_chroma_format: Some(first_dm.signal_chroma_format),
+            chroma_resampling_explicit_filter: Some(
+                first_rpu.header.chroma_resampling_explicit_filter_flag,
+            ),
+        },
+    };
+
+    let long_play_mode = dm_data.len() > 1 && dm_data.iter().all(|dm| dm.scene_refresh_flag == 1);
+
+    let mut config = GenerateConfig {
+        cm_version,
+        profile,
+        long_play_mode,
+        length: rpus.len(),
+        source_min_pq: Some(first_dm.source_min_pq),
+        source_max_pq: Some(first_dm.source_max_pq),
+        signal,
+        auto_blocks: GenerateAutoBlocksConfig {
+            level9: false,
+            level11: false,
+        },
+        level6: None,
+        ..Default::default()
+    };
+
+    if let Some(ExtMetadataBlock::Level5(level5)) = first_dm.get_block(5) {
+        config.level5 = level5.clone();
+    }
+    if let Some(ExtMetadataBlock::Level6(level6)) = first_dm.get_block(6) {
+        config.level6 = Some(level6.clone());
+    }
+    if let Some(ExtMetadataBlock::Level254(level254)) = first_dm.get_block(254) {
+        config.level254 = Some(level254.clone());
+    }
+
+    let base_blocks = dm_blocks(config.base_rpu()?.vdr_dm_data.as_ref());
+
+    let mut shot_starts: Vec<usize> = (0..dm_data.len())
+        .filter(|&frame| {
+            frame == 0
+                || if long_play_mode {
+                    !dm_data[frame - 1].metadata_eq(dm_data[frame])
+                } else {
+                    dm_data[frame].scene_refresh_flag == 1
+                }
+        })
+        .collect();
+    shot_starts.push(dm_data.len());
+
+    for window in shot_starts.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        let shot_blocks = dm_blocks(Some(dm_data[start]));
+
+        let frame_edits = (start + 1..end)
+            .filter(|&frame| !dm_data[frame].metadata_eq(dm_data[start]))
+            .map(|frame| ShotFrameEdit {
+                edit_offset: frame - start,
+                metadata_blocks: dm_blocks(Some(dm_data[frame]))
+                    .into_iter()
+                    .filter(|block| !shot_blocks.contains(block))
+                    .collect(),
+            })
+            .collect();
+
+        config.shots.push(VideoShot {
+            start,
+            duration: end - start,
+            metadata_blocks: shot_blocks
+                .into_iter()
+                .filter(|block| !base_blocks.contains(block))
+                .collect(),
+            frame_edits,
+            ..Default::default()
+        });
+    }
+
+    // Blocks identical in every shot
+    let (first_shot, other_shots) = config.shots.split_first_mut().unwrap();
+    let (default_blocks, first_shot_blocks): (Vec<_>, Vec<_>) =
+        first_shot.metadata_blocks.drain(..).partition(|block| {
+            other_shots
+                .iter()
+                .all(|shot| shot.metadata_blocks.contains(block))
+        });
+
+    first_shot.metadata_blocks = first_shot_blocks;
+    for shot in other_shots {
+        shot.metadata_blocks
+            .retain(|block| !default_blocks.contains(block));
+    }
+
+    config.default_metadata_blocks = default_blocks;
+
+    Ok(config)
+}
+
+/// Generates the RPUs of `config`, writes and parses them back,
+/// then checks that the config derived from the parsed RPUs generates the same RPUs.
+///
+/// Fails on the first frame that doesn't survive the roundtrip, returns the derived config otherwise.
+pub fn check_roundtrip(config: &GenerateConfig) -> Result<GenerateConfig> {
+    let rpus = config.generate_rpu_list()?;
+
+    let mut written = Vec::with_capacity(rpus.len());
+    let mut parsed = Vec::with_capacity(rpus.len());
+
+    for (frame, rpu) in rpus.iter().enumerate() {
+        let data = rpu.write_hevc_unspec62_nalu()?;
+        let parsed_rpu = DoviRpu::parse_unspec62_nalu(&data)?;
+
+        // Fields that aren't written, like the optional L8 fields, can be lost
+        if parsed_rpu.write_hevc_unspec62_nalu()? != data {
+            let reason = rpu_difference(rpu, &parsed_rpu)
+                .unwrap_or_else(|| String::from("the RPU bytes differ"));
+
+            bail!("Frame {frame}: the written RPU doesn't parse back to the same RPU, {reason}");
+        }
+
+        written.push(data);
+        parsed.push(parsed_rpu);
+    }
+
+    let derived = derive_config(&parsed)?;
+    let regenerated = derived.generate_rpu_list()?;
+
+    ensure!(
+        regenerated.len() == parsed.len(),
+        "The derived config generates {} RPUs, expected {}",
+        regenerated.len(),
+        parsed.len()
+    );
+
+    for (frame, (rpu, data)) in regenerated.iter().zip(&written).enumerate() {
+        if rpu.write_hevc_unspec62_nalu()? != *data {
+            let reason = rpu_difference(&parsed[frame], rpu)
+                .unwrap_or_else(|| String::from("the RPU bytes differ"));
+
+            bail!("Frame {frame}: the derived config generates a different RPU, {reason}");
+        }
+    }
+
+    Ok(derived)
+}
+
+/// Every block of both CM versions, in order
+fn dm_blocks(vdr_dm_data: Option<&VdrDmData>) -> Vec<ExtMetadataBlock> {
+    let vdr_dm_data = match vdr_dm_data {
+        Some(vdr_dm_data) => vdr_dm_data,
+        None => return Vec::new(),
+    };
+
+    [&vdr_dm_data.cmv29_metadata, &vdr_dm_data.cmv40_metadata]
+        .into_iter()
+        .flatten()
+        .flat_map(|dm_data| match dm_data {
+            DmData::V29(meta) => meta.blocks_ref().clone(),
+            DmData::V40(meta) => meta.blocks_ref().clone(),
+        })
+        .filter(|block| !matches!(block, ExtMetadataBlock::Reserved(_)))
+        .collect()
+}
+
+/// First difference found between two RPUs, ignoring the metadata IDs
+fn rpu_difference(expected: &DoviRpu, actual: &DoviRpu) -> Option<String> {
+    if expected.dovi_profile != actual.dovi_profile {
+        return Some(format!(
+            "profile {} instead of {}",
+            actual.dovi_profile, expected.dovi_profile
+        ));
+    }
+
+    match (expected.vdr_dm_data.as_ref(), actual.vdr_dm_data.as_ref()) {
+        (Some(expected), Some(actual)) => {
+            if expected.scene_refresh_flag != actual.scene_refresh_flag {
+                return Some(format!(
+                    "scene cut flag {} instead of {}",
+                    actual.scene_refresh_flag, expected.scene_refresh_flag
+                ));
+            }
+
+            let expected_blocks = dm_blocks(Some(expected));
+            let actual_blocks = dm_blocks(Some(actual));
+
+            if let Some(block) = expected_blocks.iter().find(|b| !actual_blocks.contains(b)) {
+                return Some(format!("missing or different L{} block", block.level()));
+            }
+            if let Some(block) = actual_blocks.iter().find(|b| !expected_blocks.contains(b)) {
+                return Some(format!("unexpected L{} block", block.level()));
+            }
+            if !expected.metadata_eq(actual) {
+                return Some(String::from("different DM metadata"));
+            }
+        }
+        (None, None) => (),
+        _ => return Some(String::from("DM metadata presence differs")),
+    }
+
+    None
+}
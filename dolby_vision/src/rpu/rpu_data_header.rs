@@ -1,4 +1,7 @@
-use anyhow::{bail, ensure, Result};
+#[cfg(feature = "bitstream")]
+use anyhow::bail;
+use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,6 +9,11 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::Serialize;
 
+#[cfg(feature = "bitstream")]
+use super::dovi_rpu::DoviRpu;
+#[cfg(feature = "bitstream")]
+use crate::utils::clear_start_code_emulation_prevention_3_byte;
+
 #[derive(Default, Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct RpuDataHeader {
@@ -35,6 +43,12 @@ pub struct RpuDataHeader {
     pub el_bit_depth_minus8: u64,
     pub vdr_bit_depth_minus8: u64,
 
+    /// Extension mapping signaled by newer encoders in bits 8 to 15 of `el_bit_depth_minus8`.
+    /// Lowest 5 bits
+    pub ext_mapping_idc_lsb: u8,
+    /// Highest 3 bits
+    pub ext_mapping_idc_msb: u8,
+
     pub spatial_resampling_filter_flag: bool,
     pub reserved_zero_3bits: u8,
     pub el_spatial_resampling_filter_flag: bool,
@@ -47,6 +61,22 @@ pub struct RpuDataHeader {
 }
 
 impl RpuDataHeader {
+    /// Header of a HEVC UNSPEC62 NALU, without parsing the rest of the RPU.
+    ///
+    /// Enough for the profile, with `get_dovi_profile`. The CRC32 is not verified.
+    #[cfg(feature = "bitstream")]
+    pub fn parse_unspec62_nalu(data: &[u8]) -> Result<RpuDataHeader> {
+        let bytes =
+            clear_start_code_emulation_prevention_3_byte(DoviRpu::validated_trimmed_data(data)?);
+        let mut reader = BsIoSliceReader::from_slice(&bytes);
+
+        let rpu_prefix: u8 = reader.get_n(8)?;
+        ensure!(rpu_prefix == 25, "rpu_nal_prefix should be 25");
+
+        RpuDataHeader::parse(&mut reader)
+    }
+
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader) -> Result<RpuDataHeader> {
         let rpu_type = reader.get_n(6)?;
         ensure!(rpu_type == 2);
@@ -80,7 +110,17 @@ impl RpuDataHeader {
 
             if header.rpu_format & 0x700 == 0 {
                 header.bl_bit_depth_minus8 = reader.get_ue()?;
-                header.el_bit_depth_minus8 = reader.get_ue()?;
+
+                let el_bit_depth_minus8 = reader.get_ue()?;
+                ensure!(
+                    el_bit_depth_minus8 >> 16 == 0,
+                    "Invalid el_bit_depth_minus8 value: {el_bit_depth_minus8}"
+                );
+
+                header.el_bit_depth_minus8 = el_bit_depth_minus8 & 0xFF;
+                header.ext_mapping_idc_lsb = ((el_bit_depth_minus8 >> 8) & 0x1F) as u8;
+                header.ext_mapping_idc_msb = ((el_bit_depth_minus8 >> 13) & 0x7) as u8;
+
                 header.vdr_bit_depth_minus8 = reader.get_ue()?;
                 header.spatial_resampling_filter_flag = reader.get()?;
                 header.reserved_zero_3bits = reader.get_n(3)?;
@@ -139,8 +179,8 @@ impl RpuDataHeader {
 
         ensure!(self.vdr_rpu_level == 0, "vdr_rpu_level should be 0");
         ensure!(
-            self.bl_bit_depth_minus8 == 2,
-            "bl_bit_depth_minus8 should be 2"
+            matches!(self.bl_bit_depth_minus8, 2 | 4),
+            "bl_bit_depth_minus8 should be 2 or 4"
         );
         ensure!(
             self.el_bit_depth_minus8 == 2,
@@ -158,6 +198,11 @@ impl RpuDataHeader {
         Ok(())
     }
 
+    /// Extension mapping, 0 if not signaled
+    pub fn ext_mapping_idc(&self) -> u8 {
+        (self.ext_mapping_idc_msb << 5) | self.ext_mapping_idc_lsb
+    }
+
     pub fn get_dovi_profile(&self) -> u8 {
         match self.vdr_rpu_profile {
             0 => {
@@ -184,6 +229,7 @@ impl RpuDataHeader {
         }
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write_header(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         writer.write_n(&self.rpu_type, 6)?;
         writer.write_n(&self.rpu_format, 11)?;
@@ -205,7 +251,9 @@ impl RpuDataHeader {
 
             if self.rpu_format & 0x700 == 0 {
                 writer.write_ue(&self.bl_bit_depth_minus8)?;
-                writer.write_ue(&self.el_bit_depth_minus8)?;
+                let ext_mapping_idc = (self.ext_mapping_idc() as u64) << 8;
+
+                writer.write_ue(&(self.el_bit_depth_minus8 | ext_mapping_idc))?;
                 writer.write_ue(&self.vdr_bit_depth_minus8)?;
                 writer.write(self.spatial_resampling_filter_flag)?;
                 writer.write_n(&self.reserved_zero_3bits, 3)?;
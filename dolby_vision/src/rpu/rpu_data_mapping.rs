@@ -1,4 +1,7 @@
-use anyhow::{bail, ensure, Result};
+#[cfg(feature = "bitstream")]
+use anyhow::bail;
+use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -6,8 +9,10 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::Serialize;
 
+#[cfg(feature = "bitstream")]
 use crate::rpu::MMR_MAX_COEFFS;
 
+#[cfg(feature = "bitstream")]
 use super::rpu_data_header::RpuDataHeader;
 use super::rpu_data_nlq::{DoviELType, RpuDataNlq};
 
@@ -96,6 +101,7 @@ pub struct DoviMMRCurve {
 }
 
 impl RpuDataMapping {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(
         reader: &mut BsIoSliceReader,
         header: &RpuDataHeader,
@@ -113,6 +119,11 @@ impl RpuDataMapping {
             let curve = &mut mapping.curves[cmp];
 
             curve.num_pivots_minus2 = reader.get_ue()?;
+            ensure!(
+                curve.num_pivots_minus2 <= 7,
+                "Invalid num_pivots_minus2: {}",
+                curve.num_pivots_minus2
+            );
             let num_pivots = (curve.num_pivots_minus2 + 2) as usize;
 
             curve.pivots = Vec::with_capacity(num_pivots);
@@ -149,7 +160,10 @@ impl RpuDataMapping {
             let num_pieces = (curve.num_pivots_minus2 + 1) as usize;
 
             for _ in 0..num_pieces {
-                let mapping_idc = DoviMappingMethod::from(reader.get_ue()?);
+                let mapping_idc = reader.get_ue()?;
+                ensure!(mapping_idc <= 1, "Invalid mapping_idc: {}", mapping_idc);
+
+                let mapping_idc = DoviMappingMethod::from(mapping_idc);
                 curve.mapping_idc = mapping_idc;
 
                 // MAPPING_POLYNOMIAL
@@ -176,6 +190,7 @@ impl RpuDataMapping {
         Ok(mapping)
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter, header: &RpuDataHeader) -> Result<()> {
         let coefficient_log2_denom_length = header.coefficient_log2_denom_length;
 
@@ -303,7 +318,7 @@ impl RpuDataMapping {
         Ok(())
     }
 
-    pub fn validate(&self, profile: u8) -> Result<()> {
+    pub fn validate(&self, profile: u8, bl_bit_depth: u8) -> Result<()> {
         match profile {
             5 => {
                 ensure!(
@@ -326,8 +341,10 @@ impl RpuDataMapping {
                 );
 
                 if let Some(nlq_pred_pivot_value) = self.nlq_pred_pivot_value {
+                    let max_value = (1_u16 << bl_bit_depth) - 1;
+
                     ensure!(
-                        nlq_pred_pivot_value.iter().sum::<u16>() == 1023,
+                        nlq_pred_pivot_value.iter().sum::<u16>() == max_value,
                         "profile 7: nlq_pred_pivot_value elements should add up to the BL bit depth"
                     );
                 }
@@ -381,6 +398,37 @@ impl RpuDataMapping {
     pub fn get_enhancement_layer_type(&self) -> Option<DoviELType> {
         self.nlq.as_ref().map(|nlq| nlq.el_type())
     }
+
+    /// Rescales the pivot values from a BL bit depth to another
+    pub fn rescale_pivots(&mut self, from_bit_depth: u8, to_bit_depth: u8) {
+        let from_max = (1_u32 << from_bit_depth) - 1;
+        let to_max = (1_u32 << to_bit_depth) - 1;
+
+        for curve in self.curves.iter_mut() {
+            rescale_pivot_deltas(&mut curve.pivots, from_max, to_max);
+        }
+
+        if let Some(nlq_pred_pivot_value) = self.nlq_pred_pivot_value.as_mut() {
+            rescale_pivot_deltas(nlq_pred_pivot_value, from_max, to_max);
+        }
+    }
+}
+
+/// The pivots are coded as the difference from the previous pivot.
+///
+/// The cumulative values are rescaled, so that the last pivot stays at the maximum codeword.
+fn rescale_pivot_deltas(pivots: &mut [u16], from_max: u32, to_max: u32) {
+    let mut sum = 0;
+    let mut previous = 0;
+
+    for pivot in pivots.iter_mut() {
+        sum += *pivot as u32;
+
+        let scaled = (sum * to_max + from_max / 2) / from_max;
+        *pivot = (scaled - previous) as u16;
+
+        previous = scaled;
+    }
 }
 
 impl DoviPolynomialCurve {
@@ -393,6 +441,7 @@ impl DoviPolynomialCurve {
         }
     }
 
+    #[cfg(feature = "bitstream")]
     fn parse(&mut self, reader: &mut BsIoSliceReader, header: &RpuDataHeader) -> Result<()> {
         let coefficient_log2_denom_length = header.coefficient_log2_denom_length;
 
@@ -410,7 +459,7 @@ impl DoviPolynomialCurve {
 
         if poly_order_minus1 == 0 && linear_interp_flag {
             // Linear interpolation
-            unimplemented!("parse: Polynomial interpolation: please open an issue");
+            bail!("parse: Polynomial interpolation: please open an issue");
 
             /*if header.coefficient_data_type == 0 {
                 self.pred_linear_interp_value_int[i] = reader.get_ue()?;
@@ -471,6 +520,7 @@ impl DoviPolynomialCurve {
 }
 
 impl DoviMMRCurve {
+    #[cfg(feature = "bitstream")]
     fn new(num_pieces: usize) -> Self {
         DoviMMRCurve {
             mmr_order_minus1: Vec::with_capacity(num_pieces),
@@ -481,6 +531,7 @@ impl DoviMMRCurve {
         }
     }
 
+    #[cfg(feature = "bitstream")]
     fn parse(&mut self, reader: &mut BsIoSliceReader, header: &RpuDataHeader) -> Result<()> {
         let coefficient_log2_denom_length = header.coefficient_log2_denom_length;
 
@@ -1,6 +1,8 @@
 use std::fmt::Display;
 
+#[cfg(feature = "bitstream")]
 use anyhow::{ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -8,7 +10,9 @@ use bitvec_helpers::{
 #[cfg(feature = "serde")]
 use serde::Serialize;
 
+#[cfg(feature = "bitstream")]
 use super::rpu_data_header::RpuDataHeader;
+#[cfg(feature = "bitstream")]
 use super::rpu_data_mapping::{DoviNlqMethod, RpuDataMapping};
 
 use super::NUM_COMPONENTS;
@@ -16,7 +20,7 @@ use super::NUM_COMPONENTS;
 const FEL_STR: &str = "FEL";
 const MEL_STR: &str = "MEL";
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum DoviELType {
     MEL,
@@ -37,6 +41,7 @@ pub struct RpuDataNlq {
 }
 
 impl RpuDataNlq {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(
         reader: &mut BsIoSliceReader,
         header: &RpuDataHeader,
@@ -102,6 +107,7 @@ impl RpuDataNlq {
         self.linear_deadzone_threshold.fill(0);
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(
         &self,
         writer: &mut BitstreamIoWriter,
@@ -0,0 +1,329 @@
+use super::extension_metadata::blocks::{
+    level1::{
+        L1_AVG_PQ_MIN_VALUE, L1_AVG_PQ_MIN_VALUE_CMV40, L1_MAX_PQ_MAX_VALUE, L1_MAX_PQ_MIN_VALUE,
+        L1_MIN_PQ_MAX_VALUE,
+    },
+    level10::PRESET_TARGET_DISPLAYS,
+    *,
+};
+use super::generate::{
+    GenerateAutoBlocksConfig, GenerateConfig, GenerateProfile, ShotFrameEdit, VideoShot,
+};
+use super::vdr_dm_data::CmVersion;
+use crate::utils::nits_to_pq12;
+
+/// Target displays of the synthetic L2 trims, in nits
+const L2_TARGET_NITS: [u16; 6] = [100, 300, 600, 1000, 2000, 4000];
+
+/// Valid L8 block lengths, each one adding optional fields
+const L8_LENGTHS: [u64; 5] = [10, 12, 13, 19, 25];
+
+/// Longest synthetic shot, in frames
+const MAX_SHOT_DURATION: usize = 120;
+
+/// Deterministic SplitMix64 generator.
+///
+/// Implemented here so that a seed always generates the same metadata,
+/// regardless of the platform or of external crate versions.
+struct SyntheticRng(u64);
+
+impl SyntheticRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+        z ^ (z >> 31)
+    }
+
+    /// Value in `0..n`
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Value in `min..=max`
+    fn range(&mut self, min: u16, max: u16) -> u16 {
+        min + self.below((max - min) as u64 + 1) as u16
+    }
+
+    /// True once every `n` calls on average
+    fn one_in(&mut self, n: u64) -> bool {
+        self.below(n) == 0
+    }
+
+    fn pick<T: Copy>(&mut self, values: &[T]) -> T {
+        values[self.below(values.len() as u64) as usize]
+    }
+
+    /// Value in `min..=max`, biased towards the bounds and the midpoint
+    fn edge_range(&mut self, min: u16, max: u16) -> u16 {
+        match self.below(8) {
+            0 => min,
+            1 => max,
+            2 => min + (max - min) / 2,
+            _ => self.range(min, max),
+        }
+    }
+
+    fn value_12bit(&mut self) -> u16 {
+        self.edge_range(0, MAX_12_BIT_VALUE)
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.edge_range(0, 255) as u8
+    }
+}
+
+/// Generates a config of randomized but valid metadata, for testing decoders and players.
+///
+/// The profile, CM version, present metadata blocks and their values all vary with `seed`,
+/// edge case values (bounds of the valid ranges) are favored.
+/// The same `seed` and `length` always produce the same config.
+pub fn synthetic_config(seed: u64, length: usize) -> GenerateConfig {
+    let mut rng = SyntheticRng(seed);
+
+    let profile = match rng.below(3) {
+        0 => GenerateProfile::Profile5,
+        1 => GenerateProfile::Profile81,
+        _ => GenerateProfile::Profile84,
+    };
+    let cm_version = if rng.one_in(3) {
+        CmVersion::V29
+    } else {
+        CmVersion::V40
+    };
+
+    let level6 = if rng.one_in(8) {
+        None
+    } else {
+        Some(ExtMetadataBlockLevel6 {
+            max_display_mastering_luminance: rng.pick(&[1000, 2000, 4000, 10000]),
+            min_display_mastering_luminance: rng.pick(&[0, 1, 5, 50]),
+            max_content_light_level: rng.edge_range(0, 10000),
+            max_frame_average_light_level: rng.edge_range(0, 10000),
+        })
+    };
+
+    let mut config = GenerateConfig {
+        cm_version,
+        profile,
+        length,
+        long_play_mode: rng.one_in(16),
+        auto_blocks: GenerateAutoBlocksConfig {
+            level9: !rng.one_in(4),
+            level11: !rng.one_in(4),
+        },
+        level5: synthetic_level5(&mut rng),
+        level6,
+        ..Default::default()
+    };
+
+    config.default_metadata_blocks = synthetic_default_blocks(&mut rng, cm_version);
+
+    let mut start = 0;
+    while start < length {
+        let max_duration = if rng.one_in(4) { 1 } else { MAX_SHOT_DURATION };
+        let duration = (1 + rng.below(max_duration as u64) as usize).min(length - start);
+
+        config
+            .shots
+            .push(synthetic_shot(&mut rng, cm_version, start, duration));
+
+        start += duration;
+    }
+
+    config
+}
+
+fn synthetic_default_blocks(
+    rng: &mut SyntheticRng,
+    cm_version: CmVersion,
+) -> Vec<ExtMetadataBlock> {
+    let mut blocks = Vec::new();
+
+    for nits in L2_TARGET_NITS {
+        if rng.one_in(2) {
+            blocks.push(ExtMetadataBlock::Level2(synthetic_level2(rng, nits)));
+        }
+    }
+
+    if rng.one_in(2) {
+        blocks.push(ExtMetadataBlock::Level4(ExtMetadataBlockLevel4 {
+            anchor_pq: rng.value_12bit(),
+            anchor_power: rng.value_12bit(),
+        }));
+    }
+
+    if rng.one_in(8) {
+        blocks.push(ExtMetadataBlock::Level255(ExtMetadataBlockLevel255 {
+            dm_run_mode: rng.byte(),
+            dm_run_version: rng.byte(),
+            ..Default::default()
+        }));
+    }
+
+    if cm_version == CmVersion::V29 {
+        return blocks;
+    }
+
+    if rng.one_in(2) {
+        blocks.push(ExtMetadataBlock::Level3(ExtMetadataBlockLevel3 {
+            min_pq_offset: rng.value_12bit(),
+            max_pq_offset: rng.value_12bit(),
+            avg_pq_offset: rng.value_12bit(),
+        }));
+    }
+
+    for &index in PRESET_TARGET_DISPLAYS {
+        if rng.one_in(4) {
+            blocks.push(ExtMetadataBlock::Level8(synthetic_level8(rng, index)));
+        }
+    }
+
+    // Custom target display, with its trim
+    if rng.one_in(3) {
+        // Any index that isn't a preset target display
+        let index = rng.range(2, 15) as u8;
+        let target_nits = rng.range(100, 4000);
+
+        blocks.push(ExtMetadataBlock::Level10(ExtMetadataBlockLevel10 {
+            length: 5,
+            target_display_index: index,
+            target_max_pq: nits_to_pq12(target_nits as f64),
+            target_min_pq: rng.range(0, 100),
+            target_primary_index: rng.range(0, 6) as u8,
+            ..Default::default()
+        }));
+        blocks.push(ExtMetadataBlock::Level8(synthetic_level8(rng, index)));
+    }
+
+    if rng.one_in(2) {
+        blocks.push(ExtMetadataBlock::Level9(ExtMetadataBlockLevel9 {
+            length: 1,
+            source_primary_index: rng.range(0, 6) as u8,
+            ..Default::default()
+        }));
+    }
+
+    if rng.one_in(2) {
+        blocks.push(ExtMetadataBlock::Level11(ExtMetadataBlockLevel11 {
+            content_type: rng.edge_range(0, 15) as u8,
+            whitepoint: rng.edge_range(0, 15) as u8,
+            reference_mode_flag: rng.one_in(2),
+            ..Default::default()
+        }));
+    }
+
+    blocks
+}
+
+fn synthetic_shot(
+    rng: &mut SyntheticRng,
+    cm_version: CmVersion,
+    start: usize,
+    duration: usize,
+) -> VideoShot {
+    let mut metadata_blocks = vec![ExtMetadataBlock::Level1(synthetic_level1(rng, cm_version))];
+
+    if rng.one_in(4) {
+        let nits = rng.pick(&L2_TARGET_NITS);
+        metadata_blocks.push(ExtMetadataBlock::Level2(synthetic_level2(rng, nits)));
+    }
+
+    if rng.one_in(8) {
+        metadata_blocks.push(ExtMetadataBlock::Level5(synthetic_level5(rng)));
+    }
+
+    if cm_version == CmVersion::V40 && rng.one_in(4) {
+        let index = rng.pick(PRESET_TARGET_DISPLAYS);
+        metadata_blocks.push(ExtMetadataBlock::Level8(synthetic_level8(rng, index)));
+    }
+
+    let mut frame_edits = Vec::new();
+
+    if duration > 1 && rng.one_in(4) {
+        for edit_offset in 0..duration {
+            if frame_edits.len() < 3 && rng.one_in(16) {
+                frame_edits.push(ShotFrameEdit {
+                    edit_offset,
+                    metadata_blocks: vec![ExtMetadataBlock::Level1(synthetic_level1(
+                        rng, cm_version,
+                    ))],
+                });
+            }
+        }
+    }
+
+    VideoShot {
+        start,
+        duration,
+        metadata_blocks,
+        frame_edits,
+        ..Default::default()
+    }
+}
+
+/// L1 within the ranges accepted by `ExtMetadataBlockLevel1::clamp_values_cm_version`
+fn synthetic_level1(rng: &mut SyntheticRng, cm_version: CmVersion) -> ExtMetadataBlockLevel1 {
+    let avg_min = match cm_version {
+        CmVersion::V29 => L1_AVG_PQ_MIN_VALUE,
+        CmVersion::V40 => L1_AVG_PQ_MIN_VALUE_CMV40,
+    };
+
+    let min_pq = rng.edge_range(0, L1_MIN_PQ_MAX_VALUE);
+    let max_pq = rng.edge_range(L1_MAX_PQ_MIN_VALUE, L1_MAX_PQ_MAX_VALUE);
+    let avg_pq = rng.edge_range(avg_min, max_pq - 1);
+
+    ExtMetadataBlockLevel1::new(min_pq, max_pq, avg_pq)
+}
+
+fn synthetic_level2(rng: &mut SyntheticRng, target_nits: u16) -> ExtMetadataBlockLevel2 {
+    ExtMetadataBlockLevel2 {
+        trim_slope: rng.value_12bit(),
+        trim_offset: rng.value_12bit(),
+        trim_power: rng.value_12bit(),
+        trim_chroma_weight: rng.value_12bit(),
+        trim_saturation_gain: rng.value_12bit(),
+        ms_weight: rng.edge_range(0, MAX_12_BIT_VALUE + 1) as i16 - 1,
+        ..ExtMetadataBlockLevel2::from_nits(target_nits)
+    }
+}
+
+fn synthetic_level5(rng: &mut SyntheticRng) -> ExtMetadataBlockLevel5 {
+    let mut offset = || match rng.below(4) {
+        0 | 1 => 0,
+        2 => rng.range(0, 8191),
+        _ => rng.pick(&[140, 276, 8191]),
+    };
+
+    ExtMetadataBlockLevel5::from_offsets(offset(), offset(), offset(), offset())
+}
+
+fn synthetic_level8(rng: &mut SyntheticRng, target_display_index: u8) -> ExtMetadataBlockLevel8 {
+    ExtMetadataBlockLevel8 {
+        length: rng.pick(&L8_LENGTHS),
+        target_display_index,
+        trim_slope: rng.value_12bit(),
+        trim_offset: rng.value_12bit(),
+        trim_power: rng.value_12bit(),
+        trim_chroma_weight: rng.value_12bit(),
+        trim_saturation_gain: rng.value_12bit(),
+        ms_weight: rng.value_12bit(),
+        target_mid_contrast: rng.value_12bit(),
+        clip_trim: rng.value_12bit(),
+        saturation_vector_field0: rng.byte(),
+        saturation_vector_field1: rng.byte(),
+        saturation_vector_field2: rng.byte(),
+        saturation_vector_field3: rng.byte(),
+        saturation_vector_field4: rng.byte(),
+        saturation_vector_field5: rng.byte(),
+        hue_vector_field0: rng.byte(),
+        hue_vector_field1: rng.byte(),
+        hue_vector_field2: rng.byte(),
+        hue_vector_field3: rng.byte(),
+        hue_vector_field4: rng.byte(),
+        hue_vector_field5: rng.byte(),
+    }
+}
@@ -0,0 +1,211 @@
+//! Compact binary timeline of the per-frame metadata, meant to be memory-mapped by players.
+//!
+//! Every value is little endian, and the entries are aligned to their size.
+//!
+//! Header, 16 bytes:
+//!   - 0: magic `DVTL`
+//!   - 4: `u16` layout version, currently 1
+//!   - 6: `u16` entry size in bytes, 16. Readers should skip the unknown trailing entry bytes.
+//!   - 8: `u32` frame count
+//!   - 12: 4 reserved bytes, zero
+//!
+//! Entries, one per frame in display order, 16 bytes:
+//!   - 0: `u16` L1 max PQ, 12-bit
+//!   - 2: `u16` L1 avg PQ
+//!   - 4: `u16` L1 min PQ
+//!   - 6: `u8` flags: bit 0 scene cut, bit 1 L1 present, bit 2 L5 present
+//!   - 7: reserved byte, zero
+//!   - 8: `u16` L5 offsets, left, right, top and bottom
+
+use std::io::Write;
+
+use anyhow::{ensure, Result};
+
+use super::dovi_rpu::DoviRpu;
+use super::extension_metadata::blocks::ExtMetadataBlock;
+
+pub const TIMELINE_MAGIC: [u8; 4] = *b"DVTL";
+pub const TIMELINE_VERSION: u16 = 1;
+
+pub const TIMELINE_HEADER_SIZE: usize = 16;
+pub const TIMELINE_ENTRY_SIZE: usize = 16;
+
+const FLAG_SCENE_CUT: u8 = 0x01;
+const FLAG_L1: u8 = 0x02;
+const FLAG_L5: u8 = 0x04;
+
+/// Metadata of a single frame in the timeline
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TimelineEntry {
+    pub max_pq: u16,
+    pub avg_pq: u16,
+    pub min_pq: u16,
+
+    pub scene_cut: bool,
+    pub has_l1: bool,
+
+    /// L5 left, right, top and bottom offsets
+    pub active_area: Option<[u16; 4]>,
+}
+
+/// Reader of a timeline, over borrowed bytes such as a memory-mapped file
+#[derive(Debug, Clone, Copy)]
+pub struct MetadataTimeline<'a> {
+    entries: &'a [u8],
+    entry_size: usize,
+    frames: usize,
+}
+
+impl TimelineEntry {
+    pub fn from_rpu(rpu: &DoviRpu) -> Self {
+        let vdr_dm_data = match rpu.vdr_dm_data.as_ref() {
+            Some(vdr_dm_data) => vdr_dm_data,
+            None => return Self::default(),
+        };
+
+        let mut entry = Self {
+            scene_cut: vdr_dm_data.scene_refresh_flag == 1,
+            ..Default::default()
+        };
+
+        if let Some(ExtMetadataBlock::Level1(level1)) = vdr_dm_data.get_block(1) {
+            entry.max_pq = level1.max_pq;
+            entry.avg_pq = level1.avg_pq;
+            entry.min_pq = level1.min_pq;
+            entry.has_l1 = true;
+        }
+
+        if let Some(ExtMetadataBlock::Level5(level5)) = vdr_dm_data.get_block(5) {
+            let (left, right, top, bottom) = level5.get_offsets();
+            entry.active_area = Some([left, right, top, bottom]);
+        }
+
+        entry
+    }
+
+    pub fn to_bytes(self) -> [u8; TIMELINE_ENTRY_SIZE] {
+        let mut flags = 0;
+
+        if self.scene_cut {
+            flags |= FLAG_SCENE_CUT;
+        }
+        if self.has_l1 {
+            flags |= FLAG_L1;
+        }
+        if self.active_area.is_some() {
+            flags |= FLAG_L5;
+        }
+
+        let mut bytes = [0; TIMELINE_ENTRY_SIZE];
+        bytes[0..2].copy_from_slice(&self.max_pq.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.avg_pq.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.min_pq.to_le_bytes());
+        bytes[6] = flags;
+
+        let offsets = self.active_area.unwrap_or_default();
+        for (i, offset) in offsets.iter().enumerate() {
+            bytes[8 + i * 2..10 + i * 2].copy_from_slice(&offset.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    /// Entry from the first `TIMELINE_ENTRY_SIZE` bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() >= TIMELINE_ENTRY_SIZE,
+            "Timeline entry too short: {} bytes",
+            bytes.len()
+        );
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let flags = bytes[6];
+
+        Ok(Self {
+            max_pq: read_u16(0),
+            avg_pq: read_u16(2),
+            min_pq: read_u16(4),
+            scene_cut: flags & FLAG_SCENE_CUT != 0,
+            has_l1: flags & FLAG_L1 != 0,
+            active_area: (flags & FLAG_L5 != 0)
+                .then(|| [read_u16(8), read_u16(10), read_u16(12), read_u16(14)]),
+        })
+    }
+}
+
+impl<'a> MetadataTimeline<'a> {
+    /// Validates the header and the size of the entries
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        ensure!(
+            data.len() >= TIMELINE_HEADER_SIZE && data[0..4] == TIMELINE_MAGIC,
+            "Not a metadata timeline"
+        );
+
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        ensure!(
+            version == TIMELINE_VERSION,
+            "Unsupported metadata timeline version {version}"
+        );
+
+        let entry_size = u16::from_le_bytes([data[6], data[7]]) as usize;
+        ensure!(
+            entry_size >= TIMELINE_ENTRY_SIZE,
+            "Invalid metadata timeline entry size {entry_size}"
+        );
+
+        let frames = u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize;
+        let entries = &data[TIMELINE_HEADER_SIZE..];
+        ensure!(
+            entries.len() >= frames * entry_size,
+            "Truncated metadata timeline: {} frames expected, {} bytes of entries",
+            frames,
+            entries.len()
+        );
+
+        Ok(Self {
+            entries,
+            entry_size,
+            frames,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames == 0
+    }
+
+    pub fn get(&self, frame: usize) -> Option<TimelineEntry> {
+        if frame >= self.frames {
+            return None;
+        }
+
+        let start = frame * self.entry_size;
+        TimelineEntry::from_bytes(&self.entries[start..start + self.entry_size]).ok()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = TimelineEntry> + 'a {
+        let timeline = *self;
+
+        (0..self.frames).filter_map(move |frame| timeline.get(frame))
+    }
+}
+
+/// Writes the timeline of the RPUs, in display order
+pub fn write_timeline<W: Write>(writer: &mut W, rpus: &[DoviRpu]) -> Result<()> {
+    let frames = u32::try_from(rpus.len())?;
+
+    writer.write_all(&TIMELINE_MAGIC)?;
+    writer.write_all(&TIMELINE_VERSION.to_le_bytes())?;
+    writer.write_all(&(TIMELINE_ENTRY_SIZE as u16).to_le_bytes())?;
+    writer.write_all(&frames.to_le_bytes())?;
+    writer.write_all(&[0; 4])?;
+
+    for rpu in rpus {
+        writer.write_all(&TimelineEntry::from_rpu(rpu).to_bytes())?;
+    }
+
+    Ok(())
+}
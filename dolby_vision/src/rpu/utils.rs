@@ -4,11 +4,74 @@ use std::{
     path::Path,
 };
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, ensure, Result};
+use bitvec_helpers::bitstream_io_reader::BsIoSliceReader;
 
-use super::dovi_rpu::DoviRpu;
+use super::dovi_rpu::{DoviRpu, RpuParseOpts};
+use super::rpu_data_header::RpuDataHeader;
+use super::rpu_data_nlq::DoviELType;
+use super::vdr_dm_data::CmVersion;
+use crate::utils::clear_start_code_emulation_prevention_3_byte;
+
+/// Read size when scanning files
+const SCAN_CHUNK_SIZE: usize = 100_000;
+
+/// Summary of a RPU or HEVC file, from `scan_profiles`
+#[derive(Debug, Clone, Default)]
+pub struct RpuScan {
+    pub frame_count: usize,
+
+    /// Dolby Vision profiles present, with their number of frames, in order of appearance
+    pub profiles: Vec<(u8, usize)>,
+
+    /// CM versions present, in order of appearance
+    pub cm_versions: Vec<CmVersion>,
+
+    /// Enhancement layer types present, in order of appearance. Only profile 7 has an EL.
+    pub el_types: Vec<DoviELType>,
+}
 
 pub fn parse_rpu_file<P: AsRef<Path>>(input: P) -> Result<Vec<DoviRpu>> {
+    parse_rpu_file_with_opts(input, RpuParseOpts::default())
+}
+
+/// Parses a RPU file, each RPU parsed with `opts`
+pub fn parse_rpu_file_with_opts<P: AsRef<Path>>(
+    input: P,
+    opts: RpuParseOpts,
+) -> Result<Vec<DoviRpu>> {
+    parse_rpu_file_inner(input.as_ref(), false, opts).map(|(rpus, _)| rpus)
+}
+
+/// RPU that failed to parse when parsing leniently
+#[derive(Debug, Clone)]
+pub struct RpuParseError {
+    /// Index of the RPU in the file
+    pub index: usize,
+    pub error: String,
+}
+
+/// Parses a RPU file, substituting the RPUs that fail to parse instead of aborting.
+///
+/// A corrupt RPU is replaced by the previous valid RPU, or by `DoviRpu::placeholder`
+/// if none was parsed yet. The errors are returned with the RPU list.
+pub fn parse_rpu_file_lenient<P: AsRef<Path>>(
+    input: P,
+) -> Result<(Vec<DoviRpu>, Vec<RpuParseError>)> {
+    let (rpus, errors) = parse_rpu_file_inner(input.as_ref(), true, RpuParseOpts::default())?;
+
+    if errors.len() == rpus.len() {
+        bail!("No valid RPU found");
+    }
+
+    Ok((rpus, errors))
+}
+
+fn parse_rpu_file_inner(
+    input: &Path,
+    lenient: bool,
+    opts: RpuParseOpts,
+) -> Result<(Vec<DoviRpu>, Vec<RpuParseError>)> {
     let rpu_file = File::open(input)?;
     let metadata = rpu_file.metadata()?;
     let file_size_bytes = metadata.len() as usize;
@@ -24,6 +87,7 @@ pub fn parse_rpu_file<P: AsRef<Path>>(input: P) -> Result<Vec<DoviRpu>> {
     // Estimate RPU count from file size
     let mut rpus: Vec<DoviRpu> = Vec::with_capacity(chunk_size / 400);
     let mut warning_error = None;
+    let mut errors = Vec::new();
 
     while let Ok(n) = reader.read(&mut main_buf) {
         let read_bytes = n;
@@ -65,41 +129,51 @@ pub fn parse_rpu_file<P: AsRef<Path>>(input: P) -> Result<Vec<DoviRpu>> {
         };
 
         let count = offsets.len();
-        let parsed_rpus_iter = offsets
-            .iter()
-            .enumerate()
-            .map(|(index, offset)| {
-                let size = if offset == &last {
-                    chunk.len() - offset
-                } else {
-                    let size = if index == count - 1 {
-                        last - offset
-                    } else {
-                        offsets[index + 1] - offset
-                    };
 
-                    match &chunk[offset + size - 1..offset + size + 3] {
-                        [0, 0, 0, 1] => size - 1,
-                        _ => size,
-                    }
+        for (index, offset) in offsets.iter().enumerate() {
+            let size = if offset == &last {
+                chunk.len() - offset
+            } else {
+                let size = if index == count - 1 {
+                    last - offset
+                } else {
+                    offsets[index + 1] - offset
                 };
 
-                let start = *offset;
-                let end = start + size;
+                match &chunk[offset + size - 1..offset + size + 3] {
+                    [0, 0, 0, 1] => size - 1,
+                    _ => size,
+                }
+            };
 
-                DoviRpu::parse_unspec62_nalu(&chunk[start..end])
-            })
-            .enumerate()
-            .filter_map(|(i, res)| {
-                if let Err(e) = &res {
+            let start = *offset;
+            let end = start + size;
+
+            match DoviRpu::parse_unspec62_nalu_with_opts(&chunk[start..end], opts) {
+                Ok(mut rpu) => {
+                    share_previous_metadata(&mut rpu, rpus.last());
+                    rpus.push(rpu);
+                }
+                Err(e) if lenient => {
+                    errors.push(RpuParseError {
+                        index: offsets_count + index,
+                        error: e.to_string(),
+                    });
+
+                    let substitute = match rpus.last() {
+                        Some(previous) => previous.clone(),
+                        None => DoviRpu::placeholder()?,
+                    };
+                    rpus.push(substitute);
+                }
+                Err(e) => {
                     if warning_error.is_none() {
-                        warning_error = Some(format!("Found invalid RPU: Index {i}, error: {e}"))
+                        warning_error =
+                            Some(format!("Found invalid RPU: Index {index}, error: {e}"))
                     }
                 }
-
-                res.ok()
-            });
-        rpus.extend(parsed_rpus_iter);
+            }
+        }
 
         if warning_error.is_some() {
             offsets_count += count;
@@ -122,7 +196,7 @@ pub fn parse_rpu_file<P: AsRef<Path>>(input: P) -> Result<Vec<DoviRpu>> {
     }
 
     if offsets_count > 0 && rpus.len() == offsets_count {
-        Ok(rpus)
+        Ok((rpus, errors))
     } else if offsets_count == 0 {
         bail!("No RPU found");
     } else if let Some(error) = warning_error {
@@ -135,3 +209,153 @@ pub fn parse_rpu_file<P: AsRef<Path>>(input: P) -> Result<Vec<DoviRpu>> {
         );
     }
 }
+
+/// Counts the RPUs of a RPU or HEVC file, without parsing them.
+///
+/// The file is read in chunks, so the memory use doesn't depend on its size.
+pub fn count_frames<P: AsRef<Path>>(input: P) -> Result<usize> {
+    let mut count = 0;
+
+    for_each_rpu_nalu(input.as_ref(), |_| {
+        count += 1;
+        Ok(())
+    })?;
+
+    ensure!(count > 0, "No RPU found");
+
+    Ok(count)
+}
+
+/// Scans the frame count, profiles, CM versions and EL types of a RPU or HEVC file.
+///
+/// Only the RPU headers are parsed, which is enough for the profile.
+/// The CM version and EL type require the full RPU, so only the first RPU
+/// of every run of frames with the same profile is fully parsed.
+pub fn scan_profiles<P: AsRef<Path>>(input: P) -> Result<RpuScan> {
+    let mut scan = RpuScan::default();
+    let mut previous_profile = None;
+
+    for_each_rpu_nalu(input.as_ref(), |data| {
+        let index = scan.frame_count;
+        scan.frame_count += 1;
+
+        let bytes =
+            clear_start_code_emulation_prevention_3_byte(DoviRpu::validated_trimmed_data(data)?);
+
+        let mut reader = BsIoSliceReader::from_slice(&bytes);
+        ensure!(
+            reader.get_n::<u8>(8)? == 25,
+            "Invalid RPU prefix at frame {index}"
+        );
+
+        let header = RpuDataHeader::parse(&mut reader)
+            .map_err(|e| anyhow!("Invalid RPU header at frame {index}: {e}"))?;
+        let profile = header.get_dovi_profile();
+
+        match scan.profiles.iter_mut().find(|(p, _)| *p == profile) {
+            Some((_, count)) => *count += 1,
+            None => scan.profiles.push((profile, 1)),
+        }
+
+        if previous_profile != Some(profile) {
+            previous_profile = Some(profile);
+
+            let rpu =
+                DoviRpu::parse(&bytes).map_err(|e| anyhow!("Invalid RPU at frame {index}: {e}"))?;
+
+            if let Some(vdr_dm_data) = &rpu.vdr_dm_data {
+                let cm_version = if vdr_dm_data.cmv40_metadata.is_some() {
+                    CmVersion::V40
+                } else {
+                    CmVersion::V29
+                };
+
+                if !scan.cm_versions.contains(&cm_version) {
+                    scan.cm_versions.push(cm_version);
+                }
+            }
+
+            if let Some(el_type) = rpu.el_type {
+                if !scan.el_types.contains(&el_type) {
+                    scan.el_types.push(el_type);
+                }
+            }
+        }
+
+        Ok(())
+    })?;
+
+    ensure!(scan.frame_count > 0, "No RPU found");
+
+    Ok(scan)
+}
+
+/// Calls `f` with every RPU NALU of a RPU or HEVC file, without the start code.
+///
+/// Only the RPU NALUs are buffered, the other HEVC NALUs are skipped as they are read.
+fn for_each_rpu_nalu<F: FnMut(&[u8]) -> Result<()>>(input: &Path, mut f: F) -> Result<()> {
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut buf = vec![0; SCAN_CHUNK_SIZE];
+
+    let mut zeroes = 0;
+    let mut nalu_start = false;
+    let mut nalu: Option<Vec<u8>> = None;
+
+    let mut finish_nalu = |data: Vec<u8>| {
+        // Trailing zeroes, or the first byte of a 4 bytes start code
+        let end = data.len() - data.iter().rev().take_while(|b| **b == 0).count();
+        let data = &data[..end];
+
+        // Other NALU types that happen to start like a RPU
+        if DoviRpu::validated_trimmed_data(data).is_ok() {
+            f(data)
+        } else {
+            Ok(())
+        }
+    };
+
+    loop {
+        let read_bytes = reader.read(&mut buf)?;
+        if read_bytes == 0 {
+            break;
+        }
+
+        for &byte in &buf[..read_bytes] {
+            if nalu_start {
+                nalu_start = false;
+
+                // UNSPEC62 NAL header, or RPU without it
+                if byte == 0x7C || byte == 25 {
+                    nalu = Some(vec![byte]);
+                }
+            } else if byte == 1 && zeroes >= 2 {
+                if let Some(data) = nalu.take() {
+                    finish_nalu(data)?;
+                }
+
+                nalu_start = true;
+            } else if let Some(data) = nalu.as_mut() {
+                data.push(byte);
+            }
+
+            zeroes = if byte == 0 { zeroes + 1 } else { 0 };
+        }
+    }
+
+    if let Some(data) = nalu.take() {
+        finish_nalu(data)?;
+    }
+
+    Ok(())
+}
+
+/// Consecutive frames with identical DM metadata blocks share them in memory
+fn share_previous_metadata(rpu: &mut DoviRpu, previous: Option<&DoviRpu>) {
+    let previous_dm_data = previous.and_then(|previous| previous.vdr_dm_data.as_ref());
+
+    if let (Some(vdr_dm_data), Some(previous_dm_data)) =
+        (rpu.vdr_dm_data.as_mut(), previous_dm_data)
+    {
+        vdr_dm_data.share_metadata_blocks(previous_dm_data);
+    }
+}
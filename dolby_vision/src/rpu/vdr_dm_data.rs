@@ -1,4 +1,5 @@
 use anyhow::{bail, ensure, Result};
+#[cfg(feature = "bitstream")]
 use bitvec_helpers::{
     bitstream_io_reader::BsIoSliceReader, bitstream_io_writer::BitstreamIoWriter,
 };
@@ -17,9 +18,13 @@ use super::profiles::profile84::Profile84;
 use super::profiles::DoviProfile;
 
 use super::extension_metadata::WithExtMetadataBlocks;
+#[cfg(feature = "bitstream")]
 use super::rpu_data_header::RpuDataHeader;
 
-#[derive(Debug, Default, Clone)]
+#[cfg(feature = "bitstream")]
+use super::{compat::CompatParser, extension_metadata::blocks::CustomBlockHandlers};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct VdrDmData {
     pub compressed: bool,
@@ -74,10 +79,13 @@ pub enum CmVersion {
     V40,
 }
 
+#[cfg(feature = "bitstream")]
 pub(crate) fn vdr_dm_data_payload(
     reader: &mut BsIoSliceReader,
     header: &RpuDataHeader,
     final_length: u64,
+    mut compat: Option<&mut CompatParser>,
+    handlers: &CustomBlockHandlers,
 ) -> Result<VdrDmData> {
     let compressed_dm_data = header.reserved_zero_3bits == 1;
 
@@ -94,13 +102,15 @@ pub(crate) fn vdr_dm_data_payload(
         VdrDmData::parse(reader)?
     };
 
-    if let Some(cmv29_dm_data) = DmData::parse::<CmV29DmData>(reader)? {
+    if let Some(cmv29_dm_data) =
+        DmData::parse::<CmV29DmData>(reader, compat.as_deref_mut(), handlers)?
+    {
         vdr_dm_data.cmv29_metadata = Some(DmData::V29(cmv29_dm_data));
     }
 
     // 16 bits min for required level 254
     if reader.available()? >= final_length + 16 {
-        if let Some(cmv40_dm_data) = DmData::parse::<CmV40DmData>(reader)? {
+        if let Some(cmv40_dm_data) = DmData::parse::<CmV40DmData>(reader, compat, handlers)? {
             vdr_dm_data.cmv40_metadata = Some(DmData::V40(cmv40_dm_data));
         }
     }
@@ -109,6 +119,7 @@ pub(crate) fn vdr_dm_data_payload(
 }
 
 impl VdrDmData {
+    #[cfg(feature = "bitstream")]
     pub(crate) fn parse(reader: &mut BsIoSliceReader) -> Result<VdrDmData> {
         let data = VdrDmData {
             affected_dm_metadata_id: reader.get_ue()?,
@@ -201,6 +212,7 @@ impl VdrDmData {
         ext_block_write_length
     }
 
+    #[cfg(feature = "bitstream")]
     pub fn write(&self, writer: &mut BitstreamIoWriter) -> Result<()> {
         writer.write_n(&self.affected_dm_metadata_id, 4)?;
         writer.write_n(&self.current_dm_metadata_id, 4)?;
@@ -479,6 +491,40 @@ impl VdrDmData {
         self.scene_refresh_flag = is_scene_cut as u64;
     }
 
+    /// Shares the metadata blocks of `previous` when they are identical,
+    /// so that consecutive frames of a shot use a single allocation.
+    ///
+    /// The blocks are copied again when modified.
+    #[cfg(feature = "bitstream")]
+    pub(crate) fn share_metadata_blocks(&mut self, previous: &VdrDmData) {
+        let dm_data_pairs = [
+            (&mut self.cmv29_metadata, &previous.cmv29_metadata),
+            (&mut self.cmv40_metadata, &previous.cmv40_metadata),
+        ];
+
+        for (dm_data, previous_dm_data) in dm_data_pairs {
+            if let (Some(dm_data), Some(previous_dm_data)) = (dm_data, previous_dm_data) {
+                dm_data.share_blocks_with(previous_dm_data);
+            }
+        }
+    }
+
+    /// Compares the DM metadata, ignoring the metadata IDs and scene refresh flag.
+    pub fn metadata_eq(&self, other: &VdrDmData) -> bool {
+        let without_signalling = |dm: &VdrDmData| VdrDmData {
+            affected_dm_metadata_id: 0,
+            current_dm_metadata_id: 0,
+            scene_refresh_flag: 0,
+            cmv29_metadata: None,
+            cmv40_metadata: None,
+            ..*dm
+        };
+
+        without_signalling(self) == without_signalling(other)
+            && self.cmv29_metadata == other.cmv29_metadata
+            && self.cmv40_metadata == other.cmv40_metadata
+    }
+
     pub fn default_pq() -> VdrDmData {
         VdrDmData {
             signal_eotf: 65535,
@@ -525,13 +571,18 @@ impl VdrDmData {
             self.replace_metadata_block(ExtMetadataBlock::Level6(level6.clone()))?;
         }
 
-        // Default to inserting both L9 (required) and L11 metadata
-        self.replace_metadata_block(ExtMetadataBlock::Level9(
-            ExtMetadataBlockLevel9::default_dci_p3(),
-        ))?;
-        self.replace_metadata_block(ExtMetadataBlock::Level11(
-            ExtMetadataBlockLevel11::default_reference_cinema(),
-        ))?;
+        // L9 and L11 metadata are inserted by default, unless disabled in `auto_blocks`
+        if config.auto_blocks.level9 {
+            self.replace_metadata_block(ExtMetadataBlock::Level9(
+                ExtMetadataBlockLevel9::default_dci_p3(),
+            ))?;
+        }
+
+        if config.auto_blocks.level11 {
+            self.replace_metadata_block(ExtMetadataBlock::Level11(
+                ExtMetadataBlockLevel11::default_reference_cinema(),
+            ))?;
+        }
 
         if !config.default_metadata_blocks.is_empty() {
             const LEVEL_BLOCK_LIST: &[u8] = &[5, 6];
@@ -550,6 +601,139 @@ impl VdrDmData {
     }
 }
 
+/// Finds the runs of consecutive frames with identical DM metadata.
+///
+/// Returns the `(start, length)` of every run spanning at least `min_length` frames.
+/// Frames without DM metadata break runs.
+pub fn identical_metadata_runs<'a, I>(dm_data: I, min_length: usize) -> Vec<(usize, usize)>
+where
+    I: IntoIterator<Item = Option<&'a VdrDmData>>,
+{
+    let mut runs = Vec::new();
+    let mut current: Option<(usize, usize, &VdrDmData)> = None;
+
+    for (frame, dm) in dm_data.into_iter().enumerate() {
+        if let (Some((_, length, prev)), Some(dm)) = (&mut current, dm) {
+            if prev.metadata_eq(dm) {
+                *length += 1;
+                continue;
+            }
+        }
+
+        if let Some((start, length, _)) = current.take() {
+            runs.push((start, length));
+        }
+
+        current = dm.map(|dm| (frame, 1, dm));
+    }
+
+    if let Some((start, length, _)) = current {
+        runs.push((start, length));
+    }
+
+    runs.retain(|(_, length)| *length >= min_length.max(1));
+
+    runs
+}
+
+/// Share of the frame to frame transitions changing the metadata,
+/// above which refreshing the metadata on every frame is suggested.
+pub const LONG_PLAY_MODE_MIN_CHURN: f64 = 0.5;
+
+/// Scene cut density and metadata churn, to check the long play mode signaling.
+///
+/// Long play mode sets the scene cut flag on every frame. Some devices use it to decide
+/// whether to cache the metadata, so it should only be used when the metadata changes on most frames.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LongPlayModeCheck {
+    pub frames: usize,
+    pub scene_cuts: usize,
+
+    /// Frames with metadata different from the previous frame
+    pub metadata_changes: usize,
+    /// Metadata changes on frames without the scene cut flag
+    pub unflagged_changes: usize,
+}
+
+impl LongPlayModeCheck {
+    /// Frames without DM metadata are counted as changes
+    pub fn new<'a, I>(dm_data: I) -> Self
+    where
+        I: IntoIterator<Item = Option<&'a VdrDmData>>,
+    {
+        let mut check = Self::default();
+        let mut previous: Option<&VdrDmData> = None;
+
+        for dm in dm_data {
+            let scene_cut = dm.map_or(false, |dm| dm.scene_refresh_flag == 1);
+
+            if check.frames > 0 {
+                let unchanged =
+                    matches!((previous, dm), (Some(prev), Some(dm)) if prev.metadata_eq(dm));
+
+                if !unchanged {
+                    check.metadata_changes += 1;
+
+                    if !scene_cut {
+                        check.unflagged_changes += 1;
+                    }
+                }
+            }
+
+            if scene_cut {
+                check.scene_cuts += 1;
+            }
+
+            previous = dm;
+            check.frames += 1;
+        }
+
+        check
+    }
+
+    /// Every frame is a scene cut
+    pub fn long_play_mode(&self) -> bool {
+        self.frames > 1 && self.scene_cuts == self.frames
+    }
+
+    /// Share of the frame to frame transitions changing the metadata
+    pub fn churn(&self) -> f64 {
+        if self.frames > 1 {
+            self.metadata_changes as f64 / (self.frames - 1) as f64
+        } else {
+            0.0
+        }
+    }
+
+    pub fn suggested_long_play_mode(&self) -> bool {
+        self.churn() >= LONG_PLAY_MODE_MIN_CHURN
+    }
+
+    /// Warning when the signaling doesn't match the metadata, with the suggested value
+    pub fn warning(&self) -> Option<String> {
+        if self.frames < 2 {
+            return None;
+        }
+
+        let churn = self.churn() * 100.0;
+        let cuts = self.scene_cuts as f64 * 100.0 / self.frames as f64;
+
+        match (self.long_play_mode(), self.suggested_long_play_mode()) {
+            (true, false) => Some(format!(
+                "long play mode is signaled (every frame is a scene cut), but the metadata only changes on {churn:.1}% of the frames. \
+                 Suggested: long_play_mode false, scene cuts on the shot boundaries"
+            )),
+            // Changes without scene cut can be missed by devices caching the metadata per shot
+            (false, true) if self.unflagged_changes > 0 => Some(format!(
+                "the metadata changes on {churn:.1}% of the frames, but only {cuts:.1}% of the frames are scene cuts \
+                 ({} changes within shots). Suggested: long_play_mode true",
+                self.unflagged_changes
+            )),
+            _ => None,
+        }
+    }
+}
+
 impl CmVersion {
     pub fn v29() -> Self {
         CmVersion::V29
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use bitvec_helpers::bitstream_io_reader::BsIoSliceReader;
 
+use crate::rpu::extension_metadata::blocks::CustomBlockHandlers;
 use crate::rpu::extension_metadata::{CmV29DmData, DmData};
 
 use super::UserDataTypeStruct;
@@ -23,7 +24,8 @@ impl ST2094_10DmData {
         };
 
         if meta.metadata_refresh_flag {
-            meta.dm_data = DmData::parse::<CmV29DmData>(reader)?;
+            meta.dm_data =
+                DmData::parse::<CmV29DmData>(reader, None, &CustomBlockHandlers::default())?;
         }
 
         Ok(UserDataTypeStruct::DMData(meta))
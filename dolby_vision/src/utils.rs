@@ -33,6 +33,69 @@ pub fn nits_to_pq(nits: f64) -> f64 {
         .powf(ST2084_M2)
 }
 
+/// Converts a 12-bit PQ code value to nits
+#[inline(always)]
+pub fn pq12_to_nits(pq: u16) -> f64 {
+    pq_to_nits(pq as f64 / 4095.0)
+}
+
+/// Converts nits to the closest 12-bit PQ code value
+#[inline(always)]
+pub fn nits_to_pq12(nits: f64) -> u16 {
+    (nits_to_pq(nits) * 4095.0).round().clamp(0.0, 4095.0) as u16
+}
+
+/// Converts a 12-bit trim value centered at 2048 to a -1.0..1.0 float
+#[inline(always)]
+pub fn trim12_to_normalized(value: u16) -> f64 {
+    (value as f64 - 2048.0) / 2048.0
+}
+
+/// Converts a -1.0..1.0 float to the closest 12-bit trim value centered at 2048
+#[inline(always)]
+pub fn normalized_to_trim12(value: f64) -> u16 {
+    ((value * 2048.0) + 2048.0).round().clamp(0.0, 4095.0) as u16
+}
+
+/// Converts a non drop-frame `HH:MM:SS:FF` timecode to a frame count.
+///
+/// The frames field uses the nominal frame rate, e.g. 24 for 23.976 fps.
+pub fn timecode_to_frames(timecode: &str, fps: f64) -> anyhow::Result<usize> {
+    let nominal_fps = fps.round() as usize;
+    anyhow::ensure!(nominal_fps > 0, "Invalid frame rate {fps}");
+
+    let parts = timecode
+        .split(':')
+        .map(|part| part.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+        .filter(|parts| parts.len() == 4)
+        .ok_or_else(|| anyhow::anyhow!("Invalid timecode '{timecode}', expected HH:MM:SS:FF"))?;
+
+    let (hours, minutes, seconds, frames) = (parts[0], parts[1], parts[2], parts[3]);
+    anyhow::ensure!(
+        minutes < 60 && seconds < 60 && frames < nominal_fps,
+        "Invalid timecode '{timecode}' for {fps} fps"
+    );
+
+    Ok((hours * 3600 + minutes * 60 + seconds) * nominal_fps + frames)
+}
+
+/// Converts a frame count to a non drop-frame `HH:MM:SS:FF` timecode
+pub fn frames_to_timecode(frames: usize, fps: f64) -> String {
+    let nominal_fps = (fps.round() as usize).max(1);
+
+    let total_seconds = frames / nominal_fps;
+
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        total_seconds / 3600,
+        (total_seconds / 60) % 60,
+        total_seconds % 60,
+        frames % nominal_fps
+    )
+}
+
 /// Copied from hevc_parser for convenience, and to avoid a dependency
 /// Unescapes a byte slice from annexb.
 /// Allocates a new Vec.
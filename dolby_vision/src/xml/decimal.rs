@@ -0,0 +1,140 @@
+//! Decimal values of the CM XML trims, and their rounding to the Dolby fixed-point values.
+//!
+//! Values are parsed as `f64`, in plain or exponent notation (`0.0015`, `1.5e-3`, `1.5E-03`).
+//! A comma decimal separator (`0,5`) is accepted when the values are whitespace separated,
+//! as written by some tools following the system locale.
+//!
+//! A value is converted with `round(value * scale + offset)`, rounding half away from zero,
+//! then clamped to the range of the field. The upper bound of the range, `1.0` for the trims,
+//! is one code past the max and becomes the max.
+//!
+//! The Dolby tools write 6 significant digits, which is already finer than the 12-bit step.
+//! Values written with more significant digits are reported as losing precision,
+//! for example `0.508078` is L1 2081, and so is `0.50807812345` whose last digits are dropped.
+//! Values out of the range of the field are reported as clamped.
+
+use anyhow::{bail, Result};
+
+/// Significant digits written by the Dolby tools
+const MAX_SIGNIFICANT_DIGITS: usize = 6;
+
+/// Decimal value, with the number of significant digits it was written with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct XmlDecimal {
+    pub value: f64,
+    pub significant_digits: usize,
+}
+
+/// Fixed-point format of a trim field
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct FixedPoint {
+    pub scale: f64,
+    pub offset: f64,
+    pub max: u16,
+}
+
+/// 12-bit PQ, `[0, 1]` to `[0, 4095]`
+pub(crate) const FIXED_PQ: FixedPoint = FixedPoint {
+    scale: 4095.0,
+    offset: 0.0,
+    max: 4095,
+};
+
+/// 12-bit trims and offsets, `[-1, 1]` to `[0, 4095]`
+pub(crate) const FIXED_TRIM: FixedPoint = FixedPoint {
+    scale: 2048.0,
+    offset: 2048.0,
+    max: 4095,
+};
+
+/// 8-bit L8 saturation and hue vectors, `[-1, 1]` to `[0, 255]`
+pub(crate) const FIXED_VECTOR: FixedPoint = FixedPoint {
+    scale: 128.0,
+    offset: 128.0,
+    max: 255,
+};
+
+/// Rounding of a value to fixed-point that didn't keep the written value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PrecisionLoss {
+    /// More significant digits than the Dolby tools write
+    Rounded,
+    /// Out of the range of the field
+    Clamped,
+}
+
+impl XmlDecimal {
+    /// Parses a single value, surrounding whitespace ignored.
+    ///
+    /// With `comma_decimal`, a comma is read as the decimal separator.
+    pub fn parse(text: &str, comma_decimal: bool) -> Result<Self> {
+        let text = text.trim();
+
+        let normalized;
+        let text = if comma_decimal && text.contains(',') {
+            normalized = text.replacen(',', ".", 1);
+            normalized.as_str()
+        } else {
+            text
+        };
+
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(pos) => (&text[..pos], Some(&text[pos + 1..])),
+            None => (text, None),
+        };
+
+        let digits = mantissa.strip_prefix(['+', '-']).unwrap_or(mantissa);
+        let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+
+        // Excludes `inf`, `NaN` and the other notations accepted by `f64::from_str`
+        let valid_mantissa = !(int_part.is_empty() && frac_part.is_empty())
+            && int_part
+                .bytes()
+                .chain(frac_part.bytes())
+                .all(|b| b.is_ascii_digit());
+
+        let valid_exponent = exponent.map_or(true, |exp| exp.parse::<i32>().is_ok());
+
+        if !valid_mantissa || !valid_exponent {
+            bail!("Invalid decimal value '{text}'");
+        }
+
+        let value = match text.parse::<f64>() {
+            Ok(value) if value.is_finite() => value,
+            _ => bail!("Invalid decimal value '{text}'"),
+        };
+
+        let digits = format!("{int_part}{frac_part}");
+        let significant_digits = digits.trim_start_matches('0').trim_end_matches('0').len();
+
+        Ok(Self {
+            value,
+            significant_digits,
+        })
+    }
+}
+
+impl FixedPoint {
+    /// Fixed-point value, and how precision was lost if the written value isn't kept
+    pub fn encode(&self, decimal: XmlDecimal) -> (u16, Option<PrecisionLoss>) {
+        let scaled = (decimal.value * self.scale + self.offset).round();
+
+        if scaled < 0.0 || scaled > self.max as f64 + 1.0 {
+            let code = scaled.clamp(0.0, self.max as f64) as u16;
+            return (code, Some(PrecisionLoss::Clamped));
+        }
+
+        let code = (scaled as u16).min(self.max);
+        let loss = if decimal.significant_digits > MAX_SIGNIFICANT_DIGITS {
+            Some(PrecisionLoss::Rounded)
+        } else {
+            None
+        };
+
+        (code, loss)
+    }
+
+    pub fn decode(&self, code: u16) -> f64 {
+        (code as f64 - self.offset) / self.scale
+    }
+}
@@ -1,7 +1,9 @@
 /// XML metadata parser
+mod decimal;
 mod parser;
+mod stream;
 
 #[cfg(test)]
 mod tests;
 
-pub use parser::{CmXmlParser, XmlParserOpts};
+pub use parser::{CmXmlParser, Level5Rounding, XmlParserOpts};
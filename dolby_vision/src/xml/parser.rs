@@ -1,5 +1,6 @@
 use anyhow::{bail, ensure, Result};
 use roxmltree::{Document, Node};
+use std::cell::RefCell;
 use std::cmp::min;
 use std::collections::HashMap;
 use std::convert::TryInto;
@@ -12,6 +13,9 @@ use crate::rpu::generate::{GenerateConfig, ShotFrameEdit, VideoShot};
 use crate::rpu::vdr_dm_data::CmVersion;
 use crate::utils::nits_to_pq;
 
+use super::decimal::{FixedPoint, PrecisionLoss, XmlDecimal, FIXED_PQ, FIXED_TRIM, FIXED_VECTOR};
+use super::stream::{split_shot_elements, XmlChunk};
+
 use level10::PRESET_TARGET_DISPLAYS;
 use primaries::ColorPrimaries;
 
@@ -24,12 +28,42 @@ pub struct CmXmlParser {
     pub target_displays: HashMap<String, TargetDisplay>,
 
     pub config: GenerateConfig,
+
+    /// Trim values that lost precision when rounded to fixed-point
+    warnings: RefCell<Vec<String>>,
 }
 
+/// Options of the XML parser.
+///
+/// Create with `XmlParserOpts::default()` and set the fields, more options can be added.
 #[derive(Default, Debug)]
+#[non_exhaustive]
 pub struct XmlParserOpts {
     pub canvas_width: Option<u16>,
     pub canvas_height: Option<u16>,
+
+    /// How the active image size is rounded when calculating L5 offsets
+    pub l5_rounding: Level5Rounding,
+
+    /// Clamp L5 offsets to the canvas instead of failing when they are negative or out of bounds
+    pub fix_negative_offsets: bool,
+}
+
+/// Rounding policy for the active image size of L5 metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level5Rounding {
+    /// Round down
+    Floor,
+    /// Round to the nearest pixel, the offsets can differ by one pixel
+    Round,
+    /// Round to the nearest even size, so that the offsets are symmetric
+    Even,
+}
+
+impl Default for Level5Rounding {
+    fn default() -> Self {
+        Self::Round
+    }
 }
 
 #[derive(Default, Debug)]
@@ -49,14 +83,70 @@ impl CmXmlParser {
     }
 
     pub fn new(s: String, opts: XmlParserOpts) -> Result<CmXmlParser> {
+        let doc = roxmltree::Document::parse(&s).unwrap();
+
+        Self::parse_document(&doc, opts, true)
+    }
+
+    /// Streaming variant of `parse_file`, for very large XML files.
+    ///
+    /// The shots are never all held in memory: every `Shot` element is parsed on its own
+    /// and passed to `on_shot`, in document order, along with the global config.
+    /// The file is read twice, first for the global metadata and target displays.
+    ///
+    /// The returned config has no shots, its `length` is the total duration of the shots.
+    pub fn parse_file_streaming<P, F>(
+        file_path: P,
+        opts: XmlParserOpts,
+        mut on_shot: F,
+    ) -> Result<CmXmlParser>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&GenerateConfig, VideoShot) -> Result<()>,
+    {
+        let file_path = file_path.as_ref();
+
+        let mut skeleton = Vec::new();
+        split_shot_elements(File::open(file_path)?, |chunk| {
+            if let XmlChunk::Other(data) = chunk {
+                skeleton.extend_from_slice(data);
+            }
+
+            Ok(())
+        })?;
+
+        let skeleton = String::from_utf8(skeleton)?;
+        let doc = Document::parse(&skeleton)?;
+
+        let mut parser = Self::parse_document(&doc, opts, false)?;
+
+        split_shot_elements(File::open(file_path)?, |chunk| {
+            if let XmlChunk::Shot(data) = chunk {
+                let shot_doc = Document::parse(std::str::from_utf8(data)?)?;
+                let shot = parser.parse_shot(&shot_doc.root_element())?;
+
+                parser.config.length += shot.duration;
+
+                on_shot(&parser.config, shot)?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(parser)
+    }
+
+    fn parse_document(
+        doc: &Document,
+        opts: XmlParserOpts,
+        with_shots: bool,
+    ) -> Result<CmXmlParser> {
         let mut parser = CmXmlParser {
             opts,
             ..Default::default()
         };
 
-        let doc = roxmltree::Document::parse(&s).unwrap();
-
-        parser.xml_version = parser.parse_xml_version(&doc)?;
+        parser.xml_version = parser.parse_xml_version(doc)?;
 
         parser.separator = if parser.is_cmv4() { ' ' } else { ',' };
 
@@ -85,9 +175,12 @@ impl CmXmlParser {
                 parser.add_level11(&video)?;
 
                 parser.target_displays = parser.parse_target_displays(&video)?;
+                parser.config.fps = parser.parse_edit_rate(&video);
 
-                parser.config.shots = parser.parse_shots(&video)?;
-                parser.config.shots.sort_by_key(|s| s.start);
+                if with_shots {
+                    parser.config.shots = parser.parse_shots(&video)?;
+                    parser.config.shots.sort_by_key(|s| s.start);
+                }
 
                 // Add default L10 blocks
                 if parser.is_cmv4() {
@@ -155,6 +248,20 @@ impl CmXmlParser {
         }
     }
 
+    /// Frame rate from the track `EditRate`, as a `numerator denominator` pair
+    fn parse_edit_rate(&self, video: &Node) -> Option<f64> {
+        let edit_rate = video
+            .descendants()
+            .find(|e| e.has_tag_name("EditRate"))?
+            .text()?;
+
+        let mut parts = edit_rate.split_whitespace().map(|v| v.parse::<f64>().ok());
+        let numerator = parts.next()??;
+        let denominator = parts.next().unwrap_or(Some(1.0))?;
+
+        (denominator > 0.0).then(|| numerator / denominator)
+    }
+
     fn parse_level6(&self, video: &Node) -> (u16, u16) {
         if let Some(node) = video.descendants().find(|e| e.has_tag_name("Level6")) {
             let maxfall = if let Some(fall) = node.children().find(|e| e.has_tag_name("MaxFALL")) {
@@ -372,65 +479,65 @@ impl CmXmlParser {
     }
 
     fn parse_shots(&self, video: &Node) -> Result<Vec<VideoShot>> {
-        let shots = video
+        video
             .descendants()
             .filter(|e| e.has_tag_name("Shot"))
-            .map(|n| {
-                let mut shot = VideoShot {
-                    id: n
-                        .children()
-                        .find(|e| e.has_tag_name("UniqueID"))
-                        .unwrap()
-                        .text()
-                        .unwrap()
-                        .to_string(),
-                    ..Default::default()
-                };
+            .map(|n| self.parse_shot(&n))
+            .collect()
+    }
 
-                if let Some(record) = n.children().find(|e| e.has_tag_name("Record")) {
-                    shot.start = record
-                        .children()
-                        .find(|e| e.has_tag_name("In"))
-                        .unwrap()
-                        .text()
-                        .unwrap()
-                        .parse::<usize>()
-                        .unwrap();
-                    shot.duration = record
-                        .children()
-                        .find(|e| e.has_tag_name("Duration"))
-                        .unwrap()
-                        .text()
-                        .unwrap()
-                        .parse::<usize>()
-                        .unwrap();
-                }
+    fn parse_shot(&self, n: &Node) -> Result<VideoShot> {
+        let mut shot = VideoShot {
+            id: n
+                .children()
+                .find(|e| e.has_tag_name("UniqueID"))
+                .unwrap()
+                .text()
+                .unwrap()
+                .to_string(),
+            ..Default::default()
+        };
 
-                shot.metadata_blocks = self.parse_shot_trims(&n)?;
+        if let Some(record) = n.children().find(|e| e.has_tag_name("Record")) {
+            shot.start = record
+                .children()
+                .find(|e| e.has_tag_name("In"))
+                .unwrap()
+                .text()
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
+            shot.duration = record
+                .children()
+                .find(|e| e.has_tag_name("Duration"))
+                .unwrap()
+                .text()
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
+        }
 
-                let frames = n.children().filter(|e| e.has_tag_name("Frame"));
+        shot.metadata_blocks = self.parse_shot_trims(n)?;
 
-                for frame in frames {
-                    let edit_offset = frame
-                        .children()
-                        .find(|e| e.has_tag_name("EditOffset"))
-                        .unwrap()
-                        .text()
-                        .unwrap()
-                        .parse::<usize>()
-                        .unwrap();
+        let frames = n.children().filter(|e| e.has_tag_name("Frame"));
 
-                    shot.frame_edits.push(ShotFrameEdit {
-                        edit_offset,
-                        metadata_blocks: self.parse_shot_trims(&frame)?,
-                    });
-                }
+        for frame in frames {
+            let edit_offset = frame
+                .children()
+                .find(|e| e.has_tag_name("EditOffset"))
+                .unwrap()
+                .text()
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
 
-                Ok(shot)
-            })
-            .collect();
+            shot.frame_edits.push(ShotFrameEdit {
+                edit_offset,
+                metadata_blocks: self.parse_shot_trims(&frame)?,
+            });
+        }
 
-        shots
+        Ok(shot)
     }
 
     fn parse_shot_trims(&self, node: &Node) -> Result<Vec<ExtMetadataBlock>> {
@@ -564,16 +671,16 @@ impl CmXmlParser {
             .unwrap()
             .text()
             .unwrap();
-        let measurements: Vec<&str> = measurements.split(self.separator).collect();
+        let measurements = self.parse_decimals(measurements)?;
 
         ensure!(
             measurements.len() == 3,
             "invalid L1 trim: should be 3 values"
         );
 
-        let min_pq = (measurements[0].parse::<f32>().unwrap() * 4095.0).round() as u16;
-        let avg_pq = (measurements[1].parse::<f32>().unwrap() * 4095.0).round() as u16;
-        let max_pq = (measurements[2].parse::<f32>().unwrap() * 4095.0).round() as u16;
+        let min_pq = self.fixed_point("L1 ImageCharacter", measurements[0], FIXED_PQ);
+        let avg_pq = self.fixed_point("L1 ImageCharacter", measurements[1], FIXED_PQ);
+        let max_pq = self.fixed_point("L1 ImageCharacter", measurements[2], FIXED_PQ);
 
         Ok(ExtMetadataBlockLevel1::from_stats_cm_version(
             min_pq,
@@ -598,7 +705,7 @@ impl CmXmlParser {
             .unwrap()
             .text()
             .unwrap();
-        let trim: Vec<&str> = trim.split(self.separator).collect();
+        let trim = self.parse_decimals(trim)?;
 
         let target_display = self
             .target_displays
@@ -607,35 +714,12 @@ impl CmXmlParser {
 
         ensure!(trim.len() == 9, "invalid L2 trim: should be 9 values");
 
-        let trim_lift = trim[3].parse::<f32>().unwrap();
-        let trim_gain = trim[4].parse::<f32>().unwrap();
-        let trim_gamma = trim[5].parse::<f32>().unwrap().clamp(-1.0, 1.0);
+        let (trim_slope, trim_offset, trim_power) =
+            lift_gain_gamma_trims(trim[3].value, trim[4].value, trim[5].value);
 
-        let trim_slope = min(
-            4095,
-            ((((trim_gain + 2.0) * (1.0 - trim_lift / 2.0) - 2.0) * 2048.0) + 2048.0).round()
-                as u16,
-        );
-        let trim_offset = min(
-            4095,
-            ((((trim_gain + 2.0) * (trim_lift / 2.0)) * 2048.0) + 2048.0).round() as u16,
-        );
-        let trim_power = min(
-            4095,
-            (((2.0 / (1.0 + trim_gamma / 2.0) - 2.0) * 2048.0) + 2048.0).round() as u16,
-        );
-        let trim_chroma_weight = min(
-            4095,
-            ((trim[6].parse::<f32>().unwrap() * 2048.0) + 2048.0).round() as u16,
-        );
-        let trim_saturation_gain = min(
-            4095,
-            ((trim[7].parse::<f32>().unwrap() * 2048.0) + 2048.0).round() as u16,
-        );
-        let ms_weight = min(
-            4095,
-            ((trim[8].parse::<f32>().unwrap() * 2048.0) + 2048.0).round() as i16,
-        );
+        let trim_chroma_weight = self.fixed_point("L2 Trim", trim[6], FIXED_TRIM);
+        let trim_saturation_gain = self.fixed_point("L2 Trim", trim[7], FIXED_TRIM);
+        let ms_weight = self.fixed_point("L2 Trim", trim[8], FIXED_TRIM) as i16;
 
         Ok(ExtMetadataBlockLevel2 {
             trim_slope,
@@ -657,7 +741,7 @@ impl CmXmlParser {
             .unwrap();
 
         // [min, avg, max]
-        let measurements: Vec<&str> = measurements.split(self.separator).collect();
+        let measurements = self.parse_decimals(measurements)?;
 
         ensure!(
             measurements.len() == 3,
@@ -665,12 +749,9 @@ impl CmXmlParser {
         );
 
         Ok(ExtMetadataBlockLevel3 {
-            min_pq_offset: ((measurements[0].parse::<f32>().unwrap() * 2048.0) + 2048.0).round()
-                as u16,
-            avg_pq_offset: ((measurements[1].parse::<f32>().unwrap() * 2048.0) + 2048.0).round()
-                as u16,
-            max_pq_offset: ((measurements[2].parse::<f32>().unwrap() * 2048.0) + 2048.0).round()
-                as u16,
+            min_pq_offset: self.fixed_point("L3 L1Offset", measurements[0], FIXED_TRIM),
+            avg_pq_offset: self.fixed_point("L3 L1Offset", measurements[1], FIXED_TRIM),
+            max_pq_offset: self.fixed_point("L3 L1Offset", measurements[2], FIXED_TRIM),
         })
     }
 
@@ -681,12 +762,12 @@ impl CmXmlParser {
             .unwrap()
             .text()
             .unwrap();
-        let ratios: Vec<&str> = ratios.split(self.separator).collect();
+        let ratios = self.parse_decimals(ratios)?;
 
         ensure!(ratios.len() == 2, "invalid L5 trim: should be 2 values");
 
-        let canvas_ar = ratios[0].parse::<f32>().unwrap();
-        let image_ar = ratios[1].parse::<f32>().unwrap();
+        let canvas_ar = ratios[0].value as f32;
+        let image_ar = ratios[1].value as f32;
 
         Ok(self
             .calculate_level5_metadata(canvas_ar, image_ar)
@@ -709,7 +790,7 @@ impl CmXmlParser {
             .unwrap()
             .text()
             .unwrap();
-        let trim: Vec<&str> = trim.split(self.separator).collect();
+        let trim = self.parse_decimals(trim)?;
 
         let target_display = self
             .target_displays
@@ -718,35 +799,12 @@ impl CmXmlParser {
 
         ensure!(trim.len() == 6, "Invalid L8 trim: should be 6 values");
 
-        let trim_lift = trim[0].parse::<f32>().unwrap();
-        let trim_gain = trim[1].parse::<f32>().unwrap();
-        let trim_gamma = trim[2].parse::<f32>().unwrap().clamp(-1.0, 1.0);
+        let (trim_slope, trim_offset, trim_power) =
+            lift_gain_gamma_trims(trim[0].value, trim[1].value, trim[2].value);
 
-        let trim_slope = min(
-            4095,
-            ((((trim_gain + 2.0) * (1.0 - trim_lift / 2.0) - 2.0) * 2048.0) + 2048.0).round()
-                as u16,
-        );
-        let trim_offset = min(
-            4095,
-            ((((trim_gain + 2.0) * (trim_lift / 2.0)) * 2048.0) + 2048.0).round() as u16,
-        );
-        let trim_power = min(
-            4095,
-            (((2.0 / (1.0 + trim_gamma / 2.0) - 2.0) * 2048.0) + 2048.0).round() as u16,
-        );
-        let trim_chroma_weight = min(
-            4095,
-            ((trim[3].parse::<f32>().unwrap() * 2048.0) + 2048.0).round() as u16,
-        );
-        let trim_saturation_gain = min(
-            4095,
-            ((trim[4].parse::<f32>().unwrap() * 2048.0) + 2048.0).round() as u16,
-        );
-        let ms_weight = min(
-            4095,
-            ((trim[5].parse::<f32>().unwrap() * 2048.0) + 2048.0).round() as u16,
-        );
+        let trim_chroma_weight = self.fixed_point("L8 L8Trim", trim[3], FIXED_TRIM);
+        let trim_saturation_gain = self.fixed_point("L8 L8Trim", trim[4], FIXED_TRIM);
+        let ms_weight = self.fixed_point("L8 L8Trim", trim[5], FIXED_TRIM);
 
         let mid_contrast_bias_text = node
             .children()
@@ -762,14 +820,16 @@ impl CmXmlParser {
             .text()
             .unwrap();
 
-        let target_mid_contrast = min(
-            4095,
-            ((mid_contrast_bias_text.parse::<f32>().unwrap() * 2048.0) + 2048.0).round() as u16,
+        let target_mid_contrast = self.fixed_point(
+            "L8 MidContrastBias",
+            self.parse_decimal(mid_contrast_bias_text)?,
+            FIXED_TRIM,
         );
 
-        let clip_trim = min(
-            4095,
-            ((highlight_clipping_text.parse::<f32>().unwrap() * 2048.0) + 2048.0).round() as u16,
+        let clip_trim = self.fixed_point(
+            "L8 HighlightClipping",
+            self.parse_decimal(highlight_clipping_text)?,
+            FIXED_TRIM,
         );
 
         // L8 SaturationVectorField
@@ -780,14 +840,10 @@ impl CmXmlParser {
             .text()
             .unwrap();
 
-        let satvec: Vec<u8> = satvec_text
-            .split(self.separator)
-            .map(|v| {
-                min(
-                    255,
-                    ((v.parse::<f32>().unwrap() * 128.0) + 128.0).round() as u8,
-                )
-            })
+        let satvec: Vec<u8> = self
+            .parse_decimals(satvec_text)?
+            .into_iter()
+            .map(|v| self.fixed_point("L8 SaturationVectorField", v, FIXED_VECTOR) as u8)
             .collect();
 
         ensure!(
@@ -803,14 +859,10 @@ impl CmXmlParser {
             .text()
             .unwrap();
 
-        let huevec: Vec<u8> = huevec_text
-            .split(self.separator)
-            .map(|v| {
-                min(
-                    255,
-                    ((v.parse::<f32>().unwrap() * 128.0) + 128.0).round() as u8,
-                )
-            })
+        let huevec: Vec<u8> = self
+            .parse_decimals(huevec_text)?
+            .into_iter()
+            .map(|v| self.fixed_point("L8 HueVectorField", v, FIXED_VECTOR) as u8)
             .collect();
 
         ensure!(
@@ -858,6 +910,51 @@ impl CmXmlParser {
         })
     }
 
+    /// Values of a trim element.
+    ///
+    /// CM v4.0 values are whitespace separated, a comma is then read as a decimal separator.
+    fn parse_decimals(&self, text: &str) -> Result<Vec<XmlDecimal>> {
+        if self.separator == ' ' {
+            text.split_whitespace()
+                .map(|v| XmlDecimal::parse(v, true))
+                .collect()
+        } else {
+            text.split(self.separator)
+                .map(|v| XmlDecimal::parse(v, false))
+                .collect()
+        }
+    }
+
+    fn parse_decimal(&self, text: &str) -> Result<XmlDecimal> {
+        XmlDecimal::parse(text, self.separator == ' ')
+    }
+
+    /// Fixed-point value of a trim, a warning is recorded when the written value isn't kept
+    fn fixed_point(&self, name: &str, decimal: XmlDecimal, format: FixedPoint) -> u16 {
+        let (code, loss) = format.encode(decimal);
+
+        if let Some(loss) = loss {
+            let reason = match loss {
+                PrecisionLoss::Rounded => {
+                    format!("rounded to {code} ({:.6})", format.decode(code))
+                }
+                PrecisionLoss::Clamped => format!("out of range, clamped to {code}"),
+            };
+
+            self.warnings
+                .borrow_mut()
+                .push(format!("{name} value {}: {reason}", decimal.value));
+        }
+
+        code
+    }
+
+    /// Trim values that lost precision when rounded to the fixed-point metadata,
+    /// see the `decimal` module for the rounding
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.borrow().clone()
+    }
+
     fn find_primary_index(primaries: &[f64; 8], check_realdevice: bool) -> Result<u8> {
         // Check PREDEFINED_COLORSPACE_PRIMARIES anyway
         if check_realdevice {
@@ -954,27 +1051,74 @@ impl CmXmlParser {
         if (canvas_ar - image_ar).abs() < f32::EPSILON {
             // No AR difference, zero offsets
         } else if image_ar > canvas_ar {
-            let image_h = (ch * (canvas_ar / image_ar)).round();
-            let diff = ch - image_h;
-            let offset_top = (diff / 2.0).trunc();
-            let offset_bottom = diff - offset_top;
+            let (offset_top, offset_bottom) =
+                self.level5_offsets(ch, ch * (canvas_ar / image_ar), canvas_ar, image_ar)?;
 
-            calculated_level5.active_area_top_offset = offset_top as u16;
-            calculated_level5.active_area_bottom_offset = offset_bottom as u16;
+            calculated_level5.active_area_top_offset = offset_top;
+            calculated_level5.active_area_bottom_offset = offset_bottom;
         } else {
-            let image_w = (cw * (image_ar / canvas_ar)).round();
-            let diff = cw - image_w;
-            let offset_left = (diff / 2.0).trunc();
-            let offset_right = diff - offset_left;
+            let (offset_left, offset_right) =
+                self.level5_offsets(cw, cw * (image_ar / canvas_ar), canvas_ar, image_ar)?;
 
-            calculated_level5.active_area_left_offset = offset_left as u16;
-            calculated_level5.active_area_right_offset = offset_right as u16;
+            calculated_level5.active_area_left_offset = offset_left;
+            calculated_level5.active_area_right_offset = offset_right;
         }
 
         Ok(calculated_level5)
     }
 
+    /// Splits the difference between the canvas and image sizes in two offsets
+    fn level5_offsets(
+        &self,
+        canvas_size: f32,
+        image_size: f32,
+        canvas_ar: f32,
+        image_ar: f32,
+    ) -> Result<(u16, u16)> {
+        let image_size = match self.opts.l5_rounding {
+            Level5Rounding::Floor => image_size.floor(),
+            Level5Rounding::Round => image_size.round(),
+            // Even difference, so that it can be split equally
+            Level5Rounding::Even => canvas_size - ((canvas_size - image_size) / 2.0).round() * 2.0,
+        };
+
+        let mut diff = canvas_size - image_size;
+        let max_diff = canvas_size - 1.0;
+
+        if !(0.0..=max_diff).contains(&diff) {
+            ensure!(
+                self.opts.fix_negative_offsets && diff.is_finite(),
+                "Invalid L5 offsets: canvas AR {} and image AR {} result in {} pixels of offsets, for a canvas size of {}",
+                canvas_ar,
+                image_ar,
+                diff,
+                canvas_size
+            );
+
+            diff = diff.clamp(0.0, max_diff);
+        }
+
+        let first = (diff / 2.0).trunc();
+        let second = diff - first;
+
+        Ok((first as u16, second as u16))
+    }
+
     pub fn is_cmv4(&self) -> bool {
         self.xml_version >= 0x402
     }
 }
+
+/// Slope, offset and power of the L2 and L8 lift, gain and gamma trims.
+///
+/// These are derived values, they aren't checked for precision loss.
+fn lift_gain_gamma_trims(trim_lift: f64, trim_gain: f64, trim_gamma: f64) -> (u16, u16, u16) {
+    let trim_gamma = trim_gamma.clamp(-1.0, 1.0);
+    let to_fixed = |v: f64| ((v * 2048.0) + 2048.0).round().clamp(0.0, 4095.0) as u16;
+
+    let trim_slope = to_fixed((trim_gain + 2.0) * (1.0 - trim_lift / 2.0) - 2.0);
+    let trim_offset = to_fixed((trim_gain + 2.0) * (trim_lift / 2.0));
+    let trim_power = to_fixed(2.0 / (1.0 + trim_gamma / 2.0) - 2.0);
+
+    (trim_slope, trim_offset, trim_power)
+}
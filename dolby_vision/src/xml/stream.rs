@@ -0,0 +1,96 @@
+use std::io::Read;
+
+use anyhow::{ensure, Result};
+
+const SHOT_START: &[u8] = b"<Shot";
+const SHOT_END: &[u8] = b"</Shot>";
+
+const CHUNK_SIZE: usize = 65536;
+
+/// Part of the XML document, as split by `split_shot_elements`
+pub(crate) enum XmlChunk<'a> {
+    /// Complete `Shot` element
+    Shot(&'a [u8]),
+    /// Anything outside of the `Shot` elements
+    Other(&'a [u8]),
+}
+
+/// Reads the document in chunks, splitting out every `Shot` element.
+///
+/// Only one shot is buffered at a time, the other content is passed through as it is read.
+pub(crate) fn split_shot_elements<R, F>(mut reader: R, mut on_chunk: F) -> Result<()>
+where
+    R: Read,
+    F: FnMut(XmlChunk) -> Result<()>,
+{
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let mut buffer = Vec::with_capacity(CHUNK_SIZE * 2);
+    let mut in_shot = false;
+
+    loop {
+        let read_bytes = reader.read(&mut chunk)?;
+        let eof = read_bytes == 0;
+
+        buffer.extend_from_slice(&chunk[..read_bytes]);
+
+        loop {
+            if in_shot {
+                let end = match find(&buffer, SHOT_END) {
+                    Some(end) => end + SHOT_END.len(),
+                    None => break,
+                };
+
+                on_chunk(XmlChunk::Shot(&buffer[..end]))?;
+                buffer.drain(..end);
+
+                in_shot = false;
+            } else if let Some(start) = find_shot_start(&buffer) {
+                on_chunk(XmlChunk::Other(&buffer[..start]))?;
+                buffer.drain(..start);
+
+                in_shot = true;
+            } else {
+                // Keep the end, in case a start tag is split between reads
+                let end = if eof {
+                    buffer.len()
+                } else {
+                    buffer.len().saturating_sub(SHOT_START.len())
+                };
+
+                on_chunk(XmlChunk::Other(&buffer[..end]))?;
+                buffer.drain(..end);
+
+                break;
+            }
+        }
+
+        if eof {
+            ensure!(!in_shot, "Unterminated Shot element");
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn find(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    data.windows(pattern.len()).position(|w| w == pattern)
+}
+
+/// Start of a `Shot` tag, not matching longer tag names
+fn find_shot_start(data: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+
+    while let Some(pos) = find(&data[offset..], SHOT_START) {
+        let start = offset + pos;
+
+        match data.get(start + SHOT_START.len()) {
+            Some(b) if *b == b'>' || b.is_ascii_whitespace() => return Some(start),
+            // Incomplete, the rest of the tag is not read yet
+            None => return None,
+            _ => offset = start + 1,
+        }
+    }
+
+    None
+}
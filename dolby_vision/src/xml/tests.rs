@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use crate::rpu::{extension_metadata::blocks::ExtMetadataBlock, vdr_dm_data::CmVersion};
 
-use super::{CmXmlParser, XmlParserOpts};
+use super::{CmXmlParser, Level5Rounding, XmlParserOpts};
 use anyhow::Result;
 
 fn assert_num_blocks_for_level(blocks: &[ExtMetadataBlock], level: u8, count: usize) {
@@ -19,6 +19,7 @@ fn parse_cmv2_9() -> Result<()> {
     let opts = XmlParserOpts {
         canvas_width: Some(3840),
         canvas_height: Some(2160),
+        ..Default::default()
     };
     let parser = CmXmlParser::parse_file(assets_path.join("assets/tests/cmv2_9.xml"), opts)?;
 
@@ -73,6 +74,7 @@ fn parse_cmv4_0_2() -> Result<()> {
     assert_eq!(config.cm_version, CmVersion::V40);
     assert_eq!(config.length, 259);
     assert_eq!(config.shots.len(), 3);
+    assert_eq!(config.fps, Some(24000.0 / 1001.0));
 
     // L5
     assert_eq!(config.level5.get_offsets(), (0, 0, 0, 0));
@@ -138,6 +140,7 @@ fn parse_cmv4_0_2_with_l5() -> Result<()> {
     let opts = XmlParserOpts {
         canvas_width: Some(3840),
         canvas_height: Some(2160),
+        ..Default::default()
     };
 
     let parser = CmXmlParser::parse_file(assets_path.join("assets/tests/cmv4_0_2.xml"), opts)?;
@@ -294,3 +297,180 @@ fn parse_cmv4_2_xml_510() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn level5_rounding_and_repair() -> Result<()> {
+    let lib_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let assets_path = lib_path.parent().unwrap();
+
+    let xml_path = assets_path.join("assets/tests/cmv2_9.xml");
+
+    let parse_offsets = |xml: String, l5_rounding, fix_negative_offsets| -> Result<_> {
+        let opts = XmlParserOpts {
+            canvas_width: Some(3840),
+            canvas_height: Some(1600),
+            l5_rounding,
+            fix_negative_offsets,
+        };
+
+        Ok(CmXmlParser::new(xml, opts)?.config.level5.get_offsets())
+    };
+
+    let xml = std::fs::read_to_string(xml_path)?;
+
+    // 409 pixels to split
+    assert_eq!(
+        parse_offsets(xml.clone(), Level5Rounding::Round, false)?,
+        (0, 0, 204, 205)
+    );
+    assert_eq!(
+        parse_offsets(xml.clone(), Level5Rounding::Floor, false)?,
+        (0, 0, 204, 205)
+    );
+    assert_eq!(
+        parse_offsets(xml.clone(), Level5Rounding::Even, false)?,
+        (0, 0, 204, 204)
+    );
+
+    // Offsets covering the whole canvas
+    let invalid_xml = xml.replace(
+        "<ImageAspectRatio>2.38806</ImageAspectRatio>",
+        "<ImageAspectRatio>0</ImageAspectRatio>",
+    );
+
+    assert_eq!(
+        parse_offsets(invalid_xml.clone(), Level5Rounding::Round, false)?,
+        (0, 0, 0, 0)
+    );
+    assert_eq!(
+        parse_offsets(invalid_xml, Level5Rounding::Round, true)?,
+        (1919, 1920, 0, 0)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn parse_streaming() -> Result<()> {
+    let lib_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let assets_path = lib_path.parent().unwrap();
+
+    for file in [
+        "assets/tests/cmv2_9.xml",
+        "assets/tests/cmv4_0_2_custom_displays.xml",
+        "assets/tests/cmv4_2_xml_510.xml",
+    ] {
+        let path = assets_path.join(file);
+        let config = CmXmlParser::parse_file(&path, XmlParserOpts::default())?.config;
+
+        let mut shots = Vec::new();
+        let parser =
+            CmXmlParser::parse_file_streaming(&path, XmlParserOpts::default(), |_, shot| {
+                shots.push(shot);
+                Ok(())
+            })?;
+        let streamed_config = parser.config;
+
+        assert!(streamed_config.shots.is_empty());
+        assert_eq!(streamed_config.length, config.length);
+        assert_eq!(
+            streamed_config.default_metadata_blocks,
+            config.default_metadata_blocks
+        );
+        assert_eq!(streamed_config.level6, config.level6);
+
+        assert_eq!(shots.len(), config.shots.len());
+
+        for (streamed, shot) in shots.iter().zip(&config.shots) {
+            assert_eq!(streamed.id, shot.id);
+            assert_eq!(streamed.start, shot.start);
+            assert_eq!(streamed.duration, shot.duration);
+            assert_eq!(streamed.metadata_blocks, shot.metadata_blocks);
+            assert_eq!(streamed.frame_edits.len(), shot.frame_edits.len());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn trim_decimal_notations() -> Result<()> {
+    use super::decimal::{XmlDecimal, FIXED_PQ, FIXED_TRIM};
+
+    let lib_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let assets_path = lib_path.parent().unwrap();
+
+    let xml = std::fs::read_to_string(assets_path.join("assets/tests/cmv4_0_2.xml"))?;
+
+    let parse_l1 = |xml: String| -> Result<_> {
+        let parser = CmXmlParser::new(xml, XmlParserOpts::default())?;
+
+        let level1 = parser.config.shots[1]
+            .metadata_blocks
+            .iter()
+            .find_map(|block| match block {
+                ExtMetadataBlock::Level1(level1) => Some(level1.clone()),
+                _ => None,
+            })
+            .unwrap();
+
+        Ok((level1, parser.warnings()))
+    };
+
+    let (expected, warnings) = parse_l1(xml.clone())?;
+    assert_eq!(expected.max_pq, 2081);
+    assert!(warnings.is_empty());
+
+    // Only the second shot L1 is replaced, the third shot has the same one in a frame edit
+    // Exponent notation, comma decimal separator and extra whitespace
+    let (level1, warnings) = parse_l1(xml.replacen(
+        "<ImageCharacter>0 0.3 0.508078</ImageCharacter>",
+        "<ImageCharacter> 0  3E-1\t0,508078 </ImageCharacter>",
+        1,
+    ))?;
+    assert_eq!(level1, expected);
+    assert!(warnings.is_empty());
+
+    // Extra precision is rounded away
+    let (level1, warnings) = parse_l1(xml.replacen(
+        "<ImageCharacter>0 0.3 0.508078</ImageCharacter>",
+        "<ImageCharacter>0 0.3 0.50807812345</ImageCharacter>",
+        1,
+    ))?;
+    assert_eq!(level1, expected);
+    assert_eq!(
+        warnings,
+        vec!["L1 ImageCharacter value 0.50807812345: rounded to 2081 (0.508181)"]
+    );
+
+    // Out of range
+    let (_, warnings) = parse_l1(xml.replace(
+        "<L1Offset>0 -0.3 -0.508078</L1Offset>",
+        "<L1Offset>0 -0.3 -1.5</L1Offset>",
+    ))?;
+    assert_eq!(
+        warnings,
+        vec!["L3 L1Offset value -1.5: out of range, clamped to 0"]
+    );
+
+    assert!(parse_l1(xml.replacen(
+        "<ImageCharacter>0 0.3 0.508078</ImageCharacter>",
+        "<ImageCharacter>0 0.3 NaN</ImageCharacter>",
+        1,
+    ))
+    .is_err());
+
+    for invalid in ["", "inf", "1e", "0.5.1", "0x10", "1,5"] {
+        assert!(XmlDecimal::parse(invalid, false).is_err(), "{invalid}");
+    }
+
+    assert_eq!(XmlDecimal::parse("1,5", true)?.value, 1.5);
+    assert_eq!(XmlDecimal::parse("+.25e1", false)?.value, 2.5);
+
+    // The upper bound of the range is the max
+    let one = XmlDecimal::parse("1.0", false)?;
+    assert_eq!(FIXED_TRIM.encode(one), (4095, None));
+    assert_eq!(FIXED_PQ.encode(one), (4095, None));
+
+    Ok(())
+}
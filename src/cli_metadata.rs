@@ -0,0 +1,88 @@
+use std::io::{stdout, Write};
+
+use anyhow::Result;
+use clap::{Arg, ArgAction, Command};
+use clap_complete::Shell;
+use serde_json::{json, Value};
+
+/// Writes the shell completions script to stdout
+pub fn print_completions(cmd: &mut Command, shell: Shell) {
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, cmd, name, &mut stdout());
+}
+
+/// Prints a JSON description of all the commands and their arguments,
+/// meant to be consumed by wrapper GUIs.
+pub fn print_cli_json(cmd: &mut Command) -> Result<()> {
+    cmd.build();
+
+    let mut out = stdout().lock();
+    serde_json::to_writer_pretty(&mut out, &command_to_json(cmd))?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn command_to_json(cmd: &Command) -> Value {
+    let args: Vec<Value> = cmd
+        .get_arguments()
+        .filter(|arg| {
+            !arg.is_hide_set() && !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version)
+        })
+        .map(arg_to_json)
+        .collect();
+
+    let subcommands: Vec<Value> = cmd
+        .get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+        .map(command_to_json)
+        .collect();
+
+    json!({
+        "name": cmd.get_name(),
+        "about": cmd.get_about().map(|s| s.to_string()),
+        "version": cmd.get_version(),
+        "args": args,
+        "subcommands": subcommands,
+    })
+}
+
+fn arg_to_json(arg: &Arg) -> Value {
+    let takes_value = !matches!(
+        arg.get_action(),
+        ArgAction::SetTrue | ArgAction::SetFalse | ArgAction::Count
+    );
+
+    let possible_values: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect();
+
+    let default_values: Vec<String> = arg
+        .get_default_values()
+        .iter()
+        .map(|v| v.to_string_lossy().to_string())
+        .collect();
+
+    let value_names: Vec<String> = arg
+        .get_value_names()
+        .map(|names| names.iter().map(|n| n.to_string()).collect())
+        .unwrap_or_default();
+
+    json!({
+        "id": arg.get_id().as_str(),
+        "long": arg.get_long(),
+        "short": arg.get_short().map(String::from),
+        "positional": arg.is_positional(),
+        "help": arg.get_help().map(|s| s.to_string()),
+        "required": arg.is_required_set(),
+        "global": arg.is_global_set(),
+        "takes_value": takes_value,
+        "value_names": value_names,
+        "value_hint": format!("{:?}", arg.get_value_hint()),
+        "possible_values": possible_values,
+        "default_values": default_values,
+    })
+}
@@ -0,0 +1,55 @@
+use clap::{Args, ValueHint};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct BitrateArgs {
+    #[arg(
+        id = "input",
+        help = "Sets the input RPU file to use",
+        long,
+        short = 'i',
+        conflicts_with = "input_pos",
+        required_unless_present = "input_pos",
+        value_hint = ValueHint::FilePath,
+    )]
+    pub input: Option<PathBuf>,
+
+    #[arg(
+        id = "input_pos",
+        help = "Sets the input RPU file to use (positional)",
+        conflicts_with = "input",
+        required_unless_present = "input",
+        value_hint = ValueHint::FilePath
+    )]
+    pub input_pos: Option<PathBuf>,
+
+    #[arg(long, help = "Frame rate of the video", default_value = "23.976")]
+    pub fps: f64,
+
+    #[arg(
+        long,
+        help = "Duration in seconds of the windows the bitrate is computed over",
+        default_value = "1.0"
+    )]
+    pub window: f64,
+
+    #[arg(
+        long,
+        help = "Flags the windows with a bitrate above this threshold, in bytes per second"
+    )]
+    pub threshold: Option<f64>,
+
+    #[arg(
+        long,
+        help = "Exports the bitrate of every window to a CSV file",
+        value_hint = ValueHint::FilePath
+    )]
+    pub csv: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Plots the bitrate over time to a PNG image",
+        value_hint = ValueHint::FilePath
+    )]
+    pub plot: Option<PathBuf>,
+}
@@ -0,0 +1,29 @@
+use clap::{Args, ValueHint};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct CompareInjectedArgs {
+    #[arg(
+        long,
+        short = 'i',
+        help = "Sets the original HEVC file, before injection",
+        value_hint = ValueHint::FilePath
+    )]
+    pub original: PathBuf,
+
+    #[arg(
+        long,
+        short = 'j',
+        help = "Sets the HEVC file with the injected RPU",
+        value_hint = ValueHint::FilePath
+    )]
+    pub injected: PathBuf,
+
+    #[arg(
+        long,
+        short = 'r',
+        help = "Sets the RPU file that was injected",
+        value_hint = ValueHint::FilePath
+    )]
+    pub rpu_in: PathBuf,
+}
@@ -0,0 +1,8 @@
+use clap::Args;
+use clap_complete::Shell;
+
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    #[arg(value_enum, help = "Shell to generate the completions for")]
+    pub shell: Shell,
+}
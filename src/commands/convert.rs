@@ -33,4 +33,11 @@ pub struct ConvertArgs {
 
     #[arg(short = 'd', long, help = "Discard the EL stream")]
     pub discard: bool,
+
+    #[arg(
+        long,
+        value_name = "CADENCE",
+        help = "Omit dynamic metadata blocks repeated from the previous frame. Full metadata is kept on scene cuts, IDR frames and every CADENCE frames"
+    )]
+    pub repeat_metadata: Option<usize>,
 }
@@ -41,4 +41,17 @@ pub struct DemuxArgs {
 
     #[arg(long, help = "Output the EL file only")]
     pub el_only: bool,
+
+    #[arg(
+        long,
+        help = "Resumes an interrupted run from its checkpoint, reading the input from the checkpointed offset"
+    )]
+    pub resume: bool,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Writes checkpoints next to the output at this interval, to resume with --resume. 0 checkpoints every frame"
+    )]
+    pub checkpoint_interval: Option<u64>,
 }
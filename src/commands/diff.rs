@@ -0,0 +1,36 @@
+use clap::{Args, ValueHint};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    #[arg(
+        long,
+        short = 'i',
+        help = "Sets the original RPU file",
+        value_hint = ValueHint::FilePath
+    )]
+    pub original: PathBuf,
+
+    #[arg(
+        long,
+        short = 'm',
+        help = "Sets the modified RPU file",
+        value_hint = ValueHint::FilePath
+    )]
+    pub modified: PathBuf,
+
+    #[arg(
+        long,
+        short = 'o',
+        help = "Patch JSON output file location",
+        value_hint = ValueHint::FilePath
+    )]
+    pub patch_out: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Generate config or CM XML, to show the editorial labels of the shots next to the reported frames",
+        value_hint = ValueHint::FilePath
+    )]
+    pub labels: Option<PathBuf>,
+}
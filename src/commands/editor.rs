@@ -9,7 +9,7 @@ pub struct EditorArgs {
         long,
         short = 'i',
         conflicts_with = "input_pos",
-        required_unless_present = "input_pos",
+        required_unless_present_any = &["input_pos", "batch"],
         value_hint = ValueHint::FilePath,
     )]
     pub input: Option<PathBuf>,
@@ -18,11 +18,21 @@ pub struct EditorArgs {
         id = "input_pos",
         help = "Sets the input RPU file to use (positional)",
         conflicts_with = "input",
-        required_unless_present = "input",
+        required_unless_present_any = &["input", "batch"],
         value_hint = ValueHint::FilePath
     )]
     pub input_pos: Option<PathBuf>,
 
+    #[arg(
+        id = "batch",
+        long,
+        num_args = 1..,
+        help = "Edits several RPU files with the same config. The config and output can use template variables, like ${input_stem}",
+        conflicts_with_all = &["input", "input_pos"],
+        value_hint = ValueHint::FilePath
+    )]
+    pub batch: Vec<PathBuf>,
+
     #[arg(
         id = "json",
         long,
@@ -35,7 +45,7 @@ pub struct EditorArgs {
     #[arg(
         long,
         short = 'o',
-        help = "Modified RPU output file location",
+        help = "Modified RPU output file location. Can use template variables, like ${input_stem}_fixed.bin",
         value_hint = ValueHint::FilePath
     )]
     pub rpu_out: Option<PathBuf>,
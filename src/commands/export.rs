@@ -1,6 +1,8 @@
 use clap::{Args, ValueHint};
 use std::path::PathBuf;
 
+use crate::dovi::{FramePropsFormat, JsonCompression};
+
 #[derive(Args, Debug)]
 pub struct ExportArgs {
     #[arg(
@@ -31,4 +33,94 @@ pub struct ExportArgs {
         value_hint = ValueHint::FilePath
     )]
     pub output: Option<PathBuf>,
+
+    #[arg(
+        id = "shot-stats",
+        long,
+        help = "Also export per-shot L1 statistics (max, 99.99th percentile max, average std-dev) to a JSON file",
+        value_hint = ValueHint::FilePath
+    )]
+    pub shot_stats: Option<PathBuf>,
+
+    #[arg(
+        id = "compress",
+        long,
+        help = "Compress the exported JSON, streaming to a .json.zst or .json.br file",
+        value_enum
+    )]
+    pub compress: Option<JsonCompression>,
+
+    #[arg(
+        long,
+        help = "Use the Dolby CM XML terminology for the metadata block field names, e.g. TrimSlope instead of trim_slope"
+    )]
+    pub xml_names: bool,
+
+    #[arg(
+        id = "frame-props",
+        long,
+        help = "Also export the per-frame L1/L5 metadata and scene cuts as frame props",
+        value_hint = ValueHint::FilePath
+    )]
+    pub frame_props: Option<PathBuf>,
+
+    #[arg(
+        id = "frame-props-format",
+        long,
+        help = "Format of the frame props export",
+        value_enum,
+        default_value = "vapoursynth",
+        requires = "frame-props"
+    )]
+    pub frame_props_format: FramePropsFormat,
+
+    #[arg(
+        id = "annotations",
+        long,
+        help = "JSON file with review annotations to carry into the shot statistics and frame props. Either a list, a generate config with `annotations` and shot `labels`, or a CM XML",
+        value_hint = ValueHint::FilePath
+    )]
+    pub annotations: Option<PathBuf>,
+
+    #[arg(
+        id = "fps",
+        long,
+        help = "Frame rate of the video, adds HH:MM:SS:FF timecodes to the shot statistics",
+        requires = "shot-stats"
+    )]
+    pub fps: Option<f64>,
+
+    #[arg(
+        id = "primaries",
+        long,
+        help = "Also export the L9 source primaries to a JSON file, with the RGB to XYZ and ACES2065-1 matrices",
+        value_hint = ValueHint::FilePath
+    )]
+    pub primaries: Option<PathBuf>,
+
+    #[arg(
+        id = "shot-boundaries",
+        long,
+        help = "Also export a report of the scene starts matched to the IDR frames of the video, flagging the scene cuts not on IDR frames",
+        requires = "video",
+        value_hint = ValueHint::FilePath
+    )]
+    pub shot_boundaries: Option<PathBuf>,
+
+    #[arg(
+        id = "video",
+        long,
+        help = "Raw HEVC video encoded with the RPU, for the shot boundaries report",
+        requires = "shot-boundaries",
+        value_hint = ValueHint::FilePath
+    )]
+    pub video: Option<PathBuf>,
+
+    #[arg(
+        id = "timeline",
+        long,
+        help = "Also export a compact binary timeline of the per-frame L1, scene cuts and L5 offsets, for memory-mapping by players",
+        value_hint = ValueHint::FilePath
+    )]
+    pub timeline: Option<PathBuf>,
 }
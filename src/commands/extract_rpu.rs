@@ -5,7 +5,7 @@ use std::path::PathBuf;
 pub struct ExtractRpuArgs {
     #[arg(
         id = "input",
-        help = "Sets the input HEVC file to use, or piped with -",
+        help = "Sets the input HEVC, fragmented MP4 or Blu-ray MPLS playlist file to use, or piped with -",
         long,
         short = 'i',
         conflicts_with = "input_pos",
@@ -16,7 +16,7 @@ pub struct ExtractRpuArgs {
 
     #[arg(
         id = "input_pos",
-        help = "Sets the input HEVC file to use, or piped with - (positional)",
+        help = "Sets the input HEVC, fragmented MP4 or Blu-ray MPLS playlist file to use, or piped with - (positional)",
         conflicts_with = "input",
         required_unless_present = "input",
         value_hint = ValueHint::FilePath
@@ -30,4 +30,24 @@ pub struct ExtractRpuArgs {
         value_hint = ValueHint::FilePath
     )]
     pub rpu_out: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "fMP4 init segment, when the input fragments do not contain it",
+        value_hint = ValueHint::FilePath
+    )]
+    pub init_segment: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Sets the L6 metadata from the ST 2086 mastering display and content light level SEI of the input"
+    )]
+    pub l6_from_sei: bool,
+
+    #[arg(
+        long,
+        help = "Only extracts the RPUs of the sub-bitstream up to this temporal ID, skipping the access units of higher temporal layers",
+        value_parser = clap::value_parser!(u8).range(0..=6)
+    )]
+    pub temporal_id: Option<u8>,
 }
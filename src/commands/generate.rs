@@ -1,8 +1,9 @@
 use clap::{Args, ValueHint};
-use hdr10plus::metadata::PeakBrightnessSource;
+use dolby_vision::rpu::generate::Hdr10PlusPeakSource;
+use dolby_vision::xml::Level5Rounding;
 use std::path::PathBuf;
 
-use crate::dovi::generator::GeneratorProfile;
+use crate::dovi::generator::{BackfillPolicy, CameraLog, GeneratorProfile, Y4mTransfer};
 
 #[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ArgHdr10PlusPeakBrightnessSource {
@@ -15,6 +16,18 @@ pub enum ArgHdr10PlusPeakBrightnessSource {
     /// The luminance calculated from the `maxscl` components
     /// Assumed BT.2020 primaries
     MaxSclLuminance,
+    /// The highest of the histogram max and `maxscl`
+    Max,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgLevel5Rounding {
+    /// Round the active image size down
+    Floor,
+    /// Round the active image size to the nearest pixel
+    Round,
+    /// Round the active image size to the nearest even size, for symmetric offsets
+    Even,
 }
 
 #[derive(Args, Debug, Default)]
@@ -25,7 +38,7 @@ pub struct GenerateArgs {
         short = 'j',
         help = "Sets the generator config JSON file to use",
         conflicts_with = "xml",
-        required_unless_present = "xml",
+        required_unless_present_any = &["xml", "length", "synthetic", "from-y4m"],
         value_hint = ValueHint::FilePath
     )]
     pub json_file: Option<PathBuf>,
@@ -38,11 +51,29 @@ pub struct GenerateArgs {
     )]
     pub rpu_out: Option<PathBuf>,
 
+    #[arg(
+        id = "inject-into",
+        long,
+        help = "HEVC file to inject the generated RPUs into, without writing the RPU file",
+        conflicts_with_all = &["rpu_out", "xml_streaming"],
+        value_hint = ValueHint::FilePath
+    )]
+    pub inject_into: Option<PathBuf>,
+
+    #[arg(
+        id = "inject-output",
+        long,
+        help = "Injected HEVC output file location. Defaults to injected_output.hevc",
+        requires = "inject-into",
+        value_hint = ValueHint::FilePath
+    )]
+    pub inject_output: Option<PathBuf>,
+
     #[arg(
         id = "hdr10plus-json",
         long,
         help = "HDR10+ JSON file to generate from",
-        conflicts_with = "madvr-file",
+        conflicts_with_all = &["madvr-file", "shots-file", "camera-csv"],
         value_hint = ValueHint::FilePath,
     )]
     pub hdr10plus_json: Option<PathBuf>,
@@ -50,7 +81,7 @@ pub struct GenerateArgs {
     #[arg(
         value_enum,
         long,
-        help = "HDR10+: How to extract the peak brightness for the metadata",
+        help = "HDR10+: How to extract the peak brightness for the metadata. Config shots can override it with `hdr10plus_peak_source`",
         default_value = "histogram"
     )]
     pub hdr10plus_peak_source: Option<ArgHdr10PlusPeakBrightnessSource>,
@@ -59,32 +90,172 @@ pub struct GenerateArgs {
         short = 'x',
         long,
         help = "XML metadata file to generate from",
-        conflicts_with_all = &["json", "hdr10plus-json", "madvr-file"],
-        required_unless_present = "json",
+        conflicts_with_all = &["json", "hdr10plus-json", "madvr-file", "shots-file", "camera-csv"],
+        required_unless_present_any = &["json", "length", "synthetic", "from-y4m"],
         value_hint = ValueHint::FilePath
     )]
     pub xml: Option<PathBuf>,
 
+    #[arg(
+        long,
+        help = "Static metadata: number of frames to generate without a config, with constant L1 derived from the L6 metadata",
+        conflicts_with_all = &["json", "xml", "hdr10plus-json", "madvr-file", "shots-file", "camera-csv"],
+        requires = "mdl-max"
+    )]
+    pub length: Option<usize>,
+
+    #[arg(
+        id = "mdl-max",
+        long,
+        help = "Static metadata: mastering display max luminance, in nits",
+        requires = "length"
+    )]
+    pub mdl_max: Option<u16>,
+
+    #[arg(
+        id = "mdl-min",
+        long,
+        help = "Static metadata: mastering display min luminance, in nits. Defaults to 0.0001",
+        requires = "length",
+        value_parser = clap::value_parser!(f64)
+    )]
+    pub mdl_min: Option<f64>,
+
+    #[arg(
+        long,
+        help = "Static metadata: MaxCLL, in nits. Unknown (0) by default",
+        requires = "length"
+    )]
+    pub max_cll: Option<u16>,
+
+    #[arg(
+        long,
+        help = "Static metadata: MaxFALL, in nits. Unknown (0) by default",
+        requires = "length"
+    )]
+    pub max_fall: Option<u16>,
+
+    #[arg(
+        long,
+        help = "Synthetic: number of frames to generate with randomized but valid metadata, for testing decoders and players",
+        conflicts_with_all = &["json", "xml", "length", "hdr10plus-json", "madvr-file", "shots-file", "camera-csv"],
+        value_name = "FRAMES"
+    )]
+    pub synthetic: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Synthetic: seed of the randomized metadata. The same seed always generates the same RPUs",
+        requires = "synthetic",
+        default_value = "0"
+    )]
+    pub seed: u64,
+
     #[arg(long, help = "Canvas width for L5 metadata generation")]
     pub canvas_width: Option<u16>,
 
     #[arg(long, help = "Canvas height for L5 metadata generation")]
     pub canvas_height: Option<u16>,
 
+    #[arg(
+        value_enum,
+        long,
+        help = "XML: Rounding of the active image size for L5 metadata generation",
+        requires = "xml",
+        default_value = "round"
+    )]
+    pub l5_rounding: Option<ArgLevel5Rounding>,
+
+    #[arg(
+        long,
+        help = "XML: Clamp L5 offsets to the canvas instead of failing when they are negative or out of bounds",
+        requires = "xml"
+    )]
+    pub fix_negative_offsets: bool,
+
+    #[arg(
+        long,
+        help = "XML: Parse and generate the shots one at a time, for very large XML files on limited memory. The shots must be in order",
+        requires = "xml"
+    )]
+    pub xml_streaming: bool,
+
     #[arg(
         id = "madvr-file",
         long,
         help = "madVR measurement file to generate from",
+        conflicts_with_all = &["shots-file", "camera-csv"],
         value_hint = ValueHint::FilePath
     )]
     pub madvr_file: Option<PathBuf>,
 
+    #[arg(
+        id = "shots-file",
+        long,
+        help = "Text file to generate the shots L1 from. Each line is `start,duration,min_nits,max_nits,avg_nits`",
+        conflicts_with = "camera-csv",
+        value_hint = ValueHint::FilePath
+    )]
+    pub shots_file: Option<PathBuf>,
+
+    #[arg(
+        id = "camera-csv",
+        long,
+        help = "Camera metadata CSV to generate approximate shots L1 from, one clip per row with `frames`, `min`, `max` and `avg` log luma percentage columns",
+        requires = "camera-log",
+        value_hint = ValueHint::FilePath
+    )]
+    pub camera_csv: Option<PathBuf>,
+
+    #[arg(
+        id = "camera-log",
+        value_enum,
+        long,
+        help = "Camera CSV: log encoding of the luma statistics",
+        requires = "camera-csv"
+    )]
+    pub camera_log: Option<CameraLog>,
+
+    #[arg(
+        id = "from-y4m",
+        long,
+        help = "y4m video to measure the per-frame L1 from, `-` for stdin. The config JSON is optional",
+        conflicts_with_all = &["xml", "length", "synthetic", "hdr10plus-json", "madvr-file", "shots-file", "camera-csv"],
+        value_hint = ValueHint::FilePath
+    )]
+    pub from_y4m: Option<PathBuf>,
+
+    #[arg(
+        value_enum,
+        long,
+        help = "y4m source: transfer function of the video",
+        requires = "from-y4m",
+        default_value = "pq"
+    )]
+    pub y4m_transfer: Y4mTransfer,
+
+    #[arg(
+        long,
+        help = "y4m source: share of the pixels changing brightness (0-1) over which a frame starts a new scene",
+        requires = "from-y4m",
+        default_value = "0.3"
+    )]
+    pub scene_threshold: f64,
+
     #[arg(
         long,
         help = "madVR source: use custom per-frame target nits if available"
     )]
     pub use_custom_targets: bool,
 
+    #[arg(
+        long,
+        help = "madVR source: use a percentile (0-100) of the frame peaks as the shot L1 max, instead of the absolute max. Resists single frame speculars",
+        requires = "madvr-file",
+        value_parser = clap::value_parser!(f64)
+    )]
+    pub l1_max_percentile: Option<f64>,
+
     #[arg(
         value_enum,
         short = 'p',
@@ -95,15 +266,61 @@ pub struct GenerateArgs {
 
     #[arg(long, help = "Set scene cut flag for every frame")]
     pub long_play_mode: Option<bool>,
+
+    #[arg(
+        long,
+        value_name = "NITS",
+        help = "Caps the L1 max brightness, for delivery specs below the master peak. The L1 average, L3 max offset and L6 MaxCLL are adjusted to match"
+    )]
+    pub max_pq_clip: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "NITS",
+        requires = "max_pq_clip",
+        help = "With --max-pq-clip, rolls off the L1 max smoothly above this brightness instead of clipping"
+    )]
+    pub roll_off_knee: Option<f64>,
+
+    #[arg(
+        long,
+        help = "Automatically add L9 source primaries (DCI-P3) metadata, overrides the config"
+    )]
+    pub auto_l9: Option<bool>,
+
+    #[arg(
+        long,
+        help = "Automatically add L11 content type (reference cinema) metadata, overrides the config"
+    )]
+    pub auto_l11: Option<bool>,
+
+    #[arg(
+        value_enum,
+        long,
+        help = "HDR10+, madVR, shots file, camera CSV or y4m source: how to fill the frames of the config length not covered by the source",
+        default_value = "truncate"
+    )]
+    pub backfill: BackfillPolicy,
 }
 
-impl From<ArgHdr10PlusPeakBrightnessSource> for PeakBrightnessSource {
+impl From<ArgHdr10PlusPeakBrightnessSource> for Hdr10PlusPeakSource {
     fn from(e: ArgHdr10PlusPeakBrightnessSource) -> Self {
         match e {
             ArgHdr10PlusPeakBrightnessSource::Histogram => Self::Histogram,
             ArgHdr10PlusPeakBrightnessSource::Histogram99 => Self::Histogram99,
             ArgHdr10PlusPeakBrightnessSource::MaxScl => Self::MaxScl,
             ArgHdr10PlusPeakBrightnessSource::MaxSclLuminance => Self::MaxSclLuminance,
+            ArgHdr10PlusPeakBrightnessSource::Max => Self::Max,
+        }
+    }
+}
+
+impl From<ArgLevel5Rounding> for Level5Rounding {
+    fn from(e: ArgLevel5Rounding) -> Self {
+        match e {
+            ArgLevel5Rounding::Floor => Self::Floor,
+            ArgLevel5Rounding::Round => Self::Round,
+            ArgLevel5Rounding::Even => Self::Even,
         }
     }
 }
@@ -0,0 +1,46 @@
+use clap::{Args, ValueHint};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct HistogramArgs {
+    #[arg(
+        id = "input",
+        help = "Sets the input RPU file to use",
+        long,
+        short = 'i',
+        conflicts_with = "input_pos",
+        required_unless_present = "input_pos",
+        value_hint = ValueHint::FilePath,
+    )]
+    pub input: Option<PathBuf>,
+
+    #[arg(
+        id = "input_pos",
+        help = "Sets the input RPU file to use (positional)",
+        conflicts_with = "input",
+        required_unless_present = "input",
+        value_hint = ValueHint::FilePath
+    )]
+    pub input_pos: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Number of bins, evenly spaced in PQ",
+        default_value = "16",
+        value_parser = clap::value_parser!(u16).range(1..=256)
+    )]
+    pub bins: u16,
+
+    #[arg(
+        long,
+        help = "Prints the L1 max distribution of every shot, instead of only the outlier shots"
+    )]
+    pub per_shot: bool,
+
+    #[arg(
+        long,
+        help = "Plots the histograms to a PNG image",
+        value_hint = ValueHint::FilePath
+    )]
+    pub plot: Option<PathBuf>,
+}
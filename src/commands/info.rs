@@ -31,6 +31,60 @@ pub struct InfoArgs {
     )]
     pub frame: Option<usize>,
 
+    #[arg(
+        id = "bits",
+        long,
+        requires = "frame",
+        help = "Dump the raw RPU bytes of the frame, with the annotated bit fields"
+    )]
+    pub bits: bool,
+
     #[arg(id = "summary", long, short = 's', help = "Show the RPU summary")]
     pub summary: bool,
+
+    #[arg(
+        id = "identical-runs",
+        long,
+        value_name = "MIN_LENGTH",
+        help = "Report the runs of at least MIN_LENGTH frames with identical DM metadata"
+    )]
+    pub identical_runs: Option<usize>,
+
+    #[arg(
+        id = "stability",
+        long,
+        help = "Report the metadata stability score, flagging the within-shot L1/L2 changes and very short shots"
+    )]
+    pub stability: bool,
+
+    #[arg(
+        id = "stability-threshold",
+        long,
+        help = "Stability: max within-shot change, in normalized PQ for L1 and normalized trim for L2",
+        default_value = "0.02",
+        requires = "stability"
+    )]
+    pub stability_threshold: f64,
+
+    #[arg(
+        long,
+        help = "Stability: frame rate of the video, for the scene cut density",
+        default_value = "23.976",
+        requires = "stability"
+    )]
+    pub fps: f64,
+
+    #[arg(
+        id = "validate-trims",
+        long,
+        help = "Validate the L2 trims against their target display, flagging the shots that clip, crush or invert"
+    )]
+    pub validate_trims: bool,
+
+    #[arg(
+        long,
+        help = "Generate config or CM XML, to show the editorial labels of the shots next to the reported frames",
+        value_hint = ValueHint::FilePath
+    )]
+    pub labels: Option<PathBuf>,
 }
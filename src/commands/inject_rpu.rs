@@ -1,6 +1,9 @@
 use clap::{Args, ValueHint};
 use std::path::PathBuf;
 
+use crate::dovi::rpu_injector::Hdr10PlusSei;
+use crate::dovi::RpuStartCode;
+
 #[derive(Args, Debug)]
 pub struct InjectRpuArgs {
     #[arg(
@@ -36,4 +39,58 @@ pub struct InjectRpuArgs {
 
     #[arg(long, num_args = 0, help = "Disable adding AUD NALUs between frames")]
     pub no_add_aud: bool,
+
+    #[arg(
+        long,
+        num_args = 0,
+        help = "MV-HEVC: Also insert the RPU after the second view NALUs (nuh_layer_id 1)"
+    )]
+    pub mv_hevc: bool,
+
+    #[arg(
+        value_enum,
+        long,
+        help = "Start code to use for the RPU NALUs. `auto` uses the same as the input slices. Defaults to the --start-code preset"
+    )]
+    pub rpu_start_code: Option<RpuStartCode>,
+
+    #[arg(
+        value_enum,
+        long,
+        help = "What to do with the HDR10+ SEI already in the input. `strip` is the same as --drop-hdr10plus",
+        default_value = "keep"
+    )]
+    pub hdr10plus: Hdr10PlusSei,
+
+    #[arg(
+        long,
+        help = "Sets the L6 metadata from the ST 2086 mastering display and content light level SEI of the input"
+    )]
+    pub l6_from_sei: bool,
+
+    #[arg(
+        long,
+        help = "Drops the excess RPUs when the RPU file has more frames than the video, instead of failing. The tail is dropped by default"
+    )]
+    pub truncate_rpu: bool,
+
+    #[arg(
+        long,
+        help = "With --truncate-rpu, drops the excess RPUs at the start instead, aligning the last frames",
+        requires = "truncate_rpu"
+    )]
+    pub align_end: bool,
+
+    #[arg(
+        long,
+        help = "Resumes an interrupted run from its checkpoint, reading the input from the checkpointed offset"
+    )]
+    pub resume: bool,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Writes checkpoints next to the output at this interval, to resume with --resume. 0 checkpoints every frame"
+    )]
+    pub checkpoint_interval: Option<u64>,
 }
@@ -2,30 +2,64 @@ use clap::Parser;
 
 use dolby_vision::rpu::ConversionMode;
 
+mod apply_patch;
+mod bitrate;
+mod compare_injected;
+mod completions;
 mod convert;
 mod demux;
+mod diff;
 mod editor;
 mod export;
 mod extract_rpu;
 mod generate;
+mod histogram;
 mod info;
 mod inject_rpu;
 mod mux;
 mod plot;
+mod reorder;
+mod transcode_metadata;
+mod verify;
+mod watch;
 
+pub use apply_patch::ApplyPatchArgs;
+pub use bitrate::BitrateArgs;
+pub use compare_injected::CompareInjectedArgs;
+pub use completions::CompletionsArgs;
 pub use convert::ConvertArgs;
 pub use demux::DemuxArgs;
+pub use diff::DiffArgs;
 pub use editor::EditorArgs;
 pub use export::ExportArgs;
 pub use extract_rpu::ExtractRpuArgs;
 pub use generate::{ArgHdr10PlusPeakBrightnessSource, GenerateArgs};
+pub use histogram::HistogramArgs;
 pub use info::InfoArgs;
 pub use inject_rpu::InjectRpuArgs;
 pub use mux::MuxArgs;
 pub use plot::PlotArgs;
+pub use reorder::ReorderArgs;
+pub use transcode_metadata::TranscodeMetadataArgs;
+pub use verify::VerifyArgs;
+pub use watch::WatchArgs;
 
 #[derive(Parser, Debug)]
 pub enum Command {
+    #[command(about = "Applies a metadata patch created with `diff` to a binary RPU")]
+    ApplyPatch(ApplyPatchArgs),
+
+    #[command(about = "Computes the RPU metadata bitrate over time, and flags the spikes")]
+    Bitrate(BitrateArgs),
+
+    #[command(
+        about = "Verifies that an injected HEVC file only differs from the original by the RPUs"
+    )]
+    CompareInjected(CompareInjectedArgs),
+
+    #[command(about = "Generates shell completions, printed to stdout")]
+    Completions(CompletionsArgs),
+
     #[command(about = "Converts RPU within a single layer HEVC file")]
     Convert(ConvertArgs),
 
@@ -34,6 +68,9 @@ pub enum Command {
     )]
     Demux(DemuxArgs),
 
+    #[command(about = "Creates a patch of the metadata changes between two binary RPUs")]
+    Diff(DiffArgs),
+
     #[command(about = "Edits a binary RPU according to a JSON config")]
     Editor(EditorArgs),
 
@@ -49,6 +86,11 @@ pub enum Command {
     #[command(about = "Generates a binary RPU from different sources")]
     Generate(GenerateArgs),
 
+    #[command(
+        about = "Prints the distribution of the L1 max and average brightness, per frame and per shot"
+    )]
+    Histogram(HistogramArgs),
+
     #[command(about = "Prints the parsed RPU data as JSON for a specific frame")]
     Info(InfoArgs),
 
@@ -57,6 +99,24 @@ pub enum Command {
 
     #[command(about = "Plot the L1 dynamic brightness metadata")]
     Plot(PlotArgs),
+
+    #[command(
+        about = "Reorders the RPUs between display order and encode order, from a x265 CSV log or a frame order file"
+    )]
+    Reorder(ReorderArgs),
+
+    #[command(
+        about = "Converts, edits and filters the levels of a binary RPU in a single pass. Uses the global --mode"
+    )]
+    TranscodeMetadata(TranscodeMetadataArgs),
+
+    #[command(about = "Verifies the NALU structure and CRC32 of every frame of a binary RPU")]
+    Verify(VerifyArgs),
+
+    #[command(
+        about = "Watches a directory and runs the extract, convert and inject steps on every new HEVC or RPU file"
+    )]
+    Watch(WatchArgs),
 }
 
 #[derive(clap::ValueEnum, Debug, Copy, Clone)]
@@ -73,6 +133,9 @@ pub enum ConversionModeCli {
     To84,
     #[value(name = "5")]
     To81MappingPreserved,
+    // Resolved by `convert` from the input, never converted as is
+    #[value(name = "auto")]
+    Auto,
 }
 
 impl From<ConversionModeCli> for ConversionMode {
@@ -83,6 +146,7 @@ impl From<ConversionModeCli> for ConversionMode {
             ConversionModeCli::To81 | ConversionModeCli::Profile5To81 => ConversionMode::To81,
             ConversionModeCli::To84 => ConversionMode::To84,
             ConversionModeCli::To81MappingPreserved => ConversionMode::To81MappingPreserved,
+            ConversionModeCli::Auto => ConversionMode::Lossless,
         }
     }
 }
@@ -33,4 +33,17 @@ pub struct PlotArgs {
 
     #[arg(long, short = 't', help = "Title to use at the top")]
     pub title: Option<String>,
+
+    #[arg(
+        long,
+        help = "Plots a single point for every N frames, keeping the min/max envelope. Speeds up plotting long files",
+        conflicts_with = "per_shot"
+    )]
+    pub sample_every: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Plots a single point per shot, keeping the min/max envelope"
+    )]
+    pub per_shot: bool,
 }
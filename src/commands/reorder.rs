@@ -0,0 +1,55 @@
+use clap::{Args, ValueHint};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct ReorderArgs {
+    #[arg(
+        id = "input",
+        help = "Sets the input RPU file to use",
+        long,
+        short = 'i',
+        conflicts_with = "input_pos",
+        required_unless_present = "input_pos",
+        value_hint = ValueHint::FilePath,
+    )]
+    pub input: Option<PathBuf>,
+
+    #[arg(
+        id = "input_pos",
+        help = "Sets the input RPU file to use (positional)",
+        conflicts_with = "input",
+        required_unless_present = "input",
+        value_hint = ValueHint::FilePath
+    )]
+    pub input_pos: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "frame_order",
+        required_unless_present = "frame_order",
+        help = "x265 CSV frame log (--csv with --csv-log-level 1 or higher), with the POC of every encoded frame",
+        value_hint = ValueHint::FilePath
+    )]
+    pub x265_csv: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Frame order file, with the display index of every encoded frame, one per line",
+        value_hint = ValueHint::FilePath
+    )]
+    pub frame_order: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Reorders an encode order RPU file to display order, instead of display to encode order"
+    )]
+    pub to_display: bool,
+
+    #[arg(
+        long,
+        short = 'o',
+        help = "Reordered RPU output file location",
+        value_hint = ValueHint::FilePath
+    )]
+    pub rpu_out: Option<PathBuf>,
+}
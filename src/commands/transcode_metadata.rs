@@ -0,0 +1,49 @@
+use clap::{Args, ValueHint};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct TranscodeMetadataArgs {
+    #[arg(
+        id = "input",
+        help = "Sets the input RPU file to use",
+        long,
+        short = 'i',
+        conflicts_with = "input_pos",
+        required_unless_present = "input_pos",
+        value_hint = ValueHint::FilePath,
+    )]
+    pub input: Option<PathBuf>,
+
+    #[arg(
+        id = "input_pos",
+        help = "Sets the input RPU file to use (positional)",
+        conflicts_with = "input",
+        required_unless_present = "input",
+        value_hint = ValueHint::FilePath
+    )]
+    pub input_pos: Option<PathBuf>,
+
+    #[arg(
+        id = "json",
+        long,
+        short = 'j',
+        help = "Sets the edit JSON file to use, same as for `editor`",
+        value_hint = ValueHint::FilePath
+    )]
+    pub json_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Metadata levels to remove after the conversion and edits, comma separated. Example: --drop-levels 3,254"
+    )]
+    pub drop_levels: Vec<u8>,
+
+    #[arg(
+        long,
+        short = 'o',
+        help = "Transcoded RPU output file location",
+        value_hint = ValueHint::FilePath
+    )]
+    pub rpu_out: Option<PathBuf>,
+}
@@ -0,0 +1,71 @@
+use clap::{Args, ValueHint};
+use std::path::PathBuf;
+
+use crate::dovi::watcher::WatchStep;
+
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    #[arg(
+        help = "Sets the directory to watch for new files",
+        value_hint = ValueHint::DirPath
+    )]
+    pub dir: PathBuf,
+
+    #[arg(
+        long,
+        short = 'p',
+        value_delimiter = ',',
+        default_value = "*.hevc,*.bin",
+        help = "File name patterns to process, comma separated. `*` and `?` wildcards, case insensitive"
+    )]
+    pub patterns: Vec<String>,
+
+    #[arg(
+        value_enum,
+        long,
+        short = 's',
+        required = true,
+        value_delimiter = ',',
+        help = "Pipeline steps to run on every new file, in order, comma separated. Example: --steps extract,inject"
+    )]
+    pub steps: Vec<WatchStep>,
+
+    #[arg(
+        long,
+        short = 'o',
+        help = "Output directory of the processed files. Defaults to `processed` in the watched directory",
+        value_hint = ValueHint::DirPath
+    )]
+    pub output_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Directory of the RPUs to inject, named after the HEVC files: `<name>.bin`",
+        value_hint = ValueHint::DirPath
+    )]
+    pub rpu_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "State file listing the processed files. Defaults to `.dovi_tool_watch.json` in the watched directory",
+        value_hint = ValueHint::FilePath
+    )]
+    pub state_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "5",
+        help = "Delay between the directory scans. A file is processed once its size is unchanged between two scans"
+    )]
+    pub interval: u64,
+
+    #[arg(short = 'd', long, help = "Discard the EL stream in the convert step")]
+    pub discard: bool,
+
+    #[arg(
+        long,
+        help = "Processes the files already in the directory and exits, without waiting for them to be complete. Fails if any file failed"
+    )]
+    pub once: bool,
+}
@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use anyhow::{ensure, Result};
+use serde::Deserialize;
+
+use super::DoviRpu;
+
+/// Black bars measured for a range of frames, by a bar detection script.
+///
+/// Other fields of the detection output are ignored.
+#[derive(Deserialize, Debug, Clone)]
+pub struct DetectedBars {
+    /// First and last frames of the range, inclusive
+    start: usize,
+    end: usize,
+
+    #[serde(default)]
+    left: u16,
+    #[serde(default)]
+    right: u16,
+    #[serde(default)]
+    top: u16,
+    #[serde(default)]
+    bottom: u16,
+}
+
+/// Stable active area offsets for the shots, from noisy bar detection stats.
+///
+/// Each shot uses the smallest bars measured within it, so that the active area never cuts the picture.
+/// Offsets within `tolerance` pixels of zero are dropped, and a shot keeps the offsets
+/// of the previous shot when every side is within `tolerance` pixels, to ignore the detection jitter.
+///
+/// Shots without any detection stats are not returned.
+pub fn shot_offsets(
+    detection: &[DetectedBars],
+    rpus: &[Option<DoviRpu>],
+    tolerance: u16,
+) -> Result<Vec<(RangeInclusive<usize>, [u16; 4])>> {
+    let frames = rpus.len();
+    let mut measured: Vec<Option<[u16; 4]>> = vec![None; frames];
+
+    for bars in detection {
+        ensure!(
+            bars.start <= bars.end && bars.end < frames,
+            "Invalid bar detection range {}-{}, {} available RPUs",
+            bars.start,
+            bars.end,
+            frames
+        );
+
+        let offsets = [bars.left, bars.right, bars.top, bars.bottom];
+        measured[bars.start..=bars.end].fill(Some(offsets));
+    }
+
+    let mut scene_starts: Vec<usize> = rpus
+        .iter()
+        .enumerate()
+        .filter_map(|(frame, rpu)| {
+            let scene_cut = rpu
+                .as_ref()
+                .and_then(|rpu| rpu.vdr_dm_data.as_ref())
+                .map_or(false, |vdr_dm_data| vdr_dm_data.scene_refresh_flag == 1);
+
+            (frame == 0 || scene_cut).then_some(frame)
+        })
+        .collect();
+    scene_starts.push(frames);
+
+    let mut shots = Vec::new();
+    let mut previous: Option<[u16; 4]> = None;
+
+    for window in scene_starts.windows(2) {
+        let shot = window[0]..=window[1] - 1;
+
+        let smallest =
+            measured[shot.clone()]
+                .iter()
+                .flatten()
+                .fold(None::<[u16; 4]>, |acc, bars| {
+                    Some(match acc {
+                        None => *bars,
+                        Some(acc) => std::array::from_fn(|i| bars[i].min(acc[i])),
+                    })
+                });
+
+        let Some(mut offsets) = smallest else {
+            continue;
+        };
+
+        offsets = offsets.map(|offset| if offset <= tolerance { 0 } else { offset });
+
+        if let Some(previous) = previous {
+            let jitter = offsets
+                .iter()
+                .zip(previous)
+                .all(|(offset, prev)| offset.abs_diff(prev) <= tolerance);
+
+            if jitter {
+                offsets = previous;
+            }
+        }
+
+        previous = Some(offsets);
+        shots.push((shot, offsets));
+    }
+
+    Ok(shots)
+}
+
+/// Reads a bar detection JSON, a list of frame ranges with the measured bars
+pub fn parse_detection_file<P: AsRef<Path>>(path: P) -> Result<Vec<DetectedBars>> {
+    let reader = BufReader::new(File::open(path)?);
+
+    Ok(serde_json::from_reader(reader)?)
+}
@@ -0,0 +1,317 @@
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Result};
+
+const M2TS_PACKET_SIZE: usize = 192;
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+
+const PAT_PID: u16 = 0;
+const STREAM_TYPE_HEVC: u8 = 0x24;
+
+/// Blu-ray `.mpls` playlist, listing the `.m2ts` clips in playback order.
+#[derive(Debug)]
+pub struct MplsPlaylist {
+    pub clips: Vec<String>,
+}
+
+/// Demuxes the Dolby Vision HEVC stream from a Blu-ray `.m2ts` clip,
+/// reading it returns the HEVC elementary stream.
+///
+/// The last HEVC stream of the program is used, which is the enhancement layer
+/// on dual layer discs, or the only video stream otherwise.
+pub struct M2tsHevcDemuxer<R: Read> {
+    reader: R,
+
+    pmt_pid: Option<u16>,
+    video_pid: Option<u16>,
+
+    section: Vec<u8>,
+    pes_started: bool,
+
+    // Demuxed data not read yet
+    buffer: Vec<u8>,
+    buffer_pos: usize,
+}
+
+impl MplsPlaylist {
+    pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(
+            data.len() >= 12 && &data[..4] == b"MPLS",
+            "Invalid MPLS playlist: missing header"
+        );
+
+        let playlist_start = be_u32(data, 8)? as usize;
+
+        // length (32), reserved (16)
+        let num_play_items = be_u16(data, playlist_start + 6)?;
+
+        let mut pos = playlist_start + 10;
+        let mut clips = Vec::with_capacity(num_play_items as usize);
+
+        for _ in 0..num_play_items {
+            let length = be_u16(data, pos)? as usize;
+            let name = data
+                .get(pos + 2..pos + 7)
+                .ok_or_else(|| anyhow::anyhow!("Invalid MPLS playlist: truncated PlayItem"))?;
+
+            clips.push(String::from_utf8_lossy(name).into_owned());
+
+            pos += 2 + length;
+        }
+
+        ensure!(!clips.is_empty(), "Invalid MPLS playlist: no PlayItem");
+
+        Ok(Self { clips })
+    }
+
+    /// Paths of the clips, from the `BDMV/STREAM` directory next to the playlist's `BDMV/PLAYLIST`
+    pub fn clip_paths<P: AsRef<Path>>(&self, playlist_path: P) -> Result<Vec<PathBuf>> {
+        let bdmv_dir = playlist_path
+            .as_ref()
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new("."));
+        let stream_dir = bdmv_dir.join("STREAM");
+
+        self.clips
+            .iter()
+            .map(|clip| {
+                let path = stream_dir.join(format!("{clip}.m2ts"));
+
+                if path.is_file() {
+                    Ok(path)
+                } else {
+                    bail!("Missing playlist clip: {}", path.display())
+                }
+            })
+            .collect()
+    }
+}
+
+impl M2tsHevcDemuxer<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(input: P) -> Result<Self> {
+        Ok(Self::new(BufReader::with_capacity(
+            100_000,
+            File::open(input)?,
+        )))
+    }
+}
+
+impl<R: Read> M2tsHevcDemuxer<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            pmt_pid: None,
+            video_pid: None,
+            section: Vec::new(),
+            pes_started: false,
+            buffer: Vec::new(),
+            buffer_pos: 0,
+        }
+    }
+
+    /// Demuxes packets until some stream data is available, returns false at the end of the clip
+    fn fill_buffer(&mut self) -> Result<bool> {
+        self.buffer.clear();
+        self.buffer_pos = 0;
+
+        let mut packet = [0; M2TS_PACKET_SIZE];
+
+        while self.buffer.is_empty() {
+            match self.reader.read_exact(&mut packet) {
+                Ok(_) => {}
+                // Ignore truncated packet at the end
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    ensure!(
+                        self.video_pid.is_some(),
+                        "No HEVC video stream found in M2TS clip"
+                    );
+
+                    return Ok(false);
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            // Skip the 4 bytes TP_extra_header
+            self.process_packet(&packet[4..])?;
+        }
+
+        Ok(true)
+    }
+
+    fn process_packet(&mut self, packet: &[u8]) -> Result<()> {
+        ensure!(
+            packet.len() == TS_PACKET_SIZE && packet[0] == TS_SYNC_BYTE,
+            "Invalid M2TS packet: lost sync"
+        );
+
+        let unit_start = packet[1] & 0x40 != 0;
+        let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+        let adaptation_field_control = (packet[3] >> 4) & 0x3;
+
+        // No payload
+        if adaptation_field_control & 0x1 == 0 {
+            return Ok(());
+        }
+
+        let payload_start = if adaptation_field_control & 0x2 != 0 {
+            5 + packet[4] as usize
+        } else {
+            4
+        };
+
+        let Some(payload) = packet.get(payload_start..) else {
+            return Ok(());
+        };
+
+        if Some(pid) == self.video_pid {
+            self.process_pes(payload, unit_start)?;
+        } else if pid == PAT_PID && self.pmt_pid.is_none() {
+            if let Some(section) = self.read_section(payload, unit_start) {
+                self.pmt_pid = parse_pat(&section);
+            }
+        } else if Some(pid) == self.pmt_pid && self.video_pid.is_none() {
+            if let Some(section) = self.read_section(payload, unit_start) {
+                self.video_pid = parse_pmt(&section);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Accumulates a PSI section, returns it once complete
+    fn read_section(&mut self, payload: &[u8], unit_start: bool) -> Option<Vec<u8>> {
+        if unit_start {
+            let pointer_field = *payload.first()? as usize;
+
+            self.section.clear();
+            self.section
+                .extend_from_slice(payload.get(1 + pointer_field..)?);
+        } else if !self.section.is_empty() {
+            self.section.extend_from_slice(payload);
+        }
+
+        if self.section.len() < 3 {
+            return None;
+        }
+
+        let section_length = (((self.section[1] & 0x0F) as usize) << 8) | self.section[2] as usize;
+        let total_length = 3 + section_length;
+
+        if self.section.len() >= total_length {
+            let section = self.section[..total_length].to_vec();
+            self.section.clear();
+
+            Some(section)
+        } else {
+            None
+        }
+    }
+
+    fn process_pes(&mut self, payload: &[u8], unit_start: bool) -> Result<()> {
+        if unit_start {
+            ensure!(
+                payload.len() >= 9 && payload[..3] == [0, 0, 1],
+                "Invalid PES packet in M2TS clip"
+            );
+
+            let header_data_length = payload[8] as usize;
+            self.pes_started = true;
+
+            if let Some(data) = payload.get(9 + header_data_length..) {
+                self.buffer.extend_from_slice(data);
+            }
+        } else if self.pes_started {
+            self.buffer.extend_from_slice(payload);
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads fill `buf` until the end of the clip, like file reads
+impl<R: Read> Read for M2tsHevcDemuxer<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+
+        while read < buf.len() {
+            if self.buffer_pos == self.buffer.len() {
+                let filled = self
+                    .fill_buffer()
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+                if !filled {
+                    break;
+                }
+            }
+
+            let len = (buf.len() - read).min(self.buffer.len() - self.buffer_pos);
+            buf[read..read + len]
+                .copy_from_slice(&self.buffer[self.buffer_pos..self.buffer_pos + len]);
+
+            self.buffer_pos += len;
+            read += len;
+        }
+
+        Ok(read)
+    }
+}
+
+fn parse_pat(section: &[u8]) -> Option<u16> {
+    // Header is 8 bytes, CRC32 is 4 bytes
+    let programs = section.get(8..section.len().checked_sub(4)?)?;
+
+    programs.chunks_exact(4).find_map(|program| {
+        let program_number = u16::from_be_bytes([program[0], program[1]]);
+        let pid = u16::from_be_bytes([program[2] & 0x1F, program[3]]);
+
+        // Program 0 is the network PID
+        (program_number != 0).then_some(pid)
+    })
+}
+
+fn parse_pmt(section: &[u8]) -> Option<u16> {
+    let program_info_length = u16::from_be_bytes([*section.get(10)? & 0x0F, *section.get(11)?]);
+
+    let end = section.len().checked_sub(4)?;
+    let mut pos = 12 + program_info_length as usize;
+    let mut hevc_pid = None;
+
+    while pos + 5 <= end {
+        let stream_type = section[pos];
+        let pid = u16::from_be_bytes([section[pos + 1] & 0x1F, section[pos + 2]]);
+        let es_info_length = u16::from_be_bytes([section[pos + 3] & 0x0F, section[pos + 4]]);
+
+        if stream_type == STREAM_TYPE_HEVC {
+            hevc_pid = Some(pid);
+        }
+
+        pos += 5 + es_info_length as usize;
+    }
+
+    hevc_pid
+}
+
+fn be_u16(data: &[u8], pos: usize) -> Result<u16> {
+    match data.get(pos..pos + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => bail!("Invalid MPLS playlist: unexpected end of data"),
+    }
+}
+
+fn be_u32(data: &[u8], pos: usize) -> Result<u32> {
+    match data.get(pos..pos + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes(bytes.try_into()?)),
+        None => bail!("Invalid MPLS playlist: unexpected end of data"),
+    }
+}
@@ -0,0 +1,208 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+use hevc_parser::io::IoFormat;
+
+use crate::ipc;
+
+use super::bdmv::{M2tsHevcDemuxer, MplsPlaylist};
+use super::fmp4::Fmp4RpuReader;
+use super::heif::HeifRpuReader;
+
+/// Input container or bitstream format the RPUs can be read from.
+///
+/// Implementations only have to locate the RPUs, the conversion and
+/// writing is shared by every format.
+pub trait BitstreamFormat {
+    /// Short name, used in error messages
+    fn name(&self) -> &'static str;
+
+    /// Whether this format handles the input, usually from the file extension
+    fn probe(&self, input: &Path) -> bool;
+
+    /// Whether an initialization segment can be read before the input
+    fn supports_init_segment(&self) -> bool {
+        false
+    }
+
+    fn open(&self, input: &Path, init_segment: Option<&Path>) -> Result<RpuSource>;
+}
+
+/// What a format provides to read the RPUs from
+pub enum RpuSource {
+    /// HEVC bitstream, parsed for the UNSPEC62 NALUs
+    Hevc { path: PathBuf, format: IoFormat },
+    /// HEVC bitstream demuxed while reading the `path` container, parsed for the UNSPEC62 NALUs
+    HevcStream {
+        path: PathBuf,
+        reader: Box<dyn Read>,
+    },
+    /// RPU NAL units (including the NAL header) already demuxed, in presentation order
+    Nalus(Vec<Vec<u8>>),
+    /// Inputs played back in order, each opened with its own format
+    Segments(Vec<PathBuf>),
+}
+
+/// Formats available for reading RPUs.
+///
+/// The last registered format matching the input is used,
+/// so registered formats take priority over the built-in ones.
+pub struct FormatRegistry {
+    formats: Vec<Box<dyn BitstreamFormat>>,
+}
+
+impl FormatRegistry {
+    /// Registry without any format
+    pub fn empty() -> Self {
+        Self {
+            formats: Vec::new(),
+        }
+    }
+
+    pub fn register<F: BitstreamFormat + 'static>(&mut self, format: F) {
+        self.formats.push(Box::new(format));
+    }
+
+    pub fn find(&self, input: &Path) -> Result<&dyn BitstreamFormat> {
+        match self.formats.iter().rev().find(|f| f.probe(input)) {
+            Some(format) => Ok(format.as_ref()),
+            None => bail!("Unsupported input format: {}", input.display()),
+        }
+    }
+}
+
+impl Default for FormatRegistry {
+    /// HEVC bitstreams, fMP4, HEIF/AVIF images, Blu-ray M2TS clips and MPLS playlists
+    fn default() -> Self {
+        let mut registry = Self::empty();
+
+        registry.register(HevcFormat);
+        registry.register(Fmp4Format);
+        registry.register(HeifFormat);
+        registry.register(M2tsFormat);
+        registry.register(MplsFormat);
+
+        registry
+    }
+}
+
+/// Raw HEVC bitstream file or stdin, the fallback for any input
+pub struct HevcFormat;
+
+/// Fragmented MP4 (CMAF, DASH/HLS segments)
+pub struct Fmp4Format;
+
+/// HEIF/AVIF still images and image sequences
+pub struct HeifFormat;
+
+/// Blu-ray M2TS clip, its HEVC stream is demuxed while being parsed
+pub struct M2tsFormat;
+
+/// Blu-ray MPLS playlist, its clips are read in playback order
+pub struct MplsFormat;
+
+impl BitstreamFormat for HevcFormat {
+    fn name(&self) -> &'static str {
+        "HEVC"
+    }
+
+    fn probe(&self, _input: &Path) -> bool {
+        true
+    }
+
+    fn open(&self, input: &Path, _init_segment: Option<&Path>) -> Result<RpuSource> {
+        match hevc_parser::io::format_from_path(input)? {
+            IoFormat::Matroska => bail!("Matroska input is unsupported"),
+            format => Ok(RpuSource::Hevc {
+                path: input.to_path_buf(),
+                format,
+            }),
+        }
+    }
+}
+
+impl BitstreamFormat for Fmp4Format {
+    fn name(&self) -> &'static str {
+        "fMP4"
+    }
+
+    fn probe(&self, input: &Path) -> bool {
+        has_extension(input, &["mp4", "m4s", "m4v", "cmfv"])
+    }
+
+    fn supports_init_segment(&self) -> bool {
+        true
+    }
+
+    fn open(&self, input: &Path, init_segment: Option<&Path>) -> Result<RpuSource> {
+        ipc::log("Reading fMP4 fragments...");
+
+        Fmp4RpuReader::read(input, init_segment).map(RpuSource::Nalus)
+    }
+}
+
+impl BitstreamFormat for HeifFormat {
+    fn name(&self) -> &'static str {
+        "HEIF"
+    }
+
+    fn probe(&self, input: &Path) -> bool {
+        has_extension(
+            input,
+            &["heif", "heic", "heifs", "heics", "hif", "avif", "avifs"],
+        )
+    }
+
+    fn open(&self, input: &Path, _init_segment: Option<&Path>) -> Result<RpuSource> {
+        ipc::log("Reading HEIF images...");
+
+        HeifRpuReader::read(input).map(RpuSource::Nalus)
+    }
+}
+
+impl BitstreamFormat for M2tsFormat {
+    fn name(&self) -> &'static str {
+        "M2TS"
+    }
+
+    fn probe(&self, input: &Path) -> bool {
+        has_extension(input, &["m2ts", "mts"])
+    }
+
+    fn open(&self, input: &Path, _init_segment: Option<&Path>) -> Result<RpuSource> {
+        ipc::log("Demuxing HEVC stream...");
+        let demuxer = M2tsHevcDemuxer::open(input)?;
+
+        Ok(RpuSource::HevcStream {
+            path: input.to_path_buf(),
+            reader: Box::new(demuxer),
+        })
+    }
+}
+
+impl BitstreamFormat for MplsFormat {
+    fn name(&self) -> &'static str {
+        "MPLS"
+    }
+
+    fn probe(&self, input: &Path) -> bool {
+        has_extension(input, &["mpls"])
+    }
+
+    fn open(&self, input: &Path, _init_segment: Option<&Path>) -> Result<RpuSource> {
+        let playlist = MplsPlaylist::parse_file(input)?;
+
+        playlist.clip_paths(input).map(RpuSource::Segments)
+    }
+}
+
+fn has_extension(input: &Path, extensions: &[&str]) -> bool {
+    input
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            extensions.contains(&ext.to_ascii_lowercase().as_str())
+        })
+}
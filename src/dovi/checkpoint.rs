@@ -0,0 +1,447 @@
+//! Checkpoints of the long-running `inject-rpu` and `demux` commands, to resume them with `--resume`.
+//!
+//! The outputs are flushed and synced at an access unit boundary, then the checkpoint
+//! records their sizes along with the input offset of the next access unit.
+//!
+//! A resumed run seeks the input to the checkpointed offset, truncates the outputs
+//! to the checkpointed sizes and appends to them.
+//! The commands only checkpoint access units from which the input can be processed again,
+//! without the state of the previous frames.
+
+use std::cell::Cell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use anyhow::{bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ipc;
+
+use super::CliOptions;
+
+const CHECKPOINT_VERSION: u8 = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Checkpoint {
+    version: u8,
+    command: String,
+    /// Hash of the options affecting the outputs
+    settings: String,
+
+    inputs: Vec<CheckpointInput>,
+    /// Offset of the first access unit not written, in the HEVC input
+    input_offset: u64,
+    /// Access units written
+    frames_done: usize,
+
+    outputs: Vec<CheckpointOutput>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct CheckpointInput {
+    path: PathBuf,
+    size: u64,
+    /// Modification time, in seconds since the Unix epoch
+    modified: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct CheckpointOutput {
+    path: PathBuf,
+    /// Size of the output at the checkpoint
+    offset: u64,
+}
+
+pub struct Checkpointer {
+    path: PathBuf,
+    /// No new checkpoint is written without interval
+    interval: Option<Duration>,
+    last_write: Instant,
+
+    checkpoint: Checkpoint,
+    /// Bytes written to the outputs, shared with the writers
+    written: Vec<Rc<Cell<u64>>>,
+    /// Bytes read from the input, shared with the reader
+    input_read: Rc<Cell<u64>>,
+
+    /// Last access unit boundary, not checkpointed yet
+    boundary: Option<Boundary>,
+    /// Input offset of the checkpoint being resumed
+    resumed_offset: Option<u64>,
+}
+
+struct Boundary {
+    input_offset: u64,
+    frames_done: usize,
+    outputs: Vec<u64>,
+}
+
+/// Output file of a checkpointed command, counting the bytes written
+pub struct CheckpointWriter {
+    writer: BufWriter<File>,
+    written: Rc<Cell<u64>>,
+}
+
+/// Input counting the bytes read, to locate the access units
+pub struct CountingReader<R> {
+    inner: R,
+    read: Rc<Cell<u64>>,
+}
+
+impl Checkpointer {
+    /// Checkpoints written every `interval` seconds, none without interval.
+    ///
+    /// With `resume`, the checkpoint of the previous run is loaded and must match the command.
+    /// The HEVC input, the first of `inputs`, must then be read from `resumed_input_offset`.
+    /// Returns the writers of the outputs, in the same order.
+    pub fn new(
+        command: &str,
+        settings: &str,
+        inputs: &[&Path],
+        outputs: &[&Path],
+        interval: Option<u64>,
+        resume: bool,
+    ) -> Result<(Self, Vec<CheckpointWriter>)> {
+        ensure!(!outputs.is_empty(), "No output to checkpoint");
+
+        let path = checkpoint_path(outputs[0]);
+
+        let mut checkpoint = Checkpoint {
+            version: CHECKPOINT_VERSION,
+            command: command.to_owned(),
+            settings: settings_hash(settings),
+            inputs: inputs
+                .iter()
+                .map(|input| CheckpointInput::new(input))
+                .collect::<Result<_>>()?,
+            input_offset: 0,
+            frames_done: 0,
+            outputs: outputs
+                .iter()
+                .map(|output| CheckpointOutput {
+                    path: output.to_path_buf(),
+                    offset: 0,
+                })
+                .collect(),
+        };
+
+        let resumed = if resume {
+            let resumed = read_checkpoint(&path)?;
+            resumed.check_matches(&checkpoint)?;
+
+            ipc::log(format!(
+                "Resuming from frame {}, {} bytes of the input already processed",
+                resumed.frames_done, resumed.input_offset
+            ));
+
+            Some(resumed)
+        } else {
+            None
+        };
+
+        let mut written = Vec::with_capacity(outputs.len());
+        let mut writers = Vec::with_capacity(outputs.len());
+
+        for (i, output) in outputs.iter().enumerate() {
+            let resume_offset = resumed.as_ref().map_or(0, |c| c.outputs[i].offset);
+
+            let file = if resumed.is_some() {
+                let file = OpenOptions::new().write(true).open(output)?;
+                let size = file.metadata()?.len();
+
+                ensure!(
+                    size >= resume_offset,
+                    "Output {} is smaller than at the checkpoint. Run again without --resume",
+                    output.display()
+                );
+
+                // Drops what was written after the checkpoint
+                file.set_len(resume_offset)?;
+
+                OpenOptions::new().append(true).open(output)?
+            } else {
+                File::create(output)?
+            };
+
+            let counter = Rc::new(Cell::new(resume_offset));
+            written.push(counter.clone());
+
+            writers.push(CheckpointWriter {
+                writer: BufWriter::with_capacity(100_000, file),
+                written: counter,
+            });
+        }
+
+        let resumed_offset = resumed.as_ref().map(|resumed| resumed.input_offset);
+
+        if let Some(resumed) = resumed {
+            checkpoint.input_offset = resumed.input_offset;
+            checkpoint.frames_done = resumed.frames_done;
+        }
+
+        let checkpointer = Self {
+            path,
+            interval: interval.map(Duration::from_secs),
+            last_write: Instant::now(),
+            input_read: Rc::new(Cell::new(checkpoint.input_offset)),
+            checkpoint,
+            written,
+            boundary: None,
+            resumed_offset,
+        };
+
+        Ok((checkpointer, writers))
+    }
+
+    /// Reader of the HEVC input, counting the bytes read.
+    ///
+    /// `inner` must already be at `resumed_input_offset` when resuming.
+    pub fn input_reader<R: BufRead>(&self, inner: R) -> CountingReader<R> {
+        CountingReader {
+            inner,
+            read: self.input_read.clone(),
+        }
+    }
+
+    /// Input offset to resume from, when resuming
+    pub fn resumed_input_offset(&self) -> Option<u64> {
+        self.resumed_offset
+    }
+
+    /// Access units written, including the ones of the resumed run
+    pub fn frames_done(&self) -> usize {
+        self.checkpoint.frames_done
+    }
+
+    /// Offset in the input of the bytes following the ones read
+    pub fn input_position(&self) -> u64 {
+        self.input_read.get()
+    }
+
+    /// Called once an access unit is completely written,
+    /// `next_au_offset` being the input offset of the following one.
+    pub fn frame_done(&mut self, next_au_offset: u64) {
+        self.checkpoint.frames_done += 1;
+
+        self.boundary = Some(Boundary {
+            input_offset: next_au_offset,
+            frames_done: self.checkpoint.frames_done,
+            outputs: self.written.iter().map(|written| written.get()).collect(),
+        });
+    }
+
+    /// Writes a checkpoint at the last access unit boundary, once the interval elapsed.
+    ///
+    /// The input must be processable from the boundary, without the state of the previous frames.
+    /// `flush` flushes the writers, before the checkpoint is written.
+    pub fn checkpoint<F>(&mut self, flush: F) -> Result<()>
+    where
+        F: FnOnce() -> io::Result<()>,
+    {
+        let elapsed = self
+            .interval
+            .map_or(false, |interval| self.last_write.elapsed() >= interval);
+
+        if let Some(boundary) = self.boundary.take().filter(|_| elapsed) {
+            flush()?;
+            self.write(boundary)?;
+
+            self.last_write = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// Removes the checkpoint once the outputs are complete
+    pub fn finish(self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, boundary: Boundary) -> Result<()> {
+        // The checkpoint must not reference data lost on power loss
+        for output in &self.checkpoint.outputs {
+            OpenOptions::new()
+                .write(true)
+                .open(&output.path)?
+                .sync_data()?;
+        }
+
+        self.checkpoint.input_offset = boundary.input_offset;
+        self.checkpoint.frames_done = boundary.frames_done;
+
+        for (output, offset) in self.checkpoint.outputs.iter_mut().zip(boundary.outputs) {
+            output.offset = offset;
+        }
+
+        // Written to a temporary file first, the checkpoint is never left truncated
+        let temp_path = self.path.with_extension("json.tmp");
+
+        let mut file = File::create(&temp_path)?;
+        serde_json::to_writer_pretty(&mut file, &self.checkpoint)?;
+        file.sync_data()?;
+
+        fs::rename(&temp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+impl Checkpoint {
+    fn check_matches(&self, current: &Checkpoint) -> Result<()> {
+        ensure!(
+            self.version == CHECKPOINT_VERSION,
+            "Unsupported checkpoint version {}",
+            self.version
+        );
+        ensure!(
+            self.command == current.command,
+            "The checkpoint is for the {} command",
+            self.command
+        );
+        ensure!(
+            self.settings == current.settings,
+            "The options differ from the checkpointed run"
+        );
+        ensure!(
+            self.inputs == current.inputs,
+            "The inputs changed since the checkpoint"
+        );
+
+        let same_outputs = self.outputs.len() == current.outputs.len()
+            && self
+                .outputs
+                .iter()
+                .zip(&current.outputs)
+                .all(|(a, b)| a.path == b.path);
+        ensure!(same_outputs, "The outputs differ from the checkpointed run");
+
+        Ok(())
+    }
+}
+
+impl CheckpointInput {
+    fn new(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+impl Write for CheckpointWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.written.set(self.written.get() + written as u64);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read.set(self.read.get() + read as u64);
+
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.read.set(self.read.get() + amt as u64);
+    }
+}
+
+/// `<output>.checkpoint.json`, next to the first output
+fn checkpoint_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".checkpoint.json");
+
+    PathBuf::from(name)
+}
+
+/// Options of `CliOptions` affecting the outputs, followed by the command specific ones
+pub fn cli_settings(options: &CliOptions, command_settings: &str) -> Result<String> {
+    // The maps of the edit config have no stable order
+    let edit_config = match &options.edit_config {
+        Some(config) => sorted_json(serde_json::to_value(config)?).to_string(),
+        None => String::new(),
+    };
+
+    let options = CliOptions {
+        edit_config: None,
+        summary_json: None,
+        ..options.clone()
+    };
+
+    Ok(format!("{options:?} {edit_config} {command_settings}"))
+}
+
+fn read_checkpoint(path: &Path) -> Result<Checkpoint> {
+    if !path.is_file() {
+        bail!(
+            "No checkpoint found at {}, nothing to resume",
+            path.display()
+        );
+    }
+
+    let reader = BufReader::new(File::open(path)?);
+
+    match serde_json::from_reader(reader) {
+        Ok(checkpoint) => Ok(checkpoint),
+        Err(e) => bail!("Invalid checkpoint file {}: {e}", path.display()),
+    }
+}
+
+/// 64-bit FNV-1a, stable across builds unlike `DefaultHasher`
+fn settings_hash(settings: &str) -> String {
+    let hash = settings
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+
+    format!("{hash:016x}")
+}
+
+fn sorted_json(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sorted_json(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sorted_json).collect()),
+        value => value,
+    }
+}
@@ -1,9 +1,16 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 use indicatif::ProgressBar;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::commands::ConvertArgs;
+use dolby_vision::rpu::profiles::RpuProfile;
+use dolby_vision::rpu::rpu_data_nlq::DoviELType;
+use dolby_vision::rpu::utils::scan_profiles;
+use dolby_vision::rpu::vdr_dm_data::CmVersion;
 
+use crate::commands::{ConversionModeCli, ConvertArgs};
+use crate::ipc;
+
+use super::telemetry::ConversionTelemetry;
 use super::{general_read_write, input_from_either, CliOptions, IoFormat};
 
 use general_read_write::{DoviProcessor, DoviWriter};
@@ -21,13 +28,34 @@ impl Converter {
             input_pos,
             output,
             discard,
+            repeat_metadata,
         } = args;
 
-        options.discard_el = discard;
-
         let input = input_from_either("convert", input, input_pos)?;
         let format = hevc_parser::io::format_from_path(&input)?;
 
+        if let Some(ConversionModeCli::Auto) = options.mode {
+            if let IoFormat::RawStdin = format {
+                bail!("Converter: --mode auto requires an input file, the piped input can't be scanned");
+            }
+
+            options.mode = Some(auto_conversion_mode(&input)?);
+        }
+
+        options.discard_el = discard;
+
+        if let Some(cadence) = repeat_metadata {
+            ensure!(
+                cadence > 0,
+                "Converter: repeat metadata cadence must be at least 1"
+            );
+
+            ipc::warn(
+                "Omitting repeated metadata is not supported by all players, the output may not play back correctly."
+            );
+            options.repeat_metadata = Some(cadence);
+        }
+
         let output = match output {
             Some(path) => path,
             None => match options.discard_el {
@@ -58,9 +86,88 @@ impl Converter {
     }
 
     fn convert_raw_hevc(&self, pb: ProgressBar, options: CliOptions) -> Result<()> {
-        let dovi_writer = DoviWriter::new(None, None, None, Some(&self.output));
-        let mut dovi_processor = DoviProcessor::new(options, self.input.clone(), dovi_writer, pb);
+        let dovi_writer = if options.dry_run {
+            ipc::log(format!(
+                "Dry run, {} will not be written",
+                self.output.display()
+            ));
+            DoviWriter::single_layer_sink()
+        } else {
+            DoviWriter::new(None, None, None, Some(&self.output))
+        };
+
+        let telemetry = ConversionTelemetry::new("convert");
+        let mut dovi_processor =
+            DoviProcessor::new(options, self.input.clone(), dovi_writer, pb, telemetry);
 
         dovi_processor.read_write_from_io(&self.format)
     }
 }
+
+/// Mode converting the input to profile 8.1, from its profile, EL type and CM version
+fn auto_conversion_mode(input: &Path) -> Result<ConversionModeCli> {
+    ipc::log(format!("Scanning {} for --mode auto...", input.display()));
+    let scan = scan_profiles(input)?;
+
+    let profiles: Vec<String> = scan.profiles.iter().map(|(p, _)| p.to_string()).collect();
+    let &[(profile, _)] = scan.profiles.as_slice() else {
+        bail!(
+            "Converter: --mode auto requires a single profile, found profiles {}. Set the mode manually",
+            profiles.join(", ")
+        );
+    };
+
+    let el_type = match scan.el_types.as_slice() {
+        [] => None,
+        [el_type] => Some(*el_type),
+        _ => bail!("Converter: --mode auto requires a single EL type, found both FEL and MEL. Set the mode manually"),
+    };
+
+    let (mode, reason) = match RpuProfile::new(profile, el_type) {
+        RpuProfile::Profile5 => (
+            ConversionModeCli::Profile5To81,
+            "profile 5 RPU converted to profile 8.1",
+        ),
+        RpuProfile::Profile7 {
+            el_type: Some(DoviELType::FEL),
+        } => (
+            ConversionModeCli::To81,
+            "the mapping is removed, as the FEL residual is lost without the EL",
+        ),
+        RpuProfile::Profile7 {
+            el_type: Some(DoviELType::MEL),
+        } => (
+            ConversionModeCli::To81MappingPreserved,
+            "the mapping is preserved, as the MEL carries no residual",
+        ),
+        RpuProfile::Profile8 => (
+            ConversionModeCli::Lossless,
+            "already single layer profile 8, the RPU is rewritten untouched",
+        ),
+        _ => bail!("Converter: --mode auto can't convert profile {profile} to profile 8.1"),
+    };
+
+    let cm_versions: Vec<&str> = scan
+        .cm_versions
+        .iter()
+        .map(|cm_version| match cm_version {
+            CmVersion::V29 => "CM v2.9",
+            CmVersion::V40 => "CM v4.0",
+        })
+        .collect();
+
+    let source = match el_type {
+        Some(el_type) => format!("profile {profile} {el_type}"),
+        None => format!("profile {profile}"),
+    };
+
+    // The CM version is kept by every conversion
+    ipc::log(format!(
+        "{}: {source}, {} -> mode {}, {reason}",
+        input.display(),
+        cm_versions.join(" + "),
+        mode as u8
+    ));
+
+    Ok(mode)
+}
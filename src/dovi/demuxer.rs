@@ -1,9 +1,12 @@
 use anyhow::{bail, Result};
 use indicatif::ProgressBar;
+use std::io::Write;
 use std::path::PathBuf;
 
 use crate::commands::DemuxArgs;
 
+use super::checkpoint::{cli_settings, Checkpointer};
+use super::telemetry::ConversionTelemetry;
 use super::{general_read_write, input_from_either, CliOptions, IoFormat};
 
 use general_read_write::{DoviProcessor, DoviWriter};
@@ -14,6 +17,8 @@ pub struct Demuxer {
     bl_out: PathBuf,
     el_out: PathBuf,
     el_only: bool,
+    resume: bool,
+    checkpoint_interval: Option<u64>,
 }
 
 impl Demuxer {
@@ -24,6 +29,8 @@ impl Demuxer {
             bl_out,
             el_out,
             el_only,
+            resume,
+            checkpoint_interval,
         } = args;
 
         let input = input_from_either("demux", input, input_pos)?;
@@ -45,6 +52,8 @@ impl Demuxer {
             bl_out,
             el_out,
             el_only,
+            resume,
+            checkpoint_interval,
         })
     }
 
@@ -69,8 +78,59 @@ impl Demuxer {
             Some(self.bl_out.as_path())
         };
 
-        let dovi_writer = DoviWriter::new(bl_out, Some(self.el_out.as_path()), None, None);
-        let mut dovi_processor = DoviProcessor::new(options, self.input.clone(), dovi_writer, pb);
+        // Only file inputs can be read again to resume
+        let checkpointer = if self.resume || self.checkpoint_interval.is_some() {
+            match self.format {
+                IoFormat::Raw => {
+                    let settings = cli_settings(&options, &format!("el_only: {}", self.el_only))?;
+                    let outputs: Vec<_> = bl_out
+                        .into_iter()
+                        .chain(Some(self.el_out.as_path()))
+                        .collect();
+
+                    Some(Checkpointer::new(
+                        "demux",
+                        &settings,
+                        &[self.input.as_path()],
+                        &outputs,
+                        self.checkpoint_interval,
+                        self.resume,
+                    )?)
+                }
+                _ if self.resume => bail!("Demuxer: --resume requires an input file"),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        let (dovi_writer, checkpointer) = match checkpointer {
+            Some((checkpointer, writers)) => {
+                let mut writers = writers
+                    .into_iter()
+                    .map(|writer| Box::new(writer) as Box<dyn Write>);
+
+                let bl_writer = bl_out.and_then(|_| writers.next());
+                let el_writer = writers.next().unwrap();
+
+                (
+                    DoviWriter::demux_writers(bl_writer, el_writer),
+                    Some(checkpointer),
+                )
+            }
+            None => (
+                DoviWriter::new(bl_out, Some(self.el_out.as_path()), None, None),
+                None,
+            ),
+        };
+
+        let telemetry = ConversionTelemetry::new("demux");
+        let mut dovi_processor =
+            DoviProcessor::new(options, self.input.clone(), dovi_writer, pb, telemetry);
+
+        if let Some(checkpointer) = checkpointer {
+            dovi_processor.set_checkpointer(checkpointer);
+        }
 
         dovi_processor.read_write_from_io(&self.format)
     }
@@ -1,27 +1,49 @@
-use std::fs::File;
 use std::io::{stdout, Write};
 use std::path::Path;
 use std::{collections::HashMap, path::PathBuf};
 
-use anyhow::{bail, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use serde::{Deserialize, Serialize};
 
 use dolby_vision::rpu::extension_metadata::blocks::{
-    ExtMetadataBlock, ExtMetadataBlockLevel11, ExtMetadataBlockLevel255, ExtMetadataBlockLevel5,
-    ExtMetadataBlockLevel6, ExtMetadataBlockLevel9,
+    ExtMetadataBlock, ExtMetadataBlockLevel1, ExtMetadataBlockLevel11, ExtMetadataBlockLevel255,
+    ExtMetadataBlockLevel5, ExtMetadataBlockLevel6, ExtMetadataBlockLevel9,
 };
 use dolby_vision::rpu::extension_metadata::MasteringDisplayPrimaries;
 use dolby_vision::rpu::generate::GenerateConfig;
+use dolby_vision::rpu::rpu_data_header::RpuDataHeader;
+use dolby_vision::rpu::vdr_dm_data::VdrDmData;
 
-use dolby_vision::rpu::utils::parse_rpu_file;
+use dolby_vision::rpu::utils::{parse_rpu_file, parse_rpu_file_with_opts};
+use dolby_vision::utils::timecode_to_frames;
 
-use super::{input_from_either, write_rpu_file, DoviRpu};
-use crate::commands::EditorArgs;
+use super::bar_detection;
+use super::telemetry::{ConversionTelemetry, RpuSnapshot};
+use super::{input_from_either, json_file_reader, write_rpu_file, CliOptions, DoviRpu};
+use crate::commands::{EditorArgs, TranscodeMetadataArgs};
+use crate::ipc;
+
+/// Bar detection jitter ignored by default, in pixels
+const DEFAULT_JITTER_TOLERANCE: u16 = 2;
+
+/// `vdr_rpu_id` and `prev_vdr_rpu_id` range
+const MAX_VDR_RPU_ID: u64 = 15;
+
+/// Coefficients per MMR order
+const MMR_COEFFS: usize = 7;
+
+/// Template variables of the config and output path, expanded for every input file
+const TEMPLATE_VARIABLES: &[&str] = &["input_stem", "input_name", "input_dir", "frame_count"];
 
 pub struct Editor {
-    input: PathBuf,
-    json_file: PathBuf,
-    rpu_out: PathBuf,
+    inputs: Vec<PathBuf>,
+    json_file: Option<PathBuf>,
+    // Can contain template variables
+    rpu_out: Option<PathBuf>,
+
+    // Set from the command line, in addition to the JSON config
+    mode: Option<u8>,
+    drop_levels: Vec<u8>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -30,12 +52,34 @@ pub struct EditConfig {
     #[serde(default)]
     mode: u8,
 
+    // Frame rate for the timecode and seconds frame positions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fps: Option<f64>,
+
     #[serde(default)]
     remove_cmv4: bool,
 
+    // Keeps CM v4.0 only, removing the CM v2.9 trims
+    #[serde(default)]
+    remove_cmv29: bool,
+
     #[serde(default)]
     remove_mapping: bool,
 
+    #[serde(default)]
+    fix_l1_stats: bool,
+
+    // BL bit depth to signal, matching the HEVC SPS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bl_bit_depth: Option<u8>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    header_edits: Option<Vec<HeaderEdit>>,
+
+    // Chroma MMR mapping pieces to replace, for specific frames
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mmr_edits: Option<Vec<MmrEdit>>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     min_pq: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -50,12 +94,34 @@ pub struct EditConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     duplicate: Option<Vec<DuplicateMetadata>>,
 
+    // Signed number of frames to shift the metadata by, padding or trimming the head
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<i64>,
+
+    // Black frames added for leaders/trailers, applied last
+    #[serde(skip_serializing_if = "Option::is_none")]
+    insert_black: Option<Vec<BlackFrames>>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     scene_cuts: Option<HashMap<String, bool>>,
 
+    // Frame metadata held over ranges, after the other edits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    freeze: Option<Vec<FreezeMetadata>>,
+
+    // Signed number of frames to move every scene cut by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scene_cut_offset: Option<i64>,
+
+    // Two output frames per input frame, for frame-doubled or bob-deinterlaced content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame_doubling: Option<FrameDoubling>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     level6: Option<ExtMetadataBlockLevel6>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    level8_vectors: Option<Vec<Level8VectorEdit>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     level9: Option<MasteringDisplayPrimaries>,
     #[serde(skip_serializing_if = "Option::is_none")]
     level11: Option<ExtMetadataBlockLevel11>,
@@ -66,6 +132,10 @@ pub struct EditConfig {
     source_rpu: Option<PathBuf>,
     #[serde(skip_serializing_if = "Option::is_none")]
     rpu_levels: Option<Vec<u8>>,
+
+    // Metadata levels to remove, after the other edits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drop_levels: Option<Vec<u8>>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -77,11 +147,22 @@ pub struct ActiveArea {
     #[serde(skip_serializing_if = "Option::is_none")]
     drop_l5: Option<String>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drop_l5_below: Option<u16>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     presets: Option<Vec<ActiveAreaOffsets>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     edits: Option<HashMap<String, u16>>,
+
+    // Bar detection stats JSON to generate the L5 metadata from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bar_detection: Option<PathBuf>,
+
+    // Pixels of bar detection jitter to ignore
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jitter_tolerance: Option<u16>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -102,52 +183,242 @@ pub struct DuplicateMetadata {
     length: usize,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FreezeMetadata {
+    // Frame whose DM metadata is copied
+    source: usize,
+    // Frame range, single frame or "all"
+    range: String,
+    #[serde(default)]
+    scene_cuts: FreezeSceneCuts,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FreezeSceneCuts {
+    /// The frames keep their own scene cut flag
+    #[default]
+    Keep,
+    /// The frames use the scene cut flag of the source frame
+    Source,
+    /// The range becomes a single shot, followed by a scene cut
+    Shot,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BlackFrames {
+    offset: usize,
+    length: usize,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Level8VectorEdit {
+    // All L8 blocks when not specified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_display_index: Option<u8>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    saturation: HashMap<String, VectorValue>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    hue: HashMap<String, VectorValue>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameDoubling {
+    /// The added frames are copies of the previous frame
+    Duplicate,
+    /// The added frames use the L1 midpoint of the surrounding frames, within a shot
+    Interpolate,
+}
+
+/// Absolute value, or relative adjustment as a string like "+4" or "-2"
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum VectorValue {
+    Absolute(u8),
+    Relative(String),
+}
+
+/// MMR piece replacing the parsed mapping, same fields as the export for a single piece
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MmrEdit {
+    // Frame range, single frame or "all"
+    range: String,
+    // Chroma component, 1 or 2
+    component: usize,
+    // Piece index, between two pivots
+    #[serde(default)]
+    piece: usize,
+
+    mmr_order_minus1: u8,
+    #[serde(default)]
+    mmr_constant_int: i64,
+    mmr_constant: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    mmr_coef_int: Vec<Vec<i64>>,
+    mmr_coef: Vec<Vec<u64>>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderEdit {
+    #[serde(default)]
+    conditions: HashMap<String, u64>,
+    set: HashMap<String, u64>,
+}
+
 impl Editor {
     pub fn from_args(args: EditorArgs) -> Result<Self> {
         let EditorArgs {
             input,
             input_pos,
+            batch,
             json_file,
             rpu_out,
         } = args;
 
-        let input = input_from_either("editor", input, input_pos)?;
-
-        let out_path = if let Some(out_path) = rpu_out {
-            out_path
+        let inputs = if batch.is_empty() {
+            vec![input_from_either("editor", input, input_pos)?]
         } else {
-            PathBuf::from(format!(
-                "{}{}",
-                input.file_stem().unwrap().to_str().unwrap(),
-                "_modified.bin"
-            ))
+            batch
         };
 
+        if let Some(rpu_out) = &rpu_out {
+            ensure!(
+                inputs.len() == 1 || rpu_out.to_string_lossy().contains("${"),
+                "The output must contain a template variable like ${{input_stem}} when editing multiple files"
+            );
+        }
+
         Ok(Self {
+            inputs,
+            json_file: Some(json_file),
+            rpu_out,
+            mode: None,
+            drop_levels: Vec::new(),
+        })
+    }
+
+    /// Conversion, edits and level filters in a single pass over the RPUs
+    pub fn transcode(args: TranscodeMetadataArgs, options: CliOptions) -> Result<()> {
+        let TranscodeMetadataArgs {
             input,
+            input_pos,
             json_file,
-            rpu_out: out_path,
-        })
+            drop_levels,
+            rpu_out,
+        } = args;
+
+        let editor = Self {
+            inputs: vec![input_from_either("transcode-metadata", input, input_pos)?],
+            json_file,
+            rpu_out,
+            mode: options.mode.map(|mode| mode as u8),
+            drop_levels,
+        };
+
+        editor.edit_file(&editor.inputs[0], &options)
     }
 
-    pub fn edit(args: EditorArgs) -> Result<()> {
+    pub fn edit(args: EditorArgs, options: CliOptions) -> Result<()> {
         let editor = Editor::from_args(args)?;
+        let batch = editor.inputs.len() > 1;
+
+        for input in &editor.inputs {
+            if batch {
+                ipc::log(format!("\nEditing {}...", input.display()));
+            }
 
-        let mut config: EditConfig = EditConfig::from_path(&editor.json_file)?;
+            editor.edit_file(input, &options)?;
+        }
 
-        println!("EditConfig {}", serde_json::to_string_pretty(&config)?);
+        Ok(())
+    }
 
-        println!("Parsing RPU file...");
+    fn edit_file(&self, input: &Path, options: &CliOptions) -> Result<()> {
+        ipc::log("Parsing RPU file...");
         stdout().flush().ok();
 
-        let mut rpus: Vec<Option<DoviRpu>> = parse_rpu_file(&editor.input)?
+        let mut rpus: Vec<Option<DoviRpu>> = parse_rpu_file_with_opts(input, options.parse_opts)?
             .into_iter()
             .map(Some)
             .collect();
 
+        let variables = template_variables(input, rpus.len())?;
+
+        let mut config = match &self.json_file {
+            Some(json_file) => EditConfig::for_input(json_file, &variables, rpus.len())?,
+            None => EditConfig::default(),
+        };
+
+        if let Some(mode) = self.mode {
+            ensure!(
+                config.mode == 0 || config.mode == mode,
+                "Conflicting conversion modes: {} in the edit config, {} from --mode",
+                config.mode,
+                mode
+            );
+
+            config.mode = mode;
+        }
+
+        if !self.drop_levels.is_empty() {
+            config
+                .drop_levels
+                .get_or_insert_with(Vec::new)
+                .extend(&self.drop_levels);
+        }
+
+        ipc::log(format!(
+            "EditConfig {}",
+            serde_json::to_string_pretty(&config)?
+        ));
+
+        let rpu_out = match &self.rpu_out {
+            Some(template) => {
+                PathBuf::from(expand_template(&template.to_string_lossy(), &variables)?)
+            }
+            None => PathBuf::from(format!("{}_modified.bin", variables[0].1)),
+        };
+
+        // Original RPUs, to report the changes of a dry run
+        let originals = if options.dry_run {
+            rpus.iter()
+                .flatten()
+                .map(|rpu| Ok((RpuSnapshot::new(rpu), rpu.write_hevc_unspec62_nalu()?)))
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+
         config.execute(&mut rpus)?;
 
-        let mut data = GenerateConfig::encode_option_rpus(&mut rpus);
+        let mut edited = config
+            .frame_doubling
+            .map(|frame_doubling| EditConfig::double_frames(&rpus, frame_doubling));
+
+        if let Some(offset) = config.offset {
+            let frames = edited.get_or_insert_with(|| rpus.clone());
+            EditConfig::offset_frames(frames, offset)?;
+        }
+
+        if let Some(to_insert) = config.insert_black.as_mut() {
+            to_insert.sort_by_key(|black| black.offset);
+            to_insert.reverse();
+
+            let frames = edited.get_or_insert_with(|| rpus.clone());
+            EditConfig::insert_black_frames(to_insert, frames)?;
+        }
+
+        let mut data = GenerateConfig::encode_option_rpus(match edited.as_mut() {
+            Some(edited) => edited,
+            None => &mut rpus,
+        });
 
         if let Some(to_duplicate) = config.duplicate.as_mut() {
             to_duplicate.sort_by_key(|meta| meta.offset);
@@ -158,20 +429,309 @@ impl Editor {
             config.duplicate_metadata(to_duplicate, &mut data)?;
         }
 
-        println!("Final metadata length: {}", data.len());
+        ipc::log(format!("Final metadata length: {}", data.len()));
 
-        write_rpu_file(&editor.rpu_out, data)?;
+        if options.dry_run {
+            return report_dry_run(&originals, &rpus, data.len(), options.summary_json.clone());
+        }
+
+        write_rpu_file(&rpu_out, data)?;
 
         Ok(())
     }
 }
 
+/// Values of the `TEMPLATE_VARIABLES` for an input RPU file
+fn template_variables(input: &Path, frame_count: usize) -> Result<Vec<(&'static str, String)>> {
+    let file_str = |s: Option<&std::ffi::OsStr>| {
+        s.and_then(|s| s.to_str())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Invalid input file name {}", input.display()))
+    };
+
+    let input_dir = match input.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().into_owned(),
+        _ => String::from("."),
+    };
+
+    let values = [
+        file_str(input.file_stem())?,
+        file_str(input.file_name())?,
+        input_dir,
+        frame_count.to_string(),
+    ];
+
+    Ok(TEMPLATE_VARIABLES.iter().copied().zip(values).collect())
+}
+
+/// Replaces the `${name}` variables in `text`
+fn expand_template(text: &str, variables: &[(&str, String)]) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+
+        let Some(end) = rest[start..].find('}') else {
+            bail!("Unterminated template variable in '{text}'");
+        };
+
+        let name = &rest[start + 2..start + end];
+        let Some((_, value)) = variables.iter().find(|(var, _)| *var == name) else {
+            bail!(
+                "Unknown template variable ${{{name}}}, available: {}",
+                TEMPLATE_VARIABLES.join(", ")
+            );
+        };
+
+        expanded.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// Expands the template variables in every string and object key of the config
+fn expand_json_templates(
+    value: &mut serde_json::Value,
+    variables: &[(&str, String)],
+) -> Result<()> {
+    match value {
+        serde_json::Value::String(s) => *s = expand_template(s, variables)?,
+        serde_json::Value::Array(values) => {
+            for v in values {
+                expand_json_templates(v, variables)?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (key, mut v) in std::mem::take(map) {
+                expand_json_templates(&mut v, variables)?;
+                map.insert(expand_template(&key, variables)?, v);
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+/// Prints the frames touched by the edits, instead of writing the RPU file
+fn report_dry_run(
+    originals: &[(RpuSnapshot, Vec<u8>)],
+    rpus: &[Option<DoviRpu>],
+    final_length: usize,
+    summary_json: Option<PathBuf>,
+) -> Result<()> {
+    let mut telemetry = ConversionTelemetry::new("editor");
+
+    let mut modified = 0;
+    let mut removed = 0;
+
+    for ((snapshot, original_data), rpu) in originals.iter().zip(rpus) {
+        if let Some(rpu) = rpu {
+            if rpu.write_hevc_unspec62_nalu()? != *original_data {
+                modified += 1;
+            }
+
+            telemetry.record_conversion(snapshot, rpu);
+        } else {
+            removed += 1;
+        }
+    }
+
+    let duplicated = (final_length + removed).saturating_sub(originals.len());
+
+    ipc::log(format!(
+        "Dry run, no RPU file written:\
+         \n  Frames modified: {modified}\
+         \n  Frames removed: {removed}\
+         \n  Frames duplicated: {duplicated}"
+    ));
+
+    telemetry.finish(summary_json)
+}
+
 impl EditConfig {
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let json_file = File::open(path)?;
-        let config: EditConfig = serde_json::from_reader(&json_file)?;
+        let json_file = json_file_reader(path)?;
+        let mut value: serde_json::Value = serde_json::from_reader(json_file)?;
+
+        Self::convert_times(&mut value, None)?;
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Config for an input RPU file of `frame_count` frames.
+    ///
+    /// The template `variables` are expanded, and the percentage positions converted to frames.
+    fn for_input<P: AsRef<Path>>(
+        path: P,
+        variables: &[(&str, String)],
+        frame_count: usize,
+    ) -> Result<Self> {
+        let json_file = json_file_reader(path)?;
+        let mut value: serde_json::Value = serde_json::from_reader(json_file)?;
+
+        expand_json_templates(&mut value, variables)?;
+        Self::convert_times(&mut value, Some(frame_count))?;
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Converts the timecodes, seconds and percentages in the frame positions to frame indices
+    fn convert_times(config: &mut serde_json::Value, frame_count: Option<usize>) -> Result<()> {
+        let fps = config.get("fps").and_then(serde_json::Value::as_f64);
+
+        if let Some(ranges) = config.get_mut("remove").and_then(|v| v.as_array_mut()) {
+            for range in ranges {
+                if let Some(range_str) = range.as_str() {
+                    *range = Self::convert_range(range_str, fps, frame_count)?.into();
+                }
+            }
+        }
+
+        Self::convert_range_keys(config.get_mut("scene_cuts"), fps, frame_count)?;
+
+        if let Some(edits) = config.get_mut("mmr_edits").and_then(|v| v.as_array_mut()) {
+            for edit in edits {
+                if let Some(serde_json::Value::String(range)) = edit.get("range") {
+                    edit["range"] = Self::convert_range(range, fps, frame_count)?.into();
+                }
+            }
+        }
+        Self::convert_range_keys(config.pointer_mut("/active_area/edits"), fps, frame_count)?;
+
+        if let Some(freezes) = config.get_mut("freeze").and_then(|v| v.as_array_mut()) {
+            for freeze in freezes {
+                if let Some(serde_json::Value::String(range)) = freeze.get("range") {
+                    freeze["range"] = Self::convert_range(range, fps, frame_count)?.into();
+                }
+                if let Some(serde_json::Value::String(position)) = freeze.get("source") {
+                    freeze["source"] = Self::frame_from_str(position, fps, frame_count)?.into();
+                }
+            }
+        }
+
+        if let Some(duplicates) = config.get_mut("duplicate").and_then(|v| v.as_array_mut()) {
+            for duplicate in duplicates {
+                for key in ["source", "offset", "length"] {
+                    if let Some(serde_json::Value::String(position)) = duplicate.get(key) {
+                        let frame = Self::frame_from_str(position, fps, frame_count)?;
+                        duplicate[key] = frame.into();
+                    }
+                }
+            }
+        }
+
+        if let Some(inserts) = config
+            .get_mut("insert_black")
+            .and_then(|v| v.as_array_mut())
+        {
+            for insert in inserts {
+                for key in ["offset", "length"] {
+                    if let Some(serde_json::Value::String(position)) = insert.get(key) {
+                        let frame = Self::frame_from_str(position, fps, frame_count)?;
+                        insert[key] = frame.into();
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn convert_range_keys(
+        edits: Option<&mut serde_json::Value>,
+        fps: Option<f64>,
+        frame_count: Option<usize>,
+    ) -> Result<()> {
+        if let Some(serde_json::Value::Object(edits)) = edits {
+            for (range, value) in std::mem::take(edits) {
+                let range = Self::convert_range(&range, fps, frame_count)?;
+
+                ensure!(
+                    !edits.contains_key(&range),
+                    "Range {range} is specified more than once"
+                );
+                edits.insert(range, value);
+            }
+        }
 
-        Ok(config)
+        Ok(())
+    }
+
+    /// Range of frames, as `start-end` or a single position
+    fn convert_range(range: &str, fps: Option<f64>, frame_count: Option<usize>) -> Result<String> {
+        if range.eq_ignore_ascii_case("all") {
+            return Ok(range.to_string());
+        }
+
+        if let Some((start, end)) = range.split_once('-') {
+            let start = Self::frame_from_str(start, fps, frame_count)?;
+            let end = Self::frame_from_str(end, fps, frame_count)?;
+            ensure!(start <= end, "Invalid range {range}: start is after end");
+
+            Ok(format!("{start}-{end}"))
+        } else {
+            Ok(Self::frame_from_str(range, fps, frame_count)?.to_string())
+        }
+    }
+
+    /// Frame index, `HH:MM:SS:FF` timecode, seconds with a `s` suffix (e.g. `12.5s`),
+    /// or percentage of the RPU length with a `%` suffix (e.g. `90%`).
+    ///
+    /// Times require `fps`, and seconds must fall exactly on a frame.
+    /// Percentages are relative to the last frame, rounded to the nearest frame.
+    fn frame_from_str(
+        position: &str,
+        fps: Option<f64>,
+        frame_count: Option<usize>,
+    ) -> Result<usize> {
+        let position = position.trim();
+
+        if let Ok(frame) = position.parse::<usize>() {
+            return Ok(frame);
+        }
+
+        if let Some(percent) = position.strip_suffix('%') {
+            let percent = percent
+                .parse::<f64>()
+                .ok()
+                .filter(|p| (0.0..=100.0).contains(p))
+                .ok_or_else(|| anyhow!("Invalid percentage '{position}', must be 0-100%"))?;
+            let frame_count = frame_count.ok_or_else(|| {
+                anyhow!("Percentage '{position}' requires the RPU length, only known by the editor")
+            })?;
+
+            let last_frame = frame_count.saturating_sub(1) as f64;
+
+            return Ok((percent / 100.0 * last_frame).round() as usize);
+        }
+
+        let seconds = position
+            .strip_suffix('s')
+            .and_then(|s| s.parse::<f64>().ok());
+        ensure!(
+            position.contains(':') || seconds.is_some(),
+            "Invalid frame position '{position}'"
+        );
+
+        let fps = fps.ok_or_else(|| anyhow!("Time '{position}' requires `fps` to be set"))?;
+
+        if let Some(seconds) = seconds {
+            let frames = seconds * fps;
+
+            ensure!(
+                frames >= 0.0 && (frames - frames.round()).abs() < 1e-3,
+                "Ambiguous time '{position}': {frames:.3} frames at {fps} fps, not on a frame boundary"
+            );
+
+            Ok(frames.round() as usize)
+        } else {
+            timecode_to_frames(position, fps)
+        }
     }
 
     fn execute(&self, rpus: &mut [Option<DoviRpu>]) -> Result<()> {
@@ -180,33 +740,71 @@ impl EditConfig {
             self.remove_frames(ranges, rpus)?;
         }
 
+        if self.header_edits.is_some() {
+            ipc::log("Editing RPU headers...");
+        }
+
         if self.remove_cmv4 {
-            println!("Removing CMv4.0 metadata...");
+            ipc::log("Removing CMv4.0 metadata...");
+        }
+
+        if self.remove_cmv29 {
+            ensure!(
+                !self.remove_cmv4,
+                "remove_cmv4 and remove_cmv29 can't be used together, no trims would be left"
+            );
+
+            ipc::log("Removing CMv2.9 trims (L2, L4) from the RPUs with CMv4.0 metadata...");
+            ipc::warn(
+                "CMv2.9 only devices will tone map without trims. Use it for testing the player fallback, not for delivery."
+            );
         }
 
         if self.mode > 0 {
-            println!("Converting with mode {}...", self.mode);
+            ipc::log(format!("Converting with mode {}...", self.mode));
         }
 
         if self.remove_mapping {
-            println!("Removing polynomial/MMR mapping...");
+            ipc::log("Removing polynomial/MMR mapping...");
+        }
+
+        if let Some(levels) = &self.drop_levels {
+            ipc::log(format!("Dropping metadata levels {levels:?}..."));
+        }
+
+        if let Some(bit_depth) = self.bl_bit_depth {
+            ipc::log(format!("Setting {bit_depth}-bit BL signaling..."));
+        }
+
+        if self.fix_l1_stats {
+            Self::report_inconsistent_l1_stats(rpus);
         }
 
         if let Some(active_area) = &self.active_area {
             if active_area.crop {
-                println!("Cropping...");
+                ipc::log("Cropping...");
             }
 
             if let Some(drop_opt) = &active_area.drop_l5 {
-                println!(
+                ipc::log(format!(
                     "Dropping L5 metadata with opt '{}'",
                     drop_opt.to_lowercase()
-                );
+                ));
+            }
+
+            if let Some(threshold) = active_area.drop_l5_below {
+                ipc::log(format!(
+                    "Dropping L5 metadata with all offsets below {threshold}"
+                ));
             }
         }
 
+        if let Some(offset) = self.scene_cut_offset {
+            Self::offset_scene_cuts(rpus, offset);
+        }
+
         if self.scene_cuts.is_some() {
-            println!("Editing scene cuts...")
+            ipc::log("Editing scene cuts...")
         }
 
         for rpu in rpus.iter_mut().filter_map(|e| e.as_mut()) {
@@ -217,6 +815,10 @@ impl EditConfig {
             self.set_scene_cuts(rpus, edits)?;
         }
 
+        if let Some(edits) = &self.mmr_edits {
+            Self::edit_mmr(rpus, edits)?;
+        }
+
         // Specific ranges only, requires complete list
         if let Some(active_area) = &self.active_area {
             active_area.execute(rpus)?;
@@ -227,14 +829,37 @@ impl EditConfig {
             self.replace_from_rpus(rpus, &source_rpus)?;
         }
 
+        // The frozen metadata includes the other edits of the source frame
+        if let Some(freezes) = &self.freeze {
+            Self::freeze_metadata(rpus, freezes)?;
+        }
+
+        // The range edits and replaced levels can add blocks back
+        if self.drop_levels.is_some() {
+            rpus.iter_mut()
+                .flatten()
+                .for_each(|rpu| self.drop_levels_single_rpu(rpu));
+        }
+
         Ok(())
     }
 
     pub fn execute_single_rpu(&self, rpu: &mut DoviRpu) -> Result<()> {
+        // Conditions apply to the original header values
+        if let Some(header_edits) = &self.header_edits {
+            for edit in header_edits {
+                edit.execute_single_rpu(rpu)?;
+            }
+        }
+
         if self.remove_cmv4 {
             rpu.remove_cmv40_extension_metadata()?;
         }
 
+        if self.remove_cmv29 {
+            rpu.remove_cmv29_extension_metadata()?;
+        }
+
         if self.mode > 0 {
             rpu.convert_with_mode(self.mode)?;
         }
@@ -243,14 +868,28 @@ impl EditConfig {
             self.change_source_levels(rpu);
         }
 
+        if self.fix_l1_stats {
+            Self::fix_l1_stats_single_rpu(rpu);
+        }
+
         if self.remove_mapping {
             rpu.remove_mapping();
         }
 
+        if let Some(bit_depth) = self.bl_bit_depth {
+            rpu.set_bl_bit_depth(bit_depth)?;
+        }
+
         if let Some(l6) = &self.level6 {
             self.set_level6_metadata(rpu, l6)?;
         }
 
+        if let Some(l8_edits) = &self.level8_vectors {
+            for edit in l8_edits {
+                edit.execute_single_rpu(rpu)?;
+            }
+        }
+
         if let Some(l9) = &self.level9 {
             self.set_level9_metadata(rpu, l9)?;
         }
@@ -271,9 +910,19 @@ impl EditConfig {
             active_area.execute_single_rpu(rpu)?;
         }
 
+        self.drop_levels_single_rpu(rpu);
+
         Ok(())
     }
 
+    fn drop_levels_single_rpu(&self, rpu: &mut DoviRpu) {
+        if let (Some(levels), Some(vdr_dm_data)) = (&self.drop_levels, rpu.vdr_dm_data.as_mut()) {
+            for level in levels {
+                vdr_dm_data.remove_metadata_level(*level);
+            }
+        }
+    }
+
     fn range_string_to_tuple(range: &str) -> Result<(usize, usize)> {
         let mut result = (0, 0);
 
@@ -321,7 +970,7 @@ impl EditConfig {
             }
         }
 
-        println!("Removed {amount} metadata frames.");
+        ipc::log(format!("Removed {amount} metadata frames."));
 
         Ok(())
     }
@@ -331,19 +980,155 @@ impl EditConfig {
         to_duplicate: &[DuplicateMetadata],
         data: &mut Vec<Vec<u8>>,
     ) -> Result<()> {
-        println!("Duplicating metadata. Initial metadata len {}", data.len());
+        ipc::log(format!(
+            "Duplicating metadata. Initial metadata len {}",
+            data.len()
+        ));
+
+        for meta in to_duplicate {
+            ensure!(
+                meta.source < data.len() && meta.offset < data.len(),
+                "invalid duplicate: {:?}",
+                meta
+            );
+
+            let source = data[meta.source].clone();
+            data.splice(
+                meta.offset..meta.offset,
+                std::iter::repeat(source).take(meta.length),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Shifts the metadata by `offset` frames, to resync it with a delayed or head-trimmed video.
+    ///
+    /// A positive offset pads the head with copies of the first frame, extending its shot.
+    /// A negative offset trims the first frames, and the new first frame becomes a scene cut.
+    fn offset_frames(rpus: &mut Vec<Option<DoviRpu>>, offset: i64) -> Result<()> {
+        // Relative to the frames left after the `remove` pass
+        rpus.retain(Option::is_some);
+
+        ipc::log(format!("Offsetting metadata by {offset} frames..."));
+
+        let count = offset.unsigned_abs() as usize;
+
+        if offset > 0 {
+            let Some(Some(first)) = rpus.first_mut() else {
+                bail!("No metadata frame to pad with");
+            };
+
+            let mut padding = first.clone();
+
+            if let Some(vdr_dm_data) = first.vdr_dm_data.as_mut() {
+                if vdr_dm_data.scene_refresh_flag != 0 {
+                    vdr_dm_data.set_scene_cut(false);
+                    first.modified = true;
+                }
+            }
+
+            let mut rest = padding.clone();
+
+            if let Some(vdr_dm_data) = padding.vdr_dm_data.as_mut() {
+                vdr_dm_data.set_scene_cut(true);
+                padding.modified = true;
+            }
+            if let Some(vdr_dm_data) = rest.vdr_dm_data.as_mut() {
+                vdr_dm_data.set_scene_cut(false);
+                rest.modified = true;
+            }
+
+            rpus.splice(
+                0..0,
+                std::iter::once(Some(padding)).chain(std::iter::repeat(Some(rest)).take(count - 1)),
+            );
+        } else if offset < 0 {
+            ensure!(
+                count < rpus.len(),
+                "Can't trim {count} frames, only {} metadata frames",
+                rpus.len()
+            );
+
+            rpus.drain(..count);
+
+            if let Some(Some(first)) = rpus.first_mut() {
+                if let Some(vdr_dm_data) = first.vdr_dm_data.as_mut() {
+                    if vdr_dm_data.scene_refresh_flag != 1 {
+                        vdr_dm_data.set_scene_cut(true);
+                        first.modified = true;
+                    }
+                }
+            }
+        }
+
+        ipc::log(format!("Offset metadata length: {}", rpus.len()));
+
+        Ok(())
+    }
+
+    /// Inserts frames of black L1 metadata, starting a new shot.
+    /// The frame following them also becomes a scene cut.
+    ///
+    /// The other metadata is copied from the frame at the insert position,
+    /// or the last frame when appending.
+    fn insert_black_frames(
+        to_insert: &[BlackFrames],
+        rpus: &mut Vec<Option<DoviRpu>>,
+    ) -> Result<()> {
+        // Positions are relative to the frames left after the `remove` pass
+        rpus.retain(Option::is_some);
+
+        ipc::log(format!(
+            "Inserting black frames. Initial metadata len {}",
+            rpus.len()
+        ));
+
+        for insert in to_insert {
+            ensure!(
+                !rpus.is_empty() && insert.offset <= rpus.len(),
+                "invalid black frames insert: {:?}",
+                insert
+            );
+
+            if insert.length == 0 {
+                continue;
+            }
+
+            let template = rpus.get(insert.offset).or_else(|| rpus.last());
+            let mut black = template.cloned().flatten().unwrap();
+
+            let Some(vdr_dm_data) = black.vdr_dm_data.as_mut() else {
+                bail!(
+                    "Frame {} has no DM metadata, can't insert black frames",
+                    insert.offset
+                );
+            };
+
+            vdr_dm_data.replace_metadata_level(ExtMetadataBlock::Level1(
+                ExtMetadataBlockLevel1::new(0, 0, 0),
+            ))?;
+            vdr_dm_data.set_scene_cut(true);
+            black.modified = true;
 
-        for meta in to_duplicate {
-            ensure!(
-                meta.source < data.len() && meta.offset < data.len(),
-                "invalid duplicate: {:?}",
-                meta
-            );
+            let mut rest = black.clone();
+            if let Some(vdr_dm_data) = rest.vdr_dm_data.as_mut() {
+                vdr_dm_data.set_scene_cut(false);
+            }
 
-            let source = data[meta.source].clone();
-            data.splice(
-                meta.offset..meta.offset,
-                std::iter::repeat(source).take(meta.length),
+            if let Some(Some(next)) = rpus.get_mut(insert.offset) {
+                if let Some(vdr_dm_data) = next.vdr_dm_data.as_mut() {
+                    if vdr_dm_data.scene_refresh_flag != 1 {
+                        vdr_dm_data.set_scene_cut(true);
+                        next.modified = true;
+                    }
+                }
+            }
+
+            rpus.splice(
+                insert.offset..insert.offset,
+                std::iter::once(Some(black))
+                    .chain(std::iter::repeat(Some(rest)).take(insert.length - 1)),
             );
         }
 
@@ -358,6 +1143,46 @@ impl EditConfig {
         }
     }
 
+    fn report_inconsistent_l1_stats(rpus: &[Option<DoviRpu>]) {
+        let frames: Vec<String> = rpus
+            .iter()
+            .enumerate()
+            .filter_map(|(i, rpu)| rpu.as_ref().map(|rpu| (i, rpu)))
+            .filter(|(_, rpu)| {
+                rpu.vdr_dm_data.as_ref().map_or(false, |vdr_dm_data| {
+                    vdr_dm_data.level_blocks_iter(1).any(|block| {
+                        matches!(block, ExtMetadataBlock::Level1(l1) if l1.has_inconsistent_stats())
+                    })
+                })
+            })
+            .map(|(i, _)| i.to_string())
+            .collect();
+
+        if frames.is_empty() {
+            ipc::log("No inconsistent L1 statistics found.");
+        } else {
+            ipc::log(format!(
+                "Fixing inconsistent L1 statistics in {} frames: {}",
+                frames.len(),
+                frames.join(", ")
+            ));
+        }
+    }
+
+    fn fix_l1_stats_single_rpu(rpu: &mut DoviRpu) {
+        let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() else {
+            return;
+        };
+
+        for block in vdr_dm_data.level_blocks_iter_mut(1) {
+            if let ExtMetadataBlock::Level1(l1) = block {
+                if l1.fix_inconsistent_stats() {
+                    rpu.modified = true;
+                }
+            }
+        }
+    }
+
     fn set_level6_metadata(
         &self,
         rpu: &mut DoviRpu,
@@ -451,6 +1276,227 @@ impl EditConfig {
         Ok(())
     }
 
+    fn edit_mmr(rpus: &mut [Option<DoviRpu>], edits: &[MmrEdit]) -> Result<()> {
+        ipc::log("Editing MMR coefficients...");
+
+        for edit in edits {
+            edit.validate()?;
+
+            let (start, end) = edit.frame_range(rpus.len())?;
+
+            for (frame, rpu) in rpus[start..=end].iter_mut().enumerate() {
+                if let Some(rpu) = rpu.as_mut() {
+                    edit.execute_single_rpu(rpu)
+                        .map_err(|e| anyhow!("MMR edit, frame {}: {}", start + frame, e))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies the DM metadata of the source frames over their ranges.
+    ///
+    /// The metadata IDs of the frames are kept, and their scene cut flag depends on `scene_cuts`.
+    /// Frames removed by the `remove` pass are skipped.
+    fn freeze_metadata(rpus: &mut [Option<DoviRpu>], freezes: &[FreezeMetadata]) -> Result<()> {
+        ipc::log("Freezing metadata...");
+
+        let mut frozen = 0;
+
+        for freeze in freezes {
+            let (start, end) = freeze.frame_range(rpus.len())?;
+
+            let source = match rpus.get(freeze.source) {
+                Some(Some(rpu)) => rpu.vdr_dm_data.clone(),
+                Some(None) => bail!("Freeze source frame {} was removed", freeze.source),
+                None => bail!(
+                    "Invalid freeze source frame {}: {} available RPUs",
+                    freeze.source,
+                    rpus.len()
+                ),
+            };
+
+            let Some(source) = source else {
+                bail!("Freeze source frame {} has no DM metadata", freeze.source);
+            };
+
+            let mut first_in_range = true;
+
+            for rpu in rpus[start..=end].iter_mut().flatten() {
+                let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() else {
+                    continue;
+                };
+
+                let scene_refresh_flag = match freeze.scene_cuts {
+                    FreezeSceneCuts::Keep => vdr_dm_data.scene_refresh_flag,
+                    FreezeSceneCuts::Source => source.scene_refresh_flag,
+                    FreezeSceneCuts::Shot => first_in_range as u64,
+                };
+
+                *vdr_dm_data = VdrDmData {
+                    compressed: vdr_dm_data.compressed,
+                    affected_dm_metadata_id: vdr_dm_data.affected_dm_metadata_id,
+                    current_dm_metadata_id: vdr_dm_data.current_dm_metadata_id,
+                    scene_refresh_flag,
+                    ..source.clone()
+                };
+                rpu.modified = true;
+
+                first_in_range = false;
+                frozen += 1;
+            }
+
+            if freeze.scene_cuts == FreezeSceneCuts::Shot {
+                let next = rpus[end + 1..].iter_mut().flatten().next();
+
+                if let Some(rpu) = next {
+                    if let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() {
+                        if vdr_dm_data.scene_refresh_flag != 1 {
+                            vdr_dm_data.set_scene_cut(true);
+                            rpu.modified = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        ipc::log(format!("Froze the metadata of {frozen} frames."));
+
+        Ok(())
+    }
+
+    /// Moves the scene cuts of the remaining frames by `offset` frames.
+    ///
+    /// Scene cuts moved before the first frame are clamped to it, and the ones moved
+    /// past the last frame are dropped. The first frame is always a scene cut.
+    fn offset_scene_cuts(rpus: &mut [Option<DoviRpu>], offset: i64) {
+        ipc::log(format!("Offsetting scene cuts by {offset} frames..."));
+
+        let mut frames: Vec<&mut DoviRpu> = rpus
+            .iter_mut()
+            .flatten()
+            .filter(|rpu| rpu.vdr_dm_data.is_some())
+            .collect();
+
+        let frame_count = frames.len() as i64;
+        let mut scene_cuts = vec![false; frames.len()];
+
+        for (i, rpu) in frames.iter().enumerate() {
+            let is_scene_cut = rpu
+                .vdr_dm_data
+                .as_ref()
+                .map_or(false, |vdr| vdr.scene_refresh_flag == 1);
+
+            if is_scene_cut {
+                let moved = (i as i64 + offset).max(0);
+
+                if moved < frame_count {
+                    scene_cuts[moved as usize] = true;
+                }
+            }
+        }
+
+        if let Some(first) = scene_cuts.first_mut() {
+            *first = true;
+        }
+
+        let mut changed = 0;
+        for (rpu, is_scene_cut) in frames.iter_mut().zip(scene_cuts) {
+            if let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() {
+                if (vdr_dm_data.scene_refresh_flag == 1) != is_scene_cut {
+                    vdr_dm_data.set_scene_cut(is_scene_cut);
+                    rpu.modified = true;
+
+                    changed += 1;
+                }
+            }
+        }
+
+        ipc::log(format!("Changed the scene cut flag of {changed} frames."));
+    }
+
+    /// Each frame is followed by a copy without scene cut, so the scene cuts stay on the first
+    /// output frame of the shots.
+    ///
+    /// When interpolating, the copy's L1 is the midpoint with the next frame of the same shot.
+    fn double_frames(
+        rpus: &[Option<DoviRpu>],
+        frame_doubling: FrameDoubling,
+    ) -> Vec<Option<DoviRpu>> {
+        ipc::log("Doubling metadata frames...");
+
+        let is_scene_cut = |rpu: &DoviRpu| {
+            rpu.vdr_dm_data
+                .as_ref()
+                .map_or(false, |vdr| vdr.scene_refresh_flag == 1)
+        };
+
+        let frames: Vec<&DoviRpu> = rpus.iter().flatten().collect();
+        let mut doubled = Vec::with_capacity(frames.len() * 2);
+        let mut interpolated = 0;
+
+        for (i, &rpu) in frames.iter().enumerate() {
+            let mut copy = rpu.clone();
+
+            if let Some(vdr_dm_data) = copy.vdr_dm_data.as_mut() {
+                vdr_dm_data.set_scene_cut(false);
+                copy.modified = true;
+            }
+
+            let next_in_shot = frames.get(i + 1).filter(|next| !is_scene_cut(next));
+
+            if let (FrameDoubling::Interpolate, Some(next)) = (frame_doubling, next_in_shot) {
+                if Self::interpolate_l1(&mut copy, next) {
+                    interpolated += 1;
+                }
+            }
+
+            doubled.push(Some(rpu.clone()));
+            doubled.push(Some(copy));
+        }
+
+        if frame_doubling == FrameDoubling::Interpolate {
+            ipc::log(format!(
+                "Interpolated the L1 metadata of {interpolated} frames."
+            ));
+        }
+
+        doubled
+    }
+
+    /// Sets the L1 of `rpu` to the midpoint of its own and `next`'s.
+    /// Returns whether the L1 changed.
+    fn interpolate_l1(rpu: &mut DoviRpu, next: &DoviRpu) -> bool {
+        let next_l1 = next.vdr_dm_data.as_ref().and_then(|vdr| vdr.get_block(1));
+
+        let (Some(vdr_dm_data), Some(ExtMetadataBlock::Level1(next_l1))) =
+            (rpu.vdr_dm_data.as_mut(), next_l1)
+        else {
+            return false;
+        };
+
+        let Some(ExtMetadataBlock::Level1(l1)) = vdr_dm_data.get_block_mut(1) else {
+            return false;
+        };
+
+        let midpoint = |a: u16, b: u16| ((a as u32 + b as u32 + 1) / 2) as u16;
+        let interpolated = ExtMetadataBlockLevel1::new(
+            midpoint(l1.min_pq, next_l1.min_pq),
+            midpoint(l1.max_pq, next_l1.max_pq),
+            midpoint(l1.avg_pq, next_l1.avg_pq),
+        );
+
+        if *l1 == interpolated {
+            return false;
+        }
+
+        *l1 = interpolated;
+        rpu.modified = true;
+
+        true
+    }
+
     fn set_level255_metadata(
         &self,
         rpu: &mut DoviRpu,
@@ -470,7 +1516,7 @@ impl EditConfig {
         rpus: &mut [Option<DoviRpu>],
         source_rpus: &Vec<DoviRpu>,
     ) -> Result<()> {
-        println!("Replacing metadata levels from second RPU...");
+        ipc::log("Replacing metadata levels from second RPU...");
         ensure!(rpus.len() == source_rpus.len());
 
         let zip_iter = rpus.iter_mut().filter_map(|e| e.as_mut()).zip(source_rpus);
@@ -488,8 +1534,352 @@ impl EditConfig {
     }
 }
 
+impl FreezeMetadata {
+    fn frame_range(&self, rpu_count: usize) -> Result<(usize, usize)> {
+        let (start, end) = if self.range.eq_ignore_ascii_case("all") {
+            (0, rpu_count.saturating_sub(1))
+        } else if self.range.contains('-') {
+            EditConfig::range_string_to_tuple(&self.range)?
+        } else {
+            let frame = self
+                .range
+                .parse()
+                .map_err(|_| anyhow!("Invalid freeze range {}", self.range))?;
+
+            (frame, frame)
+        };
+
+        ensure!(
+            start <= end && end < rpu_count,
+            "Invalid freeze range {}: {} available RPUs",
+            self.range,
+            rpu_count
+        );
+
+        Ok((start, end))
+    }
+}
+
+impl Level8VectorEdit {
+    fn execute_single_rpu(&self, rpu: &mut DoviRpu) -> Result<()> {
+        let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() else {
+            return Ok(());
+        };
+
+        let mut modified = false;
+
+        for block in vdr_dm_data.level_blocks_iter_mut(8) {
+            if let ExtMetadataBlock::Level8(level8) = block {
+                if self
+                    .target_display_index
+                    .map_or(false, |index| index != level8.target_display_index)
+                {
+                    continue;
+                }
+
+                if !self.saturation.is_empty() {
+                    let vectors =
+                        Self::edit_vectors(level8.saturation_vectors(), &self.saturation)?;
+                    level8.set_saturation_vectors(vectors);
+                }
+
+                if !self.hue.is_empty() {
+                    let vectors = Self::edit_vectors(level8.hue_vectors(), &self.hue)?;
+                    level8.set_hue_vectors(vectors);
+                }
+
+                modified = true;
+            }
+        }
+
+        rpu.modified |= modified;
+
+        Ok(())
+    }
+
+    fn edit_vectors(mut vectors: [u8; 6], edits: &HashMap<String, VectorValue>) -> Result<[u8; 6]> {
+        for (channel, value) in edits {
+            let index = Self::channel_index(channel)?;
+            vectors[index] = value.apply(vectors[index])?;
+        }
+
+        Ok(vectors)
+    }
+
+    fn channel_index(channel: &str) -> Result<usize> {
+        Ok(match channel.to_lowercase().as_str() {
+            "r" | "red" => 0,
+            "y" | "yellow" => 1,
+            "g" | "green" => 2,
+            "c" | "cyan" => 3,
+            "b" | "blue" => 4,
+            "m" | "magenta" => 5,
+            _ => bail!(
+                "Invalid L8 vector channel '{}', must be one of R, Y, G, C, B, M",
+                channel
+            ),
+        })
+    }
+}
+
+impl VectorValue {
+    fn apply(&self, current: u8) -> Result<u8> {
+        match self {
+            VectorValue::Absolute(value) => Ok(*value),
+            VectorValue::Relative(value) => {
+                ensure!(
+                    value.starts_with('+') || value.starts_with('-'),
+                    "Invalid relative L8 vector value '{}', must start with + or -",
+                    value
+                );
+
+                let new_value = current as i16 + value.parse::<i16>()?;
+
+                u8::try_from(new_value).map_err(|_| {
+                    anyhow::anyhow!("L8 vector value out of range: {} {}", current, value)
+                })
+            }
+        }
+    }
+}
+
+impl MmrEdit {
+    /// Checks the coefficients count against the MMR order
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            matches!(self.component, 1 | 2),
+            "Invalid MMR edit component {}, only the chroma components 1 and 2 use MMR",
+            self.component
+        );
+        ensure!(
+            self.mmr_order_minus1 <= 2,
+            "Invalid mmr_order_minus1 {}, max 2",
+            self.mmr_order_minus1
+        );
+
+        let orders = self.mmr_order_minus1 as usize + 1;
+
+        ensure!(
+            self.mmr_coef.len() == orders && self.mmr_coef.iter().all(|c| c.len() == MMR_COEFFS),
+            "mmr_coef should have {orders} orders of {MMR_COEFFS} coefficients for mmr_order_minus1 {}",
+            self.mmr_order_minus1
+        );
+
+        if !self.mmr_coef_int.is_empty() {
+            ensure!(
+                self.mmr_coef_int.len() == orders
+                    && self.mmr_coef_int.iter().all(|c| c.len() == MMR_COEFFS),
+                "mmr_coef_int should have {orders} orders of {MMR_COEFFS} coefficients for mmr_order_minus1 {}",
+                self.mmr_order_minus1
+            );
+        }
+
+        Ok(())
+    }
+
+    fn frame_range(&self, rpu_count: usize) -> Result<(usize, usize)> {
+        let (start, end) = if self.range.eq_ignore_ascii_case("all") {
+            (0, rpu_count.saturating_sub(1))
+        } else if self.range.contains('-') {
+            EditConfig::range_string_to_tuple(&self.range)?
+        } else {
+            let frame = self
+                .range
+                .parse()
+                .map_err(|_| anyhow!("Invalid MMR edit range {}", self.range))?;
+
+            (frame, frame)
+        };
+
+        ensure!(
+            end < rpu_count,
+            "Invalid MMR edit range {}: {} available RPUs",
+            self.range,
+            rpu_count
+        );
+
+        Ok((start, end))
+    }
+
+    fn execute_single_rpu(&self, rpu: &mut DoviRpu) -> Result<()> {
+        let coefficient_data_type = rpu.header.coefficient_data_type;
+        let coef_length = rpu.header.coefficient_log2_denom_length;
+
+        let Some(mapping) = rpu.rpu_data_mapping.as_mut() else {
+            bail!("no mapping to edit");
+        };
+        let Some(mmr) = mapping.curves[self.component].mmr.as_mut() else {
+            bail!("component {} mapping is not MMR", self.component);
+        };
+
+        ensure!(
+            self.piece < mmr.mmr_order_minus1.len(),
+            "piece {} out of range, {} pieces",
+            self.piece,
+            mmr.mmr_order_minus1.len()
+        );
+
+        if coefficient_data_type == 0 {
+            ensure!(
+                !self.mmr_coef_int.is_empty(),
+                "mmr_coef_int is required with coefficient_data_type 0"
+            );
+        } else {
+            ensure!(
+                self.mmr_coef_int.is_empty() && self.mmr_constant_int == 0,
+                "the integer parts are not signaled with coefficient_data_type {}",
+                coefficient_data_type
+            );
+        }
+
+        let max_coef = (1_u64 << coef_length) - 1;
+        let too_large = std::iter::once(&self.mmr_constant)
+            .chain(self.mmr_coef.iter().flatten())
+            .find(|coef| **coef > max_coef);
+
+        if let Some(coef) = too_large {
+            bail!("coefficient {coef} does not fit in {coef_length} bits");
+        }
+
+        let piece = self.piece;
+        mmr.mmr_order_minus1[piece] = self.mmr_order_minus1;
+        mmr.mmr_constant[piece] = self.mmr_constant;
+        mmr.mmr_coef[piece] = self.mmr_coef.clone();
+
+        if coefficient_data_type == 0 {
+            mmr.mmr_constant_int[piece] = self.mmr_constant_int;
+            mmr.mmr_coef_int[piece] = self.mmr_coef_int.clone();
+        }
+
+        rpu.modified = true;
+
+        Ok(())
+    }
+}
+
+impl HeaderEdit {
+    fn execute_single_rpu(&self, rpu: &mut DoviRpu) -> Result<()> {
+        for (field, expected) in &self.conditions {
+            let value = match field.as_str() {
+                "dovi_profile" => Some(rpu.dovi_profile as u64),
+                // Not signaled when the mapping of a previous RPU is used
+                "vdr_rpu_id" => rpu.rpu_data_mapping.as_ref().map(|m| m.vdr_rpu_id),
+                _ => Some(HeaderEdit::header_field(&rpu.header, field)?),
+            };
+
+            if value != Some(*expected) {
+                return Ok(());
+            }
+        }
+
+        for (field, value) in &self.set {
+            if field == "vdr_rpu_id" {
+                ensure!(
+                    *value <= MAX_VDR_RPU_ID,
+                    "Invalid value {} for RPU header field 'vdr_rpu_id', max {}",
+                    value,
+                    MAX_VDR_RPU_ID
+                );
+
+                if let Some(mapping) = rpu.rpu_data_mapping.as_mut() {
+                    mapping.vdr_rpu_id = *value;
+                }
+            } else {
+                HeaderEdit::set_header_field(&mut rpu.header, field, *value)?;
+            }
+        }
+
+        rpu.modified = true;
+
+        Ok(())
+    }
+
+    fn header_field(header: &RpuDataHeader, field: &str) -> Result<u64> {
+        let value = match field {
+            "rpu_format" => header.rpu_format as u64,
+            "vdr_rpu_profile" => header.vdr_rpu_profile as u64,
+            "vdr_rpu_level" => header.vdr_rpu_level as u64,
+            "vdr_seq_info_present_flag" => header.vdr_seq_info_present_flag as u64,
+            "chroma_resampling_explicit_filter_flag" => {
+                header.chroma_resampling_explicit_filter_flag as u64
+            }
+            "coefficient_data_type" => header.coefficient_data_type as u64,
+            "coefficient_log2_denom" => header.coefficient_log2_denom,
+            "vdr_rpu_normalized_idc" => header.vdr_rpu_normalized_idc as u64,
+            "bl_video_full_range_flag" => header.bl_video_full_range_flag as u64,
+            "bl_bit_depth_minus8" => header.bl_bit_depth_minus8,
+            "el_bit_depth_minus8" => header.el_bit_depth_minus8,
+            "ext_mapping_idc_lsb" => header.ext_mapping_idc_lsb as u64,
+            "ext_mapping_idc_msb" => header.ext_mapping_idc_msb as u64,
+            "vdr_bit_depth_minus8" => header.vdr_bit_depth_minus8,
+            "spatial_resampling_filter_flag" => header.spatial_resampling_filter_flag as u64,
+            "reserved_zero_3bits" => header.reserved_zero_3bits as u64,
+            "el_spatial_resampling_filter_flag" => header.el_spatial_resampling_filter_flag as u64,
+            "disable_residual_flag" => header.disable_residual_flag as u64,
+            "vdr_dm_metadata_present_flag" => header.vdr_dm_metadata_present_flag as u64,
+            "use_prev_vdr_rpu_flag" => header.use_prev_vdr_rpu_flag as u64,
+            "prev_vdr_rpu_id" => header.prev_vdr_rpu_id,
+            _ => bail!("Unknown RPU header field '{}'", field),
+        };
+
+        Ok(value)
+    }
+
+    /// Only fields that do not change the structure of the RPU can be set
+    fn set_header_field(header: &mut RpuDataHeader, field: &str, value: u64) -> Result<()> {
+        let max_value = match field {
+            "vdr_rpu_profile" | "vdr_rpu_level" => 0xF,
+            "vdr_rpu_normalized_idc" => 3,
+            "reserved_zero_3bits" | "ext_mapping_idc_msb" => 7,
+            "ext_mapping_idc_lsb" => 0x1F,
+            "vdr_bit_depth_minus8" => 8,
+            "prev_vdr_rpu_id" => MAX_VDR_RPU_ID,
+            "chroma_resampling_explicit_filter_flag"
+            | "bl_video_full_range_flag"
+            | "spatial_resampling_filter_flag"
+            | "el_spatial_resampling_filter_flag" => 1,
+            _ => bail!("RPU header field '{}' cannot be edited", field),
+        };
+
+        ensure!(
+            value <= max_value,
+            "Invalid value {} for RPU header field '{}', max {}",
+            value,
+            field,
+            max_value
+        );
+
+        match field {
+            "vdr_rpu_profile" => header.vdr_rpu_profile = value as u8,
+            "vdr_rpu_level" => header.vdr_rpu_level = value as u8,
+            "vdr_rpu_normalized_idc" => header.vdr_rpu_normalized_idc = value as u8,
+            "reserved_zero_3bits" => header.reserved_zero_3bits = value as u8,
+            "ext_mapping_idc_lsb" => header.ext_mapping_idc_lsb = value as u8,
+            "ext_mapping_idc_msb" => header.ext_mapping_idc_msb = value as u8,
+            "vdr_bit_depth_minus8" => header.vdr_bit_depth_minus8 = value,
+            "prev_vdr_rpu_id" => header.prev_vdr_rpu_id = value,
+            "chroma_resampling_explicit_filter_flag" => {
+                header.chroma_resampling_explicit_filter_flag = value == 1
+            }
+            "bl_video_full_range_flag" => header.bl_video_full_range_flag = value == 1,
+            "spatial_resampling_filter_flag" => header.spatial_resampling_filter_flag = value == 1,
+            "el_spatial_resampling_filter_flag" => {
+                header.el_spatial_resampling_filter_flag = value == 1
+            }
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+}
+
 impl ActiveArea {
     fn execute(&self, rpus: &mut [Option<DoviRpu>]) -> Result<()> {
+        // Before the specific ranges, which can override the detected bars
+        if let Some(path) = &self.bar_detection {
+            self.set_detected_offsets(path, rpus)?;
+        }
+
         if let Some(edits) = &self.edits {
             if !edits.is_empty() {
                 self.do_edits(edits, rpus)?;
@@ -508,6 +1898,10 @@ impl ActiveArea {
             self.drop_specific_l5(&drop_opt.to_lowercase(), rpu)?;
         }
 
+        if let Some(threshold) = self.drop_l5_below {
+            self.drop_l5_below_threshold(threshold, rpu);
+        }
+
         // Allow passing "all" instead of a range
         // Do "all" presets before specific ranges
         if let (Some(presets), Some(edits)) = (&self.presets, &self.edits) {
@@ -529,7 +1923,7 @@ impl ActiveArea {
 
     fn do_edits(&self, edits: &HashMap<String, u16>, rpus: &mut [Option<DoviRpu>]) -> Result<()> {
         if let Some(presets) = &self.presets {
-            println!("Editing active area offsets...");
+            ipc::log("Editing active area offsets...");
 
             let specific_edits = edits.iter().filter(|e| e.0.to_lowercase() != "all");
 
@@ -554,6 +1948,33 @@ impl ActiveArea {
         Ok(())
     }
 
+    fn set_detected_offsets(&self, path: &Path, rpus: &mut [Option<DoviRpu>]) -> Result<()> {
+        let detection = bar_detection::parse_detection_file(path)?;
+        let tolerance = self.jitter_tolerance.unwrap_or(DEFAULT_JITTER_TOLERANCE);
+
+        let shots = bar_detection::shot_offsets(&detection, rpus, tolerance)?;
+        ipc::log(format!(
+            "Setting active area offsets from bar detection, {} shots...",
+            shots.len()
+        ));
+
+        for (shot, [left, right, top, bottom]) in shots {
+            let active_area_offsets = ActiveAreaOffsets {
+                id: 0,
+                left,
+                right,
+                top,
+                bottom,
+            };
+
+            for rpu in rpus[shot].iter_mut().filter_map(|e| e.as_mut()) {
+                self.set_offsets(rpu, &active_area_offsets)?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn set_offsets(
         &self,
         rpu: &mut DoviRpu,
@@ -603,4 +2024,23 @@ impl ActiveArea {
 
         Ok(())
     }
+    fn drop_l5_below_threshold(&self, threshold: u16, rpu: &mut DoviRpu) {
+        if let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() {
+            let drop_it = if let Some(ExtMetadataBlock::Level5(m)) = vdr_dm_data.get_block(5) {
+                let (left, right, top, bottom) = m.get_offsets();
+
+                [left, right, top, bottom]
+                    .iter()
+                    .all(|offset| *offset < threshold)
+            } else {
+                false
+            };
+
+            if drop_it {
+                rpu.modified = true;
+
+                vdr_dm_data.remove_metadata_level(5);
+            }
+        }
+    }
 }
@@ -6,66 +6,206 @@ use anyhow::Result;
 use serde::ser::SerializeSeq;
 use serde::Serializer;
 
-use dolby_vision::rpu::utils::parse_rpu_file;
+use dolby_vision::rpu::dovi_rpu::RpuParseOpts;
+use dolby_vision::rpu::extension_metadata::cm_xml_names::to_cm_xml_names;
+use dolby_vision::rpu::generate::{Annotation, VideoShot};
+use dolby_vision::rpu::timeline::write_timeline;
+use dolby_vision::rpu::utils::parse_rpu_file_with_opts;
 
 use crate::commands::ExportArgs;
-use crate::dovi::input_from_either;
+use crate::dovi::{input_from_either, read_annotations_file, FramePropsFormat, JsonCompression};
+use crate::ipc;
 
+use super::frame_props::FrameProps;
+use super::primaries_export::PrimariesExport;
+use super::rpu_info::RpusListSummary;
+use super::shot_boundaries::{parse_idr_frames, ShotBoundaryReport};
 use super::DoviRpu;
 
 pub struct Exporter {
     input: PathBuf,
     output: PathBuf,
+    shot_stats: Option<PathBuf>,
+    compress: Option<JsonCompression>,
+    xml_names: bool,
+    frame_props: Option<PathBuf>,
+    frame_props_format: FramePropsFormat,
+    annotations: Vec<Annotation>,
+    labeled_shots: Vec<VideoShot>,
+    fps: Option<f64>,
+    primaries: Option<PathBuf>,
+    shot_boundaries: Option<(PathBuf, PathBuf)>,
+    timeline: Option<PathBuf>,
 }
 
 impl Exporter {
-    pub fn export(args: ExportArgs) -> Result<()> {
+    pub fn export(args: ExportArgs, parse_opts: RpuParseOpts) -> Result<()> {
         let ExportArgs {
             input,
             input_pos,
             output,
+            shot_stats,
+            compress,
+            xml_names,
+            frame_props,
+            frame_props_format,
+            annotations,
+            fps,
+            primaries,
+            shot_boundaries,
+            video,
+            timeline,
         } = args;
 
         let input = input_from_either("editor", input, input_pos)?;
 
         let out_path = if let Some(out_path) = output {
             out_path
+        } else if let Some(compression) = compress {
+            PathBuf::from(format!("RPU_export.json.{}", compression.extension()))
         } else {
             PathBuf::from("RPU_export.json".to_string())
         };
 
+        let (annotations, labeled_shots) = match annotations {
+            Some(path) => read_annotations_file(path)?,
+            None => (Vec::new(), Vec::new()),
+        };
+
         let exporter = Exporter {
             input,
             output: out_path,
+            shot_stats,
+            compress,
+            xml_names,
+            frame_props,
+            frame_props_format,
+            annotations,
+            labeled_shots,
+            fps,
+            primaries,
+            shot_boundaries: shot_boundaries.zip(video),
+            timeline,
         };
 
-        println!("Parsing RPU file...");
+        ipc::log("Parsing RPU file...");
         stdout().flush().ok();
 
-        let rpus = parse_rpu_file(&exporter.input)?;
+        let rpus = parse_rpu_file_with_opts(&exporter.input, parse_opts)?;
         exporter.execute(&rpus)?;
 
-        println!("Done.");
+        ipc::log("Done.");
 
         Ok(())
     }
 
     fn execute(&self, rpus: &[DoviRpu]) -> Result<()> {
-        println!("Exporting metadata...");
+        ipc::log("Exporting metadata...");
 
         let writer = BufWriter::with_capacity(
             100_000,
             File::create(&self.output).expect("Can't create file"),
         );
 
+        match self.compress {
+            Some(JsonCompression::Zstd) => {
+                let encoder = zstd::Encoder::new(writer, 0)?;
+                self.write_rpus_json(encoder, rpus)?.finish()?.flush()?;
+            }
+            Some(JsonCompression::Brotli) => {
+                let encoder = brotli::CompressorWriter::new(writer, 4096, 9, 22);
+                self.write_rpus_json(encoder, rpus)?.into_inner().flush()?;
+            }
+            None => {
+                self.write_rpus_json(writer, rpus)?.flush()?;
+            }
+        }
+
+        if let Some(stats_path) = &self.shot_stats {
+            ipc::log("Exporting shot statistics...");
+
+            let mut summary = RpusListSummary::new(rpus)?;
+
+            for shot in summary.shot_stats.iter_mut() {
+                shot.set_annotations(&self.annotations);
+                shot.set_labels(&self.labeled_shots);
+
+                if let Some(fps) = self.fps {
+                    shot.set_timecodes(fps);
+                }
+            }
+
+            let stats_writer = BufWriter::new(File::create(stats_path)?);
+
+            serde_json::to_writer_pretty(stats_writer, &summary.shot_stats)?;
+        }
+
+        if let Some(props_path) = &self.frame_props {
+            ipc::log("Exporting frame props...");
+
+            FrameProps::write_file(props_path, rpus, self.frame_props_format, &self.annotations)?;
+        }
+
+        if let Some(primaries_path) = &self.primaries {
+            ipc::log("Exporting L9 source primaries...");
+
+            PrimariesExport::from_rpus(rpus)?.write_file(primaries_path)?;
+        }
+
+        if let Some((report_path, video)) = &self.shot_boundaries {
+            let idr_frames = parse_idr_frames(video)?;
+
+            if idr_frames.last().map_or(false, |idx| *idx >= rpus.len()) {
+                ipc::warn(format!(
+                    "the video has more frames than the {} RPUs, the extra IDR frames are ignored",
+                    rpus.len()
+                ));
+            }
+
+            ipc::log("Exporting shot boundaries...");
+
+            let report = ShotBoundaryReport::new(rpus, &idr_frames);
+            report.print_summary();
+            report.write_file(report_path)?;
+        }
+
+        if let Some(timeline_path) = &self.timeline {
+            ipc::log("Exporting metadata timeline...");
+
+            let mut timeline_writer = BufWriter::new(File::create(timeline_path)?);
+            write_timeline(&mut timeline_writer, rpus)?;
+            timeline_writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Streams the RPUs as a JSON array, returning the writer to be finished
+    fn write_rpus_json<W: Write>(&self, writer: W, rpus: &[DoviRpu]) -> Result<W> {
         let mut ser = serde_json::Serializer::new(writer);
         let mut seq = ser.serialize_seq(Some(rpus.len()))?;
 
-        for rpu in rpus {
-            seq.serialize_element(&rpu)?;
+        for (i, rpu) in rpus.iter().enumerate() {
+            let texts = Annotation::frame_texts(&self.annotations, i);
+
+            if self.xml_names || !texts.is_empty() {
+                let mut value = serde_json::to_value(rpu)?;
+
+                if self.xml_names {
+                    to_cm_xml_names(&mut value);
+                }
+
+                if let Some(object) = value.as_object_mut().filter(|_| !texts.is_empty()) {
+                    object.insert("annotations".to_string(), texts.into());
+                }
+
+                seq.serialize_element(&value)?;
+            } else {
+                seq.serialize_element(&rpu)?;
+            }
         }
         seq.end()?;
 
-        Ok(())
+        Ok(ser.into_inner())
     }
 }
@@ -0,0 +1,533 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{bail, ensure, Result};
+
+use hevc_parser::hevc::NAL_UNSPEC62;
+
+const HEADER_SIZE: u64 = 8;
+
+/// Samples allowed in a single `trun`, far above any real fragment
+const MAX_TRUN_SAMPLES: usize = 1 << 20;
+
+/// HEVC sample entries, including the Dolby Vision ones
+pub(super) const HEVC_SAMPLE_ENTRIES: &[&[u8; 4]] = &[b"hvc1", b"hev1", b"dvh1", b"dvhe"];
+
+/// Reads the RPU NAL units from fragmented MP4 (fMP4/CMAF) files.
+///
+/// The samples are read from every `moof`/`mdat` fragment, in presentation order.
+/// The init segment (`moov`) can either be in the same file or provided separately.
+#[derive(Default)]
+pub struct Fmp4RpuReader {
+    track: TrackInfo,
+    samples: Vec<SampleRpu>,
+}
+
+struct TrackInfo {
+    track_id: Option<u32>,
+    nal_length_size: usize,
+    default_sample_duration: u32,
+    default_sample_size: u32,
+}
+
+struct SampleRpu {
+    presentation_time: i64,
+    decode_index: usize,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct BoxHeader {
+    pub(super) box_type: [u8; 4],
+    pub(super) start: u64,
+    pub(super) size: u64,
+    pub(super) header_size: u64,
+}
+
+#[derive(Default)]
+struct TrackFragment {
+    track_id: u32,
+    base_data_offset: Option<u64>,
+    default_sample_duration: Option<u32>,
+    default_sample_size: Option<u32>,
+    base_decode_time: u64,
+    runs: Vec<TrackRun>,
+}
+
+#[derive(Default)]
+struct TrackRun {
+    data_offset: Option<i64>,
+    samples: Vec<TrackRunSample>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct TrackRunSample {
+    duration: Option<u32>,
+    size: Option<u32>,
+    composition_offset: i64,
+}
+
+impl Default for TrackInfo {
+    fn default() -> Self {
+        Self {
+            track_id: None,
+            nal_length_size: 4,
+            default_sample_duration: 0,
+            default_sample_size: 0,
+        }
+    }
+}
+
+impl Fmp4RpuReader {
+    /// Returns the RPU NAL units (including the NAL header), in presentation order
+    pub fn read<P: AsRef<Path>>(input: P, init_segment: Option<&Path>) -> Result<Vec<Vec<u8>>> {
+        let mut fmp4_reader = Self::default();
+
+        if let Some(init_segment) = init_segment {
+            fmp4_reader.read_file(init_segment)?;
+        }
+
+        fmp4_reader.read_file(input)?;
+
+        ensure!(
+            !fmp4_reader.samples.is_empty(),
+            "fMP4: No Dolby Vision RPU found in the fragments"
+        );
+
+        let mut samples = fmp4_reader.samples;
+        samples.sort_by_key(|s| (s.presentation_time, s.decode_index));
+
+        Ok(samples.into_iter().map(|s| s.data).collect())
+    }
+
+    fn read_file<P: AsRef<Path>>(&mut self, input: P) -> Result<()> {
+        let mut reader = BufReader::new(File::open(input)?);
+        let file_size = reader.seek(SeekFrom::End(0))?;
+
+        let mut pos = 0;
+        while pos < file_size {
+            reader.seek(SeekFrom::Start(pos))?;
+            let header = read_box_header(&mut reader, pos, file_size)?;
+
+            match &header.box_type {
+                b"moov" => {
+                    let data = read_box_payload(&mut reader, &header)?;
+                    self.parse_moov(&data)?;
+                }
+                b"moof" => {
+                    let data = read_box_payload(&mut reader, &header)?;
+                    self.parse_moof(&mut reader, &header, &data, file_size)?;
+                }
+                _ => (),
+            }
+
+            pos = header.start + header.size;
+        }
+
+        Ok(())
+    }
+
+    fn parse_moov(&mut self, data: &[u8]) -> Result<()> {
+        for (header, payload) in child_boxes(data) {
+            match &header.box_type {
+                b"trak" if self.track.track_id.is_none() => {
+                    self.parse_trak(payload)?;
+                }
+                b"mvex" => {
+                    for (trex_header, trex) in child_boxes(payload) {
+                        if &trex_header.box_type == b"trex" && trex.len() >= 24 {
+                            let track_id = be_u32(trex, 4);
+
+                            if self.track.track_id.map_or(true, |id| id == track_id) {
+                                self.track.default_sample_duration = be_u32(trex, 12);
+                                self.track.default_sample_size = be_u32(trex, 16);
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Uses the first track with a HEVC sample entry
+    fn parse_trak(&mut self, data: &[u8]) -> Result<()> {
+        let track_id = find_box(data, &[b"tkhd"]).and_then(|tkhd| {
+            let version = *tkhd.first()?;
+            let offset = if version == 1 { 20 } else { 12 };
+
+            (tkhd.len() >= offset + 4).then(|| be_u32(tkhd, offset))
+        });
+
+        let stsd = find_box(data, &[b"mdia", b"minf", b"stbl", b"stsd"]);
+
+        // Skip full box header and entry_count
+        let sample_entries = stsd.and_then(|stsd| stsd.get(8..));
+
+        if let (Some(track_id), Some(sample_entries)) = (track_id, sample_entries) {
+            for (header, entry) in child_boxes(sample_entries) {
+                if !HEVC_SAMPLE_ENTRIES.contains(&&header.box_type) {
+                    continue;
+                }
+
+                self.track.track_id = Some(track_id);
+
+                // VisualSampleEntry fields are 78 bytes
+                if let Some(hvcc) = entry.get(78..).and_then(|e| find_box(e, &[b"hvcC"])) {
+                    ensure!(hvcc.len() > 21, "fMP4: Invalid hvcC box");
+                    self.track.nal_length_size = (hvcc[21] & 0x03) as usize + 1;
+                }
+
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_moof<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        moof_header: &BoxHeader,
+        data: &[u8],
+        file_size: u64,
+    ) -> Result<()> {
+        for (header, traf) in child_boxes(data) {
+            if &header.box_type != b"traf" {
+                continue;
+            }
+
+            let fragment = parse_traf(traf)?;
+
+            if self
+                .track
+                .track_id
+                .map_or(false, |id| id != fragment.track_id)
+            {
+                continue;
+            }
+
+            // No init segment, use the first track found
+            self.track.track_id.get_or_insert(fragment.track_id);
+
+            self.read_fragment_samples(reader, moof_header, &fragment, file_size)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_fragment_samples<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        moof_header: &BoxHeader,
+        fragment: &TrackFragment,
+        file_size: u64,
+    ) -> Result<()> {
+        let base_offset = fragment.base_data_offset.unwrap_or(moof_header.start);
+        let mut decode_time = fragment.base_decode_time as i64;
+
+        let mut sample_offset = base_offset;
+        for run in &fragment.runs {
+            // Without data offset, the run follows the previous one
+            if let Some(data_offset) = run.data_offset {
+                sample_offset = (base_offset as i64 + data_offset) as u64;
+            }
+
+            for sample in &run.samples {
+                let size = sample
+                    .size
+                    .or(fragment.default_sample_size)
+                    .unwrap_or(self.track.default_sample_size) as usize;
+                let duration = sample
+                    .duration
+                    .or(fragment.default_sample_duration)
+                    .unwrap_or(self.track.default_sample_duration);
+
+                ensure!(
+                    sample_offset
+                        .checked_add(size as u64)
+                        .map_or(false, |end| end <= file_size),
+                    "fMP4: Sample at offset {sample_offset} of size {size} exceeds the file size"
+                );
+
+                reader.seek(SeekFrom::Start(sample_offset))?;
+                let mut sample_data = vec![0; size];
+                reader.read_exact(&mut sample_data)?;
+
+                if let Some(rpu) = rpu_nalu_from_sample(&sample_data, self.track.nal_length_size)? {
+                    self.samples.push(SampleRpu {
+                        presentation_time: decode_time + sample.composition_offset,
+                        decode_index: self.samples.len(),
+                        data: rpu,
+                    });
+                }
+
+                sample_offset += size as u64;
+                decode_time += duration as i64;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Finds the RPU NAL unit in a sample made of length prefixed NAL units
+pub(super) fn rpu_nalu_from_sample(sample: &[u8], length_size: usize) -> Result<Option<Vec<u8>>> {
+    let mut pos = 0;
+
+    while pos + length_size <= sample.len() {
+        let nal_size = sample[pos..pos + length_size]
+            .iter()
+            .fold(0_usize, |size, b| (size << 8) | *b as usize);
+        pos += length_size;
+
+        ensure!(
+            pos + nal_size <= sample.len(),
+            "MP4: Invalid NAL unit size in sample"
+        );
+
+        let nal = &sample[pos..pos + nal_size];
+        if nal.len() > 2 && (nal[0] >> 1) & 0x3F == NAL_UNSPEC62 {
+            return Ok(Some(nal.to_vec()));
+        }
+
+        pos += nal_size;
+    }
+
+    Ok(None)
+}
+
+fn parse_traf(data: &[u8]) -> Result<TrackFragment> {
+    let mut fragment = TrackFragment::default();
+
+    for (header, payload) in child_boxes(data) {
+        match &header.box_type {
+            b"tfhd" => {
+                ensure!(payload.len() >= 8, "fMP4: Invalid tfhd box");
+
+                let flags = be_u32(payload, 0) & 0xFFFFFF;
+                fragment.track_id = be_u32(payload, 4);
+
+                let mut pos = 8;
+                if flags & 0x01 != 0 {
+                    fragment.base_data_offset = Some(be_u64(payload, pos)?);
+                    pos += 8;
+                }
+                if flags & 0x02 != 0 {
+                    pos += 4;
+                }
+                if flags & 0x08 != 0 {
+                    fragment.default_sample_duration = Some(be_u32_checked(payload, pos)?);
+                    pos += 4;
+                }
+                if flags & 0x10 != 0 {
+                    fragment.default_sample_size = Some(be_u32_checked(payload, pos)?);
+                }
+            }
+            b"tfdt" => {
+                ensure!(payload.len() >= 8, "fMP4: Invalid tfdt box");
+
+                fragment.base_decode_time = if payload[0] == 1 {
+                    be_u64(payload, 4)?
+                } else {
+                    be_u32(payload, 4) as u64
+                };
+            }
+            b"trun" => fragment.runs.push(parse_trun(payload)?),
+            _ => (),
+        }
+    }
+
+    Ok(fragment)
+}
+
+fn parse_trun(data: &[u8]) -> Result<TrackRun> {
+    ensure!(data.len() >= 8, "fMP4: Invalid trun box");
+
+    let version = data[0];
+    let flags = be_u32(data, 0) & 0xFFFFFF;
+    let sample_count = be_u32(data, 4) as usize;
+
+    let mut run = TrackRun::default();
+    let mut pos = 8;
+
+    if flags & 0x01 != 0 {
+        run.data_offset = Some(be_u32_checked(data, pos)? as i32 as i64);
+        pos += 4;
+    }
+    if flags & 0x04 != 0 {
+        pos += 4;
+    }
+
+    // Bytes of the optional duration, size, flags and composition offset fields
+    let sample_bytes = [0x100, 0x200, 0x400, 0x800]
+        .iter()
+        .filter(|&&flag| flags & flag != 0)
+        .count()
+        * 4;
+
+    // Without per sample fields, the count isn't bounded by the box size
+    let max_samples = data
+        .len()
+        .saturating_sub(pos)
+        .checked_div(sample_bytes)
+        .map_or(MAX_TRUN_SAMPLES, |max| max.min(MAX_TRUN_SAMPLES));
+
+    ensure!(
+        sample_count <= max_samples,
+        "fMP4: Invalid trun sample count {sample_count}"
+    );
+
+    run.samples.reserve_exact(sample_count);
+
+    for _ in 0..sample_count {
+        let mut sample = TrackRunSample::default();
+
+        if flags & 0x100 != 0 {
+            sample.duration = Some(be_u32_checked(data, pos)?);
+            pos += 4;
+        }
+        if flags & 0x200 != 0 {
+            sample.size = Some(be_u32_checked(data, pos)?);
+            pos += 4;
+        }
+        if flags & 0x400 != 0 {
+            pos += 4;
+        }
+        if flags & 0x800 != 0 {
+            let offset = be_u32_checked(data, pos)?;
+
+            sample.composition_offset = if version == 0 {
+                offset as i64
+            } else {
+                offset as i32 as i64
+            };
+            pos += 4;
+        }
+
+        run.samples.push(sample);
+    }
+
+    Ok(run)
+}
+
+pub(super) fn read_box_header<R: Read>(
+    reader: &mut R,
+    start: u64,
+    file_size: u64,
+) -> Result<BoxHeader> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+
+    let mut size = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as u64;
+    let box_type = [buf[4], buf[5], buf[6], buf[7]];
+    let mut header_size = HEADER_SIZE;
+
+    if size == 1 {
+        let mut largesize = [0; 8];
+        reader.read_exact(&mut largesize)?;
+
+        size = u64::from_be_bytes(largesize);
+        header_size += 8;
+    } else if size == 0 {
+        size = file_size.saturating_sub(start);
+    }
+
+    let end = start.checked_add(size);
+
+    if size < header_size || end.map_or(true, |end| end > file_size) {
+        bail!(
+            "MP4: Invalid box '{}' at offset {start}",
+            String::from_utf8_lossy(&box_type)
+        );
+    }
+
+    Ok(BoxHeader {
+        box_type,
+        start,
+        size,
+        header_size,
+    })
+}
+
+pub(super) fn read_box_payload<R: Read + Seek>(
+    reader: &mut R,
+    header: &BoxHeader,
+) -> Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(header.start + header.header_size))?;
+
+    let mut data = vec![0; (header.size - header.header_size) as usize];
+    reader.read_exact(&mut data)?;
+
+    Ok(data)
+}
+
+/// Iterates the boxes contained in a parent box payload
+pub(super) fn child_boxes(data: &[u8]) -> impl Iterator<Item = (BoxHeader, &[u8])> {
+    let mut pos = 0;
+
+    std::iter::from_fn(move || {
+        if pos + HEADER_SIZE as usize > data.len() {
+            return None;
+        }
+
+        let mut size = be_u32(data, pos) as usize;
+        let box_type = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+        let mut header_size = HEADER_SIZE as usize;
+
+        if size == 1 {
+            size = be_u64(data, pos + 8).ok()? as usize;
+            header_size += 8;
+        } else if size == 0 {
+            size = data.len() - pos;
+        }
+
+        if size < header_size || pos + size > data.len() {
+            return None;
+        }
+
+        let header = BoxHeader {
+            box_type,
+            start: pos as u64,
+            size: size as u64,
+            header_size: header_size as u64,
+        };
+        let payload = &data[pos + header_size..pos + size];
+
+        pos += size;
+
+        Some((header, payload))
+    })
+}
+
+/// Finds the payload of a nested box, following the path of box types
+pub(super) fn find_box<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    let (first, rest) = path.split_first()?;
+
+    let (_, payload) = child_boxes(data).find(|(header, _)| &&header.box_type == first)?;
+
+    if rest.is_empty() {
+        Some(payload)
+    } else {
+        find_box(payload, rest)
+    }
+}
+
+pub(super) fn be_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+pub(super) fn be_u32_checked(data: &[u8], pos: usize) -> Result<u32> {
+    ensure!(pos + 4 <= data.len(), "MP4: Unexpected end of box");
+
+    Ok(be_u32(data, pos))
+}
+
+pub(super) fn be_u64(data: &[u8], pos: usize) -> Result<u64> {
+    ensure!(pos + 8 <= data.len(), "MP4: Unexpected end of box");
+
+    Ok(((be_u32(data, pos) as u64) << 32) | be_u32(data, pos + 4) as u64)
+}
@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use dolby_vision::rpu::extension_metadata::blocks::ExtMetadataBlock;
+use dolby_vision::rpu::generate::Annotation;
+
+use super::{DoviRpu, FramePropsFormat};
+
+const VAPOURSYNTH_SCRIPT_HEADER: &str = r#"# Generated by dovi_tool
+# Applies the Dolby Vision RPU metadata as frame props, usage:
+#   clip = apply_dovi_props(clip)
+import json
+
+import vapoursynth as vs
+
+core = vs.core
+
+DOVI_FRAME_PROPS = json.loads(r"""
+"#;
+
+const VAPOURSYNTH_SCRIPT_FOOTER: &str = r#""""
+)
+
+
+def apply_dovi_props(clip: vs.VideoNode) -> vs.VideoNode:
+    if clip.num_frames != len(DOVI_FRAME_PROPS):
+        raise ValueError(
+            f"Clip has {clip.num_frames} frames, the RPU has {len(DOVI_FRAME_PROPS)}"
+        )
+
+    def set_props(n: int, f: vs.VideoFrame) -> vs.VideoFrame:
+        fout = f.copy()
+        fout.props.update(DOVI_FRAME_PROPS[n])
+        return fout
+
+    return core.std.ModifyFrame(clip, clip, set_props)
+"#;
+
+/// Per-frame metadata, named as VapourSynth/AviSynth frame props
+#[derive(Serialize, Debug, Default)]
+pub struct FrameProps {
+    #[serde(rename = "DolbyVisionSceneCut")]
+    scene_cut: u8,
+
+    #[serde(rename = "DolbyVisionL1MinPQ")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    l1_min_pq: Option<u16>,
+    #[serde(rename = "DolbyVisionL1MaxPQ")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    l1_max_pq: Option<u16>,
+    #[serde(rename = "DolbyVisionL1AvgPQ")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    l1_avg_pq: Option<u16>,
+
+    #[serde(rename = "DolbyVisionL5Left")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    l5_left: Option<u16>,
+    #[serde(rename = "DolbyVisionL5Right")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    l5_right: Option<u16>,
+    #[serde(rename = "DolbyVisionL5Top")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    l5_top: Option<u16>,
+    #[serde(rename = "DolbyVisionL5Bottom")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    l5_bottom: Option<u16>,
+
+    #[serde(rename = "DolbyVisionAnnotation")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotation: Option<String>,
+}
+
+impl FrameProps {
+    pub fn from_rpu(rpu: &DoviRpu) -> Self {
+        let mut props = Self::default();
+
+        let Some(vdr_dm_data) = &rpu.vdr_dm_data else {
+            return props;
+        };
+
+        props.scene_cut = (vdr_dm_data.scene_refresh_flag == 1) as u8;
+
+        if let Some(ExtMetadataBlock::Level1(l1)) = vdr_dm_data.get_block(1) {
+            props.l1_min_pq = Some(l1.min_pq);
+            props.l1_max_pq = Some(l1.max_pq);
+            props.l1_avg_pq = Some(l1.avg_pq);
+        }
+
+        if let Some(ExtMetadataBlock::Level5(l5)) = vdr_dm_data.get_block(5) {
+            let (left, right, top, bottom) = l5.get_offsets();
+
+            props.l5_left = Some(left);
+            props.l5_right = Some(right);
+            props.l5_top = Some(top);
+            props.l5_bottom = Some(bottom);
+        }
+
+        props
+    }
+
+    /// Writes the props of every frame, one JSON object per line.
+    ///
+    /// The VapourSynth script embeds the same JSON, to keep the frames in sync.
+    /// The text of the annotations covering a frame is joined in a single prop.
+    pub fn write_file<P: AsRef<Path>>(
+        path: P,
+        rpus: &[DoviRpu],
+        format: FramePropsFormat,
+        annotations: &[Annotation],
+    ) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        if format == FramePropsFormat::Vapoursynth {
+            writer.write_all(VAPOURSYNTH_SCRIPT_HEADER.as_bytes())?;
+        }
+
+        writeln!(writer, "[")?;
+
+        for (i, rpu) in rpus.iter().enumerate() {
+            let mut props = Self::from_rpu(rpu);
+
+            let texts = Annotation::frame_texts(annotations, i);
+
+            if !texts.is_empty() {
+                props.annotation = Some(texts.join("; "));
+            }
+
+            serde_json::to_writer(&mut writer, &props)?;
+
+            if i + 1 < rpus.len() {
+                writeln!(writer, ",")?;
+            } else {
+                writeln!(writer)?;
+            }
+        }
+
+        writeln!(writer, "]")?;
+
+        if format == FramePropsFormat::Vapoursynth {
+            writer.write_all(VAPOURSYNTH_SCRIPT_FOOTER.as_bytes())?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{bail, ensure, Result};
+
+use dolby_vision::utils::frames_to_timecode;
+
+/// Set from `--timestamp-log`, for the reports of every command
+static TIMESTAMPS: Mutex<Option<FrameTimestamps>> = Mutex::new(None);
+
+/// Timecodes of the frames, to find them in an editing software
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameTimestamps {
+    /// Constant frame rate, non drop-frame `HH:MM:SS:FF` timecodes
+    Fps(f64),
+    /// Presentation time of every frame in display order, in milliseconds.
+    /// The timecodes are `HH:MM:SS.mmm` wall-clock times.
+    Times(Vec<f64>),
+}
+
+impl FrameTimestamps {
+    /// Frame rate like `23.976` or `24000/1001`, otherwise a timestamps file
+    pub fn parse(value: &str) -> Result<Self> {
+        let fps = match value.split_once('/') {
+            Some((num, den)) => match (num.trim().parse::<f64>(), den.trim().parse::<f64>()) {
+                (Ok(num), Ok(den)) if den > 0.0 => Some(num / den),
+                _ => None,
+            },
+            None => value.trim().parse::<f64>().ok(),
+        };
+
+        match fps {
+            Some(fps) => {
+                ensure!(
+                    fps.is_finite() && fps > 0.0,
+                    "Invalid timestamp log frame rate {value}"
+                );
+
+                Ok(Self::Fps(fps))
+            }
+            None => Self::from_file(value),
+        }
+    }
+
+    /// Timestamps file in the mkvextract v2 format: one time in milliseconds per line,
+    /// the `#` lines being comments. The times are sorted, in case they are in decoding order.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+
+        if !path.is_file() {
+            bail!(
+                "Timestamp log {} is neither a frame rate nor a file",
+                path.display()
+            );
+        }
+
+        let mut times = Vec::new();
+
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.parse::<f64>() {
+                Ok(time) if time.is_finite() && time >= 0.0 => times.push(time),
+                _ => bail!("Timestamp log line {}: invalid time '{line}'", i + 1),
+            }
+        }
+
+        ensure!(
+            !times.is_empty(),
+            "Timestamp log {} is empty",
+            path.display()
+        );
+        times.sort_by(f64::total_cmp);
+
+        Ok(Self::Times(times))
+    }
+
+    /// Timecode of the frame, none past the end of the timestamps file
+    pub fn timecode(&self, frame: usize) -> Option<String> {
+        match self {
+            Self::Fps(fps) => Some(frames_to_timecode(frame, *fps)),
+            Self::Times(times) => times.get(frame).map(|&ms| format_time(ms)),
+        }
+    }
+}
+
+pub fn set_timestamps(timestamps: Option<FrameTimestamps>) {
+    *TIMESTAMPS.lock().unwrap() = timestamps;
+}
+
+fn timecode(frame: usize) -> Option<String> {
+    TIMESTAMPS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|timestamps| timestamps.timecode(frame))
+}
+
+/// Frame number, followed by its timecode with `--timestamp-log`
+pub fn frame_label(frame: usize) -> String {
+    match timecode(frame) {
+        Some(timecode) => format!("{frame} ({timecode})"),
+        None => frame.to_string(),
+    }
+}
+
+/// Inclusive frame range, followed by its timecodes with `--timestamp-log`
+pub fn range_label(start: usize, end: usize) -> String {
+    match (timecode(start), timecode(end)) {
+        (Some(start_tc), Some(end_tc)) => format!("{start}-{end} ({start_tc} - {end_tc})"),
+        _ => format!("{start}-{end}"),
+    }
+}
+
+/// `HH:MM:SS.mmm`
+fn format_time(ms: f64) -> String {
+    let ms = ms.round() as u64;
+    let seconds = ms / 1000;
+
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60,
+        ms % 1000
+    )
+}
@@ -1,17 +1,27 @@
-use std::io::{stdout, BufRead, BufReader, BufWriter, Write};
+use std::collections::BTreeMap;
+use std::io::{self, stdout, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 use std::{fs::File, path::Path};
 
 use anyhow::{bail, Result};
 use indicatif::ProgressBar;
 
-use hevc_parser::hevc::{NALUnit, NAL_SEI_PREFIX, NAL_UNSPEC62, NAL_UNSPEC63};
+use hevc_parser::hevc::{
+    NALUnit, NAL_IDR_N_LP, NAL_IDR_W_RADL, NAL_SEI_PREFIX, NAL_UNSPEC62, NAL_UNSPEC63, NAL_VPS,
+};
 use hevc_parser::io::{processor, IoFormat, IoProcessor};
 use hevc_parser::HevcParser;
 use processor::{HevcProcessor, HevcProcessorOpts};
 
+use crate::ipc;
+
+use super::checkpoint::Checkpointer;
 use super::hdr10plus_utils::prefix_sei_removed_hdr10plus_nalu;
-use super::{convert_encoded_from_opts, CliOptions, WriteStartCodePreset};
+use super::sei_static_metadata::SeiStaticMetadata;
+use super::telemetry::{ConversionTelemetry, RpuSnapshot};
+use dolby_vision::rpu::dovi_rpu::DoviRpu;
+
+use super::{convert_rpu_from_opts, CliOptions, MetadataRepeater, WriteStartCodePreset};
 
 pub struct DoviProcessor {
     input: PathBuf,
@@ -21,16 +31,43 @@ pub struct DoviProcessor {
     payload_count: usize,
     previous_frame_index: u64,
     previous_rpu_index: u64,
+    current_frame_is_idr: bool,
+    current_frame_temporal_id: Option<u8>,
+
+    // Access units and RPUs found per temporal ID
+    temporal_layers: BTreeMap<u8, TemporalLayerStats>,
+
+    metadata_repeater: Option<MetadataRepeater>,
+    telemetry: ConversionTelemetry,
+
+    // ST 2086 SEI values to set as L6 metadata
+    sei_static_metadata: SeiStaticMetadata,
+
+    // Last valid RPU, to substitute corrupt ones in lenient mode
+    previous_rpu_data: Option<Vec<u8>>,
 
     progress_bar: ProgressBar,
     dovi_writer: DoviWriter,
+    checkpointer: Option<Checkpointer>,
 }
 
 pub struct DoviWriter {
-    bl_writer: Option<BufWriter<File>>,
-    el_writer: Option<BufWriter<File>>,
-    rpu_writer: Option<BufWriter<File>>,
-    sl_writer: Option<BufWriter<File>>,
+    bl_writer: Option<Box<dyn Write>>,
+    el_writer: Option<Box<dyn Write>>,
+    rpu_writer: Option<Box<dyn Write>>,
+    sl_writer: Option<Box<dyn Write>>,
+}
+
+/// Keeps the first read error of `reader`
+struct CheckedReader<'a> {
+    reader: &'a mut dyn Read,
+    error: Option<io::Error>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct TemporalLayerStats {
+    frames: usize,
+    rpus: usize,
 }
 
 #[derive(Debug)]
@@ -48,32 +85,18 @@ impl DoviWriter {
         single_layer_out: Option<P>,
     ) -> DoviWriter {
         let chunk_size = 100_000;
-        let bl_writer = bl_out.map(|bl_out| {
-            BufWriter::with_capacity(
+        let file_writer = |path: P, msg: &str| -> Box<dyn Write> {
+            Box::new(BufWriter::with_capacity(
                 chunk_size,
-                File::create(bl_out).expect("Can't create file for BL"),
-            )
-        });
-
-        let el_writer = el_out.map(|el_out| {
-            BufWriter::with_capacity(
-                chunk_size,
-                File::create(el_out).expect("Can't create file for EL"),
-            )
-        });
-
-        let rpu_writer = rpu_out.map(|rpu_out| {
-            BufWriter::with_capacity(
-                chunk_size,
-                File::create(rpu_out).expect("Can't create file for RPU"),
-            )
-        });
+                File::create(path).expect(msg),
+            ))
+        };
 
+        let bl_writer = bl_out.map(|bl_out| file_writer(bl_out, "Can't create file for BL"));
+        let el_writer = el_out.map(|el_out| file_writer(el_out, "Can't create file for EL"));
+        let rpu_writer = rpu_out.map(|rpu_out| file_writer(rpu_out, "Can't create file for RPU"));
         let sl_writer = single_layer_out.map(|single_layer_out| {
-            BufWriter::with_capacity(
-                chunk_size,
-                File::create(single_layer_out).expect("Can't create file for SL output"),
-            )
+            file_writer(single_layer_out, "Can't create file for SL output")
         });
 
         DoviWriter {
@@ -83,6 +106,54 @@ impl DoviWriter {
             sl_writer,
         }
     }
+
+    /// Demux outputs already opened, like the checkpoint writers
+    pub fn demux_writers(
+        bl_writer: Option<Box<dyn Write>>,
+        el_writer: Box<dyn Write>,
+    ) -> DoviWriter {
+        DoviWriter {
+            bl_writer,
+            el_writer: Some(el_writer),
+            rpu_writer: None,
+            sl_writer: None,
+        }
+    }
+
+    /// Keeps the reordered RPUs in the processor, see `DoviProcessor::into_rpus`
+    pub fn rpu_sink() -> DoviWriter {
+        DoviWriter {
+            bl_writer: None,
+            el_writer: None,
+            rpu_writer: Some(Box::new(io::sink())),
+            sl_writer: None,
+        }
+    }
+
+    /// Discards the single layer output, for dry runs
+    pub fn single_layer_sink() -> DoviWriter {
+        DoviWriter {
+            bl_writer: None,
+            el_writer: None,
+            rpu_writer: None,
+            sl_writer: Some(Box::new(io::sink())),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let writers = [
+            &mut self.bl_writer,
+            &mut self.el_writer,
+            &mut self.rpu_writer,
+            &mut self.sl_writer,
+        ];
+
+        for writer in writers.into_iter().flatten() {
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
 }
 
 impl DoviProcessor {
@@ -91,7 +162,10 @@ impl DoviProcessor {
         input: PathBuf,
         dovi_writer: DoviWriter,
         progress_bar: ProgressBar,
+        telemetry: ConversionTelemetry,
     ) -> DoviProcessor {
+        let metadata_repeater = options.repeat_metadata.map(MetadataRepeater::new);
+
         DoviProcessor {
             input,
             options,
@@ -99,12 +173,56 @@ impl DoviProcessor {
             payload_count: 0,
             previous_frame_index: 0,
             previous_rpu_index: 0,
+            current_frame_is_idr: false,
+            current_frame_temporal_id: None,
+            temporal_layers: BTreeMap::new(),
+            metadata_repeater,
+            telemetry,
+            sei_static_metadata: SeiStaticMetadata::default(),
+            previous_rpu_data: None,
             progress_bar,
             dovi_writer,
+            checkpointer: None,
         }
     }
 
+    /// Writes checkpoints at the access unit boundaries
+    pub fn set_checkpointer(&mut self, checkpointer: Checkpointer) {
+        self.checkpointer = Some(checkpointer);
+    }
+
     pub fn read_write_from_io(&mut self, format: &IoFormat) -> Result<()> {
+        let stdin = std::io::stdin();
+        let mut reader = Box::new(stdin.lock()) as Box<dyn BufRead>;
+
+        if let IoFormat::Raw = format {
+            let mut file = File::open(&self.input)?;
+
+            if let Some(offset) = self
+                .checkpointer
+                .as_ref()
+                .and_then(|checkpointer| checkpointer.resumed_input_offset())
+            {
+                file.seek(SeekFrom::Start(offset))?;
+            }
+
+            let file_reader = BufReader::with_capacity(100_000, file);
+
+            reader = match &self.checkpointer {
+                Some(checkpointer) => Box::new(checkpointer.input_reader(file_reader)),
+                None => Box::new(file_reader),
+            };
+        }
+
+        self.read_write_from_reader(format, &mut reader)
+    }
+
+    /// Processes an input already opened, like a stream demuxed while reading
+    pub fn read_write_from_reader(
+        &mut self,
+        format: &IoFormat,
+        reader: &mut dyn Read,
+    ) -> Result<()> {
         let chunk_size = 100_000;
 
         let processor_opts = HevcProcessorOpts {
@@ -113,21 +231,41 @@ impl DoviProcessor {
         };
         let mut processor = HevcProcessor::new(format.clone(), processor_opts, chunk_size);
 
-        let stdin = std::io::stdin();
-        let mut reader = Box::new(stdin.lock()) as Box<dyn BufRead>;
+        // The processor stops at a read error as if at the end of the input
+        let mut reader = CheckedReader {
+            reader,
+            error: None,
+        };
 
-        if let IoFormat::Raw = format {
-            let file = File::open(&self.input)?;
-            reader = Box::new(BufReader::with_capacity(100_000, file));
+        let res = processor.process_io(&mut reader, self);
+
+        match reader.error {
+            Some(e) => Err(e.into()),
+            None => res,
         }
+    }
 
-        processor.process_io(&mut reader, self)
+    /// The RPUs in presentation order, without the NAL header as in RPU files.
+    ///
+    /// Only available once processed, with the `rpu_writer` set.
+    pub fn into_rpus(self) -> Vec<Vec<u8>> {
+        self.rpu_nals.into_iter().map(|rpu| rpu.data).collect()
     }
 
     pub fn write_nals(&mut self, chunk: &[u8], nals: &[NALUnit]) -> Result<()> {
+        let chunk_offset = self.checkpointer.as_ref().map_or(0, |checkpointer| {
+            checkpointer.input_position() - chunk.len() as u64
+        });
+
         for (i, nal) in nals.iter().enumerate() {
             let mut nalu_data_override = None;
 
+            // The SEI precede the RPU of the first frame
+            if self.options.l6_from_sei && nal.nal_type == NAL_SEI_PREFIX {
+                self.sei_static_metadata
+                    .parse_sei_nalu(&chunk[nal.start..nal.end])?;
+            }
+
             if self.options.drop_hdr10plus && nal.nal_type == NAL_SEI_PREFIX {
                 let (has_st2094_40, data) = prefix_sei_removed_hdr10plus_nalu(chunk, nal)?;
 
@@ -144,25 +282,65 @@ impl DoviProcessor {
                 && nal.nal_type == NAL_UNSPEC62
                 && nal.decoded_frame_index == self.previous_rpu_index
             {
-                println!(
-                    "Warning: Unexpected RPU NALU found for frame {}. Discarding.",
+                self.telemetry.warning(format!(
+                    "Unexpected RPU NALU found for frame {}. Discarding.",
                     self.previous_rpu_index
-                );
+                ));
 
                 continue;
             }
 
             // First NAL of stream, or frame
-            let first_nal_of_frame =
-                if i == 0 && self.payload_count == 0 && self.previous_frame_index == 0 {
-                    true
-                } else if self.previous_frame_index != nal.decoded_frame_index {
-                    self.previous_frame_index = nal.decoded_frame_index;
+            let stream_start = i == 0 && self.payload_count == 0 && self.previous_frame_index == 0;
+            let first_nal_of_frame = if stream_start {
+                true
+            } else if self.previous_frame_index != nal.decoded_frame_index {
+                self.previous_frame_index = nal.decoded_frame_index;
+
+                true
+            } else {
+                false
+            };
+
+            if let Some(checkpointer) = self.checkpointer.as_mut() {
+                // The previous access unit is completely written
+                if first_nal_of_frame && !stream_start {
+                    let nal_offset = chunk_offset + (nal.start - nal.start_code.size()) as u64;
+                    checkpointer.frame_done(nal_offset);
+                }
 
-                    true
-                } else {
-                    false
-                };
+                // The parser can restart from an access unit with parameter sets before its slices
+                let parameter_sets = nal.nal_type == NAL_VPS
+                    && (first_nal_of_frame || self.current_frame_temporal_id.is_none());
+
+                if parameter_sets {
+                    checkpointer.checkpoint(|| self.dovi_writer.flush())?;
+                }
+            }
+
+            if first_nal_of_frame {
+                self.current_frame_is_idr = false;
+                self.current_frame_temporal_id = None;
+            }
+
+            // Slices of an access unit all have the same temporal ID
+            if nal.nal_type < 32 && self.current_frame_temporal_id.is_none() {
+                let temporal_id = nal_temporal_id(&chunk[nal.start..nal.end]);
+
+                self.current_frame_temporal_id = Some(temporal_id);
+                self.temporal_layers.entry(temporal_id).or_default().frames += 1;
+            }
+
+            if matches!(nal.nal_type, NAL_IDR_W_RADL | NAL_IDR_N_LP) {
+                self.current_frame_is_idr = true;
+            }
+
+            // Lenient mode always parses the RPUs, to detect corrupt ones
+            let process_rpu = self.options.mode.is_some()
+                || self.options.edit_config.is_some()
+                || self.metadata_repeater.is_some()
+                || self.options.lenient
+                || self.options.l6_from_sei;
 
             let final_chunk_data = nalu_data_override
                 .as_ref()
@@ -174,11 +352,22 @@ impl DoviProcessor {
                     continue;
                 }
 
-                if nal.nal_type == NAL_UNSPEC62
-                    && (self.options.mode.is_some() || self.options.edit_config.is_some())
-                {
-                    let modified_data =
-                        convert_encoded_from_opts(&self.options, &chunk[nal.start..nal.end])?;
+                if nal.nal_type == NAL_UNSPEC62 && process_rpu {
+                    let res = Self::process_rpu_nalu(
+                        &self.options,
+                        self.metadata_repeater.as_mut(),
+                        &self.sei_static_metadata,
+                        &mut self.telemetry,
+                        self.current_frame_is_idr,
+                        &chunk[nal.start..nal.end],
+                    );
+                    let modified_data = Self::recover_rpu_nalu(
+                        res,
+                        &self.options,
+                        &mut self.previous_rpu_data,
+                        &mut self.telemetry,
+                        nal.decoded_frame_index,
+                    )?;
 
                     NALUnit::write_with_preset(
                         sl_writer,
@@ -191,6 +380,10 @@ impl DoviProcessor {
                     continue;
                 }
 
+                if nal.nal_type == NAL_UNSPEC62 {
+                    self.telemetry.record_unprocessed(final_chunk_data);
+                }
+
                 NALUnit::write_with_preset(
                     sl_writer,
                     final_chunk_data,
@@ -219,15 +412,45 @@ impl DoviProcessor {
                     self.previous_rpu_index = nal.decoded_frame_index;
                     let rpu_data = &chunk[nal.start..nal.end];
 
+                    let temporal_id = self
+                        .current_frame_temporal_id
+                        .unwrap_or_else(|| nal_temporal_id(rpu_data));
+                    self.temporal_layers.entry(temporal_id).or_default().rpus += 1;
+
+                    if self.is_skipped_layer(temporal_id) {
+                        continue;
+                    }
+
+                    // Frames of the skipped layers are not matched with a RPU
+                    let decoded_index = if self.options.temporal_id.is_some() {
+                        nal.decoded_frame_index as usize
+                    } else {
+                        self.rpu_nals.len()
+                    };
+
                     // No mode: Copy
                     // Mode 0: Parse, untouched
-                    if self.options.mode.is_some() || self.options.edit_config.is_some() {
-                        let modified_data = convert_encoded_from_opts(&self.options, rpu_data)?;
+                    if process_rpu {
+                        let res = Self::process_rpu_nalu(
+                            &self.options,
+                            self.metadata_repeater.as_mut(),
+                            &self.sei_static_metadata,
+                            &mut self.telemetry,
+                            self.current_frame_is_idr,
+                            rpu_data,
+                        );
+                        let modified_data = Self::recover_rpu_nalu(
+                            res,
+                            &self.options,
+                            &mut self.previous_rpu_data,
+                            &mut self.telemetry,
+                            nal.decoded_frame_index,
+                        )?;
 
                         if let Some(_rpu_writer) = self.dovi_writer.rpu_writer.as_mut() {
                             // RPU for x265, remove 0x7C01
                             self.rpu_nals.push(RpuNal {
-                                decoded_index: self.rpu_nals.len(),
+                                decoded_index,
                                 presentation_number: 0,
                                 data: modified_data[2..].to_owned(),
                             });
@@ -242,13 +465,17 @@ impl DoviProcessor {
                             )?;
                         }
                     } else if let Some(_rpu_writer) = self.dovi_writer.rpu_writer.as_mut() {
+                        self.telemetry.record_unprocessed(rpu_data);
+
                         // RPU for x265, remove 0x7C01
                         self.rpu_nals.push(RpuNal {
-                            decoded_index: self.rpu_nals.len(),
+                            decoded_index,
                             presentation_number: 0,
                             data: rpu_data[2..].to_vec(),
                         });
                     } else if let Some(el_writer) = self.dovi_writer.el_writer.as_mut() {
+                        self.telemetry.record_unprocessed(rpu_data);
+
                         // RPU should never be first NAL
                         NALUnit::write_with_preset(
                             el_writer,
@@ -276,6 +503,61 @@ impl DoviProcessor {
         Ok(())
     }
 
+    fn process_rpu_nalu(
+        options: &CliOptions,
+        metadata_repeater: Option<&mut MetadataRepeater>,
+        sei_static_metadata: &SeiStaticMetadata,
+        telemetry: &mut ConversionTelemetry,
+        is_idr: bool,
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let mut dovi_rpu = DoviRpu::parse_unspec62_nalu_with_opts(data, options.parse_opts)?;
+        let snapshot = RpuSnapshot::new(&dovi_rpu);
+
+        convert_rpu_from_opts(options, &mut dovi_rpu)?;
+
+        if options.l6_from_sei {
+            sei_static_metadata.apply_to_rpu(&mut dovi_rpu)?;
+        }
+
+        if let Some(metadata_repeater) = metadata_repeater {
+            metadata_repeater.process(&mut dovi_rpu, is_idr)?;
+        }
+
+        telemetry.record_conversion(&snapshot, &dovi_rpu);
+
+        dovi_rpu.write_hevc_unspec62_nalu()
+    }
+
+    /// In lenient mode, replaces a RPU that failed to process
+    /// with the previous valid RPU, or a placeholder if there is none yet
+    pub fn recover_rpu_nalu(
+        res: Result<Vec<u8>>,
+        options: &CliOptions,
+        previous_rpu_data: &mut Option<Vec<u8>>,
+        telemetry: &mut ConversionTelemetry,
+        frame: u64,
+    ) -> Result<Vec<u8>> {
+        match res {
+            Ok(data) => {
+                if options.lenient {
+                    *previous_rpu_data = Some(data.clone());
+                }
+
+                Ok(data)
+            }
+            Err(e) if options.lenient => {
+                telemetry.record_corrupt(frame, &e);
+
+                match previous_rpu_data {
+                    Some(data) => Ok(data.clone()),
+                    None => DoviRpu::placeholder()?.write_hevc_unspec62_nalu(),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     fn flush_writer(&mut self, parser: &HevcParser) -> Result<()> {
         if let Some(bl_writer) = self.dovi_writer.bl_writer.as_mut() {
             bl_writer.flush()?;
@@ -285,15 +567,25 @@ impl DoviProcessor {
             el_writer.flush()?;
         }
 
+        if self.dovi_writer.rpu_writer.is_some() {
+            self.print_temporal_layers();
+        }
+
         // Reorder RPUs to display output order
         if let Some(rpu_writer) = self.dovi_writer.rpu_writer.as_mut() {
+            if let Some(max) = self.options.temporal_id {
+                if self.rpu_nals.is_empty() {
+                    bail!("No RPU found in the temporal layers up to {max}");
+                }
+            }
+
             let frames = parser.ordered_frames();
 
             if frames.is_empty() {
                 bail!("No frames parsed!");
             }
 
-            print!("Reordering metadata... ");
+            ipc::print("Reordering metadata... ");
             stdout().flush().ok();
 
             // Sort by matching frame POC
@@ -318,7 +610,7 @@ impl DoviProcessor {
                 .enumerate()
                 .for_each(|(idx, rpu)| rpu.presentation_number = idx);
 
-            println!("Done.");
+            ipc::log("Done.");
 
             // Write data to file
             for rpu in self.rpu_nals.iter_mut() {
@@ -335,8 +627,54 @@ impl DoviProcessor {
             rpu_writer.flush()?;
         }
 
+        if self.options.l6_from_sei {
+            if self.sei_static_metadata.is_empty() {
+                self.telemetry
+                    .warning("No ST 2086 SEI found, the L6 metadata was not modified");
+            } else {
+                ipc::log(format!(
+                    "L6 metadata set from SEI: {}",
+                    self.sei_static_metadata
+                ));
+            }
+        }
+
         Ok(())
     }
+
+    /// Access units above the selected temporal ID are not part of the sub-bitstream
+    fn is_skipped_layer(&self, temporal_id: u8) -> bool {
+        self.options
+            .temporal_id
+            .map_or(false, |max| temporal_id > max)
+    }
+
+    /// RPU presence per temporal layer, for streams with temporal scalability
+    fn print_temporal_layers(&mut self) {
+        if self.options.temporal_id.is_none() && self.temporal_layers.len() < 2 {
+            return;
+        }
+
+        ipc::log("Temporal layers:");
+
+        for (&temporal_id, stats) in &self.temporal_layers {
+            let skipped = self.is_skipped_layer(temporal_id);
+
+            ipc::log(format!(
+                "  {temporal_id}: {} frames, {} RPUs{}",
+                stats.frames,
+                stats.rpus,
+                if skipped { " (skipped)" } else { "" }
+            ));
+
+            if stats.rpus < stats.frames {
+                self.telemetry.warning(format!(
+                    "Temporal layer {temporal_id} has {} frames without RPU",
+                    stats.frames - stats.rpus
+                ));
+            }
+        }
+    }
 }
 
 impl IoProcessor for DoviProcessor {
@@ -357,6 +695,34 @@ impl IoProcessor for DoviProcessor {
 
     fn finalize(&mut self, parser: &HevcParser) -> Result<()> {
         self.progress_bar.finish_and_clear();
-        self.flush_writer(parser)
+        self.flush_writer(parser)?;
+
+        if let Some(checkpointer) = self.checkpointer.take() {
+            checkpointer.finish()?;
+        }
+
+        self.telemetry.finish(self.options.summary_json.as_ref())
+    }
+}
+
+impl Read for CheckedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf).map_err(|e| {
+            let kind = e.kind();
+
+            if self.error.is_none() {
+                self.error = Some(e);
+            }
+
+            kind.into()
+        })
+    }
+}
+
+/// `nuh_temporal_id_plus1` - 1, from the NALU header
+fn nal_temporal_id(data: &[u8]) -> u8 {
+    match data {
+        [_, second, ..] => (second & 0x07).saturating_sub(1),
+        _ => 0,
     }
 }
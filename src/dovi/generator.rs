@@ -1,17 +1,28 @@
-use anyhow::{bail, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use hdr10plus::metadata::{PeakBrightnessSource, VariablePeakBrightness};
 use hdr10plus::metadata_json::MetadataJsonRoot;
+use madvr_parse::MadVRFrame;
 use std::fs::File;
-use std::io::{stdout, Write};
+use std::io::{stdout, BufWriter, Write};
 use std::path::{Path, PathBuf};
 
+use super::json_file_reader;
+use super::rpu_info::percentile;
+use super::rpu_injector::RpuInjector;
+use super::y4m::measure_y4m;
+use super::{CliOptions, DoviRpu};
 use crate::commands::GenerateArgs;
+use crate::ipc;
 use dolby_vision::rpu::extension_metadata::blocks::{
-    ExtMetadataBlock, ExtMetadataBlockLevel1, ExtMetadataBlockLevel6,
+    ExtMetadataBlock, ExtMetadataBlockLevel1, ExtMetadataBlockLevel6, ExtMetadataBlockLevel8,
 };
-use dolby_vision::rpu::generate::{GenerateConfig, GenerateProfile, ShotFrameEdit, VideoShot};
-use dolby_vision::utils::nits_to_pq;
-use dolby_vision::xml::{CmXmlParser, XmlParserOpts};
+use dolby_vision::rpu::generate::{
+    GenerateConfig, GenerateProfile, Hdr10PlusPeakSource, MaxPqClip, ShotFrameEdit, VideoShot,
+};
+use dolby_vision::rpu::synthetic::synthetic_config;
+use dolby_vision::rpu::vdr_dm_data::CmVersion;
+use dolby_vision::utils::{nits_to_pq, nits_to_pq12, pq12_to_nits};
+use dolby_vision::xml::{CmXmlParser, Level5Rounding, XmlParserOpts};
 
 #[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GeneratorProfile {
@@ -23,19 +34,72 @@ pub enum GeneratorProfile {
     Profile84,
 }
 
+/// Log encoding of the luma statistics in a camera metadata CSV
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraLog {
+    /// Sony S-Log3
+    #[value(name = "slog3")]
+    SLog3,
+    /// ARRI LogC3, EI 800
+    #[value(name = "logc3")]
+    LogC3,
+}
+
+/// Transfer function of a y4m video
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Y4mTransfer {
+    /// SMPTE ST 2084 (PQ)
+    #[default]
+    Pq,
+    /// BT.2100 HLG, displayed on a 1000 nits display
+    Hlg,
+}
+
+/// Nits of a 100% reflectance scene white, the HDR reference white of BT.2408
+const CAMERA_REFERENCE_WHITE_NITS: f64 = 203.0;
+
+/// How to fill the frames of the config `length` not covered by the source
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BackfillPolicy {
+    /// Ignore the config length, only generate the frames of the source
+    #[default]
+    Truncate,
+    /// Extend the last shot of the source to the config length
+    RepeatLastShot,
+    /// Add a shot with the config default metadata for the missing frames
+    DefaultL1,
+    /// Fail when the source is shorter than the config length
+    Error,
+}
+
 #[derive(Default)]
 pub struct Generator {
     json_path: Option<PathBuf>,
     rpu_out: PathBuf,
+    inject: Option<(PathBuf, PathBuf)>,
+    cli_options: Option<CliOptions>,
     hdr10plus_path: Option<PathBuf>,
-    hdr10plus_peak_source: Option<PeakBrightnessSource>,
+    hdr10plus_peak_source: Option<Hdr10PlusPeakSource>,
     xml_path: Option<PathBuf>,
     canvas_width: Option<u16>,
     canvas_height: Option<u16>,
+    l5_rounding: Option<Level5Rounding>,
+    fix_negative_offsets: bool,
+    xml_streaming: bool,
+    static_metadata: Option<(usize, ExtMetadataBlockLevel6)>,
+    synthetic: Option<(usize, u64)>,
     madvr_path: Option<PathBuf>,
+    shots_path: Option<PathBuf>,
+    camera_csv: Option<(PathBuf, CameraLog)>,
+    y4m: Option<(PathBuf, Y4mTransfer, f64)>,
     use_custom_targets: bool,
+    l1_max_percentile: Option<f64>,
     profile: Option<GeneratorProfile>,
     long_play_mode: Option<bool>,
+    max_pq_clip: Option<MaxPqClip>,
+    auto_l9: Option<bool>,
+    auto_l11: Option<bool>,
+    backfill: BackfillPolicy,
 
     pub config: Option<GenerateConfig>,
 }
@@ -45,17 +109,52 @@ impl Generator {
         let GenerateArgs {
             json_file,
             rpu_out,
+            inject_into,
+            inject_output,
             hdr10plus_json,
             hdr10plus_peak_source,
             xml,
             canvas_width,
             canvas_height,
+            l5_rounding,
+            fix_negative_offsets,
+            xml_streaming,
+            length,
+            mdl_max,
+            mdl_min,
+            max_cll,
+            max_fall,
+            synthetic,
+            seed,
             madvr_file,
+            shots_file,
+            camera_csv,
+            camera_log,
+            from_y4m,
+            y4m_transfer,
+            scene_threshold,
             use_custom_targets,
+            l1_max_percentile,
             profile,
             long_play_mode,
+            max_pq_clip,
+            roll_off_knee,
+            auto_l9,
+            auto_l11,
+            backfill,
         } = args;
 
+        let max_pq_clip = max_pq_clip
+            .map(|max_nits| max_pq_clip_from_nits(max_nits, roll_off_knee))
+            .transpose()?;
+
+        let static_metadata = match (length, mdl_max) {
+            (Some(length), Some(mdl_max)) => {
+                Some((length, static_level6(mdl_max, mdl_min, max_cll, max_fall)?))
+            }
+            _ => None,
+        };
+
         let out_path = if let Some(out_path) = rpu_out {
             out_path
         } else {
@@ -65,36 +164,73 @@ impl Generator {
         let generator = Generator {
             json_path: json_file,
             rpu_out: out_path,
+            inject: inject_into.map(|video| {
+                let output = inject_output.unwrap_or_else(|| PathBuf::from("injected_output.hevc"));
+                (video, output)
+            }),
+            cli_options: None,
             hdr10plus_path: hdr10plus_json,
             hdr10plus_peak_source: hdr10plus_peak_source.map(From::from),
             xml_path: xml,
             canvas_width,
             canvas_height,
+            l5_rounding: l5_rounding.map(From::from),
+            fix_negative_offsets,
+            xml_streaming,
+            static_metadata,
+            synthetic: synthetic.map(|length| (length, seed)),
             madvr_path: madvr_file,
+            shots_path: shots_file,
+            camera_csv: camera_csv.zip(camera_log),
+            y4m: from_y4m.map(|path| (path, y4m_transfer, scene_threshold)),
             use_custom_targets,
+            l1_max_percentile,
             profile,
             config: None,
             long_play_mode,
+            max_pq_clip,
+            auto_l9,
+            auto_l11,
+            backfill,
         };
 
         Ok(generator)
     }
 
-    pub fn generate(args: GenerateArgs) -> Result<()> {
+    pub fn generate(args: GenerateArgs, cli_options: CliOptions) -> Result<()> {
         let mut generator = Generator::from_args(args)?;
+        generator.cli_options = Some(cli_options);
+
         generator.execute()
     }
 
     pub fn execute(&mut self) -> Result<()> {
-        let mut config = if let Some(json_path) = &self.json_path {
-            let json_file = File::open(json_path)?;
+        if self.xml_streaming {
+            return self.execute_xml_streaming();
+        }
+
+        let mut config = if self.json_path.is_some() || self.y4m.is_some() {
+            // The y4m measurements are enough without config
+            let mut config = match &self.json_path {
+                Some(json_path) => {
+                    let json_file = json_file_reader(json_path)?;
 
-            println!("Reading generate config file...");
-            let mut config: GenerateConfig = serde_json::from_reader(&json_file)?;
+                    ipc::log("Reading generate config file...");
+                    GenerateConfig::from_json_value(serde_json::from_reader(json_file)?)?
+                }
+                None => GenerateConfig::default(),
+            };
 
             // Set default to the config's CM version if it wasn't specified
             config.l1_avg_pq_cm_version.get_or_insert(config.cm_version);
 
+            let config_length = config.length;
+            let has_source = self.hdr10plus_path.is_some()
+                || self.madvr_path.is_some()
+                || self.shots_path.is_some()
+                || self.camera_csv.is_some()
+                || self.y4m.is_some();
+
             if let Some(hdr10plus_path) = &self.hdr10plus_path {
                 let peak_source = self
                     .hdr10plus_peak_source
@@ -102,12 +238,27 @@ impl Generator {
                     .expect("Missing required DR10+ peak source");
                 parse_hdr10plus_for_l1(hdr10plus_path, *peak_source, &mut config)?;
             } else if let Some(madvr_path) = &self.madvr_path {
-                generate_metadata_from_madvr(madvr_path, self.use_custom_targets, &mut config)?;
+                generate_metadata_from_madvr(
+                    madvr_path,
+                    self.use_custom_targets,
+                    self.l1_max_percentile,
+                    &mut config,
+                )?;
+            } else if let Some(shots_path) = &self.shots_path {
+                generate_metadata_from_shots_file(shots_path, &mut config)?;
+            } else if let Some((csv_path, camera_log)) = &self.camera_csv {
+                generate_metadata_from_camera_csv(csv_path, *camera_log, &mut config)?;
+            } else if let Some((y4m_path, transfer, scene_threshold)) = &self.y4m {
+                generate_metadata_from_y4m(y4m_path, *transfer, *scene_threshold, &mut config)?;
             } else if config.length == 0 && !config.shots.is_empty() {
                 // Set length from sum of shot durations
                 config.length = config.shots.iter().map(|s| s.duration).sum();
             }
 
+            if has_source {
+                backfill_shots(&mut config, config_length, self.backfill)?;
+            }
+
             ensure!(
                 config.length > 0 || !config.shots.is_empty(),
                 "Missing number of RPUs to generate, and no shots to derive it from"
@@ -125,112 +276,342 @@ impl Generator {
             config
         } else if let Some(xml_path) = &self.xml_path {
             self.config_from_xml(xml_path)?
+        } else if let Some((length, level6)) = &self.static_metadata {
+            ensure!(*length > 0, "Missing number of RPUs to generate");
+
+            GenerateConfig::from_static_metadata(*length, level6.clone())
+        } else if let Some((length, seed)) = self.synthetic {
+            ensure!(length > 0, "Missing number of RPUs to generate");
+
+            ipc::log(format!("Generating synthetic metadata with seed {seed}..."));
+            synthetic_config(seed, length)
         } else {
             bail!("Missing configuration or XML file!");
         };
 
-        // Override config with manual arg
-        if let Some(profile) = self.profile {
-            config.profile = GenerateProfile::from(profile);
-        }
-
-        if let Some(long_play_mode) = self.long_play_mode {
-            config.long_play_mode = long_play_mode
-        }
+        self.apply_overrides(&mut config);
 
         self.config = Some(config);
 
         if let Some(config) = self.config.as_mut() {
-            println!("Generating metadata: {}...", &config.profile);
+            ipc::log(format!("Generating metadata: {}...", &config.profile));
 
             // Correct L1 for sources other than XML
             if self.xml_path.is_none() {
                 config.fixup_l1();
             }
 
-            config.write_rpus(&self.rpu_out)?;
+            config.validate()?;
+
+            if let Some(warning) = config.long_play_mode_check().warning() {
+                ipc::warn(warning);
+            }
+
+            if let Some((video, output)) = &self.inject {
+                let cli_options = self
+                    .cli_options
+                    .clone()
+                    .unwrap_or_else(|| super::ops::cli_options(None));
+
+                ipc::log(format!(
+                    "Injecting the generated RPUs into {}...",
+                    video.display()
+                ));
+                RpuInjector::inject_generated(
+                    config.clone(),
+                    video.clone(),
+                    output.clone(),
+                    cli_options,
+                )?;
+            } else {
+                config.write_rpus(&self.rpu_out)?;
+            }
 
-            println!("Generated metadata for {} frames", config.length);
+            ipc::log(format!("Generated metadata for {} frames", config.length));
         } else {
             bail!("No generation config to execute!");
         }
 
-        println!("Done.");
+        ipc::log("Done.");
 
         Ok(())
     }
 
-    fn config_from_xml<P: AsRef<Path>>(&self, xml_path: P) -> Result<GenerateConfig> {
-        println!("Parsing XML metadata...");
-
-        let parser_opts = XmlParserOpts {
-            canvas_width: self.canvas_width,
-            canvas_height: self.canvas_height,
+    /// Generates the RPUs shot by shot while parsing the XML, without holding every shot
+    fn execute_xml_streaming(&mut self) -> Result<()> {
+        let xml_path = self
+            .xml_path
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing XML file!"))?;
+
+        ipc::log("Parsing XML metadata and generating shots...");
+
+        let mut writer = BufWriter::with_capacity(100_000, File::create(&self.rpu_out)?);
+        let mut generation: Option<(GenerateConfig, DoviRpu)> = None;
+        let mut next_start = None;
+
+        let parser = CmXmlParser::parse_file_streaming(
+            xml_path,
+            self.parser_opts(),
+            |xml_config, mut shot| {
+                // The record timeline doesn't necessarily start at 0
+                let expected_start = next_start.unwrap_or(shot.start);
+                ensure!(
+                    shot.start == expected_start,
+                    "Shot {} starts at frame {}, expected {}. Streaming requires the shots to be in order, without gaps",
+                    shot.id,
+                    shot.start,
+                    expected_start
+                );
+                next_start = Some(shot.start + shot.duration);
+
+                if generation.is_none() {
+                    let mut config = xml_config.clone();
+                    self.apply_overrides(&mut config);
+
+                    ipc::log(format!("Generating metadata: {}...", &config.profile));
+
+                    let base_rpu = config.base_rpu()?;
+                    generation = Some((config, base_rpu));
+                }
+
+                if let Some(clip) = self.max_pq_clip {
+                    shot.clip_max_pq(clip);
+                }
+
+                let (config, base_rpu) = generation.as_ref().unwrap();
+                config.write_shot_rpus(&mut writer, base_rpu, &shot)
+            },
+        )?;
+
+        writer.flush()?;
+        print_xml_warnings(&parser);
+
+        let Some((mut config, _)) = generation else {
+            bail!("No shots found in XML");
         };
+        config.length = parser.config.length;
+
+        ipc::log(format!("Generated metadata for {} frames", config.length));
+        self.config = Some(config);
+
+        ipc::log("Done.");
+
+        Ok(())
+    }
+
+    /// Overrides the config with the manual args
+    fn apply_overrides(&self, config: &mut GenerateConfig) {
+        if let Some(profile) = self.profile {
+            for warning in config.convert_profile(GenerateProfile::from(profile)) {
+                ipc::warn(warning);
+            }
+        }
+
+        if let Some(long_play_mode) = self.long_play_mode {
+            config.long_play_mode = long_play_mode
+        }
+
+        if let Some(auto_l9) = self.auto_l9 {
+            config.auto_blocks.level9 = auto_l9;
+        }
+
+        if let Some(auto_l11) = self.auto_l11 {
+            config.auto_blocks.level11 = auto_l11;
+        }
+
+        if let Some(clip) = self.max_pq_clip {
+            let modified = config.clip_max_pq(clip);
+
+            // The streamed shots are capped as they are parsed
+            if !self.xml_streaming {
+                ipc::log(format!(
+                    "Capped the L1 max at {:.0} nits in {modified} shots and frame edits",
+                    pq12_to_nits(clip.max_pq)
+                ));
+            }
+        }
+    }
+
+    fn config_from_xml<P: AsRef<Path>>(&self, xml_path: P) -> Result<GenerateConfig> {
+        ipc::log("Parsing XML metadata...");
 
-        let parser = CmXmlParser::parse_file(xml_path, parser_opts)?;
+        let parser = CmXmlParser::parse_file(xml_path, self.parser_opts())?;
+        print_xml_warnings(&parser);
 
         Ok(parser.config)
     }
+
+    fn parser_opts(&self) -> XmlParserOpts {
+        let mut opts = XmlParserOpts::default();
+
+        opts.canvas_width = self.canvas_width;
+        opts.canvas_height = self.canvas_height;
+        opts.l5_rounding = self.l5_rounding.unwrap_or_default();
+        opts.fix_negative_offsets = self.fix_negative_offsets;
+
+        opts
+    }
+}
+
+/// L1 max cap from the nits args
+fn max_pq_clip_from_nits(max_nits: f64, knee_nits: Option<f64>) -> Result<MaxPqClip> {
+    ensure!(
+        max_nits.is_finite() && max_nits > 0.0,
+        "Invalid --max-pq-clip {max_nits}, must be above 0 nits"
+    );
+
+    if let Some(knee_nits) = knee_nits {
+        ensure!(
+            knee_nits.is_finite() && (0.0..max_nits).contains(&knee_nits),
+            "Invalid --roll-off-knee {knee_nits}, must be between 0 and the --max-pq-clip {max_nits} nits"
+        );
+    }
+
+    Ok(MaxPqClip {
+        max_pq: nits_to_pq12(max_nits),
+        knee_pq: knee_nits.map(nits_to_pq12),
+    })
+}
+
+/// L6 metadata from the static metadata args, the min luminance is in nits
+fn static_level6(
+    mdl_max: u16,
+    mdl_min: Option<f64>,
+    max_cll: Option<u16>,
+    max_fall: Option<u16>,
+) -> Result<ExtMetadataBlockLevel6> {
+    let min_display_mastering_luminance = match mdl_min {
+        Some(nits) => {
+            ensure!(
+                nits.is_finite() && (0.0..1.0).contains(&nits),
+                "Invalid mastering display min luminance {}, must be between 0 and 1 nits",
+                nits
+            );
+
+            (nits * 10_000.0).round() as u16
+        }
+        None => 1,
+    };
+
+    let level6 = ExtMetadataBlockLevel6 {
+        max_display_mastering_luminance: mdl_max,
+        min_display_mastering_luminance,
+        max_content_light_level: max_cll.unwrap_or(0),
+        max_frame_average_light_level: max_fall.unwrap_or(0),
+    };
+
+    level6
+        .validate()
+        .map_err(|_| anyhow!("Invalid static metadata, the values must be at most 10000 nits"))?;
+
+    Ok(level6)
+}
+
+/// Fills the frames up to `config_length` when the source covers fewer frames.
+///
+/// Prints the frame accounting of the source and backfilled frames.
+fn backfill_shots(
+    config: &mut GenerateConfig,
+    config_length: usize,
+    policy: BackfillPolicy,
+) -> Result<()> {
+    let source_frames = config.length;
+
+    if config_length <= source_frames {
+        ipc::log(format!("Source covers {source_frames} frames"));
+        return Ok(());
+    }
+
+    let missing = config_length - source_frames;
+
+    match policy {
+        BackfillPolicy::Truncate => {
+            ipc::log(format!(
+                "Source covers {source_frames} of the {config_length} frames of the config, the length is truncated"
+            ));
+
+            return Ok(());
+        }
+        BackfillPolicy::Error => bail!(
+            "Source covers {} of the {} frames of the config, {} frames are missing",
+            source_frames,
+            config_length,
+            missing
+        ),
+        BackfillPolicy::RepeatLastShot => {
+            let last_shot = config
+                .shots
+                .last_mut()
+                .ok_or_else(|| anyhow!("No shot to repeat for backfilling"))?;
+            last_shot.duration += missing;
+
+            ipc::log(format!(
+                "Source covers {source_frames} of the {config_length} frames of the config, {missing} frames backfilled by repeating the last shot"
+            ));
+        }
+        BackfillPolicy::DefaultL1 => {
+            config.shots.push(VideoShot {
+                start: source_frames,
+                duration: missing,
+                ..Default::default()
+            });
+
+            ipc::log(format!(
+                "Source covers {source_frames} of the {config_length} frames of the config, {missing} frames backfilled with the default metadata"
+            ));
+        }
+    }
+
+    config.length = config_length;
+
+    Ok(())
 }
 
 fn parse_hdr10plus_for_l1<P: AsRef<Path>>(
     hdr10plus_path: P,
-    peak_source: PeakBrightnessSource,
+    peak_source: Hdr10PlusPeakSource,
     config: &mut GenerateConfig,
 ) -> Result<()> {
-    println!("Parsing HDR10+ JSON file...");
+    ipc::log("Parsing HDR10+ JSON file...");
     stdout().flush().ok();
 
-    let metadata_root = MetadataJsonRoot::from_file(&hdr10plus_path)?;
+    let metadata_root = read_hdr10plus_json(&hdr10plus_path)?;
 
     let frame_count = metadata_root.scene_info.len();
+    ensure!(frame_count > 0, "HDR10+ JSON has no frame metadata");
 
-    let mut scene_first_frames = metadata_root.scene_info_summary.scene_first_frame_index;
-    let first_frame_index = scene_first_frames
-        .first()
-        .cloned()
-        .expect("Missing SceneFirstFrameIndex array");
-
-    // Offset indices according to first index, since they should start at 0
-    scene_first_frames
-        .iter_mut()
-        .for_each(|i| *i -= first_frame_index);
+    let scenes = hdr10plus_scene_boundaries(&metadata_root);
+    let mut hdr10plus_shots = Vec::with_capacity(scenes.len());
 
-    let scene_frame_lengths = metadata_root.scene_info_summary.scene_frame_numbers;
+    for (frame_no, duration) in scenes {
+        let frame_meta = &metadata_root.scene_info[frame_no];
+        let config_shot = config.shots.get(hdr10plus_shots.len());
 
-    let mut hdr10plus_shots = Vec::with_capacity(scene_first_frames.len());
+        // The config shot can override the peak source
+        let shot_peak_source = config_shot
+            .and_then(|s| s.hdr10plus_peak_source)
+            .unwrap_or(peak_source);
 
-    let first_frames = metadata_root
-        .scene_info
-        .iter()
-        .enumerate()
-        .filter(|(frame_no, _)| scene_first_frames.contains(frame_no));
-
-    for (current_shot_id, (frame_no, frame_meta)) in first_frames.enumerate() {
         let avg_nits = frame_meta.luminance_parameters.average_rgb as f64 / 10.0;
-        let max_nits = frame_meta.peak_brightness_nits(peak_source).unwrap();
-
-        let min_pq = 0;
-        let max_pq = (nits_to_pq(max_nits.round()) * 4095.0).round() as u16;
-        let avg_pq = (nits_to_pq(avg_nits.round()) * 4095.0).round() as u16;
+        let max_nits = hdr10plus_peak_nits(frame_meta, shot_peak_source).ok_or_else(|| {
+            anyhow!("HDR10+ frame {frame_no}: no peak brightness for source {shot_peak_source:?}")
+        })?;
 
         let mut shot = VideoShot {
             start: frame_no,
-            duration: scene_frame_lengths[current_shot_id],
+            duration,
             metadata_blocks: vec![ExtMetadataBlock::Level1(
-                ExtMetadataBlockLevel1::from_stats_cm_version(
-                    min_pq,
-                    max_pq,
-                    avg_pq,
+                ExtMetadataBlockLevel1::from_normalized_pq_cm_version(
+                    0.0,
+                    nits_to_pq(max_nits.round()),
+                    nits_to_pq(avg_nits.round()),
                     config.l1_avg_pq_cm_version.unwrap(),
                 ),
             )],
             ..Default::default()
         };
 
-        let config_shot = config.shots.get(hdr10plus_shots.len());
-
         if let Some(override_shot) = config_shot {
             shot.copy_metadata_from_shot(override_shot, Some(&[1]))
         }
@@ -247,12 +628,200 @@ fn parse_hdr10plus_for_l1<P: AsRef<Path>>(
     Ok(())
 }
 
+/// Peak brightness of a HDR10+ frame in nits, from the chosen measurement
+fn hdr10plus_peak_nits<T: VariablePeakBrightness>(
+    frame_meta: &T,
+    source: Hdr10PlusPeakSource,
+) -> Option<f64> {
+    let peak = |source| frame_meta.peak_brightness_nits(source);
+
+    match source {
+        Hdr10PlusPeakSource::Histogram => peak(PeakBrightnessSource::Histogram),
+        Hdr10PlusPeakSource::Histogram99 => peak(PeakBrightnessSource::Histogram99),
+        Hdr10PlusPeakSource::MaxScl => peak(PeakBrightnessSource::MaxScl),
+        Hdr10PlusPeakSource::MaxSclLuminance => peak(PeakBrightnessSource::MaxSclLuminance),
+        Hdr10PlusPeakSource::Max => peak(PeakBrightnessSource::Histogram)
+            .into_iter()
+            .chain(peak(PeakBrightnessSource::MaxScl))
+            .reduce(f64::max),
+    }
+}
+
+/// Parses the HDR10+ JSON, tolerating a missing `SceneInfoSummary` object
+fn read_hdr10plus_json<P: AsRef<Path>>(hdr10plus_path: P) -> Result<MetadataJsonRoot> {
+    let file = json_file_reader(hdr10plus_path)?;
+    let mut value: serde_json::Value = serde_json::from_reader(file)?;
+
+    if let Some(root) = value.as_object_mut() {
+        if !root.contains_key("SceneInfoSummary") {
+            ipc::warn("HDR10+ JSON is missing SceneInfoSummary, using frame SceneId");
+
+            root.insert(
+                "SceneInfoSummary".to_string(),
+                serde_json::json!({
+                    "SceneFirstFrameIndex": [],
+                    "SceneFrameNumbers": [],
+                }),
+            );
+        }
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Returns the (start, duration) of every scene.
+///
+/// The `SceneInfoSummary` is used when it is consistent with the frame metadata.
+/// Otherwise, the scenes are reconstructed from the per-frame `SceneId`.
+fn hdr10plus_scene_boundaries(metadata_root: &MetadataJsonRoot) -> Vec<(usize, usize)> {
+    let frame_count = metadata_root.scene_info.len();
+    let summary = &metadata_root.scene_info_summary;
+
+    let mut first_frames = summary.scene_first_frame_index.clone();
+
+    // Offset indices according to first index, since they should start at 0
+    if let Some(first_frame_index) = first_frames.first().copied() {
+        first_frames
+            .iter_mut()
+            .for_each(|i| *i = i.saturating_sub(first_frame_index));
+    }
+
+    let lengths = &summary.scene_frame_numbers;
+
+    if !first_frames.is_empty() {
+        let consistent = first_frames.len() == lengths.len()
+            && lengths.iter().all(|len| *len > 0)
+            && lengths.iter().sum::<usize>() == frame_count
+            && first_frames
+                .iter()
+                .zip(lengths)
+                .scan(0, |expected_start, (start, len)| {
+                    let valid = *start == *expected_start;
+                    *expected_start += len;
+
+                    Some(valid)
+                })
+                .all(|valid| valid);
+
+        if consistent {
+            return first_frames
+                .into_iter()
+                .zip(lengths.iter().copied())
+                .collect();
+        }
+
+        ipc::warn(
+            "HDR10+ SceneInfoSummary is inconsistent with the frame metadata, using frame SceneId",
+        );
+    }
+
+    let mut scenes: Vec<(usize, usize)> = Vec::new();
+    let mut current_scene_id = None;
+
+    for (frame_no, frame_meta) in metadata_root.scene_info.iter().enumerate() {
+        if current_scene_id == Some(frame_meta.scene_id) {
+            if let Some(scene) = scenes.last_mut() {
+                scene.1 += 1;
+            }
+        } else {
+            current_scene_id = Some(frame_meta.scene_id);
+            scenes.push((frame_no, 1));
+        }
+    }
+
+    scenes
+}
+
+/// L8 trims of the shot to adjust for the custom targets.
+///
+/// The config shot trims take priority over the default ones, the default 100 nits trim is used otherwise.
+fn custom_target_level8_trims(
+    config: &GenerateConfig,
+    config_shot: Option<&VideoShot>,
+) -> Vec<ExtMetadataBlockLevel8> {
+    let level8_blocks = |blocks: &[ExtMetadataBlock]| -> Vec<ExtMetadataBlockLevel8> {
+        blocks
+            .iter()
+            .filter_map(|block| match block {
+                ExtMetadataBlock::Level8(l8) => Some(l8.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let shot_trims = config_shot
+        .map(|shot| level8_blocks(&shot.metadata_blocks))
+        .unwrap_or_default();
+
+    if !shot_trims.is_empty() {
+        return shot_trims;
+    }
+
+    let default_trims = level8_blocks(&config.default_metadata_blocks);
+
+    if default_trims.is_empty() {
+        vec![ExtMetadataBlockLevel8::default()]
+    } else {
+        default_trims
+    }
+}
+
+/// Frame edits setting the L1 max to the custom target of each frame.
+///
+/// With CM v4.0, the L8 trims of the shot are also emitted per frame,
+/// with the clip trim raised by the PQ range between the frame peak and its target.
+pub(crate) fn custom_target_frame_edits(
+    config: &GenerateConfig,
+    config_shot: Option<&VideoShot>,
+    frames: &[MadVRFrame],
+    scene_avg_pq: f64,
+) -> Vec<ShotFrameEdit> {
+    let level8_trims = if config.cm_version == CmVersion::V40 {
+        custom_target_level8_trims(config, config_shot)
+    } else {
+        Vec::new()
+    };
+
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let mut metadata_blocks = vec![ExtMetadataBlock::Level1(
+                ExtMetadataBlockLevel1::from_normalized_pq_cm_version(
+                    0.0,
+                    f.target_pq,
+                    scene_avg_pq,
+                    config.l1_avg_pq_cm_version.unwrap(),
+                ),
+            )];
+
+            metadata_blocks.extend(level8_trims.iter().map(|l8| {
+                ExtMetadataBlock::Level8(l8.with_clip_to_target(f.peak_pq_2020, f.target_pq))
+            }));
+
+            ShotFrameEdit {
+                edit_offset: i,
+                metadata_blocks,
+            }
+        })
+        .collect()
+}
+
 pub fn generate_metadata_from_madvr<P: AsRef<Path>>(
     madvr_path: P,
     use_custom_targets: bool,
+    l1_max_percentile: Option<f64>,
     config: &mut GenerateConfig,
 ) -> Result<()> {
-    println!("Parsing madVR measurement file...");
+    if let Some(p) = l1_max_percentile {
+        ensure!(
+            (0.0..=100.0).contains(&p),
+            "Invalid L1 max percentile {}, must be between 0 and 100",
+            p
+        );
+    }
+
+    ipc::log("Parsing madVR measurement file...");
     stdout().flush().ok();
 
     let madvr_info = madvr_parse::MadVRMeasurements::parse_file(madvr_path)?;
@@ -267,18 +836,23 @@ pub fn generate_metadata_from_madvr<P: AsRef<Path>>(
     let mut madvr_shots = Vec::with_capacity(madvr_info.scenes.len());
 
     for (i, scene) in madvr_info.scenes.iter().enumerate() {
-        let min_pq = 0;
-        let max_pq = (scene.max_pq * 4095.0).round() as u16;
-        let avg_pq = (scene.avg_pq * 4095.0).round() as u16;
+        let scene_max_pq = if let Some(p) = l1_max_percentile {
+            let frames = scene.get_frames(frame_count, &madvr_info.frames)?;
+            let mut peaks: Vec<f64> = frames.iter().map(|f| f.peak_pq_2020).collect();
+
+            percentile(&mut peaks, p)
+        } else {
+            scene.max_pq
+        };
 
         let mut shot = VideoShot {
             start: scene.start as usize,
             duration: scene.length,
             metadata_blocks: vec![ExtMetadataBlock::Level1(
-                ExtMetadataBlockLevel1::from_stats_cm_version(
-                    min_pq,
-                    max_pq,
-                    avg_pq,
+                ExtMetadataBlockLevel1::from_normalized_pq_cm_version(
+                    0.0,
+                    scene_max_pq,
+                    scene.avg_pq,
                     config.l1_avg_pq_cm_version.unwrap(),
                 ),
             )],
@@ -291,25 +865,7 @@ pub fn generate_metadata_from_madvr<P: AsRef<Path>>(
             // Use peak per frame, average from scene
             let frames = scene.get_frames(frame_count, &madvr_info.frames)?;
 
-            frames.iter().enumerate().for_each(|(i, f)| {
-                let min_pq = 0;
-                let max_pq = (f.target_pq * 4095.0).round() as u16;
-                let avg_pq = (scene.avg_pq * 4095.0).round() as u16;
-
-                let frame_edit = ShotFrameEdit {
-                    edit_offset: i,
-                    metadata_blocks: vec![ExtMetadataBlock::Level1(
-                        ExtMetadataBlockLevel1::from_stats_cm_version(
-                            min_pq,
-                            max_pq,
-                            avg_pq,
-                            config.l1_avg_pq_cm_version.unwrap(),
-                        ),
-                    )],
-                };
-
-                shot.frame_edits.push(frame_edit);
-            });
+            shot.frame_edits = custom_target_frame_edits(config, config_shot, frames, scene.avg_pq);
         }
 
         if let Some(override_shot) = config_shot {
@@ -339,6 +895,320 @@ pub fn generate_metadata_from_madvr<P: AsRef<Path>>(
     Ok(())
 }
 
+/// Parses shots from lines formatted as `start,duration,min_nits,max_nits,avg_nits`.
+/// Empty lines and lines starting with `#` are ignored.
+pub fn generate_metadata_from_shots_file<P: AsRef<Path>>(
+    shots_path: P,
+    config: &mut GenerateConfig,
+) -> Result<()> {
+    ipc::log("Parsing shots file...");
+    stdout().flush().ok();
+
+    let content = std::fs::read_to_string(shots_path)?;
+
+    let to_pq = |nits: f64| (nits_to_pq(nits) * 4095.0).round() as u16;
+    let mut shots = Vec::new();
+
+    let lines = content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    for (line_no, line) in lines {
+        let values: Vec<&str> = line.split(',').map(str::trim).collect();
+        ensure!(
+            values.len() == 5,
+            "Shots file line {}: expected 5 values `start,duration,min_nits,max_nits,avg_nits`, found {}",
+            line_no,
+            values.len()
+        );
+
+        let start: usize = values[0]
+            .parse()
+            .map_err(|e| anyhow!("Shots file line {}: invalid start: {}", line_no, e))?;
+        let duration: usize = values[1]
+            .parse()
+            .map_err(|e| anyhow!("Shots file line {}: invalid duration: {}", line_no, e))?;
+
+        let mut nits = [0.0; 3];
+        for (value, nits) in values[2..].iter().zip(nits.iter_mut()) {
+            *nits = value
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| anyhow!("Shots file line {}: invalid nits {}", line_no, value))?;
+        }
+
+        let [min_nits, max_nits, avg_nits] = nits;
+
+        let mut shot = VideoShot {
+            start,
+            duration,
+            metadata_blocks: vec![ExtMetadataBlock::Level1(
+                ExtMetadataBlockLevel1::from_stats_cm_version(
+                    to_pq(min_nits),
+                    to_pq(max_nits),
+                    to_pq(avg_nits),
+                    config.l1_avg_pq_cm_version.unwrap(),
+                ),
+            )],
+            ..Default::default()
+        };
+
+        if let Some(override_shot) = config.shots.get(shots.len()) {
+            shot.copy_metadata_from_shot(override_shot, Some(&[1]))
+        }
+
+        shots.push(shot);
+    }
+
+    ensure!(!shots.is_empty(), "Shots file has no shots");
+
+    // Now that the metadata was copied, we can replace the shots
+    config.shots.clear();
+    config.shots.extend(shots);
+
+    config.length = config.shots.iter().map(|s| s.duration).sum();
+
+    Ok(())
+}
+
+/// Parses consecutive clips from a camera metadata CSV, with a header row.
+///
+/// The `frames` column is the clip duration, `min`, `max` and `avg` are the luma statistics
+/// in percent of the camera log signal. Other columns, such as the clip name or exposure, are ignored.
+///
+/// The statistics are linearized and scaled so that a 100% reflectance white is 203 nits,
+/// which only approximates the graded L1 metadata.
+pub fn generate_metadata_from_camera_csv<P: AsRef<Path>>(
+    csv_path: P,
+    camera_log: CameraLog,
+    config: &mut GenerateConfig,
+) -> Result<()> {
+    ipc::log("Parsing camera metadata CSV...");
+    stdout().flush().ok();
+
+    let content = std::fs::read_to_string(csv_path)?;
+
+    let split_fields = |line: &str| -> Vec<String> {
+        line.split(',')
+            .map(|v| v.trim().trim_matches('"').trim().to_lowercase())
+            .collect()
+    };
+
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+
+    let header = lines
+        .next()
+        .map(|(_, line)| split_fields(line))
+        .ok_or_else(|| anyhow!("Camera CSV is empty"))?;
+
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|h| names.contains(&h.as_str()))
+            .ok_or_else(|| anyhow!("Camera CSV: missing `{}` column", names[0]))
+    };
+
+    let frames_col = column(&["frames", "duration"])?;
+    let stats_cols = [column(&["min"])?, column(&["max"])?, column(&["avg"])?];
+
+    let to_pq = |percent: f64| {
+        let nits = camera_log.to_linear(percent / 100.0) * CAMERA_REFERENCE_WHITE_NITS;
+        (nits_to_pq(nits.clamp(0.0, 10_000.0)) * 4095.0).round() as u16
+    };
+
+    let mut shots: Vec<VideoShot> = Vec::new();
+    let mut start = 0;
+
+    for (line_no, line) in lines {
+        let values = split_fields(line);
+        let value = |col: usize| {
+            values
+                .get(col)
+                .map(String::as_str)
+                .ok_or_else(|| anyhow!("Camera CSV line {}: missing values", line_no))
+        };
+
+        let duration: usize = value(frames_col)?
+            .parse()
+            .map_err(|e| anyhow!("Camera CSV line {}: invalid frames: {}", line_no, e))?;
+
+        let mut stats = [0.0; 3];
+        for (col, stat) in stats_cols.iter().zip(stats.iter_mut()) {
+            let v = value(*col)?;
+
+            *stat = v
+                .parse::<f64>()
+                .ok()
+                .filter(|v| (0.0..=100.0).contains(v))
+                .ok_or_else(|| anyhow!("Camera CSV line {}: invalid percentage {}", line_no, v))?;
+        }
+
+        let [min, max, avg] = stats;
+
+        let mut shot = VideoShot {
+            start,
+            duration,
+            metadata_blocks: vec![ExtMetadataBlock::Level1(
+                ExtMetadataBlockLevel1::from_stats_cm_version(
+                    to_pq(min),
+                    to_pq(max),
+                    to_pq(avg),
+                    config.l1_avg_pq_cm_version.unwrap(),
+                ),
+            )],
+            ..Default::default()
+        };
+
+        if let Some(override_shot) = config.shots.get(shots.len()) {
+            shot.copy_metadata_from_shot(override_shot, Some(&[1]))
+        }
+
+        start += duration;
+        shots.push(shot);
+    }
+
+    ensure!(!shots.is_empty(), "Camera CSV has no clips");
+
+    // Now that the metadata was copied, we can replace the shots
+    config.shots.clear();
+    config.shots.extend(shots);
+
+    config.length = start;
+
+    Ok(())
+}
+
+/// Measures the frames of a y4m video, see [`measure_y4m`].
+///
+/// Each detected scene is a shot, with the L1 of its frames as frame edits.
+/// The shot L1 has the min and max of its frames, and the average of their averages.
+pub fn generate_metadata_from_y4m<P: AsRef<Path>>(
+    y4m_path: P,
+    transfer: Y4mTransfer,
+    scene_threshold: f64,
+    config: &mut GenerateConfig,
+) -> Result<()> {
+    ipc::log("Measuring y4m video...");
+    stdout().flush().ok();
+
+    let measurements = measure_y4m(y4m_path.as_ref(), transfer, scene_threshold)?;
+    let frames = &measurements.frames;
+    let cm_version = config.l1_avg_pq_cm_version.unwrap();
+
+    let mut shots = Vec::with_capacity(measurements.scene_starts.len());
+
+    for (i, &start) in measurements.scene_starts.iter().enumerate() {
+        let end = measurements
+            .scene_starts
+            .get(i + 1)
+            .copied()
+            .unwrap_or(frames.len());
+        let scene = &frames[start..end];
+
+        let min_pq = scene.iter().map(|f| f.min_pq).min().unwrap_or(0);
+        let max_pq = scene.iter().map(|f| f.max_pq).max().unwrap_or(0);
+        let avg_pq = scene.iter().map(|f| f.avg_pq as f64).sum::<f64>() / scene.len() as f64;
+
+        let mut shot = VideoShot {
+            start,
+            duration: scene.len(),
+            metadata_blocks: vec![ExtMetadataBlock::Level1(
+                ExtMetadataBlockLevel1::from_stats_cm_version(
+                    min_pq,
+                    max_pq,
+                    avg_pq.round() as u16,
+                    cm_version,
+                ),
+            )],
+            frame_edits: scene
+                .iter()
+                .enumerate()
+                .map(|(offset, f)| ShotFrameEdit {
+                    edit_offset: offset,
+                    metadata_blocks: vec![ExtMetadataBlock::Level1(
+                        ExtMetadataBlockLevel1::from_stats_cm_version(
+                            f.min_pq, f.max_pq, f.avg_pq, cm_version,
+                        ),
+                    )],
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        if let Some(override_shot) = config.shots.get(i) {
+            shot.copy_metadata_from_shot(override_shot, Some(&[1]))
+        }
+
+        shots.push(shot);
+    }
+
+    ipc::log(format!(
+        "Measured {} frames, {} scenes detected",
+        frames.len(),
+        shots.len()
+    ));
+
+    // Now that the metadata was copied, we can replace the shots
+    config.shots.clear();
+    config.shots.extend(shots);
+
+    // Set MaxCLL and MaxFALL if not set in config
+    if let Some(config_l6) = config.level6.as_mut() {
+        if config_l6.max_content_light_level == 0 {
+            let max_pq = frames.iter().map(|f| f.max_pq).max().unwrap_or(0);
+            config_l6.max_content_light_level = pq12_to_nits(max_pq).round() as u16;
+        }
+
+        if config_l6.max_frame_average_light_level == 0 {
+            let max_fall = frames.iter().map(|f| f.avg_nits).fold(0.0, f64::max);
+            config_l6.max_frame_average_light_level = max_fall.round() as u16;
+        }
+    }
+
+    if config.fps.is_none() {
+        config.fps = measurements.fps;
+    }
+
+    config.length = frames.len();
+
+    Ok(())
+}
+
+impl CameraLog {
+    /// Scene linear reflectance of a normalized log code value
+    fn to_linear(self, x: f64) -> f64 {
+        match self {
+            CameraLog::SLog3 => {
+                let code = x * 1023.0;
+
+                if code >= 171.2102946929 {
+                    10.0_f64.powf((code - 420.0) / 261.5) * 0.19 - 0.01
+                } else {
+                    (code - 95.0) * 0.01125 / (171.2102946929 - 95.0)
+                }
+            }
+            CameraLog::LogC3 => {
+                let (cut, a, b, c, d, e, f) = (
+                    0.010591, 5.555556, 0.052272, 0.247190, 0.385537, 5.367655, 0.092809,
+                );
+
+                if x > e * cut + f {
+                    (10.0_f64.powf((x - d) / c) - b) / a
+                } else {
+                    (x - f) / e
+                }
+            }
+        }
+    }
+}
+
 impl From<GeneratorProfile> for GenerateProfile {
     fn from(p: GeneratorProfile) -> Self {
         match p {
@@ -348,3 +1218,29 @@ impl From<GeneratorProfile> for GenerateProfile {
         }
     }
 }
+
+/// Trim values that lost precision when rounded to the RPU fixed-point values
+fn print_xml_warnings(parser: &CmXmlParser) {
+    const MAX_REPORTED_WARNINGS: usize = 10;
+
+    let warnings = parser.warnings();
+    if warnings.is_empty() {
+        return;
+    }
+
+    ipc::warn(format!(
+        "{} XML trim value(s) lost precision:",
+        warnings.len()
+    ));
+
+    for warning in warnings.iter().take(MAX_REPORTED_WARNINGS) {
+        ipc::log(format!("  {warning}"));
+    }
+
+    if warnings.len() > MAX_REPORTED_WARNINGS {
+        ipc::log(format!(
+            "  ... and {} more",
+            warnings.len() - MAX_REPORTED_WARNINGS
+        ));
+    }
+}
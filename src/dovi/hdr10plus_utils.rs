@@ -1,7 +1,9 @@
 use anyhow::Result;
 
 use bitvec_helpers::bitstream_io_reader::BsIoSliceReader;
-use hevc_parser::hevc::{NALUnit, SeiMessage, NAL_SEI_PREFIX, USER_DATA_REGISTERED_ITU_T_35};
+use hevc_parser::hevc::{
+    NALUnit, SeiMessage, NAL_SEI_PREFIX, NAL_SEI_SUFFIX, USER_DATA_REGISTERED_ITU_T_35,
+};
 use hevc_parser::utils::{
     add_start_code_emulation_prevention_3_byte, clear_start_code_emulation_prevention_3_byte,
 };
@@ -79,3 +81,44 @@ pub fn prefix_sei_removed_hdr10plus_nalu(
 
     Ok((has_st2094_40, None))
 }
+
+/// ST2094-40 message moved out of a prefix SEI NALU
+pub struct Hdr10PlusSuffixSei {
+    /// Suffix SEI NALU of the message
+    pub suffix: Vec<u8>,
+    /// Prefix SEI NALU without the message, when it has others
+    pub remaining: Option<Vec<u8>>,
+}
+
+/// Moves the ST2094-40 message of a prefix SEI NALU to a suffix SEI NALU.
+///
+/// Returns None without ST2094-40 message.
+pub fn hdr10plus_sei_to_suffix(data: &[u8]) -> Result<Option<Hdr10PlusSuffixSei>> {
+    let mut payload = clear_start_code_emulation_prevention_3_byte(data);
+
+    let msg = match st2094_40_sei_msg(&payload)? {
+        Some(msg) => msg,
+        None => return Ok(None),
+    };
+
+    let start = msg.msg_offset;
+    let end = msg.payload_offset + msg.payload_size;
+
+    // Same nuh_layer_id and nuh_temporal_id_plus1
+    let mut suffix = vec![(NAL_SEI_SUFFIX << 1) | (payload[0] & 0x01), payload[1]];
+    suffix.extend_from_slice(&payload[start..end]);
+    // rbsp_trailing_bits
+    suffix.push(0x80);
+    add_start_code_emulation_prevention_3_byte(&mut suffix);
+
+    let remaining = if SeiMessage::parse_sei_rbsp(&payload)?.len() > 1 {
+        payload.drain(start..end);
+        add_start_code_emulation_prevention_3_byte(&mut payload);
+
+        Some(payload)
+    } else {
+        None
+    };
+
+    Ok(Some(Hdr10PlusSuffixSei { suffix, remaining }))
+}
@@ -0,0 +1,752 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{bail, ensure, Result};
+
+use dolby_vision::av1::{parse_itu_t35_dovi_metadata_obu, ITU_T35_DOVI_RPU_PAYLOAD_HEADER};
+
+use crate::ipc;
+
+use super::fmp4::{
+    be_u32, child_boxes, find_box, read_box_header, read_box_payload, rpu_nalu_from_sample,
+    HEVC_SAMPLE_ENTRIES,
+};
+
+const OBU_METADATA: u8 = 5;
+const METADATA_TYPE_ITUT_T35: u64 = 4;
+
+/// Reads the RPU NAL units from HEIF/AVIF still images and image sequences.
+///
+/// For image sequences, the RPUs are read from the samples of the first HEVC or AV1 track,
+/// in presentation order. Otherwise, the RPUs are read from the image items, ordered by item ID.
+/// Thumbnails, hidden items and grid tiles are ignored.
+///
+/// The RPUs are in-band in the coded images: UNSPEC62 NAL units for HEVC,
+/// ITU-T T.35 metadata OBUs for AV1.
+/// AV1 RPUs are converted to HEVC UNSPEC62 NAL units.
+pub struct HeifRpuReader {
+    reader: BufReader<File>,
+    file_size: u64,
+}
+
+#[derive(Clone, Copy)]
+enum ImageCodec {
+    Hevc { nal_length_size: usize },
+    Av1,
+}
+
+#[derive(Default)]
+struct MetaBox {
+    items: Vec<ImageItem>,
+    locations: HashMap<u32, ItemLocation>,
+    properties: Vec<([u8; 4], Vec<u8>)>,
+    associations: HashMap<u32, Vec<usize>>,
+    references: Vec<ItemReference>,
+    idat: Vec<u8>,
+}
+
+struct ImageItem {
+    id: u32,
+    item_type: [u8; 4],
+    hidden: bool,
+}
+
+#[derive(Default)]
+struct ItemLocation {
+    construction_method: u16,
+    base_offset: u64,
+    extents: Vec<(u64, u64)>,
+}
+
+struct ItemReference {
+    reference_type: [u8; 4],
+    from_item: u32,
+    to_items: Vec<u32>,
+}
+
+struct SampleRpu {
+    presentation_time: i64,
+    decode_index: usize,
+    data: Vec<u8>,
+}
+
+/// Big endian reader over a box or OBU payload
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl HeifRpuReader {
+    /// Returns the RPU NAL units (including the NAL header), in presentation order
+    pub fn read<P: AsRef<Path>>(input: P) -> Result<Vec<Vec<u8>>> {
+        let mut reader = BufReader::new(File::open(input)?);
+        let file_size = reader.seek(SeekFrom::End(0))?;
+
+        let mut heif_reader = Self { reader, file_size };
+
+        let mut meta = None;
+        let mut moov = None;
+
+        let mut pos = 0;
+        while pos < file_size {
+            heif_reader.reader.seek(SeekFrom::Start(pos))?;
+            let header = read_box_header(&mut heif_reader.reader, pos, file_size)?;
+
+            match &header.box_type {
+                b"meta" => meta = Some(read_box_payload(&mut heif_reader.reader, &header)?),
+                b"moov" => moov = Some(read_box_payload(&mut heif_reader.reader, &header)?),
+                _ => (),
+            }
+
+            pos = header.start + header.size;
+        }
+
+        let mut rpus = match moov {
+            Some(moov) => heif_reader.track_rpus(&moov)?,
+            None => Vec::new(),
+        };
+
+        if rpus.is_empty() {
+            if let Some(meta) = meta {
+                rpus = heif_reader.item_rpus(&meta)?;
+            }
+        }
+
+        ensure!(
+            !rpus.is_empty(),
+            "HEIF: No Dolby Vision RPU found in the images"
+        );
+
+        Ok(rpus)
+    }
+
+    fn item_rpus(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let meta = MetaBox::parse(data)?;
+
+        let thumbnails: HashSet<u32> = meta
+            .references_of_type(b"thmb")
+            .map(|r| r.from_item)
+            .collect();
+        let tiles: HashSet<u32> = meta
+            .references_of_type(b"dimg")
+            .flat_map(|r| r.to_items.iter().copied())
+            .collect();
+
+        let mut items: Vec<&ImageItem> = meta
+            .items
+            .iter()
+            .filter(|item| {
+                !item.hidden && !thumbnails.contains(&item.id) && !tiles.contains(&item.id)
+            })
+            .collect();
+        items.sort_by_key(|item| item.id);
+
+        let mut rpus = Vec::new();
+        for item in items {
+            // Grid images use the RPU of their first tile
+            let rpu = match &item.item_type {
+                b"grid" => match meta.first_tile(item.id) {
+                    Some(tile) => self.image_item_rpu(&meta, tile)?,
+                    None => None,
+                },
+                _ => self.image_item_rpu(&meta, item)?,
+            };
+
+            match rpu {
+                Some(rpu) => rpus.push(rpu),
+                None => ipc::log(format!("HEIF: No RPU for item {}, skipping", item.id)),
+            }
+        }
+
+        Ok(rpus)
+    }
+
+    fn image_item_rpu(&mut self, meta: &MetaBox, item: &ImageItem) -> Result<Option<Vec<u8>>> {
+        let codec = match &item.item_type {
+            b"hvc1" => ImageCodec::Hevc {
+                nal_length_size: meta.hevc_nal_length_size(item.id)?,
+            },
+            b"av01" => ImageCodec::Av1,
+            _ => return Ok(None),
+        };
+
+        let data = self.item_data(meta, item.id)?;
+
+        rpu_from_image_data(codec, &data)
+    }
+
+    fn item_data(&mut self, meta: &MetaBox, item_id: u32) -> Result<Vec<u8>> {
+        let location = match meta.locations.get(&item_id) {
+            Some(location) => location,
+            None => bail!("HEIF: No location for item {item_id}"),
+        };
+
+        let mut data = Vec::new();
+        for &(offset, length) in &location.extents {
+            let offset = location.base_offset + offset;
+
+            match location.construction_method {
+                0 => {
+                    // Zero length extends to the end of the file
+                    let length = if length == 0 {
+                        self.file_size.saturating_sub(offset)
+                    } else {
+                        length
+                    };
+
+                    ensure!(
+                        offset + length <= self.file_size,
+                        "HEIF: Item {item_id} extent is out of the file"
+                    );
+
+                    self.read_data(offset, length as usize, &mut data)?;
+                }
+                1 => {
+                    let (start, end) = (offset as usize, (offset + length) as usize);
+                    let end = if length == 0 { meta.idat.len() } else { end };
+
+                    match meta.idat.get(start..end) {
+                        Some(extent) => data.extend_from_slice(extent),
+                        None => bail!("HEIF: Item {item_id} extent is out of the idat box"),
+                    }
+                }
+                method => {
+                    bail!("HEIF: Unsupported construction method {method} for item {item_id}")
+                }
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn read_data(&mut self, offset: u64, length: usize, data: &mut Vec<u8>) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+
+        let start = data.len();
+        data.resize(start + length, 0);
+        self.reader.read_exact(&mut data[start..])?;
+
+        Ok(())
+    }
+
+    /// Uses the first track with a HEVC or AV1 sample entry
+    fn track_rpus(&mut self, moov: &[u8]) -> Result<Vec<Vec<u8>>> {
+        for (header, trak) in child_boxes(moov) {
+            if &header.box_type != b"trak" {
+                continue;
+            }
+
+            let stbl = match find_box(trak, &[b"mdia", b"minf", b"stbl"]) {
+                Some(stbl) => stbl,
+                None => continue,
+            };
+
+            if let Some(codec) = track_codec(stbl)? {
+                return self.track_sample_rpus(stbl, codec);
+            }
+        }
+
+        Ok(Vec::new())
+    }
+
+    fn track_sample_rpus(&mut self, stbl: &[u8], codec: ImageCodec) -> Result<Vec<Vec<u8>>> {
+        let sample_sizes = parse_stsz(stbl)?;
+        let sample_offsets = sample_offsets(stbl, &sample_sizes)?;
+
+        let decode_deltas = expand_sample_table(find_box(stbl, &[b"stts"]), false)?;
+        let composition_offsets = expand_sample_table(find_box(stbl, &[b"ctts"]), true)?;
+
+        let mut samples = Vec::new();
+        let mut decode_time = 0;
+
+        for (i, (&offset, &size)) in sample_offsets.iter().zip(&sample_sizes).enumerate() {
+            ensure!(
+                offset + size as u64 <= self.file_size,
+                "HEIF: Sample {i} is out of the file"
+            );
+
+            let mut sample = Vec::new();
+            self.read_data(offset, size as usize, &mut sample)?;
+
+            if let Some(rpu) = rpu_from_image_data(codec, &sample)? {
+                samples.push(SampleRpu {
+                    presentation_time: decode_time + composition_offsets.get(i).unwrap_or(&0),
+                    decode_index: i,
+                    data: rpu,
+                });
+            }
+
+            decode_time += decode_deltas.get(i).unwrap_or(&0);
+        }
+
+        samples.sort_by_key(|s| (s.presentation_time, s.decode_index));
+
+        Ok(samples.into_iter().map(|s| s.data).collect())
+    }
+}
+
+impl MetaBox {
+    fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= 4, "HEIF: Invalid meta box");
+
+        let mut meta = Self::default();
+
+        // Skip full box header
+        for (header, payload) in child_boxes(&data[4..]) {
+            match &header.box_type {
+                b"iinf" => meta.parse_iinf(payload)?,
+                b"iloc" => meta.parse_iloc(payload)?,
+                b"iprp" => meta.parse_iprp(payload)?,
+                b"iref" => meta.parse_iref(payload)?,
+                b"idat" => meta.idat = payload.to_vec(),
+                _ => (),
+            }
+        }
+
+        Ok(meta)
+    }
+
+    fn parse_iinf(&mut self, data: &[u8]) -> Result<()> {
+        let mut reader = ByteReader::new(data);
+        let version = reader.u8()?;
+        reader.skip(3)?;
+
+        let entry_count_size = if version == 0 { 2 } else { 4 };
+        reader.skip(entry_count_size)?;
+
+        for (header, infe) in child_boxes(&data[reader.pos..]) {
+            if &header.box_type != b"infe" {
+                continue;
+            }
+
+            let mut reader = ByteReader::new(infe);
+            let version = reader.u8()?;
+            let flags = reader.uint(3)?;
+
+            // Versions 0 and 1 don't have an item type
+            if version < 2 {
+                continue;
+            }
+
+            let id_size = if version == 2 { 2 } else { 4 };
+            let id = reader.uint(id_size)? as u32;
+
+            // item_protection_index
+            reader.skip(2)?;
+
+            let mut item_type = [0; 4];
+            item_type.copy_from_slice(reader.bytes(4)?);
+
+            self.items.push(ImageItem {
+                id,
+                item_type,
+                hidden: flags & 0x01 != 0,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn parse_iloc(&mut self, data: &[u8]) -> Result<()> {
+        let mut reader = ByteReader::new(data);
+        let version = reader.u8()?;
+        reader.skip(3)?;
+
+        let sizes = reader.u8()?;
+        let (offset_size, length_size) = ((sizes >> 4) as usize, (sizes & 0x0F) as usize);
+
+        let sizes = reader.u8()?;
+        let base_offset_size = (sizes >> 4) as usize;
+        let index_size = if version > 0 {
+            (sizes & 0x0F) as usize
+        } else {
+            0
+        };
+
+        let id_size = if version < 2 { 2 } else { 4 };
+        let item_count = reader.uint(id_size)?;
+
+        for _ in 0..item_count {
+            let id = reader.uint(id_size)? as u32;
+
+            let construction_method = if version > 0 {
+                reader.uint(2)? as u16 & 0x0F
+            } else {
+                0
+            };
+
+            // data_reference_index
+            reader.skip(2)?;
+
+            let mut location = ItemLocation {
+                construction_method,
+                base_offset: reader.uint(base_offset_size)?,
+                extents: Vec::new(),
+            };
+
+            let extent_count = reader.uint(2)?;
+            for _ in 0..extent_count {
+                reader.skip(index_size)?;
+
+                let offset = reader.uint(offset_size)?;
+                let length = reader.uint(length_size)?;
+
+                location.extents.push((offset, length));
+            }
+
+            self.locations.insert(id, location);
+        }
+
+        Ok(())
+    }
+
+    fn parse_iprp(&mut self, data: &[u8]) -> Result<()> {
+        for (header, payload) in child_boxes(data) {
+            match &header.box_type {
+                b"ipco" => {
+                    self.properties.extend(
+                        child_boxes(payload)
+                            .map(|(header, property)| (header.box_type, property.to_vec())),
+                    );
+                }
+                b"ipma" => self.parse_ipma(payload)?,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_ipma(&mut self, data: &[u8]) -> Result<()> {
+        let mut reader = ByteReader::new(data);
+        let version = reader.u8()?;
+        let flags = reader.uint(3)?;
+
+        let entry_count = reader.uint(4)?;
+        let id_size = if version < 1 { 2 } else { 4 };
+
+        for _ in 0..entry_count {
+            let id = reader.uint(id_size)? as u32;
+            let association_count = reader.u8()?;
+
+            let associations = self.associations.entry(id).or_default();
+            for _ in 0..association_count {
+                // Top bit is the essential flag
+                let index = if flags & 0x01 != 0 {
+                    reader.uint(2)? as usize & 0x7FFF
+                } else {
+                    reader.u8()? as usize & 0x7F
+                };
+
+                // Zero means no property
+                if index > 0 {
+                    associations.push(index);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_iref(&mut self, data: &[u8]) -> Result<()> {
+        let mut reader = ByteReader::new(data);
+        let version = reader.u8()?;
+        reader.skip(3)?;
+
+        let id_size = if version == 0 { 2 } else { 4 };
+
+        for (header, payload) in child_boxes(&data[reader.pos..]) {
+            let mut reader = ByteReader::new(payload);
+
+            let from_item = reader.uint(id_size)? as u32;
+            let reference_count = reader.uint(2)?;
+
+            let to_items = (0..reference_count)
+                .map(|_| reader.uint(id_size).map(|id| id as u32))
+                .collect::<Result<_>>()?;
+
+            self.references.push(ItemReference {
+                reference_type: header.box_type,
+                from_item,
+                to_items,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn references_of_type<'a>(
+        &'a self,
+        reference_type: &'a [u8; 4],
+    ) -> impl Iterator<Item = &'a ItemReference> {
+        self.references
+            .iter()
+            .filter(move |r| &r.reference_type == reference_type)
+    }
+
+    fn first_tile(&self, item_id: u32) -> Option<&ImageItem> {
+        let tile_id = self
+            .references_of_type(b"dimg")
+            .find(|r| r.from_item == item_id)
+            .and_then(|r| r.to_items.first())?;
+
+        self.items.iter().find(|item| item.id == *tile_id)
+    }
+
+    fn item_property(&self, item_id: u32, property_type: &[u8; 4]) -> Option<&[u8]> {
+        self.associations.get(&item_id)?.iter().find_map(|&index| {
+            self.properties
+                .get(index - 1)
+                .filter(|(box_type, _)| box_type == property_type)
+                .map(|(_, property)| property.as_slice())
+        })
+    }
+
+    fn hevc_nal_length_size(&self, item_id: u32) -> Result<usize> {
+        match self.item_property(item_id, b"hvcC") {
+            Some(hvcc) => {
+                ensure!(hvcc.len() > 21, "HEIF: Invalid hvcC property");
+                Ok((hvcc[21] & 0x03) as usize + 1)
+            }
+            None => Ok(4),
+        }
+    }
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(
+            self.pos + len <= self.data.len(),
+            "HEIF: Unexpected end of box"
+        );
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    /// Unsigned integer of `size` bytes, zero sized fields are 0
+    fn uint(&mut self, size: usize) -> Result<u64> {
+        ensure!(size <= 8, "HEIF: Invalid field size {size}");
+
+        Ok(self
+            .bytes(size)?
+            .iter()
+            .fold(0, |value, b| (value << 8) | *b as u64))
+    }
+}
+
+fn rpu_from_image_data(codec: ImageCodec, data: &[u8]) -> Result<Option<Vec<u8>>> {
+    match codec {
+        ImageCodec::Hevc { nal_length_size } => rpu_nalu_from_sample(data, nal_length_size),
+        ImageCodec::Av1 => av1_rpu_nalu(data),
+    }
+}
+
+/// Converts the first Dolby Vision ITU-T T.35 metadata OBU to an UNSPEC62 NAL unit
+fn av1_rpu_nalu(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut reader = ByteReader::new(data);
+
+    while reader.pos < data.len() {
+        let obu_header = reader.u8()?;
+        let obu_type = (obu_header >> 3) & 0x0F;
+
+        if obu_header & 0x04 != 0 {
+            reader.skip(1)?;
+        }
+
+        let size = if obu_header & 0x02 != 0 {
+            read_leb128(&mut reader)? as usize
+        } else {
+            data.len() - reader.pos
+        };
+        let payload = reader.bytes(size)?;
+
+        if obu_type != OBU_METADATA {
+            continue;
+        }
+
+        let mut payload_reader = ByteReader::new(payload);
+        if read_leb128(&mut payload_reader)? != METADATA_TYPE_ITUT_T35 {
+            continue;
+        }
+
+        let mut t35_payload = payload[payload_reader.pos..].to_vec();
+
+        // Country code is optional
+        let header_start = usize::from(t35_payload.first() == Some(&0xB5));
+        if t35_payload[header_start..].starts_with(ITU_T35_DOVI_RPU_PAYLOAD_HEADER) {
+            let rpu = parse_itu_t35_dovi_metadata_obu(&mut t35_payload)?;
+
+            return rpu.write_hevc_unspec62_nalu().map(Some);
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_leb128(reader: &mut ByteReader) -> Result<u64> {
+    let mut value = 0;
+
+    for i in 0..8 {
+        let byte = reader.u8()?;
+        value |= ((byte & 0x7F) as u64) << (i * 7);
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    bail!("HEIF: Invalid leb128 value")
+}
+
+fn track_codec(stbl: &[u8]) -> Result<Option<ImageCodec>> {
+    // Skip full box header and entry_count
+    let sample_entries = match find_box(stbl, &[b"stsd"]).and_then(|stsd| stsd.get(8..)) {
+        Some(sample_entries) => sample_entries,
+        None => return Ok(None),
+    };
+
+    for (header, entry) in child_boxes(sample_entries) {
+        if &header.box_type == b"av01" {
+            return Ok(Some(ImageCodec::Av1));
+        }
+
+        if HEVC_SAMPLE_ENTRIES.contains(&&header.box_type) {
+            let mut nal_length_size = 4;
+
+            // VisualSampleEntry fields are 78 bytes
+            if let Some(hvcc) = entry.get(78..).and_then(|e| find_box(e, &[b"hvcC"])) {
+                ensure!(hvcc.len() > 21, "HEIF: Invalid hvcC box");
+                nal_length_size = (hvcc[21] & 0x03) as usize + 1;
+            }
+
+            return Ok(Some(ImageCodec::Hevc { nal_length_size }));
+        }
+    }
+
+    Ok(None)
+}
+
+fn parse_stsz(stbl: &[u8]) -> Result<Vec<u32>> {
+    let stsz = match find_box(stbl, &[b"stsz"]) {
+        Some(stsz) => stsz,
+        None => bail!("HEIF: Missing stsz box"),
+    };
+
+    let mut reader = ByteReader::new(stsz);
+    reader.skip(4)?;
+
+    let sample_size = reader.uint(4)? as u32;
+    let sample_count = reader.uint(4)? as usize;
+
+    if sample_size != 0 {
+        return Ok(vec![sample_size; sample_count]);
+    }
+
+    (0..sample_count)
+        .map(|_| reader.uint(4).map(|size| size as u32))
+        .collect()
+}
+
+/// File offsets of the samples, from the chunk offsets and the sample to chunk table
+fn sample_offsets(stbl: &[u8], sample_sizes: &[u32]) -> Result<Vec<u64>> {
+    let chunk_offsets = if let Some(stco) = find_box(stbl, &[b"stco"]) {
+        read_table(stco, 1, 4)?
+    } else if let Some(co64) = find_box(stbl, &[b"co64"]) {
+        read_table(co64, 1, 8)?
+    } else {
+        bail!("HEIF: Missing chunk offsets box");
+    };
+
+    let stsc = match find_box(stbl, &[b"stsc"]) {
+        Some(stsc) => read_table(stsc, 3, 4)?,
+        None => bail!("HEIF: Missing stsc box"),
+    };
+
+    // first_chunk, samples_per_chunk, sample_description_index
+    let entries: Vec<&[u64]> = stsc.chunks_exact(3).collect();
+
+    let mut offsets = Vec::with_capacity(sample_sizes.len());
+    for (i, entry) in entries.iter().enumerate() {
+        let last_chunk = entries
+            .get(i + 1)
+            .map_or(chunk_offsets.len() as u64 + 1, |next| next[0]);
+
+        for chunk in entry[0]..last_chunk {
+            let mut offset = match chunk_offsets.get((chunk as usize).wrapping_sub(1)) {
+                Some(offset) => *offset,
+                None => bail!("HEIF: Invalid chunk {chunk} in stsc box"),
+            };
+
+            for _ in 0..entry[1] {
+                let size = match sample_sizes.get(offsets.len()) {
+                    Some(size) => *size,
+                    None => break,
+                };
+
+                offsets.push(offset);
+                offset += size as u64;
+            }
+        }
+    }
+
+    ensure!(
+        offsets.len() == sample_sizes.len(),
+        "HEIF: Sample to chunk table doesn't match the sample count"
+    );
+
+    Ok(offsets)
+}
+
+/// Expands a (sample_count, value) table to a value per sample
+fn expand_sample_table(data: Option<&[u8]>, signed: bool) -> Result<Vec<i64>> {
+    let data = match data {
+        Some(data) => data,
+        None => return Ok(Vec::new()),
+    };
+
+    // Version 1 composition offsets are signed
+    let signed = signed && data.first() == Some(&1);
+    let table = read_table(data, 2, 4)?;
+
+    Ok(table
+        .chunks_exact(2)
+        .flat_map(|entry| {
+            let value = if signed {
+                entry[1] as u32 as i32 as i64
+            } else {
+                entry[1] as i64
+            };
+
+            std::iter::repeat(value).take(entry[0] as usize)
+        })
+        .collect())
+}
+
+/// Reads the fields of a full box table, following the entry count
+fn read_table(data: &[u8], fields_per_entry: usize, field_size: usize) -> Result<Vec<u64>> {
+    let mut reader = ByteReader::new(data);
+    reader.skip(4)?;
+
+    let entry_count = be_u32(reader.bytes(4)?, 0) as usize;
+
+    (0..entry_count * fields_per_entry)
+        .map(|_| reader.uint(field_size))
+        .collect()
+}
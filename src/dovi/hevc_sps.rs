@@ -0,0 +1,69 @@
+use anyhow::{ensure, Result};
+
+use bitvec_helpers::bitstream_io_reader::BsIoSliceReader;
+use hevc_parser::utils::clear_start_code_emulation_prevention_3_byte;
+
+/// Luma bit depth signaled by a SPS NALU, including the NALU header.
+///
+/// Only the fields preceding `bit_depth_luma_minus8` are read (7.3.2.2).
+pub fn sps_luma_bit_depth(data: &[u8]) -> Result<u8> {
+    let rbsp = clear_start_code_emulation_prevention_3_byte(data);
+    ensure!(rbsp.len() > 2, "Invalid SPS NALU");
+
+    let mut reader = BsIoSliceReader::from_slice(&rbsp[2..]);
+
+    reader.skip_n(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = reader.get_n::<u8>(3)?;
+    reader.skip_n(1)?; // sps_temporal_id_nesting_flag
+
+    // profile_tier_level: general profile, tier and level
+    reader.skip_n(96)?;
+
+    let mut sub_layer_flags = Vec::with_capacity(max_sub_layers_minus1 as usize);
+    for _ in 0..max_sub_layers_minus1 {
+        let profile_present = reader.get()?;
+        let level_present = reader.get()?;
+
+        sub_layer_flags.push((profile_present, level_present));
+    }
+
+    if max_sub_layers_minus1 > 0 {
+        // reserved_zero_2bits up to 8 sub layers
+        reader.skip_n(2 * (8 - max_sub_layers_minus1 as u32))?;
+    }
+
+    for (profile_present, level_present) in sub_layer_flags {
+        if profile_present {
+            reader.skip_n(88)?;
+        }
+
+        if level_present {
+            reader.skip_n(8)?;
+        }
+    }
+
+    reader.get_ue()?; // sps_seq_parameter_set_id
+
+    let chroma_format_idc = reader.get_ue()?;
+    if chroma_format_idc == 3 {
+        reader.skip_n(1)?; // separate_colour_plane_flag
+    }
+
+    reader.get_ue()?; // pic_width_in_luma_samples
+    reader.get_ue()?; // pic_height_in_luma_samples
+
+    let conformance_window_flag = reader.get()?;
+    if conformance_window_flag {
+        for _ in 0..4 {
+            reader.get_ue()?;
+        }
+    }
+
+    let bit_depth_luma_minus8 = reader.get_ue()?;
+    ensure!(
+        bit_depth_luma_minus8 <= 8,
+        "Invalid SPS bit_depth_luma_minus8: {bit_depth_luma_minus8}"
+    );
+
+    Ok(bit_depth_luma_minus8 as u8 + 8)
+}
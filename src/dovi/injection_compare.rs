@@ -0,0 +1,162 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+use dolby_vision::rpu::nalu_reader::{HevcNalu, NaluReader};
+use hevc_parser::hevc::{NAL_AUD, NAL_UNSPEC62};
+
+use super::ops::cli_options;
+use super::rpu_extractor::RpuExtractor;
+use super::rpu_verifier::split_nalus;
+use super::DoviRpu;
+use crate::commands::{CompareInjectedArgs, ExtractRpuArgs};
+use crate::ipc;
+
+/// Verifies that injecting only added the RPUs to a HEVC stream.
+///
+/// Every NALU other than the RPUs and AUDs must be byte-identical,
+/// and the injected RPUs must match the RPU file in presentation order.
+pub struct InjectionComparer {
+    original: PathBuf,
+    injected: PathBuf,
+    rpu_in: PathBuf,
+}
+
+impl InjectionComparer {
+    pub fn compare(args: CompareInjectedArgs) -> Result<()> {
+        let CompareInjectedArgs {
+            original,
+            injected,
+            rpu_in,
+        } = args;
+
+        let comparer = InjectionComparer {
+            original,
+            injected,
+            rpu_in,
+        };
+
+        ipc::log("Comparing video NALUs...");
+        let nalus = comparer.compare_video()?;
+        ipc::log(format!("{nalus} video NALUs are identical"));
+
+        ipc::log("Comparing RPUs...");
+        let frames = comparer.compare_rpus()?;
+        ipc::log(format!("{frames} RPUs match"));
+
+        ipc::log("PASS");
+
+        Ok(())
+    }
+
+    /// Compares the NALUs, ignoring the start code lengths, RPUs and AUDs
+    fn compare_video(&self) -> Result<usize> {
+        let mut original = NaluReader::new(File::open(&self.original)?);
+        let mut injected = NaluReader::new(File::open(&self.injected)?);
+
+        let mut count = 0;
+
+        loop {
+            let original_nalu = next_video_nalu(&mut original)?;
+            let injected_nalu = next_video_nalu(&mut injected)?;
+
+            match (original_nalu, injected_nalu) {
+                (None, None) => return Ok(count),
+                (Some(a), Some(b)) => {
+                    if let Some(pos) = a.data.iter().zip(&b.data).position(|(x, y)| x != y) {
+                        fail_at(count, a.offset + pos as u64, b.offset + pos as u64)?;
+                    } else if a.data.len() != b.data.len() {
+                        let len = a.data.len().min(b.data.len()) as u64;
+                        fail_at(count, a.offset + len, b.offset + len)?;
+                    }
+                }
+                (Some(a), None) => {
+                    ipc::log(format!(
+                        "FAIL: injected file ends early, missing video NALU {count}"
+                    ));
+                    bail!("Video data mismatch at original offset {}", a.offset);
+                }
+                (None, Some(b)) => {
+                    ipc::log(format!(
+                        "FAIL: injected file has extra video NALUs from NALU {count}"
+                    ));
+                    bail!("Video data mismatch at injected offset {}", b.offset);
+                }
+            }
+
+            count += 1;
+        }
+    }
+
+    fn compare_rpus(&self) -> Result<usize> {
+        let temp_rpu =
+            std::env::temp_dir().join(format!("dovi_tool_{}_compare_RPU.bin", std::process::id()));
+
+        let res = self.compare_extracted_rpus(&temp_rpu);
+        fs::remove_file(&temp_rpu).ok();
+
+        res
+    }
+
+    fn compare_extracted_rpus(&self, temp_rpu: &Path) -> Result<usize> {
+        let extract_args = ExtractRpuArgs {
+            input: Some(self.injected.clone()),
+            input_pos: None,
+            rpu_out: Some(temp_rpu.to_path_buf()),
+            init_segment: None,
+            l6_from_sei: false,
+            temporal_id: None,
+        };
+        RpuExtractor::extract_rpu(extract_args, cli_options(None))?;
+
+        let injected_data = fs::read(temp_rpu)?;
+        let expected_data = fs::read(&self.rpu_in)?;
+
+        let injected_rpus = split_nalus(&injected_data);
+        let expected_rpus = split_nalus(&expected_data);
+
+        for (i, (injected, expected)) in injected_rpus.iter().zip(&expected_rpus).enumerate() {
+            // Compare the re-encoded RPUs, as written when injecting
+            let matching = injected == expected
+                || DoviRpu::parse_unspec62_nalu(injected)?.write_hevc_unspec62_nalu()?
+                    == DoviRpu::parse_unspec62_nalu(expected)?.write_hevc_unspec62_nalu()?;
+
+            if !matching {
+                ipc::log(format!("FAIL: RPU mismatch at frame {i}"));
+                bail!("RPU mismatch at frame {i}");
+            }
+        }
+
+        if injected_rpus.len() != expected_rpus.len() {
+            ipc::log(format!(
+                "FAIL: {} RPUs injected, the RPU file has {}",
+                injected_rpus.len(),
+                expected_rpus.len()
+            ));
+            bail!("RPU count mismatch");
+        }
+
+        Ok(injected_rpus.len())
+    }
+}
+
+fn fail_at(index: usize, original_offset: u64, injected_offset: u64) -> Result<()> {
+    ipc::log(format!(
+        "FAIL: video NALU {index} differs, first mismatch at original offset {original_offset}, injected offset {injected_offset}"
+    ));
+
+    bail!("Video data mismatch at original offset {original_offset}")
+}
+
+/// Next NALU that isn't a RPU or AUD
+fn next_video_nalu<R: Read>(reader: &mut NaluReader<R>) -> Result<Option<HevcNalu>> {
+    while let Some(nalu) = reader.next_nalu()? {
+        if !matches!(nalu.nal_type, NAL_UNSPEC62 | NAL_AUD) {
+            return Ok(Some(nalu));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,222 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use dolby_vision::rpu::extension_metadata::blocks::ExtMetadataBlock;
+
+use super::frame_timestamps::frame_label;
+use super::DoviRpu;
+
+/// Shots shorter than this many frames are counted as unstable scene cuts
+const MIN_STABLE_SHOT_FRAMES: usize = 6;
+
+const MAX_REPORTED_FRAMES: usize = 10;
+
+/// How much the dynamic metadata fluctuates over a title.
+///
+/// Within a shot, the L1 and L2 metadata is expected to be constant,
+/// so any frame to frame change above the threshold is flagged as flicker.
+/// Scene cuts starting very short shots are flagged as well.
+///
+/// The score is the percentage of frames that were not flagged, 100 being perfectly stable.
+pub struct StabilityReport {
+    pub frames: usize,
+    pub score: f64,
+
+    /// Mean and max within-shot frame to frame L1 max/avg change, in normalized PQ
+    pub mean_l1_delta: f64,
+    pub max_l1_delta: f64,
+
+    /// Mean and max within-shot frame to frame L2 trim change, normalized to -1.0..1.0 trims
+    pub mean_l2_delta: f64,
+    pub max_l2_delta: f64,
+
+    pub scene_cuts: usize,
+    pub cuts_per_minute: f64,
+    pub short_shots: usize,
+
+    /// Flagged frames, with the reason
+    pub flagged: Vec<(usize, String)>,
+}
+
+impl StabilityReport {
+    /// `threshold` is the max allowed within-shot change, for both the L1 PQ and L2 trims
+    pub fn new(rpus: &[DoviRpu], fps: f64, threshold: f64) -> Self {
+        let scene_cut_frames: Vec<usize> = rpus
+            .iter()
+            .enumerate()
+            .filter(|(i, rpu)| *i == 0 || is_scene_cut(rpu))
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut flagged = Vec::new();
+        let mut l1_deltas = Vec::new();
+        let mut l2_deltas = Vec::new();
+
+        for (i, pair) in rpus.windows(2).enumerate() {
+            let frame = i + 1;
+            let (previous, current) = (&pair[0], &pair[1]);
+
+            if is_scene_cut(current) {
+                continue;
+            }
+
+            let l1_delta = l1_delta(previous, current);
+            let l2_delta = l2_delta(previous, current);
+
+            if l1_delta > threshold {
+                flagged.push((frame, format!("L1 change of {l1_delta:.4} PQ")));
+            } else if l2_delta > threshold {
+                flagged.push((frame, format!("L2 trim change of {l2_delta:.4}")));
+            }
+
+            l1_deltas.push(l1_delta);
+            l2_deltas.push(l2_delta);
+        }
+
+        let mut short_shots = 0;
+
+        for (i, &start) in scene_cut_frames.iter().enumerate() {
+            let end = scene_cut_frames.get(i + 1).copied().unwrap_or(rpus.len());
+
+            // The last shot can be cut short by the end of the file
+            if end - start < MIN_STABLE_SHOT_FRAMES && end < rpus.len() {
+                short_shots += 1;
+
+                if start > 0 {
+                    flagged.push((start, format!("Shot of {} frames", end - start)));
+                }
+            }
+        }
+
+        flagged.sort_by_key(|(frame, _)| *frame);
+
+        let frames = rpus.len();
+        let duration_minutes = frames as f64 / fps / 60.0;
+
+        let score = if frames > 0 {
+            100.0 * (1.0 - flagged.len() as f64 / frames as f64)
+        } else {
+            100.0
+        };
+
+        Self {
+            frames,
+            score,
+            mean_l1_delta: mean(&l1_deltas),
+            max_l1_delta: l1_deltas.iter().copied().fold(0.0, f64::max),
+            mean_l2_delta: mean(&l2_deltas),
+            max_l2_delta: l2_deltas.iter().copied().fold(0.0, f64::max),
+            scene_cuts: scene_cut_frames.len(),
+            cuts_per_minute: if duration_minutes > 0.0 {
+                scene_cut_frames.len() as f64 / duration_minutes
+            } else {
+                0.0
+            },
+            short_shots,
+            flagged,
+        }
+    }
+
+    pub fn to_report_string(&self) -> Result<String> {
+        let mut report = format!("Metadata stability score: {:.2}", self.score);
+
+        write!(
+            report,
+            "\n  L1 within-shot change: mean {:.5} PQ, max {:.5} PQ",
+            self.mean_l1_delta, self.max_l1_delta
+        )?;
+        write!(
+            report,
+            "\n  L2 within-shot change: mean {:.5}, max {:.5}",
+            self.mean_l2_delta, self.max_l2_delta
+        )?;
+        write!(
+            report,
+            "\n  Scene cuts: {} ({:.2} per minute), shots shorter than {MIN_STABLE_SHOT_FRAMES} frames: {}",
+            self.scene_cuts, self.cuts_per_minute, self.short_shots
+        )?;
+        write!(
+            report,
+            "\n  Flagged frames: {} of {}",
+            self.flagged.len(),
+            self.frames
+        )?;
+
+        for (frame, reason) in self.flagged.iter().take(MAX_REPORTED_FRAMES) {
+            write!(report, "\n    {}: {reason}", frame_label(*frame))?;
+        }
+
+        if self.flagged.len() > MAX_REPORTED_FRAMES {
+            write!(
+                report,
+                "\n    ... and {} more",
+                self.flagged.len() - MAX_REPORTED_FRAMES
+            )?;
+        }
+
+        Ok(report)
+    }
+}
+
+fn is_scene_cut(rpu: &DoviRpu) -> bool {
+    rpu.vdr_dm_data
+        .as_ref()
+        .map_or(false, |vdr| vdr.scene_refresh_flag == 1)
+}
+
+/// Largest of the L1 max and avg PQ changes
+fn l1_delta(previous: &DoviRpu, current: &DoviRpu) -> f64 {
+    let l1 = |rpu: &DoviRpu| match rpu.vdr_dm_data.as_ref()?.get_block(1) {
+        Some(ExtMetadataBlock::Level1(block)) => Some((block.max_pq, block.avg_pq)),
+        _ => None,
+    };
+
+    match (l1(previous), l1(current)) {
+        (Some((prev_max, prev_avg)), Some((max, avg))) => {
+            let max_delta = prev_max.abs_diff(max);
+            let avg_delta = prev_avg.abs_diff(avg);
+
+            max_delta.max(avg_delta) as f64 / 4095.0
+        }
+        _ => 0.0,
+    }
+}
+
+/// Largest slope/offset/power change of the L2 trims with the same target
+fn l2_delta(previous: &DoviRpu, current: &DoviRpu) -> f64 {
+    let (Some(prev_dm), Some(dm)) = (&previous.vdr_dm_data, &current.vdr_dm_data) else {
+        return 0.0;
+    };
+
+    let mut max_delta = 0_u16;
+
+    for block in dm.level_blocks_iter(2) {
+        let ExtMetadataBlock::Level2(l2) = block else {
+            continue;
+        };
+
+        let prev_l2 = prev_dm.level_blocks_iter(2).find_map(|b| match b {
+            ExtMetadataBlock::Level2(prev) if prev.target_max_pq == l2.target_max_pq => Some(prev),
+            _ => None,
+        });
+
+        if let Some(prev_l2) = prev_l2 {
+            max_delta = max_delta
+                .max(prev_l2.trim_slope.abs_diff(l2.trim_slope))
+                .max(prev_l2.trim_offset.abs_diff(l2.trim_offset))
+                .max(prev_l2.trim_power.abs_diff(l2.trim_power));
+        }
+    }
+
+    // 12 bit trims, 2048 is neutral
+    max_delta as f64 / 2048.0
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
@@ -1,31 +1,62 @@
-use std::io::Write;
+use std::io::{BufReader, Read, Write};
 use std::path::PathBuf;
 use std::{fs::File, io::BufWriter, path::Path};
 
 use anyhow::{bail, Result};
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 
-use dolby_vision::rpu::dovi_rpu::DoviRpu;
+use dolby_vision::rpu::dovi_rpu::{DoviRpu, RpuParseOpts};
+use dolby_vision::rpu::generate::{Annotation, GenerateConfig, ShotLabels, VideoShot};
+use dolby_vision::rpu::vdr_dm_data::VdrDmData;
+use dolby_vision::xml::{CmXmlParser, XmlParserOpts};
 
 use hevc_parser::hevc::{NALUnit, NAL_UNSPEC62};
 use hevc_parser::io::{IoFormat, StartCodePreset};
+use hevc_parser::NALUStartCode;
 
 use self::editor::EditConfig;
+use crate::ipc;
+
 use super::commands::ConversionModeCli;
 
+pub mod bitstream_format;
 pub mod converter;
 pub mod demuxer;
 pub mod editor;
 pub mod exporter;
+pub mod frame_timestamps;
 pub mod generator;
+pub mod injection_compare;
 pub mod muxer;
+pub mod ops;
 pub mod plotter;
+pub mod rpu_bitrate;
 pub mod rpu_extractor;
+pub mod rpu_histogram;
 pub mod rpu_info;
 pub mod rpu_injector;
+pub mod rpu_patch;
+pub mod rpu_reorder;
+pub mod rpu_verifier;
+pub mod trim_validation;
+pub mod watcher;
 
+mod bar_detection;
+mod bdmv;
+mod checkpoint;
+mod fmp4;
+mod frame_props;
 mod general_read_write;
 mod hdr10plus_utils;
+mod heif;
+mod hevc_sps;
+mod metadata_stability;
+mod primaries_export;
+mod rpu_store;
+mod sei_static_metadata;
+mod shot_boundaries;
+mod telemetry;
+mod y4m;
 
 #[derive(Debug, Clone)]
 pub struct CliOptions {
@@ -35,6 +66,16 @@ pub struct CliOptions {
     pub drop_hdr10plus: bool,
     pub edit_config: Option<EditConfig>,
     pub start_code: WriteStartCodePreset,
+    pub repeat_metadata: Option<usize>,
+    pub summary_json: Option<PathBuf>,
+    pub lenient: bool,
+    pub l6_from_sei: bool,
+    /// Highest temporal ID of the extracted sub-bitstream
+    pub temporal_id: Option<u8>,
+    /// Process without writing the outputs
+    pub dry_run: bool,
+    /// Options for parsing the RPUs, e.g. `--compat-old`
+    pub parse_opts: RpuParseOpts,
 }
 
 #[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
@@ -43,11 +84,151 @@ pub enum WriteStartCodePreset {
     AnnexB,
 }
 
+/// Start code of the RPU NALUs written in HEVC streams
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpuStartCode {
+    /// 4 bytes start code
+    Four,
+    /// 3 bytes start code
+    Three,
+    /// Same as the slices of the input stream
+    Auto,
+}
+
+/// Per-frame metadata export, for frame-synced filter chains
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramePropsFormat {
+    /// VapourSynth Python script applying the frame props
+    Vapoursynth,
+    /// JSON array of the frame props
+    Json,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonCompression {
+    Zstd,
+    Brotli,
+}
+
+impl JsonCompression {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            JsonCompression::Zstd => "zst",
+            JsonCompression::Brotli => "br",
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "zst" => Some(JsonCompression::Zstd),
+            "br" => Some(JsonCompression::Brotli),
+            _ => None,
+        }
+    }
+}
+
+impl RpuStartCode {
+    /// Detects the start code convention of the stream from its slices
+    pub fn detect(nals: &[NALUnit]) -> Self {
+        let has_short_slice_start_codes = nals
+            .iter()
+            .any(|nal| nal.nal_type < 32 && matches!(nal.start_code, NALUStartCode::Length3));
+
+        if has_short_slice_start_codes {
+            RpuStartCode::Three
+        } else {
+            RpuStartCode::Four
+        }
+    }
+
+    pub fn bytes(&self) -> &'static [u8] {
+        match self {
+            RpuStartCode::Three => &[0, 0, 1],
+            RpuStartCode::Four | RpuStartCode::Auto => &[0, 0, 0, 1],
+        }
+    }
+}
+
+impl std::fmt::Display for RpuStartCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpuStartCode::Four => write!(f, "4 bytes"),
+            RpuStartCode::Three => write!(f, "3 bytes"),
+            RpuStartCode::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+/// Opens a JSON file, decompressing it if it has a `.zst` or `.br` extension
+pub fn json_file_reader<P: AsRef<Path>>(path: P) -> Result<Box<dyn Read>> {
+    let path = path.as_ref();
+    let reader = BufReader::new(File::open(path)?);
+
+    let reader: Box<dyn Read> = match JsonCompression::from_path(path) {
+        Some(JsonCompression::Zstd) => Box::new(zstd::Decoder::with_buffer(reader)?),
+        Some(JsonCompression::Brotli) => Box::new(brotli::Decompressor::new(reader, 4096)),
+        None => Box::new(reader),
+    };
+
+    Ok(reader)
+}
+
+/// Annotations and shots of a review file.
+///
+/// Either a JSON list of annotations, a `generate` JSON config, or a CM XML.
+/// The XML shots have no labels, they are named by their `UniqueID`.
+/// Their record timecodes are made relative to the first shot, like the generated RPUs.
+pub fn read_annotations_file<P: AsRef<Path>>(path: P) -> Result<(Vec<Annotation>, Vec<VideoShot>)> {
+    let path = path.as_ref();
+
+    if path
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("xml"))
+    {
+        let mut shots = CmXmlParser::parse_file(path, XmlParserOpts::default())?
+            .config
+            .shots;
+
+        let first_start = shots.iter().map(|shot| shot.start).min().unwrap_or(0);
+
+        for shot in shots.iter_mut() {
+            shot.start -= first_start;
+
+            if shot.id.is_empty() {
+                continue;
+            }
+
+            shot.labels.get_or_insert_with(|| ShotLabels {
+                name: Some(shot.id.clone()),
+                ..Default::default()
+            });
+        }
+
+        return Ok((Vec::new(), shots));
+    }
+
+    let value: serde_json::Value = serde_json::from_reader(json_file_reader(path)?)?;
+
+    if value.is_object() {
+        let config = GenerateConfig::from_json_value(value)?;
+        Ok((config.annotations, config.shots))
+    } else {
+        Ok((serde_json::from_value(value)?, Vec::new()))
+    }
+}
+
+/// ` [labels]` suffix for the reports, empty without labels for the frame
+pub fn labels_suffix(shots: &[VideoShot], frame: usize) -> String {
+    VideoShot::labels_at(shots, frame)
+        .map(|labels| format!(" [{labels}]"))
+        .unwrap_or_default()
+}
+
 pub fn initialize_progress_bar<P: AsRef<Path>>(format: &IoFormat, input: P) -> Result<ProgressBar> {
     let pb: ProgressBar;
     let bytes_count;
 
-    if let IoFormat::RawStdin = format {
+    if matches!(format, IoFormat::RawStdin) || ipc::is_quiet() {
         pb = ProgressBar::hidden();
     } else {
         let file = File::open(input).expect("No file found");
@@ -56,18 +237,23 @@ pub fn initialize_progress_bar<P: AsRef<Path>>(format: &IoFormat, input: P) -> R
         let file_meta = file.metadata()?;
         bytes_count = file_meta.len() / 100_000_000;
 
-        pb = ProgressBar::new(bytes_count);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] {bar:60.cyan} {percent}%")?,
-        );
+        if crate::ipc::enabled() {
+            pb = ProgressBar::with_draw_target(Some(bytes_count), ProgressDrawTarget::hidden());
+            crate::ipc::watch_progress(&pb);
+        } else {
+            pb = ProgressBar::new(bytes_count);
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template("[{elapsed_precise}] {bar:60.cyan} {percent}%")?,
+            );
+        }
     }
 
     Ok(pb)
 }
 
 pub fn write_rpu_file<P: AsRef<Path>>(output_path: P, data: Vec<Vec<u8>>) -> Result<()> {
-    println!("Writing RPU file...");
+    ipc::log("Writing RPU file...");
     let mut writer = BufWriter::with_capacity(
         100_000,
         File::create(output_path).expect("Can't create file"),
@@ -90,11 +276,16 @@ pub fn write_rpu_file<P: AsRef<Path>>(output_path: P, data: Vec<Vec<u8>>) -> Res
 }
 
 pub fn convert_encoded_from_opts(opts: &CliOptions, data: &[u8]) -> Result<Vec<u8>> {
-    let mut dovi_rpu = DoviRpu::parse_unspec62_nalu(data)?;
+    let mut dovi_rpu = DoviRpu::parse_unspec62_nalu_with_opts(data, opts.parse_opts)?;
+    convert_rpu_from_opts(opts, &mut dovi_rpu)?;
 
+    dovi_rpu.write_hevc_unspec62_nalu()
+}
+
+pub fn convert_rpu_from_opts(opts: &CliOptions, dovi_rpu: &mut DoviRpu) -> Result<()> {
     // Config overrides manual arguments
     if let Some(edit_config) = &opts.edit_config {
-        edit_config.execute_single_rpu(&mut dovi_rpu)?;
+        edit_config.execute_single_rpu(dovi_rpu)?;
     } else {
         if let Some(mode) = opts.mode {
             dovi_rpu.convert_with_mode(mode)?;
@@ -105,7 +296,62 @@ pub fn convert_encoded_from_opts(opts: &CliOptions, data: &[u8]) -> Result<Vec<u
         }
     }
 
-    dovi_rpu.write_hevc_unspec62_nalu()
+    Ok(())
+}
+
+/// Strips the dynamic metadata blocks that are identical to the previous frame's.
+/// Full metadata is kept on scene cuts, IDR frames and at every `cadence` frames.
+pub struct MetadataRepeater {
+    cadence: usize,
+    frames_since_refresh: usize,
+    previous: Option<VdrDmData>,
+}
+
+impl MetadataRepeater {
+    /// Dynamic levels that can be omitted when repeated
+    const REPEATABLE_LEVELS: &'static [u8] = &[1, 2, 3, 8];
+
+    pub fn new(cadence: usize) -> Self {
+        Self {
+            cadence: cadence.max(1),
+            frames_since_refresh: 0,
+            previous: None,
+        }
+    }
+
+    pub fn process(&mut self, dovi_rpu: &mut DoviRpu, is_idr: bool) -> Result<()> {
+        let Some(vdr_dm_data) = dovi_rpu.vdr_dm_data.as_mut() else {
+            return Ok(());
+        };
+
+        let previous = self.previous.replace(vdr_dm_data.clone());
+
+        let refresh = is_idr
+            || vdr_dm_data.scene_refresh_flag == 1
+            || self.frames_since_refresh + 1 >= self.cadence;
+
+        let previous = match previous {
+            Some(previous) if !refresh => previous,
+            _ => {
+                self.frames_since_refresh = 0;
+                return Ok(());
+            }
+        };
+
+        self.frames_since_refresh += 1;
+
+        for &level in Self::REPEATABLE_LEVELS {
+            let repeated = vdr_dm_data
+                .level_blocks_iter(level)
+                .eq(previous.level_blocks_iter(level));
+
+            if repeated {
+                vdr_dm_data.remove_metadata_level(level);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 pub fn input_from_either(cmd: &str, in1: Option<PathBuf>, in2: Option<PathBuf>) -> Result<PathBuf> {
@@ -13,6 +13,7 @@ use hevc_parser::{hevc::*, NALUStartCode};
 use processor::{HevcProcessor, HevcProcessorOpts};
 
 use crate::commands::MuxArgs;
+use crate::ipc;
 
 use super::hdr10plus_utils::prefix_sei_removed_hdr10plus_nalu;
 use super::{convert_encoded_from_opts, CliOptions, IoFormat, WriteStartCodePreset};
@@ -123,7 +124,7 @@ impl Muxer {
     }
 
     fn interleave_el(&mut self) -> Result<()> {
-        println!("Rewriting file with interleaved EL NALUs..");
+        ipc::log("Rewriting file with interleaved EL NALUs..");
         stdout().flush().ok();
 
         let chunk_size = 100_000;
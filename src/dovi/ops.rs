@@ -0,0 +1,175 @@
+//! One-call functions for the common pipelines.
+//!
+//! Meant for embedding the tool (e.g. GUI wrappers) without spawning processes.
+//! Every function runs the same code as the matching CLI commands,
+//! without printing anything: the results and errors are returned instead.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Result};
+
+use dolby_vision::rpu::generate::GenerateConfig;
+use dolby_vision::rpu::utils::parse_rpu_file;
+
+use super::bitstream_format::FormatRegistry;
+use super::generator::{Generator, GeneratorProfile};
+use super::rpu_extractor::RpuExtractor;
+use super::rpu_injector::{Hdr10PlusSei, RpuInjector};
+use super::{write_rpu_file, CliOptions, WriteStartCodePreset};
+use crate::commands::{
+    ArgHdr10PlusPeakBrightnessSource, ConversionModeCli, ExtractRpuArgs, GenerateArgs,
+    InjectRpuArgs,
+};
+use crate::ipc;
+
+/// Default CLI options, with the RPU conversion mode to use
+pub fn cli_options(mode: Option<ConversionModeCli>) -> CliOptions {
+    CliOptions {
+        mode,
+        crop: false,
+        discard_el: false,
+        drop_hdr10plus: false,
+        edit_config: None,
+        start_code: WriteStartCodePreset::Four,
+        repeat_metadata: None,
+        summary_json: None,
+        lenient: false,
+        l6_from_sei: false,
+        temporal_id: None,
+        dry_run: false,
+        parse_opts: Default::default(),
+    }
+}
+
+/// Extracts the RPU from `source`, converts it with `mode` and injects it into `target`.
+///
+/// `source` can be a HEVC or fragmented MP4 file, `target` must be a raw HEVC file.
+pub fn transfer_rpu<P: AsRef<Path>>(
+    source: P,
+    target: P,
+    output: P,
+    mode: Option<ConversionModeCli>,
+) -> Result<()> {
+    let rpu_path = temp_rpu_path(output.as_ref());
+
+    let extract_args = ExtractRpuArgs {
+        input: Some(source.as_ref().to_path_buf()),
+        input_pos: None,
+        rpu_out: Some(rpu_path.clone()),
+        init_segment: None,
+        l6_from_sei: false,
+        temporal_id: None,
+    };
+    let inject_args = InjectRpuArgs {
+        input: Some(target.as_ref().to_path_buf()),
+        input_pos: None,
+        rpu_in: rpu_path.clone(),
+        output: Some(output.as_ref().to_path_buf()),
+        no_add_aud: false,
+        mv_hevc: false,
+        rpu_start_code: None,
+        hdr10plus: Hdr10PlusSei::Keep,
+        l6_from_sei: false,
+        truncate_rpu: false,
+        align_end: false,
+        resume: false,
+        checkpoint_interval: None,
+    };
+
+    let res = ipc::quiet(|| {
+        RpuExtractor::extract_rpu(extract_args, cli_options(mode))
+            .and_then(|_| RpuInjector::inject_rpu(inject_args, cli_options(None)))
+    });
+
+    fs::remove_file(&rpu_path).ok();
+
+    res
+}
+
+/// Extracts the RPU from `input`, converted with `mode`.
+///
+/// The input is read with the matching format of `formats`,
+/// which can include custom container formats.
+pub fn extract_rpu<P: AsRef<Path>>(
+    input: P,
+    rpu_out: P,
+    mode: Option<ConversionModeCli>,
+    formats: &FormatRegistry,
+) -> Result<()> {
+    let args = ExtractRpuArgs {
+        input: Some(input.as_ref().to_path_buf()),
+        input_pos: None,
+        rpu_out: Some(rpu_out.as_ref().to_path_buf()),
+        init_segment: None,
+        l6_from_sei: false,
+        temporal_id: None,
+    };
+
+    ipc::quiet(|| RpuExtractor::extract_rpu_with_formats(args, cli_options(mode), formats))
+}
+
+/// Generates a RPU from a generator config, using HDR10+ metadata for the L1 metadata.
+pub fn generate_from_hdr10plus<P: AsRef<Path>>(
+    config_json: P,
+    hdr10plus_json: P,
+    rpu_out: P,
+    profile: Option<GeneratorProfile>,
+    peak_source: ArgHdr10PlusPeakBrightnessSource,
+) -> Result<GenerateConfig> {
+    let args = GenerateArgs {
+        json_file: Some(config_json.as_ref().to_path_buf()),
+        rpu_out: Some(rpu_out.as_ref().to_path_buf()),
+        hdr10plus_json: Some(hdr10plus_json.as_ref().to_path_buf()),
+        hdr10plus_peak_source: Some(peak_source),
+        profile,
+        ..Default::default()
+    };
+
+    ipc::quiet(|| {
+        let mut generator = Generator::from_args(args)?;
+        generator.execute()?;
+
+        Ok(generator.config.expect("Missing generated config"))
+    })
+}
+
+/// Replaces the metadata `levels` of every RPU in `rpu_in` with the ones from `source_rpu`.
+///
+/// Both RPU files must have the same number of frames.
+pub fn transplant_trims<P: AsRef<Path>>(
+    rpu_in: P,
+    source_rpu: P,
+    rpu_out: P,
+    levels: &Vec<u8>,
+) -> Result<()> {
+    let mut rpus = parse_rpu_file(rpu_in.as_ref())?;
+    let source_rpus = parse_rpu_file(source_rpu.as_ref())?;
+
+    ensure!(
+        rpus.len() == source_rpus.len(),
+        "RPU frame count mismatch: {} frames, source has {}",
+        rpus.len(),
+        source_rpus.len()
+    );
+
+    for (dst_rpu, src_rpu) in rpus.iter_mut().zip(&source_rpus) {
+        dst_rpu.replace_levels_from_rpu(src_rpu, levels)?;
+    }
+
+    let data = rpus
+        .iter()
+        .map(|rpu| rpu.write_hevc_unspec62_nalu())
+        .collect::<Result<Vec<_>>>()?;
+
+    ipc::quiet(|| write_rpu_file(rpu_out, data))
+}
+
+fn temp_rpu_path(output: &Path) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+
+    std::env::temp_dir().join(format!("{stem}_{}_RPU.bin", std::process::id()))
+}
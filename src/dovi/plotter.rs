@@ -5,7 +5,7 @@ use std::path::PathBuf;
 #[cfg(not(feature = "system-font"))]
 use anyhow::bail;
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use plotters::coord::ranged1d::{KeyPointHint, NoDefaultFormatting, Ranged, ValueFormatter};
 use plotters::coord::types::RangedCoordusize;
 use plotters::prelude::{
@@ -14,12 +14,14 @@ use plotters::prelude::{
 };
 use plotters::style::{Color, IntoTextStyle, RGBColor, ShapeStyle, BLACK};
 
-use dolby_vision::rpu::utils::parse_rpu_file;
+use dolby_vision::rpu::dovi_rpu::RpuParseOpts;
+use dolby_vision::rpu::utils::parse_rpu_file_with_opts;
 use dolby_vision::utils::{nits_to_pq, pq_to_nits};
 
 use super::input_from_either;
 use super::rpu_info::RpusListSummary;
 use crate::commands::PlotArgs;
+use crate::ipc;
 
 #[cfg(not(feature = "system-font"))]
 const NOTO_SANS_REGULAR: &[u8] = include_bytes!(concat!(
@@ -27,6 +29,9 @@ const NOTO_SANS_REGULAR: &[u8] = include_bytes!(concat!(
     "/assets/NotoSans-Regular.ttf"
 ));
 
+/// L1 min, max and average PQ values at a frame
+type L1Point = (usize, (f64, f64, f64));
+
 const MAX_COLOR: RGBColor = RGBColor(65, 105, 225);
 const AVERAGE_COLOR: RGBColor = RGBColor(75, 0, 130);
 
@@ -35,7 +40,8 @@ pub struct Plotter {
 }
 
 impl Plotter {
-    pub fn plot(args: PlotArgs) -> Result<()> {
+    /// Registers the bundled font, unless using the system fonts
+    pub fn register_font() -> Result<()> {
         #[cfg(not(feature = "system-font"))]
         {
             let res = plotters::style::register_font(
@@ -49,21 +55,33 @@ impl Plotter {
             }
         }
 
+        Ok(())
+    }
+
+    pub fn plot(args: PlotArgs, parse_opts: RpuParseOpts) -> Result<()> {
+        Self::register_font()?;
+
         let PlotArgs {
             input,
             input_pos,
             output,
             title,
+            sample_every,
+            per_shot,
         } = args;
 
+        if let Some(n) = sample_every {
+            ensure!(n > 0, "Plotter: sample interval must be at least 1 frame");
+        }
+
         let output = output.unwrap_or(PathBuf::from("L1_plot.png"));
         let title = title.unwrap_or(String::from("Dolby Vision L1 plot"));
 
         let input = input_from_either("info", input, input_pos)?;
         let plotter = Plotter { input };
 
-        println!("Parsing RPU file...");
-        let rpus = parse_rpu_file(plotter.input)?;
+        ipc::log("Parsing RPU file...");
+        let rpus = parse_rpu_file_with_opts(plotter.input, parse_opts)?;
 
         let x_spec = 0..rpus.len();
 
@@ -73,7 +91,7 @@ impl Plotter {
             .margin(30, 30, 60, 60)
             .titled(&title, ("sans-serif", 40))?;
 
-        println!("Plotting...");
+        ipc::log("Plotting...");
         let summary = RpusListSummary::new(&rpus)?;
 
         let mut chart = ChartBuilder::on(&root)
@@ -94,7 +112,16 @@ impl Plotter {
             .y_desc("nits (cd/m²)")
             .draw()?;
 
-        Self::draw_l1_series(&mut chart, &summary)?;
+        let bucket_starts: Vec<usize> = if per_shot {
+            summary.shot_stats.iter().map(|s| s.start).collect()
+        } else {
+            (0..summary.l1_data.len())
+                .step_by(sample_every.unwrap_or(1))
+                .collect()
+        };
+        let points = l1_envelope(&summary.l1_data, &bucket_starts);
+
+        Self::draw_l1_series(&mut chart, &summary, &points)?;
         chart
             .configure_series_labels()
             .border_style(BLACK)
@@ -150,7 +177,7 @@ impl Plotter {
 
         root.present()?;
 
-        println!("Done.");
+        ipc::log("Done.");
 
         Ok(())
     }
@@ -158,8 +185,8 @@ impl Plotter {
     fn draw_l1_series(
         chart: &mut ChartContext<BitMapBackend, Cartesian2d<RangedCoordusize, PqCoord>>,
         summary: &RpusListSummary,
+        points: &[L1Point],
     ) -> Result<()> {
-        let data = &summary.l1_data;
         let l1_stats = &summary.l1_stats;
 
         let max_series_label = format!(
@@ -172,23 +199,20 @@ impl Plotter {
         );
 
         let max_series = AreaSeries::new(
-            (0..).zip(data.iter()).map(|(x, y)| (x, y.1)),
+            points.iter().map(|(x, y)| (*x, y.1)),
             0.0,
             MAX_COLOR.mix(0.25),
         )
         .border_style(MAX_COLOR);
         let avg_series = AreaSeries::new(
-            (0..).zip(data.iter()).map(|(x, y)| (x, y.2)),
+            points.iter().map(|(x, y)| (*x, y.2)),
             0.0,
             AVERAGE_COLOR.mix(0.50),
         )
         .border_style(AVERAGE_COLOR);
-        let min_series = AreaSeries::new(
-            (0..).zip(data.iter()).map(|(x, y)| (x, y.0)),
-            0.0,
-            BLACK.mix(0.50),
-        )
-        .border_style(BLACK);
+        let min_series =
+            AreaSeries::new(points.iter().map(|(x, y)| (*x, y.0)), 0.0, BLACK.mix(0.50))
+                .border_style(BLACK);
 
         chart
             .draw_series(max_series)?
@@ -234,6 +258,35 @@ impl Plotter {
     }
 }
 
+/// Merges the frames starting at every bucket start into a single point.
+///
+/// The min/max envelope is kept, so that short spikes are still visible.
+/// The last point is repeated at the last frame to extend the plot to the end.
+fn l1_envelope(data: &[(f64, f64, f64)], bucket_starts: &[usize]) -> Vec<L1Point> {
+    let mut points: Vec<L1Point> = bucket_starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = bucket_starts.get(i + 1).copied().unwrap_or(data.len());
+            let frames = &data[start..end];
+
+            let min = frames.iter().map(|e| e.0).fold(f64::MAX, f64::min);
+            let max = frames.iter().map(|e| e.1).fold(0.0, f64::max);
+            let avg = frames.iter().map(|e| e.2).sum::<f64>() / frames.len() as f64;
+
+            (start, (min, max, avg))
+        })
+        .collect();
+
+    if let Some(&(start, values)) = points.last() {
+        if start + 1 < data.len() {
+            points.push((data.len() - 1, values));
+        }
+    }
+
+    points
+}
+
 pub struct PqCoord {}
 
 impl Ranged for PqCoord {
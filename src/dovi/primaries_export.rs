@@ -0,0 +1,185 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use anyhow::{bail, ensure, Result};
+use serde::Serialize;
+
+use dolby_vision::rpu::extension_metadata::blocks::{ExtMetadataBlock, ExtMetadataBlockLevel9};
+
+use crate::ipc;
+
+use super::DoviRpu;
+
+type Matrix3 = [[f64; 3]; 3];
+
+/// ACES AP0 primaries and white point, as xy coordinates
+const ACES_AP0_PRIMARIES: [f64; 8] = [0.7347, 0.2653, 0.0, 1.0, 0.0001, -0.077, 0.32168, 0.33767];
+
+/// Bradford chromatic adaptation cone response matrix
+const BRADFORD: Matrix3 = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+/// L9 source primaries of the title, in a form usable by color pipelines.
+///
+/// The matrices convert linear RGB in the source primaries,
+/// to CIE XYZ and to ACES2065-1 (AP0, with Bradford adaptation to the ACES white point).
+#[derive(Debug, Serialize)]
+pub struct PrimariesExport {
+    /// L9 `source_primary_index`, 255 for custom primaries
+    pub source_primary_index: u8,
+
+    pub red: [f64; 2],
+    pub green: [f64; 2],
+    pub blue: [f64; 2],
+    pub white: [f64; 2],
+
+    pub rgb_to_xyz: Matrix3,
+    pub rgb_to_aces2065_1: Matrix3,
+}
+
+impl PrimariesExport {
+    pub fn from_level9(level9: &ExtMetadataBlockLevel9) -> Result<Self> {
+        let Some(primaries) = level9.primaries_xy() else {
+            bail!(
+                "Unknown L9 source primaries index {}",
+                level9.source_primary_index
+            );
+        };
+
+        let rgb_to_xyz = rgb_to_xyz_matrix(&primaries)?;
+        let xyz_to_aces = invert(&rgb_to_xyz_matrix(&ACES_AP0_PRIMARIES)?)?;
+
+        let adaptation = bradford_adaptation(
+            xy_to_xyz(primaries[6], primaries[7])?,
+            xy_to_xyz(ACES_AP0_PRIMARIES[6], ACES_AP0_PRIMARIES[7])?,
+        )?;
+
+        Ok(Self {
+            source_primary_index: level9.source_primary_index,
+            red: [primaries[0], primaries[1]],
+            green: [primaries[2], primaries[3]],
+            blue: [primaries[4], primaries[5]],
+            white: [primaries[6], primaries[7]],
+            rgb_to_xyz,
+            rgb_to_aces2065_1: multiply(&xyz_to_aces, &multiply(&adaptation, &rgb_to_xyz)),
+        })
+    }
+
+    /// Primaries of the first RPU with L9 metadata
+    pub fn from_rpus(rpus: &[DoviRpu]) -> Result<Self> {
+        let mut level9_blocks = rpus.iter().filter_map(|rpu| {
+            rpu.vdr_dm_data
+                .as_ref()
+                .and_then(|vdr| vdr.get_block(9))
+                .and_then(|block| match block {
+                    ExtMetadataBlock::Level9(level9) => Some(level9),
+                    _ => None,
+                })
+        });
+
+        let Some(first) = level9_blocks.next() else {
+            bail!("No L9 metadata in the RPUs, the source primaries can't be exported");
+        };
+
+        if level9_blocks.any(|level9| level9 != first) {
+            ipc::warn("the L9 source primaries change within the RPUs, using the first");
+        }
+
+        Self::from_level9(first)
+    }
+
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+
+        Ok(())
+    }
+}
+
+fn xy_to_xyz(x: f64, y: f64) -> Result<[f64; 3]> {
+    ensure!(y != 0.0, "Invalid primaries, y coordinate of zero");
+
+    Ok([x / y, 1.0, (1.0 - x - y) / y])
+}
+
+/// Normalized primary matrix, from xy primaries and white point
+fn rgb_to_xyz_matrix(primaries: &[f64; 8]) -> Result<Matrix3> {
+    let red = xy_to_xyz(primaries[0], primaries[1])?;
+    let green = xy_to_xyz(primaries[2], primaries[3])?;
+    let blue = xy_to_xyz(primaries[4], primaries[5])?;
+    let white = xy_to_xyz(primaries[6], primaries[7])?;
+
+    let xyz = [
+        [red[0], green[0], blue[0]],
+        [red[1], green[1], blue[1]],
+        [red[2], green[2], blue[2]],
+    ];
+
+    // Scale of each primary so that RGB 1,1,1 is the white point
+    let scale = apply(&invert(&xyz)?, white);
+
+    Ok(xyz.map(|row| [row[0] * scale[0], row[1] * scale[1], row[2] * scale[2]]))
+}
+
+fn bradford_adaptation(source_white: [f64; 3], dest_white: [f64; 3]) -> Result<Matrix3> {
+    let source_lms = apply(&BRADFORD, source_white);
+    let dest_lms = apply(&BRADFORD, dest_white);
+
+    let mut gain = [[0.0; 3]; 3];
+    for (i, row) in gain.iter_mut().enumerate() {
+        row[i] = dest_lms[i] / source_lms[i];
+    }
+
+    Ok(multiply(&invert(&BRADFORD)?, &multiply(&gain, &BRADFORD)))
+}
+
+fn apply(m: &Matrix3, v: [f64; 3]) -> [f64; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn multiply(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut out = [[0.0; 3]; 3];
+
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+
+    out
+}
+
+fn invert(m: &Matrix3) -> Result<Matrix3> {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+
+    let adjugate = [
+        [
+            cofactor(1, 2, 1, 2),
+            -cofactor(0, 2, 1, 2),
+            cofactor(0, 1, 1, 2),
+        ],
+        [
+            -cofactor(1, 2, 0, 2),
+            cofactor(0, 2, 0, 2),
+            -cofactor(0, 1, 0, 2),
+        ],
+        [
+            cofactor(1, 2, 0, 1),
+            -cofactor(0, 2, 0, 1),
+            cofactor(0, 1, 0, 1),
+        ],
+    ];
+
+    let determinant: f64 = (0..3).map(|j| m[0][j] * adjugate[j][0]).sum();
+    ensure!(
+        determinant.abs() > 1e-12,
+        "Invalid primaries, singular matrix"
+    );
+
+    Ok(adjugate.map(|row| row.map(|v| v / determinant)))
+}
@@ -0,0 +1,303 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Result};
+use plotters::prelude::{
+    BitMapBackend, ChartBuilder, IntoDrawingArea, LineSeries, PathElement, SeriesLabelPosition,
+    WHITE,
+};
+use plotters::style::{Color, RGBColor, ShapeStyle, BLACK};
+
+use dolby_vision::utils::frames_to_timecode;
+
+use super::input_from_either;
+use super::plotter::Plotter;
+use super::rpu_verifier::split_nalus;
+use crate::commands::BitrateArgs;
+use crate::ipc;
+
+const BITRATE_COLOR: RGBColor = RGBColor(65, 105, 225);
+const THRESHOLD_COLOR: RGBColor = RGBColor(220, 20, 60);
+
+// UNSPEC62 NAL header, not present in RPU files
+const NAL_HEADER: &[u8] = &[0x7C, 0x01];
+
+const MAX_REPORTED_SPIKES: usize = 10;
+
+pub struct RpuBitrate {
+    input: PathBuf,
+    fps: f64,
+    window: f64,
+    threshold: Option<f64>,
+}
+
+/// RPU bytes over a window of frames
+#[derive(Debug)]
+struct BitrateWindow {
+    start: usize,
+    frames: usize,
+    bytes: usize,
+    bytes_per_second: f64,
+}
+
+impl RpuBitrate {
+    pub fn bitrate(args: BitrateArgs) -> Result<()> {
+        let BitrateArgs {
+            input,
+            input_pos,
+            fps,
+            window,
+            threshold,
+            csv,
+            plot,
+        } = args;
+
+        ensure!(fps > 0.0, "Invalid frame rate {fps}");
+        ensure!(window > 0.0, "Invalid window duration {window}");
+
+        let input = input_from_either("bitrate", input, input_pos)?;
+        let bitrate = RpuBitrate {
+            input,
+            fps,
+            window,
+            threshold,
+        };
+
+        ipc::log("Reading RPU file...");
+        let frame_sizes = bitrate.frame_sizes()?;
+        let windows = bitrate.windows(&frame_sizes);
+
+        bitrate.print_summary(&frame_sizes, &windows);
+
+        if let Some(csv_path) = csv {
+            ipc::log("Writing CSV...");
+            bitrate.write_csv(csv_path, &windows)?;
+        }
+
+        if let Some(plot_path) = plot {
+            ipc::log("Plotting...");
+            bitrate.plot(plot_path, &windows)?;
+        }
+
+        Ok(())
+    }
+
+    /// Size of the RPU NAL unit of every frame in the HEVC stream,
+    /// including the 4 bytes start code and the NAL header
+    fn frame_sizes(&self) -> Result<Vec<usize>> {
+        let data = fs::read(&self.input)?;
+
+        let sizes: Vec<usize> = split_nalus(&data)
+            .iter()
+            .map(|nalu| {
+                if nalu[4..].starts_with(NAL_HEADER) {
+                    nalu.len()
+                } else {
+                    nalu.len() + NAL_HEADER.len()
+                }
+            })
+            .collect();
+
+        if sizes.is_empty() {
+            bail!("No RPU found");
+        }
+
+        Ok(sizes)
+    }
+
+    fn windows(&self, frame_sizes: &[usize]) -> Vec<BitrateWindow> {
+        let window_frames = ((self.window * self.fps).round() as usize).max(1);
+
+        frame_sizes
+            .chunks(window_frames)
+            .enumerate()
+            .map(|(i, sizes)| {
+                let bytes = sizes.iter().sum();
+
+                BitrateWindow {
+                    start: i * window_frames,
+                    frames: sizes.len(),
+                    bytes,
+                    bytes_per_second: bytes as f64 * self.fps / sizes.len() as f64,
+                }
+            })
+            .collect()
+    }
+
+    fn is_spike(&self, window: &BitrateWindow) -> bool {
+        self.threshold
+            .map_or(false, |threshold| window.bytes_per_second > threshold)
+    }
+
+    fn print_summary(&self, frame_sizes: &[usize], windows: &[BitrateWindow]) {
+        let total_bytes: usize = frame_sizes.iter().sum();
+        let duration = frame_sizes.len() as f64 / self.fps;
+        let average = total_bytes as f64 / duration;
+
+        let min_size = frame_sizes.iter().min().copied().unwrap_or(0);
+        let max_size = frame_sizes.iter().max().copied().unwrap_or(0);
+
+        ipc::log(format!(
+            "Frames: {}, duration: {:.3}s, total: {} bytes",
+            frame_sizes.len(),
+            duration,
+            total_bytes
+        ));
+        ipc::log(format!(
+            "Average bitrate: {:.1} bytes/s ({:.2} kbit/s)",
+            average,
+            average * 8.0 / 1000.0
+        ));
+        ipc::log(format!(
+            "Frame size: min {} bytes, max {} bytes, average {:.1} bytes",
+            min_size,
+            max_size,
+            total_bytes as f64 / frame_sizes.len() as f64
+        ));
+
+        let peak = windows
+            .iter()
+            .max_by(|a, b| a.bytes_per_second.total_cmp(&b.bytes_per_second));
+
+        if let Some(peak) = peak {
+            ipc::log(format!(
+                "Peak bitrate over {}s windows: {:.1} bytes/s ({:.2} kbit/s) at {}",
+                self.window,
+                peak.bytes_per_second,
+                peak.bytes_per_second * 8.0 / 1000.0,
+                frames_to_timecode(peak.start, self.fps)
+            ));
+        }
+
+        if let Some(threshold) = self.threshold {
+            let spikes: Vec<&BitrateWindow> = windows.iter().filter(|w| self.is_spike(w)).collect();
+
+            ipc::log(format!(
+                "Windows above {threshold} bytes/s: {} of {}",
+                spikes.len(),
+                windows.len()
+            ));
+
+            for spike in spikes.iter().take(MAX_REPORTED_SPIKES) {
+                ipc::log(format!(
+                    "  {} (frames {}-{}): {:.1} bytes/s",
+                    frames_to_timecode(spike.start, self.fps),
+                    spike.start,
+                    spike.start + spike.frames - 1,
+                    spike.bytes_per_second
+                ));
+            }
+
+            if spikes.len() > MAX_REPORTED_SPIKES {
+                ipc::log(format!(
+                    "  ... and {} more",
+                    spikes.len() - MAX_REPORTED_SPIKES
+                ));
+            }
+        }
+    }
+
+    fn write_csv<P: AsRef<Path>>(&self, path: P, windows: &[BitrateWindow]) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writeln!(
+            writer,
+            "start_frame,end_frame,start_seconds,bytes,bytes_per_second,spike"
+        )?;
+
+        for window in windows {
+            writeln!(
+                writer,
+                "{},{},{:.3},{},{:.1},{}",
+                window.start,
+                window.start + window.frames - 1,
+                window.start as f64 / self.fps,
+                window.bytes,
+                window.bytes_per_second,
+                self.is_spike(window)
+            )?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn plot<P: AsRef<Path>>(&self, path: P, windows: &[BitrateWindow]) -> Result<()> {
+        Plotter::register_font()?;
+
+        let duration = windows
+            .last()
+            .map_or(0.0, |w| (w.start + w.frames) as f64 / self.fps);
+        let max_rate = windows
+            .iter()
+            .map(|w| w.bytes_per_second)
+            .chain(self.threshold)
+            .fold(0.0, f64::max);
+
+        let root = BitMapBackend::new(path.as_ref(), (3000, 1200)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let root = root
+            .margin(30, 30, 60, 60)
+            .titled("Dolby Vision RPU bitrate", ("sans-serif", 40))?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .x_label_area_size(60)
+            .y_label_area_size(100)
+            .build_cartesian_2d(0.0..duration.max(1.0), 0.0..(max_rate * 1.1).max(1.0))?;
+
+        chart
+            .configure_mesh()
+            .bold_line_style(BLACK.mix(0.10))
+            .light_line_style(BLACK.mix(0.01))
+            .label_style(("sans-serif", 22))
+            .axis_desc_style(("sans-serif", 24))
+            .x_desc("seconds")
+            .y_desc("bytes/s")
+            .draw()?;
+
+        let points = windows
+            .iter()
+            .map(|w| (w.start as f64 / self.fps, w.bytes_per_second));
+
+        chart
+            .draw_series(LineSeries::new(points, BITRATE_COLOR.stroke_width(2)))?
+            .label(format!("Bitrate over {}s windows", self.window))
+            .legend(|(x, y)| {
+                PathElement::new(vec![(x, y), (x + 20, y)], legend_style(BITRATE_COLOR))
+            });
+
+        if let Some(threshold) = self.threshold {
+            chart
+                .draw_series(LineSeries::new(
+                    [(0.0, threshold), (duration, threshold)],
+                    THRESHOLD_COLOR.stroke_width(2),
+                ))?
+                .label(format!("Threshold ({threshold} bytes/s)"))
+                .legend(|(x, y)| {
+                    PathElement::new(vec![(x, y), (x + 20, y)], legend_style(THRESHOLD_COLOR))
+                });
+        }
+
+        chart
+            .configure_series_labels()
+            .border_style(BLACK)
+            .position(SeriesLabelPosition::UpperRight)
+            .label_font(("sans-serif", 24))
+            .background_style(WHITE)
+            .draw()?;
+
+        root.present()?;
+
+        Ok(())
+    }
+}
+
+fn legend_style(color: RGBColor) -> ShapeStyle {
+    ShapeStyle {
+        color: color.to_rgba(),
+        filled: false,
+        stroke_width: 2,
+    }
+}
@@ -1,28 +1,40 @@
-use anyhow::{bail, Result};
-use indicatif::ProgressBar;
-use std::path::PathBuf;
+use anyhow::{bail, ensure, Result};
+use std::path::{Path, PathBuf};
+
+use hevc_parser::io::IoFormat;
 
 use crate::commands::ExtractRpuArgs;
+use crate::ipc;
 
-use super::{general_read_write, input_from_either, CliOptions, IoFormat};
+use super::bitstream_format::{FormatRegistry, RpuSource};
+use super::telemetry::{ConversionTelemetry, RpuSnapshot};
+use super::{
+    convert_rpu_from_opts, general_read_write, input_from_either, write_rpu_file, CliOptions,
+    DoviRpu,
+};
 use general_read_write::{DoviProcessor, DoviWriter};
 
 pub struct RpuExtractor {
-    format: IoFormat,
     input: PathBuf,
+    init_segment: Option<PathBuf>,
     rpu_out: PathBuf,
 }
 
 impl RpuExtractor {
-    pub fn from_args(args: ExtractRpuArgs) -> Result<Self> {
+    pub fn from_args(args: ExtractRpuArgs, options: &mut CliOptions) -> Result<Self> {
         let ExtractRpuArgs {
             input,
             input_pos,
             rpu_out,
+            init_segment,
+            l6_from_sei,
+            temporal_id,
         } = args;
 
+        options.l6_from_sei = l6_from_sei;
+        options.temporal_id = temporal_id;
+
         let input = input_from_either("extract-rpu", input, input_pos)?;
-        let format = hevc_parser::io::format_from_path(&input)?;
 
         let rpu_out = match rpu_out {
             Some(path) => path,
@@ -30,30 +42,217 @@ impl RpuExtractor {
         };
 
         Ok(Self {
-            format,
             input,
+            init_segment,
             rpu_out,
         })
     }
 
     pub fn extract_rpu(args: ExtractRpuArgs, options: CliOptions) -> Result<()> {
-        let rpu_extractor = RpuExtractor::from_args(args)?;
-        rpu_extractor.process_input(options)
+        Self::extract_rpu_with_formats(args, options, &FormatRegistry::default())
+    }
+
+    /// Extracts the RPUs, reading the input with the matching format of `formats`
+    pub fn extract_rpu_with_formats(
+        args: ExtractRpuArgs,
+        mut options: CliOptions,
+        formats: &FormatRegistry,
+    ) -> Result<()> {
+        let rpu_extractor = RpuExtractor::from_args(args, &mut options)?;
+        rpu_extractor.process_input(options, formats)
+    }
+
+    fn process_input(&self, options: CliOptions, formats: &FormatRegistry) -> Result<()> {
+        let format = formats.find(&self.input)?;
+
+        if self.init_segment.is_some() && !format.supports_init_segment() {
+            bail!(
+                "Extractor: --init-segment is not supported for {} input",
+                format.name()
+            );
+        }
+
+        let source = format
+            .open(&self.input, self.init_segment.as_deref())
+            .map_err(|e| anyhow::anyhow!("Extractor: {e}"))?;
+
+        match source {
+            RpuSource::Segments(segments) => {
+                self.extract_rpu_from_segments(&segments, options, formats)
+            }
+            source => Self::extract_rpu_from_source(source, &self.rpu_out, options),
+        }
+    }
+
+    fn extract_rpu_from_source(
+        source: RpuSource,
+        rpu_out: &Path,
+        options: CliOptions,
+    ) -> Result<()> {
+        match source {
+            RpuSource::Nalus(rpus) => Self::extract_rpu_from_nalus(rpus, rpu_out, options),
+            source => {
+                let dovi_writer = DoviWriter::new(None, None, Some(rpu_out), None);
+                Self::extract_rpu_from_el(source, dovi_writer, options).map(|_| ())
+            }
+        }
+    }
+
+    /// Processes a HEVC bitstream source, returns the processor for the RPUs kept in memory
+    fn extract_rpu_from_el(
+        source: RpuSource,
+        dovi_writer: DoviWriter,
+        options: CliOptions,
+    ) -> Result<DoviProcessor> {
+        let telemetry = ConversionTelemetry::new("extract-rpu");
+
+        match source {
+            RpuSource::Hevc { path, format } => {
+                let pb = super::initialize_progress_bar(&format, &path)?;
+                let mut dovi_processor =
+                    DoviProcessor::new(options, path, dovi_writer, pb, telemetry);
+
+                dovi_processor.read_write_from_io(&format)?;
+
+                Ok(dovi_processor)
+            }
+            RpuSource::HevcStream { path, mut reader } => {
+                // Progress of the stream, relative to the container size
+                let pb = super::initialize_progress_bar(&IoFormat::Raw, &path)?;
+                let mut dovi_processor =
+                    DoviProcessor::new(options, path, dovi_writer, pb, telemetry);
+
+                dovi_processor.read_write_from_reader(&IoFormat::Raw, &mut reader)?;
+
+                Ok(dovi_processor)
+            }
+            RpuSource::Nalus(_) => bail!("Extractor: Expected a HEVC bitstream"),
+            RpuSource::Segments(_) => bail!("Extractor: Nested segments are unsupported"),
+        }
+    }
+
+    fn extract_rpu_from_nalus(
+        rpus: Vec<Vec<u8>>,
+        rpu_out: &Path,
+        options: CliOptions,
+    ) -> Result<()> {
+        let rpus = Self::convert_nalus(rpus, &options)?;
+        write_rpu_file(rpu_out, rpus)
     }
 
-    fn process_input(&self, options: CliOptions) -> Result<()> {
-        let pb = super::initialize_progress_bar(&self.format, &self.input)?;
+    /// Converts the demuxed RPUs with the CLI options, without writing them
+    fn convert_nalus(rpus: Vec<Vec<u8>>, options: &CliOptions) -> Result<Vec<Vec<u8>>> {
+        if options.l6_from_sei {
+            bail!("Extractor: --l6-from-sei requires a HEVC bitstream input");
+        }
+
+        if options.temporal_id.is_some() {
+            bail!("Extractor: --temporal-id requires a HEVC bitstream input");
+        }
+
+        let mut telemetry = ConversionTelemetry::new("extract-rpu");
+
+        let rpus = if options.mode.is_some() || options.edit_config.is_some() || options.lenient {
+            let mut previous_rpu_data = None;
+
+            rpus.iter()
+                .enumerate()
+                .map(|(i, data)| {
+                    let res = DoviRpu::parse_unspec62_nalu_with_opts(data, options.parse_opts)
+                        .and_then(|mut dovi_rpu| {
+                            let snapshot = RpuSnapshot::new(&dovi_rpu);
+
+                            convert_rpu_from_opts(options, &mut dovi_rpu)?;
+                            telemetry.record_conversion(&snapshot, &dovi_rpu);
+
+                            dovi_rpu.write_hevc_unspec62_nalu()
+                        });
+
+                    DoviProcessor::recover_rpu_nalu(
+                        res,
+                        options,
+                        &mut previous_rpu_data,
+                        &mut telemetry,
+                        i as u64,
+                    )
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            rpus.iter()
+                .for_each(|data| telemetry.record_unprocessed(data));
+
+            rpus
+        };
+
+        telemetry.finish(options.summary_json.as_ref())?;
 
-        match self.format {
-            IoFormat::Matroska => bail!("Extractor: Matroska input is unsupported"),
-            _ => self.extract_rpu_from_el(pb, options),
+        Ok(rpus)
+    }
+
+    /// Extracts the RPUs of every segment (e.g. clips of a Blu-ray playlist), in playback order
+    fn extract_rpu_from_segments(
+        &self,
+        segments: &[PathBuf],
+        options: CliOptions,
+        formats: &FormatRegistry,
+    ) -> Result<()> {
+        ensure!(
+            options.summary_json.is_none(),
+            "Extractor: --summary-json is not supported for playlists"
+        );
+
+        ipc::log(format!("Playlist with {} clips", segments.len()));
+
+        let mut rpus = Vec::new();
+
+        for (i, segment) in segments.iter().enumerate() {
+            ipc::log(format!(
+                "Clip {}/{}: {}",
+                i + 1,
+                segments.len(),
+                segment.display()
+            ));
+
+            let mut segment_rpus = Self::extract_segment_rpus(segment, options.clone(), formats)?;
+
+            // Playback jumps to a new clip, the first frame must be a scene cut
+            if let Some(first_rpu) = segment_rpus.first_mut() {
+                if let Some(vdr_dm_data) = first_rpu.vdr_dm_data.as_mut() {
+                    if vdr_dm_data.scene_refresh_flag != 1 {
+                        vdr_dm_data.scene_refresh_flag = 1;
+                        first_rpu.modified = true;
+                    }
+                }
+            }
+
+            rpus.extend(segment_rpus);
         }
+
+        let data = rpus
+            .iter()
+            .map(|rpu| rpu.write_hevc_unspec62_nalu())
+            .collect::<Result<Vec<_>>>()?;
+
+        write_rpu_file(&self.rpu_out, data)
     }
 
-    fn extract_rpu_from_el(&self, pb: ProgressBar, options: CliOptions) -> Result<()> {
-        let dovi_writer = DoviWriter::new(None, None, Some(&self.rpu_out), None);
-        let mut dovi_processor = DoviProcessor::new(options, self.input.clone(), dovi_writer, pb);
+    fn extract_segment_rpus(
+        segment: &Path,
+        options: CliOptions,
+        formats: &FormatRegistry,
+    ) -> Result<Vec<DoviRpu>> {
+        let source = formats.find(segment)?.open(segment, None)?;
+        let parse_opts = options.parse_opts;
+
+        let rpus = match source {
+            RpuSource::Nalus(rpus) => Self::convert_nalus(rpus, &options)?,
+            source => {
+                Self::extract_rpu_from_el(source, DoviWriter::rpu_sink(), options)?.into_rpus()
+            }
+        };
 
-        dovi_processor.read_write_from_io(&self.format)
+        rpus.iter()
+            .map(|data| DoviRpu::parse_unspec62_nalu_with_opts(data, parse_opts))
+            .collect()
     }
 }
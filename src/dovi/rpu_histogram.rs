@@ -0,0 +1,331 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Result};
+use plotters::prelude::{
+    BitMapBackend, ChartBuilder, Histogram, IntoDrawingArea, IntoSegmentedCoord, PathElement,
+    SegmentValue, SeriesLabelPosition, WHITE,
+};
+use plotters::style::{Color, RGBColor, ShapeStyle, BLACK};
+
+use dolby_vision::rpu::dovi_rpu::RpuParseOpts;
+use dolby_vision::rpu::utils::parse_rpu_file_with_opts;
+use dolby_vision::utils::pq_to_nits;
+
+use super::input_from_either;
+use super::plotter::Plotter;
+use super::rpu_info::{percentile, RpusListSummary};
+use crate::commands::HistogramArgs;
+use crate::ipc;
+
+const MAX_COLOR: RGBColor = RGBColor(65, 105, 225);
+const AVERAGE_COLOR: RGBColor = RGBColor(75, 0, 130);
+
+/// Width of the text bar of the fullest bin
+const BAR_WIDTH: usize = 40;
+
+const MAX_REPORTED_SHOTS: usize = 10;
+
+/// Shots needed for the quartiles of the shot medians to be meaningful
+const MIN_OUTLIER_SHOTS: usize = 4;
+
+/// Interquartile range multiplier of the outlier fences
+const OUTLIER_IQR_FACTOR: f64 = 1.5;
+
+pub struct RpuHistogram {
+    input: PathBuf,
+    bins: usize,
+}
+
+/// Distribution of the frame L1 max within a shot, in nits
+struct ShotDistribution {
+    start: usize,
+    duration: usize,
+
+    /// Min, 25th percentile, median, 75th percentile and max
+    quantiles: [f64; 5],
+
+    /// Median frame max, in PQ
+    median_pq: f64,
+}
+
+impl RpuHistogram {
+    pub fn histogram(args: HistogramArgs, parse_opts: RpuParseOpts) -> Result<()> {
+        let HistogramArgs {
+            input,
+            input_pos,
+            bins,
+            per_shot,
+            plot,
+        } = args;
+
+        let input = input_from_either("histogram", input, input_pos)?;
+        let histogram = RpuHistogram {
+            input,
+            bins: bins as usize,
+        };
+
+        ipc::log("Parsing RPU file...");
+        let rpus = parse_rpu_file_with_opts(&histogram.input, parse_opts)?;
+        ensure!(!rpus.is_empty(), "No RPU found");
+
+        let summary = RpusListSummary::new(&rpus)?;
+
+        let max_counts = histogram.bin_counts(summary.l1_data.iter().map(|e| e.1));
+        let avg_counts = histogram.bin_counts(summary.l1_data.iter().map(|e| e.2));
+
+        ipc::log(format!(
+            "Frames: {}, shots: {}",
+            summary.count,
+            summary.shot_stats.len()
+        ));
+
+        ipc::log("\nL1 max:");
+        histogram.print_bins(&max_counts, summary.count);
+
+        ipc::log("\nL1 average:");
+        histogram.print_bins(&avg_counts, summary.count);
+
+        let shots = shot_distributions(&summary);
+
+        if per_shot {
+            ipc::log("\nShots L1 max (min / 25% / median / 75% / max nits):");
+
+            for shot in &shots {
+                let quantiles: Vec<String> =
+                    shot.quantiles.iter().map(|q| format_nits(*q)).collect();
+
+                ipc::log(format!(
+                    "  {} ({} frames): {}",
+                    shot.start,
+                    shot.duration,
+                    quantiles.join(" / ")
+                ));
+            }
+        }
+
+        print_outlier_shots(&shots);
+
+        if let Some(plot_path) = plot {
+            ipc::log("Plotting...");
+            histogram.plot(plot_path, &max_counts, &avg_counts, summary.count)?;
+        }
+
+        ipc::log("Done.");
+
+        Ok(())
+    }
+
+    /// Number of frames in each bin, the bins being evenly spaced in PQ
+    fn bin_counts<I: Iterator<Item = f64>>(&self, values: I) -> Vec<usize> {
+        let mut counts = vec![0; self.bins];
+
+        for pq in values {
+            let bin = (pq * self.bins as f64) as usize;
+            counts[bin.min(self.bins - 1)] += 1;
+        }
+
+        counts
+    }
+
+    /// Brightness range of a bin, in nits
+    fn bin_range(&self, bin: usize) -> (f64, f64) {
+        let start = pq_to_nits(bin as f64 / self.bins as f64);
+        let end = pq_to_nits((bin + 1) as f64 / self.bins as f64);
+
+        (start, end)
+    }
+
+    fn print_bins(&self, counts: &[usize], frames: usize) {
+        let fullest = counts.iter().copied().max().unwrap_or(0).max(1);
+
+        for (bin, &count) in counts.iter().enumerate() {
+            let (start, end) = self.bin_range(bin);
+            let bar = "#".repeat((count * BAR_WIDTH + fullest - 1) / fullest);
+
+            ipc::log(format!(
+                "  {:>9} - {:>9} nits |{bar:<width$}| {:>5.1}% ({count})",
+                format_nits(start),
+                format_nits(end),
+                count as f64 * 100.0 / frames as f64,
+                width = BAR_WIDTH,
+            ));
+        }
+    }
+
+    fn plot<P: AsRef<Path>>(
+        &self,
+        path: P,
+        max_counts: &[usize],
+        avg_counts: &[usize],
+        frames: usize,
+    ) -> Result<()> {
+        Plotter::register_font()?;
+
+        let percent = |count: &usize| *count as f64 * 100.0 / frames as f64;
+        let highest = max_counts
+            .iter()
+            .chain(avg_counts)
+            .map(percent)
+            .fold(0.0, f64::max);
+
+        let root = BitMapBackend::new(path.as_ref(), (3000, 1200)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let root = root
+            .margin(30, 30, 60, 60)
+            .titled("Dolby Vision L1 histogram", ("sans-serif", 40))?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .x_label_area_size(60)
+            .y_label_area_size(100)
+            .build_cartesian_2d(
+                (0..self.bins).into_segmented(),
+                0.0..(highest * 1.1).max(1.0),
+            )?;
+
+        chart
+            .configure_mesh()
+            .bold_line_style(BLACK.mix(0.10))
+            .light_line_style(BLACK.mix(0.01))
+            .label_style(("sans-serif", 22))
+            .axis_desc_style(("sans-serif", 24))
+            .x_labels(self.bins)
+            .x_label_formatter(&|value| match value {
+                SegmentValue::Exact(bin) | SegmentValue::CenterOf(bin) => {
+                    format_nits(self.bin_range(*bin).0)
+                }
+                SegmentValue::Last => String::new(),
+            })
+            .x_desc("nits (cd/m²), bin start")
+            .y_desc("% of frames")
+            .draw()?;
+
+        for (label, color, counts) in [
+            ("L1 max", MAX_COLOR, max_counts),
+            ("L1 average", AVERAGE_COLOR, avg_counts),
+        ] {
+            chart
+                .draw_series(
+                    Histogram::vertical(&chart)
+                        .style(color.mix(0.5).filled())
+                        .margin(2)
+                        .data(counts.iter().enumerate().map(|(bin, c)| (bin, percent(c)))),
+                )?
+                .label(label)
+                .legend(move |(x, y)| {
+                    PathElement::new(
+                        vec![(x, y), (x + 20, y)],
+                        ShapeStyle {
+                            color: color.to_rgba(),
+                            filled: false,
+                            stroke_width: 2,
+                        },
+                    )
+                });
+        }
+
+        chart
+            .configure_series_labels()
+            .border_style(BLACK)
+            .position(SeriesLabelPosition::UpperRight)
+            .label_font(("sans-serif", 24))
+            .background_style(WHITE)
+            .draw()?;
+
+        root.present()?;
+
+        Ok(())
+    }
+}
+
+fn shot_distributions(summary: &RpusListSummary) -> Vec<ShotDistribution> {
+    summary
+        .shot_stats
+        .iter()
+        .map(|shot| {
+            let mut max_pq_values: Vec<f64> = summary.l1_data
+                [shot.start..shot.start + shot.duration]
+                .iter()
+                .map(|e| e.1)
+                .collect();
+
+            let quantiles_pq =
+                [0.0, 25.0, 50.0, 75.0, 100.0].map(|p| percentile(&mut max_pq_values, p));
+
+            ShotDistribution {
+                start: shot.start,
+                duration: shot.duration,
+                quantiles: quantiles_pq.map(pq_to_nits),
+                median_pq: quantiles_pq[2],
+            }
+        })
+        .collect()
+}
+
+/// Flags the shots with a median L1 max outside of the Tukey fences of all the shot medians
+fn print_outlier_shots(shots: &[ShotDistribution]) {
+    if shots.len() < MIN_OUTLIER_SHOTS {
+        return;
+    }
+
+    let mut medians: Vec<f64> = shots.iter().map(|shot| shot.median_pq).collect();
+    let q1 = percentile(&mut medians, 25.0);
+    let q3 = percentile(&mut medians, 75.0);
+
+    let iqr = q3 - q1;
+    let lower_fence = q1 - OUTLIER_IQR_FACTOR * iqr;
+    let upper_fence = q3 + OUTLIER_IQR_FACTOR * iqr;
+
+    let outliers: Vec<(&ShotDistribution, String)> = shots
+        .iter()
+        .filter_map(|shot| {
+            let reason = if shot.median_pq > upper_fence {
+                format!(
+                    "above the upper fence of {}",
+                    format_nits(pq_to_nits(upper_fence))
+                )
+            } else if shot.median_pq < lower_fence {
+                format!(
+                    "below the lower fence of {}",
+                    format_nits(pq_to_nits(lower_fence))
+                )
+            } else {
+                return None;
+            };
+
+            Some((shot, reason))
+        })
+        .collect();
+
+    ipc::log(format!(
+        "\nOutlier shots: {} of {}",
+        outliers.len(),
+        shots.len()
+    ));
+
+    for (shot, reason) in outliers.iter().take(MAX_REPORTED_SHOTS) {
+        ipc::log(format!(
+            "  Frames {}-{}: median max {} nits, {reason} nits",
+            shot.start,
+            shot.start + shot.duration - 1,
+            format_nits(shot.quantiles[2]),
+        ));
+    }
+
+    if outliers.len() > MAX_REPORTED_SHOTS {
+        ipc::log(format!(
+            "  ... and {} more",
+            outliers.len() - MAX_REPORTED_SHOTS
+        ));
+    }
+}
+
+/// Nits with more decimals for the darker values
+fn format_nits(nits: f64) -> String {
+    if nits >= 100.0 {
+        format!("{nits:.0}")
+    } else if nits >= 1.0 {
+        format!("{nits:.2}")
+    } else {
+        format!("{nits:.4}")
+    }
+}
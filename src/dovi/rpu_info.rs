@@ -2,18 +2,32 @@ use std::fmt::Write;
 use std::path::PathBuf;
 
 use anyhow::{bail, ensure, Result};
-use dolby_vision::rpu::vdr_dm_data::CmVersion;
+use dolby_vision::rpu::vdr_dm_data::{identical_metadata_runs, CmVersion, LongPlayModeCheck};
 use itertools::Itertools;
+use serde::Serialize;
 
+use dolby_vision::rpu::bit_fields::AnnotatedRpu;
 use dolby_vision::rpu::dovi_rpu::DoviRpu;
+use dolby_vision::rpu::dovi_rpu::RpuParseOpts;
 use dolby_vision::rpu::extension_metadata::blocks::{
     ExtMetadataBlock, ExtMetadataBlockLevel1, ExtMetadataBlockLevel6,
 };
-use dolby_vision::rpu::utils::parse_rpu_file;
-use dolby_vision::utils::pq_to_nits;
-
-use super::input_from_either;
+use dolby_vision::rpu::generate::{Annotation, ShotLabels, VideoShot};
+use dolby_vision::rpu::profiles::RpuProfile;
+use dolby_vision::rpu::rpu_data_nlq::DoviELType;
+use dolby_vision::rpu::utils::parse_rpu_file_with_opts;
+use dolby_vision::utils::{frames_to_timecode, pq_to_nits};
+
+use super::frame_timestamps::{frame_label, range_label};
+use super::metadata_stability::StabilityReport;
+use super::rpu_store::read_rpu_nalu;
+use super::trim_validation::TrimValidationReport;
+use super::{input_from_either, labels_suffix, read_annotations_file};
 use crate::commands::InfoArgs;
+use crate::ipc;
+
+/// Parse warnings listed in the summary, the rest are only counted
+const MAX_LISTED_PARSE_WARNINGS: usize = 20;
 
 pub struct RpuInfo {
     input: PathBuf,
@@ -31,28 +45,74 @@ pub struct RpusListSummary {
     pub l1_data: Vec<(f64, f64, f64)>,
     pub l1_stats: SummaryL1Stats,
     pub l2_trims: Vec<String>,
+
+    pub shot_stats: Vec<ShotL1Stats>,
 }
 
 pub struct SummaryL1Stats {
     pub maxcll: f64,
     pub maxcll_avg: f64,
 
+    /// 99.99th percentile of the frame max brightness
+    pub maxcll_percentile: f64,
+
     pub maxfall: f64,
     pub maxfall_avg: f64,
 
+    /// Standard deviation of the frame average brightness, in nits
+    pub maxfall_stddev: f64,
+
     pub max_min_nits: f64,
 }
 
+/// L1 statistics for a single shot, as delimited by the scene cut flags
+#[derive(Debug, Clone, Serialize)]
+pub struct ShotL1Stats {
+    pub start: usize,
+    pub duration: usize,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_timecode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_timecode: Option<String>,
+
+    pub max_nits: f64,
+    /// 99.99th percentile of the frame max brightness
+    pub max_nits_percentile: f64,
+
+    pub avg_nits: f64,
+    /// Standard deviation of the frame average brightness
+    pub avg_nits_stddev: f64,
+
+    /// Review notes overlapping the shot
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<String>,
+
+    /// Editorial labels of the `--annotations` shot containing the shot start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<ShotLabels>,
+}
+
+pub const L1_STATS_PERCENTILE: f64 = 99.99;
+
 impl RpuInfo {
-    pub fn info(args: InfoArgs) -> Result<()> {
+    pub fn info(args: InfoArgs, parse_opts: RpuParseOpts) -> Result<()> {
         let InfoArgs {
             input,
             input_pos,
             frame,
+            bits,
             summary,
+            identical_runs,
+            stability,
+            stability_threshold,
+            fps,
+            validate_trims,
+            labels,
         } = args;
 
-        if !summary && frame.is_none() {
+        if !summary && frame.is_none() && identical_runs.is_none() && !stability && !validate_trims
+        {
             bail!("No frame number to look up");
         }
 
@@ -60,9 +120,14 @@ impl RpuInfo {
 
         let info = RpuInfo { input };
 
-        println!("Parsing RPU file...");
+        ipc::log("Parsing RPU file...");
+
+        let rpus = parse_rpu_file_with_opts(&info.input, parse_opts)?;
 
-        let rpus = parse_rpu_file(info.input)?;
+        let labeled_shots = match labels {
+            Some(path) => read_annotations_file(path)?.1,
+            None => Vec::new(),
+        };
 
         if let Some(f) = frame {
             ensure!(
@@ -76,7 +141,19 @@ impl RpuInfo {
             let rpu = &rpus[f];
 
             if let Ok(rpu_serialized) = serde_json::to_string_pretty(&rpu) {
-                println!("{rpu_serialized}");
+                ipc::log(rpu_serialized);
+            }
+
+            if let Some(labels) = VideoShot::labels_at(&labeled_shots, f) {
+                ipc::log(format!("Shot labels: {labels}"));
+            }
+
+            for warning in rpu.parse_warnings() {
+                ipc::warn(format!("frame {}: {warning}", frame_label(f)));
+            }
+
+            if bits {
+                info.print_bit_fields(f)?;
             }
         }
 
@@ -106,6 +183,22 @@ impl RpuInfo {
                 )?;
             }
 
+            let ext_mapping_idcs: Vec<u8> = rpus
+                .iter()
+                .map(|rpu| rpu.header.ext_mapping_idc())
+                .filter(|&idc| idc != 0)
+                .unique()
+                .sorted()
+                .collect();
+
+            if !ext_mapping_idcs.is_empty() {
+                write!(
+                    summary_str,
+                    "\n  Extension mapping idc: {}",
+                    ext_mapping_idcs.iter().join(", ")
+                )?;
+            }
+
             write!(summary_str, "\n  Scene/shot count: {scene_count}")?;
             write!(summary_str, "\n  {rpu_mastering_meta_str}")?;
             write!(
@@ -113,6 +206,11 @@ impl RpuInfo {
                 "\n  RPU content light level (L1): MaxCLL: {:.2} nits, MaxFALL: {:.2} nits",
                 l1_stats.maxcll, l1_stats.maxfall
             )?;
+            write!(
+                summary_str,
+                "\n    MaxCLL {L1_STATS_PERCENTILE}th percentile: {:.2} nits, MaxFALL std-dev: {:.2} nits",
+                l1_stats.maxcll_percentile, l1_stats.maxfall_stddev
+            )?;
 
             if let Some(l6_meta) = l6_meta {
                 let mut final_str = String::from("L6 metadata");
@@ -129,20 +227,136 @@ impl RpuInfo {
                 write!(summary_str, "\n  L2 trims: {}", l2_trims.join(", "))?;
             }
 
-            println!("\n{summary_str}");
+            let long_play_check =
+                LongPlayModeCheck::new(rpus.iter().map(|rpu| rpu.vdr_dm_data.as_ref()));
+            write!(
+                summary_str,
+                "\n  Long play mode: {}, metadata changes on {:.1}% of the frames",
+                long_play_check.long_play_mode(),
+                long_play_check.churn() * 100.0
+            )?;
+
+            if let Some(warning) = long_play_check.warning() {
+                write!(summary_str, "\n  Warning: {warning}")?;
+            }
+
+            let parse_warnings: Vec<_> = rpus
+                .iter()
+                .enumerate()
+                .flat_map(|(frame, rpu)| {
+                    rpu.parse_warnings()
+                        .iter()
+                        .map(move |warning| (frame, warning))
+                })
+                .collect();
+
+            if !parse_warnings.is_empty() {
+                write!(summary_str, "\n  Parse warnings: {}", parse_warnings.len())?;
+
+                for (frame, warning) in parse_warnings.iter().take(MAX_LISTED_PARSE_WARNINGS) {
+                    write!(
+                        summary_str,
+                        "\n    Frame {}: {warning}",
+                        frame_label(*frame)
+                    )?;
+                }
+
+                if parse_warnings.len() > MAX_LISTED_PARSE_WARNINGS {
+                    write!(
+                        summary_str,
+                        "\n    ... and {} more",
+                        parse_warnings.len() - MAX_LISTED_PARSE_WARNINGS
+                    )?;
+                }
+            }
+
+            ipc::log(format!("\n{summary_str}"));
+        }
+
+        if let Some(min_length) = identical_runs {
+            let runs = identical_metadata_runs(
+                rpus.iter().map(|rpu| rpu.vdr_dm_data.as_ref()),
+                min_length,
+            );
+
+            let mut runs_str = format!("\nIdentical metadata runs: {}", runs.len());
+            for (start, length) in runs {
+                write!(
+                    runs_str,
+                    "\n  {}: {length} frames{}",
+                    range_label(start, start + length - 1),
+                    labels_suffix(&labeled_shots, start)
+                )?;
+            }
+
+            ipc::log(runs_str);
+        }
+
+        if stability {
+            ensure!(fps > 0.0, "info: invalid frame rate {fps}");
+
+            let report = StabilityReport::new(&rpus, fps, stability_threshold);
+            ipc::log(format!("\n{}", report.to_report_string()?));
+        }
+
+        if validate_trims {
+            let report = TrimValidationReport::new(&rpus);
+            ipc::log(format!("\n{}", report.to_report_string()?));
+        }
+
+        Ok(())
+    }
+
+    fn print_bit_fields(&self, frame: usize) -> Result<()> {
+        let Some(nalu) = read_rpu_nalu(&self.input, frame)? else {
+            bail!("info: frame {frame} not found in the RPU file");
+        };
+
+        let AnnotatedRpu { payload, fields } = AnnotatedRpu::from_unspec62_nalu(&nalu)?;
+
+        let mut dump_str = format!("\nRPU payload ({} bytes):", payload.len());
+
+        for (i, line) in payload.chunks(16).enumerate() {
+            write!(
+                dump_str,
+                "\n  {:08x}: {:02x}",
+                i * 16,
+                line.iter().format(" ")
+            )?;
+        }
+
+        write!(dump_str, "\n\nBit fields (offset, bits, name, value):")?;
+
+        for field in fields {
+            write!(
+                dump_str,
+                "\n  {:>6} {:>3} {}",
+                field.offset, field.bits, field.name
+            )?;
+
+            if let Some(value) = field.value {
+                write!(dump_str, " = {value}")?;
+            }
         }
 
+        ipc::log(dump_str);
+
         Ok(())
     }
 }
 
 impl RpusListSummary {
     pub fn new(rpus: &[DoviRpu]) -> Result<Self> {
-        let profiles = rpus
+        let rpu_profiles: Vec<RpuProfile> = rpus
             .iter()
-            .map(|rpu| rpu.dovi_profile)
+            .map(|rpu| rpu.profile())
             .unique()
             .sorted()
+            .collect();
+        let profiles = rpu_profiles
+            .iter()
+            .map(|profile| profile.number())
+            .unique()
             .join(", ");
 
         let dmv1_count = rpus
@@ -189,15 +403,18 @@ impl RpusListSummary {
         }
         write!(profiles_str, ": {profiles}")?;
 
-        if profiles.contains('7') {
+        let el_types: Vec<DoviELType> = rpu_profiles
+            .iter()
+            .filter_map(|profile| match profile {
+                RpuProfile::Profile7 { el_type } => *el_type,
+                _ => None,
+            })
+            .collect();
+
+        if !el_types.is_empty() {
             let idx = profiles_str.find('7').unwrap();
 
-            let subprofiles = rpus
-                .iter()
-                .filter_map(|rpu| rpu.el_type.as_ref().map(|e| e.to_string()))
-                .unique()
-                .sorted()
-                .join(", ");
+            let subprofiles = el_types.iter().map(|e| e.to_string()).sorted().join(", ");
 
             profiles_str.insert_str(idx + 1, &format!(" ({subprofiles})"));
         }
@@ -302,14 +519,21 @@ impl RpusListSummary {
             .max_by(|a, b| a.partial_cmp(b).unwrap())
             .unwrap();
 
+        let mut max_pq_values: Vec<f64> = l1_data.iter().map(|e| e.1).collect();
+        let avg_nits_values: Vec<f64> = l1_data.iter().map(|e| pq_to_nits(e.2)).collect();
+
         let l1_stats = SummaryL1Stats {
             maxcll: pq_to_nits(max_pq_value),
             maxcll_avg: pq_to_nits(max_pq_mean_value),
+            maxcll_percentile: pq_to_nits(percentile(&mut max_pq_values, L1_STATS_PERCENTILE)),
             maxfall: pq_to_nits(max_avg_pq_value),
             maxfall_avg: pq_to_nits(avg_pq_mean_value),
+            maxfall_stddev: std_dev(&avg_nits_values),
             max_min_nits: pq_to_nits(min_pq_max_value),
         };
 
+        let shot_stats = ShotL1Stats::from_l1_data(rpus, &l1_data);
+
         let l2_trims: Vec<String> = rpus
             .iter()
             .filter_map(|rpu| {
@@ -344,6 +568,104 @@ impl RpusListSummary {
             l1_data,
             l1_stats,
             l2_trims,
+            shot_stats,
         })
     }
 }
+
+impl ShotL1Stats {
+    /// Splits the L1 data in shots according to the scene cut flags.
+    /// The first frame always starts a shot.
+    pub fn from_l1_data(rpus: &[DoviRpu], l1_data: &[(f64, f64, f64)]) -> Vec<Self> {
+        let mut shot_starts: Vec<usize> = rpus
+            .iter()
+            .enumerate()
+            .filter(|(_, rpu)| {
+                rpu.vdr_dm_data
+                    .as_ref()
+                    .map_or(false, |vdr| vdr.scene_refresh_flag == 1)
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        if shot_starts.first() != Some(&0) {
+            shot_starts.insert(0, 0);
+        }
+
+        shot_starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = shot_starts.get(i + 1).copied().unwrap_or(l1_data.len());
+                Self::from_frames(start, &l1_data[start..end])
+            })
+            .collect()
+    }
+
+    /// Adds the text of the annotations overlapping the shot
+    pub fn set_annotations(&mut self, annotations: &[Annotation]) {
+        self.annotations = annotations
+            .iter()
+            .filter(|a| a.overlaps(self.start, self.duration))
+            .map(|a| a.text.clone())
+            .collect();
+    }
+
+    /// Sets the labels of the config shot containing the shot start
+    pub fn set_labels(&mut self, shots: &[VideoShot]) {
+        self.labels = VideoShot::labels_at(shots, self.start).cloned();
+    }
+
+    /// Sets the `HH:MM:SS:FF` timecodes of the shot start and duration
+    pub fn set_timecodes(&mut self, fps: f64) {
+        self.start_timecode = Some(frames_to_timecode(self.start, fps));
+        self.duration_timecode = Some(frames_to_timecode(self.duration, fps));
+    }
+
+    fn from_frames(start: usize, frames: &[(f64, f64, f64)]) -> Self {
+        let mut max_pq_values: Vec<f64> = frames.iter().map(|e| e.1).collect();
+        let avg_nits_values: Vec<f64> = frames.iter().map(|e| pq_to_nits(e.2)).collect();
+
+        let max_pq = max_pq_values.iter().copied().fold(0.0, f64::max);
+        let avg_nits = avg_nits_values.iter().sum::<f64>() / avg_nits_values.len() as f64;
+
+        Self {
+            start,
+            duration: frames.len(),
+            start_timecode: None,
+            duration_timecode: None,
+            max_nits: pq_to_nits(max_pq),
+            max_nits_percentile: pq_to_nits(percentile(&mut max_pq_values, L1_STATS_PERCENTILE)),
+            avg_nits,
+            avg_nits_stddev: std_dev(&avg_nits_values),
+            annotations: Vec::new(),
+            labels: None,
+        }
+    }
+}
+
+/// Nearest-rank percentile, `p` in the 0-100 range.
+/// Sorts the values in place.
+pub fn percentile(values: &mut [f64], p: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let rank = ((p / 100.0) * values.len() as f64).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+/// Population standard deviation
+pub fn std_dev(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count;
+
+    variance.sqrt()
+}
@@ -1,8 +1,8 @@
-use std::fs::File;
-use std::io::{stdout, BufReader, BufWriter, Write};
+use std::fs::{self, File};
+use std::io::{self, stdout, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 use indicatif::ProgressBar;
 
 use hevc_parser::io::{processor, FrameBuffer, IoProcessor, NalBuffer};
@@ -10,22 +10,49 @@ use hevc_parser::HevcParser;
 use hevc_parser::{hevc::*, NALUStartCode};
 use processor::{HevcProcessor, HevcProcessorOpts};
 
-use dolby_vision::rpu::utils::parse_rpu_file;
+use dolby_vision::rpu::generate::GenerateConfig;
+use dolby_vision::rpu::nalu_reader::{HevcNalu, NaluReader};
 
 use crate::commands::InjectRpuArgs;
-
-use super::hdr10plus_utils::prefix_sei_removed_hdr10plus_nalu;
-use super::{input_from_either, CliOptions, DoviRpu, IoFormat};
+use crate::ipc;
+
+use super::checkpoint::{cli_settings, Checkpointer};
+use super::hdr10plus_utils::{
+    hdr10plus_sei_to_suffix, prefix_sei_removed_hdr10plus_nalu, Hdr10PlusSuffixSei,
+};
+use super::hevc_sps::sps_luma_bit_depth;
+use super::rpu_store::RpuStore;
+use super::sei_static_metadata::SeiStaticMetadata;
+use super::telemetry::ConversionTelemetry;
+use super::{input_from_either, CliOptions, IoFormat, RpuStartCode, WriteStartCodePreset};
+
+/// Handling of the HDR10+ SEI present in the input, relative to the injected RPU
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hdr10PlusSei {
+    /// Leave the HDR10+ SEI untouched
+    Keep,
+    /// Remove the HDR10+ SEI messages
+    Strip,
+    /// Move the HDR10+ SEI messages to suffix SEI NALUs right before the RPU
+    First,
+    /// Move the HDR10+ SEI messages to suffix SEI NALUs right after the RPU
+    Last,
+}
 
 pub struct RpuInjector {
     input: PathBuf,
-    rpu_in: PathBuf,
+    output: PathBuf,
     no_add_aud: bool,
+    mv_hevc: bool,
+    // Written with the `--start-code` preset when unset
+    rpu_start_code: Option<RpuStartCode>,
+    hdr10plus: Hdr10PlusSei,
     options: CliOptions,
 
-    rpus: Vec<DoviRpu>,
+    rpus: RpuStore,
 
-    writer: BufWriter<File>,
+    writer: Box<dyn Write>,
+    checkpointer: Option<Checkpointer>,
     progress_bar: ProgressBar,
     already_checked_for_rpu: bool,
 
@@ -33,20 +60,85 @@ pub struct RpuInjector {
     nals: Vec<NALUnit>,
     mismatched_length: bool,
 
+    truncate_rpu: bool,
+    align_end: bool,
+
     frame_buffer: FrameBuffer,
+    // Leading NALUs seen after the last slice of the buffered frame,
+    // which belong to the next access unit if a new picture follows
+    pending_nals: Vec<NalBuffer>,
+    // Input offset of the first pending NALU, for the checkpoints
+    pending_offset: u64,
     last_metadata_written: Option<NalBuffer>,
+
+    // ST 2086 SEI values to set as L6 metadata, read in the first pass
+    sei_static_metadata: SeiStaticMetadata,
+
+    // Luma bit depth of the first SPS, checked against the RPU BL bit depth
+    sps_bit_depth: Option<u8>,
+
+    // Base layer AUDs and pictures counted in the first pass, checked against the frame order info
+    access_units: usize,
+    pictures: usize,
+
+    telemetry: ConversionTelemetry,
+}
+
+/// Reads the NALUs of a stream that the injection must write unchanged, in order
+struct PassthroughNalus<R: Read> {
+    reader: NaluReader<R>,
+    // Read ahead, for the trailing zero bytes
+    next: Option<HevcNalu>,
+    stream_len: u64,
+
+    // Replaced AUDs and rewritten HDR10+ SEI
+    skip_auds: bool,
+    skip_sei: bool,
+}
+
+/// NALU data, with the zero bytes following it in the stream
+struct PassthroughNalu {
+    nal_type: u8,
+    offset: u64,
+    data: Vec<u8>,
+    trailing_zeroes: u64,
 }
 
 impl RpuInjector {
     pub fn from_args(args: InjectRpuArgs, cli_options: CliOptions) -> Result<RpuInjector> {
+        ipc::log("Reading RPU file...");
+        stdout().flush().ok();
+
+        // The RPUs are parsed by a reader thread, while the video is processed
+        let rpus = RpuStore::from_file(&args.rpu_in, cli_options.lenient, cli_options.parse_opts)?;
+
+        RpuInjector::with_rpus(args, cli_options, rpus)
+    }
+
+    /// Injector of already opened RPUs, `rpu_in` is ignored
+    fn with_rpus(
+        args: InjectRpuArgs,
+        mut cli_options: CliOptions,
+        rpus: RpuStore,
+    ) -> Result<RpuInjector> {
         let InjectRpuArgs {
             input,
             input_pos,
             rpu_in,
             output,
             no_add_aud,
+            mv_hevc,
+            rpu_start_code,
+            hdr10plus,
+            l6_from_sei,
+            truncate_rpu,
+            align_end,
+            resume,
+            checkpoint_interval,
         } = args;
 
+        cli_options.l6_from_sei = l6_from_sei;
+
         let input = input_from_either("inject-rpu", input, input_pos)?;
 
         let output = match output {
@@ -57,17 +149,48 @@ impl RpuInjector {
         let chunk_size = 100_000;
         let progress_bar = super::initialize_progress_bar(&IoFormat::Raw, &input)?;
 
-        let writer =
-            BufWriter::with_capacity(chunk_size, File::create(output).expect("Can't create file"));
+        let (writer, checkpointer): (Box<dyn Write>, _) = if cli_options.dry_run {
+            ipc::log(format!("Dry run, {} will not be written", output.display()));
+            (Box::new(io::sink()), None)
+        } else if resume || checkpoint_interval.is_some() {
+            let settings = cli_settings(
+                &cli_options,
+                &format!(
+                    "{no_add_aud} {mv_hevc} {rpu_start_code:?} {hdr10plus:?} {truncate_rpu} {align_end}"
+                ),
+            )?;
+
+            let (checkpointer, mut writers) = Checkpointer::new(
+                "inject-rpu",
+                &settings,
+                &[input.as_path(), rpu_in.as_path()],
+                &[output.as_path()],
+                checkpoint_interval,
+                resume,
+            )?;
+
+            (Box::new(writers.remove(0)), Some(checkpointer))
+        } else {
+            let writer = BufWriter::with_capacity(
+                chunk_size,
+                File::create(&output).expect("Can't create file"),
+            );
+
+            (Box::new(writer), None)
+        };
 
-        let mut injector = RpuInjector {
+        let injector = RpuInjector {
             input,
-            rpu_in,
+            output,
             no_add_aud,
+            mv_hevc,
+            rpu_start_code,
+            hdr10plus,
             options: cli_options,
-            rpus: Vec::new(),
+            rpus,
 
             writer,
+            checkpointer,
             progress_bar,
             already_checked_for_rpu: false,
 
@@ -75,18 +198,25 @@ impl RpuInjector {
             nals: Vec::new(),
             mismatched_length: false,
 
+            truncate_rpu,
+            align_end,
+
             frame_buffer: FrameBuffer {
                 frame_number: 0,
                 nals: Vec::with_capacity(16),
             },
+            pending_nals: Vec::new(),
+            pending_offset: 0,
             last_metadata_written: None,
-        };
 
-        println!("Parsing RPU file...");
-        stdout().flush().ok();
+            sei_static_metadata: SeiStaticMetadata::default(),
+            sps_bit_depth: None,
 
-        // Assumes parsing returns on error
-        injector.rpus = parse_rpu_file(&injector.rpu_in)?;
+            access_units: 0,
+            pictures: 0,
+
+            telemetry: ConversionTelemetry::new("inject-rpu"),
+        };
 
         Ok(injector)
     }
@@ -98,15 +228,50 @@ impl RpuInjector {
         if let IoFormat::Raw = format {
             let mut injector = RpuInjector::from_args(args, cli_options)?;
 
-            injector.process_input()?;
-            injector.interleave_rpu_nals()
+            injector.inject_or_discard()
         } else {
             bail!("RpuInjector: Must be a raw HEVC bitstream file")
         }
     }
 
+    /// Injects the RPUs of `config` into `input`, generated while the video is rewritten
+    pub fn inject_generated(
+        config: GenerateConfig,
+        input: PathBuf,
+        output: PathBuf,
+        cli_options: CliOptions,
+    ) -> Result<()> {
+        let format = hevc_parser::io::format_from_path(&input)?;
+        ensure!(
+            matches!(format, IoFormat::Raw),
+            "RpuInjector: Must be a raw HEVC bitstream file"
+        );
+
+        let args = InjectRpuArgs {
+            input: Some(input),
+            input_pos: None,
+            rpu_in: PathBuf::new(),
+            output: Some(output),
+            no_add_aud: false,
+            mv_hevc: false,
+            // The global `--start-code` and `--drop-hdr10plus` presets apply
+            rpu_start_code: None,
+            hdr10plus: Hdr10PlusSei::Keep,
+            l6_from_sei: false,
+            truncate_rpu: false,
+            align_end: false,
+            resume: false,
+            checkpoint_interval: None,
+        };
+
+        let rpus = RpuStore::from_generate_config(config)?;
+        let mut injector = RpuInjector::with_rpus(args, cli_options, rpus)?;
+
+        injector.inject_or_discard()
+    }
+
     fn process_input(&mut self) -> Result<()> {
-        println!("Processing input video for frame order info...");
+        ipc::log("Processing input video for frame order info...");
         stdout().flush().ok();
 
         let chunk_size = 100_000;
@@ -120,66 +285,173 @@ impl RpuInjector {
         processor.process_io(&mut reader, self)
     }
 
+    /// Removes the partial output when the injection fails, unless it can be resumed
+    fn inject_or_discard(&mut self) -> Result<()> {
+        let res = self
+            .process_input()
+            .and_then(|_| self.interleave_rpu_nals());
+
+        if res.is_err() && self.checkpointer.is_none() && !self.options.dry_run {
+            // Closes the file first
+            self.writer = Box::new(io::sink());
+            fs::remove_file(&self.output).ok();
+        }
+
+        res
+    }
+
     fn interleave_rpu_nals(&mut self) -> Result<()> {
+        if self.rpus.len() > self.frames.len() {
+            self.truncate_rpus()?;
+        }
+
         let rpus = &self.rpus;
 
-        self.mismatched_length = if self.frames.len() != rpus.len() {
-            println!(
-                "\nWarning: mismatched lengths. video {}, RPU {}",
+        // Longer RPUs were truncated above
+        self.mismatched_length = if rpus.len() < self.frames.len() {
+            ipc::log("");
+            self.telemetry.warning(format!(
+                "mismatched lengths. video {}, RPU {}",
                 self.frames.len(),
                 rpus.len()
-            );
+            ));
 
-            if rpus.len() < self.frames.len() {
-                println!("Metadata will be duplicated at the end to match video length\n");
-            } else {
-                println!("Metadata will be skipped at the end to match video length\n");
-            }
+            ipc::log("Metadata will be duplicated at the end to match video length\n");
 
             true
         } else {
             false
         };
 
-        println!("Rewriting file with interleaved RPU NALs..");
+        ipc::log("Rewriting file with interleaved RPU NALs..");
         stdout().flush().ok();
 
         self.progress_bar = super::initialize_progress_bar(&IoFormat::Raw, &self.input)?;
 
         let chunk_size = 100_000;
 
-        let mut processor =
-            HevcProcessor::new(IoFormat::Raw, HevcProcessorOpts::default(), chunk_size);
+        // The frames are delimited from the slice headers, the parser state isn't needed
+        // and the input can be read from any access unit when resuming
+        let processor_opts = HevcProcessorOpts {
+            parse_nals: false,
+            ..Default::default()
+        };
+        let mut processor = HevcProcessor::new(IoFormat::Raw, processor_opts, chunk_size);
 
-        let file = File::open(&self.input)?;
-        let mut reader = Box::new(BufReader::with_capacity(chunk_size, file));
+        let mut file = File::open(&self.input)?;
+
+        let resumed = self.checkpointer.as_ref().and_then(|checkpointer| {
+            checkpointer
+                .resumed_input_offset()
+                .map(|offset| (offset, checkpointer.frames_done()))
+        });
+
+        if let Some((offset, frames_done)) = resumed {
+            file.seek(SeekFrom::Start(offset))?;
+            self.skip_written_frames(frames_done)?;
+        }
+
+        let file_reader = BufReader::with_capacity(chunk_size, file);
+
+        let mut reader: Box<dyn BufRead> = match &self.checkpointer {
+            Some(checkpointer) => Box::new(checkpointer.input_reader(file_reader)),
+            None => Box::new(file_reader),
+        };
 
         processor.process_io(&mut reader, self)
     }
 
+    /// Drops the RPUs of the frames written before the resumed checkpoint
+    fn skip_written_frames(&mut self, frames_done: usize) -> Result<()> {
+        for decoded_number in 0..frames_done as u64 {
+            let frame = self
+                .frames
+                .iter()
+                .find(|f| f.decoded_number == decoded_number);
+
+            if let Some(frame) = frame {
+                if let Some(stored) = self.rpus.take(frame.presentation_number as usize)? {
+                    self.last_metadata_written = Some(NalBuffer {
+                        nal_type: NAL_UNSPEC62,
+                        start_code: NALUStartCode::Length4,
+                        data: stored.data,
+                    });
+                }
+            }
+        }
+
+        self.frame_buffer.frame_number = frames_done as u64;
+
+        Ok(())
+    }
+
+    /// Drops the RPUs past the video length, at the end or at the start with `align_end`
+    fn truncate_rpus(&mut self) -> Result<()> {
+        let (rpu_count, frame_count) = (self.rpus.len(), self.frames.len());
+        let excess = rpu_count - frame_count;
+
+        ipc::log("");
+
+        if self.align_end {
+            self.rpus.drop_head(excess)?;
+
+            self.telemetry.warning(format!(
+                "truncating RPU to the video length, dropped the first {excess} RPUs (0-{}). Video frame 0 uses RPU {excess}",
+                excess - 1
+            ));
+        } else {
+            self.telemetry.warning(format!(
+                "truncating RPU to the video length, dropped the last {excess} RPUs ({frame_count}-{})",
+                rpu_count - 1
+            ));
+        }
+
+        ipc::log("");
+
+        Ok(())
+    }
+
     fn get_rpu_and_index_to_insert(
         frames: &[Frame],
-        rpus: &[DoviRpu],
+        rpus: &mut RpuStore,
         frame_buffer: &FrameBuffer,
         mismatched_length: bool,
         last_metadata: &Option<NalBuffer>,
-    ) -> Result<(usize, NalBuffer)> {
+        sei_static_metadata: Option<&SeiStaticMetadata>,
+        telemetry: &mut ConversionTelemetry,
+    ) -> Result<(usize, NalBuffer, Option<u64>)> {
         let existing_frame = frames
             .iter()
             .find(|f| f.decoded_number == frame_buffer.frame_number);
 
+        // BL bit depth signaled by the RPU, when it isn't a repeated one
+        let mut bl_bit_depth = None;
+
         // If we have a RPU buffered frame, write it
         // Otherwise, write the same data as previous
         let rpu_nb = if let Some(frame) = existing_frame {
-            if let Some(dovi_rpu) = rpus.get(frame.presentation_number as usize) {
-                let rpu_data = dovi_rpu.write_hevc_unspec62_nalu()?;
+            if let Some(mut stored) = rpus.take(frame.presentation_number as usize)? {
+                if let Some(sei_static_metadata) = sei_static_metadata {
+                    if sei_static_metadata.apply_to_rpu(&mut stored.rpu)? {
+                        stored.data = stored.rpu.write_hevc_unspec62_nalu()?;
+                    }
+                }
+
+                if let Some(error) = &stored.error {
+                    telemetry.record_corrupt(frame.presentation_number, error);
+                } else {
+                    telemetry.record_passthrough(&stored.rpu);
+                }
+
+                bl_bit_depth = Some(stored.rpu.header.bl_bit_depth_minus8 + 8);
 
                 Some(NalBuffer {
                     nal_type: NAL_UNSPEC62,
                     start_code: NALUStartCode::Length4,
-                    data: rpu_data,
+                    data: stored.data,
                 })
             } else if mismatched_length {
+                telemetry.record_frame();
                 last_metadata.clone()
             } else {
                 bail!(
@@ -188,6 +460,7 @@ impl RpuInjector {
                 );
             }
         } else if mismatched_length {
+            telemetry.record_frame();
             last_metadata.clone()
         } else {
             None
@@ -202,7 +475,7 @@ impl RpuInjector {
 
             if let Some(idx) = insert_index {
                 // + 1 since we want the RPU after
-                Ok((idx + 1, rpu_nb))
+                Ok((idx + 1, rpu_nb, bl_bit_depth))
             } else {
                 bail!(
                     "No slice or UNSPEC63 NALUs in decoded frame {}. Cannot insert RPU.",
@@ -216,6 +489,427 @@ impl RpuInjector {
             );
         }
     }
+
+    /// Adds the AUD and RPU to the buffered access unit, then writes it.
+    fn write_buffered_frame(&mut self) -> Result<()> {
+        let hdr10plus_nals = if matches!(self.hdr10plus, Hdr10PlusSei::First | Hdr10PlusSei::Last) {
+            Self::take_hdr10plus_nals(&mut self.frame_buffer)?
+        } else {
+            Vec::new()
+        };
+
+        if !self.no_add_aud {
+            let Some(buffered_frame) = self
+                .frames
+                .iter()
+                .find(|f| f.decoded_number == self.frame_buffer.frame_number)
+            else {
+                bail!(
+                    "No frame info for decoded frame {}. Cannot insert AUD.",
+                    self.frame_buffer.frame_number
+                );
+            };
+
+            self.frame_buffer.nals.insert(
+                0,
+                NalBuffer {
+                    nal_type: NAL_AUD,
+                    start_code: NALUStartCode::Length4,
+                    data: hevc_parser::utils::aud_for_frame(buffered_frame, None)?,
+                },
+            );
+        }
+
+        let (idx, rpu_nb, bl_bit_depth) = Self::get_rpu_and_index_to_insert(
+            &self.frames,
+            &mut self.rpus,
+            &self.frame_buffer,
+            self.mismatched_length,
+            &self.last_metadata_written,
+            self.options
+                .l6_from_sei
+                .then_some(&self.sei_static_metadata),
+            &mut self.telemetry,
+        )?;
+
+        if self.frame_buffer.frame_number == 0 {
+            if let Some(bl_bit_depth) = bl_bit_depth {
+                self.check_bl_bit_depth(bl_bit_depth);
+            }
+        }
+
+        self.last_metadata_written = Some(rpu_nb.clone());
+        Self::insert_rpu_nals(&mut self.frame_buffer, idx, rpu_nb, self.mv_hevc);
+
+        if !hdr10plus_nals.is_empty() {
+            Self::insert_hdr10plus_nals(&mut self.frame_buffer, hdr10plus_nals, self.hdr10plus);
+        }
+
+        Self::write_frame_nals(
+            &mut self.writer,
+            &self.frame_buffer,
+            self.options.start_code,
+            self.rpu_start_code,
+        )?;
+
+        self.frame_buffer.nals.clear();
+
+        Ok(())
+    }
+
+    /// Takes the HDR10+ SEI messages out of the prefix SEI NALUs, as suffix SEI NALUs
+    fn take_hdr10plus_nals(frame_buffer: &mut FrameBuffer) -> Result<Vec<NalBuffer>> {
+        let mut hdr10plus_nals = Vec::new();
+        let mut i = 0;
+
+        while i < frame_buffer.nals.len() {
+            let nal_buf = &mut frame_buffer.nals[i];
+
+            let split = if nal_buf.nal_type == NAL_SEI_PREFIX {
+                hdr10plus_sei_to_suffix(&nal_buf.data)?
+            } else {
+                None
+            };
+
+            if let Some(Hdr10PlusSuffixSei { suffix, remaining }) = split {
+                hdr10plus_nals.push(NalBuffer {
+                    nal_type: NAL_SEI_SUFFIX,
+                    start_code: nal_buf.start_code,
+                    data: suffix,
+                });
+
+                if let Some(remaining) = remaining {
+                    nal_buf.data = remaining;
+                } else {
+                    frame_buffer.nals.remove(i);
+                    continue;
+                }
+            }
+
+            i += 1;
+        }
+
+        Ok(hdr10plus_nals)
+    }
+
+    /// Inserts the HDR10+ suffix SEI NALUs next to the base layer RPU
+    fn insert_hdr10plus_nals(
+        frame_buffer: &mut FrameBuffer,
+        hdr10plus_nals: Vec<NalBuffer>,
+        position: Hdr10PlusSei,
+    ) {
+        let rpu_index = frame_buffer
+            .nals
+            .iter()
+            .rposition(|nb| nb.nal_type == NAL_UNSPEC62 && nal_layer_id(&nb.data) == 0);
+
+        if let Some(rpu_index) = rpu_index {
+            let insert_index = if position == Hdr10PlusSei::First {
+                rpu_index
+            } else {
+                rpu_index + 1
+            };
+
+            frame_buffer
+                .nals
+                .splice(insert_index..insert_index, hdr10plus_nals);
+        }
+    }
+
+    fn write_frame_nals(
+        writer: &mut Box<dyn Write>,
+        frame_buffer: &FrameBuffer,
+        preset: WriteStartCodePreset,
+        rpu_start_code: Option<RpuStartCode>,
+    ) -> Result<()> {
+        let mut previous_zero_ended = false;
+
+        for (i, nal_buf) in frame_buffer.nals.iter().enumerate() {
+            let first_nal = i == 0;
+
+            // A 3 bytes start code following a zero byte would be read as a 4 bytes one,
+            // dropping the trailing zero byte of the previous NALU
+            let (preset, rpu_start_code) = if previous_zero_ended {
+                (
+                    WriteStartCodePreset::Four,
+                    rpu_start_code.map(|_| RpuStartCode::Four),
+                )
+            } else {
+                (preset, rpu_start_code)
+            };
+
+            if let Some(rpu_start_code) =
+                rpu_start_code.filter(|_| nal_buf.nal_type == NAL_UNSPEC62 && !first_nal)
+            {
+                writer.write_all(rpu_start_code.bytes())?;
+                writer.write_all(&nal_buf.data)?;
+            } else {
+                NALUnit::write_with_preset(
+                    writer,
+                    &nal_buf.data,
+                    preset.into(),
+                    nal_buf.nal_type,
+                    first_nal,
+                )?;
+            }
+
+            previous_zero_ended = nal_buf.data.last() == Some(&0);
+        }
+
+        Ok(())
+    }
+
+    /// Reads back the output, checking that every input NALU other than the RPUs
+    /// was written byte-identical and in order, with its trailing zero bytes.
+    ///
+    /// The AUDs are skipped when replaced, and the SEI when the HDR10+ SEI are rewritten.
+    fn verify_passthrough(&self) -> Result<usize> {
+        let skip_auds = !self.no_add_aud;
+        let skip_sei = self.options.drop_hdr10plus || self.hdr10plus != Hdr10PlusSei::Keep;
+
+        let mut input = PassthroughNalus::open(&self.input, skip_auds, skip_sei)?;
+        let mut output = PassthroughNalus::open(&self.output, skip_auds, skip_sei)?;
+
+        let mut count = 0;
+
+        loop {
+            match (input.next_nalu()?, output.next_nalu()?) {
+                (None, None) => return Ok(count),
+                (Some(expected), Some(written))
+                    if expected.data == written.data
+                        && expected.trailing_zeroes == written.trailing_zeroes =>
+                {
+                    count += 1;
+                }
+                (Some(expected), written) => {
+                    let written = written.map_or(String::from("the end of the output"), |nalu| {
+                        format!(
+                            "type {} NALU at output offset {}",
+                            nalu.nal_type, nalu.offset
+                        )
+                    });
+
+                    bail!(
+                        "The written NALUs differ from the input: type {} NALU at input offset {} was not written unchanged, found {written}",
+                        expected.nal_type,
+                        expected.offset
+                    );
+                }
+                (None, Some(written)) => bail!(
+                    "The written NALUs differ from the input: unexpected type {} NALU at output offset {}",
+                    written.nal_type,
+                    written.offset
+                ),
+            }
+        }
+    }
+
+    /// Cross-checks the access unit, picture and RPU counts before writing anything.
+    ///
+    /// The RPUs are assigned from the frame order info, so any disagreement with the
+    /// access units actually written shifts the metadata from the pictures.
+    /// Longer RPU files are only accepted with `--truncate-rpu`.
+    /// With `--lenient`, longer RPU files are truncated at the end and shorter ones
+    /// are extended by duplicating the last RPU.
+    fn check_av_sync(&mut self) -> Result<()> {
+        let (frames, pictures, rpus) = (self.frames.len(), self.pictures, self.rpus.len());
+
+        // Streams without AUDs are only delimited by the slices
+        let aud_mismatch = self.access_units > 0 && self.access_units != pictures;
+        let frame_mismatch = aud_mismatch || pictures != frames;
+        let rpu_mismatch = rpus < pictures || (rpus > pictures && !self.truncate_rpu);
+
+        if !frame_mismatch && !rpu_mismatch {
+            return Ok(());
+        }
+
+        let breakdown = format!(
+            "access units (AUD): {}, pictures (slice headers): {pictures}, frames (order info): {frames}, RPUs: {rpus}",
+            self.access_units,
+        );
+
+        if !frame_mismatch {
+            // The length mismatch is reported when writing
+            if self.options.lenient {
+                return Ok(());
+            }
+
+            if rpus > pictures {
+                bail!(
+                    "The RPU file has {} more frames than the video (RPU {rpus}, video {pictures}).\n  {breakdown}\n\
+                     Use --truncate-rpu to drop the excess metadata, at the end or at the start with --align-end",
+                    rpus - pictures
+                );
+            } else {
+                bail!(
+                    "The RPU file has {} fewer frames than the video (RPU {rpus}, video {pictures}).\n  {breakdown}\n\
+                     Use --lenient to inject anyway, duplicating the last RPU",
+                    pictures - rpus
+                );
+            }
+        }
+
+        if self.options.lenient {
+            ipc::log("");
+            self.telemetry.warning(format!(
+                "frame count mismatch, the RPUs may be out of sync with the video. {breakdown}"
+            ));
+
+            Ok(())
+        } else {
+            bail!(
+                "Frame count mismatch, the RPUs would be out of sync with the video.\n  {breakdown}\n\
+                 Use --lenient to inject anyway"
+            )
+        }
+    }
+
+    /// Warns when the BL bit depth signaled by the RPU differs from the video SPS
+    fn check_bl_bit_depth(&mut self, bl_bit_depth: u64) {
+        let Some(sps_bit_depth) = self.sps_bit_depth else {
+            return;
+        };
+
+        if bl_bit_depth != sps_bit_depth as u64 {
+            self.telemetry.warning(format!(
+                "the RPU signals a {bl_bit_depth}-bit BL, but the video is {sps_bit_depth}-bit. The BL bit depth can be rewritten with the editor `bl_bit_depth`"
+            ));
+        }
+    }
+
+    /// Inserts the RPU in the frame NALUs.
+    ///
+    /// For MV-HEVC, the RPU is inserted after the last NALU of each view,
+    /// with the second view RPU using `nuh_layer_id` 1.
+    pub fn insert_rpu_nals(
+        frame_buffer: &mut FrameBuffer,
+        idx: usize,
+        rpu_nb: NalBuffer,
+        mv_hevc: bool,
+    ) {
+        if !mv_hevc {
+            frame_buffer.nals.insert(idx, rpu_nb);
+            return;
+        }
+
+        let layer_insert_index = |layer_id: u8| {
+            frame_buffer
+                .nals
+                .iter()
+                .rposition(|nb| {
+                    !matches!(nb.nal_type, NAL_EOS_NUT | NAL_EOB_NUT)
+                        && nal_layer_id(&nb.data) == layer_id
+                })
+                .map(|i| i + 1)
+        };
+
+        let base_idx = layer_insert_index(0).unwrap_or(idx);
+        let view_idx = layer_insert_index(1);
+
+        if let Some(view_idx) = view_idx {
+            let mut view_rpu_nb = rpu_nb.clone();
+            set_nal_layer_id(&mut view_rpu_nb.data, 1);
+
+            // Insert at the later index first to keep the other valid
+            if view_idx >= base_idx {
+                frame_buffer.nals.insert(view_idx, view_rpu_nb);
+                frame_buffer.nals.insert(base_idx, rpu_nb);
+            } else {
+                frame_buffer.nals.insert(base_idx, rpu_nb);
+                frame_buffer.nals.insert(view_idx, view_rpu_nb);
+            }
+        } else {
+            frame_buffer.nals.insert(base_idx, rpu_nb);
+        }
+    }
+}
+
+impl PassthroughNalus<File> {
+    fn open(path: &PathBuf, skip_auds: bool, skip_sei: bool) -> Result<Self> {
+        let file = File::open(path)?;
+        let stream_len = file.metadata()?.len();
+
+        Ok(Self {
+            reader: NaluReader::new(file),
+            next: None,
+            stream_len,
+            skip_auds,
+            skip_sei,
+        })
+    }
+}
+
+impl<R: Read> PassthroughNalus<R> {
+    fn next_nalu(&mut self) -> Result<Option<PassthroughNalu>> {
+        loop {
+            let nalu = match self.next.take() {
+                Some(nalu) => nalu,
+                None => match self.reader.next_nalu()? {
+                    Some(nalu) => nalu,
+                    None => return Ok(None),
+                },
+            };
+
+            self.next = self.reader.next_nalu()?;
+
+            let skipped = match nalu.nal_type {
+                NAL_UNSPEC62 => true,
+                NAL_AUD => self.skip_auds,
+                NAL_SEI_PREFIX | NAL_SEI_SUFFIX => self.skip_sei,
+                _ => false,
+            };
+
+            if skipped {
+                continue;
+            }
+
+            let end = nalu.offset + nalu.data.len() as u64;
+            let next_start = self
+                .next
+                .as_ref()
+                .map_or(self.stream_len, |next| next.start_code_offset);
+
+            return Ok(Some(PassthroughNalu {
+                nal_type: nalu.nal_type,
+                offset: nalu.offset,
+                data: nalu.data,
+                trailing_zeroes: next_start - end,
+            }));
+        }
+    }
+}
+
+fn is_vcl_nal(nal_type: u8) -> bool {
+    nal_type < 32
+}
+
+/// NALUs that can precede the first slice of an access unit (7.4.2.4.4)
+fn is_au_leading_nal(nal_type: u8) -> bool {
+    matches!(
+        nal_type,
+        NAL_AUD | NAL_VPS | NAL_SPS | NAL_PPS | NAL_SEI_PREFIX | 41..=44 | 48..=55
+    )
+}
+
+/// Slice with `first_slice_segment_in_pic_flag` set, on the base layer.
+///
+/// Does not rely on AUDs, which some encoders omit.
+fn is_first_slice_of_au(nal_type: u8, data: &[u8]) -> bool {
+    is_vcl_nal(nal_type) && nal_layer_id(data) == 0 && data.get(2).map_or(false, |b| b & 0x80 != 0)
+}
+
+fn nal_layer_id(data: &[u8]) -> u8 {
+    match data {
+        [first, second, ..] => ((first & 0x01) << 5) | (second >> 3),
+        _ => 0,
+    }
+}
+
+fn set_nal_layer_id(data: &mut [u8], layer_id: u8) {
+    if data.len() >= 2 {
+        data[0] = (data[0] & 0xFE) | (layer_id >> 5);
+        data[1] = ((layer_id & 0x1F) << 3) | (data[1] & 0x07);
+    }
 }
 
 impl IoProcessor for RpuInjector {
@@ -234,13 +928,20 @@ impl IoProcessor for RpuInjector {
     fn process_nals(&mut self, _parser: &HevcParser, nals: &[NALUnit], chunk: &[u8]) -> Result<()> {
         // Second pass
         if !self.frames.is_empty() && !self.nals.is_empty() {
-            let rpus = &self.rpus;
+            let chunk_offset = self.checkpointer.as_ref().map_or(0, |checkpointer| {
+                checkpointer.input_position() - chunk.len() as u64
+            });
 
             for nal in nals {
+                let nal_offset = chunk_offset + (nal.start - nal.start_code.size()) as u64;
+
                 let mut nalu_data_override = None;
 
                 // Ignore HDR10+
-                if self.options.drop_hdr10plus && nal.nal_type == NAL_SEI_PREFIX {
+                let drop_hdr10plus =
+                    self.options.drop_hdr10plus || self.hdr10plus == Hdr10PlusSei::Strip;
+
+                if drop_hdr10plus && nal.nal_type == NAL_SEI_PREFIX {
                     let (has_st2094_40, data) = prefix_sei_removed_hdr10plus_nalu(chunk, nal)?;
 
                     // Drop NALUs containing only one SEI message
@@ -251,85 +952,99 @@ impl IoProcessor for RpuInjector {
                     }
                 }
 
-                if self.frame_buffer.frame_number != nal.decoded_frame_index {
-                    // On new frame, write AUD
-                    if !self.no_add_aud {
-                        // Skip existing AUDs
-                        if nal.nal_type == NAL_AUD {
-                            continue;
-                        }
-
-                        if self.frame_buffer.frame_number != nal.decoded_frame_index {
-                            // Find existing frame for the current buffered frame
-                            let buffered_frame = self
-                                .frames
-                                .iter()
-                                .find(|f| f.decoded_number == self.frame_buffer.frame_number)
-                                .unwrap();
-
-                            self.frame_buffer.nals.insert(
-                                0,
-                                NalBuffer {
-                                    nal_type: NAL_AUD,
-                                    start_code: NALUStartCode::Length4,
-                                    data: hevc_parser::utils::aud_for_frame(buffered_frame, None)?,
-                                },
-                            );
-                        }
+                // Ignore existing RPU
+                if nal.nal_type == NAL_UNSPEC62 {
+                    continue;
+                }
+
+                // Skip AUD NALUs if we're adding them
+                if !self.no_add_aud && nal.nal_type == NAL_AUD {
+                    continue;
+                }
+
+                // Override in case of modified multi-message SEI
+                let final_chunk_data = if let Some(data) = nalu_data_override {
+                    data
+                } else {
+                    chunk[nal.start..nal.end].to_vec()
+                };
+
+                let nal_buf = NalBuffer {
+                    nal_type: nal.nal_type,
+                    start_code: nal.start_code,
+                    data: final_chunk_data,
+                };
+
+                let buffered_picture = self
+                    .frame_buffer
+                    .nals
+                    .iter()
+                    .any(|nb| is_vcl_nal(nb.nal_type));
+
+                if is_first_slice_of_au(nal_buf.nal_type, &nal_buf.data) && buffered_picture {
+                    self.write_buffered_frame()?;
+
+                    if let Some(checkpointer) = self.checkpointer.as_mut() {
+                        // The dropped NALUs before the first pending one aren't needed
+                        let next_au_offset = if self.pending_nals.is_empty() {
+                            nal_offset
+                        } else {
+                            self.pending_offset
+                        };
+
+                        checkpointer.frame_done(next_au_offset);
+                        checkpointer.checkpoint(|| self.writer.flush())?;
                     }
 
-                    let (idx, rpu_nb) = Self::get_rpu_and_index_to_insert(
-                        &self.frames,
-                        rpus,
-                        &self.frame_buffer,
-                        self.mismatched_length,
-                        &self.last_metadata_written,
-                    )?;
-
-                    self.last_metadata_written = Some(rpu_nb.clone());
-                    self.frame_buffer.nals.insert(idx, rpu_nb);
-
-                    // Write NALUs for the frame
-                    for (i, nal_buf) in self.frame_buffer.nals.iter().enumerate() {
-                        let first_nal = i == 0;
-
-                        NALUnit::write_with_preset(
-                            &mut self.writer,
-                            &nal_buf.data,
-                            self.options.start_code.into(),
-                            nal_buf.nal_type,
-                            first_nal,
-                        )?;
+                    self.frame_buffer.frame_number += 1;
+                    self.frame_buffer.nals.append(&mut self.pending_nals);
+                } else if is_au_leading_nal(nal_buf.nal_type) && buffered_picture {
+                    // Can't know yet if it starts the next access unit
+                    if self.pending_nals.is_empty() {
+                        self.pending_offset = nal_offset;
                     }
 
-                    self.frame_buffer.frame_number = nal.decoded_frame_index;
-                    self.frame_buffer.nals.clear();
+                    self.pending_nals.push(nal_buf);
+                    continue;
+                } else {
+                    // Still part of the buffered access unit
+                    self.frame_buffer.nals.append(&mut self.pending_nals);
                 }
 
-                // Ignore existing RPU
-                if nal.nal_type != NAL_UNSPEC62 {
-                    // Skip AUD NALUs if we're adding them
-                    if !self.no_add_aud && nal.nal_type == NAL_AUD {
-                        continue;
-                    }
+                self.frame_buffer.nals.push(nal_buf);
+            }
+        } else {
+            if self.options.l6_from_sei {
+                for nal in nals.iter().filter(|nal| nal.nal_type == NAL_SEI_PREFIX) {
+                    self.sei_static_metadata
+                        .parse_sei_nalu(&chunk[nal.start..nal.end])?;
+                }
+            }
 
-                    // Override in case of modified multi-message SEI
-                    let final_chunk_data = if let Some(data) = nalu_data_override {
-                        data
-                    } else {
-                        chunk[nal.start..nal.end].to_vec()
-                    };
+            if self.sps_bit_depth.is_none() {
+                if let Some(nal) = nals.iter().find(|nal| nal.nal_type == NAL_SPS) {
+                    // Only used for a warning, unsupported SPS are ignored
+                    self.sps_bit_depth = sps_luma_bit_depth(&chunk[nal.start..nal.end]).ok();
+                }
+            }
 
-                    self.frame_buffer.nals.push(NalBuffer {
-                        nal_type: nal.nal_type,
-                        start_code: nal.start_code,
-                        data: final_chunk_data,
-                    });
+            for nal in nals {
+                let data = &chunk[nal.start..nal.end];
+
+                if nal.nal_type == NAL_AUD && nal_layer_id(data) == 0 {
+                    self.access_units += 1;
+                } else if is_first_slice_of_au(nal.nal_type, data) {
+                    self.pictures += 1;
                 }
             }
-        } else if !self.already_checked_for_rpu && nals.iter().any(|e| e.nal_type == NAL_UNSPEC62) {
-            self.already_checked_for_rpu = true;
-            println!("\nWarning: Input file already has RPUs, they will be replaced.");
+
+            if !self.already_checked_for_rpu && nals.iter().any(|e| e.nal_type == NAL_UNSPEC62) {
+                self.already_checked_for_rpu = true;
+
+                ipc::log("");
+                self.telemetry
+                    .warning("Input file already has RPUs, they will be replaced.");
+            }
         }
 
         Ok(())
@@ -340,61 +1055,60 @@ impl IoProcessor for RpuInjector {
         if self.frames.is_empty() && self.nals.is_empty() {
             self.frames = parser.ordered_frames().clone();
             self.nals = parser.get_nals().clone();
-        } else {
-            let ordered_frames = parser.ordered_frames();
-            let total_frames = ordered_frames.len();
-
-            // Last slice wasn't considered (no AUD/EOS NALU at the end)
-            if (self.frame_buffer.frame_number as usize) != total_frames
-                && !self.frame_buffer.nals.is_empty()
-            {
-                let rpus = &self.rpus;
-
-                if !self.no_add_aud {
-                    let last_frame = ordered_frames
-                        .iter()
-                        .find(|f| f.decoded_number == self.frame_buffer.frame_number)
-                        .unwrap();
-
-                    self.frame_buffer.nals.insert(
-                        0,
-                        NalBuffer {
-                            nal_type: NAL_AUD,
-                            start_code: NALUStartCode::Length4,
-                            data: hevc_parser::utils::aud_for_frame(last_frame, None)?,
-                        },
-                    );
-                }
 
-                let (idx, rpu_nb) = Self::get_rpu_and_index_to_insert(
-                    &self.frames,
-                    rpus,
-                    &self.frame_buffer,
-                    self.mismatched_length,
-                    &self.last_metadata_written,
-                )?;
+            self.check_av_sync()?;
 
-                self.last_metadata_written = Some(rpu_nb.clone());
-                self.frame_buffer.nals.insert(idx, rpu_nb);
+            if self.rpu_start_code == Some(RpuStartCode::Auto) {
+                let detected = RpuStartCode::detect(&self.nals);
+                ipc::log(format!("Using {detected} RPU start code"));
 
-                // Write NALUs for the last frame
-                for (i, nal_buf) in self.frame_buffer.nals.iter().enumerate() {
-                    let first_nal = i == 0;
+                self.rpu_start_code = Some(detected);
+            }
 
-                    NALUnit::write_with_preset(
-                        &mut self.writer,
-                        &nal_buf.data,
-                        self.options.start_code.into(),
-                        nal_buf.nal_type,
-                        first_nal,
-                    )?;
+            if self.options.l6_from_sei {
+                if self.sei_static_metadata.is_empty() {
+                    self.telemetry
+                        .warning("No ST 2086 SEI found, the L6 metadata will not be modified");
+                } else {
+                    ipc::log(format!(
+                        "Setting L6 metadata from SEI: {}",
+                        self.sei_static_metadata
+                    ));
                 }
+            }
+        } else {
+            // Last frame, nothing follows it
+            self.frame_buffer.nals.append(&mut self.pending_nals);
 
-                self.frame_buffer.nals.clear();
+            if !self.frame_buffer.nals.is_empty() {
+                self.write_buffered_frame()?;
+            }
+
+            let inferred_frames = self.frame_buffer.frame_number as usize + 1;
+            if inferred_frames != self.frames.len() {
+                self.telemetry.warning(format!(
+                    "{} access units were found, but the video has {} frames",
+                    inferred_frames,
+                    self.frames.len()
+                ));
             }
 
             // Second pass
             self.writer.flush()?;
+            self.progress_bar.finish_and_clear();
+
+            if let Some(checkpointer) = self.checkpointer.take() {
+                checkpointer.finish()?;
+            }
+
+            if !self.options.dry_run {
+                ipc::log("Verifying the written NALUs...");
+                let count = self.verify_passthrough()?;
+
+                ipc::log(format!("{count} input NALUs written unchanged"));
+            }
+
+            return self.telemetry.finish(self.options.summary_json.as_ref());
         }
 
         self.progress_bar.finish_and_clear();
@@ -0,0 +1,336 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use dolby_vision::rpu::dovi_rpu::RpuParseOpts;
+use dolby_vision::rpu::extension_metadata::blocks::ExtMetadataBlock;
+use dolby_vision::rpu::utils::parse_rpu_file_with_opts;
+use dolby_vision::rpu::vdr_dm_data::VdrDmData;
+
+use super::frame_timestamps::range_label;
+use super::{
+    input_from_either, json_file_reader, labels_suffix, read_annotations_file, write_rpu_file,
+    DoviRpu,
+};
+use crate::commands::{ApplyPatchArgs, DiffArgs};
+use crate::ipc;
+
+const PATCH_VERSION: u8 = 1;
+
+const MAX_REPORTED_CHANGES: usize = 10;
+
+/// Per-frame metadata changes between two RPU files.
+///
+/// Only the DM extension metadata blocks and scene cut flags are described,
+/// consecutive frames with the same changes are grouped.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RpuPatch {
+    version: u8,
+
+    /// Number of frames of the RPU the patch applies to
+    length: usize,
+
+    changes: Vec<FrameChanges>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+struct FrameChanges {
+    start: usize,
+    duration: usize,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scene_cut: Option<bool>,
+
+    /// Levels replaced by `blocks`, the level is removed when it has no block
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    levels: Vec<u8>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    blocks: Vec<ExtMetadataBlock>,
+}
+
+pub struct RpuDiff;
+pub struct RpuPatcher;
+
+impl RpuDiff {
+    pub fn diff(args: DiffArgs, parse_opts: RpuParseOpts) -> Result<()> {
+        let DiffArgs {
+            original,
+            modified,
+            patch_out,
+            labels,
+        } = args;
+
+        let patch_out = patch_out.unwrap_or_else(|| PathBuf::from("RPU_patch.json"));
+
+        ipc::log("Parsing RPU files...");
+        let original_rpus = parse_rpu_file_with_opts(original, parse_opts)?;
+        let modified_rpus = parse_rpu_file_with_opts(modified, parse_opts)?;
+
+        let labeled_shots = match labels {
+            Some(path) => read_annotations_file(path)?.1,
+            None => Vec::new(),
+        };
+
+        let patch = RpuPatch::from_diff(&original_rpus, &modified_rpus)?;
+
+        let changed_frames: usize = patch.changes.iter().map(|c| c.duration).sum();
+        ipc::log(format!(
+            "{} changed frames in {} ranges",
+            changed_frames,
+            patch.changes.len()
+        ));
+
+        for change in patch.changes.iter().take(MAX_REPORTED_CHANGES) {
+            ipc::log(format!(
+                "  {}: {}{}",
+                range_label(change.start, change.start + change.duration - 1),
+                change.description(),
+                labels_suffix(&labeled_shots, change.start)
+            ));
+        }
+
+        if patch.changes.len() > MAX_REPORTED_CHANGES {
+            ipc::log(format!(
+                "  ... and {} more",
+                patch.changes.len() - MAX_REPORTED_CHANGES
+            ));
+        }
+
+        patch.write_file(patch_out)
+    }
+}
+
+impl RpuPatcher {
+    pub fn apply_patch(args: ApplyPatchArgs, parse_opts: RpuParseOpts) -> Result<()> {
+        let ApplyPatchArgs {
+            input,
+            input_pos,
+            patch,
+            rpu_out,
+        } = args;
+
+        let input = input_from_either("apply-patch", input, input_pos)?;
+        let rpu_out = rpu_out.unwrap_or_else(|| PathBuf::from("RPU_patched.bin"));
+
+        let patch = RpuPatch::from_file(patch)?;
+
+        ipc::log("Parsing RPU file...");
+        let mut rpus = parse_rpu_file_with_opts(input, parse_opts)?;
+
+        ipc::log("Applying patch...");
+        patch.apply(&mut rpus)?;
+
+        let data = rpus
+            .iter()
+            .map(|rpu| rpu.write_hevc_unspec62_nalu())
+            .collect::<Result<Vec<_>>>()?;
+
+        write_rpu_file(rpu_out, data)
+    }
+}
+
+impl RpuPatch {
+    /// Patch transforming `original` into `modified`.
+    ///
+    /// Fails if other parts of the RPUs than the patchable metadata differ.
+    pub fn from_diff(original: &[DoviRpu], modified: &[DoviRpu]) -> Result<Self> {
+        ensure!(
+            original.len() == modified.len(),
+            "Frame count mismatch: original has {} frames, modified {}",
+            original.len(),
+            modified.len()
+        );
+
+        let mut patch = Self {
+            version: PATCH_VERSION,
+            length: original.len(),
+            changes: Vec::new(),
+        };
+
+        for (frame, (original_rpu, modified_rpu)) in original.iter().zip(modified).enumerate() {
+            let changes = FrameChanges::from_diff(frame, original_rpu, modified_rpu)?;
+
+            // Check that the changes are enough to get the modified RPU
+            let mut patched_rpu = original_rpu.clone();
+            if !changes.is_empty() {
+                changes.apply_to_rpu(&mut patched_rpu)?;
+            }
+
+            if patched_rpu.write_hevc_unspec62_nalu()? != modified_rpu.write_hevc_unspec62_nalu()? {
+                bail!("Frame {frame}: the RPUs differ in more than the DM metadata blocks and scene cut, cannot create a patch");
+            }
+
+            if changes.is_empty() {
+                continue;
+            }
+
+            match patch.changes.last_mut() {
+                Some(last)
+                    if last.start + last.duration == frame && last.same_changes(&changes) =>
+                {
+                    last.duration += 1;
+                }
+                _ => patch.changes.push(changes),
+            }
+        }
+
+        Ok(patch)
+    }
+
+    pub fn apply(&self, rpus: &mut [DoviRpu]) -> Result<()> {
+        ensure!(
+            self.length == rpus.len(),
+            "The patch is for {} frames, the RPU has {}",
+            self.length,
+            rpus.len()
+        );
+
+        for changes in &self.changes {
+            let end = changes.start + changes.duration;
+            ensure!(
+                end <= rpus.len(),
+                "Changes for frames {}-{} are out of range",
+                changes.start,
+                end - 1
+            );
+
+            for rpu in &mut rpus[changes.start..end] {
+                changes.apply_to_rpu(rpu)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let patch: Self = serde_json::from_reader(json_file_reader(path)?)?;
+
+        ensure!(
+            patch.version == PATCH_VERSION,
+            "Unsupported patch version {}",
+            patch.version
+        );
+
+        Ok(patch)
+    }
+
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        ipc::log("Writing patch...");
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+impl FrameChanges {
+    fn from_diff(frame: usize, original: &DoviRpu, modified: &DoviRpu) -> Result<Self> {
+        let (Some(original_dm), Some(modified_dm)) =
+            (original.vdr_dm_data.as_ref(), modified.vdr_dm_data.as_ref())
+        else {
+            bail!("Frame {frame}: missing DM metadata");
+        };
+
+        let scene_cut = (original_dm.scene_refresh_flag != modified_dm.scene_refresh_flag)
+            .then_some(modified_dm.scene_refresh_flag == 1);
+
+        let mut levels: Vec<u8> = dm_levels(original_dm)
+            .into_iter()
+            .chain(dm_levels(modified_dm))
+            .collect();
+        levels.sort_unstable();
+        levels.dedup();
+
+        levels.retain(|level| {
+            !original_dm
+                .level_blocks_iter(*level)
+                .eq(modified_dm.level_blocks_iter(*level))
+        });
+
+        let blocks = levels
+            .iter()
+            .flat_map(|level| modified_dm.level_blocks_iter(*level).cloned())
+            .collect();
+
+        Ok(Self {
+            start: frame,
+            duration: 1,
+            scene_cut,
+            levels,
+            blocks,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.scene_cut.is_none() && self.levels.is_empty()
+    }
+
+    /// Changed levels and scene cut flag, e.g. `L1, L2, scene cut removed`
+    fn description(&self) -> String {
+        let mut changes: Vec<String> = self
+            .levels
+            .iter()
+            .map(|level| format!("L{level}"))
+            .collect();
+
+        match self.scene_cut {
+            Some(true) => changes.push(String::from("scene cut added")),
+            Some(false) => changes.push(String::from("scene cut removed")),
+            None => (),
+        }
+
+        changes.join(", ")
+    }
+
+    fn same_changes(&self, other: &Self) -> bool {
+        self.scene_cut == other.scene_cut
+            && self.levels == other.levels
+            && self.blocks == other.blocks
+    }
+
+    fn apply_to_rpu(&self, rpu: &mut DoviRpu) -> Result<()> {
+        let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() else {
+            bail!("Cannot apply patch, frame has no DM metadata");
+        };
+
+        if let Some(scene_cut) = self.scene_cut {
+            vdr_dm_data.set_scene_cut(scene_cut);
+        }
+
+        for level in &self.levels {
+            vdr_dm_data.remove_metadata_level(*level);
+        }
+
+        for block in &self.blocks {
+            ensure!(
+                self.levels.contains(&block.level()),
+                "Patch block of level {} is not in the changed levels",
+                block.level()
+            );
+
+            vdr_dm_data.add_metadata_block(block.clone())?;
+        }
+
+        rpu.modified = true;
+
+        Ok(())
+    }
+}
+
+/// Levels of the CM v2.9 and CM v4.0 blocks
+fn dm_levels(vdr_dm_data: &VdrDmData) -> Vec<u8> {
+    [1, 3]
+        .into_iter()
+        .filter_map(|level| vdr_dm_data.metadata_blocks(level))
+        .flatten()
+        .map(|block| block.level())
+        .collect()
+}
@@ -0,0 +1,203 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use super::input_from_either;
+use super::rpu_verifier::split_nalus;
+use crate::commands::ReorderArgs;
+use crate::ipc;
+
+const X265_ENCODE_ORDER_COLUMN: &str = "Encode Order";
+const X265_POC_COLUMN: &str = "POC";
+
+/// Reorders the RPUs of a file between display order and encoder output order.
+///
+/// The frame order lists the display index of every frame, in encode order.
+/// The RPU NAL units are moved as is, without being parsed.
+pub struct RpuReorderer {
+    input: PathBuf,
+    frame_order: Vec<usize>,
+    to_display: bool,
+}
+
+impl RpuReorderer {
+    pub fn reorder(args: ReorderArgs) -> Result<()> {
+        let ReorderArgs {
+            input,
+            input_pos,
+            x265_csv,
+            frame_order,
+            to_display,
+            rpu_out,
+        } = args;
+
+        let input = input_from_either("reorder", input, input_pos)?;
+        let rpu_out = rpu_out.unwrap_or_else(|| PathBuf::from("RPU_reordered.bin"));
+
+        let frame_order = if let Some(csv_path) = x265_csv {
+            ipc::log("Reading x265 CSV log...");
+            parse_x265_csv(csv_path)?
+        } else if let Some(order_path) = frame_order {
+            ipc::log("Reading frame order...");
+            parse_frame_order(order_path)?
+        } else {
+            bail!("Missing frame order");
+        };
+
+        let reorderer = RpuReorderer {
+            input,
+            frame_order,
+            to_display,
+        };
+
+        ipc::log("Reading RPU file...");
+        let data = fs::read(&reorderer.input)?;
+        let nalus = split_nalus(&data);
+
+        reorderer.validate(nalus.len())?;
+        reorderer.print_summary();
+
+        ipc::log("Writing RPU file...");
+        fs::write(rpu_out, reorderer.reordered(&nalus).concat())?;
+
+        ipc::log("Done.");
+
+        Ok(())
+    }
+
+    /// The frame order must be a permutation of the RPU frames
+    fn validate(&self, rpu_count: usize) -> Result<()> {
+        ensure!(
+            self.frame_order.len() == rpu_count,
+            "Frame count mismatch: the frame order has {} frames, the RPU file {}",
+            self.frame_order.len(),
+            rpu_count
+        );
+
+        let mut seen = vec![false; rpu_count];
+
+        for (encode_index, &display_index) in self.frame_order.iter().enumerate() {
+            ensure!(
+                display_index < rpu_count,
+                "Encoded frame {encode_index}: display frame {display_index} is out of range, {rpu_count} RPUs"
+            );
+            ensure!(
+                !seen[display_index],
+                "Encoded frame {encode_index}: display frame {display_index} is encoded more than once"
+            );
+
+            seen[display_index] = true;
+        }
+
+        Ok(())
+    }
+
+    fn print_summary(&self) {
+        let moved = self
+            .frame_order
+            .iter()
+            .enumerate()
+            .filter(|(encode_index, display_index)| encode_index != *display_index);
+        let (count, max_distance) =
+            moved.fold((0, 0), |(count, max), (encode_index, display_index)| {
+                (count + 1, max.max(encode_index.abs_diff(*display_index)))
+            });
+
+        let direction = if self.to_display {
+            "encode to display"
+        } else {
+            "display to encode"
+        };
+
+        ipc::log(format!(
+            "Reordering {} frames from {direction} order: {count} frames moved, up to {max_distance} frames away",
+            self.frame_order.len()
+        ));
+    }
+
+    fn reordered<'a>(&self, nalus: &[&'a [u8]]) -> Vec<&'a [u8]> {
+        if self.to_display {
+            let mut display_order = nalus.to_vec();
+
+            for (encode_index, &display_index) in self.frame_order.iter().enumerate() {
+                display_order[display_index] = nalus[encode_index];
+            }
+
+            display_order
+        } else {
+            self.frame_order
+                .iter()
+                .map(|&display_index| nalus[display_index])
+                .collect()
+        }
+    }
+}
+
+/// Display order of the frames from a x265 CSV frame log, in encode order.
+///
+/// The x265 POC is the input frame index, as the frames are written by the encoder.
+fn parse_x265_csv<P: AsRef<Path>>(path: P) -> Result<Vec<usize>> {
+    let csv = fs::read_to_string(path)?;
+    let mut lines = csv.lines();
+
+    let header: Vec<&str> = lines
+        .next()
+        .map(|line| line.split(',').map(str::trim).collect())
+        .unwrap_or_default();
+
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| *column == name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Missing '{name}' column in the x265 CSV log, the frame level log is required"
+                )
+            })
+    };
+    let encode_order_column = column(X265_ENCODE_ORDER_COLUMN)?;
+    let poc_column = column(X265_POC_COLUMN)?;
+
+    let mut frames: Vec<(usize, usize)> = lines
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+
+            // Skips the summary lines
+            let encode_order = fields.get(encode_order_column)?.parse().ok()?;
+            let poc = fields.get(poc_column)?.parse().ok()?;
+
+            Some((encode_order, poc))
+        })
+        .collect();
+    frames.sort_unstable_by_key(|(encode_order, _)| *encode_order);
+
+    ensure!(!frames.is_empty(), "No frame found in the x265 CSV log");
+
+    Ok(frames.into_iter().map(|(_, poc)| poc).collect())
+}
+
+/// Display indexes of the frames, in encode order.
+/// Whitespace separated, `#` starts a comment.
+fn parse_frame_order<P: AsRef<Path>>(path: P) -> Result<Vec<usize>> {
+    let contents = fs::read_to_string(path)?;
+
+    let frame_order = contents
+        .lines()
+        .flat_map(|line| {
+            line.split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+        })
+        .map(|index| {
+            index
+                .parse()
+                .map_err(|_| anyhow!("Invalid frame index '{index}' in the frame order"))
+        })
+        .collect::<Result<Vec<usize>>>()?;
+
+    ensure!(!frame_order.is_empty(), "Empty frame order");
+
+    Ok(frame_order)
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+
+use anyhow::{bail, ensure, Result};
+
+use dolby_vision::rpu::dovi_rpu::RpuParseOpts;
+use dolby_vision::rpu::generate::GenerateConfig;
+
+use super::DoviRpu;
+
+/// Number of RPUs parsed and encoded ahead of the frame being written
+const STREAM_QUEUE_SIZE: usize = 64;
+
+const START_CODE: &[u8] = &[0, 0, 0, 1];
+
+/// RPUs of a file or a generate config, streamed from a thread.
+///
+/// The RPUs are parsed and encoded ahead of the writer, in a bounded queue.
+pub struct RpuStore {
+    len: usize,
+    receiver: Receiver<Result<StoredRpu>>,
+
+    // Index of the next RPU to receive
+    next_index: usize,
+    // RPUs dropped from the start of the file
+    dropped: usize,
+    // RPUs received ahead of the requested one, for frame reordering
+    reordered: HashMap<usize, StoredRpu>,
+}
+
+/// RPU ready to be written
+pub struct StoredRpu {
+    pub rpu: DoviRpu,
+    /// Encoded UNSPEC62 NALU, without start code
+    pub data: Vec<u8>,
+    /// Parsing error, when the RPU was substituted in lenient mode
+    pub error: Option<String>,
+}
+
+impl RpuStore {
+    /// Opens the RPU file, in `lenient` mode the corrupt RPUs are reported and substituted
+    pub fn from_file<P: AsRef<Path>>(
+        input: P,
+        lenient: bool,
+        parse_opts: RpuParseOpts,
+    ) -> Result<Self> {
+        let input = input.as_ref();
+
+        // Only the start codes are counted, the RPUs are parsed by the reader thread
+        let mut len = 0;
+        read_rpu_nalus(input, |_| {
+            len += 1;
+            Ok(true)
+        })?;
+
+        if len == 0 {
+            bail!("No RPU found");
+        }
+
+        let (sender, receiver) = sync_channel(STREAM_QUEUE_SIZE);
+        let input = input.to_path_buf();
+
+        thread::spawn(move || {
+            if let Err(e) = Self::send_rpus(&input, lenient, parse_opts, &sender) {
+                // The receiver may be gone already
+                sender.send(Err(e)).ok();
+            }
+        });
+
+        Ok(Self {
+            len,
+            receiver,
+            next_index: 0,
+            dropped: 0,
+            reordered: HashMap::new(),
+        })
+    }
+
+    /// Generates the RPUs of the config ahead of the writer, without an intermediate RPU file
+    pub fn from_generate_config(config: GenerateConfig) -> Result<Self> {
+        ensure!(config.length > 0, "No RPU to generate");
+        config.ensure_shots_length()?;
+
+        let len = config.length;
+        let base_rpu = config.base_rpu()?;
+
+        let (sender, receiver) = sync_channel(STREAM_QUEUE_SIZE);
+
+        thread::spawn(move || {
+            if let Err(e) = Self::send_generated_rpus(&config, &base_rpu, &sender) {
+                sender.send(Err(e)).ok();
+            }
+        });
+
+        Ok(Self {
+            len,
+            receiver,
+            next_index: 0,
+            dropped: 0,
+            reordered: HashMap::new(),
+        })
+    }
+
+    fn send_generated_rpus(
+        config: &GenerateConfig,
+        base_rpu: &DoviRpu,
+        sender: &SyncSender<Result<StoredRpu>>,
+    ) -> Result<()> {
+        for shot in &config.shots {
+            for rpu in config.shot_rpus(base_rpu, shot)? {
+                let data = rpu.write_hevc_unspec62_nalu()?;
+                let stored = StoredRpu {
+                    rpu,
+                    data,
+                    error: None,
+                };
+
+                // Stop generating when the receiver is dropped
+                if sender.send(Ok(stored)).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_rpus(
+        input: &Path,
+        lenient: bool,
+        parse_opts: RpuParseOpts,
+        sender: &SyncSender<Result<StoredRpu>>,
+    ) -> Result<()> {
+        let mut index = 0;
+        let mut previous: Option<DoviRpu> = None;
+
+        read_rpu_nalus(input, |nalu| {
+            let stored = parse_and_encode(nalu, index, lenient, parse_opts, previous.as_ref())?;
+            index += 1;
+
+            if lenient {
+                previous = Some(stored.rpu.clone());
+            }
+
+            // Stop reading when the receiver is dropped
+            Ok(sender.send(Ok(stored)).is_ok())
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Discards the first `count` RPUs, the next one becomes index 0.
+    /// Must be called before taking any RPU.
+    pub fn drop_head(&mut self, count: usize) -> Result<()> {
+        let count = count.min(self.len);
+
+        for _ in 0..count {
+            let Ok(res) = self.receiver.recv() else {
+                bail!("RPU reader stopped before RPU {}", self.next_index);
+            };
+
+            res?;
+            self.next_index += 1;
+        }
+
+        self.len -= count;
+        self.dropped += count;
+
+        Ok(())
+    }
+
+    /// RPU at `index`, `None` past the end of the file.
+    ///
+    /// RPUs can only be taken once, in roughly increasing order.
+    pub fn take(&mut self, index: usize) -> Result<Option<StoredRpu>> {
+        // Index in the file
+        let index = index + self.dropped;
+
+        if let Some(stored) = self.reordered.remove(&index) {
+            return Ok(Some(stored));
+        } else if index < self.next_index {
+            bail!("RPU {index} was already written");
+        } else if index >= self.len + self.dropped {
+            return Ok(None);
+        }
+
+        loop {
+            let Ok(res) = self.receiver.recv() else {
+                bail!("RPU reader stopped before RPU {index}");
+            };
+
+            let stored = res?;
+            let received_index = self.next_index;
+            self.next_index += 1;
+
+            if received_index == index {
+                return Ok(Some(stored));
+            }
+
+            self.reordered.insert(received_index, stored);
+        }
+    }
+}
+
+/// Parses and re-encodes a RPU NALU.
+///
+/// In `lenient` mode, a corrupt RPU is replaced by the previous one, or a placeholder.
+fn parse_and_encode(
+    nalu: &[u8],
+    index: usize,
+    lenient: bool,
+    parse_opts: RpuParseOpts,
+    previous: Option<&DoviRpu>,
+) -> Result<StoredRpu> {
+    let res = DoviRpu::parse_unspec62_nalu_with_opts(nalu, parse_opts)
+        .and_then(|rpu| rpu.write_hevc_unspec62_nalu().map(|data| (rpu, data)));
+
+    match res {
+        Ok((rpu, data)) => Ok(StoredRpu {
+            rpu,
+            data,
+            error: None,
+        }),
+        Err(e) if lenient => {
+            let rpu = match previous {
+                Some(rpu) => rpu.clone(),
+                None => DoviRpu::placeholder()?,
+            };
+            let data = rpu.write_hevc_unspec62_nalu()?;
+
+            Ok(StoredRpu {
+                rpu,
+                data,
+                error: Some(e.to_string()),
+            })
+        }
+        Err(e) => bail!("Found invalid RPU: Index {index}, error: {e}"),
+    }
+}
+
+/// Calls `f` with the data of every NALU of the RPU file,
+/// without the start code.
+///
+/// Reading stops early when `f` returns `false`.
+fn read_rpu_nalus<F>(input: &Path, mut f: F) -> Result<()>
+where
+    F: FnMut(&[u8]) -> Result<bool>,
+{
+    let mut reader = BufReader::with_capacity(100_000, File::open(input)?);
+
+    let mut chunk = vec![0; 100_000];
+    let mut buffer = Vec::with_capacity(200_000);
+
+    loop {
+        let read_bytes = reader.read(&mut chunk)?;
+        let eof = read_bytes == 0;
+
+        buffer.extend_from_slice(&chunk[..read_bytes]);
+
+        // The last NALU is only complete at the end of the file
+        let start_codes = find_start_codes(&buffer);
+        let complete = if eof {
+            start_codes.len()
+        } else {
+            start_codes.len().saturating_sub(1)
+        };
+
+        for (i, start_code) in start_codes.iter().enumerate().take(complete) {
+            let start = start_code + START_CODE.len();
+            let end = start_codes.get(i + 1).copied().unwrap_or(buffer.len());
+
+            if !f(&buffer[start..end])? {
+                return Ok(());
+            }
+        }
+
+        if eof {
+            return Ok(());
+        } else if complete > 0 {
+            buffer.drain(..start_codes[complete]);
+        }
+    }
+}
+
+/// NALU at `index` in the RPU file, without the start code
+pub fn read_rpu_nalu(input: &Path, index: usize) -> Result<Option<Vec<u8>>> {
+    let mut current = 0;
+    let mut nalu = None;
+
+    read_rpu_nalus(input, |data| {
+        if current == index {
+            nalu = Some(data.to_vec());
+        }
+
+        current += 1;
+
+        Ok(nalu.is_none())
+    })?;
+
+    Ok(nalu)
+}
+
+fn find_start_codes(data: &[u8]) -> Vec<usize> {
+    data.windows(START_CODE.len())
+        .enumerate()
+        .filter_map(|(i, w)| (w == START_CODE).then_some(i))
+        .collect()
+}
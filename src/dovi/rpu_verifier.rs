@@ -0,0 +1,261 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::mem;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+
+use anyhow::{bail, Result};
+
+use dolby_vision::rpu::dovi_rpu::DoviRpu;
+use dolby_vision::rpu::nalu_reader::NaluReader;
+
+use super::frame_timestamps::frame_label;
+use super::input_from_either;
+use crate::commands::VerifyArgs;
+use crate::ipc;
+
+const MAX_REPORTED_ERRORS: usize = 10;
+
+// RPUs sent at once to a verification thread
+const BATCH_SIZE: usize = 1000;
+
+/// RPU NALUs with their frame index
+type RpuBatch = Vec<(usize, Vec<u8>)>;
+
+/// Invalid frames and mapping ids, with their frame index
+type BatchResults = (Vec<(usize, String)>, Vec<(usize, RpuId)>);
+
+pub struct RpuVerifier {
+    input: PathBuf,
+    threads: usize,
+    check_ids: bool,
+}
+
+/// Mapping id signaling of a RPU
+#[derive(Debug, Clone, Copy)]
+enum RpuId {
+    /// `vdr_rpu_id` of the RPU mapping
+    Mapping(u64),
+    /// `prev_vdr_rpu_id`, using the mapping of a previous RPU
+    Previous(u64),
+}
+
+impl RpuVerifier {
+    pub fn verify(args: VerifyArgs) -> Result<()> {
+        let VerifyArgs {
+            input,
+            input_pos,
+            threads,
+            check_ids,
+        } = args;
+
+        let input = input_from_either("verify", input, input_pos)?;
+        let threads = threads
+            .or_else(|| thread::available_parallelism().ok().map(NonZeroUsize::get))
+            .unwrap_or(1)
+            .max(1);
+
+        let verifier = RpuVerifier {
+            input,
+            threads,
+            check_ids,
+        };
+        verifier.execute()
+    }
+
+    fn execute(&self) -> Result<()> {
+        ipc::log("Verifying RPU file...");
+
+        // Split on the 3 and 4 bytes start codes while reading,
+        // the batches are verified by the threads as they are read
+        let mut reader = NaluReader::new(File::open(&self.input)?);
+        let check_ids = self.check_ids;
+
+        let (sender, receiver) = sync_channel(self.threads * 2);
+        let receiver = Mutex::new(receiver);
+
+        let mut errors: Vec<(usize, String)> = Vec::new();
+        let mut ids: Vec<(usize, RpuId)> = Vec::new();
+
+        let frames = thread::scope(|s| {
+            let handles: Vec<_> = (0..self.threads)
+                .map(|_| s.spawn(|| verify_batches(&receiver, check_ids)))
+                .collect();
+
+            // The threads stop once the sender is dropped
+            let res = send_batches(&mut reader, sender);
+
+            for handle in handles {
+                let (batch_errors, batch_ids) =
+                    handle.join().expect("Verification thread panicked");
+
+                errors.extend(batch_errors);
+                ids.extend(batch_ids);
+            }
+
+            res
+        })?;
+
+        if frames == 0 {
+            bail!("No RPU found");
+        }
+
+        if check_ids {
+            ids.sort_by_key(|(index, _)| *index);
+            errors.extend(inconsistent_ids(&ids));
+        }
+
+        if errors.is_empty() {
+            ipc::log(format!("Verified {frames} frames, no errors found."));
+
+            return Ok(());
+        }
+
+        errors.sort_by_key(|(index, _)| *index);
+
+        for (index, error) in errors.iter().take(MAX_REPORTED_ERRORS) {
+            ipc::log(format!("Frame {}: {error}", frame_label(*index)));
+        }
+
+        if errors.len() > MAX_REPORTED_ERRORS {
+            ipc::log(format!(
+                "... and {} more",
+                errors.len() - MAX_REPORTED_ERRORS
+            ));
+        }
+
+        bail!(
+            "Verification failed: {} invalid frames out of {frames}",
+            errors.len()
+        )
+    }
+}
+
+/// Reads the RPU NALUs in batches, returns the frame count
+fn send_batches<R: Read>(
+    reader: &mut NaluReader<R>,
+    sender: SyncSender<RpuBatch>,
+) -> Result<usize> {
+    let mut frames = 0;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+
+    while let Some(nalu) = reader.next_nalu()? {
+        batch.push((frames, nalu.data));
+        frames += 1;
+
+        // The threads only stop early when panicking
+        if batch.len() == BATCH_SIZE && sender.send(mem::take(&mut batch)).is_err() {
+            break;
+        }
+    }
+
+    if !batch.is_empty() {
+        sender.send(batch).ok();
+    }
+
+    Ok(frames)
+}
+
+fn verify_batches(receiver: &Mutex<Receiver<RpuBatch>>, check_ids: bool) -> BatchResults {
+    let mut errors = Vec::new();
+    let mut ids = Vec::new();
+
+    loop {
+        let batch = receiver.lock().expect("Verification queue poisoned").recv();
+
+        let Ok(batch) = batch else {
+            break;
+        };
+
+        for (index, nalu) in batch {
+            let res = DoviRpu::verify_unspec62_nalu(&nalu).and_then(|_| {
+                if check_ids {
+                    let rpu = DoviRpu::parse_unspec62_nalu(&nalu)?;
+                    ids.extend(RpuId::from_rpu(&rpu).map(|id| (index, id)));
+                }
+
+                Ok(())
+            });
+
+            if let Err(e) = res {
+                errors.push((index, e.to_string()));
+            }
+        }
+    }
+
+    (errors, ids)
+}
+
+impl RpuId {
+    fn from_rpu(rpu: &DoviRpu) -> Option<Self> {
+        if rpu.header.use_prev_vdr_rpu_flag {
+            Some(RpuId::Previous(rpu.header.prev_vdr_rpu_id))
+        } else {
+            rpu.rpu_data_mapping
+                .as_ref()
+                .map(|mapping| RpuId::Mapping(mapping.vdr_rpu_id))
+        }
+    }
+}
+
+/// Frames with a `vdr_rpu_id` different from the first frame,
+/// or referencing a mapping id that was not signaled before.
+///
+/// `ids` must be in frame order.
+fn inconsistent_ids(ids: &[(usize, RpuId)]) -> Vec<(usize, String)> {
+    let mut errors = Vec::new();
+
+    let mut first_id = None;
+    let mut signaled_ids = HashSet::new();
+
+    for (index, id) in ids {
+        match *id {
+            RpuId::Mapping(vdr_rpu_id) => {
+                signaled_ids.insert(vdr_rpu_id);
+
+                match first_id {
+                    None => first_id = Some(vdr_rpu_id),
+                    Some(first) if first != vdr_rpu_id => errors.push((
+                        *index,
+                        format!("vdr_rpu_id {vdr_rpu_id} differs from the first frame id {first}"),
+                    )),
+                    _ => (),
+                }
+            }
+            RpuId::Previous(prev_vdr_rpu_id) => {
+                if !signaled_ids.contains(&prev_vdr_rpu_id) {
+                    errors.push((
+                        *index,
+                        format!(
+                            "prev_vdr_rpu_id {prev_vdr_rpu_id} references an unsignaled vdr_rpu_id"
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+/// Splits the RPU file data on the 4 bytes start codes
+pub fn split_nalus(data: &[u8]) -> Vec<&[u8]> {
+    let offsets: Vec<usize> = data
+        .windows(4)
+        .enumerate()
+        .filter_map(|(i, w)| matches!(w, [0, 0, 0, 1]).then_some(i))
+        .collect();
+
+    offsets
+        .iter()
+        .enumerate()
+        .map(|(i, start)| {
+            let end = offsets.get(i + 1).copied().unwrap_or(data.len());
+            &data[*start..end]
+        })
+        .collect()
+}
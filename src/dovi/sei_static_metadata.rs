@@ -0,0 +1,141 @@
+use std::fmt;
+
+use anyhow::Result;
+
+use bitvec_helpers::bitstream_io_reader::BsIoSliceReader;
+use hevc_parser::hevc::SeiMessage;
+use hevc_parser::utils::clear_start_code_emulation_prevention_3_byte;
+
+use dolby_vision::rpu::extension_metadata::blocks::level6::MAX_PQ_LUMINANCE;
+use dolby_vision::rpu::extension_metadata::blocks::ExtMetadataBlock;
+
+use super::DoviRpu;
+
+const MASTERING_DISPLAY_COLOUR_VOLUME: u8 = 137;
+const CONTENT_LIGHT_LEVEL_INFO: u8 = 144;
+
+/// HDR10 static metadata from the ST 2086 mastering display and content light level SEI messages
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SeiStaticMetadata {
+    /// Max and min mastering display luminance, in 0.0001 cd/m²
+    mastering_luminance: Option<(u32, u32)>,
+    /// MaxCLL and MaxFALL, in cd/m²
+    content_light_level: Option<(u16, u16)>,
+}
+
+impl SeiStaticMetadata {
+    /// Reads the messages of a prefix SEI NALU.
+    ///
+    /// Only the first values found in the stream are kept.
+    pub fn parse_sei_nalu(&mut self, data: &[u8]) -> Result<()> {
+        if self.is_complete() {
+            return Ok(());
+        }
+
+        let sei_payload = clear_start_code_emulation_prevention_3_byte(data);
+
+        if sei_payload.len() < 4 {
+            return Ok(());
+        }
+
+        for msg in SeiMessage::parse_sei_rbsp(&sei_payload)? {
+            let start = msg.payload_offset;
+            let end = start + msg.payload_size;
+
+            let Some(bytes) = sei_payload.get(start..end) else {
+                continue;
+            };
+            let mut reader = BsIoSliceReader::from_slice(bytes);
+
+            match msg.payload_type {
+                MASTERING_DISPLAY_COLOUR_VOLUME
+                    if msg.payload_size >= 24 && self.mastering_luminance.is_none() =>
+                {
+                    // Display primaries and white point
+                    for _ in 0..8 {
+                        reader.get_n::<u16>(16)?;
+                    }
+
+                    let max_luminance = reader.get_n::<u32>(32)?;
+                    let min_luminance = reader.get_n::<u32>(32)?;
+
+                    self.mastering_luminance = Some((max_luminance, min_luminance));
+                }
+                CONTENT_LIGHT_LEVEL_INFO
+                    if msg.payload_size >= 4 && self.content_light_level.is_none() =>
+                {
+                    let max_cll = reader.get_n::<u16>(16)?;
+                    let max_fall = reader.get_n::<u16>(16)?;
+
+                    self.content_light_level = Some((max_cll, max_fall));
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mastering_luminance.is_none() && self.content_light_level.is_none()
+    }
+
+    fn is_complete(&self) -> bool {
+        self.mastering_luminance.is_some() && self.content_light_level.is_some()
+    }
+
+    /// Sets the L6 values found in the SEI, keeping the current ones for the missing messages.
+    ///
+    /// Returns whether the RPU was modified.
+    pub fn apply_to_rpu(&self, rpu: &mut DoviRpu) -> Result<bool> {
+        let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() else {
+            return Ok(false);
+        };
+
+        let existing = match vdr_dm_data.get_block(6) {
+            Some(ExtMetadataBlock::Level6(level6)) => Some(level6.clone()),
+            _ => None,
+        };
+
+        let mut level6 = existing.clone().unwrap_or_default();
+        let max_luminance = u32::from(MAX_PQ_LUMINANCE);
+
+        if let Some((max, min)) = self.mastering_luminance {
+            level6.max_display_mastering_luminance =
+                ((max + 5000) / 10_000).min(max_luminance) as u16;
+            level6.min_display_mastering_luminance = min.min(max_luminance) as u16;
+        }
+
+        if let Some((max_cll, max_fall)) = self.content_light_level {
+            level6.max_content_light_level = max_cll.min(MAX_PQ_LUMINANCE);
+            level6.max_frame_average_light_level = max_fall.min(MAX_PQ_LUMINANCE);
+        }
+
+        if existing.as_ref() == Some(&level6) {
+            return Ok(false);
+        }
+
+        vdr_dm_data.replace_metadata_block(ExtMetadataBlock::Level6(level6))?;
+        rpu.modified = true;
+
+        Ok(true)
+    }
+}
+
+impl fmt::Display for SeiStaticMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mastering = self.mastering_luminance.map_or_else(
+            || String::from("none"),
+            |(max, min)| format!("{:.4}/{} nits", min as f64 / 10_000.0, max / 10_000),
+        );
+        let content_light_level = self.content_light_level.map_or_else(
+            || String::from("none"),
+            |(max_cll, max_fall)| format!("MaxCLL {max_cll}, MaxFALL {max_fall}"),
+        );
+
+        write!(
+            f,
+            "mastering display {mastering}, content light level {content_light_level}"
+        )
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs::File;
+use std::io::{stdout, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use indicatif::ProgressBar;
+use serde::Serialize;
+
+use hevc_parser::hevc::{Frame, NALUnit, NAL_IDR_N_LP, NAL_IDR_W_RADL};
+use hevc_parser::io::{processor, IoProcessor};
+use hevc_parser::HevcParser;
+use processor::{HevcProcessor, HevcProcessorOpts};
+
+use crate::ipc;
+
+use super::{DoviRpu, IoFormat};
+
+/// Scene starts of the RPU list, matched to the IDR frames of the video.
+///
+/// Scene cuts that don't land on an IDR frame can't be seeked to directly,
+/// the previous and next IDR frames are reported to help tune the keyframe placement.
+#[derive(Serialize)]
+pub struct ShotBoundaryReport {
+    frames: usize,
+    idr_frames: usize,
+    shots: usize,
+
+    /// Scene starts not on an IDR frame
+    misaligned_shots: usize,
+
+    /// IDR frames within a shot
+    idrs_within_shots: usize,
+
+    boundaries: Vec<ShotBoundary>,
+}
+
+#[derive(Serialize)]
+struct ShotBoundary {
+    frame: usize,
+    on_idr: bool,
+
+    /// Closest IDR frames, when the scene start is not on an IDR frame
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_idr: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_idr: Option<usize>,
+}
+
+struct IdrFramesCollector {
+    input: PathBuf,
+    progress_bar: ProgressBar,
+    frames: Vec<Frame>,
+}
+
+impl ShotBoundaryReport {
+    /// `idr_frames` are the sorted presentation indices of the IDR frames
+    pub fn new(rpus: &[DoviRpu], idr_frames: &[usize]) -> Self {
+        let frames = rpus.len();
+        let idr_frames: Vec<usize> = idr_frames
+            .iter()
+            .copied()
+            .filter(|idx| *idx < frames)
+            .collect();
+
+        let scene_starts: Vec<usize> = rpus
+            .iter()
+            .enumerate()
+            .filter(|(i, rpu)| {
+                *i == 0
+                    || rpu
+                        .vdr_dm_data
+                        .as_ref()
+                        .map_or(false, |vdr| vdr.scene_refresh_flag == 1)
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        let boundaries: Vec<ShotBoundary> = scene_starts
+            .iter()
+            .map(|&frame| match idr_frames.binary_search(&frame) {
+                Ok(_) => ShotBoundary {
+                    frame,
+                    on_idr: true,
+                    previous_idr: None,
+                    next_idr: None,
+                },
+                Err(pos) => ShotBoundary {
+                    frame,
+                    on_idr: false,
+                    previous_idr: pos.checked_sub(1).map(|i| idr_frames[i]),
+                    next_idr: idr_frames.get(pos).copied(),
+                },
+            })
+            .collect();
+
+        let misaligned_shots = boundaries.iter().filter(|b| !b.on_idr).count();
+        let idrs_within_shots = idr_frames
+            .iter()
+            .filter(|idx| scene_starts.binary_search(idx).is_err())
+            .count();
+
+        Self {
+            frames,
+            idr_frames: idr_frames.len(),
+            shots: boundaries.len(),
+            misaligned_shots,
+            idrs_within_shots,
+            boundaries,
+        }
+    }
+
+    pub fn print_summary(&self) {
+        ipc::log(format!(
+            "{} of {} scene cuts are not on IDR frames, {} IDR frames are within shots",
+            self.misaligned_shots, self.shots, self.idrs_within_shots
+        ));
+    }
+
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+
+        Ok(())
+    }
+}
+
+/// Presentation indices of the IDR frames of a raw HEVC bitstream, sorted
+pub fn parse_idr_frames(input: &Path) -> Result<Vec<usize>> {
+    let format = hevc_parser::io::format_from_path(input)?;
+
+    if !matches!(format, IoFormat::Raw) {
+        bail!("Shot boundaries: the video must be a raw HEVC bitstream file");
+    }
+
+    ipc::log("Processing input video for IDR frames...");
+    stdout().flush().ok();
+
+    let mut collector = IdrFramesCollector {
+        input: input.to_path_buf(),
+        progress_bar: super::initialize_progress_bar(&format, input)?,
+        frames: Vec::new(),
+    };
+
+    let chunk_size = 100_000;
+    let mut processor = HevcProcessor::new(format, HevcProcessorOpts::default(), chunk_size);
+
+    let file = File::open(input)?;
+    let mut reader = Box::new(BufReader::with_capacity(100_000, file));
+
+    processor.process_io(&mut reader, &mut collector)?;
+
+    let mut idr_frames: Vec<usize> = collector
+        .frames
+        .iter()
+        .filter(|frame| {
+            frame
+                .nals
+                .iter()
+                .any(|nal| matches!(nal.nal_type, NAL_IDR_W_RADL | NAL_IDR_N_LP))
+        })
+        .map(|frame| frame.presentation_number as usize)
+        .collect();
+    idr_frames.sort_unstable();
+
+    Ok(idr_frames)
+}
+
+impl IoProcessor for IdrFramesCollector {
+    fn input(&self) -> &PathBuf {
+        &self.input
+    }
+
+    fn update_progress(&mut self, delta: u64) {
+        self.progress_bar.inc(delta);
+    }
+
+    fn process_nals(
+        &mut self,
+        _parser: &HevcParser,
+        _nals: &[NALUnit],
+        _chunk: &[u8],
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn finalize(&mut self, parser: &HevcParser) -> Result<()> {
+        self.progress_bar.finish_and_clear();
+        self.frames = parser.ordered_frames().clone();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Write};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use dolby_vision::rpu::dovi_rpu::DoviRpu;
+use dolby_vision::rpu::rpu_data_header::RpuDataHeader;
+
+use crate::ipc;
+
+/// Summary of what a processing run did to the metadata.
+#[derive(Serialize, Debug)]
+pub struct ConversionTelemetry {
+    command: &'static str,
+    frames: usize,
+
+    input_profiles: BTreeMap<u8, usize>,
+    output_profiles: BTreeMap<u8, usize>,
+
+    blocks_added: BTreeMap<u8, usize>,
+    blocks_removed: BTreeMap<u8, usize>,
+
+    warnings: usize,
+
+    /// RPUs that failed to parse, substituted in lenient mode
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    corrupt_rpus: Vec<CorruptRpu>,
+
+    elapsed_seconds: f64,
+    frames_per_second: f64,
+
+    #[serde(skip)]
+    start: Instant,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CorruptRpu {
+    /// Frame index in decoding order
+    frame: u64,
+    error: String,
+}
+
+/// Profile and block counts of a RPU before processing
+pub struct RpuSnapshot {
+    profile: u8,
+    block_counts: BTreeMap<u8, usize>,
+}
+
+impl ConversionTelemetry {
+    pub fn new(command: &'static str) -> Self {
+        Self {
+            command,
+            frames: 0,
+            input_profiles: BTreeMap::new(),
+            output_profiles: BTreeMap::new(),
+            blocks_added: BTreeMap::new(),
+            blocks_removed: BTreeMap::new(),
+            warnings: 0,
+            corrupt_rpus: Vec::new(),
+            elapsed_seconds: 0.0,
+            frames_per_second: 0.0,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn warning<T: Display>(&mut self, message: T) {
+        self.warnings += 1;
+        ipc::warn(message.to_string());
+    }
+
+    /// Frame without a valid RPU
+    pub fn record_frame(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Copied RPU NAL unit, only its header is parsed for the profile
+    pub fn record_unprocessed(&mut self, data: &[u8]) {
+        match RpuDataHeader::parse_unspec62_nalu(data) {
+            Ok(header) => self.record_profile(header.get_dovi_profile()),
+            Err(_) => self.record_frame(),
+        }
+    }
+
+    /// Frame with a corrupt RPU, replaced by a substitute
+    pub fn record_corrupt<T: Display>(&mut self, frame: u64, error: T) {
+        self.frames += 1;
+        self.warning(format!(
+            "Corrupt RPU for frame {frame}, substituted. Error: {error}"
+        ));
+
+        self.corrupt_rpus.push(CorruptRpu {
+            frame,
+            error: error.to_string(),
+        });
+    }
+
+    /// Frame with an unmodified RPU
+    pub fn record_passthrough(&mut self, rpu: &DoviRpu) {
+        self.record_profile(rpu.dovi_profile);
+    }
+
+    fn record_profile(&mut self, profile: u8) {
+        self.frames += 1;
+
+        *self.input_profiles.entry(profile).or_default() += 1;
+        *self.output_profiles.entry(profile).or_default() += 1;
+    }
+
+    pub fn record_conversion(&mut self, input: &RpuSnapshot, output: &DoviRpu) {
+        self.frames += 1;
+
+        *self.input_profiles.entry(input.profile).or_default() += 1;
+        *self.output_profiles.entry(output.dovi_profile).or_default() += 1;
+
+        let output_counts = RpuSnapshot::block_counts(output);
+
+        let levels: BTreeSet<u8> = input
+            .block_counts
+            .keys()
+            .chain(output_counts.keys())
+            .copied()
+            .collect();
+
+        for level in levels {
+            let before = input.block_counts.get(&level).copied().unwrap_or(0);
+            let after = output_counts.get(&level).copied().unwrap_or(0);
+
+            if after > before {
+                *self.blocks_added.entry(level).or_default() += after - before;
+            } else if before > after {
+                *self.blocks_removed.entry(level).or_default() += before - after;
+            }
+        }
+    }
+
+    /// Prints the summary, and writes it as JSON if a path is specified
+    pub fn finish<P: AsRef<Path>>(&mut self, json_path: Option<P>) -> Result<()> {
+        self.elapsed_seconds = self.start.elapsed().as_secs_f64();
+        self.frames_per_second = if self.elapsed_seconds > 0.0 {
+            self.frames as f64 / self.elapsed_seconds
+        } else {
+            0.0
+        };
+
+        ipc::log(self.summary());
+
+        if let Some(json_path) = json_path {
+            let writer = BufWriter::new(File::create(json_path)?);
+            serde_json::to_writer_pretty(writer, &*self)?;
+        }
+
+        Ok(())
+    }
+
+    fn summary(&self) -> String {
+        let counts_str = |counts: &BTreeMap<u8, usize>, prefix: &str| {
+            if counts.is_empty() {
+                "none".to_string()
+            } else {
+                counts
+                    .iter()
+                    .map(|(k, v)| format!("{prefix}{k}: {v}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        };
+
+        let mut summary = format!(
+            "Summary:\
+             \n  Frames: {}\
+             \n  Input profiles: {}\
+             \n  Output profiles: {}\
+             \n  Blocks added: {}\
+             \n  Blocks removed: {}\
+             \n  Warnings: {}\
+             \n  Time: {:.2}s ({:.1} fps)",
+            self.frames,
+            counts_str(&self.input_profiles, "Profile "),
+            counts_str(&self.output_profiles, "Profile "),
+            counts_str(&self.blocks_added, "L"),
+            counts_str(&self.blocks_removed, "L"),
+            self.warnings,
+            self.elapsed_seconds,
+            self.frames_per_second,
+        );
+
+        if !self.corrupt_rpus.is_empty() {
+            let frames = self.corrupt_rpus.iter().map(|c| c.frame.to_string());
+            write!(
+                summary,
+                "\n  Corrupt RPUs: {} (frames {})",
+                self.corrupt_rpus.len(),
+                frames.collect::<Vec<_>>().join(", ")
+            )
+            .ok();
+        }
+
+        summary
+    }
+}
+
+impl RpuSnapshot {
+    pub fn new(rpu: &DoviRpu) -> Self {
+        Self {
+            profile: rpu.dovi_profile,
+            block_counts: Self::block_counts(rpu),
+        }
+    }
+
+    fn block_counts(rpu: &DoviRpu) -> BTreeMap<u8, usize> {
+        let mut counts = BTreeMap::new();
+
+        if let Some(vdr_dm_data) = &rpu.vdr_dm_data {
+            // CM v2.9 and CM v4.0 blocks
+            let blocks = [1, 3]
+                .into_iter()
+                .filter_map(|level| vdr_dm_data.metadata_blocks(level))
+                .flatten();
+
+            for block in blocks {
+                *counts.entry(block.level()).or_default() += 1;
+            }
+        }
+
+        counts
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use dolby_vision::rpu::extension_metadata::blocks::{
+    ExtMetadataBlock, ExtMetadataBlockLevel1, ExtMetadataBlockLevel2, Level2Trims,
+};
+use dolby_vision::utils::pq12_to_nits;
+
+use super::frame_timestamps::frame_label;
+use super::DoviRpu;
+
+const MAX_REPORTED_SHOTS: usize = 10;
+
+/// Trimmed L1 avg difference allowed between targets before flagging an inversion, in 12-bit PQ
+const INVERSION_TOLERANCE_PQ: f64 = 1.0;
+
+/// Checks of the L2 trims against the capabilities of their target display.
+///
+/// The shot L1 metadata is normalized to the target display range, and the trims applied
+/// with the CM lift, gain and gamma math of `Level2Trims::apply`.
+/// A trim is flagged when:
+///   - the shot max is pushed above the target peak, clipping the highlights.
+///   - the shot average is pushed below zero, crushing the midtones to black.
+///   - the trimmed shot average is brighter than with the trim of a brighter target.
+pub struct TrimValidationReport {
+    pub shots: usize,
+
+    /// Flagged shots start frame, with the reason
+    pub flagged: Vec<(usize, String)>,
+}
+
+/// Kind of flagged issue, to report each once per shot and target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum TrimIssue {
+    Clip,
+    Crush,
+    Inversion,
+}
+
+impl TrimValidationReport {
+    pub fn new(rpus: &[DoviRpu]) -> Self {
+        let mut shots = 0;
+        let mut shot_start = 0;
+
+        let mut flagged = Vec::new();
+        let mut reported: HashSet<(usize, u16, TrimIssue)> = HashSet::new();
+
+        for (frame, rpu) in rpus.iter().enumerate() {
+            let Some(vdr_dm_data) = rpu.vdr_dm_data.as_ref() else {
+                continue;
+            };
+
+            if frame == 0 || vdr_dm_data.scene_refresh_flag == 1 {
+                shots += 1;
+                shot_start = frame;
+            }
+
+            let Some(ExtMetadataBlock::Level1(level1)) = vdr_dm_data.get_block(1) else {
+                continue;
+            };
+
+            let mut trims: Vec<&ExtMetadataBlockLevel2> = vdr_dm_data
+                .level_blocks_iter(2)
+                .filter_map(|block| match block {
+                    ExtMetadataBlock::Level2(level2) if level2.target_max_pq > 0 => Some(level2),
+                    _ => None,
+                })
+                .collect();
+            trims.sort_by_key(|level2| level2.target_max_pq);
+
+            let mut issues = Vec::new();
+
+            for level2 in &trims {
+                for (issue, reason) in target_issues(level1, level2) {
+                    issues.push((level2.target_max_pq, issue, reason));
+                }
+            }
+
+            for pair in trims.windows(2) {
+                let (lower, higher) = (pair[0], pair[1]);
+
+                let lower_avg = trimmed_avg_pq(level1, lower);
+                let higher_avg = trimmed_avg_pq(level1, higher);
+
+                if lower_avg > higher_avg + INVERSION_TOLERANCE_PQ {
+                    let reason = format!(
+                        "{} nits trim is brighter than the {} nits trim, avg {:.2} > {:.2} nits",
+                        target_nits(lower),
+                        target_nits(higher),
+                        pq12_to_nits(lower_avg.round() as u16),
+                        pq12_to_nits(higher_avg.round() as u16),
+                    );
+
+                    issues.push((lower.target_max_pq, TrimIssue::Inversion, reason));
+                }
+            }
+
+            for (target_max_pq, issue, reason) in issues {
+                if reported.insert((shot_start, target_max_pq, issue)) {
+                    flagged.push((shot_start, reason));
+                }
+            }
+        }
+
+        Self { shots, flagged }
+    }
+
+    pub fn to_report_string(&self) -> Result<String> {
+        let flagged_shots: HashSet<usize> = self.flagged.iter().map(|(frame, _)| *frame).collect();
+
+        let mut report = format!(
+            "L2 trim validation: {} flagged shots of {}",
+            flagged_shots.len(),
+            self.shots
+        );
+
+        for (frame, reason) in self.flagged.iter().take(MAX_REPORTED_SHOTS) {
+            write!(report, "\n    {}: {reason}", frame_label(*frame))?;
+        }
+
+        if self.flagged.len() > MAX_REPORTED_SHOTS {
+            write!(
+                report,
+                "\n    ... and {} more",
+                self.flagged.len() - MAX_REPORTED_SHOTS
+            )?;
+        }
+
+        Ok(report)
+    }
+}
+
+/// Clipping and crushing of the shot by the trim, alone
+fn target_issues(
+    level1: &ExtMetadataBlockLevel1,
+    level2: &ExtMetadataBlockLevel2,
+) -> Vec<(TrimIssue, String)> {
+    let trims = Level2Trims::from(level2);
+    let nits = target_nits(level2);
+
+    let trimmed_max = trims.apply(normalized_to_target(level1.max_pq, level2));
+    let avg = normalized_to_target(level1.avg_pq, level2);
+
+    let clip = (trimmed_max > 1.0).then(|| {
+        let reason = format!(
+            "{nits} nits trim clips the highlights, max at {:.0}% of the target peak",
+            trimmed_max * 100.0
+        );
+
+        (TrimIssue::Clip, reason)
+    });
+
+    let crush = (avg > 0.0 && trims.apply(avg) == 0.0).then(|| {
+        let reason = format!("{nits} nits trim crushes the shot average to black");
+
+        (TrimIssue::Crush, reason)
+    });
+
+    clip.into_iter().chain(crush).collect()
+}
+
+/// L1 PQ normalized to the target display range, content above the target being tone mapped to its peak
+fn normalized_to_target(pq: u16, level2: &ExtMetadataBlockLevel2) -> f64 {
+    (pq as f64 / level2.target_max_pq as f64).min(1.0)
+}
+
+/// Trimmed L1 avg, in 12-bit PQ
+fn trimmed_avg_pq(level1: &ExtMetadataBlockLevel1, level2: &ExtMetadataBlockLevel2) -> f64 {
+    let trimmed = Level2Trims::from(level2).apply(normalized_to_target(level1.avg_pq, level2));
+
+    trimmed.min(1.0) * level2.target_max_pq as f64
+}
+
+fn target_nits(level2: &ExtMetadataBlockLevel2) -> f64 {
+    pq12_to_nits(level2.target_max_pq).round()
+}
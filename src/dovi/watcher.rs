@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{bail, ensure, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::converter::Converter;
+use super::editor::Editor;
+use super::rpu_extractor::RpuExtractor;
+use super::rpu_injector::{Hdr10PlusSei, RpuInjector};
+use super::CliOptions;
+use crate::commands::{
+    ConvertArgs, ExtractRpuArgs, InjectRpuArgs, TranscodeMetadataArgs, WatchArgs,
+};
+use crate::ipc;
+
+const DEFAULT_OUTPUT_DIR: &str = "processed";
+const DEFAULT_STATE_FILE: &str = ".dovi_tool_watch.json";
+
+/// Step of the pipeline run on the watched files
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchStep {
+    // Extracts the RPU of the HEVC file
+    Extract,
+    // Converts the HEVC file with --mode, or the RPU for `.bin` inputs
+    Convert,
+    // Injects the extracted RPU, or the RPU of the same name from --rpu-dir
+    Inject,
+}
+
+pub struct Watcher {
+    dir: PathBuf,
+    patterns: Vec<String>,
+    steps: Vec<WatchStep>,
+    output_dir: PathBuf,
+    rpu_dir: Option<PathBuf>,
+    state_file: PathBuf,
+    interval: Duration,
+    discard: bool,
+    once: bool,
+}
+
+/// Files already processed, keyed by file name
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct WatchState {
+    files: BTreeMap<String, ProcessedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct ProcessedFile {
+    size: u64,
+    /// Modification time, in seconds since the Unix epoch
+    modified: u64,
+
+    outputs: Vec<PathBuf>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Files of the pipeline, the HEVC file and the RPU
+#[derive(Default)]
+struct Artifacts {
+    hevc: Option<PathBuf>,
+    rpu: Option<PathBuf>,
+    outputs: Vec<PathBuf>,
+}
+
+impl Watcher {
+    pub fn watch(args: WatchArgs, options: CliOptions) -> Result<()> {
+        let watcher = Watcher::from_args(args)?;
+
+        let mut state = watcher.read_state()?;
+        let mut pending: HashMap<PathBuf, (u64, u64)> = HashMap::new();
+        let mut failed = 0;
+
+        ipc::log(format!(
+            "Watching {} for {}, steps: {}",
+            watcher.dir.display(),
+            watcher.patterns.join(", "),
+            watcher
+                .steps
+                .iter()
+                .filter_map(|step| step.to_possible_value())
+                .map(|value| value.get_name().to_owned())
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        ));
+
+        loop {
+            for (path, name, size, modified) in watcher.scan()? {
+                let unchanged = state
+                    .files
+                    .get(&name)
+                    .map_or(false, |file| file.size == size && file.modified == modified);
+
+                if unchanged {
+                    continue;
+                }
+
+                // Still being written if the size changed since the last scan
+                let stable =
+                    pending.insert(path.clone(), (size, modified)) == Some((size, modified));
+                if !stable && !watcher.once {
+                    continue;
+                }
+
+                pending.remove(&path);
+                ipc::log(format!("\nProcessing {name}..."));
+
+                let file = match watcher.process_file(&path, options.clone()) {
+                    Ok(outputs) => {
+                        ipc::log(format!("Processed {name}"));
+
+                        ProcessedFile {
+                            size,
+                            modified,
+                            outputs,
+                            error: None,
+                        }
+                    }
+                    Err(e) => {
+                        ipc::log(format!("Failed to process {name}: {e:#}"));
+                        failed += 1;
+
+                        ProcessedFile {
+                            size,
+                            modified,
+                            outputs: Vec::new(),
+                            error: Some(format!("{e:#}")),
+                        }
+                    }
+                };
+
+                // Saved after every file, so that an interrupted watch resumes where it stopped
+                state.files.insert(name, file);
+                watcher.write_state(&state)?;
+            }
+
+            if watcher.once {
+                break;
+            }
+
+            thread::sleep(watcher.interval);
+        }
+
+        ensure!(failed == 0, "{failed} file(s) failed to process");
+
+        Ok(())
+    }
+
+    fn from_args(args: WatchArgs) -> Result<Self> {
+        let WatchArgs {
+            dir,
+            patterns,
+            steps,
+            output_dir,
+            rpu_dir,
+            state_file,
+            interval,
+            discard,
+            once,
+        } = args;
+
+        ensure!(
+            dir.is_dir(),
+            "Watched directory {} not found",
+            dir.display()
+        );
+        ensure!(
+            steps.contains(&WatchStep::Extract)
+                || !steps.contains(&WatchStep::Inject)
+                || rpu_dir.is_some(),
+            "The inject step requires --rpu-dir, or a previous extract step"
+        );
+
+        let output_dir = output_dir.unwrap_or_else(|| dir.join(DEFAULT_OUTPUT_DIR));
+        fs::create_dir_all(&output_dir)?;
+
+        // The outputs would be processed again
+        ensure!(
+            fs::canonicalize(&output_dir)? != fs::canonicalize(&dir)?,
+            "The output directory can't be the watched directory"
+        );
+
+        let state_file = state_file.unwrap_or_else(|| dir.join(DEFAULT_STATE_FILE));
+
+        Ok(Self {
+            dir,
+            patterns,
+            steps,
+            output_dir,
+            rpu_dir,
+            state_file,
+            interval: Duration::from_secs(interval),
+            discard,
+            once,
+        })
+    }
+
+    /// Files matching the patterns, with their name, size and modification time
+    fn scan(&self) -> Result<Vec<(PathBuf, String, u64, u64)>> {
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+
+            let Some(name) = entry.file_name().to_str().map(String::from) else {
+                continue;
+            };
+
+            if !metadata.is_file() || !self.patterns.iter().any(|p| wildcard_match(p, &name)) {
+                continue;
+            }
+
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs());
+
+            files.push((entry.path(), name, metadata.len(), modified));
+        }
+
+        files.sort();
+
+        Ok(files)
+    }
+
+    /// Runs the steps on the file, returns the output files
+    fn process_file(&self, path: &Path, options: CliOptions) -> Result<Vec<PathBuf>> {
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("output");
+
+        let is_rpu = path
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("bin"));
+
+        let mut artifacts = Artifacts::default();
+        if is_rpu {
+            artifacts.rpu = Some(path.to_path_buf());
+        } else {
+            artifacts.hevc = Some(path.to_path_buf());
+        }
+
+        for step in &self.steps {
+            match step {
+                WatchStep::Extract => {
+                    let Some(hevc) = artifacts.hevc.clone() else {
+                        bail!("extract: requires a HEVC file");
+                    };
+
+                    let rpu_out = self.output_dir.join(format!("{stem}.bin"));
+                    let args = ExtractRpuArgs {
+                        input: Some(hevc),
+                        input_pos: None,
+                        rpu_out: Some(rpu_out.clone()),
+                        init_segment: None,
+                        l6_from_sei: false,
+                        temporal_id: None,
+                    };
+
+                    RpuExtractor::extract_rpu(args, options.clone())?;
+                    artifacts.set_rpu(rpu_out);
+                }
+                WatchStep::Convert => {
+                    if let Some(hevc) = artifacts.hevc.clone() {
+                        let output = self.output_dir.join(format!("{stem}_converted.hevc"));
+                        let args = ConvertArgs {
+                            input: Some(hevc),
+                            input_pos: None,
+                            output: Some(output.clone()),
+                            discard: self.discard,
+                            repeat_metadata: None,
+                        };
+
+                        Converter::convert(args, options.clone())?;
+                        artifacts.set_hevc(output);
+                    } else if let Some(rpu) = artifacts.rpu.clone() {
+                        let rpu_out = self.output_dir.join(format!("{stem}_converted.bin"));
+                        let args = TranscodeMetadataArgs {
+                            input: Some(rpu),
+                            input_pos: None,
+                            json_file: None,
+                            drop_levels: Vec::new(),
+                            rpu_out: Some(rpu_out.clone()),
+                        };
+
+                        Editor::transcode(args, options.clone())?;
+                        artifacts.set_rpu(rpu_out);
+                    }
+                }
+                WatchStep::Inject => {
+                    let Some(hevc) = artifacts.hevc.clone() else {
+                        bail!("inject: requires a HEVC file");
+                    };
+
+                    let rpu_in = match (&artifacts.rpu, &self.rpu_dir) {
+                        (Some(rpu), _) => rpu.clone(),
+                        (None, Some(rpu_dir)) => rpu_dir.join(format!("{stem}.bin")),
+                        (None, None) => bail!("inject: no RPU to inject"),
+                    };
+                    ensure!(
+                        rpu_in.is_file(),
+                        "inject: RPU {} not found",
+                        rpu_in.display()
+                    );
+
+                    let output = self.output_dir.join(format!("{stem}_injected.hevc"));
+                    let args = InjectRpuArgs {
+                        input: Some(hevc),
+                        input_pos: None,
+                        rpu_in,
+                        output: Some(output.clone()),
+                        no_add_aud: false,
+                        mv_hevc: false,
+                        rpu_start_code: None,
+                        hdr10plus: Hdr10PlusSei::Keep,
+                        l6_from_sei: false,
+                        truncate_rpu: false,
+                        align_end: false,
+                        resume: false,
+                        checkpoint_interval: None,
+                    };
+
+                    RpuInjector::inject_rpu(args, options.clone())?;
+                    artifacts.set_hevc(output);
+                }
+            }
+        }
+
+        Ok(artifacts.outputs)
+    }
+
+    fn read_state(&self) -> Result<WatchState> {
+        if !self.state_file.exists() {
+            return Ok(WatchState::default());
+        }
+
+        let reader = BufReader::new(File::open(&self.state_file)?);
+
+        match serde_json::from_reader(reader) {
+            Ok(state) => Ok(state),
+            Err(e) => bail!(
+                "Invalid watch state file {}: {e}",
+                self.state_file.display()
+            ),
+        }
+    }
+
+    /// Written to a temporary file first, the state is never left truncated
+    fn write_state(&self, state: &WatchState) -> Result<()> {
+        let temp_path = self.state_file.with_extension("json.tmp");
+
+        serde_json::to_writer_pretty(File::create(&temp_path)?, state)?;
+        fs::rename(&temp_path, &self.state_file)?;
+
+        Ok(())
+    }
+}
+
+impl Artifacts {
+    fn set_hevc(&mut self, path: PathBuf) {
+        self.outputs.push(path.clone());
+        self.hevc = Some(path);
+    }
+
+    fn set_rpu(&mut self, path: PathBuf) {
+        self.outputs.push(path.clone());
+        self.rpu = Some(path);
+    }
+}
+
+/// Matches a file name against a pattern with `*` and `?` wildcards, ignoring the ASCII case
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_ascii_lowercase().chars().collect();
+    let name: Vec<char> = name.to_ascii_lowercase().chars().collect();
+
+    // Position after the last `*`, and the name position it was matched at
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Backtrack, the `*` takes one more character
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
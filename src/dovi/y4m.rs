@@ -0,0 +1,444 @@
+//! YUV4MPEG2 (y4m) video measurement, for generating the L1 metadata without another tool.
+//!
+//! The frames are read one at a time, only their statistics are kept.
+//! The pixels are converted to R'G'B' with the BT.2020 non-constant luminance matrix,
+//! and the L1 statistics are computed on the max of the components, in 12-bit PQ.
+//! HLG input is converted to display light for a 1000 nits display, as specified by BT.2100.
+//!
+//! Scene cuts are detected from the change of the brightness histogram between frames.
+
+use std::fs::File;
+use std::io::{stdin, BufRead, BufReader, Read};
+use std::path::Path;
+
+use anyhow::{bail, ensure, Result};
+
+use dolby_vision::utils::{nits_to_pq, pq_to_nits};
+
+use super::generator::Y4mTransfer;
+
+const Y4M_MAGIC: &[u8] = b"YUV4MPEG2";
+const MAX_HEADER_LEN: u64 = 1024;
+
+/// Bins of the 12-bit PQ histogram used for the scene detection
+const HISTOGRAM_BINS: usize = 64;
+
+/// Nominal peak of the HLG display, and its BT.2100 system gamma
+const HLG_PEAK_NITS: f32 = 1000.0;
+const HLG_SYSTEM_GAMMA: f32 = 1.2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Y4mHeader {
+    pub width: usize,
+    pub height: usize,
+    /// Horizontal and vertical chroma subsampling shifts, none for monochrome
+    pub chroma_shift: Option<(u32, u32)>,
+    pub bit_depth: u8,
+    pub full_range: bool,
+    pub fps: Option<f64>,
+    /// Extra alpha plane, ignored
+    alpha: bool,
+}
+
+/// Reads the frames of a YUV4MPEG2 stream
+pub struct Y4mReader<R: BufRead> {
+    reader: R,
+    pub header: Y4mHeader,
+    frame: Vec<u8>,
+}
+
+/// Statistics of a frame, on the max of the R'G'B' components
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Y4mFrameStats {
+    /// 12-bit PQ
+    pub min_pq: u16,
+    pub max_pq: u16,
+    pub avg_pq: u16,
+
+    /// Average of the linear light, for the MaxFALL
+    pub avg_nits: f64,
+}
+
+pub struct Y4mMeasurements {
+    pub fps: Option<f64>,
+    pub frames: Vec<Y4mFrameStats>,
+    /// First frame of every scene, starting with 0
+    pub scene_starts: Vec<usize>,
+}
+
+impl Y4mHeader {
+    fn parse(line: &str) -> Result<Self> {
+        let mut params = line.split_ascii_whitespace();
+
+        ensure!(
+            params.next().map(str::as_bytes) == Some(Y4M_MAGIC),
+            "Not a YUV4MPEG2 stream"
+        );
+
+        let mut header = Self {
+            width: 0,
+            height: 0,
+            // 4:2:0 when not specified
+            chroma_shift: Some((1, 1)),
+            bit_depth: 8,
+            full_range: false,
+            fps: None,
+            alpha: false,
+        };
+
+        for param in params {
+            let Some(tag) = param.chars().next() else {
+                continue;
+            };
+            let value = &param[tag.len_utf8()..];
+
+            match tag {
+                'W' => header.width = value.parse()?,
+                'H' => header.height = value.parse()?,
+                'F' => {
+                    let fps = value
+                        .split_once(':')
+                        .and_then(|(num, den)| {
+                            Some((num.parse::<f64>().ok()?, den.parse::<f64>().ok()?))
+                        })
+                        .filter(|(num, den)| *num > 0.0 && *den > 0.0)
+                        .map(|(num, den)| num / den);
+
+                    header.fps = fps;
+                }
+                'C' => header.parse_colorspace(value)?,
+                'X' => {
+                    if let Some(range) = value.strip_prefix("COLORRANGE=") {
+                        header.full_range = range.eq_ignore_ascii_case("full");
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        ensure!(
+            header.width > 0 && header.height > 0,
+            "Invalid y4m frame size {}x{}",
+            header.width,
+            header.height
+        );
+
+        Ok(header)
+    }
+
+    /// `420jpeg`, `422`, `444alpha`, `420p10`, `mono`, `mono16`...
+    fn parse_colorspace(&mut self, colorspace: &str) -> Result<()> {
+        let (chroma_shift, rest) = if let Some(rest) = colorspace.strip_prefix("mono") {
+            (None, rest)
+        } else if let Some(rest) = colorspace.strip_prefix("420") {
+            (Some((1, 1)), rest)
+        } else if let Some(rest) = colorspace.strip_prefix("422") {
+            (Some((1, 0)), rest)
+        } else if let Some(rest) = colorspace.strip_prefix("444") {
+            (Some((0, 0)), rest)
+        } else {
+            bail!("Unsupported y4m colorspace {colorspace}");
+        };
+
+        let (bit_depth, alpha) = match rest {
+            "" | "jpeg" | "paldv" | "mpeg2" => (8, false),
+            "alpha" if chroma_shift == Some((0, 0)) => (8, true),
+            _ => match rest.strip_prefix('p').unwrap_or(rest).parse::<u8>() {
+                Ok(bit_depth) if (8..=16).contains(&bit_depth) => (bit_depth, false),
+                _ => bail!("Unsupported y4m colorspace {colorspace}"),
+            },
+        };
+
+        self.chroma_shift = chroma_shift;
+        self.bit_depth = bit_depth;
+        self.alpha = alpha;
+
+        Ok(())
+    }
+
+    fn bytes_per_sample(&self) -> usize {
+        if self.bit_depth > 8 {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn chroma_size(&self) -> (usize, usize) {
+        match self.chroma_shift {
+            Some((shift_x, shift_y)) => (
+                (self.width + (1 << shift_x) - 1) >> shift_x,
+                (self.height + (1 << shift_y) - 1) >> shift_y,
+            ),
+            None => (0, 0),
+        }
+    }
+
+    fn frame_size(&self) -> usize {
+        let luma = self.width * self.height;
+        let (chroma_width, chroma_height) = self.chroma_size();
+        let alpha = if self.alpha { luma } else { 0 };
+
+        (luma + 2 * chroma_width * chroma_height + alpha) * self.bytes_per_sample()
+    }
+}
+
+impl<R: BufRead> Y4mReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut line = Vec::new();
+        reader
+            .by_ref()
+            .take(MAX_HEADER_LEN)
+            .read_until(b'\n', &mut line)?;
+
+        ensure!(
+            line.starts_with(Y4M_MAGIC) && line.ends_with(b"\n"),
+            "Not a YUV4MPEG2 stream"
+        );
+
+        let header = Y4mHeader::parse(&String::from_utf8_lossy(&line))?;
+        let frame = vec![0; header.frame_size()];
+
+        Ok(Self {
+            reader,
+            header,
+            frame,
+        })
+    }
+
+    /// Raw samples of the next frame, none at the end of the stream
+    pub fn next_frame(&mut self) -> Result<Option<&[u8]>> {
+        let mut line = Vec::new();
+        self.reader
+            .by_ref()
+            .take(MAX_HEADER_LEN)
+            .read_until(b'\n', &mut line)?;
+
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        ensure!(
+            line.starts_with(b"FRAME") && line.ends_with(b"\n"),
+            "Invalid y4m frame header"
+        );
+
+        if let Err(e) = self.reader.read_exact(&mut self.frame) {
+            bail!("Truncated y4m frame: {e}");
+        }
+
+        Ok(Some(&self.frame))
+    }
+}
+
+/// Measures every frame of the y4m file, or stdin if the path is `-`.
+///
+/// A frame is a scene cut when the share of the pixels moving to another brightness
+/// histogram bin is over `scene_threshold`.
+pub fn measure_y4m(
+    path: &Path,
+    transfer: Y4mTransfer,
+    scene_threshold: f64,
+) -> Result<Y4mMeasurements> {
+    ensure!(
+        (0.0..=1.0).contains(&scene_threshold),
+        "Invalid scene threshold {scene_threshold}, must be between 0 and 1"
+    );
+
+    let reader: Box<dyn BufRead> = if path == Path::new("-") {
+        Box::new(stdin().lock())
+    } else {
+        Box::new(BufReader::with_capacity(100_000, File::open(path)?))
+    };
+
+    let mut reader = Y4mReader::new(reader)?;
+    let header = reader.header.clone();
+    let measurer = FrameMeasurer::new(&header, transfer);
+
+    let mut frames = Vec::new();
+    let mut scene_starts = Vec::new();
+    let mut previous_histogram: Option<Vec<u32>> = None;
+
+    while let Some(frame) = reader.next_frame()? {
+        let (stats, histogram) = measurer.measure(frame);
+
+        let is_scene_cut = match &previous_histogram {
+            Some(previous) => histogram_change(previous, &histogram) > scene_threshold,
+            None => true,
+        };
+
+        if is_scene_cut {
+            scene_starts.push(frames.len());
+        }
+
+        frames.push(stats);
+        previous_histogram = Some(histogram);
+    }
+
+    ensure!(!frames.is_empty(), "The y4m stream has no frames");
+
+    Ok(Y4mMeasurements {
+        fps: header.fps,
+        frames,
+        scene_starts,
+    })
+}
+
+/// Share of the pixels in a different histogram bin, from 0 to 1
+fn histogram_change(previous: &[u32], current: &[u32]) -> f64 {
+    let total: u64 = current.iter().map(|&count| count as u64).sum();
+    let moved: u64 = previous
+        .iter()
+        .zip(current)
+        .map(|(&a, &b)| (a as i64 - b as i64).unsigned_abs())
+        .sum();
+
+    moved as f64 / (2 * total.max(1)) as f64
+}
+
+/// Converts the samples of a frame to the 12-bit PQ of the max component
+struct FrameMeasurer<'a> {
+    header: &'a Y4mHeader,
+    transfer: Y4mTransfer,
+
+    /// Sample offset and scale, for luma and chroma
+    luma_range: (f32, f32),
+    chroma_range: (f32, f32),
+
+    /// Nits of the 12-bit PQ values
+    pq_nits: Vec<f64>,
+    /// HLG scene linear light of the 12-bit signal values
+    hlg_linear: Vec<f32>,
+}
+
+impl<'a> FrameMeasurer<'a> {
+    fn new(header: &'a Y4mHeader, transfer: Y4mTransfer) -> Self {
+        let max_code = ((1_u32 << header.bit_depth) - 1) as f32;
+        let scale = (1_u32 << (header.bit_depth - 8)) as f32;
+
+        let (luma_range, chroma_range) = if header.full_range {
+            ((0.0, max_code), (128.0 * scale, max_code))
+        } else {
+            (
+                (16.0 * scale, 219.0 * scale),
+                (128.0 * scale, 224.0 * scale),
+            )
+        };
+
+        let pq_nits = (0..4096).map(|pq| pq_to_nits(pq as f64 / 4095.0)).collect();
+
+        let hlg_linear = match transfer {
+            Y4mTransfer::Pq => Vec::new(),
+            Y4mTransfer::Hlg => (0..4096)
+                .map(|v| hlg_inverse_oetf(v as f64 / 4095.0) as f32)
+                .collect(),
+        };
+
+        Self {
+            header,
+            transfer,
+            luma_range,
+            chroma_range,
+            pq_nits,
+            hlg_linear,
+        }
+    }
+
+    fn measure(&self, frame: &[u8]) -> (Y4mFrameStats, Vec<u32>) {
+        let header = self.header;
+        let (chroma_width, chroma_height) = header.chroma_size();
+
+        let luma_len = header.width * header.height;
+        let chroma_len = chroma_width * chroma_height;
+
+        let sample = |index: usize| -> f32 {
+            if header.bit_depth > 8 {
+                u16::from_le_bytes([frame[2 * index], frame[2 * index + 1]]) as f32
+            } else {
+                frame[index] as f32
+            }
+        };
+
+        let mut min_pq = u16::MAX;
+        let mut max_pq = 0;
+        let mut pq_sum = 0_u64;
+        let mut nits_sum = 0.0;
+        let mut histogram = vec![0_u32; HISTOGRAM_BINS];
+
+        for y in 0..header.height {
+            for x in 0..header.width {
+                let luma = (sample(y * header.width + x) - self.luma_range.0) / self.luma_range.1;
+
+                let rgb = match header.chroma_shift {
+                    Some((shift_x, shift_y)) => {
+                        let chroma_index = (y >> shift_y) * chroma_width + (x >> shift_x);
+                        let cb = (sample(luma_len + chroma_index) - self.chroma_range.0)
+                            / self.chroma_range.1;
+                        let cr = (sample(luma_len + chroma_len + chroma_index)
+                            - self.chroma_range.0)
+                            / self.chroma_range.1;
+
+                        // BT.2020 non-constant luminance
+                        [
+                            luma + 1.4746 * cr,
+                            luma - 0.164_553 * cb - 0.571_353 * cr,
+                            luma + 1.8814 * cb,
+                        ]
+                    }
+                    None => [luma; 3],
+                };
+
+                let pq = self.max_component_pq(rgb);
+
+                min_pq = min_pq.min(pq);
+                max_pq = max_pq.max(pq);
+                pq_sum += pq as u64;
+                nits_sum += self.pq_nits[pq as usize];
+                histogram[pq as usize * HISTOGRAM_BINS / 4096] += 1;
+            }
+        }
+
+        let stats = Y4mFrameStats {
+            min_pq,
+            max_pq,
+            avg_pq: (pq_sum as f64 / luma_len as f64).round() as u16,
+            avg_nits: nits_sum / luma_len as f64,
+        };
+
+        (stats, histogram)
+    }
+
+    /// 12-bit PQ of the brightest component, in display light
+    fn max_component_pq(&self, rgb: [f32; 3]) -> u16 {
+        let to_12bit = |v: f32| (v.clamp(0.0, 1.0) * 4095.0).round() as u16;
+
+        match self.transfer {
+            Y4mTransfer::Pq => to_12bit(rgb[0].max(rgb[1]).max(rgb[2])),
+            Y4mTransfer::Hlg => {
+                let [r, g, b] = rgb.map(|v| self.hlg_linear[to_12bit(v) as usize]);
+
+                // BT.2100 OOTF, the system gamma applies to the scene luminance
+                let luminance = 0.2627 * r + 0.6780 * g + 0.0593 * b;
+                let gain = HLG_PEAK_NITS * luminance.powf(HLG_SYSTEM_GAMMA - 1.0);
+                let nits = gain * r.max(g).max(b);
+
+                (nits_to_pq(nits as f64) * 4095.0)
+                    .round()
+                    .clamp(0.0, 4095.0) as u16
+            }
+        }
+    }
+}
+
+/// BT.2100 HLG inverse OETF, normalized scene linear light
+fn hlg_inverse_oetf(signal: f64) -> f64 {
+    const A: f64 = 0.178_832_77;
+    const B: f64 = 0.284_668_92;
+    const C: f64 = 0.559_910_73;
+
+    if signal <= 0.5 {
+        signal * signal / 3.0
+    } else {
+        (((signal - C) / A).exp() + B) / 12.0
+    }
+}
@@ -0,0 +1,179 @@
+use std::cell::Cell;
+use std::io::{stdout, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use indicatif::ProgressBar;
+use serde_json::{json, Value};
+
+/// Interval between the progress events
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+static IPC_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Set once the last event is sent
+static IPC_FINISHED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// No output at all on this thread, for library callers
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Machine readable output, for GUI frontends embedding the binary
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcMode {
+    /// JSON-RPC 2.0 notifications on stdout, one per line
+    JsonRpc,
+}
+
+pub fn enable(mode: IpcMode) {
+    match mode {
+        IpcMode::JsonRpc => IPC_ENABLED.store(true, Ordering::Relaxed),
+    }
+}
+
+pub fn enabled() -> bool {
+    IPC_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Runs `f` without any log, warning or progress output on the current thread
+pub fn quiet<T, F: FnOnce() -> T>(f: F) -> T {
+    let previous = QUIET.with(|quiet| quiet.replace(true));
+    let res = f();
+    QUIET.with(|quiet| quiet.set(previous));
+
+    res
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.with(Cell::get)
+}
+
+/// Human readable output line.
+///
+/// In IPC mode, the line is sent as a `log` event instead, so that stdout only contains events.
+/// Empty lines are only printed to the terminal.
+pub fn log<S: AsRef<str>>(message: S) {
+    let message = message.as_ref();
+
+    if is_quiet() {
+        return;
+    }
+
+    if enabled() {
+        emit_message("log", message);
+    } else {
+        println!("{message}");
+    }
+}
+
+/// Human readable output without a newline, completed by a following `log`
+pub fn print<S: AsRef<str>>(message: S) {
+    let message = message.as_ref();
+
+    if is_quiet() {
+        return;
+    }
+
+    if enabled() {
+        emit_message("log", message);
+    } else {
+        print!("{message}");
+        stdout().flush().ok();
+    }
+}
+
+/// Warning line, prefixed with `Warning: `, or sent as a `warning` event in IPC mode
+pub fn warn<S: AsRef<str>>(message: S) {
+    let message = message.as_ref();
+
+    if is_quiet() {
+        return;
+    }
+
+    if enabled() {
+        emit_message("warning", message);
+    } else {
+        println!("Warning: {message}");
+    }
+}
+
+fn emit_message(method: &str, message: &str) {
+    let message = message.trim();
+
+    if !message.is_empty() {
+        emit(method, json!({ "message": message }));
+    }
+}
+
+/// Sends `progress` events for the progress bar, until it is finished
+pub fn watch_progress(progress_bar: &ProgressBar) {
+    if !enabled() {
+        return;
+    }
+
+    let progress_bar = progress_bar.clone();
+
+    thread::spawn(move || {
+        let mut last_position = None;
+
+        while !progress_bar.is_finished() {
+            let position = progress_bar.position();
+
+            if last_position != Some(position) {
+                last_position = Some(position);
+
+                let total = progress_bar.length().filter(|total| *total > 0);
+                let percent =
+                    total.map(|total| (position as f64 * 100.0 / total as f64).min(100.0));
+
+                emit(
+                    "progress",
+                    json!({ "position": position, "total": total, "percent": percent }),
+                );
+            }
+
+            thread::sleep(PROGRESS_INTERVAL);
+        }
+    });
+}
+
+/// Sends the `completed` or `error` event of the command
+pub fn finish(result: &Result<()>) {
+    if !enabled() {
+        return;
+    }
+
+    match result {
+        Ok(()) => write_event("completed", json!({}), true),
+        Err(e) => write_event("error", json!({ "message": format!("{e:#}") }), true),
+    }
+}
+
+fn emit(method: &str, params: Value) {
+    write_event(method, params, false);
+}
+
+fn write_event(method: &str, params: Value, last: bool) {
+    let event = json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params,
+    });
+
+    let mut out = stdout().lock();
+
+    // A progress thread can still be running after the last event
+    if IPC_FINISHED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    writeln!(out, "{event}").ok();
+    out.flush().ok();
+
+    if last {
+        IPC_FINISHED.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,15 @@
+//! Library target of `dovi_tool`, for embedding the tool without spawning processes.
+//!
+//! [`ops`] has one-call functions for the common pipelines,
+//! and [`BitstreamFormat`] implementations can be registered in a [`FormatRegistry`]
+//! to extract RPUs from other containers.
+
+#[cfg(test)]
+mod tests;
+
+pub mod commands;
+pub mod dovi;
+pub mod ipc;
+
+pub use dovi::bitstream_format::{BitstreamFormat, FormatRegistry, RpuSource};
+pub use dovi::ops;
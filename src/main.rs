@@ -1,35 +1,44 @@
 use std::path::PathBuf;
 
 use anyhow::Result;
-use clap::{Parser, ValueHint};
+use clap::{CommandFactory, Parser, ValueHint};
 
-#[cfg(test)]
-mod tests;
+use dolby_vision::rpu::dovi_rpu::RpuParseOpts;
 
-mod commands;
-use commands::{Command, ConversionModeCli};
+mod cli_metadata;
 
-mod dovi;
-use dovi::{
+use dovi_tool::commands::{Command, ConversionModeCli};
+use dovi_tool::dovi::{
     converter::Converter,
     demuxer::Demuxer,
     editor::{EditConfig, Editor},
     exporter::Exporter,
+    frame_timestamps::{set_timestamps, FrameTimestamps},
     generator::Generator,
+    injection_compare::InjectionComparer,
     muxer::Muxer,
     plotter::Plotter,
+    rpu_bitrate::RpuBitrate,
     rpu_extractor::RpuExtractor,
+    rpu_histogram::RpuHistogram,
     rpu_info::RpuInfo,
     rpu_injector::RpuInjector,
+    rpu_patch::{RpuDiff, RpuPatcher},
+    rpu_reorder::RpuReorderer,
+    rpu_verifier::RpuVerifier,
+    watcher::Watcher,
     CliOptions, WriteStartCodePreset,
 };
+use dovi_tool::ipc;
 
 #[derive(Parser, Debug)]
 #[command(
     name = env!("CARGO_PKG_NAME"),
     about = "CLI tool combining multiple utilities for working with Dolby Vision",
     author = "quietvoid",
-    version = option_env!("VERGEN_GIT_DESCRIBE").unwrap_or(env!("CARGO_PKG_VERSION"))
+    version = option_env!("VERGEN_GIT_DESCRIBE").unwrap_or(env!("CARGO_PKG_VERSION")),
+    // The subcommand is only optional for `--dump-cli-json`
+    override_usage = "dovi_tool [OPTIONS] <COMMAND>"
 )]
 struct Opt {
     #[arg(
@@ -43,7 +52,8 @@ struct Opt {
                      Mode 2: Converts the RPU to be profile 8.1 compatible. Removes mapping\n  \
                      Mode 3: Converts profile 5 to 8.1\n  \
                      Mode 4: Converts to profile 8.4\n  \
-                     Mode 5: Converts to profile 8.1, preserving luma/chroma mapping",
+                     Mode 5: Converts to profile 8.1, preserving luma/chroma mapping\n  \
+                     Mode auto: convert only. Picks the profile 8.1 conversion from the input profile, EL type and CM version",
         value_enum
     )]
     mode: Option<ConversionModeCli>,
@@ -73,19 +83,90 @@ struct Opt {
     )]
     start_code: WriteStartCodePreset,
 
+    #[arg(
+        long,
+        help = "Writes the processing summary (frames, profiles, metadata blocks changed) to a JSON file",
+        value_hint = ValueHint::FilePath
+    )]
+    summary_json: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Substitutes the RPUs that fail to parse with the previous valid RPU instead of aborting, and reports them. Also allows injecting with mismatched frame counts"
+    )]
+    lenient: bool,
+
+    #[arg(
+        long,
+        help = "Corrects the artifacts of the writer bugs of very old dovi_tool versions when parsing RPUs (misordered metadata blocks, wrong num_ext_blocks)"
+    )]
+    compat_old: bool,
+
+    #[arg(
+        long,
+        help = "Processes and validates everything without writing any output file, then reports the changes (editor, convert and inject-rpu only)"
+    )]
+    dry_run: bool,
+
+    #[arg(
+        long,
+        value_name = "FPS|FILE",
+        help = "Adds timecodes to the frame numbers of the reports (info, verify and diff). Either a frame rate like 23.976 or 24000/1001, or an mkvextract v2 timestamps file"
+    )]
+    timestamp_log: Option<String>,
+
+    #[arg(
+        value_enum,
+        long,
+        value_name = "MODE",
+        help = "Reports the progress, warnings and completion as JSON-RPC notifications on stdout, one per line, for GUI frontends"
+    )]
+    ipc: Option<ipc::IpcMode>,
+
+    #[arg(
+        long,
+        exclusive = true,
+        help = "Prints a JSON description of all the commands and their arguments"
+    )]
+    dump_cli_json: bool,
+
     #[command(subcommand)]
-    cmd: Command,
+    cmd: Option<Command>,
 }
 
 fn main() -> Result<()> {
     let opt = Opt::parse();
 
+    if opt.dump_cli_json {
+        return cli_metadata::print_cli_json(&mut Opt::command());
+    }
+
+    if let Some(timestamp_log) = &opt.timestamp_log {
+        set_timestamps(Some(FrameTimestamps::parse(timestamp_log)?));
+    }
+
+    if let Some(mode) = opt.ipc {
+        ipc::enable(mode);
+    }
+
+    let Some(cmd) = opt.cmd else {
+        Opt::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "A subcommand is required",
+            )
+            .exit();
+    };
+
     let edit_config = opt
         .edit_config
         .as_ref()
         .map(EditConfig::from_path)
         .and_then(Result::ok);
 
+    let mut parse_opts = RpuParseOpts::default();
+    parse_opts.compat_old = opt.compat_old;
+
     let mut cli_options = CliOptions {
         mode: opt.mode,
         crop: opt.crop,
@@ -93,23 +174,58 @@ fn main() -> Result<()> {
         drop_hdr10plus: opt.drop_hdr10plus,
         edit_config,
         start_code: opt.start_code,
+        repeat_metadata: None,
+        summary_json: opt.summary_json,
+        lenient: opt.lenient,
+        l6_from_sei: false,
+        temporal_id: None,
+        dry_run: opt.dry_run,
+        parse_opts,
     };
 
+    if matches!(cli_options.mode, Some(ConversionModeCli::Auto))
+        && !matches!(cmd, Command::Convert(_))
+    {
+        Opt::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--mode auto is only supported by convert",
+            )
+            .exit();
+    }
+
     // Set mode 0 by default if cropping, otherwise it has no effect
     if cli_options.mode.is_none() && cli_options.crop {
         cli_options.mode = Some(ConversionModeCli::Lossless);
     }
 
-    match opt.cmd {
+    let result = match cmd {
+        Command::ApplyPatch(args) => RpuPatcher::apply_patch(args, parse_opts),
+        Command::Bitrate(args) => RpuBitrate::bitrate(args),
+        Command::CompareInjected(args) => InjectionComparer::compare(args),
+        Command::Completions(args) => {
+            cli_metadata::print_completions(&mut Opt::command(), args.shell);
+            Ok(())
+        }
+        Command::Diff(args) => RpuDiff::diff(args, parse_opts),
         Command::Demux(args) => Demuxer::demux(args, cli_options),
-        Command::Editor(args) => Editor::edit(args),
+        Command::Editor(args) => Editor::edit(args, cli_options),
         Command::Convert(args) => Converter::convert(args, cli_options),
         Command::ExtractRpu(args) => RpuExtractor::extract_rpu(args, cli_options),
         Command::InjectRpu(args) => RpuInjector::inject_rpu(args, cli_options),
-        Command::Info(args) => RpuInfo::info(args),
-        Command::Generate(args) => Generator::generate(args),
-        Command::Export(args) => Exporter::export(args),
+        Command::Info(args) => RpuInfo::info(args, parse_opts),
+        Command::Generate(args) => Generator::generate(args, cli_options),
+        Command::Histogram(args) => RpuHistogram::histogram(args, parse_opts),
+        Command::Export(args) => Exporter::export(args, parse_opts),
         Command::Mux(args) => Muxer::mux_el(args, cli_options),
-        Command::Plot(args) => Plotter::plot(args),
-    }
+        Command::Plot(args) => Plotter::plot(args, parse_opts),
+        Command::Reorder(args) => RpuReorderer::reorder(args),
+        Command::TranscodeMetadata(args) => Editor::transcode(args, cli_options),
+        Command::Verify(args) => RpuVerifier::verify(args),
+        Command::Watch(args) => Watcher::watch(args, cli_options),
+    };
+
+    ipc::finish(&result);
+
+    result
 }
@@ -1,2 +1,3 @@
 mod av1_rpu;
+mod ops;
 mod rpu;
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::commands::ArgHdr10PlusPeakBrightnessSource;
+use crate::dovi::ops;
+
+#[test]
+fn transplant_trims() -> Result<()> {
+    let temp = assert_fs::TempDir::new()?;
+    let output_rpu = temp.path().join("RPU.bin");
+
+    ops::transplant_trims(
+        Path::new("assets/tests/fel_orig.bin"),
+        Path::new("assets/tests/cmv40_full_rpu.bin"),
+        &output_rpu,
+        &vec![1, 4, 6],
+    )?;
+
+    // Same as the editor with `source_rpu`
+    let expected = fs::read("assets/tests/source_rpu_replaced_fel_orig.bin")?;
+    assert_eq!(fs::read(&output_rpu)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn generate_from_hdr10plus() -> Result<()> {
+    let temp = assert_fs::TempDir::new()?;
+    let output_rpu = temp.path().join("RPU.bin");
+
+    let config = ops::generate_from_hdr10plus(
+        Path::new("assets/generator_examples/no_duration.json"),
+        Path::new("assets/tests/hdr10plus_metadata.json"),
+        &output_rpu,
+        None,
+        ArgHdr10PlusPeakBrightnessSource::Histogram,
+    )?;
+
+    assert_eq!(config.length, 9);
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(&output_rpu)?;
+    assert_eq!(rpus.len(), 9);
+
+    Ok(())
+}
+
+#[test]
+fn transfer_rpu() -> Result<()> {
+    let temp = assert_fs::TempDir::new()?;
+    let output = temp.path().join("injected.hevc");
+
+    ops::transfer_rpu(
+        Path::new("assets/hevc_tests/regular.hevc"),
+        Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc"),
+        &output,
+        None,
+    )?;
+
+    // Same as injecting the extracted RPU
+    let expected = fs::read("assets/hevc_tests/regular_start_code_4.hevc")?;
+    assert_eq!(fs::read(&output)?, expected);
+
+    Ok(())
+}
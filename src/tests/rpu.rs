@@ -6,7 +6,8 @@ use anyhow::Result;
 use dolby_vision::rpu::dovi_rpu::DoviRpu;
 use dolby_vision::rpu::extension_metadata::blocks::{ExtMetadataBlock, ExtMetadataBlockLevel6};
 use dolby_vision::rpu::extension_metadata::{ColorPrimaries, MasteringDisplayPrimaries};
-use dolby_vision::rpu::generate::GenerateConfig;
+use dolby_vision::rpu::generate::{GenerateConfig, VideoShot};
+use dolby_vision::rpu::rpu_data_header::RpuDataHeader;
 use dolby_vision::rpu::rpu_data_nlq::DoviELType;
 use dolby_vision::rpu::ConversionMode;
 use hevc_parser::hevc::{NALUnit, NAL_UNSPEC62};
@@ -400,6 +401,28 @@ fn st2094_10_level3_invalid() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn header_only_profile() -> Result<()> {
+    for (file, profile) in [("profile5.bin", 5), ("profile8.bin", 8)] {
+        let (original_data, dovi_rpu) = _parse_file(PathBuf::from("./assets/tests").join(file))?;
+        let header = RpuDataHeader::parse_unspec62_nalu(&original_data)?;
+
+        assert_eq!(header.get_dovi_profile(), profile);
+        assert_eq!(header.get_dovi_profile(), dovi_rpu.dovi_profile);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn truncated_rpu_is_err() {
+    // Only zeroes after the header start
+    let data = [&[25, 8, 9][..], &[0; 22]].concat();
+
+    assert!(DoviRpu::parse_unspec62_nalu(&data).is_err());
+    assert!(DoviRpu::parse_rpu(&data).is_err());
+}
+
 #[test]
 fn cmv40_full_rpu() -> Result<()> {
     use dolby_vision::rpu::extension_metadata::blocks::*;
@@ -505,6 +528,71 @@ fn profile8_unordered_l8_blocks() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn custom_block_level_roundtrip() -> Result<()> {
+    use std::sync::Arc;
+
+    use dolby_vision::rpu::extension_metadata::blocks::*;
+    use dolby_vision::rpu::extension_metadata::{DmData, WithExtMetadataBlocks};
+
+    struct PrivateBlock;
+
+    impl CustomExtBlockHandler for PrivateBlock {
+        fn parse(&self, block: &ReservedExtMetadataBlock) -> Result<()> {
+            anyhow::ensure!(block.ext_block_length == 4, "Invalid private block");
+            Ok(())
+        }
+    }
+
+    let (_, mut dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/unordered_l8_blocks.bin"))?;
+
+    let payload = [0xDE, 0xAD, 0xBE, 0xEF];
+    let custom_block = ExtMetadataBlock::Reserved(ReservedExtMetadataBlock::new(200, &payload));
+
+    let mut handlers = CustomBlockHandlers::default();
+    handlers.register(200, Arc::new(PrivateBlock))?;
+    assert!(handlers.register(8, Arc::new(PrivateBlock)).is_err());
+
+    let vdr_dm_data = dovi_rpu.vdr_dm_data.as_mut().unwrap();
+    if let Some(DmData::V40(cmv40)) = vdr_dm_data.cmv40_metadata.as_mut() {
+        // Defined level
+        let level1 = ExtMetadataBlock::Reserved(ReservedExtMetadataBlock::new(1, &payload));
+        assert!(cmv40.add_block(level1).is_err());
+
+        cmv40.add_block(custom_block)?;
+    }
+
+    // Custom level without a handler
+    assert!(dovi_rpu
+        .write_hevc_unspec62_nalu_with_handlers(&CustomBlockHandlers::default())
+        .is_err());
+
+    let parsed_data = dovi_rpu.write_hevc_unspec62_nalu_with_handlers(&handlers)?;
+    assert!(DoviRpu::parse_unspec62_nalu(&parsed_data).is_err());
+
+    let reparsed_rpu = DoviRpu::parse_unspec62_nalu_with_handlers(&parsed_data, &handlers)?;
+
+    let reparsed_dm_data = reparsed_rpu.vdr_dm_data.as_ref().unwrap();
+    let custom_block = reparsed_dm_data
+        .cmv40_metadata
+        .as_ref()
+        .and_then(|dm_data| match dm_data {
+            DmData::V40(cmv40) => cmv40.blocks_ref().iter().find(|b| b.level() == 200),
+            _ => None,
+        });
+
+    if let Some(ExtMetadataBlock::Reserved(block)) = custom_block {
+        assert_eq!(block.payload(), payload);
+    } else {
+        panic!("Missing custom block");
+    }
+
+    assert!(handlers.unregister(200));
+    assert!(DoviRpu::parse_unspec62_nalu_with_handlers(&parsed_data, &handlers).is_err());
+
+    Ok(())
+}
+
 #[test]
 fn empty_dmv1_blocks() -> Result<()> {
     let (original_data, mut dovi_rpu) =
@@ -633,6 +721,112 @@ fn generate_default_cmv40() -> Result<()> {
     Ok(())
 }
 
+#[cfg(target_os = "linux")]
+#[test]
+fn generate_static_metadata() -> Result<()> {
+    let args = GenerateArgs {
+        length: Some(24),
+        mdl_max: Some(4000),
+        mdl_min: Some(0.005),
+        rpu_out: Some(PathBuf::from("/dev/null")),
+        ..Default::default()
+    };
+
+    let mut generator = Generator::from_args(args)?;
+    generator.execute()?;
+
+    let config = generator.config.unwrap();
+
+    let rpus = config.generate_rpu_list()?;
+    assert_eq!(rpus.len(), 24);
+
+    let vdr_dm_data = rpus[0].vdr_dm_data.as_ref().unwrap();
+    assert_eq!(vdr_dm_data.source_min_pq, 62);
+    assert_eq!(vdr_dm_data.source_max_pq, 3696);
+
+    // Max from the mastering display without MaxCLL, default 100 nits avg
+    if let ExtMetadataBlock::Level1(level1) = vdr_dm_data.get_block(1).unwrap() {
+        assert_eq!(level1.min_pq, 12);
+        assert_eq!(level1.max_pq, 3696);
+        assert_eq!(level1.avg_pq, 2081);
+    } else {
+        panic!("No L1 metadata");
+    }
+
+    assert_eq!(
+        vdr_dm_data.get_block(6),
+        Some(&ExtMetadataBlock::Level6(ExtMetadataBlockLevel6 {
+            max_display_mastering_luminance: 4000,
+            min_display_mastering_luminance: 50,
+            max_content_light_level: 0,
+            max_frame_average_light_level: 0,
+        }))
+    );
+
+    // Constant metadata
+    assert!(rpus
+        .iter()
+        .all(|rpu| rpu.vdr_dm_data.as_ref().unwrap().metadata_eq(vdr_dm_data)));
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn generate_encoded_matches_rpu_list() -> Result<()> {
+    let args = GenerateArgs {
+        json_file: Some(PathBuf::from(
+            "./assets/generator_examples/full_example.json",
+        )),
+        rpu_out: Some(PathBuf::from("/dev/null")),
+        ..Default::default()
+    };
+
+    let mut generator = Generator::from_args(args)?;
+    generator.execute()?;
+
+    let mut config = generator.config.unwrap();
+
+    for long_play_mode in [false, true] {
+        config.long_play_mode = long_play_mode;
+
+        let mut expected = Vec::new();
+        for rpu in config.generate_rpu_list()? {
+            expected.extend_from_slice(&[0, 0, 0, 1]);
+            expected.extend_from_slice(&rpu.write_hevc_unspec62_nalu()?[2..]);
+        }
+
+        let mut encoded = Vec::new();
+        config.write_rpus_to(&mut encoded)?;
+
+        assert_eq!(encoded, expected);
+    }
+
+    // Enough shots for several chunks per thread
+    let shot = config.shots[0].clone();
+    config.shots = (0..5000)
+        .map(|i| VideoShot {
+            start: i * 3,
+            duration: 3,
+            ..shot.clone()
+        })
+        .collect();
+    config.length = 15000;
+
+    let mut expected = Vec::new();
+    for rpu in config.generate_rpu_list()? {
+        expected.extend_from_slice(&[0, 0, 0, 1]);
+        expected.extend_from_slice(&rpu.write_hevc_unspec62_nalu()?[2..]);
+    }
+
+    let mut encoded = Vec::new();
+    config.write_rpus_to(&mut encoded)?;
+
+    assert_eq!(encoded, expected);
+
+    Ok(())
+}
+
 #[cfg(target_os = "linux")]
 #[test]
 fn generate_full() -> Result<()> {
@@ -859,6 +1053,166 @@ fn generate_full_hdr10plus() -> Result<()> {
     Ok(())
 }
 
+#[cfg(target_os = "linux")]
+#[test]
+fn generate_hdr10plus_missing_or_bad_summary() -> Result<()> {
+    use crate::commands::ArgHdr10PlusPeakBrightnessSource;
+
+    for json in [
+        "./assets/tests/hdr10plus_metadata_no_summary.json",
+        "./assets/tests/hdr10plus_metadata_bad_summary.json",
+    ] {
+        let args = GenerateArgs {
+            json_file: Some(PathBuf::from(
+                "./assets/generator_examples/no_duration.json",
+            )),
+            rpu_out: Some(PathBuf::from("/dev/null")),
+            hdr10plus_json: Some(PathBuf::from(json)),
+            hdr10plus_peak_source: Some(ArgHdr10PlusPeakBrightnessSource::MaxScl),
+            ..Default::default()
+        };
+
+        let mut generator = Generator::from_args(args)?;
+        generator.execute()?;
+
+        // Scenes are rebuilt from the frame SceneId
+        let config = generator.config.unwrap();
+        assert_eq!(config.shots.len(), 3);
+        assert_eq!(config.length, 9);
+
+        let starts: Vec<usize> = config.shots.iter().map(|s| s.start).collect();
+        assert_eq!(starts, vec![0, 3, 6]);
+        assert!(config.shots.iter().all(|s| s.duration == 3));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn generate_from_shots_file() -> Result<()> {
+    let args = GenerateArgs {
+        json_file: Some(PathBuf::from(
+            "./assets/generator_examples/default_cmv40.json",
+        )),
+        rpu_out: Some(PathBuf::from("/dev/null")),
+        shots_file: Some(PathBuf::from("./assets/tests/l1_shots.txt")),
+        ..Default::default()
+    };
+
+    let mut generator = Generator::from_args(args)?;
+    generator.execute()?;
+
+    let config = generator.config.unwrap();
+    assert_eq!(config.length, 82);
+
+    let shots: Vec<(usize, usize)> = config.shots.iter().map(|s| (s.start, s.duration)).collect();
+    assert_eq!(shots, vec![(0, 24), (24, 48), (72, 10)]);
+
+    if let ExtMetadataBlock::Level1(l1) = &config.shots[0].metadata_blocks[0] {
+        assert_eq!(l1.max_pq, 3079);
+    } else {
+        panic!("No L1 block");
+    }
+
+    if let ExtMetadataBlock::Level1(l1) = &config.shots[2].metadata_blocks[0] {
+        assert_eq!(l1.max_pq, 3696);
+    } else {
+        panic!("No L1 block");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn generate_from_camera_csv() -> Result<()> {
+    use crate::dovi::generator::CameraLog;
+
+    let args = GenerateArgs {
+        json_file: Some(PathBuf::from(
+            "./assets/generator_examples/default_cmv40.json",
+        )),
+        rpu_out: Some(PathBuf::from("/dev/null")),
+        camera_csv: Some(PathBuf::from("./assets/tests/camera_clips.csv")),
+        camera_log: Some(CameraLog::SLog3),
+        ..Default::default()
+    };
+
+    let mut generator = Generator::from_args(args)?;
+    generator.execute()?;
+
+    let config = generator.config.unwrap();
+    assert_eq!(config.length, 72);
+
+    let shots: Vec<(usize, usize)> = config.shots.iter().map(|s| (s.start, s.duration)).collect();
+    assert_eq!(shots, vec![(0, 24), (24, 48)]);
+
+    // 60% S-Log3 is about 210 nits
+    if let ExtMetadataBlock::Level1(l1) = &config.shots[0].metadata_blocks[0] {
+        assert_eq!(l1.min_pq, 0);
+        assert_eq!(l1.max_pq, 2393);
+    } else {
+        panic!("No L1 block");
+    }
+
+    if let ExtMetadataBlock::Level1(l1) = &config.shots[1].metadata_blocks[0] {
+        assert_eq!(l1.max_pq, 3752);
+    } else {
+        panic!("No L1 block");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn generate_config_validate() -> Result<()> {
+    use dolby_vision::rpu::extension_metadata::blocks::{
+        ExtMetadataBlockLevel1, ExtMetadataBlockLevel2, ExtMetadataBlockLevel3,
+    };
+    use dolby_vision::rpu::generate::VideoShot;
+    use dolby_vision::rpu::vdr_dm_data::CmVersion;
+
+    let mut config = GenerateConfig {
+        length: 10,
+        shots: vec![
+            VideoShot {
+                start: 0,
+                duration: 5,
+                ..Default::default()
+            },
+            VideoShot {
+                start: 5,
+                duration: 5,
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    config.validate()?;
+
+    config.cm_version = CmVersion::V29;
+    config.length = 12;
+    config.shots[1].start = 4;
+    config.shots[1].metadata_blocks = vec![
+        ExtMetadataBlock::Level2(ExtMetadataBlockLevel2::from_nits(600)),
+        ExtMetadataBlock::Level2(ExtMetadataBlockLevel2::from_nits(600)),
+        ExtMetadataBlock::Level3(ExtMetadataBlockLevel3::default()),
+    ];
+    config.shots[0].metadata_blocks = vec![ExtMetadataBlock::Level1(ExtMetadataBlockLevel1::new(
+        0, 2000, 2500,
+    ))];
+
+    let err = config.validate().unwrap_err().to_string();
+    assert!(err.contains("shots[1] (start 4): overlaps the previous shot"));
+    assert!(err.contains(
+        "shots[0] (start 0).metadata_blocks[0] (L1): inconsistent statistics, min_pq 0 avg_pq 2500 max_pq 2000"
+    ));
+    assert!(err.contains("shots[1] (start 4).metadata_blocks[1] (L2): duplicate target"));
+    assert!(err.contains("shots[1] (start 4).metadata_blocks[2] (L3): level is invalid"));
+    assert!(err.contains("length: 12 is not the same as the shots total duration 10"));
+
+    Ok(())
+}
+
 #[test]
 fn cmv40_full_l8_l9_l10() -> Result<()> {
     use dolby_vision::rpu::extension_metadata::blocks::*;
@@ -1072,6 +1426,19 @@ fn profile5_to_p84() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn conversion_mode_from_u8() {
+    // Mode 5, as set from the CLI or an edit config
+    assert_eq!(
+        ConversionMode::from(5),
+        ConversionMode::To81MappingPreserved
+    );
+
+    assert_eq!(ConversionMode::from(0), ConversionMode::Lossless);
+    assert_eq!(ConversionMode::from(2), ConversionMode::To81);
+    assert_eq!(ConversionMode::from(255), ConversionMode::Lossless);
+}
+
 #[test]
 fn fel_to_p81_preserve_mapping() -> Result<()> {
     let (original_data, mut dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/fel_orig.bin"))?;
@@ -1122,3 +1489,1546 @@ fn source_p5_to_p8_001_end_crc32() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn vdr_dm_data_metadata_eq_runs() -> Result<()> {
+    use dolby_vision::rpu::extension_metadata::blocks::ExtMetadataBlockLevel1;
+    use dolby_vision::rpu::vdr_dm_data::identical_metadata_runs;
+
+    let (_, dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/fel_orig.bin"))?;
+    let vdr_dm_data = dovi_rpu.vdr_dm_data.unwrap();
+
+    // Scene cut flag is ignored
+    let mut scene_cut = vdr_dm_data.clone();
+    scene_cut.set_scene_cut(true);
+    assert!(vdr_dm_data.metadata_eq(&scene_cut));
+
+    let mut changed_l1 = vdr_dm_data.clone();
+    changed_l1.replace_metadata_block(ExtMetadataBlock::Level1(ExtMetadataBlockLevel1::new(
+        0, 1000, 500,
+    )))?;
+    assert!(!vdr_dm_data.metadata_eq(&changed_l1));
+
+    let frames = [
+        Some(&vdr_dm_data),
+        Some(&scene_cut),
+        Some(&vdr_dm_data),
+        Some(&changed_l1),
+        None,
+        Some(&changed_l1),
+        Some(&changed_l1),
+    ];
+
+    assert_eq!(
+        identical_metadata_runs(frames, 1),
+        vec![(0, 3), (3, 1), (5, 2)]
+    );
+    assert_eq!(identical_metadata_runs(frames, 2), vec![(0, 3), (5, 2)]);
+
+    Ok(())
+}
+
+#[test]
+fn rpu_inject_writer() -> Result<()> {
+    use std::io::Write;
+
+    use dolby_vision::rpu::inject_writer::RpuInjectWriter;
+    use dolby_vision::rpu::utils::parse_rpu_file;
+    use hevc_parser::HevcParser;
+
+    let bl = std::fs::read("./assets/hevc_tests/regular_bl_start_code_4.hevc")?;
+
+    // Distinct RPUs, in presentation order
+    let mut rpus = parse_rpu_file("./assets/hevc_tests/regular_rpu.bin")?;
+    for (i, rpu) in rpus.iter_mut().enumerate() {
+        if let Some(vdr_dm_data) = rpu.vdr_dm_data.as_mut() {
+            vdr_dm_data.change_source_levels(Some(i as u16), None);
+        }
+    }
+
+    let encoded_rpus = rpus
+        .iter()
+        .map(|rpu| rpu.write_hevc_unspec62_nalu())
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut writer = RpuInjectWriter::new(Vec::new(), rpus.clone());
+
+    // Small writes to split NAL units and start codes
+    for chunk in bl.chunks(1000) {
+        writer.write_all(chunk)?;
+    }
+
+    let output = writer.finish()?;
+
+    let mut parser = HevcParser::default();
+    let mut offsets = Vec::new();
+    parser.get_offsets(&output, &mut offsets);
+
+    let last = *offsets.last().unwrap();
+    let nals = parser.split_nals(&output, &offsets, last, true)?;
+    parser.finish();
+
+    let frames = parser.ordered_frames();
+    assert_eq!(frames.len(), encoded_rpus.len());
+    assert!(frames
+        .iter()
+        .any(|frame| frame.decoded_number != frame.presentation_number));
+
+    // Each decoded frame has the RPU of its presentation frame
+    for frame in frames {
+        let frame_rpus: Vec<&NALUnit> = nals
+            .iter()
+            .filter(|nal| {
+                nal.nal_type == NAL_UNSPEC62 && nal.decoded_frame_index == frame.decoded_number
+            })
+            .collect();
+
+        assert_eq!(frame_rpus.len(), 1);
+        assert_eq!(
+            &output[frame_rpus[0].start..frame_rpus[0].end],
+            encoded_rpus[frame.presentation_number as usize].as_slice()
+        );
+    }
+
+    // Missing RPUs for the frames after the first
+    let mut writer = RpuInjectWriter::new(Vec::new(), rpus.into_iter().take(1));
+    let missing_rpus = match writer.write_all(&bl) {
+        Ok(()) => writer.finish().is_err(),
+        Err(_) => true,
+    };
+    assert!(missing_rpus);
+
+    Ok(())
+}
+
+#[test]
+fn nalu_reader() -> Result<()> {
+    use dolby_vision::rpu::nalu_reader::NaluReader;
+
+    // Reads a few bytes at a time, to split the start codes between reads
+    struct SlowReader<'a>(&'a [u8]);
+
+    impl Read for SlowReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(3);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+
+            Ok(len)
+        }
+    }
+
+    let (_, rpu) = _parse_file(PathBuf::from("./assets/tests/fel_orig.bin"))?;
+    let rpu_nalu_data = rpu.write_hevc_unspec62_nalu()?;
+
+    let mut stream = vec![0xFF, 0x00];
+    // VPS, 4 bytes start code
+    stream.extend_from_slice(&[0, 0, 0, 1, 0x40, 0x01, 0x0C]);
+    // IDR slice, 3 bytes start code and trailing zeroes
+    stream.extend_from_slice(&[0, 0, 1, 0x26, 0x01, 0xAF, 0x00, 0x00, 0x03, 0x01, 0, 0]);
+    // RPU, the trailing zeroes of the slice are ignored
+    stream.extend_from_slice(&[0, 0, 0, 1]);
+    stream.extend_from_slice(&rpu_nalu_data);
+
+    for nalus in [
+        NaluReader::new(stream.as_slice()).collect::<std::io::Result<Vec<_>>>()?,
+        NaluReader::new(SlowReader(&stream)).collect::<std::io::Result<Vec<_>>>()?,
+    ] {
+        assert_eq!(nalus.len(), 3);
+
+        assert_eq!(nalus[0].nal_type, 32);
+        assert_eq!(nalus[0].start_code_offset, 2);
+        assert_eq!(nalus[0].start_code_len, 4);
+        assert_eq!(nalus[0].offset, 6);
+        assert_eq!(nalus[0].data, [0x40, 0x01, 0x0C]);
+
+        assert_eq!(nalus[1].nal_type, 19);
+        assert_eq!(nalus[1].start_code_offset, 9);
+        assert_eq!(nalus[1].start_code_len, 3);
+        assert_eq!(nalus[1].data, [0x26, 0x01, 0xAF, 0x00, 0x00, 0x03, 0x01]);
+        assert!(nalus[1].rpu_payload().is_err());
+
+        let rpu_nalu = &nalus[2];
+        assert!(rpu_nalu.is_rpu());
+        assert_eq!(rpu_nalu.start_code_offset, 21);
+        assert_eq!(rpu_nalu.start_code_len, 4);
+        assert_eq!(rpu_nalu.offset, 25);
+        assert_eq!(rpu_nalu.data, rpu_nalu_data);
+
+        let parsed_rpu = DoviRpu::parse_rpu(&rpu_nalu.rpu_payload()?)?;
+        assert_eq!(parsed_rpu.write_hevc_unspec62_nalu()?, rpu_nalu_data);
+    }
+
+    let file = File::open("./assets/hevc_tests/regular.hevc")?;
+    let mut rpu_count = 0;
+
+    for nalu in NaluReader::new(file) {
+        if nalu?.is_rpu() {
+            rpu_count += 1;
+        }
+    }
+
+    assert_eq!(rpu_count, 259);
+
+    Ok(())
+}
+
+#[test]
+fn dovi_rpu_reader() -> Result<()> {
+    use dolby_vision::rpu::dovi_rpu::DoviRpuReader;
+    use dolby_vision::rpu::utils::parse_rpu_file;
+
+    let rpus = parse_rpu_file("./assets/hevc_tests/regular_rpu.bin")?;
+    assert_eq!(rpus.len(), 259);
+
+    for input in [
+        "./assets/hevc_tests/regular.hevc",
+        "./assets/hevc_tests/regular_rpu.bin",
+    ] {
+        let mut reader = DoviRpuReader::new(File::open(input)?);
+        let streamed_rpus = reader.by_ref().collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(reader.rpu_count(), 259);
+        assert_eq!(streamed_rpus.len(), 259);
+
+        for (rpu, streamed_rpu) in rpus.iter().zip(&streamed_rpus) {
+            assert_eq!(
+                streamed_rpu.write_hevc_unspec62_nalu()?,
+                rpu.write_hevc_unspec62_nalu()?
+            );
+        }
+    }
+
+    // An invalid RPU doesn't end the stream
+    let (_, rpu) = _parse_file(PathBuf::from("./assets/tests/fel_orig.bin"))?;
+    let rpu_nalu_data = rpu.write_hevc_unspec62_nalu()?;
+
+    let mut corrupt_nalu_data = rpu_nalu_data.clone();
+    let len = corrupt_nalu_data.len();
+    corrupt_nalu_data[len - 3] ^= 0x55;
+
+    let mut stream = Vec::new();
+    for data in [&corrupt_nalu_data, &rpu_nalu_data] {
+        stream.extend_from_slice(&[0, 0, 0, 1]);
+        stream.extend_from_slice(data);
+    }
+
+    let mut reader = DoviRpuReader::new(stream.as_slice());
+
+    let error = reader.next_rpu().unwrap_err();
+    assert!(error.to_string().starts_with("Invalid RPU 0"));
+
+    let streamed_rpu = reader.next_rpu()?.unwrap();
+    assert_eq!(streamed_rpu.write_hevc_unspec62_nalu()?, rpu_nalu_data);
+    assert!(reader.next_rpu()?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn mv_hevc_rpu_insertion() -> Result<()> {
+    use hevc_parser::hevc::{NAL_EOS_NUT, NAL_TRAIL_R};
+    use hevc_parser::io::{FrameBuffer, NalBuffer};
+    use hevc_parser::NALUStartCode;
+
+    use crate::dovi::rpu_injector::RpuInjector;
+
+    let nal = |nal_type: u8, header: [u8; 2]| NalBuffer {
+        nal_type,
+        start_code: NALUStartCode::Length4,
+        data: vec![header[0], header[1], 0xAF],
+    };
+
+    // Base view slice, second view slice (nuh_layer_id 1), EOS
+    let mut frame_buffer = FrameBuffer {
+        frame_number: 0,
+        nals: vec![
+            nal(NAL_TRAIL_R, [0x02, 0x01]),
+            nal(NAL_TRAIL_R, [0x02, 0x09]),
+            nal(NAL_EOS_NUT, [0x48, 0x01]),
+        ],
+    };
+
+    let rpu_nb = nal(NAL_UNSPEC62, [0x7C, 0x01]);
+    RpuInjector::insert_rpu_nals(&mut frame_buffer, 2, rpu_nb, true);
+
+    let headers: Vec<_> = frame_buffer.nals.iter().map(|nb| &nb.data[..2]).collect();
+    assert_eq!(
+        headers,
+        [
+            &[0x02, 0x01][..],
+            &[0x7C, 0x01],
+            &[0x02, 0x09],
+            &[0x7C, 0x09],
+            &[0x48, 0x01]
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn editor_fix_l1_stats() -> Result<()> {
+    use dolby_vision::rpu::extension_metadata::blocks::ExtMetadataBlockLevel1;
+
+    use crate::dovi::editor::EditConfig;
+
+    let (_, mut dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/profile8.bin"))?;
+
+    let set_l1 = |rpu: &mut DoviRpu, min_pq: u16, max_pq: u16, avg_pq: u16| {
+        rpu.vdr_dm_data
+            .as_mut()
+            .unwrap()
+            .replace_metadata_block(ExtMetadataBlock::Level1(ExtMetadataBlockLevel1::new(
+                min_pq, max_pq, avg_pq,
+            )))
+    };
+
+    let config: EditConfig = serde_json::from_str(r#"{ "fix_l1_stats": true }"#)?;
+
+    // Inverted min/max, avg above the actual max
+    set_l1(&mut dovi_rpu, 2000, 10, 2500)?;
+    config.execute_single_rpu(&mut dovi_rpu)?;
+
+    let vdr_dm_data = dovi_rpu.vdr_dm_data.as_ref().unwrap();
+    if let Some(ExtMetadataBlock::Level1(l1)) = vdr_dm_data.get_block(1) {
+        assert_eq!((l1.min_pq, l1.max_pq, l1.avg_pq), (10, 2000, 2000));
+    } else {
+        panic!("No L1 block");
+    }
+
+    // Avg below the min
+    set_l1(&mut dovi_rpu, 500, 2500, 100)?;
+    assert!(ExtMetadataBlockLevel1::new(500, 2500, 100).has_inconsistent_stats());
+    config.execute_single_rpu(&mut dovi_rpu)?;
+
+    let vdr_dm_data = dovi_rpu.vdr_dm_data.as_ref().unwrap();
+    if let Some(ExtMetadataBlock::Level1(l1)) = vdr_dm_data.get_block(1) {
+        assert_eq!((l1.min_pq, l1.max_pq, l1.avg_pq), (500, 2500, 500));
+    } else {
+        panic!("No L1 block");
+    }
+
+    // Consistent values are left alone
+    set_l1(&mut dovi_rpu, 0, 2500, 1200)?;
+    assert!(!ExtMetadataBlockLevel1::new(0, 2500, 1200).has_inconsistent_stats());
+    config.execute_single_rpu(&mut dovi_rpu)?;
+
+    let vdr_dm_data = dovi_rpu.vdr_dm_data.as_ref().unwrap();
+    if let Some(ExtMetadataBlock::Level1(l1)) = vdr_dm_data.get_block(1) {
+        assert_eq!((l1.min_pq, l1.max_pq, l1.avg_pq), (0, 2500, 1200));
+    } else {
+        panic!("No L1 block");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn level_float_views() {
+    use dolby_vision::rpu::extension_metadata::blocks::*;
+
+    let level1 = ExtMetadataBlockLevel1::new(0, 3079, 2048);
+    let nits = Level1Nits::from(&level1);
+
+    assert_eq!(nits.min, 0.0);
+    assert!((nits.max - 1000.0).abs() < 1.0);
+    assert!((nits.avg - 92.0).abs() < 1.0);
+    assert_eq!(ExtMetadataBlockLevel1::from(nits), level1);
+
+    let level2 = ExtMetadataBlockLevel2 {
+        trim_slope: 1024,
+        trim_power: 3072,
+        ..ExtMetadataBlockLevel2::from_nits(600)
+    };
+    let trims = Level2Trims::from(&level2);
+
+    assert!((trims.target_max_nits - 600.0).abs() < 1.0);
+    assert_eq!(trims.trim_slope, -0.5);
+    assert_eq!(trims.trim_offset, 0.0);
+    assert_eq!(trims.trim_power, 0.5);
+    assert_eq!(ExtMetadataBlockLevel2::from(trims), level2);
+
+    let level3 = ExtMetadataBlockLevel3 {
+        min_pq_offset: 2048,
+        max_pq_offset: 0,
+        avg_pq_offset: 2560,
+    };
+    let offsets = Level3Offsets::from(&level3);
+
+    assert_eq!((offsets.min, offsets.max, offsets.avg), (0.0, -1.0, 0.25));
+    assert_eq!(ExtMetadataBlockLevel3::from(offsets), level3);
+
+    // Clamped to the 12-bit range
+    let level8 = ExtMetadataBlockLevel8::from(Level8Trims {
+        target_display_index: 1,
+        trim_slope: 2.0,
+        clip_trim: -0.5,
+        ..Default::default()
+    });
+
+    assert_eq!(level8.trim_slope, 4095);
+    assert_eq!(level8.trim_offset, 2048);
+    assert_eq!(level8.clip_trim, 1024);
+    assert_eq!(level8.length, 13);
+}
+
+#[test]
+fn generate_signal_overrides() -> Result<()> {
+    use dolby_vision::rpu::generate::{GenerateProfile, GenerateSignalConfig, VideoShot};
+
+    let mut config = GenerateConfig {
+        profile: GenerateProfile::Profile84,
+        length: 5,
+        shots: vec![VideoShot {
+            start: 0,
+            duration: 5,
+            ..Default::default()
+        }],
+        signal: GenerateSignalConfig {
+            bl_video_full_range: Some(true),
+            signal_full_range_flag: Some(0),
+            signal_chroma_format: Some(1),
+            chroma_resampling_explicit_filter: Some(true),
+        },
+        ..Default::default()
+    };
+
+    let rpus = config.generate_rpu_list()?;
+    let data = rpus[0].write_hevc_unspec62_nalu()?;
+    let parsed_rpu = DoviRpu::parse_unspec62_nalu(&data)?;
+
+    assert_eq!(parsed_rpu.dovi_profile, 8);
+    assert!(parsed_rpu.header.bl_video_full_range_flag);
+    assert!(parsed_rpu.header.chroma_resampling_explicit_filter_flag);
+
+    let vdr_dm_data = parsed_rpu.vdr_dm_data.as_ref().unwrap();
+    assert_eq!(vdr_dm_data.signal_full_range_flag, 0);
+    assert_eq!(vdr_dm_data.signal_chroma_format, 1);
+
+    config.profile = GenerateProfile::Profile5;
+    assert!(config.generate_rpu_list().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn generate_long_play_mode_check() -> Result<()> {
+    use dolby_vision::rpu::extension_metadata::blocks::ExtMetadataBlockLevel1;
+    use dolby_vision::rpu::generate::{ShotFrameEdit, VideoShot};
+    use dolby_vision::rpu::vdr_dm_data::LongPlayModeCheck;
+
+    let mut config = GenerateConfig {
+        length: 10,
+        long_play_mode: true,
+        shots: vec![VideoShot {
+            start: 0,
+            duration: 10,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let rpus = config.generate_rpu_list()?;
+    let check = LongPlayModeCheck::new(rpus.iter().map(|rpu| rpu.vdr_dm_data.as_ref()));
+
+    assert_eq!(check, config.long_play_mode_check());
+    assert!(check.long_play_mode());
+    assert_eq!(check.metadata_changes, 0);
+    assert!(check
+        .warning()
+        .unwrap()
+        .ends_with("Suggested: long_play_mode false, scene cuts on the shot boundaries"));
+
+    // Different L1 on every frame, with a single scene cut
+    config.long_play_mode = false;
+    config.shots[0].frame_edits = (1..10)
+        .map(|i| ShotFrameEdit {
+            edit_offset: i,
+            metadata_blocks: vec![ExtMetadataBlock::Level1(ExtMetadataBlockLevel1::new(
+                0,
+                1000 + i as u16 * 100,
+                500,
+            ))],
+        })
+        .collect();
+
+    let rpus = config.generate_rpu_list()?;
+    let check = LongPlayModeCheck::new(rpus.iter().map(|rpu| rpu.vdr_dm_data.as_ref()));
+
+    assert_eq!(check, config.long_play_mode_check());
+    assert!(!check.long_play_mode());
+    assert_eq!(check.scene_cuts, 1);
+    assert_eq!(check.unflagged_changes, 9);
+    assert!(check
+        .warning()
+        .unwrap()
+        .ends_with("Suggested: long_play_mode true"));
+
+    config.long_play_mode = true;
+    let rpus = config.generate_rpu_list()?;
+    let check = LongPlayModeCheck::new(rpus.iter().map(|rpu| rpu.vdr_dm_data.as_ref()));
+
+    assert!(check.long_play_mode());
+    assert_eq!(check.warning(), None);
+
+    Ok(())
+}
+
+#[test]
+fn generate_convert_profile() -> Result<()> {
+    use dolby_vision::rpu::extension_metadata::blocks::{
+        ExtMetadataBlockLevel2, ExtMetadataBlockLevel3,
+    };
+    use dolby_vision::rpu::generate::{GenerateProfile, GenerateSignalConfig, VideoShot};
+    use dolby_vision::rpu::vdr_dm_data::CmVersion;
+
+    let mut config = GenerateConfig {
+        cm_version: CmVersion::V29,
+        length: 5,
+        shots: vec![VideoShot {
+            start: 0,
+            duration: 5,
+            metadata_blocks: vec![
+                ExtMetadataBlock::Level2(ExtMetadataBlockLevel2::default()),
+                ExtMetadataBlock::Level3(ExtMetadataBlockLevel3::default()),
+            ],
+            ..Default::default()
+        }],
+        signal: GenerateSignalConfig {
+            signal_full_range_flag: Some(0),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    assert!(config
+        .convert_profile(GenerateProfile::Profile81)
+        .is_empty());
+
+    let warnings = config.convert_profile(GenerateProfile::Profile5);
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings[1].starts_with("dropped 1 L3 blocks"));
+
+    assert_eq!(config.profile, GenerateProfile::Profile5);
+    assert_eq!(config.signal, GenerateSignalConfig::default());
+    assert_eq!(config.shots[0].metadata_blocks.len(), 1);
+    assert_eq!(config.generate_rpu_list()?[0].dovi_profile, 5);
+
+    let warnings = config.convert_profile(GenerateProfile::Profile84);
+    assert_eq!(warnings.len(), 1);
+
+    assert_eq!(config.cm_version, CmVersion::V40);
+    assert_eq!(config.l1_avg_pq_cm_version, Some(CmVersion::V29));
+    assert_eq!(config.generate_rpu_list()?[0].dovi_profile, 8);
+
+    Ok(())
+}
+
+#[test]
+fn generate_config_timecodes() -> Result<()> {
+    use dolby_vision::utils::{frames_to_timecode, timecode_to_frames};
+
+    assert_eq!(timecode_to_frames("00:02:04:12", 24.0)?, 2988);
+    assert_eq!(timecode_to_frames("00:00:01:00", 24000.0 / 1001.0)?, 24);
+    assert!(timecode_to_frames("00:00:01:24", 24.0).is_err());
+    assert!(timecode_to_frames("00:01:00", 24.0).is_err());
+
+    assert_eq!(frames_to_timecode(2988, 24.0), "00:02:04:12");
+
+    let value = serde_json::json!({
+        "cm_version": "V40",
+        "fps": 24.0,
+        "length": "00:00:02:00",
+        "shots": [
+            { "start": 0, "duration": "00:00:01:00" },
+            {
+                "start": "00:00:01:00",
+                "duration": 24,
+                "frame_edits": [{ "edit_offset": "00:00:00:12", "metadata_blocks": [] }]
+            }
+        ]
+    });
+
+    let config = GenerateConfig::from_json_value(value)?;
+    assert_eq!(config.length, 48);
+    assert_eq!(config.shots[0].duration, 24);
+    assert_eq!(config.shots[1].start, 24);
+    assert_eq!(config.shots[1].frame_edits[0].edit_offset, 12);
+
+    let missing_fps = serde_json::json!({ "length": "00:00:02:00" });
+    assert!(GenerateConfig::from_json_value(missing_fps).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_rpu_file_lenient() -> Result<()> {
+    use dolby_vision::rpu::generate::VideoShot;
+    use dolby_vision::rpu::utils::{parse_rpu_file, parse_rpu_file_lenient};
+
+    let config = GenerateConfig {
+        length: 3,
+        shots: vec![VideoShot {
+            start: 0,
+            duration: 3,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let mut rpus = config.generate_rpu_list()?;
+    let mut data = GenerateConfig::encode_rpus(&mut rpus);
+
+    // Corrupt the CRC32 of the second RPU
+    let len = data[1].len();
+    data[1][len - 3] ^= 0x55;
+
+    let temp = assert_fs::TempDir::new()?;
+    let rpu_path = temp.path().join("RPU.bin");
+    crate::dovi::write_rpu_file(&rpu_path, data)?;
+
+    assert!(parse_rpu_file(&rpu_path).is_err());
+
+    let (parsed_rpus, errors) = parse_rpu_file_lenient(&rpu_path)?;
+    assert_eq!(parsed_rpus.len(), 3);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].index, 1);
+
+    // Substituted by the previous RPU
+    assert_eq!(
+        parsed_rpus[1].write_hevc_unspec62_nalu()?,
+        parsed_rpus[0].write_hevc_unspec62_nalu()?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn generate_auto_blocks() -> Result<()> {
+    use dolby_vision::rpu::generate::{GenerateAutoBlocksConfig, VideoShot};
+
+    let mut config = GenerateConfig {
+        length: 1,
+        shots: vec![VideoShot {
+            start: 0,
+            duration: 1,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let rpus = config.generate_rpu_list()?;
+    let vdr_dm_data = rpus[0].vdr_dm_data.as_ref().unwrap();
+    assert!(vdr_dm_data.get_block(9).is_some());
+    assert!(vdr_dm_data.get_block(11).is_some());
+    assert!(vdr_dm_data.get_block(254).is_some());
+
+    config.auto_blocks = GenerateAutoBlocksConfig {
+        level9: true,
+        level11: false,
+    };
+
+    let rpus = config.generate_rpu_list()?;
+    let vdr_dm_data = rpus[0].vdr_dm_data.as_ref().unwrap();
+    assert!(vdr_dm_data.get_block(9).is_some());
+    assert!(vdr_dm_data.get_block(11).is_none());
+    assert!(vdr_dm_data.get_block(254).is_some());
+
+    let config: GenerateConfig = serde_json::from_str(
+        r#"{ "length": 1, "level6": null, "auto_blocks": { "level9": false } }"#,
+    )?;
+    assert!(!config.auto_blocks.level9);
+    assert!(config.auto_blocks.level11);
+
+    Ok(())
+}
+
+#[test]
+fn level1_from_histogram() -> Result<()> {
+    use dolby_vision::rpu::histogram::{HistogramL1Opts, HistogramScale, LuminanceHistogram};
+    use dolby_vision::rpu::vdr_dm_data::CmVersion;
+
+    let edges = [0.0, 0.25, 0.5, 0.75, 1.0];
+    let histogram = LuminanceHistogram::new(&edges, &[0, 4, 0, 4], HistogramScale::Pq)?;
+
+    assert_eq!(histogram.min_pq(), 0.25);
+    assert_eq!(histogram.max_pq(), 1.0);
+    assert_eq!(histogram.mean_pq(), 0.625);
+    assert_eq!(histogram.percentile_pq(50.0), 0.5);
+    assert_eq!(histogram.percentile_pq(75.0), 0.875);
+    assert_eq!(histogram.percentile_weighted_avg_pq(&[50.0]), 0.5625);
+
+    let level1 = histogram.level1(&HistogramL1Opts::default(), CmVersion::V40)?;
+    assert_eq!(
+        (level1.min_pq, level1.max_pq, level1.avg_pq),
+        (12, 4095, 2559)
+    );
+
+    let opts = HistogramL1Opts {
+        max_percentile: Some(75.0),
+        avg_percentiles: vec![50.0],
+    };
+    let level1 = histogram.level1(&opts, CmVersion::V40)?;
+    assert_eq!(
+        (level1.min_pq, level1.max_pq, level1.avg_pq),
+        (12, 3583, 2303)
+    );
+
+    // Nits edges
+    let histogram = LuminanceHistogram::new(&[0.0, 100.0, 1000.0], &[0, 1], HistogramScale::Nits)?;
+    let level1 = histogram.level1(&HistogramL1Opts::default(), CmVersion::V40)?;
+    assert_eq!(level1.max_pq, 3079);
+
+    assert!(LuminanceHistogram::new(&edges, &[1, 2], HistogramScale::Pq).is_err());
+    assert!(LuminanceHistogram::new(&edges, &[0, 0, 0, 0], HistogramScale::Pq).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn level2_trims_apply() {
+    use dolby_vision::rpu::extension_metadata::blocks::{ExtMetadataBlockLevel2, Level2Trims};
+
+    let neutral = Level2Trims::from(&ExtMetadataBlockLevel2::from_nits(100));
+    assert_eq!(neutral.apply(0.0), 0.0);
+    assert_eq!(neutral.apply(0.5), 0.5);
+    assert_eq!(neutral.apply(1.0), 1.0);
+
+    // CM XML gain of 0.5: the peak is raised above the target
+    let gain = Level2Trims {
+        trim_slope: 0.5,
+        ..neutral
+    };
+    assert_eq!(gain.apply(1.0), 1.25);
+
+    // CM XML lift of 0.5: black is raised, the peak is kept
+    let lift = Level2Trims {
+        trim_slope: -0.5,
+        trim_offset: 0.5,
+        ..neutral
+    };
+    assert_eq!(lift.apply(0.0), 0.25);
+    assert_eq!(lift.apply(1.0), 1.0);
+
+    // Negative offset crushing to black
+    let crush = Level2Trims {
+        trim_offset: -1.0,
+        ..neutral
+    };
+    assert_eq!(crush.apply(0.5), 0.0);
+
+    // CM XML gamma of 1.0
+    let gamma = Level2Trims {
+        trim_power: -2.0 / 3.0,
+        ..neutral
+    };
+    assert!((gamma.apply(0.25) - 0.25_f64.powf(2.0 / 3.0)).abs() < 1e-9);
+}
+
+#[test]
+fn validate_trims_clip() -> Result<()> {
+    use dolby_vision::rpu::extension_metadata::blocks::{
+        ExtMetadataBlockLevel1, ExtMetadataBlockLevel2,
+    };
+    use dolby_vision::rpu::generate::VideoShot;
+    use dolby_vision::rpu::vdr_dm_data::CmVersion;
+
+    use crate::dovi::trim_validation::TrimValidationReport;
+
+    let shot = |start: usize, trim_slope: u16| VideoShot {
+        start,
+        duration: 10,
+        metadata_blocks: vec![
+            ExtMetadataBlock::Level1(ExtMetadataBlockLevel1::from_stats_cm_version(
+                0,
+                2000,
+                1200,
+                CmVersion::V29,
+            )),
+            ExtMetadataBlock::Level2(ExtMetadataBlockLevel2 {
+                trim_slope,
+                ..ExtMetadataBlockLevel2::from_nits(100)
+            }),
+        ],
+        ..Default::default()
+    };
+
+    // The shot max is tone mapped to the 100 nits peak, any gain above it clips
+    let config = GenerateConfig {
+        cm_version: CmVersion::V29,
+        length: 30,
+        shots: vec![shot(0, 2048), shot(10, 3072), shot(20, 1024)],
+        ..Default::default()
+    };
+
+    let report = TrimValidationReport::new(&config.generate_rpu_list()?);
+    assert_eq!(report.shots, 3);
+    assert_eq!(report.flagged.len(), 1);
+
+    let (frame, reason) = &report.flagged[0];
+    assert_eq!(*frame, 10);
+    assert_eq!(
+        reason,
+        "100 nits trim clips the highlights, max at 125% of the target peak"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn level8_clip_to_target() {
+    use dolby_vision::rpu::extension_metadata::blocks::ExtMetadataBlockLevel8;
+
+    let level8 = ExtMetadataBlockLevel8::default();
+
+    let clipped = level8.with_clip_to_target(0.75, 0.5);
+    assert_eq!(clipped.clip_trim, 2560);
+    assert_eq!(clipped.length, 13);
+    assert_eq!(clipped.trim_slope, level8.trim_slope);
+
+    // Target above the peak, nothing clipped
+    assert_eq!(level8.with_clip_to_target(0.5, 0.75).clip_trim, 2048);
+
+    let level8 = ExtMetadataBlockLevel8 {
+        length: 25,
+        clip_trim: 4000,
+        ..Default::default()
+    };
+    let clipped = level8.with_clip_to_target(1.0, 0.5);
+    assert_eq!((clipped.clip_trim, clipped.length), (4095, 25));
+}
+
+#[test]
+fn madvr_custom_target_frame_edits() {
+    use dolby_vision::rpu::extension_metadata::blocks::{
+        ExtMetadataBlockLevel1, ExtMetadataBlockLevel8,
+    };
+    use dolby_vision::rpu::generate::VideoShot;
+    use dolby_vision::rpu::vdr_dm_data::CmVersion;
+    use madvr_parse::MadVRFrame;
+
+    use crate::dovi::generator::custom_target_frame_edits;
+
+    let frames = [
+        MadVRFrame {
+            peak_pq_2020: 0.75,
+            target_pq: 0.5,
+            ..Default::default()
+        },
+        MadVRFrame {
+            peak_pq_2020: 0.6,
+            target_pq: 0.6,
+            ..Default::default()
+        },
+    ];
+
+    let mut config = GenerateConfig {
+        l1_avg_pq_cm_version: Some(CmVersion::V40),
+        ..Default::default()
+    };
+
+    // Default 100 nits trim
+    let edits = custom_target_frame_edits(&config, None, &frames, 0.1);
+    assert_eq!(edits.len(), 2);
+
+    let clip_trims: Vec<(usize, u16, u8)> = edits
+        .iter()
+        .flat_map(|edit| {
+            edit.metadata_blocks.iter().filter_map(|block| match block {
+                ExtMetadataBlock::Level8(l8) => {
+                    Some((edit.edit_offset, l8.clip_trim, l8.target_display_index))
+                }
+                _ => None,
+            })
+        })
+        .collect();
+    assert_eq!(clip_trims, vec![(0, 2560, 1), (1, 2048, 1)]);
+
+    // L1 max from the frame targets
+    for (edit, frame) in edits.iter().zip(&frames) {
+        let expected = ExtMetadataBlockLevel1::from_normalized_pq_cm_version(
+            0.0,
+            frame.target_pq,
+            0.1,
+            CmVersion::V40,
+        );
+        assert_eq!(edit.metadata_blocks[0], ExtMetadataBlock::Level1(expected));
+    }
+
+    // Shot trims take priority over the default ones
+    config.default_metadata_blocks = vec![ExtMetadataBlock::Level8(ExtMetadataBlockLevel8 {
+        target_display_index: 3,
+        ..Default::default()
+    })];
+    let config_shot = VideoShot {
+        metadata_blocks: vec![ExtMetadataBlock::Level8(ExtMetadataBlockLevel8 {
+            target_display_index: 48,
+            clip_trim: 2148,
+            ..Default::default()
+        })],
+        ..Default::default()
+    };
+
+    let edits = custom_target_frame_edits(&config, Some(&config_shot), &frames, 0.1);
+    match &edits[0].metadata_blocks[1] {
+        ExtMetadataBlock::Level8(l8) => {
+            assert_eq!((l8.target_display_index, l8.clip_trim), (48, 2660))
+        }
+        block => panic!("Unexpected block {block:?}"),
+    }
+
+    let edits = custom_target_frame_edits(&config, None, &frames, 0.1);
+    match &edits[0].metadata_blocks[1] {
+        ExtMetadataBlock::Level8(l8) => assert_eq!(l8.target_display_index, 3),
+        block => panic!("Unexpected block {block:?}"),
+    }
+
+    // No L8 trims with CM v2.9
+    config.cm_version = CmVersion::V29;
+    let edits = custom_target_frame_edits(&config, None, &frames, 0.1);
+    assert!(edits.iter().all(|edit| edit.metadata_blocks.len() == 1));
+}
+
+#[test]
+fn generate_synthetic() -> Result<()> {
+    use dolby_vision::rpu::synthetic::synthetic_config;
+
+    let encode = |seed: u64| -> Result<Vec<Vec<u8>>> {
+        let config = synthetic_config(seed, 200);
+        config.validate()?;
+
+        config
+            .generate_rpu_list()?
+            .iter()
+            .map(|rpu| rpu.write_hevc_unspec62_nalu())
+            .collect()
+    };
+
+    for seed in 0..64 {
+        let rpus = encode(seed)?;
+        assert_eq!(rpus.len(), 200);
+
+        // Same seed, same metadata
+        assert_eq!(rpus, encode(seed)?);
+
+        for data in &rpus {
+            DoviRpu::parse_unspec62_nalu(data)?;
+        }
+    }
+
+    assert_ne!(encode(1)?, encode(2)?);
+
+    Ok(())
+}
+
+#[test]
+fn generate_roundtrip() -> Result<()> {
+    use dolby_vision::rpu::roundtrip::{check_roundtrip, derive_config};
+    use dolby_vision::rpu::synthetic::synthetic_config;
+
+    for seed in 0..32 {
+        let config = synthetic_config(seed, 200);
+        let derived = check_roundtrip(&config)?;
+
+        assert_eq!(derived.length, 200);
+        assert_eq!(derived.profile, config.profile);
+        assert_eq!(derived.cm_version, config.cm_version);
+    }
+
+    let mut config: GenerateConfig =
+        serde_json::from_reader(File::open("./assets/generator_examples/full_example.json")?)?;
+    config.length = config.shots.iter().map(|shot| shot.duration).sum();
+
+    let derived = check_roundtrip(&config)?;
+
+    // The shots start on the scene cuts
+    let shots = |config: &GenerateConfig| -> Vec<(usize, usize)> {
+        config.shots.iter().map(|s| (s.start, s.duration)).collect()
+    };
+    assert_eq!(shots(&derived), shots(&config));
+
+    assert!(derive_config(&[]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn generate_config_cm_xml_names() -> Result<()> {
+    use dolby_vision::rpu::extension_metadata::cm_xml_names::{from_cm_xml_names, to_cm_xml_names};
+
+    let value: serde_json::Value =
+        serde_json::from_reader(File::open("./assets/generator_examples/full_example.json")?)?;
+    let config = GenerateConfig::from_json_value(value)?;
+    let original = serde_json::to_value(&config)?;
+
+    let mut renamed = original.clone();
+    to_cm_xml_names(&mut renamed);
+
+    let json = renamed.to_string();
+    assert!(json.contains("\"ImageCharacterMax\""));
+    assert!(json.contains("\"MaxCLL\""));
+    assert!(!json.contains("\"max_pq\""));
+
+    // Configs with the XML names can be used directly
+    let config = GenerateConfig::from_json_value(renamed.clone())?;
+    assert_eq!(serde_json::to_value(&config)?, original);
+
+    from_cm_xml_names(&mut renamed);
+    assert_eq!(renamed, original);
+
+    Ok(())
+}
+
+#[test]
+fn parse_warnings() -> Result<()> {
+    use dolby_vision::rpu::extension_metadata::blocks::{
+        ExtMetadataBlockLevel1, ExtMetadataBlockLevel8,
+    };
+    use dolby_vision::rpu::parse_warning::ParseWarning;
+
+    let mut rpu = DoviRpu::profile81_config(&GenerateConfig::default())?;
+
+    let data = rpu.write_hevc_unspec62_nalu()?;
+    assert!(DoviRpu::parse_unspec62_nalu(&data)?
+        .parse_warnings()
+        .is_empty());
+
+    let vdr_dm_data = rpu.vdr_dm_data.as_mut().unwrap();
+    vdr_dm_data.replace_metadata_block(ExtMetadataBlock::Level1(ExtMetadataBlockLevel1 {
+        min_pq: 0,
+        max_pq: 2081,
+        avg_pq: 2500,
+    }))?;
+    vdr_dm_data.add_metadata_block(ExtMetadataBlock::Level8(ExtMetadataBlockLevel8 {
+        length: 10,
+        target_display_index: 5,
+        ..Default::default()
+    }))?;
+
+    let data = rpu.write_hevc_unspec62_nalu()?;
+    let parsed_rpu = DoviRpu::parse_unspec62_nalu(&data)?;
+
+    assert_eq!(
+        parsed_rpu.parse_warnings(),
+        &[
+            ParseWarning::InconsistentLevel1 {
+                min_pq: 0,
+                max_pq: 2081,
+                avg_pq: 2500,
+            },
+            ParseWarning::UndefinedTargetDisplay {
+                target_display_index: 5
+            },
+        ]
+    );
+
+    // Warnings don't prevent rewriting the RPU
+    assert_eq!(parsed_rpu.write_hevc_unspec62_nalu()?, data);
+
+    Ok(())
+}
+
+#[test]
+fn compat_old_rpus() -> Result<()> {
+    use bitvec::prelude::{BitVec, Msb0};
+    use dolby_vision::rpu::extension_metadata::{DmData, WithExtMetadataBlocks};
+    use dolby_vision::rpu::parse_warning::ParseWarning;
+
+    fn push_bits(bits: &mut BitVec<u8, Msb0>, value: u64, n: usize) {
+        for i in (0..n).rev() {
+            bits.push((value >> i) & 1 == 1);
+        }
+    }
+
+    fn push_ue(bits: &mut BitVec<u8, Msb0>, value: u64) {
+        let n = 64 - (value + 1).leading_zeros() as usize;
+
+        push_bits(bits, 0, n - 1);
+        push_bits(bits, value + 1, n);
+    }
+
+    fn crc32_mpeg2(data: &[u8]) -> u32 {
+        data.iter().fold(0xFFFF_FFFF, |crc, byte| {
+            (0..8).fold(crc ^ ((*byte as u32) << 24), |crc, _| {
+                if crc & 0x8000_0000 != 0 {
+                    (crc << 1) ^ 0x04C1_1DB7
+                } else {
+                    crc << 1
+                }
+            })
+        })
+    }
+
+    // Profile 8 RPU using the previous mapping, with compressed DM data and L1/L6 blocks
+    fn old_rpu(num_ext_blocks: u64, levels: &[u8]) -> Vec<u8> {
+        let mut bits: BitVec<u8, Msb0> = BitVec::new();
+
+        push_bits(&mut bits, 25, 8);
+
+        // rpu_type, rpu_format, vdr_rpu_profile, vdr_rpu_level, vdr_seq_info_present_flag
+        push_bits(&mut bits, 2, 6);
+        push_bits(&mut bits, 18, 11);
+        push_bits(&mut bits, 1, 4);
+        push_bits(&mut bits, 0, 4);
+        push_bits(&mut bits, 1, 1);
+
+        // chroma_resampling_explicit_filter_flag, coefficient_data_type, coefficient_log2_denom
+        push_bits(&mut bits, 0, 3);
+        push_ue(&mut bits, 23);
+
+        // vdr_rpu_normalized_idc, bl_video_full_range_flag, bit depths
+        push_bits(&mut bits, 0b010, 3);
+        push_ue(&mut bits, 2);
+        push_ue(&mut bits, 2);
+        push_ue(&mut bits, 4);
+
+        // reserved_zero_3bits is 1 for compressed DM data, disable_residual_flag
+        push_bits(&mut bits, 0b000101, 6);
+
+        // vdr_dm_metadata_present_flag, use_prev_vdr_rpu_flag, prev_vdr_rpu_id
+        push_bits(&mut bits, 0b11, 2);
+        push_ue(&mut bits, 0);
+
+        // affected_dm_metadata_id, current_dm_metadata_id, scene_refresh_flag
+        for _ in 0..3 {
+            push_ue(&mut bits, 0);
+        }
+
+        push_ue(&mut bits, num_ext_blocks);
+        while bits.len() % 8 != 0 {
+            bits.push(false);
+        }
+
+        for level in levels {
+            match level {
+                1 => {
+                    push_ue(&mut bits, 5);
+                    push_bits(&mut bits, 1, 8);
+                    push_bits(&mut bits, 100, 12);
+                    push_bits(&mut bits, 2000, 12);
+                    push_bits(&mut bits, 1000, 12);
+                    push_bits(&mut bits, 0, 4);
+                }
+                6 => {
+                    push_ue(&mut bits, 8);
+                    push_bits(&mut bits, 6, 8);
+                    push_bits(&mut bits, 1000, 16);
+                    push_bits(&mut bits, 1, 16);
+                    push_bits(&mut bits, 1000, 16);
+                    push_bits(&mut bits, 400, 16);
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        while bits.len() % 8 != 0 {
+            bits.push(false);
+        }
+
+        let mut data = bits.into_vec();
+        let crc32 = crc32_mpeg2(&data[1..]);
+
+        data.extend_from_slice(&crc32.to_be_bytes());
+        data.push(0x80);
+
+        data
+    }
+
+    fn cmv29_levels(rpu: &DoviRpu) -> (u64, Vec<u8>) {
+        match &rpu.vdr_dm_data.as_ref().unwrap().cmv29_metadata {
+            Some(DmData::V29(cmv29)) => (
+                cmv29.num_ext_blocks(),
+                cmv29.blocks_ref().iter().map(|b| b.level()).collect(),
+            ),
+            _ => panic!("Missing CM v2.9 metadata"),
+        }
+    }
+
+    // Misordered blocks
+    let data = old_rpu(2, &[6, 1]);
+
+    let rpu = DoviRpu::parse_unspec62_nalu(&data)?;
+    assert_eq!(cmv29_levels(&rpu), (2, vec![6, 1]));
+    assert!(rpu.parse_warnings().is_empty());
+
+    let rpu = DoviRpu::parse_unspec62_nalu_compat_old(&data)?;
+    assert!(rpu.modified);
+    assert_eq!(cmv29_levels(&rpu), (2, vec![1, 6]));
+    assert_eq!(
+        rpu.parse_warnings(),
+        &[ParseWarning::MisorderedBlocks {
+            cm_version: "CM v2.9"
+        }]
+    );
+
+    // num_ext_blocks too high
+    let data = old_rpu(3, &[1, 6]);
+    assert!(DoviRpu::parse_unspec62_nalu(&data).is_err());
+
+    let rpu = DoviRpu::parse_unspec62_nalu_compat_old(&data)?;
+    assert_eq!(cmv29_levels(&rpu), (2, vec![1, 6]));
+    assert_eq!(
+        rpu.parse_warnings(),
+        &[ParseWarning::WrongNumExtBlocks {
+            cm_version: "CM v2.9",
+            signaled: 3,
+            parsed: 2,
+        }]
+    );
+
+    // num_ext_blocks too low
+    let data = old_rpu(1, &[1, 6]);
+
+    let rpu = DoviRpu::parse_unspec62_nalu_compat_old(&data)?;
+    assert_eq!(cmv29_levels(&rpu), (2, vec![1, 6]));
+    assert_eq!(
+        rpu.parse_warnings(),
+        &[ParseWarning::WrongNumExtBlocks {
+            cm_version: "CM v2.9",
+            signaled: 1,
+            parsed: 2,
+        }]
+    );
+
+    // Valid RPUs are unchanged
+    let (original_data, dovi_rpu) =
+        _parse_file(PathBuf::from("./assets/tests/cmv40_full_rpu.bin"))?;
+    let compat_rpu = DoviRpu::parse_unspec62_nalu_compat_old(&original_data)?;
+
+    assert!(!compat_rpu.modified);
+    assert_eq!(compat_rpu.vdr_dm_data, dovi_rpu.vdr_dm_data);
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn generate_hdr10plus_peak_source() -> Result<()> {
+    use crate::commands::ArgHdr10PlusPeakBrightnessSource;
+
+    fn shot_max_pqs(
+        config_path: PathBuf,
+        peak_source: ArgHdr10PlusPeakBrightnessSource,
+    ) -> Result<Vec<u16>> {
+        let args = GenerateArgs {
+            json_file: Some(config_path),
+            rpu_out: Some(PathBuf::from("/dev/null")),
+            hdr10plus_json: Some(PathBuf::from("./assets/tests/hdr10plus_metadata.json")),
+            hdr10plus_peak_source: Some(peak_source),
+            ..Default::default()
+        };
+
+        let mut generator = Generator::from_args(args)?;
+        generator.execute()?;
+
+        let config = generator.config.unwrap();
+
+        Ok(config
+            .shots
+            .iter()
+            .map(|shot| match shot.metadata_blocks.first() {
+                Some(ExtMetadataBlock::Level1(level1)) => level1.max_pq,
+                _ => panic!("Missing shot L1"),
+            })
+            .collect())
+    }
+
+    let config_path = PathBuf::from("./assets/generator_examples/no_duration.json");
+
+    let histogram = shot_max_pqs(
+        config_path.clone(),
+        ArgHdr10PlusPeakBrightnessSource::Histogram,
+    )?;
+    let max_scl = shot_max_pqs(
+        config_path.clone(),
+        ArgHdr10PlusPeakBrightnessSource::MaxScl,
+    )?;
+    let max = shot_max_pqs(config_path.clone(), ArgHdr10PlusPeakBrightnessSource::Max)?;
+
+    let expected_max: Vec<u16> = histogram
+        .iter()
+        .zip(&max_scl)
+        .map(|(a, b)| *a.max(b))
+        .collect();
+    assert_eq!(max, expected_max);
+
+    // Per-shot overrides in the config
+    let mut config: serde_json::Value = serde_json::from_reader(File::open(&config_path)?)?;
+    config["shots"][0]["hdr10plus_peak_source"] = "histogram".into();
+    config["shots"][1]["hdr10plus_peak_source"] = "max".into();
+
+    let temp = assert_fs::TempDir::new()?;
+    let override_path = temp.path().join("config.json");
+    serde_json::to_writer(File::create(&override_path)?, &config)?;
+
+    let overridden = shot_max_pqs(override_path, ArgHdr10PlusPeakBrightnessSource::MaxScl)?;
+    // The third shot isn't in the config
+    assert_eq!(overridden, vec![histogram[0], max[1], max_scl[2]]);
+
+    Ok(())
+}
+
+#[test]
+fn scan_profiles() -> Result<()> {
+    use dolby_vision::rpu::utils::{count_frames, parse_rpu_file, scan_profiles};
+    use dolby_vision::rpu::vdr_dm_data::CmVersion;
+
+    let rpus = parse_rpu_file("./assets/hevc_tests/regular_rpu.bin")?;
+
+    // Same RPUs, muxed in HEVC
+    for input in [
+        "./assets/hevc_tests/regular_rpu.bin",
+        "./assets/hevc_tests/regular.hevc",
+    ] {
+        assert_eq!(count_frames(input)?, rpus.len());
+
+        let scan = scan_profiles(input)?;
+        assert_eq!(scan.frame_count, rpus.len());
+        assert_eq!(scan.profiles, vec![(rpus[0].dovi_profile, rpus.len())]);
+    }
+
+    let scan = scan_profiles("./assets/tests/cmv40_full_rpu.bin")?;
+    assert_eq!(scan.frame_count, 1);
+    assert_eq!(scan.cm_versions, vec![CmVersion::V40]);
+
+    let scan = scan_profiles("./assets/tests/fel_rpu.bin")?;
+    assert_eq!(scan.profiles, vec![(7, 1)]);
+    assert_eq!(scan.el_types, vec![DoviELType::FEL]);
+
+    let scan = scan_profiles("./assets/tests/mel_rpu.bin")?;
+    assert_eq!(scan.el_types, vec![DoviELType::MEL]);
+
+    let scan = scan_profiles("./assets/tests/profile8.bin")?;
+    assert!(scan.el_types.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn bl_bit_depth_rewrite() -> Result<()> {
+    let (original_data, mut dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/fel_rpu.bin"))?;
+
+    let pivots_sum = |rpu: &DoviRpu| -> Vec<u32> {
+        let mapping = rpu.rpu_data_mapping.as_ref().unwrap();
+
+        mapping
+            .curves
+            .iter()
+            .map(|curve| curve.pivots.iter().map(|p| *p as u32).sum())
+            .collect()
+    };
+    let original_sums = pivots_sum(&dovi_rpu);
+
+    dovi_rpu.set_bl_bit_depth(12)?;
+    assert_eq!(dovi_rpu.header.bl_bit_depth_minus8, 4);
+
+    let nlq_pred_pivot_value = dovi_rpu
+        .rpu_data_mapping
+        .as_ref()
+        .and_then(|mapping| mapping.nlq_pred_pivot_value)
+        .unwrap();
+    assert_eq!(nlq_pred_pivot_value.iter().sum::<u16>(), 4095);
+
+    // Written and parsed with the 12-bit pivots
+    let data = dovi_rpu.write_hevc_unspec62_nalu()?;
+    let mut dovi_rpu = DoviRpu::parse_unspec62_nalu(&data)?;
+
+    for (sum, original) in pivots_sum(&dovi_rpu).iter().zip(&original_sums) {
+        assert_eq!(*sum, (original * 4095 + 511) / 1023);
+    }
+
+    // Back to the original
+    dovi_rpu.set_bl_bit_depth(10)?;
+    assert_eq!(
+        &original_data[4..],
+        &dovi_rpu.write_hevc_unspec62_nalu()?[2..]
+    );
+
+    assert!(dovi_rpu.set_bl_bit_depth(8).is_err());
+
+    // The pivots must match the signaled depth
+    dovi_rpu.header.bl_bit_depth_minus8 = 4;
+    assert!(dovi_rpu.write_hevc_unspec62_nalu().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn rpu_profile() -> Result<()> {
+    use dolby_vision::rpu::profiles::{BlCompatibility, RpuProfile};
+
+    let (_, dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/fel_rpu.bin"))?;
+    let profile = dovi_rpu.profile();
+    assert_eq!(
+        profile,
+        RpuProfile::Profile7 {
+            el_type: Some(DoviELType::FEL)
+        }
+    );
+    assert!(profile.requires_el());
+    assert!(!profile.is_single_layer());
+    assert_eq!(profile.bl_compatibility(), Some(BlCompatibility::Hdr10));
+    assert_eq!(profile.bl_signal_compatibility_id(), Some(6));
+    assert_eq!(profile.to_string(), "7 (FEL)");
+
+    let (_, dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/mel_rpu.bin"))?;
+    assert!(!dovi_rpu.profile().requires_el());
+    assert_eq!(dovi_rpu.profile().to_string(), "7 (MEL)");
+
+    let (_, dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/profile4.bin"))?;
+    assert!(dovi_rpu.profile().requires_el());
+    assert_eq!(
+        dovi_rpu.profile().bl_compatibility(),
+        Some(BlCompatibility::Sdr)
+    );
+
+    let (_, dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/profile5.bin"))?;
+    let profile = dovi_rpu.profile();
+    assert_eq!(profile, RpuProfile::Profile5);
+    assert!(profile.is_single_layer());
+    assert_eq!(profile.bl_compatibility(), Some(BlCompatibility::None));
+    assert_eq!(profile.to_string(), "5");
+
+    // The 8.x base layer isn't signaled in the RPU
+    let (_, dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/profile8.bin"))?;
+    let profile = dovi_rpu.profile();
+    assert_eq!(profile, RpuProfile::Profile8);
+    assert!(profile.is_single_layer());
+    assert!(!profile.requires_el());
+    assert_eq!(profile.bl_compatibility(), None);
+
+    assert_eq!(RpuProfile::new(3, None), RpuProfile::Unknown);
+    assert_eq!(RpuProfile::Unknown.number(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn generate_max_pq_clip() -> Result<()> {
+    use dolby_vision::rpu::extension_metadata::blocks::{
+        ExtMetadataBlockLevel1, ExtMetadataBlockLevel3,
+    };
+    use dolby_vision::rpu::generate::{MaxPqClip, VideoShot};
+    use dolby_vision::utils::nits_to_pq12;
+
+    let max_pq = nits_to_pq12(1000.0);
+
+    let clip = MaxPqClip {
+        max_pq,
+        knee_pq: None,
+    };
+    let roll_off = MaxPqClip {
+        max_pq,
+        knee_pq: Some(2700),
+    };
+
+    assert_eq!(clip.apply(2000), 2000);
+    assert_eq!(clip.apply(3500), max_pq);
+    assert_eq!(roll_off.apply(2700), 2700);
+
+    // Slope of 1 at the knee, monotonic and below the cap
+    assert_eq!(roll_off.apply(2701), 2701);
+    let rolled: Vec<u16> = (2700..=4095).map(|pq| roll_off.apply(pq)).collect();
+    assert!(rolled.windows(2).all(|w| w[0] <= w[1]));
+    assert!(rolled.iter().all(|&pq| pq < max_pq));
+
+    // The offset L1 max is capped as well
+    let mut shot = VideoShot {
+        duration: 1,
+        metadata_blocks: vec![
+            ExtMetadataBlock::Level1(ExtMetadataBlockLevel1::new(0, 3500, 3400)),
+            ExtMetadataBlock::Level3(ExtMetadataBlockLevel3 {
+                min_pq_offset: 2048,
+                max_pq_offset: 2148,
+                avg_pq_offset: 2048,
+            }),
+        ],
+        ..Default::default()
+    };
+    assert_eq!(shot.clip_max_pq(clip), 1);
+
+    let ExtMetadataBlock::Level1(level1) = &shot.metadata_blocks[0] else {
+        panic!("Missing L1");
+    };
+    assert_eq!((level1.max_pq, level1.avg_pq), (max_pq, max_pq - 1));
+
+    let ExtMetadataBlock::Level3(level3) = &shot.metadata_blocks[1] else {
+        panic!("Missing L3");
+    };
+    assert_eq!(level3.max_pq_offset, 2048);
+
+    // Static metadata with a 4000 nits MaxCLL
+    for (knee, expected_max) in [(None, max_pq), (Some(600.0), roll_off_max(600.0, 4000.0))] {
+        let args = GenerateArgs {
+            length: Some(10),
+            mdl_max: Some(4000),
+            max_cll: Some(4000),
+            max_pq_clip: Some(1000.0),
+            roll_off_knee: knee,
+            rpu_out: Some(PathBuf::from("/dev/null")),
+            ..Default::default()
+        };
+
+        let mut generator = Generator::from_args(args)?;
+        generator.execute()?;
+
+        let config = generator.config.unwrap();
+        assert_eq!(
+            config.level6.as_ref().unwrap().max_content_light_level,
+            1000
+        );
+
+        for rpu in config.generate_rpu_list()? {
+            let vdr_dm_data = rpu.vdr_dm_data.unwrap();
+            let Some(ExtMetadataBlock::Level1(level1)) = vdr_dm_data.get_block(1) else {
+                panic!("Missing L1");
+            };
+
+            assert_eq!(level1.max_pq, expected_max);
+        }
+    }
+
+    fn roll_off_max(knee_nits: f64, nits: f64) -> u16 {
+        MaxPqClip {
+            max_pq: nits_to_pq12(1000.0),
+            knee_pq: Some(nits_to_pq12(knee_nits)),
+        }
+        .apply(nits_to_pq12(nits))
+    }
+
+    let args = GenerateArgs {
+        length: Some(10),
+        mdl_max: Some(4000),
+        max_pq_clip: Some(1000.0),
+        roll_off_knee: Some(1000.0),
+        ..Default::default()
+    };
+    assert!(Generator::from_args(args).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn ext_mapping_idc() -> Result<()> {
+    let (original_data, mut dovi_rpu) = _parse_file(PathBuf::from("./assets/tests/fel_rpu.bin"))?;
+    assert_eq!(dovi_rpu.header.ext_mapping_idc(), 0);
+
+    dovi_rpu.header.ext_mapping_idc_lsb = 3;
+    dovi_rpu.header.ext_mapping_idc_msb = 1;
+
+    let data = dovi_rpu.write_hevc_unspec62_nalu()?;
+    assert_ne!(&original_data[4..], &data[2..]);
+
+    // The EL bit depth of the NLQ params is unchanged
+    let parsed_rpu = DoviRpu::parse_unspec62_nalu(&data)?;
+    assert_eq!(parsed_rpu.header.el_bit_depth_minus8, 2);
+    assert_eq!(parsed_rpu.header.ext_mapping_idc_lsb, 3);
+    assert_eq!(parsed_rpu.header.ext_mapping_idc_msb, 1);
+    assert_eq!(parsed_rpu.header.ext_mapping_idc(), 35);
+    assert_eq!(parsed_rpu.el_type, Some(DoviELType::FEL));
+
+    assert_eq!(parsed_rpu.write_hevc_unspec62_nalu()?, data);
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "compare-injected";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "--original <ORIGINAL> --injected <INJECTED> --rpu-in <RPU_IN>",
+        ));
+    Ok(())
+}
+
+#[test]
+fn identical() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+
+    let original = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let injected = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+    let rpu_in = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg("--original")
+        .arg(original)
+        .arg("--injected")
+        .arg(injected)
+        .arg("--rpu-in")
+        .arg(rpu_in)
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("PASS"));
+
+    Ok(())
+}
+
+#[test]
+fn video_mismatch() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let original = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let rpu_in = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    // Corrupt the last NALU of the stream
+    let mut data = std::fs::read("assets/hevc_tests/regular_start_code_4.hevc")?;
+    let last = data.len() - 1;
+    data[last] ^= 0xFF;
+
+    let injected = temp.child("injected.hevc");
+    injected.write_binary(&data)?;
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg("--original")
+        .arg(original)
+        .arg("--injected")
+        .arg(injected.as_ref())
+        .arg("--rpu-in")
+        .arg(rpu_in)
+        .assert();
+
+    assert
+        .failure()
+        .stdout(predicate::str::contains("FAIL: video NALU"))
+        .stderr(predicate::str::contains("Video data mismatch"));
+
+    Ok(())
+}
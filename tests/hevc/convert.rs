@@ -103,6 +103,49 @@ fn mode_lossless_discard() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn mode_auto() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+    let output_file = temp.child("BL_EL_RPU.hevc");
+
+    let assert = cmd
+        .arg("--mode")
+        .arg("auto")
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    // Profile 8 is already single layer
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("regular_start_code_4.hevc: profile 8, CM v").and(
+            predicate::str::contains("-> mode 0, already single layer profile 8"),
+        ),
+    );
+
+    output_file
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(input_file));
+
+    // Other commands can't pick the mode
+    let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--mode")
+        .arg("auto")
+        .arg("demux")
+        .arg(input_file)
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "--mode auto is only supported by convert",
+    ));
+
+    Ok(())
+}
+
 /// Edit config with specific active area
 #[test]
 fn edit_config() -> Result<()> {
@@ -152,6 +195,99 @@ fn edit_config() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn repeat_metadata() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+    let output_file = temp.child("BL_EL_RPU.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--repeat-metadata")
+        .arg("1000")
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: Omitting repeated metadata",
+        ));
+
+    // Extract result
+    let output_rpu = temp.child("RPU.bin");
+    let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("extract-rpu")
+        .arg(output_file.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 259);
+
+    // Full metadata on scene cuts
+    let vdr_dm_data: Vec<_> = rpus
+        .iter()
+        .map(|rpu| rpu.vdr_dm_data.as_ref().unwrap())
+        .collect();
+
+    assert!(vdr_dm_data
+        .iter()
+        .filter(|dm| dm.scene_refresh_flag == 1)
+        .all(|dm| dm.get_block(1).is_some()));
+    assert!(vdr_dm_data.iter().any(|dm| dm.get_block(1).is_none()));
+
+    Ok(())
+}
+
+#[test]
+fn summary_json() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+    let output_file = temp.child("BL_RPU.hevc");
+    let summary_file = temp.child("summary.json");
+
+    let assert = cmd
+        .arg("--mode")
+        .arg("2")
+        .arg("--summary-json")
+        .arg(summary_file.as_ref())
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--discard")
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("Summary:")
+            .and(predicate::str::contains("  Frames: 259"))
+            .and(predicate::str::contains(
+                "  Output profiles: Profile 8: 259",
+            )),
+    );
+
+    let summary: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(summary_file.path())?)?;
+
+    assert_eq!(summary["command"], "convert");
+    assert_eq!(summary["frames"], 259);
+    assert_eq!(summary["output_profiles"]["8"], 259);
+    assert_eq!(summary["warnings"], 0);
+
+    Ok(())
+}
+
 #[test]
 fn annexb() -> Result<()> {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
@@ -206,3 +342,35 @@ fn drop_hdr10plus_case() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn dry_run() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+    let output_file = temp.child("BL_RPU.hevc");
+
+    let assert = cmd
+        .arg("--mode")
+        .arg("2")
+        .arg("--dry-run")
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--discard")
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("Dry run, ")
+            .and(predicate::str::contains("  Frames: 259"))
+            .and(predicate::str::contains(
+                "  Output profiles: Profile 8: 259",
+            )),
+    );
+
+    output_file.assert(predicate::path::missing());
+
+    Ok(())
+}
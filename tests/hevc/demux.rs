@@ -211,3 +211,47 @@ fn annexb() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn checkpoints() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_start_code_4_muxed_el.hevc");
+    let expected_bl = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+
+    let output_bl = temp.child("BL.hevc");
+    let output_el = temp.child("EL.hevc");
+    let checkpoint = temp.child("BL.hevc.checkpoint.json");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--bl-out")
+        .arg(output_bl.as_ref())
+        .arg("--el-out")
+        .arg(output_el.as_ref())
+        .arg("--checkpoint-interval")
+        .arg("1")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    output_bl.assert(predicate::path::eq_file(expected_bl));
+
+    // Removed once the demux completes
+    checkpoint.assert(predicate::path::missing());
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--bl-out")
+        .arg(output_bl.as_ref())
+        .arg("--el-out")
+        .arg(output_el.as_ref())
+        .arg("--resume")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("nothing to resume"));
+
+    Ok(())
+}
@@ -113,3 +113,553 @@ fn edit_config() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn l6_from_sei() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular.hevc");
+
+    // Different L6 than the SEI, overwritten
+    let edit_config = temp.child("edit.json");
+    edit_config.write_str(
+        r#"{
+            "level6": {
+                "max_display_mastering_luminance": 4000,
+                "min_display_mastering_luminance": 50,
+                "max_content_light_level": 2000,
+                "max_frame_average_light_level": 500
+            }
+        }"#,
+    )?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    let assert = cmd
+        .arg("--edit-config")
+        .arg(edit_config.as_ref())
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .arg("--l6-from-sei")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "L6 metadata set from SEI: mastering display 0.0001/1000 nits, content light level MaxCLL 1000, MaxFALL 400",
+        ));
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 259);
+
+    rpus.iter().for_each(|rpu| {
+        let block = rpu.vdr_dm_data.as_ref().unwrap().get_block(6).unwrap();
+        if let ExtMetadataBlock::Level6(b) = block {
+            assert_eq!(b.max_display_mastering_luminance, 1000);
+            assert_eq!(b.min_display_mastering_luminance, 1);
+            assert_eq!(b.max_content_light_level, 1000);
+            assert_eq!(b.max_frame_average_light_level, 400);
+        } else {
+            panic!("No L6 metadata");
+        }
+    });
+
+    Ok(())
+}
+
+fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(box_type);
+    data.extend_from_slice(payload);
+
+    data
+}
+
+/// Single fragment, samples with a NAL length size of 4
+fn fmp4_fragment(samples: &[(Vec<u8>, i32)]) -> Vec<u8> {
+    let mfhd = mp4_box(b"mfhd", &[0, 0, 0, 0, 0, 0, 0, 1]);
+
+    // default-base-is-moof, track 1
+    let tfhd = mp4_box(b"tfhd", &[0, 0x02, 0, 0, 0, 0, 0, 1]);
+    let tfdt = mp4_box(b"tfdt", &[0; 8]);
+
+    // data offset, duration, size and composition offset
+    let mut trun_payload = vec![1, 0, 0x0B, 0x01];
+    trun_payload.extend_from_slice(&(samples.len() as u32).to_be_bytes());
+
+    let data_offset_pos = trun_payload.len();
+    trun_payload.extend_from_slice(&[0; 4]);
+
+    for (nal, composition_offset) in samples {
+        trun_payload.extend_from_slice(&1000_u32.to_be_bytes());
+        trun_payload.extend_from_slice(&(nal.len() as u32 + 4).to_be_bytes());
+        trun_payload.extend_from_slice(&composition_offset.to_be_bytes());
+    }
+
+    let traf_len = 8 + tfhd.len() + tfdt.len() + 8 + trun_payload.len();
+    let moof_len = 8 + mfhd.len() + traf_len;
+
+    // Samples start after the moof and mdat headers
+    let data_offset = (moof_len + 8) as u32;
+    trun_payload[data_offset_pos..data_offset_pos + 4].copy_from_slice(&data_offset.to_be_bytes());
+
+    let trun = mp4_box(b"trun", &trun_payload);
+    let traf = mp4_box(b"traf", &[tfhd, tfdt, trun].concat());
+    let moof = mp4_box(b"moof", &[mfhd, traf].concat());
+
+    let mdat_payload: Vec<u8> = samples
+        .iter()
+        .flat_map(|(nal, _)| [(nal.len() as u32).to_be_bytes().to_vec(), nal.clone()].concat())
+        .collect();
+
+    [moof, mp4_box(b"mdat", &mdat_payload)].concat()
+}
+
+#[test]
+fn fmp4_fragments() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let first_rpu = std::fs::read("assets/tests/profile5.bin")?;
+    let second_rpu = std::fs::read("assets/tests/profile5-02.bin")?;
+
+    // Replace start code with NAL header
+    let to_nal = |rpu: &[u8]| [&[0x7C, 0x01][..], &rpu[4..]].concat();
+
+    // Second sample is presented first
+    let fragment = fmp4_fragment(&[(to_nal(&first_rpu), 2000), (to_nal(&second_rpu), 0)]);
+
+    let input_file = temp.child("segment.m4s");
+    input_file.write_binary(&fragment)?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_rpu.assert(predicate::path::is_file());
+    assert_eq!(
+        std::fs::read(output_rpu.path())?,
+        [second_rpu, first_rpu].concat()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn fmp4_invalid_trun() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let rpu = std::fs::read("assets/tests/profile5.bin")?;
+    let nal = [&[0x7C, 0x01][..], &rpu[4..]].concat();
+    let fragment = fmp4_fragment(&[(nal, 0)]);
+
+    let trun_payload = fragment.windows(4).position(|w| w == b"trun").unwrap() + 4;
+
+    let patched = |pos: usize, value: u32| {
+        let mut data = fragment.clone();
+        data[pos..pos + 4].copy_from_slice(&value.to_be_bytes());
+
+        data
+    };
+
+    // No per sample duration, size and composition offset fields
+    let mut no_sample_fields = patched(trun_payload + 4, u32::MAX);
+    no_sample_fields[trun_payload + 2] = 0;
+
+    // Box with a 64-bit size overflowing the offsets
+    let mut largesize = fragment.clone();
+    largesize.extend([0, 0, 0, 1]);
+    largesize.extend(b"free");
+    largesize.extend(u64::MAX.to_be_bytes());
+
+    // Sample count larger than the trun box, or unbounded without per sample fields,
+    // sample size past the end of the file
+    let cases = [
+        (
+            patched(trun_payload + 4, u32::MAX),
+            "Invalid trun sample count",
+        ),
+        (no_sample_fields, "Invalid trun sample count"),
+        (
+            patched(trun_payload + 16, u32::MAX),
+            "exceeds the file size",
+        ),
+        (largesize, "Invalid box 'free'"),
+    ];
+
+    for (i, (data, error)) in cases.into_iter().enumerate() {
+        let input_file = temp.child(format!("segment-{i}.m4s"));
+        input_file.write_binary(&data)?;
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+            .arg(SUBCOMMAND)
+            .arg(input_file.as_ref())
+            .arg("--rpu-out")
+            .arg(temp.child("RPU.bin").as_ref())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(error));
+    }
+
+    Ok(())
+}
+
+/// HEIF file with HEVC image items, `rpu_items` are (item ID, RPU NAL unit)
+///
+/// The last `thumbnails` items are thumbnails of the item before them
+fn heif_file(rpu_items: &[(u16, Vec<u8>)], thumbnails: usize) -> Vec<u8> {
+    let ftyp = mp4_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+
+    let infe = |id: u16| {
+        mp4_box(
+            b"infe",
+            &[&[2, 0, 0, 0][..], &id.to_be_bytes(), &[0, 0], b"hvc1\0"].concat(),
+        )
+    };
+
+    let mut iinf_payload = vec![0, 0, 0, 0];
+    iinf_payload.extend_from_slice(&(rpu_items.len() as u16).to_be_bytes());
+    rpu_items
+        .iter()
+        .for_each(|(id, _)| iinf_payload.extend(infe(*id)));
+
+    let mut iref_payload = vec![0, 0, 0, 0];
+    let first_thumbnail = rpu_items.len() - thumbnails;
+    for i in first_thumbnail..rpu_items.len() {
+        let thmb = [
+            rpu_items[i].0.to_be_bytes(),
+            [0, 1],
+            rpu_items[i - 1].0.to_be_bytes(),
+        ]
+        .concat();
+        iref_payload.extend(mp4_box(b"thmb", &thmb));
+    }
+    let iref = mp4_box(b"iref", &iref_payload);
+
+    // The RPUs are the coded images, with a NAL length size of 4
+    let mdat_payload: Vec<u8> = rpu_items
+        .iter()
+        .flat_map(|(_, nal)| [(nal.len() as u32).to_be_bytes().to_vec(), nal.clone()].concat())
+        .collect();
+
+    let iloc = |mdat_offset: u32| {
+        let mut payload = vec![0, 0, 0, 0, 0x44, 0x00];
+        payload.extend_from_slice(&(rpu_items.len() as u16).to_be_bytes());
+
+        let mut offset = mdat_offset;
+        for (id, nal) in rpu_items {
+            let length = nal.len() as u32 + 4;
+
+            payload.extend_from_slice(&id.to_be_bytes());
+            payload.extend_from_slice(&[0, 0, 0, 1]);
+            payload.extend_from_slice(&offset.to_be_bytes());
+            payload.extend_from_slice(&length.to_be_bytes());
+
+            offset += length;
+        }
+
+        mp4_box(b"iloc", &payload)
+    };
+
+    let meta = |mdat_offset: u32| {
+        let hdlr = mp4_box(b"hdlr", &[&[0; 8][..], b"pict", &[0; 13]].concat());
+        let payload = [
+            vec![0, 0, 0, 0],
+            hdlr,
+            mp4_box(b"iinf", &iinf_payload),
+            iloc(mdat_offset),
+            iref.clone(),
+        ]
+        .concat();
+
+        mp4_box(b"meta", &payload)
+    };
+
+    // Samples start after the meta box and the mdat header
+    let mdat_offset = (ftyp.len() + meta(0).len() + 8) as u32;
+
+    [ftyp, meta(mdat_offset), mp4_box(b"mdat", &mdat_payload)].concat()
+}
+
+#[test]
+fn heif_items() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let first_rpu = std::fs::read("assets/tests/profile5.bin")?;
+    let second_rpu = std::fs::read("assets/tests/profile5-02.bin")?;
+
+    let to_nal = |rpu: &[u8]| [&[0x7C, 0x01][..], &rpu[4..]].concat();
+
+    // Items are ordered by ID, the last item is a thumbnail
+    let heif = heif_file(
+        &[
+            (2, to_nal(&first_rpu)),
+            (1, to_nal(&second_rpu)),
+            (3, to_nal(&second_rpu)),
+        ],
+        1,
+    );
+
+    let input_file = temp.child("image.heic");
+    input_file.write_binary(&heif)?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Reading HEIF images..."));
+
+    output_rpu.assert(predicate::path::is_file());
+    assert_eq!(
+        std::fs::read(output_rpu.path())?,
+        [second_rpu, first_rpu].concat()
+    );
+
+    Ok(())
+}
+
+/// Single program M2TS clip with the HEVC stream on PID 0x1011
+fn m2ts_clip(hevc: &[u8]) -> Vec<u8> {
+    let ts_packet = |pid: u16, unit_start: bool, payload: &[u8]| {
+        let mut packet = vec![0; 4];
+        packet.extend_from_slice(&[0x47, (pid >> 8) as u8, pid as u8]);
+
+        if unit_start {
+            packet[5] |= 0x40;
+        }
+
+        if payload.len() < 184 {
+            // Stuffing in the adaptation field
+            let af_length = 183 - payload.len();
+            packet.extend_from_slice(&[0x30, af_length as u8]);
+
+            if af_length > 0 {
+                packet.push(0);
+                packet.resize(packet.len() + af_length - 1, 0xFF);
+            }
+        } else {
+            packet.push(0x10);
+        }
+
+        packet.extend_from_slice(payload);
+        packet
+    };
+
+    let pat = [
+        0, 0x00, 0xB0, 13, 0, 1, 0xC1, 0, 0, 0, 1, 0xE1, 0x00, 0, 0, 0, 0,
+    ];
+    let pmt = [
+        0, 0x02, 0xB0, 18, 0, 1, 0xC1, 0, 0, 0xF0, 0x11, 0xF0, 0, 0x24, 0xF0, 0x11, 0xF0, 0, 0, 0,
+        0, 0,
+    ];
+
+    let pes = [&[0, 0, 1, 0xE0, 0, 0, 0x80, 0, 0][..], hevc].concat();
+
+    let mut clip = [ts_packet(0, true, &pat), ts_packet(0x100, true, &pmt)].concat();
+
+    for (i, payload) in pes.chunks(184).enumerate() {
+        clip.extend(ts_packet(0x1011, i == 0, payload));
+    }
+
+    clip
+}
+
+#[test]
+fn m2ts_clip_input() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let hevc = std::fs::read("assets/hevc_tests/regular.hevc")?;
+
+    let clip = temp.child("00001.m2ts");
+    clip.write_binary(&m2ts_clip(&hevc))?;
+
+    let output_rpu = temp.child("RPU.bin");
+    let expected_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(clip.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_rpu
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_rpu));
+
+    Ok(())
+}
+
+fn mpls_playlist(clips: &[&str]) -> Vec<u8> {
+    let mut data = b"MPLS0200".to_vec();
+
+    // PlayList start address, PlayListMark and extension addresses
+    data.extend_from_slice(&40_u32.to_be_bytes());
+    data.resize(40, 0);
+
+    data.extend_from_slice(&[0; 6]);
+    data.extend_from_slice(&(clips.len() as u16).to_be_bytes());
+    data.extend_from_slice(&[0; 2]);
+
+    for clip in clips {
+        data.extend_from_slice(&9_u16.to_be_bytes());
+        data.extend_from_slice(clip.as_bytes());
+        data.extend_from_slice(b"M2TS");
+    }
+
+    data
+}
+
+#[test]
+fn mpls_playlist_clips() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let hevc = std::fs::read("assets/hevc_tests/regular.hevc")?;
+    let expected_rpus =
+        dolby_vision::rpu::utils::parse_rpu_file("assets/hevc_tests/regular_rpu.bin")?;
+
+    temp.child("BDMV/STREAM/00001.m2ts")
+        .write_binary(&m2ts_clip(&hevc))?;
+
+    let playlist = temp.child("BDMV/PLAYLIST/00800.mpls");
+    playlist.write_binary(&mpls_playlist(&["00001", "00001"]))?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(playlist.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.path())?;
+    assert_eq!(rpus.len(), expected_rpus.len() * 2);
+
+    // Every clip starts with a scene cut
+    let second_clip_rpu = &rpus[expected_rpus.len()];
+    assert_eq!(
+        second_clip_rpu
+            .vdr_dm_data
+            .as_ref()
+            .unwrap()
+            .scene_refresh_flag,
+        1
+    );
+
+    Ok(())
+}
+
+#[test]
+fn mpls_playlist_summary_json() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let hevc = std::fs::read("assets/hevc_tests/regular.hevc")?;
+
+    temp.child("BDMV/STREAM/00001.m2ts")
+        .write_binary(&m2ts_clip(&hevc))?;
+
+    let playlist = temp.child("BDMV/PLAYLIST/00800.mpls");
+    playlist.write_binary(&mpls_playlist(&["00001"]))?;
+
+    let output_rpu = temp.child("RPU.bin");
+    let summary = temp.child("summary.json");
+
+    let assert = cmd
+        .arg("--summary-json")
+        .arg(summary.as_ref())
+        .arg(SUBCOMMAND)
+        .arg(playlist.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "--summary-json is not supported for playlists",
+    ));
+
+    output_rpu.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+/// Every other access unit moved to temporal layer 1
+#[test]
+fn temporal_id() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut data = std::fs::read("assets/hevc_tests/regular.hevc")?;
+    let mut au_index = 0;
+
+    for i in 0..data.len().saturating_sub(5) {
+        if data[i..i + 3] != [0, 0, 1] {
+            continue;
+        }
+
+        let nal_type = (data[i + 3] >> 1) & 0x3F;
+        let first_slice = nal_type < 32 && data[i + 5] & 0x80 != 0;
+
+        if first_slice {
+            au_index += 1;
+        }
+
+        if (nal_type < 32 || nal_type == 62) && au_index % 2 == 0 {
+            data[i + 4] = (data[i + 4] & 0xF8) | 2;
+        }
+    }
+
+    let input_file = temp.child("temporal.hevc");
+    input_file.write_binary(&data)?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .arg("--temporal-id")
+        .arg("0")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("0: 130 frames, 130 RPUs"))
+        .stdout(predicate::str::contains(
+            "1: 129 frames, 129 RPUs (skipped)",
+        ));
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 130);
+
+    Ok(())
+}
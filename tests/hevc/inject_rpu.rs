@@ -5,6 +5,8 @@ use assert_cmd::Command;
 use assert_fs::prelude::*;
 use predicates::prelude::*;
 
+use dolby_vision::rpu::extension_metadata::blocks::{ExtMetadataBlock, ExtMetadataBlockLevel6};
+
 const SUBCOMMAND: &str = "inject-rpu";
 
 #[test]
@@ -109,6 +111,352 @@ fn inject_no_add_aud() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn inject_no_aud_mid_stream_parameter_sets() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let bl = std::fs::read("assets/hevc_tests/regular_bl_start_code_4.hevc")?;
+
+    // No AUDs, and the parameter sets repeated before every picture
+    let bl_nals = split_nals(&bl);
+    let parameter_sets: Vec<&[u8]> = bl_nals
+        .iter()
+        .copied()
+        .filter(|nal| matches!(nal_type(nal), 32..=34))
+        .collect();
+
+    let mut input_nals = Vec::new();
+    let mut first_picture = true;
+
+    for nal in bl_nals.iter().copied() {
+        if nal_type(nal) == 35 {
+            continue;
+        }
+
+        if nal_type(nal) < 32 && nal[2] & 0x80 != 0 {
+            if !first_picture {
+                input_nals.extend(parameter_sets.iter().copied());
+            }
+
+            first_picture = false;
+        }
+
+        input_nals.push(nal);
+    }
+
+    let input_file = temp.child("no_aud_parameter_sets.hevc");
+    input_file.write_binary(&join_nals(&input_nals))?;
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file.as_ref())
+        .arg("--rpu-in")
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--no-add-aud")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let output = std::fs::read(output_file.path())?;
+    let output_nals = split_nals(&output);
+
+    let pictures = input_nals
+        .iter()
+        .filter(|nal| nal_type(nal) < 32 && nal[2] & 0x80 != 0)
+        .count();
+    let rpu_count = output_nals.iter().filter(|nal| nal_type(nal) == 62).count();
+    assert_eq!(rpu_count, pictures);
+
+    // RPUs always directly follow the picture, never the next parameter sets
+    for pair in output_nals.windows(2) {
+        if nal_type(pair[1]) == 62 {
+            assert!(!matches!(nal_type(pair[0]), 32..=35 | 39));
+        }
+    }
+
+    // Otherwise unchanged
+    let without_rpu: Vec<&[u8]> = output_nals
+        .iter()
+        .copied()
+        .filter(|nal| nal_type(nal) != 62)
+        .collect();
+    assert_eq!(without_rpu, input_nals);
+
+    Ok(())
+}
+
+#[test]
+fn passthrough_nalus() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let bl = std::fs::read("assets/hevc_tests/regular_bl_start_code_4.hevc")?;
+
+    let bl_nals = split_nals(&bl);
+    let parameter_sets: Vec<&[u8]> = bl_nals
+        .iter()
+        .copied()
+        .filter(|nal| matches!(nal_type(nal), 32..=34))
+        .collect();
+
+    // user_data_unregistered SEI messages
+    let sei_payload = [&[0x05, 0x11][..], &[0x42; 16], &[0x01, 0x80]].concat();
+    let prefix_sei = [&[0x4E, 0x01][..], &sei_payload].concat();
+    let suffix_sei = [&[0x50, 0x01][..], &sei_payload].concat();
+
+    let unspec48 = [0x60, 0x01, 0xAB, 0xCD];
+    // With trailing zero bytes
+    let filler_data = [0x4C, 0x01, 0xFF, 0xFF, 0xFF, 0x80, 0x00, 0x00];
+
+    // Slices with trailing cabac_zero_words
+    let slices: Vec<Vec<u8>> = bl_nals
+        .iter()
+        .filter(|nal| nal_type(nal) < 32)
+        .map(|nal| [nal, &[0x00, 0x00, 0x03][..]].concat())
+        .collect();
+
+    let mut input_nals: Vec<&[u8]> = Vec::new();
+    let mut slices_iter = slices.iter();
+    let mut first_picture = true;
+
+    for nal in bl_nals.iter().copied() {
+        if nal_type(nal) >= 32 {
+            input_nals.push(nal);
+            continue;
+        }
+
+        if nal[2] & 0x80 != 0 {
+            if !first_picture {
+                input_nals.extend(parameter_sets.iter().copied());
+            }
+
+            input_nals.extend([prefix_sei.as_slice(), &unspec48]);
+            first_picture = false;
+        }
+
+        input_nals.push(slices_iter.next().unwrap());
+        input_nals.extend([suffix_sei.as_slice(), &filler_data]);
+    }
+
+    let input_file = temp.child("exotic.hevc");
+    input_file.write_binary(&join_nals(&input_nals))?;
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file.as_ref())
+        .arg("--rpu-in")
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--no-add-aud")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("input NALUs written unchanged"));
+
+    let output = std::fs::read(output_file.path())?;
+    let output_nals = split_nals(&output);
+
+    let pictures = slices.iter().filter(|nal| nal[2] & 0x80 != 0).count();
+    let rpu_count = output_nals.iter().filter(|nal| nal_type(nal) == 62).count();
+    assert_eq!(rpu_count, pictures);
+
+    // Every other NALU is unchanged and in order, AUDs included
+    let without_rpu: Vec<&[u8]> = output_nals
+        .iter()
+        .copied()
+        .filter(|nal| nal_type(nal) != 62)
+        .collect();
+    assert_eq!(without_rpu, input_nals);
+
+    // The AUDs are replaced and the SEI rewritten, the other NALUs are still checked
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg(SUBCOMMAND)
+        .arg(input_file.as_ref())
+        .arg("--rpu-in")
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--hdr10plus")
+        .arg("strip")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("input NALUs written unchanged"));
+
+    Ok(())
+}
+
+#[test]
+fn hdr10plus_strip_and_last() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    // HDR10+ prefix SEI added after every AUD, the second one also with a user data unregistered message
+    let bl_data = std::fs::read("assets/hevc_tests/regular_bl_start_code_4.hevc")?;
+    let hdr10plus_sei: &[u8] = &[0x4E, 0x01, 4, 8, 0xB5, 0, 0x3C, 0, 1, 4, 1, 0x40, 0x80];
+    let multi_sei: &[u8] = &[
+        0x4E, 0x01, 4, 8, 0xB5, 0, 0x3C, 0, 1, 4, 1, 0x40, 5, 16, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10,
+        11, 12, 13, 14, 15, 16, 0x80,
+    ];
+
+    let mut input_nals = Vec::new();
+    let mut aud_count = 0;
+
+    for nal in split_nals(&bl_data) {
+        input_nals.push(nal);
+
+        if nal_type(nal) == 35 {
+            input_nals.push(if aud_count == 1 {
+                multi_sei
+            } else {
+                hdr10plus_sei
+            });
+            aud_count += 1;
+        }
+    }
+
+    let input_file = temp.child("hdr10plus.hevc");
+    input_file.write_binary(&join_nals(&input_nals))?;
+
+    let is_hdr10plus = |nal: &[u8]| {
+        matches!(nal_type(nal), 39 | 40) && nal.windows(7).any(|w| w == [0xB5, 0, 0x3C, 0, 1, 4, 1])
+    };
+
+    let inject = |mode: &str| -> Result<Vec<u8>> {
+        let output_file = temp.child(format!("injected_{mode}.hevc"));
+
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        let assert = cmd
+            .arg(SUBCOMMAND)
+            .arg(input_file.as_ref())
+            .arg("--rpu-in")
+            .arg(input_rpu)
+            .arg("--output")
+            .arg(output_file.as_ref())
+            .arg("--hdr10plus")
+            .arg(mode)
+            .assert();
+
+        assert.success().stderr(predicate::str::is_empty());
+
+        Ok(std::fs::read(output_file.path())?)
+    };
+
+    let hdr10plus_count = |nals: &[&[u8]], nal_type_value: u8| {
+        nals.iter()
+            .filter(|nal| nal_type(nal) == nal_type_value && is_hdr10plus(nal))
+            .count()
+    };
+
+    let kept = inject("keep")?;
+    assert_eq!(hdr10plus_count(&split_nals(&kept), 39), aud_count);
+
+    let stripped = inject("strip")?;
+    assert!(!split_nals(&stripped).iter().any(|nal| is_hdr10plus(nal)));
+
+    // Suffix SEI directly before the RPU
+    let first = inject("first")?;
+    let first_nals = split_nals(&first);
+    assert_eq!(hdr10plus_count(&first_nals, 39), 0);
+    assert_eq!(hdr10plus_count(&first_nals, 40), aud_count);
+
+    for pair in first_nals.windows(2) {
+        if is_hdr10plus(pair[0]) {
+            assert_eq!(nal_type(pair[1]), 62);
+        }
+    }
+
+    // Suffix SEI directly after the RPU
+    let last = inject("last")?;
+    let last_nals = split_nals(&last);
+    assert_eq!(hdr10plus_count(&last_nals, 39), 0);
+    assert_eq!(hdr10plus_count(&last_nals, 40), aud_count);
+
+    for pair in last_nals.windows(2) {
+        if is_hdr10plus(pair[1]) {
+            assert_eq!(nal_type(pair[0]), 62);
+        }
+    }
+
+    // The other message stays in the prefix SEI
+    let unregistered_sei: &[u8] = &[0x4E, 0x01, 5, 16, 1, 2, 3];
+    assert!(last_nals
+        .iter()
+        .any(|nal| nal.starts_with(unregistered_sei)));
+
+    Ok(())
+}
+
+#[test]
+fn dry_run() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg("--dry-run")
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--rpu-in")
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("Dry run, ")
+            .and(predicate::str::contains("Summary:"))
+            .and(predicate::str::contains("  Frames: 259")),
+    );
+
+    output_file.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+fn split_nals(data: &[u8]) -> Vec<&[u8]> {
+    let starts: Vec<usize> = data
+        .windows(4)
+        .enumerate()
+        .filter_map(|(i, w)| (w == [0, 0, 0, 1]).then_some(i + 4))
+        .collect();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).map_or(data.len(), |next| next - 4);
+            &data[start..end]
+        })
+        .collect()
+}
+
+fn join_nals(nals: &[&[u8]]) -> Vec<u8> {
+    nals.iter()
+        .flat_map(|nal| [&[0, 0, 0, 1][..], nal].concat())
+        .collect()
+}
+
+fn nal_type(nal: &[u8]) -> u8 {
+    (nal[0] >> 1) & 0x3F
+}
+
 #[test]
 fn annexb() -> Result<()> {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
@@ -140,6 +488,114 @@ fn annexb() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn rpu_start_code_three() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--rpu-in")
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--rpu-start-code")
+        .arg("three")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let data = std::fs::read(output_file.path())?;
+    let rpu_nals_with_start_code = |start_code: &[u8]| {
+        let pattern = [start_code, &[0x7C, 0x01]].concat();
+        data.windows(pattern.len())
+            .filter(|w| *w == pattern)
+            .count()
+    };
+
+    assert!(rpu_nals_with_start_code(&[0, 0, 1]) > 0);
+    assert_eq!(rpu_nals_with_start_code(&[0, 0, 0, 1]), 0);
+
+    Ok(())
+}
+
+#[test]
+fn rpu_start_code_auto() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    let output_file = temp.child("injected_output.hevc");
+    let expected_bl_rpu = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--rpu-in")
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--rpu-start-code")
+        .arg("auto")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Using 4 bytes RPU start code"));
+
+    output_file
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_bl_rpu));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_rpu_streamed() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+
+    let mut data = std::fs::read("assets/hevc_tests/regular_rpu.bin")?;
+
+    // Flip a bit in the first frame's payload
+    data[20] ^= 0x01;
+
+    let input_rpu = temp.child("RPU.bin");
+    input_rpu.write_binary(&data)?;
+
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--rpu-in")
+        .arg(input_rpu.as_ref())
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert();
+
+    // Reported by the RPU reader when the first frame is written
+    assert
+        .failure()
+        .stderr(predicate::str::contains("Found invalid RPU: Index 0"));
+
+    // The partial output is removed
+    output_file.assert(predicate::path::missing());
+
+    Ok(())
+}
+
 #[test]
 fn duplicated_end() -> Result<()> {
     // Generate shorter RPU
@@ -161,13 +617,33 @@ fn duplicated_end() -> Result<()> {
 
     output_rpu.assert(predicate::path::is_file());
 
-    // Inject and expect to duplicate
-    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
-
     let input_bl = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
     let output_file = temp.child("injected_output.hevc");
 
+    // Fails before writing without --lenient
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_bl)
+        .arg("--rpu-in")
+        .arg(output_rpu.as_ref())
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "The RPU file has 249 fewer frames than the video (RPU 10, video 259)",
+        ))
+        .stderr(predicate::str::contains(
+            "access units (AUD): 259, pictures (slice headers): 259, frames (order info): 259, RPUs: 10",
+        ));
+
+    output_file.assert(predicate::path::missing());
+
+    // Inject and expect to duplicate
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+
     let assert = cmd
+        .arg("--lenient")
         .arg(SUBCOMMAND)
         .arg(input_bl)
         .arg("--rpu-in")
@@ -207,6 +683,84 @@ fn duplicated_end() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn l6_from_sei() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let generate_config = temp.child("generate.json");
+    generate_config.write_str(
+        r#"{
+            "cm_version": "V29",
+            "length": 259,
+            "level6": {
+                "max_display_mastering_luminance": 4000,
+                "min_display_mastering_luminance": 50,
+                "max_content_light_level": 0,
+                "max_frame_average_light_level": 0
+            }
+        }"#,
+    )?;
+
+    let generated_rpu = temp.child("RPU.bin");
+
+    cmd.arg("generate")
+        .arg("--json")
+        .arg(generate_config.as_ref())
+        .arg("--rpu-out")
+        .arg(generated_rpu.as_ref())
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+
+    let input_bl = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let output_file = temp.child("injected_output.hevc");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_bl)
+        .arg("--rpu-in")
+        .arg(generated_rpu.as_ref())
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--l6-from-sei")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Setting L6 metadata from SEI"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let output_rpu = temp.child("RPU_injected.bin");
+
+    cmd.arg("extract-rpu")
+        .arg(output_file.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success();
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 259);
+
+    for rpu in &rpus {
+        let block = rpu.vdr_dm_data.as_ref().unwrap().get_block(6).unwrap();
+        assert_eq!(
+            block,
+            &ExtMetadataBlock::Level6(ExtMetadataBlockLevel6 {
+                max_display_mastering_luminance: 1000,
+                min_display_mastering_luminance: 1,
+                max_content_light_level: 1000,
+                max_frame_average_light_level: 400,
+            })
+        );
+    }
+
+    Ok(())
+}
+
 // Makes sure the injected RPU NALU is placed after SEI_SUFFIX NALUs
 #[test]
 fn sei_suffix_before_rpu() -> Result<()> {
@@ -268,3 +822,279 @@ fn sei_suffix_before_rpu() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn truncate_rpu() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let expected_bl_rpu = Path::new("assets/hevc_tests/regular_start_code_4.hevc");
+    let regular_rpu = std::fs::read("assets/hevc_tests/regular_rpu.bin")?;
+
+    // 10 extra RPUs
+    let extra_rpu = temp.child("extra.bin");
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("generate")
+        .arg("--json")
+        .arg("assets/generator_examples/default_cmv29.json")
+        .arg("--rpu-out")
+        .arg(extra_rpu.as_ref())
+        .assert()
+        .success();
+    let extra_rpu = std::fs::read(extra_rpu.path())?;
+
+    let output_file = temp.child("injected_output.hevc");
+    let inject = |rpu: &[u8], args: &[&str]| -> Result<assert_cmd::assert::Assert> {
+        let input_rpu = temp.child("RPU.bin");
+        input_rpu.write_binary(rpu)?;
+
+        Ok(Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+            .arg(SUBCOMMAND)
+            .arg(input_file)
+            .arg("--rpu-in")
+            .arg(input_rpu.as_ref())
+            .arg("--output")
+            .arg(output_file.as_ref())
+            .args(args)
+            .assert())
+    };
+
+    let longer_rpu = [regular_rpu.as_slice(), &extra_rpu].concat();
+
+    inject(&longer_rpu, &[])?
+        .failure()
+        .stderr(predicate::str::contains(
+            "The RPU file has 10 more frames than the video (RPU 269, video 259)",
+        ))
+        .stderr(predicate::str::contains(
+            "access units (AUD): 259, pictures (slice headers): 259, frames (order info): 259, RPUs: 269",
+        ));
+    output_file.assert(predicate::path::missing());
+
+    inject(&longer_rpu, &["--truncate-rpu"])?
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "dropped the last 10 RPUs (259-268)",
+        ));
+    output_file.assert(predicate::path::eq_file(expected_bl_rpu));
+
+    let longer_rpu = [extra_rpu.as_slice(), &regular_rpu].concat();
+
+    inject(&longer_rpu, &["--truncate-rpu", "--align-end"])?
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "dropped the first 10 RPUs (0-9). Video frame 0 uses RPU 10",
+        ));
+    output_file.assert(predicate::path::eq_file(expected_bl_rpu));
+
+    Ok(())
+}
+
+#[test]
+fn bl_bit_depth_mismatch() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    // Signaled as 12-bit, the video is 10-bit
+    let edit_config = temp.child("bit_depth.json");
+    edit_config.write_str(r#"{ "bl_bit_depth": 12 }"#)?;
+
+    let edited_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("editor")
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(edited_rpu.as_ref())
+        .assert()
+        .success();
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(edited_rpu.as_ref())?;
+    assert_eq!(rpus[0].header.bl_bit_depth_minus8, 4);
+
+    let output_file = temp.child("injected_output.hevc");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--dry-run")
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--rpu-in")
+        .arg(edited_rpu.as_ref())
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Warning: the RPU signals a 12-bit BL, but the video is 10-bit",
+        ));
+
+    // Matching bit depth
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--dry-run")
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--rpu-in")
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("-bit BL").not());
+
+    Ok(())
+}
+
+#[test]
+fn av_sync_guard() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let data = std::fs::read("assets/hevc_tests/regular_bl_start_code_4.hevc")?;
+
+    // Drop the AUD of the 100th access unit
+    let mut aud_count = 0;
+    let nals: Vec<&[u8]> = split_nals(&data)
+        .into_iter()
+        .filter(|nal| {
+            if nal_type(nal) != 35 {
+                return true;
+            }
+
+            aud_count += 1;
+            aud_count != 100
+        })
+        .collect();
+
+    let input_file = temp.child("missing_aud.hevc");
+    input_file.write_binary(&join_nals(&nals))?;
+
+    let output_file = temp.child("injected_output.hevc");
+    let inject = |args: &[&str]| -> Result<assert_cmd::assert::Assert> {
+        Ok(Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+            .args(args)
+            .arg(SUBCOMMAND)
+            .arg(input_file.as_ref())
+            .arg("--rpu-in")
+            .arg(input_rpu)
+            .arg("--output")
+            .arg(output_file.as_ref())
+            .assert())
+    };
+
+    let breakdown =
+        "access units (AUD): 258, pictures (slice headers): 259, frames (order info): 259, RPUs: 259";
+
+    inject(&[])?
+        .failure()
+        .stderr(predicate::str::contains(
+            "Frame count mismatch, the RPUs would be out of sync with the video.",
+        ))
+        .stderr(predicate::str::contains(breakdown));
+
+    inject(&["--lenient"])?
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(format!(
+            "Warning: frame count mismatch, the RPUs may be out of sync with the video. {breakdown}"
+        )));
+
+    output_file.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn checkpoint_resume() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    let rpu_file = temp.child("RPU.bin");
+    let expected_file = temp.child("expected.hevc");
+    let output_file = temp.child("injected_output.hevc");
+    let checkpoint = temp.child("injected_output.hevc.checkpoint.json");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--rpu-in")
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(expected_file.as_ref())
+        .assert()
+        .success();
+
+    // Wrong CRC32 for the RPU of frame 15, interrupting the run
+    let rpu_data = std::fs::read(input_rpu)?;
+    let rpu_starts: Vec<usize> = rpu_data
+        .windows(5)
+        .enumerate()
+        .filter(|(_, w)| w == &[0, 0, 0, 1, 0x19])
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut corrupt_data = rpu_data.clone();
+    corrupt_data[rpu_starts[16] - 3] ^= 0xFF;
+    rpu_file.write_binary(&corrupt_data)?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--rpu-in")
+        .arg(rpu_file.as_ref())
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--checkpoint-interval")
+        .arg("0")
+        .assert()
+        .failure();
+
+    // Fixed RPU file, with the checkpoint updated to its modification time
+    rpu_file.write_binary(&rpu_data)?;
+
+    let modified = std::fs::metadata(rpu_file.path())?
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    let mut checkpoint_json: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(checkpoint.path())?)?;
+    checkpoint_json["inputs"][1]["modified"] = modified.into();
+    std::fs::write(checkpoint.path(), checkpoint_json.to_string())?;
+
+    let frames_done = checkpoint_json["frames_done"].as_u64().unwrap();
+    let output_offset = checkpoint_json["outputs"][0]["offset"].as_u64().unwrap();
+    assert!(frames_done > 0 && frames_done < 15);
+
+    // Partially written access unit after the checkpoint
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(output_file.path())?
+        .set_len(output_offset + 100)?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_file)
+        .arg("--rpu-in")
+        .arg(rpu_file.as_ref())
+        .arg("--output")
+        .arg(output_file.as_ref())
+        .arg("--resume")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Resuming from frame {frames_done}"
+        )));
+
+    output_file.assert(predicate::path::eq_file(expected_file.path()));
+    checkpoint.assert(predicate::path::missing());
+
+    Ok(())
+}
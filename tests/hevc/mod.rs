@@ -1,5 +1,7 @@
+mod compare_injected;
 mod convert;
 mod demux;
 mod extract_rpu;
 mod inject_rpu;
 mod mux;
+mod watch;
@@ -0,0 +1,104 @@
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "watch";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("dovi_tool watch [OPTIONS]"));
+    Ok(())
+}
+
+#[test]
+fn extract_once() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_file = Path::new("assets/hevc_tests/regular.hevc");
+    let expected_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    temp.child("regular.HEVC").write_file(input_file)?;
+    temp.child("notes.txt").write_str("not processed")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(temp.path())
+        .arg("--steps")
+        .arg("extract")
+        .arg("--once")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Processed regular.HEVC"));
+
+    temp.child("processed/regular.bin")
+        .assert(predicate::path::eq_file(expected_rpu));
+    temp.child("processed/notes.bin")
+        .assert(predicate::path::missing());
+
+    let state: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.child(".dovi_tool_watch.json").path())?)?;
+    assert!(state["files"]["regular.HEVC"]["error"].is_null());
+    assert!(state["files"]["notes.txt"].is_null());
+
+    // Already processed, skipped
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(temp.path())
+        .arg("--steps")
+        .arg("extract")
+        .arg("--once")
+        .assert();
+
+    assert
+        .success()
+        .stdout(predicate::str::contains("Processing").not());
+
+    Ok(())
+}
+
+#[test]
+fn failed_step() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    temp.child("RPU.bin")
+        .write_file(Path::new("assets/hevc_tests/regular_rpu.bin"))?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(temp.path())
+        .arg("--steps")
+        .arg("extract")
+        .arg("--once")
+        .assert();
+
+    assert
+        .failure()
+        .stdout(predicate::str::contains(
+            "Failed to process RPU.bin: extract: requires a HEVC file",
+        ))
+        .stderr(predicate::str::contains("1 file(s) failed to process"));
+
+    let state: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.child(".dovi_tool_watch.json").path())?)?;
+    assert_eq!(
+        state["files"]["RPU.bin"]["error"],
+        "extract: requires a HEVC file"
+    );
+
+    Ok(())
+}
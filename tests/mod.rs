@@ -25,3 +25,48 @@ fn version() -> Result<()> {
     assert.success().stderr(predicate::str::is_empty());
     Ok(())
 }
+
+#[test]
+fn completions() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd.arg("completions").arg("bash").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("_dovi_tool()"));
+    Ok(())
+}
+
+#[test]
+fn dump_cli_json() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let output = cmd.arg("--dump-cli-json").output()?;
+
+    assert!(output.status.success());
+
+    let cli: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(cli["name"], "dovi_tool");
+
+    let subcommands = cli["subcommands"].as_array().unwrap();
+    let convert = subcommands
+        .iter()
+        .find(|sub| sub["name"] == "convert")
+        .unwrap();
+
+    assert!(convert["args"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|arg| arg["long"] == "discard"));
+    Ok(())
+}
+
+#[test]
+fn missing_subcommand() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd.arg("--crop").assert();
+
+    assert.failure();
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "bitrate";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "dovi_tool bitrate [OPTIONS] [input_pos]",
+        ));
+    Ok(())
+}
+
+#[test]
+fn csv_windows() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_csv = temp.child("bitrate.csv");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--threshold")
+        .arg("1")
+        .arg("--csv")
+        .arg(output_csv.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Frames: 259"))
+        .stdout(predicate::str::contains(
+            "Windows above 1 bytes/s: 11 of 11",
+        ));
+
+    let csv = std::fs::read_to_string(output_csv.path())?;
+    let mut lines = csv.lines();
+
+    assert_eq!(
+        lines.next(),
+        Some("start_frame,end_frame,start_seconds,bytes,bytes_per_second,spike")
+    );
+
+    let rows: Vec<&str> = lines.collect();
+    assert_eq!(rows.len(), 11);
+    assert!(rows[0].starts_with("0,23,0.000,"));
+    assert!(rows[10].starts_with("240,258,"));
+    assert!(rows.iter().all(|row| row.ends_with(",true")));
+
+    Ok(())
+}
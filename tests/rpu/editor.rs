@@ -101,6 +101,94 @@ fn remove_cmv4() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn remove_cmv29() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let generate_config = temp.child("config.json");
+    generate_config.write_str(
+        &serde_json::json!({
+            "length": 5,
+            "shots": [{ "start": 0, "duration": 5 }],
+            "default_metadata_blocks": [
+                {
+                    "Level2": {
+                        "target_max_pq": 2081,
+                        "trim_slope": 2048,
+                        "trim_offset": 2048,
+                        "trim_power": 2048,
+                        "trim_chroma_weight": 2048,
+                        "trim_saturation_gain": 2048,
+                        "ms_weight": 2048
+                    }
+                },
+                { "Level4": { "anchor_pq": 100, "anchor_power": 200 } }
+            ]
+        })
+        .to_string(),
+    )?;
+
+    let input_rpu = temp.child("RPU_cmv40.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("generate")
+        .arg("--json")
+        .arg(generate_config.as_ref())
+        .arg("--rpu-out")
+        .arg(input_rpu.as_ref())
+        .assert()
+        .success();
+
+    let edit_config = temp.child("remove_cmv29.json");
+    edit_config.write_str(r#"{ "remove_cmv29": true }"#)?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu.as_ref())
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: CMv2.9 only devices will tone map without trims",
+        ));
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 5);
+
+    for rpu in &rpus {
+        let vdr_dm_data = rpu.vdr_dm_data.as_ref().unwrap();
+
+        assert!(vdr_dm_data.get_block(2).is_none());
+        assert!(vdr_dm_data.get_block(4).is_none());
+        assert!(vdr_dm_data.get_block(1).is_some());
+        assert!(vdr_dm_data.get_block(254).is_some());
+    }
+
+    // No trims left
+    edit_config.write_str(r#"{ "remove_cmv29": true, "remove_cmv4": true }"#)?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu.as_ref())
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "remove_cmv4 and remove_cmv29 can't be used together",
+        ));
+
+    Ok(())
+}
+
 #[test]
 fn active_area_specific() -> Result<()> {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
@@ -154,6 +242,65 @@ fn active_area_specific() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn drop_l5_below() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let active_area_config = Path::new("assets/editor_examples/active_area.json");
+    let edit_config = Path::new("assets/editor_examples/drop_l5_below.json");
+
+    let active_area_rpu = temp.child("active_area.bin");
+    let output_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(active_area_config)
+        .arg("--rpu-out")
+        .arg(active_area_rpu.as_ref())
+        .assert()
+        .success();
+
+    let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(active_area_rpu.as_ref())
+        .arg("--json")
+        .arg(edit_config)
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 259);
+
+    // Letterbox offsets are kept
+    let block = rpus[40].vdr_dm_data.as_ref().unwrap().get_block(5).unwrap();
+    if let ExtMetadataBlock::Level5(b) = block {
+        assert_eq!(vec![0, 0, 210, 210], b.get_offsets_vec());
+    }
+
+    assert!(rpus[41]
+        .vdr_dm_data
+        .as_ref()
+        .unwrap()
+        .get_block(5)
+        .is_none());
+    assert!(rpus
+        .last()
+        .unwrap()
+        .vdr_dm_data
+        .as_ref()
+        .unwrap()
+        .get_block(5)
+        .is_none());
+
+    Ok(())
+}
+
 #[test]
 fn add_l9_l11_no_effect() -> Result<()> {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
@@ -271,6 +418,42 @@ fn add_l9_l11() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn header_edits() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/tests/fel_orig.bin");
+    let edit_config = Path::new("assets/editor_examples/header_edits.json");
+
+    let output_rpu = temp.child("RPU.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config)
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_rpu.assert(predicate::path::is_file());
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 1);
+
+    let rpu = &rpus[0];
+    assert_eq!(rpu.dovi_profile, 7);
+
+    // Only the profile 7 edit applies
+    assert!(rpu.header.bl_video_full_range_flag);
+    assert_eq!(rpu.header.vdr_rpu_level, 0);
+
+    Ok(())
+}
+
 #[test]
 fn source_rpu() -> Result<()> {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
@@ -299,3 +482,676 @@ fn source_rpu() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn level8_vectors() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/tests/mel_variable_l8_length13.bin");
+    let edit_config = Path::new("assets/editor_examples/level8_vectors.json");
+
+    let output_rpu = temp.child("RPU.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config)
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_rpu.assert(predicate::path::is_file());
+
+    let original_rpus = dolby_vision::rpu::utils::parse_rpu_file(input_rpu)?;
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+
+    let original_blocks = original_rpus[0]
+        .vdr_dm_data
+        .as_ref()
+        .unwrap()
+        .level_blocks_iter(8);
+    let blocks = rpus[0].vdr_dm_data.as_ref().unwrap().level_blocks_iter(8);
+
+    let mut level8_count = 0;
+
+    for (original, block) in original_blocks.zip(blocks) {
+        if let (ExtMetadataBlock::Level8(original), ExtMetadataBlock::Level8(level8)) =
+            (original, block)
+        {
+            level8_count += 1;
+
+            // Extended to include the hue vectors
+            assert_eq!(level8.length, 25);
+
+            let saturation = level8.saturation_vectors();
+            assert_eq!(saturation[0], 140);
+            assert_eq!(saturation[1], original.saturation_vectors()[1] + 4);
+
+            let hue = level8.hue_vectors();
+            assert_eq!(hue[5], original.hue_vectors()[5] - 3);
+            assert_eq!(hue[..5], original.hue_vectors()[..5]);
+        }
+    }
+
+    assert!(level8_count > 0);
+
+    Ok(())
+}
+
+#[test]
+fn time_positions() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let edit_config = Path::new("assets/editor_examples/active_area_timecodes.json");
+
+    let output_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config)
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 259);
+
+    let offsets = |index: usize| {
+        let vdr_dm_data = rpus[index].vdr_dm_data.as_ref().unwrap();
+
+        match vdr_dm_data.get_block(5) {
+            Some(ExtMetadataBlock::Level5(b)) => b.get_offsets_vec(),
+            _ => unreachable!(),
+        }
+    };
+
+    // 00:00:01:16 at 24 fps is frame 40
+    assert_eq!(offsets(40), vec![0, 0, 210, 210]);
+    assert_eq!(offsets(41), vec![0, 0, 0, 0]);
+
+    // 2s at 24 fps is frame 48
+    assert_eq!(rpus[48].vdr_dm_data.as_ref().unwrap().scene_refresh_flag, 1);
+
+    // Not on a frame boundary
+    let ambiguous_config = temp.child("ambiguous.json");
+    ambiguous_config.write_str(r#"{ "fps": 24, "remove": ["0.01s-1s"] }"#)?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(ambiguous_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Ambiguous time"));
+
+    Ok(())
+}
+
+#[test]
+fn dry_run() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu_mel.bin");
+    let edit_config = Path::new("assets/editor_examples/remove.json");
+
+    let output_rpu = temp.child("RPU.bin");
+
+    let assert = cmd
+        .arg("--dry-run")
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config)
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("Final metadata length: 219")
+            .and(predicate::str::contains("  Frames modified: 0"))
+            .and(predicate::str::contains("  Frames removed: 40"))
+            .and(predicate::str::contains("  Frames duplicated: 0"))
+            .and(predicate::str::contains("  Frames: 219")),
+    );
+
+    output_rpu.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn scene_cut_offset() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_rpu = temp.child("RPU.bin");
+
+    let scene_cut_frames = |rpus: &[dolby_vision::rpu::dovi_rpu::DoviRpu]| -> Vec<usize> {
+        rpus.iter()
+            .enumerate()
+            .filter(|(_, rpu)| rpu.vdr_dm_data.as_ref().unwrap().scene_refresh_flag == 1)
+            .map(|(i, _)| i)
+            .collect()
+    };
+
+    let original_cuts = scene_cut_frames(&dolby_vision::rpu::utils::parse_rpu_file(input_rpu)?);
+    let frame_count = 259;
+
+    for offset in [-3_i64, 5] {
+        let edit_config = temp.child("scene_cut_offset.json");
+        edit_config.write_str(&format!(r#"{{ "scene_cut_offset": {offset} }}"#))?;
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+            .arg(SUBCOMMAND)
+            .arg(input_rpu)
+            .arg("--json")
+            .arg(edit_config.as_ref())
+            .arg("--rpu-out")
+            .arg(output_rpu.as_ref())
+            .assert()
+            .success()
+            .stderr(predicate::str::is_empty());
+
+        let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+        assert_eq!(rpus.len(), frame_count);
+
+        let mut expected: Vec<usize> = original_cuts
+            .iter()
+            .map(|&i| (i as i64 + offset).max(0))
+            .filter(|&i| i < frame_count as i64)
+            .map(|i| i as usize)
+            .chain(std::iter::once(0))
+            .collect();
+        expected.sort_unstable();
+        expected.dedup();
+
+        assert_eq!(scene_cut_frames(&rpus), expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn offset() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_rpu = temp.child("RPU.bin");
+
+    let original = dolby_vision::rpu::utils::parse_rpu_file(input_rpu)?;
+    let vdr = |rpus: &[dolby_vision::rpu::dovi_rpu::DoviRpu], index: usize| {
+        rpus[index].vdr_dm_data.clone().unwrap()
+    };
+
+    for offset in [5_i64, -3] {
+        let edit_config = temp.child("offset.json");
+        edit_config.write_str(&format!(r#"{{ "offset": {offset} }}"#))?;
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+            .arg(SUBCOMMAND)
+            .arg(input_rpu)
+            .arg("--json")
+            .arg(edit_config.as_ref())
+            .arg("--rpu-out")
+            .arg(output_rpu.as_ref())
+            .assert()
+            .success()
+            .stderr(predicate::str::is_empty());
+
+        let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+        assert_eq!(rpus.len() as i64, 259 + offset);
+
+        if offset > 0 {
+            // The padding extends the first shot
+            for i in 0..=5 {
+                assert!(vdr(&rpus, i).metadata_eq(&vdr(&original, 0)));
+                assert_eq!(vdr(&rpus, i).scene_refresh_flag, (i == 0) as u64);
+            }
+
+            assert_eq!(vdr(&rpus, 100), vdr(&original, 95));
+        } else {
+            assert!(vdr(&rpus, 0).metadata_eq(&vdr(&original, 3)));
+            assert_eq!(vdr(&rpus, 0).scene_refresh_flag, 1);
+
+            assert_eq!(vdr(&rpus, 100), vdr(&original, 103));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn frame_doubling() -> Result<()> {
+    use dolby_vision::rpu::dovi_rpu::DoviRpu;
+    use dolby_vision::rpu::extension_metadata::blocks::ExtMetadataBlock;
+
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_rpu = temp.child("RPU.bin");
+    let edit_config = temp.child("frame_doubling.json");
+
+    let original = dolby_vision::rpu::utils::parse_rpu_file(input_rpu)?;
+
+    let scene_cut = |rpu: &DoviRpu| rpu.vdr_dm_data.as_ref().unwrap().scene_refresh_flag == 1;
+    let level1 = |rpu: &DoviRpu| {
+        if let Some(ExtMetadataBlock::Level1(l1)) = rpu.vdr_dm_data.as_ref().unwrap().get_block(1) {
+            (l1.min_pq, l1.max_pq, l1.avg_pq)
+        } else {
+            panic!("No L1 block");
+        }
+    };
+
+    for mode in ["duplicate", "interpolate"] {
+        edit_config.write_str(&format!(r#"{{ "frame_doubling": "{mode}" }}"#))?;
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+            .arg(SUBCOMMAND)
+            .arg(input_rpu)
+            .arg("--json")
+            .arg(edit_config.as_ref())
+            .arg("--rpu-out")
+            .arg(output_rpu.as_ref())
+            .assert()
+            .success()
+            .stderr(predicate::str::is_empty());
+
+        let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+        assert_eq!(rpus.len(), original.len() * 2);
+
+        for (i, rpu) in original.iter().enumerate() {
+            let (first, added) = (&rpus[i * 2], &rpus[i * 2 + 1]);
+
+            assert_eq!(scene_cut(first), scene_cut(rpu));
+            assert!(!scene_cut(added));
+            assert_eq!(level1(first), level1(rpu));
+
+            let expected = match original.get(i + 1) {
+                Some(next) if mode == "interpolate" && !scene_cut(next) => {
+                    let (a, b) = (level1(rpu), level1(next));
+                    let midpoint = |a: u16, b: u16| (a + b + 1) / 2;
+
+                    (midpoint(a.0, b.0), midpoint(a.1, b.1), midpoint(a.2, b.2))
+                }
+                _ => level1(rpu),
+            };
+            assert_eq!(level1(added), expected);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn batch_templates() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpus = [
+        Path::new("assets/hevc_tests/regular_rpu.bin"),
+        Path::new("assets/hevc_tests/regular_rpu_mel.bin"),
+    ];
+
+    // 50% of 259 frames is frame 129
+    let edit_config = temp.child("batch.json");
+    edit_config.write_str(r#"{ "remove": ["50%-100%"] }"#)?;
+
+    let output_template = temp.path().join("${input_stem}_cut.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg("--batch")
+        .args(input_rpus)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(&output_template)
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    for stem in ["regular_rpu", "regular_rpu_mel"] {
+        let output_rpu = temp.child(format!("{stem}_cut.bin"));
+        output_rpu.assert(predicate::path::is_file());
+
+        let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+        assert_eq!(rpus.len(), 129);
+    }
+
+    // Every file would be written to the same output
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg("--batch")
+        .args(input_rpus)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(temp.child("RPU.bin").as_ref())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("template variable"));
+
+    Ok(())
+}
+
+#[test]
+fn insert_black() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    // 5 frames leader, 3 frames trailer
+    let edit_config = temp.child("insert_black.json");
+    edit_config.write_str(
+        r#"{ "insert_black": [{ "offset": 0, "length": 5 }, { "offset": 259, "length": 3 }] }"#,
+    )?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 267);
+
+    let frame = |index: usize| {
+        let vdr_dm_data = rpus[index].vdr_dm_data.as_ref().unwrap();
+
+        let max_pq = match vdr_dm_data.get_block(1) {
+            Some(ExtMetadataBlock::Level1(b)) => b.max_pq,
+            _ => unreachable!(),
+        };
+
+        (vdr_dm_data.scene_refresh_flag, max_pq)
+    };
+
+    assert_eq!(frame(0), (1, 0));
+    assert_eq!(frame(4), (0, 0));
+    assert_eq!(frame(5).0, 1);
+    assert_ne!(frame(5).1, 0);
+
+    assert_eq!(frame(264), (1, 0));
+    assert_eq!(frame(266), (0, 0));
+
+    Ok(())
+}
+
+#[test]
+fn freeze() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let edit_config = Path::new("assets/editor_examples/freeze.json");
+
+    let output_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config)
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let original = dolby_vision::rpu::utils::parse_rpu_file(input_rpu)?;
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 259);
+
+    let vdr = |rpus: &[dolby_vision::rpu::dovi_rpu::DoviRpu], index: usize| {
+        rpus[index].vdr_dm_data.clone().unwrap()
+    };
+
+    // Scene cuts kept
+    for i in 41..=59 {
+        assert!(vdr(&rpus, i).metadata_eq(&vdr(&original, 40)));
+        assert_eq!(
+            vdr(&rpus, i).scene_refresh_flag,
+            vdr(&original, i).scene_refresh_flag
+        );
+    }
+    assert_eq!(vdr(&rpus, 60), vdr(&original, 60));
+
+    // Single shot
+    for i in 120..=129 {
+        assert!(vdr(&rpus, i).metadata_eq(&vdr(&original, 120)));
+        assert_eq!(vdr(&rpus, i).scene_refresh_flag, (i == 120) as u64);
+    }
+    assert_eq!(vdr(&rpus, 130).scene_refresh_flag, 1);
+
+    Ok(())
+}
+
+#[test]
+fn rewrite_rpu_ids() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    let edit_config = temp.child("rpu_ids.json");
+    edit_config.write_str(
+        r#"{ "header_edits": [{ "conditions": { "vdr_rpu_id": 0 }, "set": { "vdr_rpu_id": 3 } }] }"#,
+    )?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 259);
+
+    rpus.iter().for_each(|rpu| {
+        assert_eq!(rpu.rpu_data_mapping.as_ref().unwrap().vdr_rpu_id, 3);
+    });
+
+    // Out of range
+    edit_config.write_str(r#"{ "header_edits": [{ "set": { "vdr_rpu_id": 16 } }] }"#)?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid value 16 for RPU header field 'vdr_rpu_id', max 15",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn active_area_bar_detection() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    // Noisy detection, the second shot is within the jitter tolerance of the first
+    let detection = temp.child("bars.json");
+    detection.write_str(
+        r#"[
+            { "start": 0, "end": 49, "top": 138, "bottom": 140, "confidence": 0.9 },
+            { "start": 50, "end": 99, "top": 140, "bottom": 139 },
+            { "start": 100, "end": 199, "top": 139, "bottom": 141 },
+            { "start": 200, "end": 258, "top": 0, "bottom": 1 }
+        ]"#,
+    )?;
+
+    let edit_config = temp.child("bar_detection.json");
+    edit_config.write_str(
+        &serde_json::json!({
+            "scene_cuts": {
+                "0-99": false,
+                "100-100": true,
+                "101-199": false,
+                "200-200": true,
+                "201-258": false
+            },
+            "active_area": {
+                "bar_detection": detection.path()
+            }
+        })
+        .to_string(),
+    )?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Setting active area offsets from bar detection, 3 shots...",
+        ));
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 259);
+
+    let offsets = |index: usize| match rpus[index].vdr_dm_data.as_ref().unwrap().get_block(5) {
+        Some(ExtMetadataBlock::Level5(b)) => b.get_offsets_vec(),
+        _ => unreachable!(),
+    };
+
+    assert_eq!(offsets(0), vec![0, 0, 138, 139]);
+    assert_eq!(offsets(99), vec![0, 0, 138, 139]);
+    assert_eq!(offsets(150), vec![0, 0, 138, 139]);
+    assert_eq!(offsets(258), vec![0, 0, 0, 0]);
+
+    Ok(())
+}
+
+#[test]
+fn mmr_edits() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let generate_config = temp.child("config.json");
+    generate_config.write_str(
+        r#"{ "profile": "8.4", "length": 10, "shots": [{ "start": 0, "duration": 10 }] }"#,
+    )?;
+
+    let input_rpu = temp.child("RPU_p84.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("generate")
+        .arg("--json")
+        .arg(generate_config.as_ref())
+        .arg("--rpu-out")
+        .arg(input_rpu.as_ref())
+        .assert()
+        .success();
+
+    let coef_int = vec![vec![0, 0, 0, 0, 0, 0, 0]];
+    let coef = vec![vec![1, 2, 3, 4, 5, 6, 7]];
+
+    let edit_config = temp.child("mmr_edits.json");
+    edit_config.write_str(
+        &serde_json::json!({
+            "mmr_edits": [{
+                "range": "2-4",
+                "component": 1,
+                "mmr_order_minus1": 0,
+                "mmr_constant_int": 1,
+                "mmr_constant": 100,
+                "mmr_coef_int": coef_int,
+                "mmr_coef": coef
+            }]
+        })
+        .to_string(),
+    )?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu.as_ref())
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Editing MMR coefficients..."));
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 10);
+
+    for (frame, rpu) in rpus.iter().enumerate() {
+        let mapping = rpu.rpu_data_mapping.as_ref().unwrap();
+        let mmr = mapping.curves[1].mmr.as_ref().unwrap();
+
+        if (2..=4).contains(&frame) {
+            assert_eq!(mmr.mmr_order_minus1, vec![0]);
+            assert_eq!(mmr.mmr_constant_int, vec![1]);
+            assert_eq!(mmr.mmr_constant, vec![100]);
+            assert_eq!(mmr.mmr_coef_int[0], coef_int);
+            assert_eq!(mmr.mmr_coef[0], coef);
+        } else {
+            assert_eq!(mmr.mmr_order_minus1, vec![2]);
+        }
+
+        // Other chroma component untouched
+        let mmr2 = mapping.curves[2].mmr.as_ref().unwrap();
+        assert_eq!(mmr2.mmr_order_minus1, vec![2]);
+    }
+
+    // Coefficients not matching the order
+    edit_config.write_str(
+        r#"{ "mmr_edits": [{ "range": "all", "component": 2, "mmr_order_minus1": 1, "mmr_constant": 0, "mmr_coef": [[1, 2, 3, 4, 5, 6, 7]] }] }"#,
+    )?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu.as_ref())
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "mmr_coef should have 2 orders of 7 coefficients for mmr_order_minus1 1",
+        ));
+
+    Ok(())
+}
@@ -45,3 +45,444 @@ fn exports_json() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn exports_xml_names() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_json = temp.child("RPU_export.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--xml-names")
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    output_json.assert(
+        predicate::str::contains("\"ImageCharacterMax\"")
+            .and(predicate::str::contains("\"TrimSlope\""))
+            .and(predicate::str::contains("\"max_pq\"").not()),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn exports_shot_stats() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_json = temp.child("RPU_export.json");
+    let output_stats = temp.child("RPU_shot_stats.json");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--shot-stats")
+        .arg(output_stats.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Exporting shot statistics..."));
+
+    output_stats.assert(predicate::path::is_file());
+
+    let stats: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(output_stats.path())?)?;
+    let shots = stats.as_array().unwrap();
+
+    assert_eq!(shots.len(), 3);
+    assert_eq!(shots[0]["start"], 0);
+
+    Ok(())
+}
+
+#[test]
+fn exports_compressed_json() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/tests/fel_orig.bin");
+    let output_json = temp.child("RPU_export.json");
+    let output_zstd = temp.child("RPU_export.json.zst");
+    let output_brotli = temp.child("RPU_export.json.br");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .assert()
+        .success();
+
+    for (compression, output) in [("zstd", &output_zstd), ("brotli", &output_brotli)] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        let assert = cmd
+            .arg(SUBCOMMAND)
+            .arg(input_rpu)
+            .arg("--compress")
+            .arg(compression)
+            .arg("--output")
+            .arg(output.as_ref())
+            .assert();
+
+        assert.success().stderr(predicate::str::is_empty());
+        output.assert(predicate::path::is_file());
+    }
+
+    let expected = std::fs::read(output_json.path())?;
+
+    let zstd_json = zstd::decode_all(std::fs::File::open(output_zstd.path())?)?;
+    assert_eq!(zstd_json, expected);
+
+    let mut brotli_json = Vec::new();
+    brotli::BrotliDecompress(
+        &mut std::fs::File::open(output_brotli.path())?,
+        &mut brotli_json,
+    )?;
+    assert_eq!(brotli_json, expected);
+
+    Ok(())
+}
+
+#[test]
+fn exports_frame_props() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_json = temp.child("RPU_export.json");
+    let output_props = temp.child("RPU_frame_props.json");
+    let output_script = temp.child("RPU_frame_props.py");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--frame-props")
+        .arg(output_props.as_ref())
+        .arg("--frame-props-format")
+        .arg("json")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Exporting frame props..."));
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(input_rpu)?;
+
+    let props: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(output_props.path())?)?;
+    let frames = props.as_array().unwrap();
+
+    assert_eq!(frames.len(), rpus.len());
+    assert_eq!(frames[0]["DolbyVisionSceneCut"], 1);
+    assert!(frames.iter().all(|f| f["DolbyVisionL1MaxPQ"].is_u64()));
+
+    let scene_cuts = frames
+        .iter()
+        .filter(|f| f["DolbyVisionSceneCut"] == 1)
+        .count();
+    assert_eq!(scene_cuts, 3);
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--frame-props")
+        .arg(output_script.as_ref())
+        .assert()
+        .success();
+
+    output_script
+        .assert(predicate::str::contains(
+            "def apply_dovi_props(clip: vs.VideoNode)",
+        ))
+        .assert(predicate::str::contains(r#"{"DolbyVisionSceneCut":1,"#));
+
+    Ok(())
+}
+
+#[test]
+fn exports_annotations() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_json = temp.child("RPU_export.json");
+    let output_stats = temp.child("RPU_shot_stats.json");
+    let output_props = temp.child("RPU_frame_props.json");
+
+    let annotations = temp.child("annotations.json");
+    annotations.write_str(
+        r#"{
+            "annotations": [
+                { "start": 0, "text": "Fix this shot's trim" },
+                { "start": 120, "duration": 20, "text": "Check L5" },
+                { "start": 110, "duration": 20, "text": "Fade" }
+            ]
+        }"#,
+    )?;
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--shot-stats")
+        .arg(output_stats.as_ref())
+        .arg("--frame-props")
+        .arg(output_props.as_ref())
+        .arg("--frame-props-format")
+        .arg("json")
+        .arg("--annotations")
+        .arg(annotations.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let stats: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(output_stats.path())?)?;
+    // Shots 0-119 and 120-218, the fade overlaps both
+    assert_eq!(
+        stats[0]["annotations"],
+        serde_json::json!(["Fix this shot's trim", "Fade"])
+    );
+    assert_eq!(
+        stats[1]["annotations"],
+        serde_json::json!(["Check L5", "Fade"])
+    );
+    assert!(stats[2].get("annotations").is_none());
+
+    let props: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(output_props.path())?)?;
+    let frames = props.as_array().unwrap();
+
+    assert_eq!(frames[0]["DolbyVisionAnnotation"], "Fix this shot's trim");
+    assert!(frames[1].get("DolbyVisionAnnotation").is_none());
+    assert_eq!(frames[110]["DolbyVisionAnnotation"], "Fade");
+    assert_eq!(frames[125]["DolbyVisionAnnotation"], "Check L5; Fade");
+    assert_eq!(frames[139]["DolbyVisionAnnotation"], "Check L5");
+    assert!(frames[140].get("DolbyVisionAnnotation").is_none());
+
+    // Only on the annotated frames of the RPU export
+    let rpu_json: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(output_json.path())?)?;
+    assert_eq!(
+        rpu_json[0]["annotations"],
+        serde_json::json!(["Fix this shot's trim"])
+    );
+    assert_eq!(
+        rpu_json[125]["annotations"],
+        serde_json::json!(["Check L5", "Fade"])
+    );
+    assert!(rpu_json[1].get("annotations").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn exports_shot_labels() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_json = temp.child("RPU_export.json");
+    let output_stats = temp.child("RPU_shot_stats.json");
+
+    let generate_config = temp.child("config.json");
+    generate_config.write_str(
+        r#"{
+            "length": 259,
+            "shots": [
+                {
+                    "start": 0,
+                    "duration": 1,
+                    "labels": { "name": "Cold open", "reel": "R1", "scene": "12A" }
+                },
+                { "start": 1, "duration": 258 }
+            ]
+        }"#,
+    )?;
+
+    // The labels don't affect the generation
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("generate")
+        .arg("--json")
+        .arg(generate_config.as_ref())
+        .arg("--rpu-out")
+        .arg(temp.child("RPU_generated.bin").as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--shot-stats")
+        .arg(output_stats.as_ref())
+        .arg("--annotations")
+        .arg(generate_config.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let stats: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(output_stats.path())?)?;
+    let shots = stats.as_array().unwrap();
+
+    assert_eq!(
+        shots[0]["labels"],
+        serde_json::json!({ "name": "Cold open", "reel": "R1", "scene": "12A" })
+    );
+    assert!(shots[1..].iter().all(|shot| shot.get("labels").is_none()));
+
+    Ok(())
+}
+
+#[test]
+fn exports_primaries() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // CM v4.0 configs default to DCI-P3 D65 L9 source primaries
+    let generate_config = Path::new("assets/generator_examples/default_cmv40.json");
+    let generated_rpu = temp.child("RPU.bin");
+    let output_json = temp.child("RPU_export.json");
+    let output_primaries = temp.child("primaries.json");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("generate")
+        .arg("--json")
+        .arg(generate_config)
+        .arg("--rpu-out")
+        .arg(generated_rpu.as_ref())
+        .assert()
+        .success();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(generated_rpu.as_ref())
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--primaries")
+        .arg(output_primaries.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let primaries: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(output_primaries.path())?)?;
+
+    assert_eq!(primaries["source_primary_index"], 0);
+    assert_eq!(primaries["red"], serde_json::json!([0.68, 0.32]));
+
+    let row_sum = |matrix: &str, row: usize| -> f64 {
+        primaries[matrix][row]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_f64().unwrap())
+            .sum()
+    };
+
+    // RGB white maps to Y = 1, and to ACES white
+    assert!((row_sum("rgb_to_xyz", 1) - 1.0).abs() < 1e-6);
+    for row in 0..3 {
+        assert!((row_sum("rgb_to_aces2065_1", row) - 1.0).abs() < 1e-6);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn exports_shot_boundaries() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let input_video = Path::new("assets/hevc_tests/regular.hevc");
+    let output_json = temp.child("RPU_export.json");
+    let output_report = temp.child("shot_boundaries.json");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--shot-boundaries")
+        .arg(output_report.as_ref())
+        .arg("--video")
+        .arg(input_video)
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let report: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(output_report.path())?)?;
+
+    assert_eq!(report["frames"], 259);
+    assert_eq!(report["idr_frames"], 2);
+
+    let boundaries = report["boundaries"].as_array().unwrap();
+    assert_eq!(report["shots"], boundaries.len());
+    assert_eq!(boundaries[0]["frame"], 0);
+    assert_eq!(boundaries[0]["on_idr"], true);
+
+    let misaligned = boundaries.iter().filter(|b| b["on_idr"] == false).count();
+    assert_eq!(report["misaligned_shots"], misaligned);
+
+    Ok(())
+}
+
+#[test]
+fn exports_timeline() -> Result<()> {
+    use dolby_vision::rpu::timeline::{MetadataTimeline, TimelineEntry, TIMELINE_HEADER_SIZE};
+    use dolby_vision::rpu::utils::parse_rpu_file;
+
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_json = temp.child("RPU_export.json");
+    let output_timeline = temp.child("timeline.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--output")
+        .arg(output_json.as_ref())
+        .arg("--timeline")
+        .arg(output_timeline.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let rpus = parse_rpu_file(input_rpu)?;
+    let data = std::fs::read(output_timeline.path())?;
+    assert_eq!(data.len(), TIMELINE_HEADER_SIZE + rpus.len() * 16);
+    assert_eq!(&data[0..4], b"DVTL");
+
+    let timeline = MetadataTimeline::new(&data)?;
+    assert_eq!(timeline.len(), rpus.len());
+
+    for (entry, rpu) in timeline.iter().zip(&rpus) {
+        assert_eq!(entry, TimelineEntry::from_rpu(rpu));
+        assert!(entry.has_l1);
+    }
+
+    assert!(timeline.iter().any(|entry| entry.scene_cut));
+
+    assert!(timeline.get(rpus.len()).is_none());
+    assert!(MetadataTimeline::new(&data[..TIMELINE_HEADER_SIZE + 8]).is_err());
+
+    Ok(())
+}
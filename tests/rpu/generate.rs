@@ -21,6 +21,33 @@ fn help() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn generate_compressed_config() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let config = std::fs::read("assets/generator_examples/default_cmv40.json")?;
+    let generate_config = temp.child("config.json.zst");
+    generate_config.write_binary(&zstd::encode_all(config.as_slice(), 0)?)?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg("--json")
+        .arg(generate_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 10);
+
+    Ok(())
+}
+
 #[test]
 fn generate_default_cmv29() -> Result<()> {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
@@ -358,6 +385,67 @@ fn generate_full_hdr10plus() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn hdr10plus_backfill() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // Config length of 10 frames, the HDR10+ metadata has 9
+    let generate_config = Path::new("assets/generator_examples/default_cmv40.json");
+    let hdr10plus_json = Path::new("./assets/tests/hdr10plus_metadata.json");
+
+    let output_rpu = temp.child("RPU.bin");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg("--json")
+        .arg(generate_config)
+        .arg("--hdr10plus-json")
+        .arg(hdr10plus_json)
+        .arg("--backfill")
+        .arg("repeat-last-shot")
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Source covers 9 of the 10 frames of the config, 1 frames backfilled by repeating the last shot",
+        ));
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 10);
+
+    let last_vdr_dm_data = rpus[9].vdr_dm_data.as_ref().unwrap();
+    assert_eq!(last_vdr_dm_data.scene_refresh_flag, 0);
+    assert_eq!(
+        last_vdr_dm_data.get_block(1),
+        rpus[8].vdr_dm_data.as_ref().unwrap().get_block(1)
+    );
+
+    // Missing frames are an error with the `error` policy
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg("--json")
+        .arg(generate_config)
+        .arg("--hdr10plus-json")
+        .arg(hdr10plus_json)
+        .arg("--backfill")
+        .arg("error")
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert
+        .failure()
+        .stderr(predicate::str::contains("1 frames are missing"));
+
+    Ok(())
+}
+
 #[test]
 fn xml_cmv2_9_with_l5() -> Result<()> {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
@@ -416,6 +504,39 @@ fn xml_cmv4_0_2() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn xml_cmv4_0_2_streaming() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let xml = Path::new("assets/tests/cmv4_0_2.xml");
+    let output_rpu = temp.child("RPU.bin");
+
+    let expected_rpu = Path::new("assets/tests/cmv4_0_2_xml_rpu.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg("--xml")
+        .arg(xml)
+        .arg("--xml-streaming")
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Generated metadata for 259 frames",
+        ));
+
+    output_rpu
+        .assert(predicate::path::is_file())
+        .assert(predicate::path::eq_file(expected_rpu));
+
+    Ok(())
+}
+
 #[test]
 fn xml_cmv4_0_2_with_l5() -> Result<()> {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
@@ -686,3 +807,151 @@ fn l1_cmv29_override_avg_cmv40() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn inject_into() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let generate_config = Path::new("assets/generator_examples/default_cmv40.json");
+    let input_bl = Path::new("assets/hevc_tests/regular_bl_start_code_4.hevc");
+
+    let generated_rpu = temp.child("RPU.bin");
+    let injected_separately = temp.child("injected_separately.hevc");
+    let injected_directly = temp.child("injected_directly.hevc");
+
+    // The config is shorter than the video, the last RPU is duplicated
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg("--json")
+        .arg(generate_config)
+        .arg("--rpu-out")
+        .arg(generated_rpu.as_ref())
+        .assert()
+        .success();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--lenient")
+        .arg("inject-rpu")
+        .arg(input_bl)
+        .arg("--rpu-in")
+        .arg(generated_rpu.as_ref())
+        .arg("-o")
+        .arg(injected_separately.as_ref())
+        .assert()
+        .success();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--lenient")
+        .arg(SUBCOMMAND)
+        .arg("--json")
+        .arg(generate_config)
+        .arg("--inject-into")
+        .arg(input_bl)
+        .arg("--inject-output")
+        .arg(injected_directly.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    injected_directly.assert(predicate::path::eq_file(injected_separately.path()));
+
+    // The global start code preset applies to both
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--lenient")
+        .arg("--start-code")
+        .arg("annex-b")
+        .arg("inject-rpu")
+        .arg(input_bl)
+        .arg("--rpu-in")
+        .arg(generated_rpu.as_ref())
+        .arg("-o")
+        .arg(injected_separately.as_ref())
+        .assert()
+        .success();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--lenient")
+        .arg("--start-code")
+        .arg("annex-b")
+        .arg(SUBCOMMAND)
+        .arg("--json")
+        .arg(generate_config)
+        .arg("--inject-into")
+        .arg(input_bl)
+        .arg("--inject-output")
+        .arg(injected_directly.as_ref())
+        .assert()
+        .success();
+
+    injected_directly.assert(predicate::path::eq_file(injected_separately.path()));
+
+    Ok(())
+}
+
+#[test]
+fn from_y4m_stdin() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let output_rpu = temp.child("RPU.bin");
+
+    // 16x8 4:2:0 10-bit frames, the left half black
+    let (width, height) = (16, 8);
+    let frame = |right_luma: u16| {
+        let mut data = b"FRAME\n".to_vec();
+
+        for _ in 0..height {
+            for x in 0..width {
+                let luma = if x < width / 2 { 64_u16 } else { right_luma };
+                data.extend(luma.to_le_bytes());
+            }
+        }
+
+        for _ in 0..(width * height / 2) {
+            data.extend(512_u16.to_le_bytes());
+        }
+
+        data
+    };
+
+    let mut y4m = b"YUV4MPEG2 W16 H8 F24000:1001 Ip A1:1 C420p10 XCOLORRANGE=LIMITED\n".to_vec();
+
+    // 60% and 75% PQ, the second scene starting at frame 3
+    for right_luma in [590, 595, 590, 721, 721, 721] {
+        y4m.extend(frame(right_luma));
+    }
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg("--from-y4m")
+        .arg("-")
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .write_stdin(y4m)
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Measured 6 frames, 2 scenes detected",
+        ));
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 6);
+
+    let frame_l1 = |index: usize| {
+        let vdr_dm_data = rpus[index].vdr_dm_data.as_ref().unwrap();
+
+        let l1 = match vdr_dm_data.get_block(1) {
+            Some(ExtMetadataBlock::Level1(b)) => (b.min_pq, b.max_pq, b.avg_pq),
+            _ => unreachable!(),
+        };
+
+        (vdr_dm_data.scene_refresh_flag, l1)
+    };
+
+    assert_eq!(frame_l1(0), (1, (0, 2459, 1230)));
+    assert_eq!(frame_l1(1), (0, (0, 2482, 1241)));
+    assert_eq!(frame_l1(3), (1, (0, 3071, 1536)));
+    assert_eq!(frame_l1(5), (0, (0, 3071, 1536)));
+
+    Ok(())
+}
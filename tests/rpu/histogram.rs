@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "histogram";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "dovi_tool histogram [OPTIONS] [input_pos]",
+        ));
+    Ok(())
+}
+
+#[test]
+fn histogram_p7() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let output_file = temp.child("L1_histogram.png");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--per-shot")
+        .arg("--plot")
+        .arg(output_file.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("Frames: 259, shots: 3")
+            .and(predicate::str::contains("L1 max:"))
+            .and(predicate::str::contains("L1 average:"))
+            .and(predicate::str::contains(
+                "Shots L1 max (min / 25% / median / 75% / max nits):",
+            )),
+    );
+
+    output_file.assert(predicate::path::is_file());
+
+    Ok(())
+}
+
+#[test]
+fn outlier_shots() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // Shot 40 is much brighter than the others
+    let shot = |start: usize, max_pq: u16| {
+        format!(
+            r#"{{
+                "start": {start},
+                "duration": 10,
+                "metadata_blocks": [
+                    {{ "Level1": {{ "min_pq": 0, "max_pq": {max_pq}, "avg_pq": 1000 }} }}
+                ]
+            }}"#
+        )
+    };
+    let shots: Vec<String> = [(0, 2000), (10, 2000), (20, 2000), (30, 2000), (40, 3500)]
+        .iter()
+        .map(|(start, max_pq)| shot(*start, *max_pq))
+        .collect();
+
+    let generate_config = temp.child("config.json");
+    generate_config.write_str(&format!(
+        r#"{{ "length": 50, "shots": [{}] }}"#,
+        shots.join(",")
+    ))?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("generate")
+        .arg("--json")
+        .arg(generate_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success();
+
+    let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(output_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("Frames: 50, shots: 5")
+            .and(predicate::str::contains("80.0% (40)"))
+            .and(predicate::str::contains("20.0% (10)"))
+            .and(predicate::str::contains("Outlier shots: 1 of 5"))
+            .and(predicate::str::contains("  Frames 40-49: median max")),
+    );
+
+    Ok(())
+}
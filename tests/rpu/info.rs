@@ -2,6 +2,7 @@ use std::path::Path;
 
 use anyhow::Result;
 use assert_cmd::Command;
+use assert_fs::prelude::*;
 use predicates::prelude::*;
 
 const SUBCOMMAND: &str = "info";
@@ -99,3 +100,324 @@ fn invalid_l3_error() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn identical_runs() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+
+    let input_rpu = Path::new("assets/tests/fel_orig.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--identical-runs")
+        .arg("1")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("Identical metadata runs: 1")
+            .and(predicate::str::contains("  0-0: 1 frames")),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn identical_runs_timestamp_log() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/tests/fel_orig.bin");
+
+    let timestamps = temp.child("timestamps.txt");
+    timestamps.write_str("# timestamp format v2\n41.708\n0\n")?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--timestamp-log")
+        .arg("24000/1001")
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--identical-runs")
+        .arg("1")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "  0-0 (00:00:00:00 - 00:00:00:00): 1 frames",
+        ));
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--timestamp-log")
+        .arg(timestamps.as_ref())
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--identical-runs")
+        .arg("1")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "  0-0 (00:00:00.000 - 00:00:00.000): 1 frames",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn shot_labels() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/tests/fel_orig.bin");
+
+    let generate_config = temp.child("config.json");
+    generate_config.write_str(
+        r#"{
+            "length": 1,
+            "shots": [
+                {
+                    "start": 0,
+                    "duration": 1,
+                    "labels": { "name": "Cold open", "reel": "R1", "scene": "12A" }
+                }
+            ]
+        }"#,
+    )?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--frame")
+        .arg("0")
+        .arg("--identical-runs")
+        .arg("1")
+        .arg("--labels")
+        .arg(generate_config.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(
+            predicate::str::contains("Shot labels: Cold open, reel R1, scene 12A").and(
+                predicate::str::contains("  0-0: 1 frames [Cold open, reel R1, scene 12A]"),
+            ),
+        );
+
+    // XML shots are named by their UniqueID
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--identical-runs")
+        .arg("1")
+        .arg("--labels")
+        .arg("assets/tests/cmv4_0_2.xml")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "  0-0: 1 frames [7b4b3d42-81c8-4767-aa38-f08a6f35994b]",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn compat_old() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // CM v2.9 RPU signaling 3 blocks, with only L1 and L6 written
+    let input_rpu = temp.child("RPU.bin");
+    input_rpu.write_binary(&[
+        0, 0, 0, 1, 0x19, 0x08, 0x09, 0x08, 0x40, 0x61, 0x36, 0x51, 0x7F, 0x20, 0x30, 0x08, 0x32,
+        0x3E, 0x81, 0xF4, 0x00, 0x90, 0x60, 0x3E, 0x80, 0x00, 0x10, 0x3E, 0x80, 0x19, 0x00, 0xBC,
+        0xDE, 0xE7, 0xA7, 0x80,
+    ])?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu.as_ref())
+        .arg("--frame")
+        .arg("0")
+        .assert()
+        .failure();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--compat-old")
+        .arg(SUBCOMMAND)
+        .arg(input_rpu.as_ref())
+        .arg("--frame")
+        .arg("0")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Warning: frame 0: CM v2.9 num_ext_blocks is 3 for 2 blocks, corrected",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn stability() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu_mel.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--stability")
+        .arg("--fps")
+        .arg("24")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("Metadata stability score: ")
+            .and(predicate::str::contains("  Scene cuts: 3 ("))
+            .and(predicate::str::contains("  Flagged frames: ")),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn frame_bit_fields() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+
+    let input_rpu = Path::new("assets/tests/fel_orig.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--frame")
+        .arg("0")
+        .arg("--bits")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("RPU payload (")
+            .and(predicate::str::contains("  00000000: 19 08"))
+            .and(predicate::str::contains("       0   8 rpu_nal_prefix = 25"))
+            .and(predicate::str::contains("       8   6 rpu_type = 2"))
+            .and(predicate::str::contains("vdr_rpu_profile = 1"))
+            .and(predicate::str::contains("cmv29.num_ext_blocks"))
+            .and(predicate::str::contains("  32 rpu_data_crc32 = "))
+            .and(predicate::str::contains("   8 rpu_nal_end = 128")),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn validate_trims() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // Shot 0: neutral trims, shot 10: the 100 nits trim lifts the highlights above the peak
+    // and is brighter than the 600 nits trim, which crushes the average to black
+    let generate_config = temp.child("config.json");
+    generate_config.write_str(
+        r#"{
+            "cm_version": "V29",
+            "length": 20,
+            "shots": [
+                {
+                    "start": 0,
+                    "duration": 10,
+                    "metadata_blocks": [
+                        { "Level1": { "min_pq": 0, "max_pq": 2000, "avg_pq": 1200 } },
+                        { "Level2": { "target_max_pq": 2081 } }
+                    ]
+                },
+                {
+                    "start": 10,
+                    "duration": 10,
+                    "metadata_blocks": [
+                        { "Level1": { "min_pq": 0, "max_pq": 2000, "avg_pq": 1200 } },
+                        { "Level2": { "target_max_pq": 2081, "trim_offset": 2458 } },
+                        { "Level2": { "target_max_pq": 2851, "trim_offset": 0 } }
+                    ]
+                }
+            ]
+        }"#,
+    )?;
+
+    let output_rpu = temp.child("RPU.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("generate")
+        .arg("--json")
+        .arg(generate_config.as_ref())
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success();
+
+    let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(output_rpu.as_ref())
+        .arg("--validate-trims")
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty()).stdout(
+        predicate::str::contains("L2 trim validation: 1 flagged shots of 2")
+            .and(predicate::str::contains(
+                "    10: 100 nits trim clips the highlights",
+            ))
+            .and(predicate::str::contains(
+                "    10: 600 nits trim crushes the shot average to black",
+            ))
+            .and(predicate::str::contains(
+                "    10: 100 nits trim is brighter than the 600 nits trim",
+            )),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn ipc_json_rpc() -> Result<()> {
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu_mel.bin");
+
+    // Every stdout line is an event
+    fn parse_events(stdout: &[u8]) -> Result<Vec<serde_json::Value>> {
+        let events = std::str::from_utf8(stdout)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+
+        Ok(events)
+    }
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--ipc")
+        .arg("json-rpc")
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--summary")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let events = parse_events(&output)?;
+    assert!(events.iter().all(|event| event["jsonrpc"] == "2.0"));
+    assert!(events.iter().any(
+        |event| event["method"] == "log" && event["params"]["message"] == "Parsing RPU file..."
+    ));
+    assert_eq!(events.last().unwrap()["method"], "completed");
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--ipc")
+        .arg("json-rpc")
+        .arg(SUBCOMMAND)
+        .arg("missing_rpu.bin")
+        .arg("--summary")
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+
+    let events = parse_events(&output)?;
+    assert_eq!(events.last().unwrap()["method"], "error");
+
+    Ok(())
+}
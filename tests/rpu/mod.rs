@@ -1,5 +1,11 @@
+mod bitrate;
 mod editor;
 mod export;
 mod generate;
+mod histogram;
 mod info;
+mod patch;
 mod plot;
+mod reorder;
+mod transcode_metadata;
+mod verify;
@@ -0,0 +1,126 @@
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+use dolby_vision::rpu::utils::parse_rpu_file;
+
+fn edit(input_rpu: &Path, edit_config: &Path, output_rpu: &Path) -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg("editor")
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config)
+        .arg("--rpu-out")
+        .arg(output_rpu)
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn diff_and_apply() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let edit_config = Path::new("assets/editor_examples/active_area.json");
+
+    let modified_rpu = temp.child("RPU_modified.bin");
+    edit(input_rpu, edit_config, modified_rpu.path())?;
+
+    let patch = temp.child("patch.json");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg("diff")
+        .arg("--original")
+        .arg(input_rpu)
+        .arg("--modified")
+        .arg(modified_rpu.as_ref())
+        .arg("--patch-out")
+        .arg(patch.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+    patch.assert(predicate::path::is_file());
+
+    let labels = temp.child("config.json");
+    labels.write_str(
+        r#"{ "shots": [{ "start": 0, "duration": 259, "labels": { "reel": "R2" } }] }"#,
+    )?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("diff")
+        .arg("--original")
+        .arg(input_rpu)
+        .arg("--modified")
+        .arg(modified_rpu.as_ref())
+        .arg("--patch-out")
+        .arg(patch.as_ref())
+        .arg("--labels")
+        .arg(labels.as_ref())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"(?m)^  0-\d+: .* \[reel R2\]$")?);
+
+    let patched_rpu = temp.child("RPU_patched.bin");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg("apply-patch")
+        .arg(input_rpu)
+        .arg("--patch")
+        .arg(patch.as_ref())
+        .arg("--rpu-out")
+        .arg(patched_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let expected_rpus = parse_rpu_file(modified_rpu.path())?;
+    let patched_rpus = parse_rpu_file(patched_rpu.path())?;
+
+    assert_eq!(patched_rpus.len(), expected_rpus.len());
+
+    for (patched, expected) in patched_rpus.iter().zip(&expected_rpus) {
+        assert_eq!(
+            patched.write_hevc_unspec62_nalu()?,
+            expected.write_hevc_unspec62_nalu()?
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn diff_unsupported_changes() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/tests/fel_orig.bin");
+    let edit_config = Path::new("assets/editor_examples/header_edits.json");
+
+    let modified_rpu = temp.child("RPU_modified.bin");
+    edit(input_rpu, edit_config, modified_rpu.path())?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd
+        .arg("diff")
+        .arg("--original")
+        .arg(input_rpu)
+        .arg("--modified")
+        .arg(modified_rpu.as_ref())
+        .arg("--patch-out")
+        .arg(temp.child("patch.json").as_ref())
+        .assert();
+
+    assert
+        .failure()
+        .stderr(predicate::str::contains("cannot create a patch"));
+
+    Ok(())
+}
@@ -42,3 +42,31 @@ fn plot_p7() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn plot_sampled() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    for (name, args) in [
+        ("L1_plot_sampled.png", vec!["--sample-every", "24"]),
+        ("L1_plot_shots.png", vec!["--per-shot"]),
+    ] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        let output_file = temp.child(name);
+
+        let assert = cmd
+            .arg(SUBCOMMAND)
+            .arg(input_rpu)
+            .args(args)
+            .arg("--output")
+            .arg(output_file.as_ref())
+            .assert();
+
+        assert.success().stderr(predicate::str::is_empty());
+
+        output_file.assert(predicate::path::is_file());
+    }
+
+    Ok(())
+}
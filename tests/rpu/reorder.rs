@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "reorder";
+
+/// Display index of the encoded frames, every pair of frames swapped
+fn swapped_pairs_order(frames: usize) -> Vec<usize> {
+    (0..frames)
+        .map(|i| {
+            if i % 2 == 0 {
+                (i + 1).min(frames - 1)
+            } else {
+                i - 1
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("dovi_tool reorder [OPTIONS]"));
+    Ok(())
+}
+
+#[test]
+fn frame_order_roundtrip() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let frame_order = temp.child("frame_order.txt");
+    let encode_order_rpu = temp.child("RPU_encode_order.bin");
+    let display_order_rpu = temp.child("RPU_display_order.bin");
+
+    let order: Vec<String> = swapped_pairs_order(259)
+        .iter()
+        .map(|i| i.to_string())
+        .collect();
+    frame_order.write_str(&format!(
+        "# display index of the encoded frames\n{}\n",
+        order.join("\n")
+    ))?;
+
+    let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--frame-order")
+        .arg(frame_order.as_ref())
+        .arg("--rpu-out")
+        .arg(encode_order_rpu.as_ref())
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+        "Reordering 259 frames from display to encode order: 258 frames moved, up to 1 frames away",
+    ));
+
+    let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(encode_order_rpu.as_ref())
+        .arg("--frame-order")
+        .arg(frame_order.as_ref())
+        .arg("--to-display")
+        .arg("--rpu-out")
+        .arg(display_order_rpu.as_ref())
+        .assert();
+
+    assert.success().stderr(predicate::str::is_empty());
+
+    let input_data = std::fs::read(input_rpu)?;
+    assert_ne!(std::fs::read(encode_order_rpu.path())?, input_data);
+    assert_eq!(std::fs::read(display_order_rpu.path())?, input_data);
+
+    Ok(())
+}
+
+#[test]
+fn x265_csv() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let x265_csv = temp.child("x265.csv");
+    let frame_order = temp.child("frame_order.txt");
+    let csv_output_rpu = temp.child("RPU_csv.bin");
+    let order_output_rpu = temp.child("RPU_order.bin");
+
+    let order = swapped_pairs_order(259);
+
+    let mut csv = String::from("Encode Order, Type, POC, QP, Bits\n");
+    for (encode_order, poc) in order.iter().enumerate() {
+        csv.push_str(&format!("{encode_order}, B-SLICE, {poc}, 30.00, 1000\n"));
+    }
+    x265_csv.write_str(&csv)?;
+
+    let order: Vec<String> = order.iter().map(|i| i.to_string()).collect();
+    frame_order.write_str(&order.join(" "))?;
+
+    for (order_arg, order_file, output) in [
+        ("--x265-csv", &x265_csv, &csv_output_rpu),
+        ("--frame-order", &frame_order, &order_output_rpu),
+    ] {
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+            .arg(SUBCOMMAND)
+            .arg(input_rpu)
+            .arg(order_arg)
+            .arg(order_file.as_ref())
+            .arg("--rpu-out")
+            .arg(output.as_ref())
+            .assert()
+            .success()
+            .stderr(predicate::str::is_empty());
+    }
+
+    assert_eq!(
+        std::fs::read(csv_output_rpu.path())?,
+        std::fs::read(order_output_rpu.path())?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn frame_count_mismatch() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+    let frame_order = temp.child("frame_order.txt");
+    frame_order.write_str("1 0 2")?;
+
+    let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--frame-order")
+        .arg(frame_order.as_ref())
+        .arg("--rpu-out")
+        .arg(temp.child("RPU.bin").as_ref())
+        .assert();
+
+    assert.failure().stderr(predicate::str::contains(
+        "Frame count mismatch: the frame order has 3 frames, the RPU file 259",
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "transcode-metadata";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "dovi_tool transcode-metadata [OPTIONS]",
+        ));
+    Ok(())
+}
+
+#[test]
+fn same_as_editor() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    let edit_config = temp.child("crop.json");
+    edit_config.write_str(r#"{ "active_area": { "crop": true } }"#)?;
+
+    let output_rpu = temp.child("RPU_transcoded.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--mode")
+        .arg("2")
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--drop-levels")
+        .arg("6,11")
+        .arg("--rpu-out")
+        .arg(output_rpu.as_ref())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains("Converting with mode 2..."))
+        .stdout(predicate::str::contains(
+            "Dropping metadata levels [6, 11]...",
+        ));
+
+    // Everything in a single editor config
+    let full_config = temp.child("full.json");
+    full_config
+        .write_str(r#"{ "mode": 2, "active_area": { "crop": true }, "drop_levels": [6, 11] }"#)?;
+
+    let editor_rpu = temp.child("RPU_edited.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("editor")
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(full_config.as_ref())
+        .arg("--rpu-out")
+        .arg(editor_rpu.as_ref())
+        .assert()
+        .success();
+
+    output_rpu.assert(predicate::path::eq_file(editor_rpu.path()));
+
+    let rpus = dolby_vision::rpu::utils::parse_rpu_file(output_rpu.as_ref())?;
+    assert_eq!(rpus.len(), 259);
+
+    for rpu in &rpus {
+        assert_eq!(rpu.dovi_profile, 8);
+
+        let vdr_dm_data = rpu.vdr_dm_data.as_ref().unwrap();
+        assert!(vdr_dm_data.get_block(6).is_none());
+        assert!(vdr_dm_data.get_block(11).is_none());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn conflicting_modes() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let edit_config = temp.child("mode.json");
+    edit_config.write_str(r#"{ "mode": 4 }"#)?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("--mode")
+        .arg("2")
+        .arg(SUBCOMMAND)
+        .arg("assets/hevc_tests/regular_rpu.bin")
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(temp.child("RPU.bin").as_ref())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Conflicting conversion modes: 4 in the edit config, 2 from --mode",
+        ));
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::path::Path;
+
+use anyhow::Result;
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+const SUBCOMMAND: &str = "verify";
+
+#[test]
+fn help() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let assert = cmd.arg(SUBCOMMAND).arg("--help").assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "dovi_tool verify [OPTIONS] [input_pos]",
+        ));
+    Ok(())
+}
+
+#[test]
+fn valid_rpu() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu_mel.bin");
+
+    let assert = cmd
+        .arg(SUBCOMMAND)
+        .arg(input_rpu)
+        .arg("-t")
+        .arg("4")
+        .assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Verified 259 frames, no errors found.",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn start_code_3_bytes() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let data = std::fs::read("assets/hevc_tests/regular_rpu_mel.bin")?;
+
+    // Every other RPU with a 3 bytes start code
+    let mut converted = Vec::with_capacity(data.len());
+    let mut rpu_index = 0;
+    let mut i = 0;
+
+    while i < data.len() {
+        if data[i..].starts_with(&[0, 0, 0, 1]) {
+            if rpu_index % 2 == 1 {
+                i += 1;
+            }
+
+            rpu_index += 1;
+        }
+
+        converted.push(data[i]);
+        i += 1;
+    }
+
+    let input_rpu = temp.child("RPU.bin");
+    input_rpu.write_binary(&converted)?;
+
+    let assert = cmd.arg(SUBCOMMAND).arg(input_rpu.as_ref()).assert();
+
+    assert
+        .success()
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::contains(
+            "Verified 259 frames, no errors found.",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn corrupted_rpu() -> Result<()> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut data = std::fs::read("assets/hevc_tests/regular_rpu_mel.bin")?;
+
+    // Flip a bit in the first frame's payload
+    data[20] ^= 0x01;
+
+    let input_rpu = temp.child("RPU.bin");
+    input_rpu.write_binary(&data)?;
+
+    let assert = cmd.arg(SUBCOMMAND).arg(input_rpu.as_ref()).assert();
+
+    assert
+        .failure()
+        .stdout(predicate::str::contains(
+            "Frame 0: RPU CRC32 does not match",
+        ))
+        .stderr(predicate::str::contains(
+            "Verification failed: 1 invalid frames out of 259",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn inconsistent_ids() -> Result<()> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let input_rpu = Path::new("assets/hevc_tests/regular_rpu.bin");
+
+    let edit_config = temp.child("rpu_ids.json");
+    edit_config.write_str(r#"{ "header_edits": [{ "set": { "vdr_rpu_id": 1 } }] }"#)?;
+
+    let edited_rpu = temp.child("edited.bin");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("editor")
+        .arg(input_rpu)
+        .arg("--json")
+        .arg(edit_config.as_ref())
+        .arg("--rpu-out")
+        .arg(edited_rpu.as_ref())
+        .assert()
+        .success();
+
+    // Same ids, consistent
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(edited_rpu.as_ref())
+        .arg("--check-ids")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Verified 259 frames, no errors found.",
+        ));
+
+    // Assembled from both files
+    let mut data = std::fs::read(input_rpu)?;
+    data.extend(std::fs::read(edited_rpu.path())?);
+
+    let assembled_rpu = temp.child("RPU.bin");
+    assembled_rpu.write_binary(&data)?;
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg(SUBCOMMAND)
+        .arg(assembled_rpu.as_ref())
+        .arg("--check-ids")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Frame 259: vdr_rpu_id 1 differs from the first frame id 0",
+        ))
+        .stderr(predicate::str::contains(
+            "Verification failed: 259 invalid frames out of 518",
+        ));
+
+    Ok(())
+}